@@ -0,0 +1,247 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Command line interface of beat-detector.
+//!
+//! ```text
+//! beat-detector detect <file.wav>   prints the time of each beat in seconds
+//! beat-detector report <file.wav>   prints a quality report as Markdown
+//! beat-detector tune <file.wav>     finds the best parameters for the
+//!                                   annotated beats of a fixture
+//! beat-detector live [device]       prints the beats of live audio input
+//! ```
+
+#![deny(
+    clippy::all,
+    clippy::cargo,
+    clippy::nursery,
+    // clippy::restriction,
+    // clippy::pedantic
+)]
+#![allow(clippy::multiple_crate_versions)]
+#![deny(missing_debug_implementations)]
+#![deny(rustdoc::all)]
+
+use beat_detector::audio_io::file::WavSource;
+use beat_detector::fixture::Fixture;
+use beat_detector::offline::detect_beats_from_source_with_progress;
+use beat_detector::report::QualityReport;
+use beat_detector::stop::StopSource;
+use beat_detector::sweep::{sweep, AnnotatedAudio, ParameterGrid, SweepResult, DEFAULT_TOLERANCE};
+use std::io::{IsTerminal, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+    beat-detector detect <file.wav>   prints the time of each beat in seconds
+    beat-detector report <file.wav>   prints a quality report as Markdown
+    beat-detector tune <file.wav>     finds the best parameters for the
+                                      annotated beats of a fixture
+    beat-detector live [device]       prints the beats of live audio input";
+
+/// A command of the command line interface.
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Detect { path: String },
+    Report { path: String },
+    Tune { path: String },
+    Live { device: Option<String> },
+}
+
+impl Command {
+    /// Parses the command line arguments, without the name of the binary.
+    fn parse(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        let command = match args.next()?.as_str() {
+            "detect" => Self::Detect { path: args.next()? },
+            "report" => Self::Report { path: args.next()? },
+            "tune" => Self::Tune { path: args.next()? },
+            "live" => Self::Live {
+                device: args.next(),
+            },
+            _ => return None,
+        };
+        args.next().is_none().then_some(command)
+    }
+
+    fn run(self) -> Result<(), String> {
+        match self {
+            Self::Detect { path } => {
+                let source = WavSource::open(&path).map_err(|err| format!("{path}: {err}"))?;
+                let total_samples = source.total_samples();
+                // The progress is only shown to humans, not written to logs.
+                let show_progress = std::io::stderr().is_terminal();
+                detect_beats_from_source_with_progress(
+                    source,
+                    Some(total_samples),
+                    true,
+                    &StopSource::new().token(),
+                    |progress| {
+                        for beat in progress.new_beats {
+                            println!("{:.3}", beat.timestamp().as_secs_f64());
+                        }
+                        if let Some(percent) = progress.percent().filter(|_| show_progress) {
+                            eprint!("\r{percent:3.0} %");
+                            let _ = std::io::stderr().flush();
+                        }
+                    },
+                )
+                .map_err(|err| format!("{path}: {err}"))?;
+                if show_progress {
+                    eprintln!();
+                }
+                Ok(())
+            }
+            Self::Report { path } => {
+                let report = QualityReport::analyze_wav_file(&path, true)
+                    .map_err(|err| format!("{path}: {err}"))?;
+                print!("{report}");
+                Ok(())
+            }
+            Self::Tune { path } => tune(&path),
+            Self::Live { device } => live(device),
+        }
+    }
+}
+
+/// Amount of the best parameter combinations that `tune` prints.
+const TUNE_RESULTS: usize = 5;
+
+fn tune(path: &str) -> Result<(), String> {
+    let fixture = Fixture::load(path).map_err(|err| format!("{path}: {err}"))?;
+    let beats = fixture.beat_times().collect::<Vec<_>>();
+    let audio = AnnotatedAudio {
+        samples: &fixture.samples,
+        sampling_rate: fixture.sampling_rate as f32,
+        beats: &beats,
+    };
+    let results = sweep(&ParameterGrid::default(), &[audio], DEFAULT_TOLERANCE);
+    println!("| Cutoff | Peak to median | Min distance | F-measure | Precision | Recall |");
+    println!("|---|---|---|---|---|---|");
+    for SweepResult {
+        parameters,
+        evaluation,
+    } in results.iter().take(TUNE_RESULTS)
+    {
+        println!(
+            "| {} Hz | {} | {} ms | {:.3} | {:.3} | {:.3} |",
+            parameters.lowpass_cutoff_hz,
+            parameters.max_peak_to_median_min_ratio,
+            parameters.min_beat_distance.as_millis(),
+            evaluation.f_measure(),
+            evaluation.precision(),
+            evaluation.recall()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "recording")]
+fn live(device: Option<String>) -> Result<(), String> {
+    use beat_detector::recording::record_until;
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    let device = match device {
+        Some(name) => {
+            let mut devices = cpal::default_host()
+                .input_devices()
+                .map_err(|err| err.to_string())?;
+            let device = devices
+                .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+                .ok_or_else(|| format!("no input device named {name:?}"))?;
+            Some(device)
+        }
+        None => None,
+    };
+
+    let stop_source = StopSource::new();
+    let stop = stop_source.token();
+    ctrlc::set_handler(move || stop_source.stop()).map_err(|err| err.to_string())?;
+    record_until(
+        |beat| println!("{:.3}", beat.timestamp().as_secs_f64()),
+        device,
+        &stop,
+    )
+    .map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "recording"))]
+fn live(_device: Option<String>) -> Result<(), String> {
+    Err("live audio input needs the `recording` feature".into())
+}
+
+fn main() -> ExitCode {
+    let Some(command) = Command::parse(std::env::args().skip(1)) else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    match command.run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Option<Command> {
+        Command::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(
+            parse(&["detect", "song.wav"]),
+            Some(Command::Detect {
+                path: "song.wav".into()
+            })
+        );
+        assert_eq!(
+            parse(&["report", "song.wav"]),
+            Some(Command::Report {
+                path: "song.wav".into()
+            })
+        );
+        assert_eq!(
+            parse(&["tune", "song.wav"]),
+            Some(Command::Tune {
+                path: "song.wav".into()
+            })
+        );
+        assert_eq!(parse(&["live"]), Some(Command::Live { device: None }));
+        assert_eq!(
+            parse(&["live", "USB Audio"]),
+            Some(Command::Live {
+                device: Some("USB Audio".into())
+            })
+        );
+
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["detect"]), None);
+        assert_eq!(parse(&["detect", "a.wav", "b.wav"]), None);
+        assert_eq!(parse(&["unknown"]), None);
+    }
+}
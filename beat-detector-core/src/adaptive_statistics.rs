@@ -0,0 +1,177 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`AdaptiveStatistics`].
+
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::SnapshotError;
+use core::time::Duration;
+
+/// Fraction of the long-term median of the peaks that the median of the
+/// audio history must reach. Below, the long-term median takes over.
+const MEDIAN_FLOOR: f32 = 0.5;
+
+/// Fraction of the noise floor below which peaks count as noise.
+const NOISE_GATE: f32 = 0.5;
+
+/// Long-term statistics of the processed audio, which follow the audio with
+/// the statistics decay of the detector.
+///
+/// The envelope search compares peaks with the median of the peaks in the
+/// audio history, which only covers a few hundred milliseconds. Right after a
+/// loud passage, the quiet audio that follows would only be compared with
+/// itself, so that small bumps look like beats. The long-term median remembers
+/// the level of the loud passage and fades to the new level with the decay.
+///
+/// The noise floor is the median of the quietest audio. It falls right away
+/// and rises with the decay, so that steady noise, such as the hiss of a
+/// microphone, doesn't start envelopes.
+#[derive(Debug, Clone)]
+pub(crate) struct AdaptiveStatistics {
+    peak_median: f32,
+    noise_floor: f32,
+    /// Time constant of the followers in samples.
+    decay_samples: f32,
+}
+
+impl AdaptiveStatistics {
+    /// Creates new statistics for processed samples of the given sampling
+    /// frequency.
+    pub(crate) fn new(sampling_frequency: f32, decay: Duration) -> Self {
+        let mut statistics = Self {
+            peak_median: 0.0,
+            noise_floor: 0.0,
+            decay_samples: 0.0,
+        };
+        statistics.set_decay(sampling_frequency, decay);
+        statistics
+    }
+
+    /// Sets the time constant of the followers.
+    pub(crate) fn set_decay(&mut self, sampling_frequency: f32, decay: Duration) {
+        self.decay_samples = (decay.as_secs_f32() * sampling_frequency).max(1.0);
+    }
+
+    /// Updates the followers with the median of the peaks in the audio
+    /// history after an update of `len` processed samples. `None` if there
+    /// are no peaks, such as in silence, which keeps the statistics.
+    pub(crate) fn update(&mut self, median: Option<i16>, len: usize) {
+        let Some(median) = median.map(f32::from) else {
+            return;
+        };
+        if self.peak_median == 0.0 {
+            // Adopt the level of the first audio right away.
+            self.peak_median = median;
+            self.noise_floor = median;
+            return;
+        }
+        let alpha = (len as f32 / self.decay_samples).min(1.0);
+        self.peak_median += alpha * (median - self.peak_median);
+        if median < self.noise_floor {
+            self.noise_floor = median;
+        } else {
+            self.noise_floor += alpha * (median - self.noise_floor);
+        }
+    }
+
+    /// Returns the value below which the median of the peaks of the audio
+    /// history doesn't count.
+    pub(crate) fn median_floor(&self) -> i16 {
+        (self.peak_median * MEDIAN_FLOOR) as i16
+    }
+
+    /// Returns the amplitude below which peaks count as noise.
+    pub(crate) fn noise_gate(&self) -> i16 {
+        (self.noise_floor * NOISE_GATE) as i16
+    }
+
+    /// Writes the state of the followers. The time constant isn't included.
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.f32(self.peak_median);
+        writer.f32(self.noise_floor);
+    }
+
+    pub(crate) fn read_snapshot(
+        &mut self,
+        reader: &mut SnapshotReader,
+    ) -> Result<(), SnapshotError> {
+        self.peak_median = reader.f32()?;
+        self.noise_floor = reader.f32()?;
+        let range = 0.0..=i16::MAX as f32;
+        SnapshotReader::check(
+            range.contains(&self.peak_median) && range.contains(&self.noise_floor),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds updates of 1024 samples with the given median.
+    fn feed(statistics: &mut AdaptiveStatistics, median: i16, updates: usize) {
+        for _ in 0..updates {
+            statistics.update(Some(median), 1024);
+        }
+    }
+
+    #[test]
+    fn adaptive_statistics_follow_the_audio() {
+        let mut statistics = AdaptiveStatistics::new(44100.0, Duration::from_secs(3));
+        feed(&mut statistics, 8000, 1);
+        assert_eq!(statistics.median_floor(), 4000);
+        assert_eq!(statistics.noise_gate(), 4000);
+
+        // Silence keeps the statistics.
+        statistics.update(None, 1024);
+        assert_eq!(statistics.median_floor(), 4000);
+
+        // A quiet passage: the noise floor falls at once, the median slowly.
+        feed(&mut statistics, 2000, 1);
+        assert_eq!(statistics.noise_gate(), 1000);
+        assert!(statistics.median_floor() > 3900);
+        feed(&mut statistics, 2000, 600);
+        assert!((1000..1100).contains(&statistics.median_floor()));
+
+        // And louder again: the noise floor rises slowly.
+        feed(&mut statistics, 8000, 10);
+        assert!(statistics.noise_gate() < 1500);
+    }
+
+    #[test]
+    fn adaptive_statistics_decay() {
+        let median_floor_after = |decay| {
+            let mut statistics = AdaptiveStatistics::new(44100.0, decay);
+            feed(&mut statistics, 8000, 1);
+            // Two seconds of quieter audio.
+            feed(&mut statistics, 2000, 86);
+            statistics.median_floor()
+        };
+
+        // The longer the decay, the more the louder part is remembered.
+        let short = median_floor_after(Duration::from_secs(2));
+        let long = median_floor_after(Duration::from_secs(10));
+        assert!(long > short + 1000, "{short} {long}");
+    }
+}
@@ -0,0 +1,174 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`AmplitudeHistogram`].
+
+/// Amount of buckets of the histogram.
+const BUCKET_COUNT: usize = 64;
+
+/// Range of absolute amplitudes covered by a single bucket.
+const BUCKET_WIDTH: usize = (i16::MAX as usize + 1) / BUCKET_COUNT;
+
+/// Compact histogram of absolute amplitudes that is updated incrementally.
+///
+/// Percentiles of the histogram are robust against outliers, in contrast to
+/// the mean. A single very loud peak doesn't shift the median, for example.
+/// The resolution of all queries is the width of a bucket, which is
+/// `32768 / 64 = 512`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmplitudeHistogram {
+    buckets: [u32; BUCKET_COUNT],
+    count: u32,
+}
+
+impl AmplitudeHistogram {
+    /// Creates an empty histogram.
+    pub const fn new() -> Self {
+        Self {
+            buckets: [0; BUCKET_COUNT],
+            count: 0,
+        }
+    }
+
+    /// Adds the absolute value of the given amplitude.
+    pub fn add(&mut self, amplitude: i16) {
+        self.buckets[Self::bucket_index(amplitude)] += 1;
+        self.count += 1;
+    }
+
+    /// Removes the absolute value of the given amplitude, which must have
+    /// been added before.
+    pub fn remove(&mut self, amplitude: i16) {
+        let bucket = &mut self.buckets[Self::bucket_index(amplitude)];
+        debug_assert!(*bucket > 0);
+        *bucket -= 1;
+        self.count -= 1;
+    }
+
+    /// Removes all amplitudes.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Returns the amount of amplitudes in the histogram.
+    pub const fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    /// Returns whether the histogram contains no amplitudes.
+    pub const fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the absolute amplitude below which the given fraction of all
+    /// amplitudes lies. `percentile` must be in range `0.0..=1.0`. The value
+    /// is the center of the corresponding bucket.
+    ///
+    /// Returns `None` if the histogram is empty.
+    pub fn percentile(&self, percentile: f32) -> Option<i16> {
+        assert!((0.0..=1.0).contains(&percentile));
+        if self.is_empty() {
+            return None;
+        }
+
+        // Rank of the requested amplitude, starting at 1.
+        let rank = (libm::ceilf(percentile * self.count as f32) as u32).clamp(1, self.count);
+        let mut cumulative_count = 0;
+        let index = self
+            .buckets
+            .iter()
+            .position(|&count| {
+                cumulative_count += count;
+                cumulative_count >= rank
+            })
+            .unwrap_or(BUCKET_COUNT - 1);
+        let center = index * BUCKET_WIDTH + BUCKET_WIDTH / 2;
+        Some(center as i16)
+    }
+
+    /// Returns the median of all absolute amplitudes.
+    pub fn median(&self) -> Option<i16> {
+        self.percentile(0.5)
+    }
+
+    /// Returns the 90th percentile of all absolute amplitudes.
+    pub fn p90(&self) -> Option<i16> {
+        self.percentile(0.9)
+    }
+
+    fn bucket_index(amplitude: i16) -> usize {
+        // `i16::MIN` has no positive counterpart.
+        (amplitude.unsigned_abs() as usize / BUCKET_WIDTH).min(BUCKET_COUNT - 1)
+    }
+}
+
+impl Default for AmplitudeHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromIterator<i16> for AmplitudeHistogram {
+    fn from_iter<T: IntoIterator<Item = i16>>(iter: T) -> Self {
+        let mut histogram = Self::new();
+        iter.into_iter()
+            .for_each(|amplitude| histogram.add(amplitude));
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let mut histogram = AmplitudeHistogram::new();
+        assert_eq!(histogram.median(), None);
+
+        // Nine quiet amplitudes and a single outlier.
+        for amplitude in [100, -200, 300, 400, -500, 600, 700, 800, 900] {
+            histogram.add(amplitude);
+        }
+        histogram.add(i16::MIN);
+        assert_eq!(histogram.len(), 10);
+
+        assert_eq!(histogram.percentile(0.0), Some(256));
+        assert_eq!(histogram.median(), Some(256));
+        assert_eq!(histogram.p90(), Some(768));
+        assert_eq!(histogram.percentile(1.0), Some(32512));
+
+        histogram.remove(i16::MIN);
+        assert_eq!(histogram.percentile(1.0), Some(768));
+        assert_eq!(histogram.len(), 9);
+
+        histogram.clear();
+        assert!(histogram.is_empty());
+    }
+
+    #[test]
+    fn from_iter() {
+        let histogram = [0, 1000, 2000].into_iter().collect::<AmplitudeHistogram>();
+        assert_eq!(histogram.median(), Some(768));
+    }
+}
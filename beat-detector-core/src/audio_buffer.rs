@@ -0,0 +1,256 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`AudioBuffer`].
+
+use core::iter::Chain;
+use core::ops::{Index, IndexMut};
+use core::ptr::addr_of_mut;
+use core::slice;
+
+/// Fixed-size ring buffer for audio samples. Once full, adding a new sample
+/// overwrites the oldest one.
+///
+/// Index `0` always refers to the oldest sample. In contrast to general
+/// purpose ring buffers, this exposes the underlying storage as two
+/// contiguous slices and supports bulk copies.
+///
+/// Indices wrap at the storage size `N` with a comparison instead of a
+/// division. The capacity can be smaller than `N`.
+#[derive(Debug, Clone)]
+pub(crate) struct AudioBuffer<const N: usize> {
+    data: [i16; N],
+    /// Index of the oldest sample in `data`.
+    head: usize,
+    len: usize,
+    capacity: usize,
+}
+
+impl<const N: usize> AudioBuffer<N> {
+    /// Wraps an index in range `0..2 * N` into the storage.
+    #[inline]
+    const fn wrap(index: usize) -> usize {
+        if index >= N {
+            index - N
+        } else {
+            index
+        }
+    }
+
+    pub const fn new(capacity: usize) -> Self {
+        Self::check_params(capacity);
+        Self {
+            data: [0; N],
+            head: 0,
+            len: 0,
+            capacity,
+        }
+    }
+
+    /// Like [`Self::new`] but initializes the buffer in place, without
+    /// creating the (large) buffer on the stack first.
+    ///
+    /// # Safety
+    /// `this` must be valid for writes and properly aligned.
+    pub unsafe fn init_in_place(this: *mut Self, capacity: usize) {
+        Self::check_params(capacity);
+        // SAFETY: Guaranteed by the caller. All-zero is a valid `[i16; N]`.
+        unsafe {
+            addr_of_mut!((*this).data).write_bytes(0, 1);
+            addr_of_mut!((*this).head).write(0);
+            addr_of_mut!((*this).len).write(0);
+            addr_of_mut!((*this).capacity).write(capacity);
+        }
+    }
+
+    const fn check_params(capacity: usize) {
+        if capacity == 0 || capacity > N {
+            panic!("The capacity must be in range 1..=N");
+        }
+    }
+
+    /// Adds a sample. If the buffer is full, the oldest sample is dropped.
+    #[inline]
+    pub fn push(&mut self, sample: i16) {
+        self.data[Self::wrap(self.head + self.len)] = sample;
+        if self.len < self.capacity {
+            self.len += 1;
+        } else {
+            self.head = Self::wrap(self.head + 1);
+        }
+    }
+
+    /// Adds all samples with at most two bulk copies. If the buffer
+    /// overflows, the oldest samples are dropped.
+    #[inline]
+    pub fn extend_from_slice(&mut self, samples: &[i16]) {
+        if samples.len() >= self.capacity {
+            let samples = &samples[samples.len() - self.capacity..];
+            self.data[..self.capacity].copy_from_slice(samples);
+            self.head = 0;
+            self.len = self.capacity;
+            return;
+        }
+
+        let write_index = Self::wrap(self.head + self.len);
+        let (first, second) = samples.split_at(samples.len().min(N - write_index));
+        self.data[write_index..write_index + first.len()].copy_from_slice(first);
+        self.data[..second.len()].copy_from_slice(second);
+
+        let len = self.len + samples.len();
+        if len > self.capacity {
+            self.head = Self::wrap(self.head + len - self.capacity);
+            self.len = self.capacity;
+        } else {
+            self.len = len;
+        }
+    }
+
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the samples as two slices, where the first one holds the older
+    /// samples. The second slice is empty if the data is contiguous.
+    #[inline]
+    pub fn as_slices(&self) -> (&[i16], &[i16]) {
+        if self.head + self.len <= N {
+            (&self.data[self.head..self.head + self.len], &[])
+        } else {
+            let (wrapped, tail) = self.data.split_at(self.head);
+            (tail, &wrapped[..self.len - tail.len()])
+        }
+    }
+
+    /// Iterates the samples from oldest to newest.
+    #[inline]
+    pub fn iter(&self) -> Chain<slice::Iter<'_, i16>, slice::Iter<'_, i16>> {
+        let (older, newer) = self.as_slices();
+        older.iter().chain(newer.iter())
+    }
+}
+
+impl<const N: usize> Index<usize> for AudioBuffer<N> {
+    type Output = i16;
+
+    #[inline]
+    fn index(&self, index: usize) -> &Self::Output {
+        assert!(index < self.len);
+        &self.data[Self::wrap(self.head + index)]
+    }
+}
+
+impl<const N: usize> IndexMut<usize> for AudioBuffer<N> {
+    #[inline]
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        assert!(index < self.len);
+        &mut self.data[Self::wrap(self.head + index)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn push_and_wrap_around() {
+        let mut buffer = AudioBuffer::<4>::new(4);
+        assert_eq!(buffer.len(), 0);
+        assert_eq!(buffer.as_slices(), (&[][..], &[][..]));
+
+        (1..=3).for_each(|sample| buffer.push(sample));
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.as_slices(), (&[1, 2, 3][..], &[][..]));
+
+        (4..=6).for_each(|sample| buffer.push(sample));
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.capacity(), 4);
+        assert_eq!(buffer.as_slices(), (&[3, 4][..], &[5, 6][..]));
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), [3, 4, 5, 6]);
+        assert_eq!(buffer[0], 3);
+        assert_eq!(buffer[3], 6);
+
+        (7..=8).for_each(|sample| buffer.push(sample));
+        assert_eq!(buffer.as_slices(), (&[5, 6, 7, 8][..], &[][..]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_bounds() {
+        let mut buffer = AudioBuffer::<4>::new(4);
+        buffer.push(1);
+        let _ = buffer[1];
+    }
+
+    #[test]
+    fn capacity_smaller_than_storage() {
+        let mut buffer = AudioBuffer::<8>::new(5);
+        (1..=7).for_each(|sample| buffer.push(sample));
+        assert_eq!(buffer.len(), 5);
+        assert_eq!(buffer.capacity(), 5);
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), [3, 4, 5, 6, 7]);
+
+        (8..=10).for_each(|sample| buffer.push(sample));
+        assert_eq!(buffer.as_slices(), (&[6, 7, 8][..], &[9, 10][..]));
+        assert_eq!(buffer[4], 10);
+    }
+
+    #[test]
+    fn wrap_at_storage_size() {
+        let mut buffer = AudioBuffer::<6>::new(6);
+        (1..=8).for_each(|sample| buffer.push(sample));
+        assert_eq!(buffer.as_slices(), (&[3, 4, 5, 6][..], &[7, 8][..]));
+        assert_eq!(buffer[5], 8);
+
+        buffer.extend_from_slice(&[9, 10, 11]);
+        assert_eq!(
+            buffer.iter().copied().collect::<Vec<_>>(),
+            [6, 7, 8, 9, 10, 11]
+        );
+    }
+
+    #[test]
+    fn extend_from_slice_matches_push() {
+        let samples = (0..100).collect::<Vec<i16>>();
+        for chunk_size in 1..=12 {
+            let mut expected = AudioBuffer::<8>::new(6);
+            let mut actual = AudioBuffer::<8>::new(6);
+            for chunk in samples.chunks(chunk_size) {
+                chunk.iter().for_each(|&sample| expected.push(sample));
+                actual.extend_from_slice(chunk);
+                assert_eq!(
+                    actual.iter().collect::<Vec<_>>(),
+                    expected.iter().collect::<Vec<_>>(),
+                    "chunk size {chunk_size}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,996 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+use crate::audio_buffer::AudioBuffer;
+use crate::envelope_iterator::ENVELOPE_MIN_DURATION_MS;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::util::saturating_f32_to_i16;
+use core::cmp::Ordering;
+use core::fmt::{Display, Formatter};
+use core::ptr::addr_of_mut;
+use core::time::Duration;
+
+const SAFETY_BUFFER_FACTOR: f64 = 3.0;
+/// Length in ms of the captured audio history used for analysis.
+pub(crate) const DEFAULT_AUDIO_HISTORY_WINDOW_MS: usize =
+    (ENVELOPE_MIN_DURATION_MS as f64 * SAFETY_BUFFER_FACTOR) as usize;
+
+/// Based on the de-facto default sampling rate of 44100 Hz / 44.1 kHz.
+const DEFAULT_SAMPLES_PER_SECOND: usize = 44100;
+const MS_PER_SECOND: usize = 1000;
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+/// The sampling frequency is stored in millihertz. This keeps the time
+/// bookkeeping exact for all common (integer) sampling rates while still
+/// supporting fractional ones.
+const MILLIHERTZ_PER_HERTZ: f32 = 1000.0;
+/// Size of the chunks of [`AudioHistory::update`]. Small enough for the
+/// stack of MCUs, but large enough to amortize the bookkeeping of the ring
+/// buffer.
+const UPDATE_CHUNK_LEN: usize = 128;
+
+/// Default buffer size for [`AudioHistory`]. The size is a trade-off between
+/// memory efficiency and effectiveness in detecting envelops properly.
+pub const DEFAULT_BUFFER_SIZE: usize =
+    (DEFAULT_AUDIO_HISTORY_WINDOW_MS * DEFAULT_SAMPLES_PER_SECOND) / MS_PER_SECOND;
+
+/// Size of the underlying storage of the default [`AudioHistory`]. It matches
+/// the default capacity, so that no memory is wasted.
+pub(crate) const BUFFER_STORAGE_SIZE: usize = DEFAULT_BUFFER_SIZE;
+
+/// Sample info with time context.
+#[derive(Copy, Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct SampleInfo {
+    /// The value of the sample.
+    pub value: i16,
+    /// Absolute value of `value`.
+    pub value_abs: i16,
+    /// The current index in [`AudioHistory`].
+    pub index: usize,
+    /// The total index since the beginning of audio history. This is a `u64`
+    /// so that it doesn't overflow in long-running sessions on 32-bit
+    /// targets.
+    ///
+    /// This refers to the (possibly downsampled) audio in the history. Use
+    /// [`Self::source`] for the position in the original audio.
+    pub total_index: u64,
+    /// Relative timestamp since beginning of audio history.
+    pub timestamp: Duration,
+    /// The time the sample is behind the latest data.
+    pub duration_behind: Duration,
+    /// Position of the sample in the original audio. For samples returned by
+    /// the [`BeatDetector`], this accounts for downsampling and the delay of
+    /// the lowpass filter. For samples of a plain [`AudioHistory`], this
+    /// corresponds to [`Self::total_index`].
+    ///
+    /// This field is new since 0.2. Struct literals and exhaustive patterns
+    /// of older code must be updated.
+    ///
+    /// [`BeatDetector`]: crate::BeatDetector
+    pub source: SourcePosition,
+}
+
+/// Position in the original audio, i.e., the audio that was passed to the
+/// [`BeatDetector`] before any downsampling or filtering.
+///
+/// [`BeatDetector`]: crate::BeatDetector
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourcePosition {
+    /// Index of the sample in the original audio.
+    pub samples: u64,
+    /// Time since the beginning of the original audio.
+    pub time: Duration,
+}
+
+impl SourcePosition {
+    /// Creates the position of the sample with the given index in audio with
+    /// the given sampling frequency.
+    pub fn from_samples(samples: u64, sampling_frequency: f32) -> Self {
+        Self {
+            samples,
+            time: Duration::from_secs_f64(samples as f64 / sampling_frequency as f64),
+        }
+    }
+
+    /// Creates the position of the sample at the given time in audio with the
+    /// given sampling frequency.
+    pub fn from_time(time: Duration, sampling_frequency: f32) -> Self {
+        Self {
+            samples: (time.as_secs_f64() * sampling_frequency as f64) as u64,
+            time,
+        }
+    }
+}
+
+impl SampleInfo {
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.i16(self.value);
+        writer.u32(self.index as u32);
+        writer.u64(self.total_index);
+        writer.duration(self.timestamp);
+        writer.duration(self.duration_behind);
+        writer.u64(self.source.samples);
+        writer.duration(self.source.time);
+    }
+
+    pub(crate) fn read_snapshot(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let value = reader.i16()?;
+        Ok(Self {
+            value,
+            value_abs: value.saturating_abs(),
+            index: reader.u32()? as usize,
+            total_index: reader.u64()?,
+            timestamp: reader.duration()?,
+            duration_behind: reader.duration()?,
+            source: SourcePosition {
+                samples: reader.u64()?,
+                time: reader.duration()?,
+            },
+        })
+    }
+}
+
+impl PartialEq for SampleInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.total_index.eq(&other.total_index)
+    }
+}
+
+impl PartialOrd for SampleInfo {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Eq for SampleInfo {}
+
+impl Ord for SampleInfo {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.total_index
+            .partial_cmp(&other.total_index)
+            .expect("Should be comparable")
+    }
+}
+
+/// Accessor over the captured audio history that helps to identify the
+/// timestamp of each sample.
+///
+/// Users are supposed to add new data in chunks that are less than the buffer
+/// size, to slowly fade out old data from the underlying ringbuffer.
+///
+/// `N` is the size of the underlying storage. The default is suitable for
+/// typical audio input with 44.1 or 48 kHz.
+#[derive(Debug)]
+pub struct AudioHistory<const N: usize = BUFFER_STORAGE_SIZE> {
+    audio_buffer: AudioBuffer<N>,
+    total_consumed_samples: u64,
+    /// Sampling frequency in millihertz. Timestamps are calculated with
+    /// integer arithmetic from this, so they don't drift over time.
+    sampling_frequency_millihz: u64,
+}
+
+impl AudioHistory {
+    pub fn new(sampling_frequency: f32) -> Self {
+        Self::with_capacity(sampling_frequency, DEFAULT_BUFFER_SIZE)
+    }
+}
+
+impl<const N: usize> AudioHistory<N> {
+    /// Creates a new history that holds up to `capacity` samples. The
+    /// capacity must be in range `1..=N`.
+    pub fn with_capacity(sampling_frequency: f32, capacity: usize) -> Self {
+        let audio_buffer = AudioBuffer::new(capacity);
+        Self {
+            audio_buffer,
+            sampling_frequency_millihz: Self::to_millihz(sampling_frequency),
+            total_consumed_samples: 0,
+        }
+    }
+
+    /// Like [`Self::with_capacity`] but initializes the history in place,
+    /// without creating the (large) audio buffer on the stack first.
+    ///
+    /// # Safety
+    /// `this` must be valid for writes and properly aligned.
+    pub(crate) unsafe fn init_in_place(this: *mut Self, sampling_frequency: f32, capacity: usize) {
+        let sampling_frequency_millihz = Self::to_millihz(sampling_frequency);
+        // SAFETY: Guaranteed by the caller.
+        unsafe {
+            AudioBuffer::init_in_place(addr_of_mut!((*this).audio_buffer), capacity);
+            addr_of_mut!((*this).sampling_frequency_millihz).write(sampling_frequency_millihz);
+            addr_of_mut!((*this).total_consumed_samples).write(0);
+        }
+    }
+
+    fn to_millihz(sampling_frequency: f32) -> u64 {
+        assert!(sampling_frequency.is_normal() && sampling_frequency.is_sign_positive());
+        let sampling_frequency_millihz =
+            libm::roundf(sampling_frequency * MILLIHERTZ_PER_HERTZ) as u64;
+        assert!(sampling_frequency_millihz > 0);
+        sampling_frequency_millihz
+    }
+
+    /// Update the audio history with fresh samples. The audio samples are
+    /// expected to be in mono channel format.
+    ///
+    /// The samples are collected in small chunks on the stack, which are
+    /// copied in bulk into the ring buffer.
+    #[inline]
+    pub fn update<I: Iterator<Item = i16>>(&mut self, mono_samples_iter: I) {
+        let mut chunk = [0; UPDATE_CHUNK_LEN];
+        let mut chunk_len = 0;
+        let mut len = 0_usize;
+        for sample in mono_samples_iter {
+            chunk[chunk_len] = sample;
+            chunk_len += 1;
+            if chunk_len == UPDATE_CHUNK_LEN {
+                self.audio_buffer.extend_from_slice(&chunk);
+                len += chunk_len;
+                chunk_len = 0;
+            }
+        }
+        self.audio_buffer.extend_from_slice(&chunk[..chunk_len]);
+        len += chunk_len;
+
+        self.on_samples_added(len);
+    }
+
+    /// Like [`Self::update`] but takes the samples from a slice, which are
+    /// copied in bulk without an intermediate chunk.
+    #[inline]
+    pub fn update_from_slice(&mut self, mono_samples: &[i16]) {
+        self.audio_buffer.extend_from_slice(mono_samples);
+        self.on_samples_added(mono_samples.len());
+    }
+
+    fn on_samples_added(&mut self, len: usize) {
+        self.total_consumed_samples += len as u64;
+
+        if len >= self.audio_buffer.capacity() {
+            log::warn!(
+                "Adding {} samples to the audio buffer that only has a capacity for {} samples.",
+                len,
+                self.audio_buffer.capacity()
+            );
+            #[cfg(test)]
+            std::eprintln!(
+                "WARN: AudioHistory::update: Adding {} samples to the audio buffer that only has a capacity for {} samples.",
+                len,
+                self.audio_buffer.capacity()
+            );
+        }
+    }
+
+    /// Get the passed time in seconds.
+    #[inline]
+    pub fn passed_time(&self) -> Duration {
+        self.timestamp_of_sample(self.total_consumed_samples)
+    }
+
+    /// Returns the amount of samples that were added since the history was
+    /// created.
+    #[inline]
+    pub const fn total_consumed_samples(&self) -> u64 {
+        self.total_consumed_samples
+    }
+
+    /// Returns the amount of samples in the history.
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.audio_buffer.len()
+    }
+
+    /// Returns whether the history doesn't hold any samples yet.
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum amount of samples the history can hold.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        self.audio_buffer.capacity()
+    }
+
+    /// Iterates the samples from oldest to newest.
+    ///
+    /// All internal consumers access the samples through this, so that the
+    /// underlying buffer stays an implementation detail.
+    #[inline]
+    pub(crate) fn samples(&self) -> impl Iterator<Item = &i16> + '_ {
+        self.audio_buffer.iter()
+    }
+
+    /// Scales all samples from the given total index on by `factor`, with
+    /// saturation. Samples that are older than the history are skipped.
+    pub(crate) fn scale_since(&mut self, total_index: u64, factor: f32) {
+        let oldest_total_index = self.total_consumed_samples - self.len() as u64;
+        let begin = total_index.saturating_sub(oldest_total_index) as usize;
+        for index in begin..self.len() {
+            let sample = &mut self.audio_buffer[index];
+            *sample = saturating_f32_to_i16(*sample as f32 * factor);
+        }
+    }
+
+    /// Returns the captured audio as two slices without copying. The first
+    /// slice holds the older samples. Concatenated, they form the history
+    /// from oldest to newest sample. The second slice is empty if the data
+    /// is contiguous.
+    #[inline]
+    pub fn as_slices(&self) -> (&[i16], &[i16]) {
+        self.audio_buffer.as_slices()
+    }
+
+    /// Copies the latest samples into `dst`, ordered from oldest to newest.
+    /// If the history holds fewer samples than `dst`, only the beginning of
+    /// `dst` is written.
+    ///
+    /// Returns the amount of copied samples.
+    pub fn copy_latest_into(&self, dst: &mut [i16]) -> usize {
+        let (older, newer) = self.as_slices();
+        let count = dst.len().min(older.len() + newer.len());
+        let (dst, _) = dst.split_at_mut(count);
+
+        // Copy from the back, as we want the latest samples.
+        let from_newer = count.min(newer.len());
+        let from_older = count - from_newer;
+        dst[..from_older].copy_from_slice(&older[older.len() - from_older..]);
+        dst[from_older..].copy_from_slice(&newer[newer.len() - from_newer..]);
+        count
+    }
+
+    /// Returns the [`SampleInfo`] about a sample from the current index of that
+    /// sample.
+    #[inline]
+    pub fn index_to_sample_info(&self, index: usize) -> SampleInfo {
+        assert!(index < self.capacity());
+
+        let timestamp = self.timestamp_of_index(index);
+        let value = self.audio_buffer[index];
+        let total_index = self.index_to_sample_number(index);
+        SampleInfo {
+            index,
+            timestamp,
+            value,
+            value_abs: value.abs(),
+            total_index,
+            duration_behind: self.timestamp_of_index(self.len() - 1) - timestamp,
+            source: SourcePosition {
+                samples: total_index,
+                time: timestamp,
+            },
+        }
+    }
+
+    /// Returns the index in the current captured audio window from the total
+    /// index of the given sample, if present.
+    #[inline]
+    pub const fn total_index_to_index(&self, total_index: u64) -> Option<usize> {
+        // TODO this looks way too complicated. Probably can be simplified.
+        if self.lost_samples() == 0 {
+            if total_index < self.total_consumed_samples {
+                Some(total_index as usize)
+            } else {
+                None
+            }
+        } else if total_index < self.lost_samples() {
+            None
+        } else {
+            let index = total_index - self.lost_samples();
+            if index <= self.capacity() as u64 {
+                Some(index as usize)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Returns the sample number that an index belongs to. Note that a higher
+    /// index and a higher sample number means fresher data.
+    ///
+    /// This function takes care of the fact that the underlying ringbuffer will
+    /// overflow over time and indices will change.
+    #[inline]
+    fn index_to_sample_number(&self, index: usize) -> u64 {
+        assert!(index <= self.len());
+        index as u64 + self.lost_samples()
+    }
+
+    /// Returns the amount of lost samples, i.e., samples that are no in the
+    /// underlying ringbuffer anymore.
+    #[inline]
+    const fn lost_samples(&self) -> u64 {
+        self.total_consumed_samples
+            .saturating_sub(self.capacity() as u64)
+    }
+
+    /// Returns the relative timestamp (passed duration) of the given sample,
+    /// it is in the range.
+    #[inline]
+    fn timestamp_of_sample(&self, sample_num: u64) -> Duration {
+        if sample_num > self.total_consumed_samples {
+            return Duration::default();
+        };
+
+        // sample_num / (millihz / 1000) in nanoseconds. u128 prevents
+        // overflows in the intermediate result.
+        let nanos = sample_num as u128 * NANOS_PER_SECOND * MILLIHERTZ_PER_HERTZ as u128
+            / self.sampling_frequency_millihz as u128;
+        Duration::new(
+            (nanos / NANOS_PER_SECOND) as u64,
+            (nanos % NANOS_PER_SECOND) as u32,
+        )
+    }
+
+    /// Convenient accessor over [`Self::timestamp_of_sample`] and
+    /// [`Self::index_to_sample_number`]
+    #[inline]
+    fn timestamp_of_index(&self, index: usize) -> Duration {
+        let sample_number = self.index_to_sample_number(index);
+        self.timestamp_of_sample(sample_number)
+    }
+
+    /// Getter for the sampling frequency.
+    pub fn sampling_frequency(&self) -> f32 {
+        self.sampling_frequency_millihz as f32 / MILLIHERTZ_PER_HERTZ
+    }
+}
+
+/// Identifies a snapshot of an [`AudioHistory`].
+const SNAPSHOT_MAGIC: [u8; 4] = *b"BDAH";
+/// Version of the snapshot format. Bump on incompatible changes.
+const SNAPSHOT_VERSION: u8 = 1;
+/// Magic, version, sampling frequency (millihertz), capacity, total consumed
+/// samples, and number of samples.
+const SNAPSHOT_HEADER_LEN: usize = 4 + 1 + 8 + 4 + 8 + 4;
+
+/// Errors when creating or loading a snapshot of an [`AudioHistory`] or of a
+/// [`BeatDetector`].
+///
+/// [`BeatDetector`]: crate::BeatDetector
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    /// The destination buffer can't hold the snapshot.
+    BufferTooSmall {
+        /// Size of the snapshot in bytes.
+        required: usize,
+    },
+    /// The data is not a snapshot or it is truncated.
+    InvalidFormat,
+    /// The snapshot was created by an incompatible version of this crate.
+    UnsupportedVersion(u8),
+    /// The capacity of the snapshot exceeds the storage of the history.
+    CapacityTooLarge {
+        /// Capacity of the snapshot.
+        capacity: usize,
+    },
+    /// The snapshot was taken by a detector with another downsample factor.
+    DownsampleFactorMismatch {
+        /// Downsample factor of the detector of the snapshot.
+        downsample_factor: usize,
+    },
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BufferTooSmall { required } => {
+                write!(f, "buffer too small, {required} bytes required")
+            }
+            Self::InvalidFormat => f.write_str("not a valid snapshot"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {version}")
+            }
+            Self::CapacityTooLarge { capacity } => {
+                write!(f, "capacity {capacity} exceeds the storage of the history")
+            }
+            Self::DownsampleFactorMismatch { downsample_factor } => {
+                write!(
+                    f,
+                    "snapshot has another downsample factor {downsample_factor}"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SnapshotError {}
+
+impl<const N: usize> AudioHistory<N> {
+    /// Returns the size of the snapshot in bytes.
+    pub const fn snapshot_len(&self) -> usize {
+        SNAPSHOT_HEADER_LEN + self.len() * 2
+    }
+
+    /// Serializes the current state, i.e., the captured audio window, the
+    /// counters, and the configuration, into a compact binary format. The
+    /// snapshot can be loaded with [`Self::from_snapshot`], for example, to
+    /// attach the exact state that led to a wrong detection to a bug report.
+    ///
+    /// Returns the amount of written bytes, see [`Self::snapshot_len`].
+    pub fn snapshot_into(&self, buf: &mut [u8]) -> Result<usize, SnapshotError> {
+        let required = self.snapshot_len();
+        let buf = buf
+            .get_mut(..required)
+            .ok_or(SnapshotError::BufferTooSmall { required })?;
+
+        let (header, samples) = buf.split_at_mut(SNAPSHOT_HEADER_LEN);
+        let fields = [
+            &SNAPSHOT_MAGIC[..],
+            &[SNAPSHOT_VERSION],
+            &self.sampling_frequency_millihz.to_le_bytes(),
+            &(self.capacity() as u32).to_le_bytes(),
+            &self.total_consumed_samples.to_le_bytes(),
+            &(self.len() as u32).to_le_bytes(),
+        ];
+        let mut pos = 0;
+        for field in fields {
+            header[pos..pos + field.len()].copy_from_slice(field);
+            pos += field.len();
+        }
+        for (bytes, sample) in samples.chunks_exact_mut(2).zip(self.samples()) {
+            bytes.copy_from_slice(&sample.to_le_bytes());
+        }
+        Ok(required)
+    }
+
+    /// Like [`Self::snapshot_into`], but allocates the buffer.
+    #[cfg(feature = "std")]
+    pub fn snapshot(&self) -> std::vec::Vec<u8> {
+        let mut buf = vec![0; self.snapshot_len()];
+        self.snapshot_into(&mut buf)
+            .expect("buffer should have the right size");
+        buf
+    }
+
+    /// Restores a history from a snapshot created with
+    /// [`Self::snapshot_into`].
+    ///
+    /// For the default storage size, the type must be annotated:
+    /// `let history: AudioHistory = AudioHistory::from_snapshot(&data)?;`
+    pub fn from_snapshot(snapshot: &[u8]) -> Result<Self, SnapshotError> {
+        if snapshot.len() < SNAPSHOT_HEADER_LEN {
+            return Err(SnapshotError::InvalidFormat);
+        }
+        let (header, samples) = snapshot.split_at(SNAPSHOT_HEADER_LEN);
+        let mut pos = 0;
+        let mut next = |len: usize| {
+            let field = &header[pos..pos + len];
+            pos += len;
+            field
+        };
+        if next(4) != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::InvalidFormat);
+        }
+        let version = next(1)[0];
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let sampling_frequency_millihz = u64::from_le_bytes(next(8).try_into().unwrap());
+        let capacity = u32::from_le_bytes(next(4).try_into().unwrap()) as usize;
+        let total_consumed_samples = u64::from_le_bytes(next(8).try_into().unwrap());
+        let len = u32::from_le_bytes(next(4).try_into().unwrap()) as usize;
+
+        if capacity > N {
+            return Err(SnapshotError::CapacityTooLarge { capacity });
+        }
+        let consistent = sampling_frequency_millihz > 0
+            && capacity > 0
+            && len as u64 == total_consumed_samples.min(capacity as u64)
+            && samples.len() == len * 2;
+        if !consistent {
+            return Err(SnapshotError::InvalidFormat);
+        }
+
+        let mut history = Self::with_capacity(1.0, capacity);
+        history.sampling_frequency_millihz = sampling_frequency_millihz;
+        history.total_consumed_samples = total_consumed_samples;
+        for bytes in samples.chunks_exact(2) {
+            history
+                .audio_buffer
+                .push(i16::from_le_bytes([bytes[0], bytes[1]]));
+        }
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::iter;
+    use std::vec::Vec;
+
+    #[test]
+    fn buffer_len_sane() {
+        let sampling_rate = 1.0 / DEFAULT_SAMPLES_PER_SECOND as f32;
+        let duration = Duration::from_secs_f32(sampling_rate * DEFAULT_BUFFER_SIZE as f32);
+        assert!(duration.as_millis() > 10);
+        assert!(duration.as_millis() <= 1000);
+    }
+
+    #[test]
+    fn audio_duration_is_updated_properly() {
+        let mut hist = AudioHistory::new(2.0);
+        assert_eq!(hist.total_consumed_samples, 0);
+
+        hist.update(iter::once(0));
+        assert_eq!(hist.total_consumed_samples, 1);
+        assert_eq!(hist.passed_time(), Duration::from_secs_f32(0.5));
+
+        hist.update([0, 0].iter().copied());
+        assert_eq!(hist.total_consumed_samples, 3);
+        assert_eq!(hist.passed_time(), Duration::from_secs_f32(1.5));
+    }
+
+    #[test]
+    fn index_to_sample_number_works_across_ringbuffer_overflow() {
+        let mut hist = AudioHistory::new(2.0);
+
+        let test_data = [0; DEFAULT_BUFFER_SIZE + 10];
+
+        hist.update(test_data[0..10].iter().copied());
+        assert_eq!(hist.index_to_sample_number(0), 0);
+        assert_eq!(hist.index_to_sample_number(10), 10);
+
+        // now the buffer is full, but no overflow yet
+        hist.update(test_data[10..DEFAULT_BUFFER_SIZE].iter().copied());
+        assert_eq!(hist.index_to_sample_number(0), 0);
+        assert_eq!(hist.index_to_sample_number(10), 10);
+        assert_eq!(
+            hist.index_to_sample_number(DEFAULT_BUFFER_SIZE),
+            DEFAULT_BUFFER_SIZE as u64
+        );
+
+        // now the buffer overflowed
+        hist.update(
+            test_data[DEFAULT_BUFFER_SIZE..DEFAULT_BUFFER_SIZE + 10]
+                .iter()
+                .copied(),
+        );
+        assert_eq!(hist.index_to_sample_number(0), 10);
+        assert_eq!(hist.index_to_sample_number(10), 20);
+        assert_eq!(
+            hist.index_to_sample_number(DEFAULT_BUFFER_SIZE),
+            DEFAULT_BUFFER_SIZE as u64 + 10
+        );
+    }
+
+    #[test]
+    // transitively tests timestamp_of_sample()
+    fn timestamp_of_index_properly_calculated() {
+        let mut hist = AudioHistory::new(2.0);
+
+        let test_data = [0; DEFAULT_BUFFER_SIZE + 10];
+
+        hist.update(test_data[0..10].iter().copied());
+        assert_eq!(hist.timestamp_of_index(0), Duration::from_secs_f32(0.0));
+        assert_eq!(hist.timestamp_of_index(10), Duration::from_secs_f32(5.0));
+
+        // now the buffer is full, but no overflow yet
+        hist.update(test_data[10..DEFAULT_BUFFER_SIZE].iter().copied());
+        assert_eq!(hist.timestamp_of_index(0), Duration::from_secs_f32(0.0));
+        assert_eq!(hist.timestamp_of_index(10), Duration::from_secs_f32(5.0));
+
+        // now the buffer overflowed
+        hist.update(
+            test_data[DEFAULT_BUFFER_SIZE..DEFAULT_BUFFER_SIZE + 10]
+                .iter()
+                .copied(),
+        );
+        assert_eq!(hist.timestamp_of_index(0), Duration::from_secs_f32(5.0));
+        assert_eq!(hist.timestamp_of_index(10), Duration::from_secs_f32(10.0));
+    }
+
+    #[test]
+    fn timestamps_stay_precise_in_long_running_sessions() {
+        let mut hist = AudioHistory::new(44100.0);
+        // Pretend that the history already consumed one week of audio.
+        let one_week_in_samples = 44100 * 60 * 60 * 24 * 7;
+        hist.total_consumed_samples = one_week_in_samples - 1;
+        hist.update(iter::once(0));
+
+        let expected = Duration::from_secs(60 * 60 * 24 * 7);
+        assert_eq!(hist.passed_time(), expected);
+        assert_eq!(hist.timestamp_of_sample(one_week_in_samples), expected);
+    }
+
+    #[test]
+    fn timestamps_are_exact_for_common_sampling_rates() {
+        for sampling_rate in [44100, 48000, 88200, 96000, 192000] {
+            let mut hist = AudioHistory::new(sampling_rate as f32);
+            for _ in 0..3 {
+                hist.update([0].repeat(sampling_rate).iter().copied());
+            }
+            assert_eq!(hist.passed_time(), Duration::from_secs(3));
+            assert_eq!(
+                hist.timestamp_of_sample(sampling_rate as u64 / 2),
+                Duration::from_millis(500)
+            );
+        }
+    }
+
+    #[test]
+    fn audio_history_on_real_data() {
+        let (samples, header) = crate::test_utils::samples::sample1_long();
+
+        let mut history = AudioHistory::new(header.sample_rate as f32);
+        history.update(samples.iter().copied());
+
+        // 352735 samples at 44.1 kHz
+        assert_eq!(history.passed_time(), Duration::from_nanos(7_998_526_077));
+
+        let timestamp_at_end = history
+            .index_to_sample_info(history.capacity() - 1)
+            .timestamp;
+        assert_eq!(timestamp_at_end, Duration::from_nanos(7_998_503_401));
+    }
+
+    #[test]
+    fn sample_info() {
+        let mut hist = AudioHistory::new(1.0);
+
+        hist.update(iter::once(0));
+        assert_eq!(
+            hist.index_to_sample_info(0).duration_behind,
+            Duration::from_secs(0)
+        );
+        hist.update(iter::once(0));
+        assert_eq!(
+            hist.index_to_sample_info(0).duration_behind,
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            hist.index_to_sample_info(1).duration_behind,
+            Duration::from_secs(0)
+        );
+
+        hist.update([0].repeat(hist.capacity() * 2).iter().copied());
+
+        assert_eq!(
+            hist.index_to_sample_info(0).duration_behind,
+            Duration::from_secs_f32((DEFAULT_BUFFER_SIZE - 1) as f32)
+        );
+        assert_eq!(
+            hist.index_to_sample_info(DEFAULT_BUFFER_SIZE - 10)
+                .duration_behind,
+            Duration::from_secs_f32(9.0)
+        );
+        assert_eq!(
+            hist.index_to_sample_info(DEFAULT_BUFFER_SIZE - 1)
+                .duration_behind,
+            Duration::from_secs(0)
+        );
+    }
+
+    /// Ensure that [`SampleInfo`] is ordered by `total_index`.
+    #[test]
+    fn sample_info_ordering() {
+        assert_eq!(
+            SampleInfo {
+                total_index: 0,
+                ..Default::default()
+            },
+            SampleInfo {
+                total_index: 0,
+                ..Default::default()
+            }
+        );
+
+        assert!(
+            SampleInfo {
+                total_index: 0,
+                ..Default::default()
+            } < SampleInfo {
+                total_index: 1,
+                ..Default::default()
+            }
+        );
+
+        assert!(
+            SampleInfo {
+                total_index: 11,
+                ..Default::default()
+            } > SampleInfo {
+                total_index: 10,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn total_index_to_index_works() {
+        let mut history = AudioHistory::new(1.0);
+        for i in 0..history.capacity() {
+            assert_eq!(history.total_index_to_index(i as u64), None);
+            history.update(iter::once(0));
+            assert_eq!(history.total_index_to_index(i as u64), Some(i));
+        }
+
+        history.update(iter::once(0));
+        // No longer existing.
+        assert_eq!(history.total_index_to_index(0), None);
+        assert_eq!(history.total_index_to_index(1), Some(0));
+        assert_eq!(history.total_index_to_index(2), Some(1));
+        assert_eq!(
+            history.total_index_to_index(history.total_consumed_samples),
+            Some(history.capacity())
+        );
+    }
+
+    #[test]
+    fn counters_work_across_32_bit_boundary() {
+        let mut history = AudioHistory::new(44100.0);
+        // Simulate a session that ran for ~27 hours at 44.1 kHz.
+        let samples_before_boundary = u32::MAX as u64 - 5;
+        history.total_consumed_samples = samples_before_boundary;
+        history.update([0].repeat(history.capacity()).iter().copied());
+
+        let capacity = history.capacity() as u64;
+        let total_consumed_samples = samples_before_boundary + capacity;
+        assert_eq!(history.total_consumed_samples, total_consumed_samples);
+        assert!(history.total_consumed_samples > u32::MAX as u64);
+
+        assert_eq!(history.lost_samples(), samples_before_boundary);
+        assert_eq!(
+            history.index_to_sample_info(0).total_index,
+            samples_before_boundary
+        );
+        assert_eq!(
+            history.index_to_sample_info(10).total_index,
+            u32::MAX as u64 + 5
+        );
+        assert_eq!(history.total_index_to_index(u32::MAX as u64 + 5), Some(10));
+        assert_eq!(
+            history.total_index_to_index(samples_before_boundary - 1),
+            None
+        );
+        assert_eq!(
+            history.passed_time(),
+            Duration::from_nanos(total_consumed_samples * 1_000_000_000 / 44100)
+        );
+    }
+
+    #[test]
+    fn zero_copy_access() {
+        let mut history = AudioHistory::new(1000.0);
+        let mut dst = [0; 4];
+        assert_eq!(history.as_slices(), (&[][..], &[][..]));
+        assert_eq!(history.copy_latest_into(&mut dst), 0);
+
+        history.update([1, 2, 3].iter().copied());
+        assert_eq!(history.as_slices(), (&[1, 2, 3][..], &[][..]));
+        assert_eq!(history.copy_latest_into(&mut dst), 3);
+        assert_eq!(dst, [1, 2, 3, 0]);
+
+        // Let the ringbuffer overflow.
+        let capacity = history.capacity() as i16;
+        history.update(4..=capacity + 2);
+        let (older, newer) = history.as_slices();
+        let all = older.iter().chain(newer).copied().collect::<Vec<_>>();
+        assert_eq!(all.len(), capacity as usize);
+        assert_eq!(all[0], 3);
+        assert_eq!(all[all.len() - 2..], [capacity + 1, capacity + 2]);
+
+        assert_eq!(history.copy_latest_into(&mut dst), 4);
+        assert_eq!(dst, [capacity - 1, capacity, capacity + 1, capacity + 2]);
+
+        let mut dst = [0; 1];
+        assert_eq!(history.copy_latest_into(&mut dst), 1);
+        assert_eq!(dst, [capacity + 2]);
+    }
+
+    #[test]
+    fn update_from_slice_matches_update() {
+        let (samples, header) = crate::test_utils::samples::holiday_long();
+        let mut expected = AudioHistory::new(header.sample_rate as f32);
+        let mut actual = AudioHistory::new(header.sample_rate as f32);
+        // Chunks that aren't a multiple of the internal chunk size of
+        // `update`.
+        for chunk in samples.chunks(4000) {
+            expected.update(chunk.iter().copied());
+            actual.update_from_slice(chunk);
+        }
+        assert_eq!(actual.passed_time(), expected.passed_time());
+        assert!(actual.samples().eq(expected.samples()));
+    }
+
+    #[test]
+    fn scale_since() {
+        let mut history = AudioHistory::<8>::with_capacity(1.0, 4);
+        history.update([100, 200, 300, 400, 500, -600].iter().copied());
+        history.scale_since(4, 2.0);
+        assert!(history.samples().eq(&[300, 400, 1000, -1200]));
+        history.scale_since(0, 100.0);
+        assert!(history
+            .samples()
+            .eq(&[30000, i16::MAX, i16::MAX, -i16::MAX]));
+    }
+
+    /// [`AudioHistory::snapshot`] is only available with the `std` feature.
+    fn snapshot<const N: usize>(hist: &AudioHistory<N>) -> Vec<u8> {
+        let mut buf = vec![0; hist.snapshot_len()];
+        hist.snapshot_into(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let (samples, header) = crate::test_utils::samples::holiday_long();
+        let mut hist = AudioHistory::new(header.sample_rate as f32);
+        hist.update(samples[..30000].iter().copied());
+
+        let snapshot = snapshot(&hist);
+        assert_eq!(snapshot.len(), hist.snapshot_len());
+        let loaded: AudioHistory = AudioHistory::from_snapshot(&snapshot).unwrap();
+        assert_eq!(loaded.capacity(), hist.capacity());
+        assert_eq!(loaded.total_consumed_samples(), 30000);
+        assert_eq!(loaded.sampling_frequency(), 44100.0);
+        assert!(loaded.samples().eq(hist.samples()));
+        assert_eq!(
+            loaded.index_to_sample_info(100).timestamp,
+            hist.index_to_sample_info(100).timestamp
+        );
+
+        // Smaller storage that still fits the capacity.
+        let mut small = AudioHistory::<8>::with_capacity(2.0, 5);
+        small.update([1, 2, 3].iter().copied());
+        let mut buf = [0; 64];
+        let len = small.snapshot_into(&mut buf).unwrap();
+        let loaded = AudioHistory::<8>::from_snapshot(&buf[..len]).unwrap();
+        assert!(loaded.samples().eq([1, 2, 3].iter()));
+    }
+
+    #[test]
+    fn snapshot_errors() {
+        let mut hist = AudioHistory::<8>::with_capacity(2.0, 8);
+        hist.update([1, 2, 3].iter().copied());
+        assert_eq!(
+            hist.snapshot_into(&mut [0; 10]),
+            Err(SnapshotError::BufferTooSmall { required: 35 })
+        );
+
+        let snapshot = snapshot(&hist);
+        assert_eq!(
+            AudioHistory::<4>::from_snapshot(&snapshot).unwrap_err(),
+            SnapshotError::CapacityTooLarge { capacity: 8 }
+        );
+        assert_eq!(
+            AudioHistory::<8>::from_snapshot(&snapshot[..snapshot.len() - 1]).unwrap_err(),
+            SnapshotError::InvalidFormat
+        );
+        let mut modified = snapshot.clone();
+        modified[0] = b'X';
+        assert_eq!(
+            AudioHistory::<8>::from_snapshot(&modified).unwrap_err(),
+            SnapshotError::InvalidFormat
+        );
+        let mut modified = snapshot;
+        modified[4] = 42;
+        assert_eq!(
+            AudioHistory::<8>::from_snapshot(&modified).unwrap_err(),
+            SnapshotError::UnsupportedVersion(42)
+        );
+    }
+}
@@ -0,0 +1,316 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`BassPitch`].
+
+use crate::{AudioHistory, BeatInfo};
+use core::ops::Range;
+
+/// Lowest frequency that is considered a bass note, slightly below B0.
+const MIN_FREQUENCY_HZ: f32 = 30.0;
+
+/// Highest frequency that is considered a bass note, slightly above B3.
+const MAX_FREQUENCY_HZ: f32 = 250.0;
+
+/// The audio is decimated to at least this sampling frequency before the
+/// autocorrelation, which is plenty for bass notes and keeps it cheap.
+const DECIMATED_SAMPLING_FREQUENCY_HZ: f32 = 4000.0;
+
+/// Duration before the maximum of a beat that belongs to its attack and is
+/// excluded from [`BassPitch::between_beats`].
+const ATTACK_DURATION_MS: f32 = 20.0;
+
+/// Maximum amount of decimated samples that are analyzed, i.e., at least the
+/// latest 256 ms before the beat.
+const MAX_WINDOW: usize = 1024;
+
+/// Largest possible lag of the autocorrelation, with a decimated sampling
+/// frequency just below twice [`DECIMATED_SAMPLING_FREQUENCY_HZ`].
+const MAX_LAG: usize = (2.0 * DECIMATED_SAMPLING_FREQUENCY_HZ / MIN_FREQUENCY_HZ) as usize + 1;
+
+/// The first peak of the autocorrelation that reaches this fraction of the
+/// highest peak is the period. This avoids octave errors, as multiples of
+/// the period correlate about as well as the period itself.
+const PEAK_THRESHOLD: f32 = 0.9;
+
+/// Minimum [`BassPitch::clarity`] of a pitch. Below, the audio has no
+/// distinct pitch, such as noise or a chord.
+const MIN_CLARITY: f32 = 0.6;
+
+/// Approximate pitch of the bassline, estimated without an FFT.
+///
+/// It uses the normalized autocorrelation of the audio between two beats, so
+/// that the kick drum doesn't mask the bass. This only works on the lowpassed
+/// audio, which the [history] of a detector holds if it applies the lowpass
+/// filter. It is optional and costs a few hundred thousand multiplications
+/// per estimate, so call it once per beat at most.
+///
+/// With [`Self::hue`], light colors can follow the bassline.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BassPitch, BeatDetector};
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut previous_beat = None;
+///
+/// // TODO regularly call this with the latest audio data.
+/// if let Some(beat) = detector.update_and_detect_beat(mono_samples.iter().copied()) {
+///     let pitch = BassPitch::between_beats(detector.history(), previous_beat.as_ref(), &beat);
+///     if let Some(pitch) = pitch {
+///         let pitch_class = pitch.pitch_class();
+///     }
+///     previous_beat = Some(beat);
+/// }
+/// ```
+///
+/// [history]: crate::BeatDetector::history
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct BassPitch {
+    /// Fundamental frequency in Hz.
+    pub frequency_hz: f32,
+    /// How periodic the audio is, in range `0.0..=1.0`. `1.0` means a pure
+    /// tone.
+    pub clarity: f32,
+}
+
+impl BassPitch {
+    /// Estimates the pitch of the bass between two beats, i.e., in the audio
+    /// after `previous` and before the attack of `beat`. Without a previous
+    /// beat, or if it is no longer in the history, the oldest audio of the
+    /// history is used. Returns `None` if there is no distinct pitch or too
+    /// little audio.
+    pub fn between_beats<const N: usize>(
+        history: &AudioHistory<N>,
+        previous: Option<&BeatInfo>,
+        beat: &BeatInfo,
+    ) -> Option<Self> {
+        let begin = previous.map_or(0, |previous| previous.to.total_index + 1);
+        // The envelope of a beat often begins long before its attack, when
+        // the bass is loud, so this goes back from the maximum instead.
+        let attack = (ATTACK_DURATION_MS * history.sampling_frequency() / 1000.0) as u64;
+        let end = beat.max.total_index.saturating_sub(attack);
+        Self::estimate(history, begin..end)
+    }
+
+    /// Estimates the pitch of the bass in the given range of samples, in
+    /// total indices like [`crate::SampleInfo::total_index`]. The part of
+    /// the range that is no longer in the history is ignored. Long ranges are
+    /// limited to the latest audio.
+    pub fn estimate<const N: usize>(history: &AudioHistory<N>, range: Range<u64>) -> Option<Self> {
+        let oldest_total_index = history.total_consumed_samples() - history.len() as u64;
+        let begin = (range.start.max(oldest_total_index) - oldest_total_index) as usize;
+        let end = (range.end.min(history.total_consumed_samples()) - oldest_total_index) as usize;
+        if end <= begin {
+            return None;
+        }
+
+        // Averaging blocks of samples decimates the audio, with a crude
+        // lowpass filter against aliasing.
+        let step =
+            ((history.sampling_frequency() / DECIMATED_SAMPLING_FREQUENCY_HZ) as usize).max(1);
+        let sampling_frequency = history.sampling_frequency() / step as f32;
+        let mut window = [0.0; MAX_WINDOW];
+        let len = ((end - begin) / step).min(MAX_WINDOW);
+        let begin = end - len * step;
+        let samples = history.samples().skip(begin).take(len * step);
+        let mut samples = samples.map(|&sample| sample as f32 / i16::MAX as f32);
+        for value in window[..len].iter_mut() {
+            *value = samples.by_ref().take(step).sum::<f32>() / step as f32;
+        }
+        let window = &mut window[..len];
+        let mean = window.iter().sum::<f32>() / len.max(1) as f32;
+        window.iter_mut().for_each(|value| *value -= mean);
+
+        let min_lag = ((sampling_frequency / MAX_FREQUENCY_HZ) as usize).max(1);
+        let max_lag = ((sampling_frequency / MIN_FREQUENCY_HZ) as usize + 1).min(MAX_LAG);
+        // At least two periods of the lowest note must fit into the window.
+        if len < 2 * max_lag {
+            return None;
+        }
+
+        // Normalized square difference function (McLeod): 1.0 for a perfect
+        // correlation, -1.0 for an inverted one.
+        let mut nsdf = [0.0; MAX_LAG + 1];
+        for (lag, nsdf) in nsdf
+            .iter_mut()
+            .enumerate()
+            .take(max_lag + 1)
+            .skip(min_lag - 1)
+        {
+            let (mut correlation, mut energy) = (0.0, 0.0);
+            for (a, b) in window.iter().zip(window[lag..].iter()) {
+                correlation += a * b;
+                energy += a * a + b * b;
+            }
+            *nsdf = if energy > 0.0 {
+                2.0 * correlation / energy
+            } else {
+                0.0
+            };
+        }
+
+        let is_peak = |lag: usize| nsdf[lag - 1] < nsdf[lag] && nsdf[lag] >= nsdf[lag + 1];
+        let peaks = (min_lag..max_lag).filter(|&lag| is_peak(lag));
+        let highest = peaks.clone().map(|lag| nsdf[lag]).fold(0.0, f32::max);
+        let lag = peaks
+            .into_iter()
+            .find(|&lag| nsdf[lag] >= PEAK_THRESHOLD * highest)?;
+        if nsdf[lag] < MIN_CLARITY {
+            return None;
+        }
+
+        // Parabolic interpolation of the peak for a finer resolution than
+        // the decimated sampling frequency.
+        let (left, center, right) = (nsdf[lag - 1], nsdf[lag], nsdf[lag + 1]);
+        let curvature = left - 2.0 * center + right;
+        let offset = if curvature < 0.0 {
+            0.5 * (left - right) / curvature
+        } else {
+            0.0
+        };
+        Some(Self {
+            frequency_hz: sampling_frequency / (lag as f32 + offset),
+            clarity: center.min(1.0),
+        })
+    }
+
+    /// Returns the MIDI note number, such as `33.0` for A1 with 55 Hz. The
+    /// fractional part is the deviation from the tempered note.
+    pub fn midi_note(&self) -> f32 {
+        69.0 + 12.0 * libm::log2f(self.frequency_hz / 440.0)
+    }
+
+    /// Returns the pitch class of the nearest note in range `0..12`, where
+    /// `0` is C, `1` is C#, and so on.
+    pub fn pitch_class(&self) -> u8 {
+        (libm::roundf(self.midi_note()) as i32).rem_euclid(12) as u8
+    }
+
+    /// Returns a hue in degrees for the pitch class, so that each note of an
+    /// octave gets its own color. Fifths are neighbours on the color wheel,
+    /// so that notes that sound well together get similar colors.
+    pub fn hue(&self) -> f32 {
+        // Walk the circle of fifths: 7 semitones per step.
+        ((self.pitch_class() as u32 * 7) % 12) as f32 * 30.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BeatDetector;
+    use std::vec::Vec;
+
+    fn sine(frequency: f32, sampling_frequency: f32, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / sampling_frequency;
+                // With a harmonic, as bass instruments have.
+                let sample = libm::sinf(t * frequency * 2.0 * core::f32::consts::PI)
+                    + 0.5 * libm::sinf(t * frequency * 4.0 * core::f32::consts::PI);
+                (sample * 12000.0) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn estimate() {
+        for (frequency, pitch_class) in [(41.2, 4), (55.0, 9), (98.0, 7), (196.0, 7)] {
+            let mut history = AudioHistory::new(44100.0);
+            history.update(sine(frequency, 44100.0, 11025).into_iter());
+            let pitch = BassPitch::estimate(&history, 0..11025).unwrap();
+            assert!(
+                (pitch.frequency_hz - frequency).abs() < frequency * 0.01,
+                "{frequency}: {pitch:?}"
+            );
+            assert!(pitch.clarity > 0.95);
+            assert_eq!(pitch.pitch_class(), pitch_class);
+        }
+
+        let mut history = AudioHistory::new(44100.0);
+        history.update(core::iter::repeat(0).take(11025));
+        assert_eq!(BassPitch::estimate(&history, 0..11025), None);
+        history.update(sine(55.0, 44100.0, 11025).into_iter());
+        // Too short for the lowest notes.
+        assert_eq!(BassPitch::estimate(&history, 22000..22050), None);
+        // Only the part of the range that is in the history counts.
+        let pitch = BassPitch::estimate(&history, 11025..u64::MAX).unwrap();
+        assert_eq!(pitch.pitch_class(), 9);
+    }
+
+    #[test]
+    fn midi_note_and_hue() {
+        let pitch = |frequency_hz| BassPitch {
+            frequency_hz,
+            clarity: 1.0,
+        };
+        assert_eq!(pitch(440.0).midi_note(), 69.0);
+        assert_eq!(pitch(440.0).pitch_class(), 9);
+        assert_eq!(pitch(65.41).pitch_class(), 0);
+        assert_eq!(pitch(63.0).pitch_class(), 11);
+        assert_eq!(pitch(65.41).hue(), 0.0);
+        // G is a fifth above C.
+        assert_eq!(pitch(98.0).hue(), 30.0);
+    }
+
+    #[test]
+    fn follows_bassline() {
+        // A kick drum every 500 ms over a bassline of A1, C2 and E2.
+        let notes = [55.0, 65.41, 82.41, 55.0, 65.41, 82.41];
+        let samples = notes
+            .iter()
+            .enumerate()
+            .flat_map(|(beat, &frequency)| {
+                let bass = sine(frequency, 44100.0, 22050);
+                bass.into_iter().enumerate().map(move |(i, bass)| {
+                    let t = i as f32 / 44100.0;
+                    let kick =
+                        libm::sinf(t * 50.0 * 2.0 * core::f32::consts::PI) * libm::expf(-t * 30.0);
+                    let kick = if beat == 0 { 0.0 } else { kick * 25000.0 };
+                    (bass as f32 * 0.3 + kick) as i16
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut detector = BeatDetector::new(44100.0, true);
+        let mut previous_beat = None;
+        let mut pitch_classes = Vec::new();
+        for chunk in samples.chunks(2048) {
+            if let Some(beat) = detector.update_and_detect_beat(chunk.iter().copied()) {
+                let pitch =
+                    BassPitch::between_beats(detector.history(), previous_beat.as_ref(), &beat);
+                pitch_classes.push(pitch.map(|pitch| pitch.pitch_class()));
+                previous_beat = Some(beat);
+            }
+        }
+        // The note before each beat: A, C, E, and so on.
+        assert_eq!(
+            pitch_classes,
+            [Some(9), Some(0), Some(4), Some(9), Some(0)],
+            "{pitch_classes:?}"
+        );
+    }
+}
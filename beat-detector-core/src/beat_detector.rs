@@ -0,0 +1,3237 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`BeatDetector`].
+
+use crate::adaptive_statistics::AdaptiveStatistics;
+use crate::audio_history::{
+    BUFFER_STORAGE_SIZE, DEFAULT_AUDIO_HISTORY_WINDOW_MS, DEFAULT_BUFFER_SIZE,
+};
+use crate::calibration::Calibration;
+use crate::custom_filter::{BiquadCoefficients, CustomFilter, CustomFilterChain, FilterStage};
+use crate::decision_trace::{Decision, DecisionTrace, DecisionTraceEntry};
+use crate::diagnosis::{self, ClippingDetector, Diagnosis};
+use crate::envelope_iterator::{EnvelopeConfig, ENVELOPE_MIN_DURATION_MS};
+use crate::gain_normalizer::GainNormalizer;
+use crate::hum_filter::HumFilter;
+use crate::noise_profile::NoiseSuppressor;
+use crate::peak_cache::{PeakCache, MAX_TRACKED_PEAKS};
+use crate::root_iterator::DEFAULT_SCAN_STRIDE;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::sustain_suppressor::SustainSuppressor;
+use crate::util;
+use crate::EnvelopeInfo;
+use crate::{
+    AmplitudeHistogram, AudioHistory, EnvelopeIterator, MainsFrequency, NoiseProfile, SampleInfo,
+    SnapshotError, SourcePosition, NOISE_PROFILE_BANDS,
+};
+use biquad::{Coefficients, ToHertz, Type, Q_BUTTERWORTH_F32};
+use core::fmt::Debug;
+use core::mem::MaybeUninit;
+use core::ops::RangeInclusive;
+use core::ptr::addr_of_mut;
+use core::time::Duration;
+
+/// Cutoff frequency for the lowpass filter to detect beats.
+const CUTOFF_FREQUENCY_HZ: f32 = 95.0;
+
+/// Center frequency of the emphasis of [`FrequencyWeighting::KickEmphasis`].
+const KICK_EMPHASIS_CENTER_HZ: f32 = 70.0;
+/// Emphasis of [`FrequencyWeighting::KickEmphasis`] relative to the other
+/// frequencies. Wide enough to cover roughly 50 to 100 Hz.
+const KICK_EMPHASIS_GAIN_DB: f32 = 6.0;
+const KICK_EMPHASIS_Q: f32 = 0.7;
+/// Cutoff of the lowpass filter of [`FrequencyWeighting::KickEmphasis`]. The
+/// emphasis already singles out the kick drums, so the roll-off can be gentle.
+const KICK_EMPHASIS_CUTOFF_HZ: f32 = 150.0;
+
+/// Weighting of the frequencies of the audio input, before beats are
+/// searched. Only applies if the detector uses its lowpass filter.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FrequencyWeighting {
+    /// Lowpass filter with a cutoff at 95 Hz.
+    #[default]
+    Lowpass,
+    /// Emphasizes the frequencies of kick drums between roughly 50 and 100 Hz
+    /// and rolls off above with a lowpass filter at 150 Hz. This tracks kick
+    /// drums across genres better than [`Self::Lowpass`], as it keeps more of
+    /// the attack of the kick drums. The kick drums pass with unity gain and
+    /// the other frequencies are attenuated, so that loud kick drums don't
+    /// clip.
+    KickEmphasis,
+}
+
+impl FrequencyWeighting {
+    /// Returns the cutoff frequency of the lowpass filter.
+    const fn cutoff_frequency_hz(self) -> f32 {
+        match self {
+            Self::Lowpass => CUTOFF_FREQUENCY_HZ,
+            Self::KickEmphasis => KICK_EMPHASIS_CUTOFF_HZ,
+        }
+    }
+}
+
+/// Frequency at which the group delay of a [`CustomFilter`] is compensated.
+const CUSTOM_FILTER_GROUP_DELAY_HZ: f32 = 60.0;
+
+/// Frequencies of [`BeatDetectorConst::frequency_response_into`].
+const FREQUENCY_RESPONSE_RANGE_HZ: RangeInclusive<f32> = 10.0..=1000.0;
+
+/// Duration of audio that is fed through the lowpass filter before the first
+/// real sample, so that the filter doesn't start with a transient.
+const LOWPASS_FILTER_PRIMING_DURATION_MS: f32 = 20.0;
+
+/// Amount of audio the detector must have consumed before beats are reported.
+/// Before that, the statistics over the audio history are not meaningful and
+/// cause false positives. One minimum envelope duration is the least we need.
+const WARM_UP_DURATION: Duration = Duration::from_millis(ENVELOPE_MIN_DURATION_MS);
+
+/// Default for [`BeatDetector::set_search_overlap`].
+pub const DEFAULT_SEARCH_OVERLAP: Duration = Duration::ZERO;
+
+/// Default for [`BeatDetector::set_statistics_decay`].
+pub const DEFAULT_STATISTICS_DECAY: Duration = Duration::from_secs(3);
+
+/// Range of [`BeatDetector::set_statistics_decay`].
+const STATISTICS_DECAY_RANGE: RangeInclusive<Duration> =
+    Duration::from_secs(2)..=Duration::from_secs(10);
+
+/// Identifies a snapshot of a [`BeatDetectorConst`].
+const SNAPSHOT_MAGIC: [u8; 4] = *b"BDDS";
+/// Version of the snapshot format. Bump on incompatible changes.
+const SNAPSHOT_VERSION: u8 = 1;
+
+/// Information about a beat.
+pub type BeatInfo = EnvelopeInfo;
+
+/// State of a [`BeatDetectorConst`] that survives a change of the sampling
+/// rate, such as when a resampler is inserted after the audio device
+/// changed. See [`BeatDetectorConst::warm_state`].
+///
+/// It covers the time base, the previous beat, and the configuration of the
+/// detector. The audio history isn't transferred, so the new detector warms
+/// up again.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WarmState {
+    /// Sampling frequency of the audio history of the old detector.
+    history_sampling_frequency: f32,
+    passed_time: Duration,
+    last_beat_time: Option<Duration>,
+    scan_stride: usize,
+    search_overlap: Duration,
+    envelope_config: EnvelopeConfig,
+    frequency_weighting: FrequencyWeighting,
+    hum_filter: Option<MainsFrequency>,
+    sustain_suppression: bool,
+    gain_normalization: bool,
+    statistics_decay: Duration,
+}
+
+impl WarmState {
+    /// Returns the duration of all audio the old detector consumed.
+    pub const fn passed_time(&self) -> Duration {
+        self.passed_time
+    }
+
+    /// Returns the timestamp of the previous beat of the old detector.
+    pub const fn last_beat_time(&self) -> Option<Duration> {
+        self.last_beat_time
+    }
+}
+
+/// [`BeatDetectorConst`] with the default configuration, which is suitable
+/// for typical audio input with 44.1 or 48 kHz.
+pub type BeatDetector = BeatDetectorConst;
+
+/// Beat detector following the properties described in the
+/// [module description].
+///
+/// Usually, you want to use the default configuration, i.e., [`BeatDetector`].
+/// The const generics configure the detector at compile time, so that
+/// embedded users can precisely trade RAM for CPU time and precision:
+/// - `N`: Maximum amount of samples in the audio history. The history never
+///   covers more than ~420ms of audio, so storage beyond that is unused.
+/// - `D`: Downsample factor. Only every `D`-th sample (after the lowpass
+///   filter) is analyzed. As only low frequencies are relevant for beats,
+///   factors up to `4` barely affect the results at 44.1 kHz.
+///   [`SampleInfo::total_index`] refers to the downsampled audio.
+/// - `P`: Maximum amount of tracked peaks in the audio history.
+///
+/// ```rust
+/// use beat_detector_core::BeatDetectorConst;
+/// // 4630 samples * 2 bytes = ~9 KiB of audio history, which covers 420ms
+/// // of audio at 44.1 kHz with a downsample factor of 4.
+/// let mut detector = BeatDetectorConst::<4630, 4, 128>::new(44100.0, true);
+/// assert_eq!(detector.history().capacity(), 4630);
+/// let is_beat = detector.update_and_detect_beat([0, 500, -800, 700].iter().copied());
+/// ```
+///
+/// [`SampleInfo::total_index`]: crate::SampleInfo::total_index
+///
+/// ## Example with audio source emitting mono samples
+/// ```rust
+/// use beat_detector_core::BeatDetector;
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+///
+/// // TODO regularly call this with the latest audio data.
+/// let is_beat = detector.update_and_detect_beat(
+///     mono_samples.iter().copied()
+/// );
+/// ```
+///
+/// ## Example with audio source emitting stereo samples
+/// Mix the channels down to mono, or use a [`StereoBeatDetector`], which
+/// also reports the balance of each beat between the channels.
+/// ```rust
+/// use beat_detector_core::BeatDetector;
+/// use beat_detector_core::util::stereo_to_mono;
+/// // Let's pretend this is interleaved LRLR stereo data.
+/// let stereo_samples =  [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+///
+/// // TODO regularly call this with the latest audio data.
+/// let is_beat = detector.update_and_detect_beat(
+///     stereo_samples.chunks(2).map(|slice| {
+///         let l = slice[0];
+///         let r = slice[1];
+///         stereo_to_mono(l, r)
+///     })
+/// );
+/// ```
+///
+/// [module description]: crate
+/// [`StereoBeatDetector`]: crate::StereoBeatDetector
+#[derive(Debug)]
+pub struct BeatDetectorConst<
+    const N: usize = BUFFER_STORAGE_SIZE,
+    const D: usize = 1,
+    const P: usize = MAX_TRACKED_PEAKS,
+> {
+    /// The large buffers are initialized in place by [`Self::new_in_place`].
+    history: AudioHistory<N>,
+    /// Cache of the peaks in the audio history.
+    peak_cache: PeakCache<P>,
+    /// Everything else, see [`Self::initial_state`].
+    state: DetectorState,
+}
+
+/// State and configuration of a [`BeatDetectorConst`], besides its large
+/// buffers.
+#[derive(Debug)]
+struct DetectorState {
+    lowpass_filter: FilterStage,
+    /// Filter that boosts some frequencies before the lowpass filter, if the
+    /// [`FrequencyWeighting`] requires it.
+    emphasis_filter: Option<FilterStage>,
+    frequency_weighting: FrequencyWeighting,
+    /// Replaces the lowpass filter and the emphasis filter, if set.
+    custom_filter: Option<CustomFilterChain>,
+    /// Whether the lowpass filter should be applied. Usually you want to
+    /// set this to true. Set it to false if you know that all your audio
+    /// input already only contains the interesting frequencies to save some
+    /// computations.
+    needs_lowpass_filter: bool,
+    /// Amount of samples to prime the lowpass filter with.
+    lowpass_filter_priming_samples: usize,
+    /// Whether the lowpass filter was already primed with the first sample.
+    is_lowpass_filter_primed: bool,
+    /// Amount of samples to advance per step when scanning the audio history
+    /// for peaks.
+    scan_stride: usize,
+    /// Holds the previous beat. Once this is initialized, it is never `None`.
+    previous_beat: Option<BeatInfo>,
+    /// Timestamp of the previous beat of the detector whose
+    /// [`WarmState`] was restored, until this detector finds a beat.
+    transferred_beat_time: Option<Duration>,
+    /// Total index where the next envelope search begins. Everything before
+    /// was already analyzed and is either noise or belongs to a reported
+    /// beat.
+    search_begin_total_index: Option<u64>,
+    /// Total index from which on the hum filter removes the hum. The audio
+    /// before still hums and looks like beats next to the filtered audio, so
+    /// the envelope search never reaches back before this.
+    hum_free_total_index: u64,
+    /// How far the envelope search reaches back into already analyzed audio.
+    search_overlap: Duration,
+    /// Tuning parameters of the envelope search.
+    envelope_config: EnvelopeConfig,
+    /// Watches the raw audio input for [`BeatDetectorConst::diagnose`].
+    clipping_detector: ClippingDetector,
+    /// Maximum absolute value of the (lowpassed) samples of the latest update.
+    latest_max_abs: i16,
+    /// Position in the current group of `D` samples of which only the first
+    /// one is kept.
+    downsample_phase: usize,
+    /// Amount of samples that the latest update added to the audio history.
+    latest_processed_count: usize,
+    /// Suppresses the self-noise of the audio source, if a profile was set.
+    noise_suppressor: Option<NoiseSuppressor>,
+    /// Removes hum of the mains power grid, if enabled.
+    hum_filter: Option<HumFilter>,
+    /// Attenuates sustained sounds in the (lowpassed) audio, if enabled.
+    sustain_suppressor: Option<SustainSuppressor>,
+    /// Compensates sudden gain changes of the audio input, if enabled.
+    gain_normalizer: Option<GainNormalizer>,
+    /// Time constant of the adaptive statistics.
+    statistics_decay: Duration,
+    /// Long-term median of the peaks and noise floor.
+    statistics: AdaptiveStatistics,
+    /// Gaps in the audio source that were reported with
+    /// [`BeatDetectorConst::signal_gap`].
+    gaps: Gaps,
+    /// Beats up to this point in time are not reported.
+    muted_until: Option<Duration>,
+    /// Whether the input is an envelope stream that is analyzed as it is.
+    /// See [`BeatDetectorConst::new_envelope_input`].
+    envelope_input: bool,
+    /// Whether the input already ran through the filter stages. See
+    /// [`BeatDetectorConst::set_preprocessed_input`].
+    preprocessed_input: bool,
+}
+
+/// Accumulated duration of the gaps in the audio source, in samples of the
+/// original audio.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Gaps {
+    total_samples: u64,
+    /// Total gap of all gaps before the latest one.
+    samples_before_latest: u64,
+    /// Total index of the first processed sample after the latest gap.
+    latest_total_index: u64,
+}
+
+impl Gaps {
+    /// Returns the gap that precedes the processed sample with the given
+    /// total index. Only the latest gap is tracked exactly, which is enough
+    /// as long as gaps are further apart than the audio history is long.
+    const fn samples_before(&self, total_index: u64) -> u64 {
+        if total_index >= self.latest_total_index {
+            self.total_samples
+        } else {
+            self.samples_before_latest
+        }
+    }
+}
+
+impl<const N: usize, const D: usize, const P: usize> BeatDetectorConst<N, D, P> {
+    /// Maximum capacity of the audio history.
+    const HISTORY_CAPACITY: usize = {
+        if D == 0 {
+            panic!("The downsample factor must not be zero");
+        }
+        let capacity = DEFAULT_BUFFER_SIZE / D;
+        if capacity < N {
+            capacity
+        } else {
+            N
+        }
+    };
+
+    /// Creates a new beat detector. It is recommended to pass `true` to
+    /// `needs_lowpass_filter`. If you know that the audio source has already
+    /// run through a low-pass filter, you can set it to `false` to save
+    /// a few cycles, with results in a slightly lower latency.
+    pub fn new(sampling_frequency_hz: f32, needs_lowpass_filter: bool) -> Self {
+        let history_sampling_frequency = sampling_frequency_hz / D as f32;
+        Self {
+            history: AudioHistory::with_capacity(
+                history_sampling_frequency,
+                Self::history_capacity(history_sampling_frequency),
+            ),
+            peak_cache: PeakCache::new(DEFAULT_SCAN_STRIDE),
+            state: Self::initial_state(sampling_frequency_hz, needs_lowpass_filter),
+        }
+    }
+
+    /// Creates a new beat detector for a stream that is already an envelope
+    /// of the audio, such as the 200 Hz envelope that some wireless sensors
+    /// send instead of the audio. The samples are the (non-negative)
+    /// amplitudes of the envelope in the range of the `i16` samples.
+    ///
+    /// The samples are neither filtered nor downsampled. Hence, the noise
+    /// profile, the hum filter, the frequency weighting, the custom filter,
+    /// and the sustain suppression don't apply. Instead of the peaks between
+    /// the roots of the audio, each sample above the noise floor counts as a
+    /// peak. A beat is reported once [`EnvelopeConfig::trend_window`] samples
+    /// after its end arrived, as only then the envelope can't rise or decay
+    /// further.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use beat_detector_core::BeatDetector;
+    /// let envelope_samples = [2000, 2100, 9000, 18000, 12000 /*, ... */];
+    /// let mut detector = BeatDetector::new_envelope_input(200.0);
+    ///
+    /// // TODO regularly call this with the latest envelope data.
+    /// let is_beat = detector.update_and_detect_beat(envelope_samples.iter().copied());
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the downsample factor `D` isn't `1`, as the envelope is
+    /// already downsampled.
+    pub fn new_envelope_input(sampling_frequency_hz: f32) -> Self {
+        assert_eq!(D, 1, "An envelope stream must not be downsampled again");
+        let mut detector = Self::new(sampling_frequency_hz, false);
+        detector.peak_cache.use_samples_as_peaks();
+        detector.state.envelope_input = true;
+        detector
+    }
+
+    /// Like [`Self::new`] but initializes the detector in the given memory,
+    /// such as a `static`, without creating the detector on the stack first.
+    /// This prevents stack spikes during the initialization, which matters on
+    /// embedded targets with small stacks.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use beat_detector_core::BeatDetector;
+    /// use core::mem::MaybeUninit;
+    ///
+    /// let mut memory = MaybeUninit::uninit();
+    /// let detector = BeatDetector::new_in_place(&mut memory, 44100.0, true);
+    /// let _beat = detector.update_and_detect_beat([0, 500, -800, 700].iter().copied());
+    /// ```
+    pub fn new_in_place(
+        memory: &mut MaybeUninit<Self>,
+        sampling_frequency_hz: f32,
+        needs_lowpass_filter: bool,
+    ) -> &mut Self {
+        let history_sampling_frequency = sampling_frequency_hz / D as f32;
+        let state = Self::initial_state(sampling_frequency_hz, needs_lowpass_filter);
+        let this = memory.as_mut_ptr();
+        // SAFETY: The pointer is valid and aligned. Each field is written
+        // exactly once.
+        unsafe {
+            AudioHistory::init_in_place(
+                addr_of_mut!((*this).history),
+                history_sampling_frequency,
+                Self::history_capacity(history_sampling_frequency),
+            );
+            PeakCache::init_in_place(addr_of_mut!((*this).peak_cache), DEFAULT_SCAN_STRIDE);
+            addr_of_mut!((*this).state).write(state);
+            memory.assume_init_mut()
+        }
+    }
+
+    /// Returns the state of a new detector. Shared by [`Self::new`] and
+    /// [`Self::new_in_place`].
+    fn initial_state(sampling_frequency_hz: f32, needs_lowpass_filter: bool) -> DetectorState {
+        DetectorState {
+            lowpass_filter: Self::create_lowpass_filter(
+                sampling_frequency_hz,
+                FrequencyWeighting::default(),
+            ),
+            emphasis_filter: None,
+            frequency_weighting: FrequencyWeighting::default(),
+            custom_filter: None,
+            needs_lowpass_filter,
+            lowpass_filter_priming_samples: (sampling_frequency_hz
+                * LOWPASS_FILTER_PRIMING_DURATION_MS
+                / 1000.0) as usize,
+            is_lowpass_filter_primed: false,
+            scan_stride: DEFAULT_SCAN_STRIDE,
+            previous_beat: None,
+            transferred_beat_time: None,
+            search_begin_total_index: None,
+            hum_free_total_index: 0,
+            search_overlap: DEFAULT_SEARCH_OVERLAP,
+            envelope_config: EnvelopeConfig::DEFAULT,
+            clipping_detector: ClippingDetector::default(),
+            latest_max_abs: 0,
+            downsample_phase: 0,
+            latest_processed_count: 0,
+            noise_suppressor: None,
+            hum_filter: None,
+            sustain_suppressor: None,
+            gain_normalizer: None,
+            statistics_decay: DEFAULT_STATISTICS_DECAY,
+            statistics: AdaptiveStatistics::new(
+                sampling_frequency_hz / D as f32,
+                DEFAULT_STATISTICS_DECAY,
+            ),
+            gaps: Gaps::default(),
+            muted_until: None,
+            envelope_input: false,
+            preprocessed_input: false,
+        }
+    }
+
+    /// Consumes the latest audio data and returns if the audio history,
+    /// consisting of previously captured audio and the new data, contains a
+    /// beat. This function is supposed to be frequently
+    /// called everytime new audio data from the input source is available so
+    /// that:
+    /// - the latency is low
+    /// - no beats are missed
+    ///
+    /// From experience, Linux audio input libraries (using ALSA as backend)
+    /// give you a 20-40ms audio buffer every 20-40ms with the latest data.
+    /// That's a good rule of thumb. This corresponds to 1800 mono samples at a
+    /// sampling rate of 44.1kHz.
+    ///
+    /// If new audio data contains two beats, only the first one will be
+    /// discovered. On the next invocation, the next beat will be discovered,
+    /// if still present in the internal audio window.
+    ///
+    /// No beats are reported until the detector [is warmed up].
+    ///
+    /// [is warmed up]: Self::is_warmed_up
+    pub fn update_and_detect_beat(
+        &mut self,
+        mono_samples_iter: impl Iterator<Item = i16>,
+    ) -> Option<BeatInfo> {
+        self.consume_audio(mono_samples_iter);
+        self.detect_beat().ok()
+    }
+
+    /// Like [`Self::update_and_detect_beat`], but additionally records why
+    /// the update reported a beat or not in `trace`, such as the noise gate
+    /// or a peak that doesn't stand out enough. This turns reports like "it
+    /// missed the drop" into actionable data. Each decision is also logged
+    /// with the `trace` level.
+    ///
+    /// The trace is owned by the caller, so that detectors that aren't
+    /// debugged don't carry its buffer around.
+    pub fn update_and_detect_beat_traced(
+        &mut self,
+        mono_samples_iter: impl Iterator<Item = i16>,
+        trace: &mut DecisionTrace,
+    ) -> Option<BeatInfo> {
+        self.consume_audio(mono_samples_iter);
+        let beat = self.detect_beat();
+        trace.push(
+            self.decision_trace_entry(beat.map_or_else(|decision| decision, |_| Decision::Beat)),
+        );
+        beat.ok()
+    }
+
+    /// Searches the audio history for the next beat. Returns why no beat was
+    /// found otherwise.
+    fn detect_beat(&mut self) -> Result<BeatInfo, Decision> {
+        if !self.is_warmed_up() {
+            return Err(Decision::WarmingUp);
+        }
+
+        // Only analyze audio that is newer than what previous searches already
+        // covered. The overlap never reaches back into the previous beat, so
+        // that reported beats never overlap. If that position left the audio
+        // history, all of the remaining audio is new.
+        let search_begin_index = self.state.search_begin_total_index.map(|total_index| {
+            let overlap_samples = (self.state.search_overlap.as_secs_f32()
+                * self.history.sampling_frequency()) as u64;
+            let previous_beat_end = self
+                .state
+                .previous_beat
+                .map_or(0, |beat| beat.to.total_index);
+            let total_index = total_index
+                .saturating_sub(overlap_samples)
+                .max(previous_beat_end)
+                .max(self.state.hum_free_total_index)
+                .min(total_index);
+            self.history.total_index_to_index(total_index).unwrap_or(0)
+        });
+
+        // Envelope iterator with respect to previous beats.
+        let mut envelope_iter = EnvelopeIterator::with_scan_stride(
+            &self.history,
+            search_begin_index,
+            self.state.scan_stride,
+        )
+        .with_peak_cache(&self.peak_cache)
+        .with_config(EnvelopeConfig {
+            min_value: (self.state.envelope_config.min_value)
+                .max(self.state.statistics.noise_gate()),
+            ..self.state.envelope_config
+        })
+        .with_median_floor(self.state.statistics.median_floor());
+        let beat = envelope_iter.next();
+        // The few samples of an envelope stream at the end of the history
+        // can't tell whether the envelope still rises or decays. Such an
+        // envelope is searched again once more samples arrived.
+        if self.state.envelope_input
+            && beat.is_some_and(|beat| {
+                beat.to.index + self.state.envelope_config.trend_window >= self.history.len()
+            })
+        {
+            return Err(Decision::TooRecent);
+        }
+        self.state.search_begin_total_index = Some(
+            self.history
+                .index_to_sample_info(envelope_iter.resume_index())
+                .total_index,
+        );
+        let beat = beat.ok_or_else(|| envelope_iter.rejection().unwrap_or(Decision::NothingNew))?;
+        debug_assert!(!self
+            .state
+            .previous_beat
+            .is_some_and(|prev| prev.overlaps(&beat)));
+        let beat = EnvelopeInfo {
+            from: self.with_source_position(beat.from),
+            to: self.with_source_position(beat.to),
+            max: self.with_source_position(beat.max),
+            ..beat
+        };
+
+        // A follow-up of the previous beat that belongs to the same musical
+        // event. It is not reported but extends the previous beat.
+        if let (Some(policy), Some(previous_beat)) = (
+            self.state.envelope_config.merge_policy,
+            self.state.previous_beat.as_mut(),
+        ) {
+            if policy.should_merge(previous_beat, &beat, self.history.sampling_frequency()) {
+                previous_beat.to = beat.to;
+                return Err(Decision::Refractory);
+            }
+        }
+
+        // The notch of the hum filter rings after a kick drum, which may
+        // cause another, quieter envelope right after the beat. It is merged
+        // as well.
+        if let (Some(filter), Some(previous_beat)) = (
+            self.state.hum_filter.as_ref(),
+            self.state.previous_beat.as_mut(),
+        ) {
+            if filter.is_ringing(previous_beat, &beat) {
+                previous_beat.to = beat.to;
+                return Err(Decision::Refractory);
+            }
+        }
+
+        self.state.previous_beat.replace(beat);
+
+        // The beat still counts as previous beat, so that it isn't found again
+        // once the detector is unmuted.
+        if self
+            .state
+            .muted_until
+            .is_some_and(|until| beat.timestamp() < until)
+        {
+            return Err(Decision::Muted);
+        }
+        Ok(beat)
+    }
+
+    /// Describes the decision of the latest update for the decision trace.
+    fn decision_trace_entry(&self, decision: Decision) -> DecisionTraceEntry {
+        let threshold = self.state.envelope_config.max_peak_to_median_min_ratio;
+        let peak_to_median_ratio = self.amplitude_histogram().median().map_or(0.0, |median| {
+            self.state.latest_max_abs as f32 / median as f32
+        });
+        let entry = DecisionTraceEntry {
+            time: self.passed_time(),
+            decision,
+            peak_to_median_ratio,
+            threshold,
+        };
+        log::trace!("Decision: {entry:?}");
+        entry
+    }
+
+    /// Returns the amount of samples the detector advances per step when
+    /// scanning the audio history for peaks.
+    pub const fn scan_stride(&self) -> usize {
+        self.state.scan_stride
+    }
+
+    /// Sets the amount of samples the detector advances per step when
+    /// scanning the audio history for peaks. The default is
+    /// [`DEFAULT_SCAN_STRIDE`]. Higher values reduce the CPU load at the cost
+    /// of precision, which may be useful on weak hardware.
+    pub fn set_scan_stride(&mut self, scan_stride: usize) {
+        assert!(scan_stride > 0);
+        self.state.scan_stride = scan_stride;
+        self.peak_cache.reset(scan_stride);
+    }
+
+    /// Returns how far the envelope search reaches back into audio that was
+    /// already analyzed by a previous search.
+    pub const fn search_overlap(&self) -> Duration {
+        self.state.search_overlap
+    }
+
+    /// Sets how far the envelope search reaches back into audio that was
+    /// already analyzed by a previous search. The default is
+    /// [`DEFAULT_SEARCH_OVERLAP`].
+    ///
+    /// The detector only analyzes audio that is newer than the end of the
+    /// previous beat or, if no beat was found, newer than the noise that
+    /// previous searches skipped. A small overlap makes the search more
+    /// tolerant against imprecise peak positions, e.g., with a high
+    /// [scan stride]. The search never reaches back into the previous beat,
+    /// hence, reported beats never overlap.
+    ///
+    /// [scan stride]: Self::set_scan_stride
+    pub fn set_search_overlap(&mut self, overlap: Duration) {
+        self.state.search_overlap = overlap;
+    }
+
+    /// Returns the noise profile that is suppressed in the audio input.
+    pub fn noise_profile(&self) -> Option<&NoiseProfile> {
+        self.state
+            .noise_suppressor
+            .as_ref()
+            .map(NoiseSuppressor::profile)
+    }
+
+    /// Sets the self-noise of the audio source, such as the hiss of a cheap
+    /// microphone, that is suppressed in the audio input. This lowers the
+    /// noise floor and makes beats stand out more clearly. `None` disables
+    /// the suppression, which is the default.
+    ///
+    /// The profile must be learned at the sampling frequency of the detector.
+    /// See [`NoiseProfileLearner`].
+    ///
+    /// [`NoiseProfileLearner`]: crate::NoiseProfileLearner
+    pub fn set_noise_profile(&mut self, profile: Option<NoiseProfile>) {
+        if let Some(profile) = &profile {
+            assert!(
+                (profile.sampling_frequency() - self.original_sampling_frequency()).abs() < 1.0,
+                "The noise profile must be learned at the sampling frequency of the detector"
+            );
+        }
+        self.state.noise_suppressor = profile.map(NoiseSuppressor::new);
+    }
+
+    /// Returns the mains frequency whose hum is removed from the audio input.
+    pub fn hum_filter(&self) -> Option<MainsFrequency> {
+        self.state
+            .hum_filter
+            .as_ref()
+            .map(HumFilter::mains_frequency)
+    }
+
+    /// Removes hum of the given mains frequency and its harmonics from the
+    /// audio input. Ground loops cause such hum, which sits right in the
+    /// frequency range of beats and can dominate the lowpassed signal. `None`
+    /// disables the filter, which is the default.
+    ///
+    /// The filter only engages once the audio hums steadily for about half a
+    /// second. Until then, and on audio without hum, the audio passes
+    /// unchanged. Beats in the hummy audio before the filter engaged are not
+    /// reported.
+    ///
+    /// While engaged, the filter also removes a small part of the beats,
+    /// i.e., the fundamental of kick drums at the mains frequency, and rings
+    /// for a while after each kick. Envelopes within about 100 ms after a
+    /// beat that are clearly quieter than the beat are therefore merged into
+    /// it, like with a [`MergePolicy`]. Louder ones are reported, as they
+    /// can't be told apart from fast real beats, such as double kicks.
+    ///
+    /// [`MergePolicy`]: crate::MergePolicy
+    pub fn set_hum_filter(&mut self, mains_frequency: Option<MainsFrequency>) {
+        let sampling_frequency = self.original_sampling_frequency();
+        self.state.hum_filter =
+            mains_frequency.map(|frequency| HumFilter::new(frequency, sampling_frequency));
+    }
+
+    /// Returns whether sustained sounds are suppressed.
+    pub const fn sustain_suppression(&self) -> bool {
+        self.state.sustain_suppressor.is_some()
+    }
+
+    /// Suppresses sustained sounds, so that only transients, such as kick
+    /// drums, count as beats. Sustained sub-bass, such as 808 slides, keeps
+    /// the level of the lowpassed audio high and masks the kick drums
+    /// otherwise. This is disabled by default.
+    ///
+    /// The beginning of a sustained sound is still a transient and may be
+    /// detected as a beat.
+    pub fn set_sustain_suppression(&mut self, enabled: bool) {
+        self.state.sustain_suppressor =
+            enabled.then(|| SustainSuppressor::new(self.original_sampling_frequency()));
+    }
+
+    /// Returns the gain that currently compensates a change of the volume of
+    /// the audio input, if gain normalization is enabled.
+    pub fn gain_normalization(&self) -> Option<f32> {
+        self.state
+            .gain_normalizer
+            .as_ref()
+            .map(GainNormalizer::gain)
+    }
+
+    /// Compensates sudden and sustained gain changes of the audio input,
+    /// such as when the user turns the volume knob mid-song. The audio
+    /// history that was captured since the change is rescaled at once, so
+    /// that the adaptive statistics don't lag behind and beats aren't missed
+    /// until the old audio faded out of the history. This is disabled by
+    /// default.
+    ///
+    /// Quiet or loud passages of a song that last longer than a few hundred
+    /// milliseconds are compensated as well.
+    pub fn set_gain_normalization(&mut self, enabled: bool) {
+        self.state.gain_normalizer = enabled.then(|| {
+            GainNormalizer::new(
+                self.history.sampling_frequency(),
+                self.state.statistics_decay,
+            )
+        });
+    }
+
+    /// Returns the time constant of the adaptive statistics.
+    pub const fn statistics_decay(&self) -> Duration {
+        self.state.statistics_decay
+    }
+
+    /// Sets the time constant with which the adaptive statistics follow the
+    /// audio. Shorter values adapt faster to quieter and louder passages,
+    /// longer values remember the level of a song for longer. The default is
+    /// [`DEFAULT_STATISTICS_DECAY`]. The adaptive statistics are:
+    /// - the long-term median of the peaks. The envelope search compares
+    ///   beats with the median of the peaks in the audio history, but at
+    ///   least with half of the long-term median. So, after a loud passage,
+    ///   small bumps in a quiet passage aren't reported as beats until the
+    ///   statistics adapted.
+    /// - the noise floor, i.e., the median of the peaks of the quietest
+    ///   audio. Peaks below half of it don't begin an envelope.
+    /// - the reference level of the [gain normalization].
+    ///
+    /// Range: `2..=10` s.
+    ///
+    /// [gain normalization]: Self::set_gain_normalization
+    pub fn set_statistics_decay(&mut self, decay: Duration) {
+        assert!(
+            STATISTICS_DECAY_RANGE.contains(&decay),
+            "The statistics decay must be in range 2..=10 s"
+        );
+        self.state.statistics_decay = decay;
+        let sampling_frequency = self.history.sampling_frequency();
+        self.state.statistics.set_decay(sampling_frequency, decay);
+        if let Some(normalizer) = self.state.gain_normalizer.as_mut() {
+            normalizer.set_decay(sampling_frequency, decay);
+        }
+    }
+
+    /// Returns whether the detector applies its lowpass filter, as passed to
+    /// [`Self::new`].
+    pub const fn needs_lowpass_filter(&self) -> bool {
+        self.state.needs_lowpass_filter
+    }
+
+    /// Returns the weighting of the frequencies of the audio input.
+    pub const fn frequency_weighting(&self) -> FrequencyWeighting {
+        self.state.frequency_weighting
+    }
+
+    /// Sets the weighting of the frequencies of the audio input. The default
+    /// is [`FrequencyWeighting::Lowpass`]. This has no effect if the detector
+    /// was created without a lowpass filter.
+    ///
+    /// The filters start over, so this should be set before audio is
+    /// consumed.
+    pub fn set_frequency_weighting(&mut self, weighting: FrequencyWeighting) {
+        let sampling_frequency = self.original_sampling_frequency();
+        self.state.lowpass_filter = Self::create_lowpass_filter(sampling_frequency, weighting);
+        self.state.emphasis_filter = Self::create_emphasis_filter(sampling_frequency, weighting);
+        self.state.frequency_weighting = weighting;
+        self.state.is_lowpass_filter_primed = false;
+    }
+
+    /// Writes the frequency response of the filters that the detector applies
+    /// to the audio input, i.e., the lowpass filter and the
+    /// [frequency weighting] or the [custom filter], into `response`. This is
+    /// useful for UIs to show which frequencies can still trigger beats.
+    ///
+    /// Each entry is a pair of a frequency in Hz and the gain in dB at that
+    /// frequency. The frequencies are spaced logarithmically over
+    /// `10..=1000` Hz, as far as the sampling frequency permits, in
+    /// ascending order. The length of `response` is the amount of points.
+    /// Without a filter, the gain is `0.0` everywhere.
+    ///
+    /// [frequency weighting]: Self::set_frequency_weighting
+    /// [custom filter]: Self::set_custom_filter
+    pub fn frequency_response_into(&self, response: &mut [(f32, f32)]) {
+        let sampling_frequency = self.original_sampling_frequency();
+        let min = *FREQUENCY_RESPONSE_RANGE_HZ.start();
+        // Stay below the Nyquist frequency.
+        let max = FREQUENCY_RESPONSE_RANGE_HZ
+            .end()
+            .min(sampling_frequency / 2.0 * 0.99);
+        let lowpass = BiquadCoefficients::from(Self::lowpass_coefficients(
+            sampling_frequency,
+            self.state.frequency_weighting,
+        ));
+        let emphasis =
+            Self::emphasis_coefficients(sampling_frequency, self.state.frequency_weighting)
+                .map(BiquadCoefficients::from);
+        let steps = response.len().saturating_sub(1).max(1) as f32;
+        for (i, (frequency, gain_db)) in response.iter_mut().enumerate() {
+            *frequency = min * libm::powf(max / min, i as f32 / steps);
+            let omega = 2.0 * core::f32::consts::PI * *frequency / sampling_frequency;
+            *gain_db = match (&self.state.custom_filter, self.state.needs_lowpass_filter) {
+                (Some(custom_filter), _) => custom_filter.filter().response(omega).0,
+                (None, true) => {
+                    lowpass.response(omega).0
+                        + emphasis.map_or(0.0, |emphasis| emphasis.response(omega).0)
+                }
+                (None, false) => 0.0,
+            };
+        }
+    }
+
+    /// Like [`Self::frequency_response_into`], but allocates a response with
+    /// the given amount of points.
+    #[cfg(feature = "std")]
+    pub fn frequency_response(&self, points: usize) -> std::vec::Vec<(f32, f32)> {
+        let mut response = vec![(0.0, 0.0); points];
+        self.frequency_response_into(&mut response);
+        response
+    }
+
+    /// Returns the custom filter that replaces the built-in filters.
+    pub fn custom_filter(&self) -> Option<&CustomFilter> {
+        self.state
+            .custom_filter
+            .as_ref()
+            .map(CustomFilterChain::filter)
+    }
+
+    /// Replaces the lowpass filter and the [frequency weighting] with a
+    /// cascade of biquad filters that was designed elsewhere. `None` restores
+    /// the built-in filters, which is the default.
+    ///
+    /// The custom filter is applied even if the detector was created without
+    /// a lowpass filter. It must be designed for the sampling frequency of the
+    /// detector and should pass the frequencies of kick drums (roughly
+    /// 40..=120 Hz). The timestamps of beats compensate its group delay around
+    /// 60 Hz. Like the frequency weighting, this should be set before audio is
+    /// consumed.
+    ///
+    /// [frequency weighting]: Self::set_frequency_weighting
+    pub fn set_custom_filter(&mut self, filter: Option<CustomFilter>) {
+        self.state.custom_filter = filter.map(CustomFilterChain::new);
+        self.state.is_lowpass_filter_primed = false;
+    }
+
+    /// Returns the cutoff frequency of the lowpass filter of the
+    /// [`FrequencyWeighting`], if any filter is applied to the audio input.
+    pub(crate) fn filter_cutoff_frequency_hz(&self) -> Option<f32> {
+        self.applies_filter()
+            .then(|| self.state.frequency_weighting.cutoff_frequency_hz())
+    }
+
+    /// Returns whether any filter is applied to the audio input before it is
+    /// added to the history.
+    const fn applies_filter(&self) -> bool {
+        !self.state.envelope_input
+            && !self.state.preprocessed_input
+            && (self.state.needs_lowpass_filter || self.state.custom_filter.is_some())
+    }
+
+    /// Returns whether the detector analyzes an envelope stream, see
+    /// [`Self::new_envelope_input`].
+    pub const fn is_envelope_input(&self) -> bool {
+        self.state.envelope_input
+    }
+
+    /// Returns whether the input already ran through the filter stages, see
+    /// [`Self::set_preprocessed_input`].
+    pub const fn is_preprocessed_input(&self) -> bool {
+        self.state.preprocessed_input
+    }
+
+    /// Marks the input as already preprocessed, i.e., as the output of the
+    /// noise suppression, the hum filter, the lowpass or custom filter, and
+    /// the sustain suppression of a detector with the same configuration.
+    /// Such samples are returned by [`Self::processed_samples_since_last_call`]
+    /// if there is no downsampling and no gain normalization.
+    ///
+    /// The detector then skips these stages, but still compensates their
+    /// delay in the timestamps of beats. This way, an offline analysis can be
+    /// repeated with different thresholds, such as another
+    /// [`EnvelopeConfig`], without filtering the audio again.
+    pub fn set_preprocessed_input(&mut self, enabled: bool) {
+        self.state.preprocessed_input = enabled;
+    }
+
+    /// Returns the tuning parameters of the envelope search.
+    pub const fn envelope_config(&self) -> &EnvelopeConfig {
+        &self.state.envelope_config
+    }
+
+    /// Sets the tuning parameters of the envelope search. The default is
+    /// [`EnvelopeConfig::DEFAULT`].
+    ///
+    /// # Panics
+    /// Panics if a value is out of its documented range.
+    pub fn set_envelope_config(&mut self, config: EnvelopeConfig) {
+        config.check();
+        self.state.envelope_config = config;
+    }
+
+    /// Returns whether the detector consumed enough audio to report beats.
+    /// During the warm-up phase, detections are suppressed to prevent false
+    /// positives caused by an almost empty audio history.
+    pub fn is_warmed_up(&self) -> bool {
+        self.history.passed_time() >= WARM_UP_DURATION
+    }
+
+    /// Returns the duration of all audio the detector consumed so far,
+    /// including the gaps reported with [`Self::signal_gap`].
+    pub fn passed_time(&self) -> Duration {
+        self.history.passed_time() + self.gap_duration()
+    }
+
+    /// Returns how long ago the previous beat was, measured with the sample
+    /// clock. Returns `None` if no beat was detected so far.
+    pub fn last_beat_age(&self) -> Option<Duration> {
+        self.last_beat_time()
+            .map(|time| self.passed_time().saturating_sub(time))
+    }
+
+    /// Returns the timestamp of the previous beat, including beats of a
+    /// restored [`WarmState`].
+    fn last_beat_time(&self) -> Option<Duration> {
+        self.state
+            .previous_beat
+            .map(|beat| beat.timestamp())
+            .or(self.state.transferred_beat_time)
+    }
+
+    /// Returns the state that a new detector for a different sampling rate
+    /// can continue with, see [`Self::restore_warm_state`].
+    pub fn warm_state(&self) -> WarmState {
+        WarmState {
+            history_sampling_frequency: self.history.sampling_frequency(),
+            passed_time: self.passed_time(),
+            last_beat_time: self.last_beat_time(),
+            scan_stride: self.state.scan_stride,
+            search_overlap: self.state.search_overlap,
+            envelope_config: self.state.envelope_config,
+            frequency_weighting: self.state.frequency_weighting,
+            hum_filter: self.hum_filter(),
+            sustain_suppression: self.sustain_suppression(),
+            gain_normalization: self.state.gain_normalizer.is_some(),
+            statistics_decay: self.state.statistics_decay,
+        }
+    }
+
+    /// Continues with the [`WarmState`] of another detector, which usually
+    /// ran at a different sampling rate. This is useful when the audio device
+    /// changes mid-show and a resampler is inserted, so that the light show
+    /// doesn't lose its tempo lock.
+    ///
+    /// The time of this detector continues where the other detector stopped.
+    /// Hence, the timestamps of all following beats and
+    /// [`Self::last_beat_age`] stay aligned with the timestamps of the other
+    /// detector, and a [`TempoEstimator`] fed with both keeps its tempo. The
+    /// configuration is transferred as well, with the scan stride rescaled to
+    /// the new sampling rate. The noise profile and the custom filter are not
+    /// transferred, as they only apply to the sampling rate they were made
+    /// for.
+    ///
+    /// # Panics
+    /// Panics if this detector already consumed audio.
+    ///
+    /// [`TempoEstimator`]: crate::TempoEstimator
+    pub fn restore_warm_state(&mut self, state: &WarmState) {
+        assert_eq!(
+            self.passed_time(),
+            Duration::ZERO,
+            "The warm state must be restored before the detector consumes audio"
+        );
+        let ratio = self.history.sampling_frequency() / state.history_sampling_frequency;
+        self.set_scan_stride((libm::roundf(state.scan_stride as f32 * ratio) as usize).max(1));
+        self.set_search_overlap(state.search_overlap);
+        self.set_envelope_config(state.envelope_config);
+        self.set_frequency_weighting(state.frequency_weighting);
+        self.set_hum_filter(state.hum_filter);
+        self.set_sustain_suppression(state.sustain_suppression);
+        self.set_statistics_decay(state.statistics_decay);
+        self.set_gain_normalization(state.gain_normalization);
+        self.signal_gap(state.passed_time);
+        self.state.transferred_beat_time = state.last_beat_time;
+    }
+
+    /// Returns what the detector learned about the installation, to persist
+    /// it until the next run. See [`Calibration`].
+    pub fn calibration(&self) -> Calibration {
+        let gain = self
+            .state
+            .gain_normalizer
+            .as_ref()
+            .filter(|normalizer| normalizer.level() > 0.0)
+            .map(|normalizer| (normalizer.gain(), normalizer.level()));
+        Calibration::new(
+            self.noise_profile().copied(),
+            self.state.envelope_config.max_peak_to_median_min_ratio,
+            gain,
+        )
+    }
+
+    /// Continues with the [`Calibration`] of a previous run. This sets the
+    /// sensitivity and enables the gain normalization with its settled gain,
+    /// if the calibration has one. The noise profile is only restored if it
+    /// was learned at the sampling frequency of this detector.
+    pub fn restore_calibration(&mut self, calibration: &Calibration) {
+        let sampling_frequency = self.original_sampling_frequency();
+        if let Some(profile) = calibration
+            .noise_profile()
+            .filter(|profile| (profile.sampling_frequency() - sampling_frequency).abs() < 1.0)
+        {
+            self.set_noise_profile(Some(profile));
+        }
+        self.set_envelope_config(EnvelopeConfig {
+            max_peak_to_median_min_ratio: calibration.sensitivity(),
+            ..self.state.envelope_config
+        });
+        if let Some((gain, level)) = calibration.gain_and_level() {
+            if self.state.gain_normalizer.is_none() {
+                self.set_gain_normalization(true);
+            }
+            if let Some(normalizer) = self.state.gain_normalizer.as_mut() {
+                normalizer.restore(gain, level);
+            }
+        }
+    }
+
+    /// Returns the size of the snapshot in bytes.
+    pub fn snapshot_len(&self) -> usize {
+        let mut writer = SnapshotWriter::new(&mut []);
+        self.write_snapshot(&mut writer);
+        writer.position() + self.history.snapshot_len()
+    }
+
+    /// Serializes the complete state of the detector into a compact binary
+    /// format: the configuration, the state of all filter stages, the
+    /// adaptive statistics, the previous beat, the cached peaks, and the
+    /// [audio history]. The snapshot can be loaded with
+    /// [`Self::from_snapshot`], for example, to attach the exact state that
+    /// led to a wrong detection to a bug report. A restored detector
+    /// continues exactly like the original one.
+    ///
+    /// The [diagnosis] of the latest update isn't included.
+    ///
+    /// Returns the amount of written bytes, see [`Self::snapshot_len`].
+    ///
+    /// [audio history]: AudioHistory::snapshot_into
+    /// [diagnosis]: Self::diagnose
+    pub fn snapshot_into(&self, buf: &mut [u8]) -> Result<usize, SnapshotError> {
+        let required = self.snapshot_len();
+        let buf = buf
+            .get_mut(..required)
+            .ok_or(SnapshotError::BufferTooSmall { required })?;
+        let mut writer = SnapshotWriter::new(buf);
+        self.write_snapshot(&mut writer);
+        let len = writer.finish()?;
+        self.history.snapshot_into(&mut buf[len..])?;
+        Ok(required)
+    }
+
+    /// Like [`Self::snapshot_into`], but allocates the buffer.
+    #[cfg(feature = "std")]
+    pub fn snapshot(&self) -> std::vec::Vec<u8> {
+        let mut buf = vec![0; self.snapshot_len()];
+        self.snapshot_into(&mut buf)
+            .expect("buffer should have the right size");
+        buf
+    }
+
+    /// Restores a detector from a snapshot created with
+    /// [`Self::snapshot_into`]. The detector must have the same downsample
+    /// factor `D` and enough storage for the audio history.
+    ///
+    /// For the default configuration, the type must be annotated:
+    /// `let detector: BeatDetector = BeatDetector::from_snapshot(&data)?;`
+    pub fn from_snapshot(snapshot: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = SnapshotReader::new(snapshot);
+        if reader.bytes()? != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::InvalidFormat);
+        }
+        let version = reader.u8()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let downsample_factor = reader.u32()? as usize;
+        if downsample_factor != D {
+            return Err(SnapshotError::DownsampleFactorMismatch { downsample_factor });
+        }
+        let sampling_frequency = reader.f32()?;
+        SnapshotReader::check(sampling_frequency >= 1.0)?;
+        let needs_lowpass_filter = reader.bool()?;
+        let envelope_input = reader.bool()?;
+        let mut detector = if envelope_input {
+            Self::new_envelope_input(sampling_frequency)
+        } else {
+            Self::new(sampling_frequency, needs_lowpass_filter)
+        };
+        detector.set_preprocessed_input(reader.bool()?);
+
+        detector.set_frequency_weighting(match reader.u8()? {
+            0 => FrequencyWeighting::Lowpass,
+            1 => FrequencyWeighting::KickEmphasis,
+            _ => return Err(SnapshotError::InvalidFormat),
+        });
+        detector.state.lowpass_filter.read_snapshot(&mut reader)?;
+        let has_emphasis_filter = reader.bool()?;
+        match detector.state.emphasis_filter.as_mut() {
+            Some(filter) if has_emphasis_filter => filter.read_snapshot(&mut reader)?,
+            None if !has_emphasis_filter => {}
+            _ => return Err(SnapshotError::InvalidFormat),
+        }
+        detector.state.custom_filter = reader.option(CustomFilterChain::read_snapshot)?;
+        detector.state.is_lowpass_filter_primed = reader.bool()?;
+
+        let scan_stride = reader.u32()? as usize;
+        SnapshotReader::check(scan_stride > 0)?;
+        detector.set_scan_stride(scan_stride);
+        detector.set_search_overlap(reader.duration()?);
+        detector.set_envelope_config(EnvelopeConfig::read_snapshot(&mut reader)?);
+        let statistics_decay = reader.duration()?;
+        SnapshotReader::check(STATISTICS_DECAY_RANGE.contains(&statistics_decay))?;
+        detector.set_statistics_decay(statistics_decay);
+        detector.state.statistics.read_snapshot(&mut reader)?;
+
+        if reader.bool()? {
+            let profile_sampling_frequency = reader.f32()?;
+            let mut levels = [0.0; NOISE_PROFILE_BANDS];
+            for level in &mut levels {
+                *level = reader.f32()?;
+            }
+            SnapshotReader::check(
+                (profile_sampling_frequency - sampling_frequency).abs() < 1.0
+                    && levels.iter().all(|&level| level >= 0.0),
+            )?;
+            detector.set_noise_profile(Some(NoiseProfile::from_levels(
+                profile_sampling_frequency,
+                levels,
+            )));
+            if let Some(suppressor) = detector.state.noise_suppressor.as_mut() {
+                suppressor.read_snapshot(&mut reader)?;
+            }
+        }
+        if reader.bool()? {
+            detector.set_hum_filter(Some(match reader.u8()? {
+                50 => MainsFrequency::Hz50,
+                60 => MainsFrequency::Hz60,
+                _ => return Err(SnapshotError::InvalidFormat),
+            }));
+            if let Some(filter) = detector.state.hum_filter.as_mut() {
+                filter.read_snapshot(&mut reader)?;
+            }
+        }
+        if reader.bool()? {
+            detector.set_sustain_suppression(true);
+            if let Some(suppressor) = detector.state.sustain_suppressor.as_mut() {
+                suppressor.read_snapshot(&mut reader)?;
+            }
+        }
+        if reader.bool()? {
+            detector.set_gain_normalization(true);
+            if let Some(normalizer) = detector.state.gain_normalizer.as_mut() {
+                normalizer.read_snapshot(&mut reader)?;
+            }
+        }
+
+        detector.state.previous_beat = reader.option(EnvelopeInfo::read_snapshot)?;
+        detector.state.transferred_beat_time = reader.option(SnapshotReader::duration)?;
+        detector.state.search_begin_total_index = reader.option(SnapshotReader::u64)?;
+        detector.state.hum_free_total_index = reader.u64()?;
+        detector.state.muted_until = reader.option(SnapshotReader::duration)?;
+        detector.state.gaps = Gaps {
+            total_samples: reader.u64()?,
+            samples_before_latest: reader.u64()?,
+            latest_total_index: reader.u64()?,
+        };
+        detector.state.downsample_phase = reader.u32()? as usize;
+        SnapshotReader::check(detector.state.downsample_phase < D)?;
+        detector.state.latest_max_abs = reader.i16()?;
+        detector.state.latest_processed_count = reader.u32()? as usize;
+        detector.peak_cache.read_snapshot(&mut reader)?;
+
+        let history = AudioHistory::from_snapshot(reader.rest())?;
+        SnapshotReader::check(
+            history.capacity() == detector.history.capacity()
+                && history.sampling_frequency() == detector.history.sampling_frequency(),
+        )?;
+        detector.history = history;
+        Ok(detector)
+    }
+
+    /// Writes everything but the audio history, see [`Self::snapshot_into`].
+    fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        let state = &self.state;
+        writer.bytes(&SNAPSHOT_MAGIC);
+        writer.u8(SNAPSHOT_VERSION);
+        writer.u32(D as u32);
+        writer.f32(self.original_sampling_frequency());
+        writer.bool(state.needs_lowpass_filter);
+        writer.bool(state.envelope_input);
+        writer.bool(state.preprocessed_input);
+
+        writer.u8(match state.frequency_weighting {
+            FrequencyWeighting::Lowpass => 0,
+            FrequencyWeighting::KickEmphasis => 1,
+        });
+        state.lowpass_filter.write_snapshot(writer);
+        writer.option(state.emphasis_filter.as_ref(), |writer, filter| {
+            filter.write_snapshot(writer);
+        });
+        writer.option(state.custom_filter.as_ref(), |writer, filter| {
+            filter.write_snapshot(writer);
+        });
+        writer.bool(state.is_lowpass_filter_primed);
+
+        writer.u32(state.scan_stride as u32);
+        writer.duration(state.search_overlap);
+        state.envelope_config.write_snapshot(writer);
+        writer.duration(state.statistics_decay);
+        state.statistics.write_snapshot(writer);
+
+        writer.option(state.noise_suppressor.as_ref(), |writer, suppressor| {
+            let profile = suppressor.profile();
+            writer.f32(profile.sampling_frequency());
+            for level in profile.levels() {
+                writer.f32(level);
+            }
+            suppressor.write_snapshot(writer);
+        });
+        writer.option(state.hum_filter.as_ref(), |writer, filter| {
+            writer.u8(filter.mains_frequency().hz() as u8);
+            filter.write_snapshot(writer);
+        });
+        writer.option(state.sustain_suppressor.as_ref(), |writer, suppressor| {
+            suppressor.write_snapshot(writer);
+        });
+        writer.option(state.gain_normalizer.as_ref(), |writer, normalizer| {
+            normalizer.write_snapshot(writer);
+        });
+
+        writer.option(state.previous_beat.as_ref(), |writer, beat| {
+            beat.write_snapshot(writer);
+        });
+        writer.option(state.transferred_beat_time, SnapshotWriter::duration);
+        writer.option(state.search_begin_total_index, SnapshotWriter::u64);
+        writer.u64(state.hum_free_total_index);
+        writer.option(state.muted_until, SnapshotWriter::duration);
+        writer.u64(state.gaps.total_samples);
+        writer.u64(state.gaps.samples_before_latest);
+        writer.u64(state.gaps.latest_total_index);
+        writer.u32(state.downsample_phase as u32);
+        writer.i16(state.latest_max_abs);
+        writer.u32(state.latest_processed_count as u32);
+        self.peak_cache.write_snapshot(writer);
+    }
+
+    /// Suppresses all beats in the audio of the given duration, beginning with
+    /// the next update. This is useful while the application plays a sound
+    /// on its own speakers, such as a jingle, that the microphone picks up and
+    /// that would cause false beats.
+    ///
+    /// The duration is measured with the sample clock, like
+    /// [`Self::passed_time`]. Beats whose maximum lies in the muted audio are
+    /// detected but not reported, even if they are detected after the mute
+    /// ended. They still count as the previous beat, e.g., for
+    /// [`Self::last_beat_age`]. Muting again extends the mute if it ends
+    /// later.
+    pub fn mute_for(&mut self, duration: Duration) {
+        let until = self.passed_time() + duration;
+        self.state.muted_until = Some(
+            self.state
+                .muted_until
+                .map_or(until, |muted| muted.max(until)),
+        );
+    }
+
+    /// Ends a mute of [`Self::mute_for`] at once.
+    pub fn unmute(&mut self) {
+        self.state.muted_until = None;
+    }
+
+    /// Returns the point in time, like [`Self::passed_time`], until which
+    /// beats are suppressed. `None` if the detector isn't muted.
+    pub fn muted_until(&self) -> Option<Duration> {
+        self.state
+            .muted_until
+            .filter(|&until| until > self.passed_time())
+    }
+
+    /// Reports that the audio source skipped audio of the given duration,
+    /// such as during a hiccup of a Bluetooth connection.
+    ///
+    /// Other than feeding silence, this doesn't disturb the detection. The
+    /// time of the detector advances by the gap, so that the timestamps of
+    /// all following beats stay aligned with the real time. The lowpass filter
+    /// starts over, so that the jump in the audio doesn't cause a beat.
+    pub fn signal_gap(&mut self, duration: Duration) {
+        let samples =
+            libm::roundf(duration.as_secs_f32() * self.original_sampling_frequency()) as u64;
+        if samples == 0 {
+            return;
+        }
+        self.state.gaps = Gaps {
+            total_samples: self.state.gaps.total_samples + samples,
+            samples_before_latest: self.state.gaps.total_samples,
+            latest_total_index: self.history.total_consumed_samples(),
+        };
+        self.state.is_lowpass_filter_primed = false;
+    }
+
+    /// Returns the accumulated duration of all gaps reported with
+    /// [`Self::signal_gap`].
+    pub fn gap_duration(&self) -> Duration {
+        SourcePosition::from_samples(
+            self.state.gaps.total_samples,
+            self.original_sampling_frequency(),
+        )
+        .time
+    }
+
+    /// Returns the fill level of the internal audio buffer in range
+    /// `0.0..=1.0`.
+    pub fn buffer_fill(&self) -> f32 {
+        self.history.len() as f32 / self.history.capacity() as f32
+    }
+
+    /// Returns a continuous score in range `0.0..=1.0` of how much the audio
+    /// of the latest update looks like a beat. It relates the loudest sample
+    /// of the latest update to the threshold the envelope search uses for
+    /// the maximum of a beat. `1.0` means that the threshold is reached.
+    ///
+    /// Other than the discrete beats, this changes with every update, which
+    /// is useful to drive animations, such as a VU meter, between beats.
+    /// Before the detector [is warmed up], this is `0.0`.
+    ///
+    /// [is warmed up]: Self::is_warmed_up
+    pub fn beat_probability(&self) -> f32 {
+        if !self.is_warmed_up() {
+            return 0.0;
+        }
+        self.amplitude_histogram().median().map_or(0.0, |median| {
+            let threshold = median as f32 * self.state.envelope_config.max_peak_to_median_min_ratio;
+            (self.state.latest_max_abs as f32 / threshold).clamp(0.0, 1.0)
+        })
+    }
+
+    /// Returns the internal audio history. To attach the state of the
+    /// detector to a bug report, use [`Self::snapshot_into`].
+    pub const fn history(&self) -> &AudioHistory<N> {
+        &self.history
+    }
+
+    /// Returns the samples that the latest update added to the internal audio
+    /// history, i.e., what the detector "sees" after the lowpass filter and
+    /// downsampling. This is useful for visualizers.
+    ///
+    /// [`SampleInfo::total_index`] refers to the processed audio whereas
+    /// [`SampleInfo::source`] refers to the audio that was passed to the
+    /// detector. If the latest update contained more samples than the history
+    /// can hold, only the newest samples are returned.
+    pub fn processed_samples_since_last_call(&self) -> impl Iterator<Item = SampleInfo> + '_ {
+        let count = self.state.latest_processed_count.min(self.history.len());
+        (self.history.len() - count..self.history.len())
+            .map(|index| self.with_source_position(self.history.index_to_sample_info(index)))
+    }
+
+    /// Maps the total index of a processed sample, such as
+    /// [`SampleInfo::total_index`] of a beat, to the total index of the
+    /// corresponding sample in the audio that was passed to the detector.
+    ///
+    /// This doesn't account for the delay of the lowpass filter. See
+    /// [`Self::source_position`].
+    pub const fn original_total_index(&self, total_index: u64) -> u64 {
+        // Of each group of `D` samples, the first one is kept.
+        total_index * D as u64
+    }
+
+    /// Maps the total index of a processed sample to its position in the audio
+    /// that was passed to the detector. Other than
+    /// [`Self::original_total_index`], this compensates the delay of the
+    /// lowpass filter, so that the position matches the audible event.
+    ///
+    /// All samples returned by the detector already carry this position in
+    /// [`SampleInfo::source`]. Gaps reported with [`Self::signal_gap`] are
+    /// included.
+    pub fn source_position(&self, total_index: u64) -> SourcePosition {
+        let samples = (self.original_total_index(total_index)
+            + self.state.gaps.samples_before(total_index))
+        .saturating_sub(self.lowpass_group_delay_samples());
+        SourcePosition::from_samples(samples, self.original_sampling_frequency())
+    }
+
+    /// Returns the sampling frequency of the audio that is passed to the
+    /// detector.
+    pub fn original_sampling_frequency(&self) -> f32 {
+        self.history.sampling_frequency() * D as f32
+    }
+
+    /// Returns the delay of the lowpass filter in samples of the original
+    /// audio.
+    ///
+    /// The group delay of a second order lowpass filter at low frequencies
+    /// is `1 / (Q * ω0)`. Beats are mostly made of such low frequencies.
+    fn lowpass_group_delay_samples(&self) -> u64 {
+        if self.state.envelope_input {
+            return 0;
+        }
+        if let Some(custom_filter) = &self.state.custom_filter {
+            return Self::custom_filter_group_delay_samples(
+                custom_filter.filter(),
+                self.original_sampling_frequency(),
+            );
+        }
+        if !self.state.needs_lowpass_filter {
+            return 0;
+        }
+        let omega0 =
+            2.0 * core::f32::consts::PI * self.state.frequency_weighting.cutoff_frequency_hz();
+        let delay_secs = 1.0 / (Q_BUTTERWORTH_F32 * omega0);
+        libm::roundf(delay_secs * self.original_sampling_frequency()) as u64
+    }
+
+    /// Replaces [`SampleInfo::source`] by the position in the audio that was
+    /// passed to the detector.
+    fn with_source_position(&self, info: SampleInfo) -> SampleInfo {
+        SampleInfo {
+            source: self.source_position(info.total_index),
+            ..info
+        }
+    }
+
+    /// Returns the histogram of the absolute peak amplitudes in the internal
+    /// audio buffer. Its percentiles are the reference for the thresholds of
+    /// the envelope search.
+    pub const fn amplitude_histogram(&self) -> &AmplitudeHistogram {
+        self.peak_cache.histogram()
+    }
+
+    /// Returns whether the raw audio input of the latest update was clipping.
+    /// This is cheaper than [`Self::diagnose`].
+    pub const fn is_clipping(&self) -> bool {
+        self.state.clipping_detector.is_clipping()
+    }
+
+    /// Checks the consumed audio for common problems and returns possible
+    /// reasons why no beats are detected.
+    ///
+    /// This is meant to help users to find issues in their setup, such as a
+    /// muted microphone or a wrong sampling rate.
+    pub fn diagnose(&self) -> Diagnosis {
+        let insufficient_history = !self.is_warmed_up();
+        let below_noise_floor = diagnosis::is_below_noise_floor(self.history.samples().copied());
+        // Only the built-in lowpass filter limits the frequencies to a known
+        // range. An envelope has no roots that tell its frequencies.
+        let sample_rate_mismatch_suspected = !insufficient_history
+            && !below_noise_floor
+            && self.state.needs_lowpass_filter
+            && self.state.custom_filter.is_none()
+            && !self.state.envelope_input
+            && diagnosis::is_sample_rate_mismatch_suspected(
+                self.history.samples().copied(),
+                self.history.len(),
+                self.history.sampling_frequency(),
+                self.state.frequency_weighting.cutoff_frequency_hz(),
+            );
+        Diagnosis {
+            insufficient_history,
+            below_noise_floor,
+            clipping: self.state.clipping_detector.is_clipping(),
+            sample_rate_mismatch_suspected,
+        }
+    }
+
+    /// Applies the data from the given audio input to the lowpass filter (if
+    /// necessary) and adds it to the internal audio window.
+    fn consume_audio(&mut self, mono_samples_iter: impl Iterator<Item = i16>) {
+        let mut mono_samples_iter = mono_samples_iter.peekable();
+        if self.applies_filter() && !self.state.is_lowpass_filter_primed {
+            if let Some(&first_sample) = mono_samples_iter.peek() {
+                self.prime_lowpass_filter(first_sample);
+            }
+        }
+
+        self.state.clipping_detector.begin_update();
+        let total_consumed_samples = self.history.total_consumed_samples();
+        let mut latest_max_abs = 0;
+        let mut uncompensated_max_abs = 0;
+        let mut downsample_phase = self.state.downsample_phase;
+        let hum_filter_was_engaged = self
+            .state
+            .hum_filter
+            .as_ref()
+            .is_some_and(HumFilter::is_engaged);
+        let iter = mono_samples_iter.map(|sample| {
+            self.state.clipping_detector.feed(sample);
+            // An envelope stream or preprocessed audio is analyzed as it is.
+            let sample = if self.state.envelope_input || self.state.preprocessed_input {
+                sample
+            } else {
+                let sample = self
+                    .state
+                    .noise_suppressor
+                    .as_mut()
+                    .map_or(sample, |suppressor| suppressor.process(sample));
+                let sample = self
+                    .state
+                    .hum_filter
+                    .as_mut()
+                    .map_or(sample, |filter| filter.process(sample));
+                let sample = if let Some(custom_filter) = self.state.custom_filter.as_mut() {
+                    util::saturating_f32_to_i16(custom_filter.run(sample as f32))
+                } else if let (true, Some(emphasis_filter)) = (
+                    self.state.needs_lowpass_filter,
+                    self.state.emphasis_filter.as_mut(),
+                ) {
+                    let sample = self
+                        .state
+                        .lowpass_filter
+                        .run(emphasis_filter.run(sample as f32));
+                    // The filters may overshoot the range of the samples.
+                    util::saturating_f32_to_i16(sample)
+                } else if self.state.needs_lowpass_filter {
+                    let sample = self.state.lowpass_filter.run(sample as f32);
+                    // The lowpass filter may overshoot the range of the samples
+                    // for pathological inputs, such as full-scale square waves.
+                    // Hence, the conversion saturates by default. The
+                    // `unchecked-conversion` feature opts into a plain cast.
+                    #[cfg(any(not(feature = "unchecked-conversion"), feature = "all-safe"))]
+                    let sample = util::saturating_f32_to_i16(sample);
+                    // SAFETY: The user opted in and accepts that the filter must
+                    // never overshoot.
+                    #[cfg(all(feature = "unchecked-conversion", not(feature = "all-safe")))]
+                    let sample = unsafe { util::f32_to_i16_unchecked(sample) };
+                    sample
+                } else {
+                    sample
+                };
+                let sample = self
+                    .state
+                    .sustain_suppressor
+                    .as_mut()
+                    .map_or(sample, |suppressor| suppressor.process(sample));
+                sample
+            };
+            let sample = self
+                .state
+                .gain_normalizer
+                .as_ref()
+                .map_or(sample, |normalizer| {
+                    uncompensated_max_abs = uncompensated_max_abs.max(sample.saturating_abs());
+                    normalizer.process(sample)
+                });
+            latest_max_abs = latest_max_abs.max(sample.saturating_abs());
+            sample
+        });
+        if D == 1 {
+            self.history.update(iter);
+        } else {
+            // The lowpass filter also acts as anti-aliasing filter.
+            self.history.update(iter.filter(|_| {
+                let keep = downsample_phase == 0;
+                downsample_phase = (downsample_phase + 1) % D;
+                keep
+            }));
+        }
+        self.state.latest_max_abs = latest_max_abs;
+        self.state.downsample_phase = downsample_phase;
+        self.state.latest_processed_count =
+            (self.history.total_consumed_samples() - total_consumed_samples) as usize;
+
+        // Skip the hummy audio once the hum filter engaged. This also skips
+        // the filtered samples of this update, as the filter engaged
+        // somewhere in between.
+        if !hum_filter_was_engaged
+            && self
+                .state
+                .hum_filter
+                .as_ref()
+                .is_some_and(HumFilter::is_engaged)
+        {
+            // The latest sample, as the search must begin within the history.
+            let total_index = self.history.total_consumed_samples().saturating_sub(1);
+            self.state.hum_free_total_index = total_index;
+            self.state.search_begin_total_index = Some(
+                self.state
+                    .search_begin_total_index
+                    .map_or(total_index, |begin| begin.max(total_index)),
+            );
+        }
+
+        let gain_change = self.state.gain_normalizer.as_mut().and_then(|normalizer| {
+            normalizer.update(
+                uncompensated_max_abs,
+                self.state.latest_processed_count,
+                total_consumed_samples,
+            )
+        });
+        if let Some(change) = gain_change {
+            self.history
+                .scale_since(change.begin_total_index, change.correction);
+            // The cached peaks and their histogram are outdated.
+            self.peak_cache.reset(self.state.scan_stride);
+        }
+        self.peak_cache.update(&self.history);
+        self.state.statistics.update(
+            self.peak_cache.histogram().median(),
+            self.state.latest_processed_count,
+        );
+    }
+
+    /// Feeds the first sample multiple times through the lowpass filter so
+    /// that the filter is in a steady state and doesn't produce a transient
+    /// (which would look like an envelope) when the audio doesn't start at
+    /// zero.
+    fn prime_lowpass_filter(&mut self, first_sample: i16) {
+        if let Some(custom_filter) = self.state.custom_filter.as_mut() {
+            for _ in 0..self.state.lowpass_filter_priming_samples {
+                let _ = custom_filter.run(first_sample as f32);
+            }
+            self.state.is_lowpass_filter_primed = true;
+            return;
+        }
+        for _ in 0..self.state.lowpass_filter_priming_samples {
+            let sample = self
+                .state
+                .emphasis_filter
+                .as_mut()
+                .map_or(first_sample as f32, |filter| {
+                    filter.run(first_sample as f32)
+                });
+            let _ = self.state.lowpass_filter.run(sample);
+        }
+        self.state.is_lowpass_filter_primed = true;
+    }
+
+    fn create_lowpass_filter(
+        sampling_frequency_hz: f32,
+        weighting: FrequencyWeighting,
+    ) -> FilterStage {
+        FilterStage::new(Self::lowpass_coefficients(sampling_frequency_hz, weighting))
+    }
+
+    fn lowpass_coefficients(
+        sampling_frequency_hz: f32,
+        weighting: FrequencyWeighting,
+    ) -> Coefficients<f32> {
+        // Cutoff frequency.
+        let f0 = weighting.cutoff_frequency_hz().hz();
+        // Samling frequency.
+        let fs = sampling_frequency_hz.hz();
+
+        Coefficients::<f32>::from_params(Type::LowPass, fs, f0, Q_BUTTERWORTH_F32)
+            .expect("The sampling frequency should be above twice the cutoff frequency")
+    }
+
+    fn create_emphasis_filter(
+        sampling_frequency_hz: f32,
+        weighting: FrequencyWeighting,
+    ) -> Option<FilterStage> {
+        Self::emphasis_coefficients(sampling_frequency_hz, weighting).map(FilterStage::new)
+    }
+
+    fn emphasis_coefficients(
+        sampling_frequency_hz: f32,
+        weighting: FrequencyWeighting,
+    ) -> Option<Coefficients<f32>> {
+        match weighting {
+            FrequencyWeighting::Lowpass => None,
+            FrequencyWeighting::KickEmphasis => {
+                let mut coefficients = Coefficients::<f32>::from_params(
+                    Type::PeakingEQ(KICK_EMPHASIS_GAIN_DB),
+                    sampling_frequency_hz.hz(),
+                    KICK_EMPHASIS_CENTER_HZ.hz(),
+                    KICK_EMPHASIS_Q,
+                )
+                .expect("The sampling frequency should be above twice the center frequency");
+                // Attenuate the other frequencies instead of boosting the kick
+                // drums, so that loud kick drums don't clip.
+                let gain = libm::powf(10.0, -KICK_EMPHASIS_GAIN_DB / 20.0);
+                coefficients.b0 *= gain;
+                coefficients.b1 *= gain;
+                coefficients.b2 *= gain;
+                Some(coefficients)
+            }
+        }
+    }
+
+    /// Returns the group delay of a custom filter in samples, measured at
+    /// [`CUSTOM_FILTER_GROUP_DELAY_HZ`], where beats have most of their
+    /// energy.
+    fn custom_filter_group_delay_samples(filter: &CustomFilter, sampling_frequency: f32) -> u64 {
+        let omega = 2.0 * core::f32::consts::PI * CUSTOM_FILTER_GROUP_DELAY_HZ / sampling_frequency;
+        libm::roundf(filter.group_delay(omega).max(0.0)) as u64
+    }
+
+    /// Returns the capacity of the audio history for the given sampling
+    /// frequency of the history. The history always covers the same duration,
+    /// also for sampling frequencies other than those of typical audio, such
+    /// as the readings of an accelerometer or an envelope stream.
+    fn history_capacity(sampling_frequency: f32) -> usize {
+        let capacity =
+            (DEFAULT_AUDIO_HISTORY_WINDOW_MS as f32 * sampling_frequency / 1000.0) as usize;
+        capacity.clamp(1, Self::HISTORY_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::excessive_precision)]
+#[allow(clippy::missing_const_for_fn)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils, BiquadCoefficients, CustomFilter, Decision, DiagnosticIssue, MergePolicy,
+        NoiseProfileLearner, MAX_CUSTOM_FILTER_STAGES,
+    };
+    use std::time::Duration;
+    use std::vec::Vec;
+
+    #[test]
+    fn is_send_and_sync() {
+        fn accept<I: Send + Sync>() {}
+
+        accept::<BeatDetector>();
+    }
+
+    #[test]
+    fn new_in_place() {
+        let mut memory = std::boxed::Box::new(MaybeUninit::uninit());
+        let detector = BeatDetector::new_in_place(&mut memory, 44100.0, true);
+        let expected = BeatDetector::new(44100.0, true);
+        assert_eq!(format!("{detector:?}"), format!("{expected:?}"));
+
+        let (samples, header) = test_utils::samples::holiday_long();
+        let detector = BeatDetector::new_in_place(&mut memory, header.sample_rate as f32, true);
+        let mut expected = BeatDetector::new(header.sample_rate as f32, true);
+        for chunk in samples.chunks(2048) {
+            assert_eq!(
+                detector.update_and_detect_beat(chunk.iter().copied()),
+                expected.update_and_detect_beat(chunk.iter().copied())
+            );
+        }
+    }
+
+    #[test]
+    fn diagnose() {
+        let (samples, header) = test_utils::samples::holiday_long();
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        assert_eq!(
+            detector.diagnose().issues().collect::<Vec<_>>(),
+            [
+                DiagnosticIssue::InsufficientHistory,
+                DiagnosticIssue::BelowNoiseFloor
+            ]
+        );
+        let _ = detector.update_and_detect_beat(samples.iter().copied());
+        assert_eq!(detector.diagnose(), Diagnosis::default());
+        let histogram = detector.amplitude_histogram();
+        assert!(histogram.median().unwrap() < histogram.p90().unwrap());
+
+        // Quiet audio
+        let _ = detector.update_and_detect_beat(samples.iter().map(|sample| sample / 100));
+        assert!(detector.diagnose().below_noise_floor);
+
+        // Clipping audio
+        let _ =
+            detector.update_and_detect_beat(samples.iter().map(|&sample| sample.saturating_mul(8)));
+        assert!(detector.diagnose().clipping);
+
+        // Wrong sampling rate
+        let mut detector = BeatDetector::new(8000.0, true);
+        let _ = detector.update_and_detect_beat(samples.iter().copied());
+        assert_eq!(
+            detector.diagnose().issues().collect::<Vec<_>>(),
+            [DiagnosticIssue::SampleRateMismatchSuspected]
+        );
+
+        // Without the lowpass filter, the frequencies of the full spectrum,
+        // such as a 1 kHz tone, don't tell anything about the sampling rate.
+        let tone = (0..44100)
+            .map(|i| {
+                (libm::sinf(i as f32 * 1000.0 * 2.0 * core::f32::consts::PI / 44100.0) * 10000.0)
+                    as i16
+            })
+            .collect::<Vec<_>>();
+        let mut detector = BeatDetector::new(44100.0, false);
+        let _ = detector.update_and_detect_beat(tone.iter().copied());
+        assert_eq!(detector.diagnose(), Diagnosis::default());
+    }
+
+    /// This test serves as base so that the underlying functionality
+    /// (forwarding to envelope iterator, do not detect same beat twice) works.
+    /// It is not feasible to test the complex return type that way in every
+    /// test.
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__static__no_lowpass__holiday_single_beat() {
+        let (samples, header) = test_utils::samples::holiday_single_beat();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, false);
+        let beat = detector
+            .update_and_detect_beat(samples.iter().copied())
+            .unwrap();
+        // `PartialEq` only compares the total indices. Hence, the other
+        // fields are checked one by one.
+        for (info, value, index, timestamp_ns, duration_behind_ns, source_ns) in [
+            (beat.from, 3728, 259, 5_873_015, 401_836_735, 5_873_016),
+            (beat.to, 12808, 1969, 44_648_526, 363_061_224, 44_648_526),
+            (beat.max, -21146, 829, 18_798_185, 388_911_565, 18_798_186),
+        ] {
+            assert_eq!(info.value, value);
+            assert_eq!(info.value_abs, value.abs());
+            assert_eq!(info.index, index);
+            assert_eq!(info.total_index, index as u64);
+            assert_eq!(info.timestamp, Duration::from_nanos(timestamp_ns));
+            assert_eq!(
+                info.duration_behind,
+                Duration::from_nanos(duration_behind_ns)
+            );
+            assert_eq!(
+                info.source,
+                SourcePosition {
+                    samples: index as u64,
+                    time: Duration::from_nanos(source_ns),
+                }
+            );
+        }
+        assert_eq!(detector.update_and_detect_beat(core::iter::empty()), None);
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__static__lowpass__holiday_single_beat() {
+        let (samples, header) = test_utils::samples::holiday_single_beat();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        assert_eq!(
+            detector
+                .update_and_detect_beat(samples.iter().copied())
+                .map(|info| info.max.index),
+            // It seems that the lowpass filter causes a slight delay. This
+            // is also what my research found [0].
+            //
+            // As long as it is reasonable small, I think this is good, I guess?
+            // [0]: https://electronics.stackexchange.com/questions/372692/low-pass-filter-delay
+            Some(939)
+        );
+        assert_eq!(detector.update_and_detect_beat(core::iter::empty()), None);
+    }
+
+    #[test]
+    fn lowpass_filter_is_primed_with_first_sample() {
+        let mut detector = BeatDetector::new(44100.0, true);
+        let _ = detector.update_and_detect_beat([10000; 100].iter().copied());
+
+        // Without priming, the filter output would start at zero and slowly
+        // swing in.
+        assert!(detector
+            .history
+            .samples()
+            .all(|&sample| (9900..=10100).contains(&sample)));
+    }
+
+    #[test]
+    fn is_warmed_up() {
+        let (samples, header) = test_utils::samples::holiday_single_beat();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, false);
+        assert!(!detector.is_warmed_up());
+
+        let warm_up_samples = (WARM_UP_DURATION.as_secs_f32() * header.sample_rate as f32) as usize;
+        let (warm_up, remaining) = samples.split_at(warm_up_samples - 1);
+
+        assert_eq!(
+            detector.update_and_detect_beat(warm_up.iter().copied()),
+            None
+        );
+        assert!(!detector.is_warmed_up());
+
+        let beat = detector.update_and_detect_beat(remaining.iter().copied());
+        assert!(detector.is_warmed_up());
+        assert_eq!(beat.map(|info| info.max.total_index), Some(829));
+    }
+
+    #[test]
+    fn beat_probability() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        assert_eq!(detector.beat_probability(), 0.0);
+
+        let probabilities = samples
+            .chunks(1024)
+            .map(|chunk| {
+                let _ = detector.update_and_detect_beat(chunk.iter().copied());
+                detector.beat_probability()
+            })
+            .collect::<Vec<_>>();
+
+        assert!(probabilities
+            .iter()
+            .all(|probability| (0.0..=1.0).contains(probability)));
+        // Strong beats reach the threshold.
+        assert!(probabilities.contains(&1.0));
+        // The audio between the beats doesn't.
+        assert!(probabilities.iter().any(|&probability| probability < 0.5));
+
+        // Silence, once the lowpass filter settled.
+        let _ = detector.update_and_detect_beat([0; 4096].iter().copied());
+        let _ = detector.update_and_detect_beat([0; 1024].iter().copied());
+        assert!(detector.beat_probability() < 0.01);
+    }
+
+    #[test]
+    fn processed_samples_since_last_call() {
+        let samples = (0..1000).map(|i| i as i16).collect::<Vec<_>>();
+
+        let mut detector = BeatDetector::new(44100.0, false);
+        assert_eq!(detector.processed_samples_since_last_call().count(), 0);
+        let _ = detector.update_and_detect_beat(samples[..600].iter().copied());
+        let _ = detector.update_and_detect_beat(samples[600..].iter().copied());
+        let processed = detector
+            .processed_samples_since_last_call()
+            .map(|info| (info.total_index, info.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            processed,
+            (600..1000).map(|i| (i, i as i16)).collect::<Vec<_>>()
+        );
+
+        // Lowpassed and downsampled.
+        let mut detector = BeatDetectorConst::<4630, 4, 128>::new(44100.0, true);
+        let _ = detector.update_and_detect_beat(samples[..601].iter().copied());
+        let _ = detector.update_and_detect_beat(samples[601..].iter().copied());
+        let processed = detector
+            .processed_samples_since_last_call()
+            .map(|info| detector.original_total_index(info.total_index))
+            .collect::<Vec<_>>();
+        assert_eq!(processed, (604..1000).step_by(4).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn source_position_compensates_downsampling_and_lowpass_delay() {
+        let (samples, header) = test_utils::samples::holiday_single_beat();
+        let detect = |detector: &mut dyn FnMut(&[i16]) -> Option<BeatInfo>| {
+            let beat = detector(&samples).unwrap();
+            (beat.max.total_index, beat.max.source)
+        };
+
+        // Reference without any processing.
+        let mut detector = BeatDetector::new(header.sample_rate as f32, false);
+        let (total_index, source) =
+            detect(&mut |s| detector.update_and_detect_beat(s.iter().copied()));
+        assert_eq!(total_index, 829);
+        assert_eq!(source, SourcePosition::from_samples(829, 44100.0));
+
+        // The lowpass filter delays the maximum.
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        let (total_index, source) =
+            detect(&mut |s| detector.update_and_detect_beat(s.iter().copied()));
+        assert_eq!(total_index, 939);
+        assert_eq!(detector.lowpass_group_delay_samples(), 104);
+        assert_eq!(source.samples, 835);
+
+        // The total index refers to the downsampled audio whereas the source
+        // position is still close to the one above. Downsampling reduces the
+        // precision a bit.
+        let mut detector = BeatDetectorConst::<4630, 4, 128>::new(44100.0, true);
+        let (total_index, source) =
+            detect(&mut |s| detector.update_and_detect_beat(s.iter().copied()));
+        assert_eq!(total_index, 239);
+        assert_eq!(source.samples, 239 * 4 - 104);
+        assert_eq!(source.time, SourcePosition::from_samples(852, 44100.0).time);
+        assert!(detector
+            .processed_samples_since_last_call()
+            .all(|info| info.source == detector.source_position(info.total_index)));
+    }
+
+    #[test]
+    fn signalled_gaps_keep_timestamps_aligned() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        // 100 ms between two beats.
+        let gap = 74970..79380;
+        let detect = |drop_gap: bool, signal_gap: bool| {
+            let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+            let mut beats = Vec::new();
+            for (i, chunk) in samples.chunks(2205).enumerate() {
+                let chunk_begin = i * 2205;
+                if drop_gap && gap.contains(&chunk_begin) {
+                    if signal_gap && chunk_begin == gap.start {
+                        detector.signal_gap(Duration::from_millis(100));
+                    }
+                    continue;
+                }
+                if let Some(beat) = detector.update_and_detect_beat(chunk.iter().copied()) {
+                    beats.push(beat.max.source.samples);
+                }
+            }
+            (beats, detector.passed_time())
+        };
+
+        let (reference, passed_time) = detect(false, false);
+        assert_eq!(
+            reference,
+            &[31230, 47060, 65820, 84120, 102000, 120140, 138460]
+        );
+        assert_eq!(detect(true, true), (reference.clone(), passed_time));
+
+        // Without reporting the gap, all following beats are too early.
+        let (beats, _) = detect(true, false);
+        assert_eq!(beats[..3], reference[..3]);
+        assert_eq!(beats[3], reference[3] - 4410);
+    }
+
+    #[test]
+    fn search_skips_analyzed_noise() {
+        let mut detector = BeatDetector::new(44100.0, false);
+        // Sine wave with 100 Hz that is louder than the noise threshold of the
+        // root search but still too quiet for an envelope.
+        let noise = (0..44100)
+            .map(|i| {
+                (libm::sinf(i as f32 * 100.0 * 2.0 * core::f32::consts::PI / 44100.0) * 2500.0)
+                    as i16
+            })
+            .collect::<Vec<_>>();
+
+        let mut prev_search_begin = 0;
+        for chunk in noise.chunks(8820) {
+            assert_eq!(detector.update_and_detect_beat(chunk.iter().copied()), None);
+            let search_begin = detector.state.search_begin_total_index.unwrap();
+            assert!(search_begin > prev_search_begin);
+            prev_search_begin = search_begin;
+        }
+
+        // Tolerate an overlap into already analyzed audio.
+        let mut detector_with_overlap = BeatDetector::new(44100.0, false);
+        detector_with_overlap.set_search_overlap(Duration::from_millis(50));
+        let (samples, header) = test_utils::samples::holiday_long();
+        assert_eq!(header.sample_rate, 44100);
+        let beats = simulate_dynamic_audio_source(2048, &samples, &mut detector_with_overlap);
+        assert!(!beats.is_empty());
+        assert!(beats.windows(2).all(|w| w[0] < w[1]));
+
+        // Even a huge overlap doesn't report overlapping beats.
+        let mut detector = BeatDetector::new(44100.0, false);
+        detector.set_search_overlap(Duration::from_secs(10));
+        let beats = samples
+            .chunks(2048)
+            .filter_map(|chunk| detector.update_and_detect_beat(chunk.iter().copied()))
+            .collect::<Vec<_>>();
+        assert!(!beats.is_empty());
+        assert!(beats
+            .windows(2)
+            .all(|w| w[0] < w[1] && !w[0].overlaps(&w[1])));
+    }
+
+    fn simulate_dynamic_audio_source<const N: usize, const D: usize, const P: usize>(
+        chunk_size: usize,
+        samples: &[i16],
+        detector: &mut BeatDetectorConst<N, D, P>,
+    ) -> Vec<u64> {
+        samples
+            .chunks(chunk_size)
+            .flat_map(|samples| {
+                detector
+                    .update_and_detect_beat(samples.iter().copied())
+                    .map(|info| info.max.total_index)
+            })
+            .collect::<std::vec::Vec<_>>()
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__no_lowpass__holiday_single_beat() {
+        let (samples, header) = test_utils::samples::holiday_single_beat();
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, false);
+        assert_eq!(
+            simulate_dynamic_audio_source(256, &samples, &mut detector),
+            &[829]
+        );
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, false);
+        assert_eq!(
+            simulate_dynamic_audio_source(2048, &samples, &mut detector),
+            &[829]
+        );
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__no_lowpass__sample1_double_beat() {
+        let (samples, header) = test_utils::samples::sample1_double_beat();
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, false);
+        assert_eq!(
+            simulate_dynamic_audio_source(2048, &samples, &mut detector),
+            &[1309, 8639]
+        );
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__no_lowpass__holiday_long_with_hiss() {
+        use rand::{Rng, SeedableRng};
+
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let hiss = (0..samples.len())
+            .map(|_| rng.gen_range(-4000..=4000))
+            .collect::<Vec<i16>>();
+        let noisy = samples
+            .iter()
+            .zip(&hiss)
+            .map(|(&sample, &hiss)| sample.saturating_add(hiss))
+            .collect::<Vec<_>>();
+        let detect = |samples: &[i16], profile| {
+            let mut detector = BeatDetector::new(header.sample_rate as f32, false);
+            detector.set_noise_profile(profile);
+            simulate_dynamic_audio_source(2048, samples, &mut detector)
+        };
+
+        let clean = detect(&samples, None);
+        assert_eq!(
+            clean,
+            &[29077, 31227, 47047, 65817, 83767, 101997, 120137, 138127]
+        );
+        // Without a lowpass filter, the hiss causes many false positives.
+        assert!(detect(&noisy, None).len() > clean.len() * 3);
+
+        let mut learner = NoiseProfileLearner::new(header.sample_rate as f32);
+        learner.update(hiss.iter().copied());
+        let suppressed = detect(&noisy, learner.finish());
+        assert_eq!(
+            suppressed,
+            &[29069, 31219, 47049, 49759, 65819, 83769, 85729, 102009, 120149, 138129]
+        );
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__lowpass__holiday_long_with_hum() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let hum = samples
+            .iter()
+            .enumerate()
+            .map(|(i, &sample)| {
+                let hum = libm::sinf(i as f32 * 50.0 * 2.0 * core::f32::consts::PI / 44100.0);
+                sample.saturating_add((hum * 10000.0) as i16)
+            })
+            .collect::<Vec<_>>();
+        let detect = |samples: &[i16], mains_frequency| {
+            let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+            detector.set_hum_filter(mains_frequency);
+            simulate_dynamic_audio_source(2048, samples, &mut detector)
+        };
+
+        // The hum dominates the lowpassed signal.
+        assert_eq!(detect(&hum, None), &[66189]);
+
+        // With the filter, all real beats are found again.
+        let real_beats = detect(&samples, None);
+        assert_eq!(
+            real_beats,
+            &[31337, 47167, 65927, 84217, 102107, 120247, 138557]
+        );
+        let filtered = detect(&hum, Some(MainsFrequency::Hz50));
+        for beat in &real_beats {
+            assert!(
+                filtered.iter().any(|&other| other.abs_diff(*beat) < 441),
+                "{beat}: {filtered:?}"
+            );
+        }
+        // Known limitation: the notch at 50 Hz also removes the fundamental
+        // of the kick drums and of the bass. On hummy audio, this splits the
+        // first two beats into envelopes of about equal loudness, which are
+        // reported, as they can't be told apart from fast real beats. The
+        // quieter ringing after the other beats is merged.
+        assert_eq!(
+            filtered,
+            &[29819, 31379, 32179, 47449, 49869, 66239, 84249, 102409, 120249, 138589]
+        );
+
+        // Without hum, the filter doesn't engage and changes nothing.
+        assert_eq!(detect(&samples, Some(MainsFrequency::Hz50)), real_beats);
+        assert_eq!(detect(&samples, Some(MainsFrequency::Hz60)), real_beats);
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__no_lowpass__sample1_double_beat_fast_with_hum() {
+        let (samples, header) = test_utils::samples::sample1_double_beat();
+        for speed in [2.3, 2.4, 2.5, 2.6] {
+            // The two beats are 64 to 72 ms apart. The hum filter needs
+            // about half a second to engage.
+            let mut fast = vec![0; 44100];
+            fast.extend(test_utils::render::resample(&samples, speed));
+            fast.extend([0; 22050]);
+            let hum = fast
+                .iter()
+                .enumerate()
+                .map(|(i, &sample)| {
+                    let hum = libm::sinf(i as f32 * 50.0 * 2.0 * core::f32::consts::PI / 44100.0);
+                    sample.saturating_add((hum * 2000.0) as i16)
+                })
+                .collect::<Vec<_>>();
+            let detect = |samples: &[i16], mains_frequency| {
+                let mut detector = BeatDetector::new(header.sample_rate as f32, false);
+                detector.set_hum_filter(mains_frequency);
+                simulate_dynamic_audio_source(2048, samples, &mut detector)
+            };
+
+            let real_beats = detect(&fast, None);
+            assert_eq!(real_beats.len(), 2, "{speed}: {real_beats:?}");
+            let gap = (real_beats[1] - real_beats[0]) as f32 / header.sample_rate as f32;
+            assert!((0.06..=0.08).contains(&gap), "{speed}: {gap}");
+
+            // Both beats are reported, none is merged as ringing.
+            let filtered = detect(&hum, Some(MainsFrequency::Hz50));
+            assert_eq!(filtered.len(), 2, "{speed}: {filtered:?}");
+            for (beat, real_beat) in filtered.iter().zip(&real_beats) {
+                assert!(beat.abs_diff(*real_beat) < 441, "{speed}: {filtered:?}");
+            }
+        }
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__kick_emphasis() {
+        let detect = |(samples, header): (Vec<i16>, hound::WavSpec)| {
+            let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+            detector.set_frequency_weighting(FrequencyWeighting::KickEmphasis);
+            simulate_dynamic_audio_source(2048, &samples, &mut detector)
+        };
+
+        // The same beats as with the lowpass filter, at slightly different
+        // maxima, and a quieter transient at 29387 that the sensitive preset
+        // finds as well.
+        assert_eq!(
+            detect(test_utils::samples::holiday_long()),
+            &[29387, 31607, 47137, 65887, 83857, 102367, 120207, 138517]
+        );
+        assert_eq!(
+            detect(test_utils::samples::sample1_long()),
+            &[12887, 93753, 101413, 189555, 270741, 278421]
+        );
+
+        // The higher cutoff of the lowpass filter causes less delay.
+        let mut detector = BeatDetector::new(44100.0, true);
+        let delay = detector.lowpass_group_delay_samples();
+        detector.set_frequency_weighting(FrequencyWeighting::KickEmphasis);
+        assert!(detector.lowpass_group_delay_samples() < delay);
+    }
+
+    #[test]
+    #[cfg(any(not(feature = "unchecked-conversion"), feature = "all-safe"))]
+    fn lowpass_overshoot_saturates() {
+        // The lowpass filter rings at each edge of a full-scale square wave
+        // and overshoots the range of the samples.
+        let samples = (0..44100)
+            .map(|i| {
+                if (i / 551) % 2 == 0 {
+                    i16::MAX
+                } else {
+                    -i16::MAX
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut filter = BeatDetector::create_lowpass_filter(44100.0, FrequencyWeighting::Lowpass);
+        assert!(samples
+            .iter()
+            .any(|&sample| libm::fabsf(filter.run(sample as f32)) > i16::MAX as f32));
+
+        let mut detector = BeatDetector::new(44100.0, true);
+        let _ = detector.update_and_detect_beat(samples.iter().copied());
+        assert_eq!(detector.state.latest_max_abs, i16::MAX);
+    }
+
+    /// Four notes of sustained sub-bass that slides from 60 to 40 Hz, like an
+    /// 808. A kick drum hits in the middle of each note.
+    fn sub_bass_with_kicks() -> Vec<i16> {
+        let sampling_frequency = 44100.0;
+        let mut phase = 0.0;
+        (0..4 * 44100)
+            .map(|i| {
+                let t = (i % 44100) as f32 / sampling_frequency;
+                phase += (60.0 - 20.0 * t) / sampling_frequency * 2.0 * core::f32::consts::PI;
+                let amplitude = if t < 0.01 { t / 0.01 } else { 1.0 - 0.3 * t };
+                let mut sample = libm::sinf(phase) * amplitude * 12000.0;
+                if t >= 0.5 {
+                    let t = t - 0.5;
+                    let kick = libm::sinf(t * 55.0 * 2.0 * core::f32::consts::PI);
+                    sample += kick * libm::expf(-t * 30.0) * 14000.0;
+                }
+                sample as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__sustain_suppression() {
+        let samples = sub_bass_with_kicks();
+        let detect = |samples: &[i16], enabled| {
+            let mut detector = BeatDetector::new(44100.0, true);
+            detector.set_sustain_suppression(enabled);
+            simulate_dynamic_audio_source(2048, samples, &mut detector)
+        };
+
+        // The sub-bass masks the kick drums.
+        assert_eq!(detect(&samples, false), &[]);
+        // The begin of each note and each kick drum.
+        assert_eq!(
+            detect(&samples, true),
+            &[4789, 25079, 45509, 69179, 89609, 113279, 133709, 157379]
+        );
+
+        // Music without much sub-bass is barely affected.
+        let (samples, _) = test_utils::samples::holiday_long();
+        assert_eq!(
+            detect(&samples, true),
+            &[47167, 65927, 84217, 102107, 120247, 138557]
+        );
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__merged__sample1_double_beat() {
+        let (samples, header) = test_utils::samples::sample1_double_beat();
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, false);
+        detector.set_envelope_config(EnvelopeConfig {
+            merge_policy: Some(MergePolicy {
+                max_gap: Duration::from_millis(20),
+                max_peak_ratio: 1.2,
+            }),
+            ..EnvelopeConfig::DEFAULT
+        });
+        assert_eq!(
+            simulate_dynamic_audio_source(2048, &samples, &mut detector),
+            &[1309]
+        );
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__lowpass__sample1_long() {
+        let (samples, header) = test_utils::samples::sample1_long();
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        assert_eq!(
+            simulate_dynamic_audio_source(2048, &samples, &mut detector),
+            &[12937, 93793, 101453, 189595, 270781, 278471]
+        );
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__no_lowpass__holiday_long() {
+        let (samples, header) = test_utils::samples::holiday_long();
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, false);
+        assert_eq!(
+            simulate_dynamic_audio_source(2048, &samples, &mut detector),
+            &[29077, 31227, 47047, 65817, 83767, 101997, 120137, 138127]
+        );
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__lowpass__holiday_long() {
+        let (samples, header) = test_utils::samples::holiday_long();
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        assert_eq!(
+            simulate_dynamic_audio_source(2048, &samples, &mut detector),
+            &[31337, 47167, 65927, 84217, 102107, 120247, 138557]
+        );
+    }
+
+    /// Trailing silence, so that the last envelope can decay in every
+    /// variant of [`test_utils::render::corpus`].
+    fn holiday_long_with_trailing_silence() -> (Vec<i16>, hound::WavSpec) {
+        let (mut samples, header) = test_utils::samples::holiday_long();
+        samples.extend([0; 22050]);
+        (samples, header)
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__time_stretch_and_gain_invariance() {
+        let (samples, header) = holiday_long_with_trailing_silence();
+        let reference = [31337, 47167, 65927, 84217, 102107, 120247, 138557];
+
+        for variant in test_utils::render::corpus(&samples) {
+            let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+            let chunk_size = (2048.0 / variant.speed) as usize;
+            let beats = simulate_dynamic_audio_source(chunk_size, &variant.samples, &mut detector);
+
+            // Quieter or louder variants report up to two additional beats.
+            // But every reference beat must be found at its scaled position.
+            assert!(
+                beats.len() <= reference.len() + 2,
+                "speed={}, gain={}: too many beats: {beats:?}",
+                variant.speed,
+                variant.gain,
+            );
+            let found = |expected: u64| beats.iter().any(|beat| beat.abs_diff(expected) < 600);
+            for (i, &beat) in reference.iter().enumerate() {
+                // See `detect__dynamic__time_stretch__second_beat_of_faster_variants`.
+                if i == 1 && variant.speed > 1.0 {
+                    continue;
+                }
+                let expected = (beat as f32 / variant.speed) as u64;
+                assert!(
+                    found(expected),
+                    "speed={}, gain={}: beat {expected} not found: {beats:?}",
+                    variant.speed,
+                    variant.gain,
+                );
+            }
+        }
+    }
+
+    /// In faster variants, the decay of the second kick rises by more than
+    /// the tolerated 5% right after its maximum. The envelope is rejected and
+    /// merges into the next one. Tracked in the known issues of the
+    /// changelog.
+    #[test]
+    #[ignore = "known issue: the second beat is missed in faster variants"]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__time_stretch__second_beat_of_faster_variants() {
+        let (samples, header) = holiday_long_with_trailing_silence();
+
+        for variant in test_utils::render::corpus(&samples) {
+            if variant.speed <= 1.0 {
+                continue;
+            }
+            let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+            let chunk_size = (2048.0 / variant.speed) as usize;
+            let beats = simulate_dynamic_audio_source(chunk_size, &variant.samples, &mut detector);
+
+            let expected = (47167.0 / variant.speed) as u64;
+            assert!(
+                beats.iter().any(|beat| beat.abs_diff(expected) < 600),
+                "speed={}, gain={}: beat {expected} not found: {beats:?}",
+                variant.speed,
+                variant.gain,
+            );
+        }
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__gain_normalization() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let detect = |audio: &[i16], enabled| {
+            let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+            detector.set_gain_normalization(enabled);
+            let beats = simulate_dynamic_audio_source(2048, audio, &mut detector);
+            (beats, detector.gain_normalization())
+        };
+
+        // Steady audio is not affected.
+        let (reference, gain) = detect(&samples, true);
+        assert_eq!(reference, detect(&samples, false).0);
+        assert_eq!(gain, Some(1.0));
+
+        // The song repeats after the volume was turned down. Once the gain
+        // change is detected, the beats of the song are found again.
+        for volume in [0.35, 0.25] {
+            let mut audio = samples.clone();
+            audio.extend(test_utils::render::gain(&samples, volume));
+            let finds_reference = |beats: &[u64]| {
+                reference.iter().all(|&beat| {
+                    let beat = samples.len() as u64 + beat;
+                    beats.iter().any(|&found| found.abs_diff(beat) < 20)
+                })
+            };
+
+            let (beats, gain) = detect(&audio, false);
+            assert_eq!(gain, None);
+            assert!(!finds_reference(&beats));
+
+            let (beats, gain) = detect(&audio, true);
+            let gain = gain.unwrap();
+            assert!(gain > 0.8 / volume && gain < 1.2 / volume, "{gain}");
+            assert!(finds_reference(&beats), "{volume}: {beats:?}");
+        }
+    }
+
+    #[test]
+    fn statistics_decay() {
+        let mut detector = BeatDetector::new(44100.0, true);
+        assert_eq!(detector.statistics_decay(), DEFAULT_STATISTICS_DECAY);
+        detector.set_gain_normalization(true);
+        detector.set_statistics_decay(Duration::from_secs(10));
+        assert_eq!(detector.statistics_decay(), Duration::from_secs(10));
+    }
+
+    #[test]
+    #[should_panic]
+    fn statistics_decay_out_of_range() {
+        let mut detector = BeatDetector::new(44100.0, true);
+        detector.set_statistics_decay(Duration::from_secs(1));
+    }
+
+    #[test]
+    fn statistics_decay_adapts_to_quiet_passages() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        // The song continues at a quarter of the volume.
+        let quiet = test_utils::render::gain(&samples, 0.25);
+        let audio = [&samples[..], &quiet, &quiet].concat();
+        let quiet_beats = |decay| {
+            let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+            detector.set_statistics_decay(decay);
+            let beats = simulate_dynamic_audio_source(2048, &audio, &mut detector);
+            beats
+                .into_iter()
+                .filter(|&beat| beat > samples.len() as u64)
+                .collect::<Vec<_>>()
+        };
+
+        // A long decay remembers the loud passage for longer, so that the
+        // quieter beats stand out less.
+        let short = quiet_beats(Duration::from_secs(2));
+        let long = quiet_beats(Duration::from_secs(10));
+        assert!(short.len() > long.len(), "{short:?} vs {long:?}");
+        assert!(short[0] < long[0], "{short:?} vs {long:?}");
+    }
+
+    #[test]
+    fn warm_state() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let timestamps = |detector: &mut BeatDetector, samples: &[i16]| {
+            samples
+                .chunks(2048)
+                .flat_map(|samples| detector.update_and_detect_beat(samples.iter().copied()))
+                .map(|beat| beat.timestamp())
+                .collect::<Vec<_>>()
+        };
+        let mut reference = BeatDetector::new(header.sample_rate as f32, true);
+        reference.set_sustain_suppression(true);
+        let expected = timestamps(&mut reference, &samples);
+
+        // The device changes after the fourth beat. The new device runs at
+        // twice the sampling rate.
+        let (before, after) = samples.split_at(90112);
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        detector.set_sustain_suppression(true);
+        detector.set_scan_stride(8);
+        let mut actual = timestamps(&mut detector, before);
+        let state = detector.warm_state();
+        assert_eq!(state.passed_time(), detector.passed_time());
+        assert_eq!(state.last_beat_time(), actual.last().copied());
+
+        let mut detector = BeatDetector::new(2.0 * header.sample_rate as f32, true);
+        detector.restore_warm_state(&state);
+        assert!(detector.sustain_suppression());
+        assert_eq!(detector.scan_stride(), 16);
+        assert_eq!(detector.passed_time(), state.passed_time());
+        assert_eq!(
+            detector.last_beat_age(),
+            Some(state.passed_time() - actual[actual.len() - 1])
+        );
+        let upsampled = after
+            .iter()
+            .flat_map(|&sample| [sample, sample])
+            .collect::<Vec<_>>();
+        actual.extend(timestamps(&mut detector, &upsampled));
+
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            let diff = actual.as_secs_f32() - expected.as_secs_f32();
+            assert!(libm::fabsf(diff) < 0.005, "{actual:?} vs {expected:?}");
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn warm_state_after_audio() {
+        let state = BeatDetector::new(44100.0, true).warm_state();
+        let mut detector = BeatDetector::new(48000.0, true);
+        let _ = detector.update_and_detect_beat([0; 64].into_iter());
+        detector.restore_warm_state(&state);
+    }
+
+    /// [`BeatDetectorConst::snapshot`] is only available with the `std`
+    /// feature.
+    fn snapshot<const N: usize, const D: usize, const P: usize>(
+        detector: &BeatDetectorConst<N, D, P>,
+    ) -> Vec<u8> {
+        let mut buf = vec![0; detector.snapshot_len()];
+        assert_eq!(detector.snapshot_into(&mut buf), Ok(buf.len()));
+        buf
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        fn check<const N: usize, const D: usize, const P: usize>(
+            samples: &[i16],
+            new_detector: impl Fn() -> BeatDetectorConst<N, D, P>,
+        ) {
+            let expected = simulate_dynamic_audio_source(2048, samples, &mut new_detector());
+
+            // Take the snapshot in the middle of the audio, after some beats.
+            let (before, after) = samples.split_at(90112);
+            let mut detector = new_detector();
+            let mut actual = simulate_dynamic_audio_source(2048, before, &mut detector);
+            let data = snapshot(&detector);
+            let mut restored = BeatDetectorConst::<N, D, P>::from_snapshot(&data).unwrap();
+            assert_eq!(snapshot(&restored), data);
+
+            actual.extend(simulate_dynamic_audio_source(2048, after, &mut restored));
+            assert!(actual.len() > 4, "{actual:?}");
+            assert_eq!(actual, expected);
+        }
+
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sampling_frequency = header.sample_rate as f32;
+
+        check(&samples, || {
+            let mut detector = BeatDetectorConst::<4630, 4, 128>::new(sampling_frequency, true);
+            detector.set_frequency_weighting(FrequencyWeighting::KickEmphasis);
+            detector.set_hum_filter(Some(MainsFrequency::Hz50));
+            detector.set_gain_normalization(true);
+            detector.set_scan_stride(2);
+            detector.set_envelope_config(EnvelopeConfig {
+                merge_policy: Some(MergePolicy {
+                    max_gap: Duration::from_millis(20),
+                    max_peak_ratio: 1.2,
+                }),
+                ..EnvelopeConfig::DEFAULT
+            });
+            detector
+        });
+
+        let hiss = (0..22050)
+            .map(|i| (i * 7919 % 401) as i16 - 200)
+            .collect::<Vec<_>>();
+        let mut learner = NoiseProfileLearner::new(sampling_frequency);
+        learner.update(hiss.iter().copied());
+        let lowpass =
+            BeatDetector::lowpass_coefficients(sampling_frequency, FrequencyWeighting::Lowpass);
+        check(&samples, || {
+            let mut detector = BeatDetector::new(sampling_frequency, false);
+            detector.set_custom_filter(Some(CustomFilter::new(&[lowpass.into()]).unwrap()));
+            detector.set_noise_profile(learner.finish());
+            detector.set_sustain_suppression(true);
+            detector.mute_for(Duration::from_secs(1));
+            detector
+        });
+    }
+
+    #[test]
+    fn snapshot_errors() {
+        let mut detector = BeatDetector::new(44100.0, true);
+        let _ = detector.update_and_detect_beat([0, 500, -800, 700].into_iter());
+        let mut buf = snapshot(&detector);
+        assert_eq!(
+            detector.snapshot_into(&mut [0; 16]),
+            Err(SnapshotError::BufferTooSmall {
+                required: buf.len()
+            })
+        );
+        assert_eq!(
+            BeatDetectorConst::<4630, 4, 128>::from_snapshot(&buf).unwrap_err(),
+            SnapshotError::DownsampleFactorMismatch {
+                downsample_factor: 1
+            }
+        );
+        assert_eq!(
+            BeatDetectorConst::<1000>::from_snapshot(&buf).unwrap_err(),
+            SnapshotError::CapacityTooLarge {
+                capacity: detector.history().capacity()
+            }
+        );
+        for len in [0, 20, buf.len() - 1] {
+            assert_eq!(
+                BeatDetector::from_snapshot(&buf[..len]).unwrap_err(),
+                SnapshotError::InvalidFormat
+            );
+        }
+        // An audio history alone isn't a snapshot of a detector.
+        let len = detector.history().snapshot_into(&mut buf).unwrap();
+        assert_eq!(
+            BeatDetector::from_snapshot(&buf[..len]).unwrap_err(),
+            SnapshotError::InvalidFormat
+        );
+    }
+
+    #[test]
+    fn frequency_response() {
+        let response = |detector: &BeatDetector, points: usize| {
+            let mut response = vec![(0.0, 0.0); points];
+            detector.frequency_response_into(&mut response);
+            response
+        };
+        let gain_at = |response: &[(f32, f32)], frequency: f32| {
+            response
+                .iter()
+                .min_by(|a, b| {
+                    libm::fabsf(a.0 - frequency).total_cmp(&libm::fabsf(b.0 - frequency))
+                })
+                .unwrap()
+                .1
+        };
+
+        let mut detector = BeatDetector::new(44100.0, true);
+        let coarse = response(&detector, 64);
+        assert!(approx_eq!(f32, coarse[0].0, 10.0, epsilon = 0.01));
+        assert!(approx_eq!(f32, coarse[63].0, 1000.0, epsilon = 0.1));
+        assert!(coarse.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        #[cfg(feature = "std")]
+        assert_eq!(detector.frequency_response(64), coarse);
+
+        // Passband, -3 dB at the cutoff, and the stopband.
+        let fine = response(&detector, 1001);
+        assert!(libm::fabsf(gain_at(&fine, 20.0)) < 0.1);
+        assert!(libm::fabsf(gain_at(&fine, 95.0) + 3.0) < 0.2);
+        assert!(gain_at(&fine, 150.0) < -7.0);
+        assert!(gain_at(&fine, 1000.0) < -40.0);
+
+        // The kick emphasis passes the kick drums with unity gain, attenuates
+        // the frequencies below, and passes more of 150 Hz.
+        detector.set_frequency_weighting(FrequencyWeighting::KickEmphasis);
+        let fine = response(&detector, 1001);
+        assert!(libm::fabsf(gain_at(&fine, 70.0)) < 0.5);
+        assert!(gain_at(&fine, 70.0) - gain_at(&fine, 20.0) > 4.0);
+        assert!(gain_at(&fine, 150.0) > -7.0);
+
+        let detector = BeatDetector::new(44100.0, false);
+        assert!(response(&detector, 8)
+            .iter()
+            .all(|&(_, gain_db)| gain_db == 0.0));
+
+        // Low sampling rates limit the range to the Nyquist frequency.
+        let detector = BeatDetector::new(2000.0, true);
+        assert!(response(&detector, 8)[7].0 < 1000.0);
+    }
+
+    #[test]
+    fn custom_filter() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sampling_frequency = header.sample_rate as f32;
+        let mut reference = BeatDetector::new(sampling_frequency, true);
+        let expected = simulate_dynamic_audio_source(2048, &samples, &mut reference);
+
+        // The built-in lowpass filter, designed elsewhere.
+        let lowpass =
+            BeatDetector::lowpass_coefficients(sampling_frequency, FrequencyWeighting::Lowpass);
+        let filter = CustomFilter::new(&[lowpass.into()]).unwrap();
+        let mut detector = BeatDetector::new(sampling_frequency, false);
+        detector.set_custom_filter(Some(filter));
+        assert_eq!(detector.custom_filter(), Some(&filter));
+        assert_eq!(
+            simulate_dynamic_audio_source(2048, &samples, &mut detector),
+            expected
+        );
+        // Close to the approximation of the group delay of the built-in
+        // lowpass filter at low frequencies, which is 104 samples.
+        let delay = detector.lowpass_group_delay_samples();
+        assert!((100..=140).contains(&delay), "{delay}");
+
+        let mut custom_response = [(0.0, 0.0); 16];
+        detector.frequency_response_into(&mut custom_response);
+        let mut expected_response = [(0.0, 0.0); 16];
+        reference.frequency_response_into(&mut expected_response);
+        for (custom, expected) in custom_response.iter().zip(expected_response) {
+            assert!(libm::fabsf(custom.1 - expected.1) < 0.01);
+        }
+
+        detector.set_custom_filter(None);
+        assert_eq!(detector.custom_filter(), None);
+        assert_eq!(detector.lowpass_group_delay_samples(), 0);
+    }
+
+    #[test]
+    fn custom_filter_group_delay_of_cascade() {
+        let sampling_frequency = 44100.0;
+        let delay = |stages: &[BiquadCoefficients]| {
+            let filter = CustomFilter::new(stages).unwrap();
+            BeatDetector::custom_filter_group_delay_samples(&filter, sampling_frequency)
+        };
+        let lowpass =
+            BeatDetector::lowpass_coefficients(sampling_frequency, FrequencyWeighting::Lowpass)
+                .into();
+        // A stage whose phase is exactly ±π at 60 Hz, where the delay is
+        // measured. Its numerator is `0.5 - 2 cos(ω) z^-1 + z^-2`, which is
+        // real and negative at that frequency.
+        let omega = 2.0 * core::f32::consts::PI * CUSTOM_FILTER_GROUP_DELAY_HZ / sampling_frequency;
+        let inverting = BiquadCoefficients::new(0.5, -2.0 * libm::cosf(omega), 1.0, 0.0, 0.0);
+
+        // The delays of the stages add up, also if the phase of the cascade
+        // wraps around.
+        let single = delay(&[lowpass]);
+        assert_eq!(delay(&[lowpass; MAX_CUSTOM_FILTER_STAGES]), single * 4);
+        let expected = single * 2 + delay(&[inverting]);
+        assert!(expected < 1000, "{expected}");
+        assert!(delay(&[lowpass, inverting, lowpass]).abs_diff(expected) <= 1);
+    }
+
+    #[test]
+    fn decision_trace() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        detector.set_envelope_config(EnvelopeConfig::STRICT);
+        let mut trace = DecisionTrace::new();
+
+        let mut decisions = Vec::new();
+        for chunk in samples.chunks(2048) {
+            let beat = detector.update_and_detect_beat_traced(chunk.iter().copied(), &mut trace);
+            let entry = *trace.latest().unwrap();
+            assert_eq!(entry.time, detector.passed_time());
+            assert_eq!(beat.is_some(), entry.decision == Decision::Beat);
+            decisions.push(entry.decision);
+        }
+        assert_eq!(
+            trace.len(),
+            decisions.len().min(crate::DECISION_TRACE_CAPACITY)
+        );
+        assert_eq!(decisions[0], Decision::WarmingUp);
+        // The strict config misses the quieter beats, as they don't stand out
+        // of the median enough.
+        let count = |decision| decisions.iter().filter(|&&d| d == decision).count();
+        assert_eq!(count(Decision::Beat), 4);
+        assert!(count(Decision::RatioBelowThreshold) > 30);
+        assert!(count(Decision::EnvelopeRejected) > 0);
+    }
+
+    #[test]
+    fn mute() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        assert_eq!(detector.muted_until(), None);
+        detector.mute_for(Duration::from_millis(1200));
+        // A shorter mute doesn't shorten the current one.
+        detector.mute_for(Duration::from_millis(500));
+        assert_eq!(detector.muted_until(), Some(Duration::from_millis(1200)));
+
+        // The first two beats are in the muted audio.
+        let mut trace = DecisionTrace::new();
+        let beats = samples
+            .chunks(2048)
+            .flat_map(|chunk| {
+                detector
+                    .update_and_detect_beat_traced(chunk.iter().copied(), &mut trace)
+                    .map(|info| info.max.total_index)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(beats, &[65927, 84217, 102107, 120247, 138557]);
+        assert_eq!(detector.muted_until(), None);
+        let muted = trace
+            .iter()
+            .filter(|entry| entry.decision == Decision::Muted)
+            .count();
+        assert_eq!(muted, 2);
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        detector.mute_for(Duration::from_secs(10));
+        detector.unmute();
+        assert_eq!(detector.muted_until(), None);
+        assert_eq!(
+            simulate_dynamic_audio_source(2048, &samples, &mut detector),
+            &[31337, 47167, 65927, 84217, 102107, 120247, 138557]
+        );
+    }
+
+    #[test]
+    fn envelope_input() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        let mut lowpassed = Vec::new();
+        let mut expected = Vec::new();
+        for chunk in samples.chunks(2048) {
+            if let Some(beat) = detector.update_and_detect_beat(chunk.iter().copied()) {
+                expected.push(beat.max.timestamp);
+            }
+            lowpassed.extend(
+                detector
+                    .processed_samples_since_last_call()
+                    .map(|info| info.value_abs),
+            );
+        }
+
+        // The envelope that a sensor sends at 200 Hz: the peak of the last
+        // 10 ms every 5 ms.
+        let hop = header.sample_rate as usize / 200;
+        let envelope = (0..lowpassed.len() / hop)
+            .map(|i| {
+                let begin = (i * hop).saturating_sub(hop);
+                lowpassed[begin..(i + 1) * hop]
+                    .iter()
+                    .copied()
+                    .max()
+                    .unwrap()
+            })
+            // The last beat is only complete with a few more samples.
+            .chain([0; 10])
+            .collect::<Vec<_>>();
+        let mut detector = BeatDetector::new_envelope_input(200.0);
+        assert!(detector.is_envelope_input());
+        let actual = envelope
+            .chunks(8)
+            .filter_map(|chunk| detector.update_and_detect_beat(chunk.iter().copied()))
+            .map(|beat| beat.max.timestamp)
+            .collect::<Vec<_>>();
+
+        assert_eq!(actual.len(), expected.len(), "{actual:?} vs {expected:?}");
+        // The peak of the last 10 ms lags behind by up to 10 ms.
+        for (&actual, &expected) in actual.iter().zip(&expected) {
+            assert!(actual.max(expected) - actual.min(expected) <= Duration::from_millis(10));
+        }
+        assert_eq!(
+            detector.passed_time(),
+            Duration::from_millis(envelope.len() as u64 * 5)
+        );
+    }
+
+    #[test]
+    fn preprocessed_input() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        let mut preprocessed = Vec::new();
+        let mut expected = Vec::new();
+        for chunk in samples.chunks(1024) {
+            expected.extend(detector.update_and_detect_beat(chunk.iter().copied()));
+            preprocessed.extend(
+                detector
+                    .processed_samples_since_last_call()
+                    .map(|info| info.value),
+            );
+        }
+        assert_eq!(preprocessed.len(), samples.len());
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        detector.set_preprocessed_input(true);
+        assert!(detector.is_preprocessed_input());
+        let actual = preprocessed
+            .chunks(1024)
+            .filter_map(|chunk| detector.update_and_detect_beat(chunk.iter().copied()))
+            .collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+        // `PartialEq` only compares the total indices. The timestamps must
+        // also compensate the lowpass filter that already ran.
+        for (actual, expected) in actual.iter().zip(&expected) {
+            assert_eq!(actual.timestamp(), expected.timestamp());
+            assert_eq!(actual.max.source, expected.max.source);
+        }
+    }
+
+    #[test]
+    fn envelope_config_presets() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let detect = |samples: &[i16], config| {
+            let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+            detector.set_envelope_config(config);
+            simulate_dynamic_audio_source(2048, samples, &mut detector)
+        };
+
+        let default = detect(&samples, EnvelopeConfig::DEFAULT);
+        assert_eq!(
+            default,
+            &[31337, 47167, 65927, 84217, 102107, 120247, 138557]
+        );
+        let strict = detect(&samples, EnvelopeConfig::STRICT);
+        assert_eq!(strict, &[65927, 102107, 120247, 138557]);
+
+        // The sensitive preset reports some false positives, which aren't
+        // asserted. Only the real beats are. The last beat ends with the
+        // fixture and is hidden behind a false positive in the final update.
+        let real_beats = &default[..default.len() - 1];
+        let found = |beats: &[u64]| {
+            real_beats
+                .iter()
+                .filter(|&&beat| beats.iter().any(|&found| found.abs_diff(beat) < 441))
+                .count()
+        };
+        let sensitive = detect(&samples, EnvelopeConfig::SENSITIVE);
+        assert_eq!(found(&sensitive), real_beats.len(), "{sensitive:?}");
+
+        // Quiet input is what the preset is for. At a quarter of the volume,
+        // it still finds all of these beats, unlike the default.
+        let quiet = test_utils::render::gain(&samples, 0.25);
+        assert_eq!(found(&detect(&quiet, EnvelopeConfig::DEFAULT)), 4);
+        assert_eq!(
+            found(&detect(&quiet, EnvelopeConfig::SENSITIVE)),
+            real_beats.len()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn envelope_config_out_of_range() {
+        let mut detector = BeatDetector::new(44100.0, true);
+        detector.set_envelope_config(EnvelopeConfig {
+            min_duration: Duration::from_millis(500),
+            ..EnvelopeConfig::DEFAULT
+        });
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__lowpass__holiday_long__downsampled() {
+        let (samples, header) = test_utils::samples::holiday_long();
+
+        let mut detector = BeatDetectorConst::<4630, 4, 128>::new(header.sample_rate as f32, true);
+        let beats = simulate_dynamic_audio_source(2048, &samples, &mut detector);
+        assert_eq!(beats, &[7831, 11791, 16481, 21051, 25531, 30061, 34641]);
+
+        // Roughly the same beats as with the default configuration.
+        let expected = [31337, 47167, 65927, 84217, 102107, 120247, 138557];
+        for (beat, expected) in beats.iter().zip(expected) {
+            assert!((beat * 4).abs_diff(expected) < 20);
+        }
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__lowpass__holiday_long__48khz() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let samples = test_utils::render::with_sampling_rate(&samples, header.sample_rate, 48000);
+
+        let mut detector = BeatDetector::new(48000.0, true);
+        let beats = simulate_dynamic_audio_source(2048, &samples, &mut detector);
+        assert_eq!(beats, &[34107, 51337, 71757, 91667, 111137, 130877, 150817]);
+
+        // The same beats as at 44.1 kHz.
+        let expected = [31337, 47167, 65927, 84217, 102107, 120247, 138557];
+        for (beat, expected) in beats.iter().zip(expected) {
+            let expected = (expected as f32 * 48000.0 / header.sample_rate as f32) as u64;
+            assert!(beat.abs_diff(expected) < 20);
+        }
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__lowpass__sample1_long__48khz() {
+        let (samples, header) = test_utils::samples::sample1_long();
+        let samples = test_utils::render::with_sampling_rate(&samples, header.sample_rate, 48000);
+
+        let mut detector = BeatDetector::new(48000.0, true);
+        assert_eq!(
+            simulate_dynamic_audio_source(2048, &samples, &mut detector),
+            &[14077, 102091, 110431, 206365, 294733, 303093]
+        );
+    }
+
+    /// Below 44.1 kHz, the audio history covers the same time as at 44.1 kHz,
+    /// so the same beats as at 44.1 kHz are found.
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__lowpass__holiday_long__22khz() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let samples = test_utils::render::with_sampling_rate(&samples, header.sample_rate, 22050);
+
+        let mut detector = BeatDetector::new(22050.0, true);
+        assert_eq!(
+            simulate_dynamic_audio_source(1024, &samples, &mut detector),
+            &[15663, 23583, 32963, 42113, 51053, 60123, 69283]
+        );
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__lowpass__holiday_long__16khz() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let samples = test_utils::render::with_sampling_rate(&samples, header.sample_rate, 16000);
+
+        let mut detector = BeatDetector::new(16000.0, true);
+        assert_eq!(
+            simulate_dynamic_audio_source(1024, &samples, &mut detector),
+            &[11369, 17109, 23919, 30559, 37049, 43629, 50269]
+        );
+    }
+
+    /// At 96 kHz, the default audio history covers only half of the time.
+    /// Hence, we downsample by two so that the detector sees the same
+    /// window as at 44.1 or 48 kHz.
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__lowpass__holiday_long__96khz() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let samples = test_utils::render::with_sampling_rate(&samples, header.sample_rate, 96000);
+
+        let mut detector = BeatDetectorConst::<16384, 2, MAX_TRACKED_PEAKS>::new(96000.0, true);
+        let beats = simulate_dynamic_audio_source(2048, &samples, &mut detector);
+        // Total indices of the downsampled audio, i.e., at 48 kHz. Compared
+        // to 44.1 kHz, the first beat is reported at the onset of the kick
+        // (like without lowpass filter at 44.1 kHz, 29077).
+        assert_eq!(
+            beats,
+            &[
+                32023, 51333, //
+                // False positive between the second and the third beat. It
+                // is only reported at this sampling rate.
+                63153, //
+                71753, 91673, 111143, 130883, 150813
+            ]
+        );
+
+        // All other beats match the ones at 44.1 kHz.
+        let scale = |index: u64| (index as f32 * 96000.0 / header.sample_rate as f32) as u64;
+        let expected = [29077, 47167, 65927, 84217, 102107, 120247, 138557];
+        let real_beats = beats.iter().filter(|&&beat| beat != 63153);
+        for (beat, expected) in real_beats.zip(expected.map(scale)) {
+            assert!((beat * 2).abs_diff(expected) < 1000, "{beat} vs {expected}");
+        }
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__dynamic__lowpass__sample1_long__96khz() {
+        let (samples, header) = test_utils::samples::sample1_long();
+        let samples = test_utils::render::with_sampling_rate(&samples, header.sample_rate, 96000);
+
+        let mut detector = BeatDetector::new(96000.0, true);
+        assert_eq!(
+            simulate_dynamic_audio_source(2048, &samples, &mut detector),
+            &[28157, 204179, 220859, 412729, 589463, 606193]
+        );
+    }
+
+    /// Replays the audio with different (jittering) chunk sizes and checks
+    /// that all beats are found and reported within a bounded latency. This
+    /// catches regressions that only show up in live mode.
+    #[test]
+    #[allow(non_snake_case)]
+    fn detect__realtime__lowpass__holiday_long() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sampling_rate = header.sample_rate as f32;
+        let max_delivery_jitter = Duration::from_millis(5);
+        // Value taken from observations with the holiday sample.
+        let max_detection_latency = Duration::from_millis(80);
+
+        for chunk_size in [256, 512, 1024, 2048] {
+            let mut detector = BeatDetector::new(sampling_rate, true);
+            let beats = test_utils::realtime::replay(
+                &samples,
+                sampling_rate,
+                chunk_size,
+                20,
+                max_delivery_jitter,
+                &mut detector,
+            );
+
+            assert_eq!(beats.len(), 7, "chunk_size={chunk_size}");
+
+            let max_chunk_duration =
+                Duration::from_secs_f32(chunk_size as f32 * 1.2 / sampling_rate);
+            let max_latency = max_detection_latency + max_chunk_duration + max_delivery_jitter;
+            for beat in beats {
+                assert!(
+                    beat.latency() <= max_latency,
+                    "chunk_size={chunk_size}, latency={:?}",
+                    beat.latency()
+                );
+            }
+        }
+    }
+}
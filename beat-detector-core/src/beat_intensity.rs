@@ -0,0 +1,222 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`BeatIntensity`].
+
+use core::time::Duration;
+
+/// Steepness of [`IntensityCurve::Exponential`].
+const EXPONENTIAL_STEEPNESS: f32 = 5.0;
+
+/// Shape of the rise and the fall of a [`BeatIntensity`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum IntensityCurve {
+    /// Constant rate of change.
+    #[default]
+    Linear,
+    /// Fast change at first that slows down exponentially, similar to the
+    /// afterglow of a light bulb.
+    Exponential,
+    /// Fast change at first that slows down quadratically towards the end.
+    EaseOut,
+}
+
+impl IntensityCurve {
+    /// Returns the remaining intensity of a fall for the given progress in
+    /// range `0.0..=1.0`. Starts at `1.0` and ends at `0.0`.
+    fn fall(self, progress: f32) -> f32 {
+        let progress = progress.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => 1.0 - progress,
+            Self::Exponential => {
+                // Normalized, so that the curve actually reaches zero.
+                let end = libm::expf(-EXPONENTIAL_STEEPNESS);
+                (libm::expf(-EXPONENTIAL_STEEPNESS * progress) - end) / (1.0 - end)
+            }
+            Self::EaseOut => (1.0 - progress) * (1.0 - progress),
+        }
+    }
+
+    /// Returns the intensity of a rise for the given progress in range
+    /// `0.0..=1.0`. Starts at `0.0` and ends at `1.0`.
+    fn rise(self, progress: f32) -> f32 {
+        1.0 - self.fall(progress)
+    }
+}
+
+/// Converts discrete beats into a continuous intensity in range `0.0..=1.0`,
+/// which is useful to drive lights or animations.
+///
+/// On each beat, the intensity rises to `1.0` within the attack duration,
+/// holds this value for the hold duration, and then decays to `0.0` within
+/// the decay duration. The intensity can be queried at arbitrary times.
+///
+/// All points in time are relative to an arbitrary clock of the caller, such
+/// as the [timestamp] of a beat or the elapsed time since the program start.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BeatIntensity, IntensityCurve};
+/// use core::time::Duration;
+///
+/// let mut intensity = BeatIntensity::new(Duration::from_millis(300))
+///     .with_curve(IntensityCurve::Exponential);
+///
+/// // TODO call this on every beat.
+/// intensity.trigger(Duration::from_millis(1000));
+///
+/// // TODO call this on every frame.
+/// let brightness = intensity.intensity(Duration::from_millis(1100));
+/// assert!(brightness > 0.0 && brightness < 1.0);
+/// ```
+///
+/// [timestamp]: crate::SampleInfo::timestamp
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeatIntensity {
+    attack: Duration,
+    hold: Duration,
+    decay: Duration,
+    curve: IntensityCurve,
+    /// Time of the latest beat and the intensity at that time.
+    trigger: Option<(Duration, f32)>,
+}
+
+impl BeatIntensity {
+    /// Creates a new intensity that instantly jumps to `1.0` on a beat and
+    /// then decays linearly within the given duration.
+    pub const fn new(decay: Duration) -> Self {
+        Self {
+            attack: Duration::ZERO,
+            hold: Duration::ZERO,
+            decay,
+            curve: IntensityCurve::Linear,
+            trigger: None,
+        }
+    }
+
+    /// Sets the duration to rise to `1.0` after a beat.
+    pub const fn with_attack(mut self, attack: Duration) -> Self {
+        self.attack = attack;
+        self
+    }
+
+    /// Sets the duration to stay at `1.0` before the decay begins.
+    pub const fn with_hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    /// Sets the shape of the attack and of the decay.
+    pub const fn with_curve(mut self, curve: IntensityCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Registers a beat at the given time. If the previous beat still
+    /// affects the intensity, the attack begins at the current intensity
+    /// instead of `0.0`.
+    pub fn trigger(&mut self, now: Duration) {
+        let intensity = self.intensity(now);
+        self.trigger = Some((now, intensity));
+    }
+
+    /// Returns the intensity in range `0.0..=1.0` at the given time. Times
+    /// before the latest beat are treated as the time of the latest beat.
+    pub fn intensity(&self, now: Duration) -> f32 {
+        let Some((trigger_time, trigger_intensity)) = self.trigger else {
+            return 0.0;
+        };
+        let mut elapsed = now.saturating_sub(trigger_time);
+
+        if elapsed < self.attack {
+            let progress = elapsed.as_secs_f32() / self.attack.as_secs_f32();
+            return trigger_intensity + (1.0 - trigger_intensity) * self.curve.rise(progress);
+        }
+        elapsed -= self.attack;
+
+        if elapsed < self.hold {
+            return 1.0;
+        }
+        elapsed -= self.hold;
+
+        if elapsed < self.decay {
+            let progress = elapsed.as_secs_f32() / self.decay.as_secs_f32();
+            self.curve.fall(progress)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn hold_and_decay() {
+        let mut intensity = BeatIntensity::new(ms(100)).with_hold(ms(50));
+        assert_eq!(intensity.intensity(ms(0)), 0.0);
+
+        intensity.trigger(ms(1000));
+        assert_eq!(intensity.intensity(ms(1000)), 1.0);
+        assert_eq!(intensity.intensity(ms(1049)), 1.0);
+        assert!((intensity.intensity(ms(1100)) - 0.5).abs() < 0.001);
+        assert_eq!(intensity.intensity(ms(1150)), 0.0);
+        assert_eq!(intensity.intensity(ms(5000)), 0.0);
+    }
+
+    #[test]
+    fn attack_begins_at_current_intensity() {
+        let mut intensity = BeatIntensity::new(ms(100)).with_attack(ms(10));
+        intensity.trigger(ms(0));
+        assert_eq!(intensity.intensity(ms(0)), 0.0);
+        assert!((intensity.intensity(ms(5)) - 0.5).abs() < 0.001);
+        assert_eq!(intensity.intensity(ms(10)), 1.0);
+
+        // Retrigger during the decay.
+        intensity.trigger(ms(60));
+        assert!((intensity.intensity(ms(60)) - 0.5).abs() < 0.001);
+        assert!(intensity.intensity(ms(65)) > 0.5);
+    }
+
+    #[test]
+    fn curves() {
+        for curve in [
+            IntensityCurve::Linear,
+            IntensityCurve::Exponential,
+            IntensityCurve::EaseOut,
+        ] {
+            assert_eq!(curve.fall(0.0), 1.0);
+            assert!(curve.fall(1.0).abs() < f32::EPSILON);
+            assert!(curve.fall(0.3) > curve.fall(0.6));
+        }
+
+        // Both fall faster than linear in the beginning.
+        assert!(IntensityCurve::Exponential.fall(0.2) < IntensityCurve::Linear.fall(0.2));
+        assert!(IntensityCurve::EaseOut.fall(0.2) < IntensityCurve::Linear.fall(0.2));
+    }
+}
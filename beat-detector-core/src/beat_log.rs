@@ -0,0 +1,268 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`BeatLog`].
+
+use crate::BeatInfo;
+use core::ops::{Bound, RangeBounds};
+use core::time::Duration;
+
+/// Default capacity of a [`BeatLog`]: roughly half a minute of music at
+/// 120 BPM. This keeps the log at ~16 KiB, so that it also fits on
+/// microcontrollers. Choose a larger capacity to keep more beats.
+pub const DEFAULT_BEAT_LOG_CAPACITY: usize = 64;
+
+/// Decides which beats a [`BeatLog`] drops.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PruningPolicy {
+    /// Keeps the latest beats up to the capacity of the log.
+    #[default]
+    Capacity,
+    /// Additionally drops beats that are older than the given duration,
+    /// relative to the time passed to [`BeatLog::prune`] or to the latest
+    /// beat.
+    MaxAge(Duration),
+}
+
+/// Bounded log of all detected beats, such as for a GUI that is opened long
+/// after the detection started and wants to show what happened so far.
+///
+/// The log never holds more than `N` beats. Each beat takes ~256 bytes on
+/// 64-bit targets. The oldest beat is dropped if the log is full. With [`PruningPolicy::MaxAge`], beats are also dropped
+/// once they are older than a time window.
+///
+/// # Example
+/// ```rust
+/// use beat_detector_core::{BeatDetector, BeatLog, PruningPolicy};
+/// use core::time::Duration;
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut log = BeatLog::<256>::new(PruningPolicy::MaxAge(Duration::from_secs(60)));
+///
+/// // TODO regularly call this with the latest audio data.
+/// if let Some(beat) = detector.update_and_detect_beat(mono_samples.iter().copied()) {
+///     log.push(beat);
+/// }
+/// log.prune(detector.passed_time());
+///
+/// // Beats of the last ten seconds.
+/// let now = detector.passed_time();
+/// let recent = log.range(now.saturating_sub(Duration::from_secs(10))..);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BeatLog<const N: usize = DEFAULT_BEAT_LOG_CAPACITY> {
+    beats: [BeatInfo; N],
+    len: usize,
+    next: usize,
+    policy: PruningPolicy,
+}
+
+impl<const N: usize> BeatLog<N> {
+    /// Creates an empty log.
+    pub fn new(policy: PruningPolicy) -> Self {
+        assert!(N > 0, "The capacity must not be zero");
+        Self {
+            beats: [BeatInfo::default(); N],
+            len: 0,
+            next: 0,
+            policy,
+        }
+    }
+
+    /// Returns the pruning policy.
+    pub const fn policy(&self) -> PruningPolicy {
+        self.policy
+    }
+
+    /// Sets the pruning policy. It applies from the next call to
+    /// [`Self::push`] or [`Self::prune`] on.
+    pub fn set_policy(&mut self, policy: PruningPolicy) {
+        self.policy = policy;
+    }
+
+    /// Adds the latest beat. The oldest beat is dropped if the log is full.
+    /// Beats are expected in chronological order.
+    pub fn push(&mut self, beat: BeatInfo) {
+        debug_assert!(self
+            .latest()
+            .map_or(true, |latest| latest.timestamp() <= beat.timestamp()));
+        self.beats[self.next] = beat;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        self.prune(beat.timestamp());
+    }
+
+    /// Drops all beats that are too old at the given time according to
+    /// [`PruningPolicy::MaxAge`]. `now` usually is
+    /// [`BeatDetector::passed_time`], so that the log also empties during
+    /// silence.
+    ///
+    /// [`BeatDetector::passed_time`]: crate::BeatDetector::passed_time
+    pub fn prune(&mut self, now: Duration) {
+        let PruningPolicy::MaxAge(max_age) = self.policy else {
+            return;
+        };
+        while self
+            .oldest()
+            .is_some_and(|beat| now.saturating_sub(beat.timestamp()) > max_age)
+        {
+            self.len -= 1;
+        }
+    }
+
+    /// Drops all beats.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Returns the maximum amount of beats.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the amount of beats.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there are no beats.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the beat `age` beats ago. `0` is the latest beat.
+    pub fn get(&self, age: usize) -> Option<&BeatInfo> {
+        (age < self.len).then(|| &self.beats[(self.next + N - 1 - age) % N])
+    }
+
+    /// Returns the latest beat.
+    pub fn latest(&self) -> Option<&BeatInfo> {
+        self.get(0)
+    }
+
+    /// Returns the oldest beat.
+    pub fn oldest(&self) -> Option<&BeatInfo> {
+        self.len.checked_sub(1).and_then(|age| self.get(age))
+    }
+
+    /// Returns an iterator over all beats, from the oldest to the latest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &BeatInfo> + ExactSizeIterator + '_ {
+        (0..self.len)
+            .rev()
+            .map(|age| &self.beats[(self.next + N - 1 - age) % N])
+    }
+
+    /// Returns an iterator over the beats whose [timestamp] is in the given
+    /// range, from the oldest to the latest.
+    ///
+    /// [timestamp]: BeatInfo::timestamp
+    pub fn range(&self, range: impl RangeBounds<Duration>) -> impl Iterator<Item = &BeatInfo> + '_ {
+        let start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+        let is_before = move |time: Duration| match start {
+            Bound::Included(start) => time < start,
+            Bound::Excluded(start) => time <= start,
+            Bound::Unbounded => false,
+        };
+        let is_after = move |time: Duration| match end {
+            Bound::Included(end) => time > end,
+            Bound::Excluded(end) => time >= end,
+            Bound::Unbounded => false,
+        };
+        self.iter()
+            .skip_while(move |beat| is_before(beat.timestamp()))
+            .take_while(move |beat| !is_after(beat.timestamp()))
+    }
+}
+
+impl<const N: usize> Default for BeatLog<N> {
+    fn default() -> Self {
+        Self::new(PruningPolicy::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn beat(millis: u64) -> BeatInfo {
+        let mut beat = BeatInfo::default();
+        beat.max.source.time = Duration::from_millis(millis);
+        beat
+    }
+
+    fn timestamps<'a>(beats: impl Iterator<Item = &'a BeatInfo>) -> Vec<u64> {
+        beats
+            .map(|beat| beat.timestamp().as_millis() as u64)
+            .collect()
+    }
+
+    #[test]
+    fn capacity() {
+        let mut log = BeatLog::<3>::default();
+        assert!(log.is_empty());
+        for millis in [100, 200, 300, 400] {
+            log.push(beat(millis));
+        }
+        assert_eq!(log.len(), 3);
+        assert_eq!(timestamps(log.iter()), &[200, 300, 400]);
+        assert_eq!(timestamps(log.iter().rev()), &[400, 300, 200]);
+        assert_eq!(log.latest(), Some(&beat(400)));
+        assert_eq!(log.oldest(), Some(&beat(200)));
+        assert_eq!(log.get(3), None);
+
+        log.clear();
+        assert_eq!(log.latest(), None);
+        assert_eq!(log.oldest(), None);
+    }
+
+    #[test]
+    fn max_age() {
+        let mut log = BeatLog::<8>::new(PruningPolicy::MaxAge(Duration::from_millis(250)));
+        for millis in [100, 200, 300, 400] {
+            log.push(beat(millis));
+        }
+        assert_eq!(timestamps(log.iter()), &[200, 300, 400]);
+        log.prune(Duration::from_millis(600));
+        assert_eq!(timestamps(log.iter()), &[400]);
+        log.prune(Duration::from_millis(700));
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn range() {
+        let mut log = BeatLog::<4>::default();
+        for millis in [100, 200, 300, 400, 500] {
+            log.push(beat(millis));
+        }
+        let millis = Duration::from_millis;
+        assert_eq!(timestamps(log.range(..)), &[200, 300, 400, 500]);
+        assert_eq!(timestamps(log.range(millis(300)..)), &[300, 400, 500]);
+        assert_eq!(timestamps(log.range(..millis(400))), &[200, 300]);
+        assert_eq!(timestamps(log.range(..=millis(400))), &[200, 300, 400]);
+        assert_eq!(timestamps(log.range(millis(250)..millis(450))), &[300, 400]);
+        assert_eq!(timestamps(log.range(millis(600)..)), &[] as &[u64]);
+    }
+}
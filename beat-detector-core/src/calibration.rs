@@ -0,0 +1,370 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`Calibration`].
+
+use crate::{NoiseProfile, TempoEstimator, NOISE_PROFILE_BANDS};
+use core::fmt::{Display, Formatter};
+
+/// Identifies a serialized [`Calibration`].
+const MAGIC: [u8; 4] = *b"BDCL";
+/// Version of the format. Bump on incompatible changes.
+const VERSION: u8 = 1;
+
+/// Flags of the optional parts.
+const HAS_NOISE_PROFILE: u8 = 1 << 0;
+const HAS_GAIN: u8 = 1 << 1;
+const HAS_BPM_RANGE: u8 = 1 << 2;
+
+/// Size of a serialized [`Calibration`] in bytes: magic, version, flags,
+/// sensitivity, the sampling frequency and the levels of the noise profile,
+/// gain, level, and the BPM range.
+pub const CALIBRATION_LEN: usize = 4 + 1 + 1 + 4 + 4 + 4 * NOISE_PROFILE_BANDS + 4 + 4 + 4 + 4;
+
+/// Error when loading a [`Calibration`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CalibrationError {
+    /// The data is not a calibration, it is truncated, or a value is out of
+    /// range.
+    InvalidFormat,
+    /// The calibration was created by an incompatible version of this crate.
+    UnsupportedVersion(u8),
+}
+
+impl Display for CalibrationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidFormat => f.write_str("not a valid calibration"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported calibration version {version}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CalibrationError {}
+
+/// What a detector and a tempo estimator learned about an installation,
+/// persisted between runs.
+///
+/// Installations that restart every night otherwise begin from scratch:
+/// the gain normalization needs a few seconds to settle and the tempo is
+/// unknown until a few beats were detected. The calibration contains the
+/// noise floor, i.e., the [noise profile], the sensitivity, i.e.,
+/// [`EnvelopeConfig::max_peak_to_median_min_ratio`], the settled level of
+/// the [gain normalization], and the typical BPM range. It serializes into a
+/// small blob of [`CALIBRATION_LEN`] bytes for a config file or an EEPROM.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BeatDetector, Calibration, TempoConfig, TempoEstimator};
+///
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut tempo = TempoEstimator::new(TempoConfig::default());
+/// // TODO run the show.
+///
+/// // At shutdown.
+/// let blob = detector.calibration().with_tempo(&tempo).to_bytes();
+///
+/// // At the next startup.
+/// let calibration = Calibration::from_bytes(&blob).unwrap();
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut tempo = TempoEstimator::new(TempoConfig::default());
+/// detector.restore_calibration(&calibration);
+/// calibration.restore_tempo(&mut tempo);
+/// ```
+///
+/// [noise profile]: crate::BeatDetector::set_noise_profile
+/// [`EnvelopeConfig::max_peak_to_median_min_ratio`]: crate::EnvelopeConfig::max_peak_to_median_min_ratio
+/// [gain normalization]: crate::BeatDetector::set_gain_normalization
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Calibration {
+    noise_profile: Option<NoiseProfile>,
+    sensitivity: f32,
+    /// Compensating gain and settled level of the gain normalization.
+    gain: Option<(f32, f32)>,
+    bpm_range: Option<(f32, f32)>,
+}
+
+impl Calibration {
+    pub(crate) const fn new(
+        noise_profile: Option<NoiseProfile>,
+        sensitivity: f32,
+        gain: Option<(f32, f32)>,
+    ) -> Self {
+        Self {
+            noise_profile,
+            sensitivity,
+            gain,
+            bpm_range: None,
+        }
+    }
+
+    /// Adds the typical BPM range of the latest beats of the estimator.
+    pub fn with_tempo(mut self, tempo: &TempoEstimator) -> Self {
+        self.bpm_range = tempo.bpm_range().or(self.bpm_range);
+        self
+    }
+
+    /// Returns the noise profile of the detector, if it had one.
+    pub const fn noise_profile(&self) -> Option<NoiseProfile> {
+        self.noise_profile
+    }
+
+    /// Returns the sensitivity, i.e.,
+    /// [`EnvelopeConfig::max_peak_to_median_min_ratio`].
+    ///
+    /// [`EnvelopeConfig::max_peak_to_median_min_ratio`]: crate::EnvelopeConfig::max_peak_to_median_min_ratio
+    pub const fn sensitivity(&self) -> f32 {
+        self.sensitivity
+    }
+
+    /// Returns the compensating gain of the gain normalization, if it was
+    /// enabled and had settled.
+    pub fn gain(&self) -> Option<f32> {
+        self.gain.map(|(gain, _)| gain)
+    }
+
+    pub(crate) const fn gain_and_level(&self) -> Option<(f32, f32)> {
+        self.gain
+    }
+
+    /// Returns the typical range of the tempo in BPM, from the slowest to
+    /// the fastest, see [`Self::with_tempo`].
+    pub const fn bpm_range(&self) -> Option<(f32, f32)> {
+        self.bpm_range
+    }
+
+    /// Seeds the tempo of the estimator with the center of the typical BPM
+    /// range, so that the tempo is known before the first beats. Like a
+    /// tapped tempo, it is blended towards the detected tempo with each
+    /// beat. The configuration of the estimator isn't changed.
+    pub fn restore_tempo(&self, tempo: &mut TempoEstimator) {
+        if let Some((slowest, fastest)) = self.bpm_range {
+            tempo.seed(libm::sqrtf(slowest * fastest));
+        }
+    }
+
+    /// Serializes the calibration into a compact binary format.
+    pub fn to_bytes(&self) -> [u8; CALIBRATION_LEN] {
+        let mut flags = 0;
+        let mut values = [0.0; 1 + 1 + NOISE_PROFILE_BANDS + 2 + 2];
+        values[0] = self.sensitivity;
+        if let Some(profile) = &self.noise_profile {
+            flags |= HAS_NOISE_PROFILE;
+            values[1] = profile.sampling_frequency();
+            values[2..2 + NOISE_PROFILE_BANDS].copy_from_slice(&profile.levels());
+        }
+        if let Some((gain, level)) = self.gain {
+            flags |= HAS_GAIN;
+            values[2 + NOISE_PROFILE_BANDS] = gain;
+            values[3 + NOISE_PROFILE_BANDS] = level;
+        }
+        if let Some((slowest, fastest)) = self.bpm_range {
+            flags |= HAS_BPM_RANGE;
+            values[4 + NOISE_PROFILE_BANDS] = slowest;
+            values[5 + NOISE_PROFILE_BANDS] = fastest;
+        }
+
+        let mut bytes = [0; CALIBRATION_LEN];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4] = VERSION;
+        bytes[5] = flags;
+        for (chunk, value) in bytes[6..].chunks_exact_mut(4).zip(values) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Loads a calibration created with [`Self::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CalibrationError> {
+        if bytes.len() != CALIBRATION_LEN || bytes[..4] != MAGIC {
+            return Err(CalibrationError::InvalidFormat);
+        }
+        if bytes[4] != VERSION {
+            return Err(CalibrationError::UnsupportedVersion(bytes[4]));
+        }
+        let flags = bytes[5];
+        let value = |index: usize| {
+            let begin = 6 + index * 4;
+            let value = f32::from_le_bytes(bytes[begin..begin + 4].try_into().unwrap());
+            if value.is_finite() {
+                Ok(value)
+            } else {
+                Err(CalibrationError::InvalidFormat)
+            }
+        };
+        let check = |valid: bool| valid.then_some(()).ok_or(CalibrationError::InvalidFormat);
+
+        let sensitivity = value(0)?;
+        check((1.0..=10.0).contains(&sensitivity))?;
+
+        let noise_profile = if flags & HAS_NOISE_PROFILE != 0 {
+            let sampling_frequency = value(1)?;
+            let mut levels = [0.0; NOISE_PROFILE_BANDS];
+            for (band, level) in levels.iter_mut().enumerate() {
+                *level = value(2 + band)?;
+            }
+            check(sampling_frequency > 0.0 && levels.iter().all(|&level| level >= 0.0))?;
+            Some(NoiseProfile::from_levels(sampling_frequency, levels))
+        } else {
+            None
+        };
+
+        let gain = if flags & HAS_GAIN != 0 {
+            let (gain, level) = (
+                value(2 + NOISE_PROFILE_BANDS)?,
+                value(3 + NOISE_PROFILE_BANDS)?,
+            );
+            check(gain > 0.0 && level > 0.0)?;
+            Some((gain, level))
+        } else {
+            None
+        };
+
+        let bpm_range = if flags & HAS_BPM_RANGE != 0 {
+            let range = (
+                value(4 + NOISE_PROFILE_BANDS)?,
+                value(5 + NOISE_PROFILE_BANDS)?,
+            );
+            check(range.0 > 0.0 && range.0 <= range.1)?;
+            Some(range)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            noise_profile,
+            sensitivity,
+            gain,
+            bpm_range,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NoiseProfileLearner;
+    use crate::{BeatDetector, EnvelopeConfig, TempoConfig};
+    use core::time::Duration;
+
+    #[test]
+    fn bytes_roundtrip() {
+        let calibration = Calibration {
+            noise_profile: Some(NoiseProfile::from_levels(44100.0, [1.0, 2.0, 3.0, 4.0])),
+            sensitivity: 2.5,
+            gain: Some((0.5, 3000.0)),
+            bpm_range: Some((120.0, 128.0)),
+        };
+        let bytes = calibration.to_bytes();
+        assert_eq!(Calibration::from_bytes(&bytes), Ok(calibration));
+
+        let calibration = Calibration::new(None, 2.0, None);
+        assert_eq!(
+            Calibration::from_bytes(&calibration.to_bytes()),
+            Ok(calibration)
+        );
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        let bytes = Calibration::new(None, 2.0, None).to_bytes();
+        assert_eq!(
+            Calibration::from_bytes(&bytes[..CALIBRATION_LEN - 1]),
+            Err(CalibrationError::InvalidFormat)
+        );
+        let mut invalid = bytes;
+        invalid[0] = b'X';
+        assert_eq!(
+            Calibration::from_bytes(&invalid),
+            Err(CalibrationError::InvalidFormat)
+        );
+        let mut invalid = bytes;
+        invalid[4] = 42;
+        assert_eq!(
+            Calibration::from_bytes(&invalid),
+            Err(CalibrationError::UnsupportedVersion(42))
+        );
+        let mut invalid = bytes;
+        invalid[6..10].copy_from_slice(&f32::NAN.to_le_bytes());
+        assert_eq!(
+            Calibration::from_bytes(&invalid),
+            Err(CalibrationError::InvalidFormat)
+        );
+        let mut invalid = bytes;
+        invalid[5] = HAS_BPM_RANGE;
+        assert_eq!(
+            Calibration::from_bytes(&invalid),
+            Err(CalibrationError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn restore() {
+        let mut learner = NoiseProfileLearner::new(44100.0);
+        learner.update((0..44100).map(|i| [30, -20, 10, -40][i % 4]));
+        let mut detector = BeatDetector::new(44100.0, true);
+        detector.set_noise_profile(learner.finish());
+        detector.set_gain_normalization(true);
+        detector.set_envelope_config(EnvelopeConfig {
+            max_peak_to_median_min_ratio: 2.5,
+            ..EnvelopeConfig::DEFAULT
+        });
+        // A loud sine, so that the gain normalization settles.
+        let sine = (0..44100 * 4).map(|i| {
+            (libm::sinf(i as f32 * 50.0 * 2.0 * core::f32::consts::PI / 44100.0) * 20000.0) as i16
+        });
+        let _ = detector.update_and_detect_beat(sine);
+        let mut tempo = TempoEstimator::new(TempoConfig::default());
+        for beat in 0..8 {
+            tempo.update(Duration::from_millis(beat * 500 + beat % 2 * 10));
+        }
+
+        let calibration = detector.calibration().with_tempo(&tempo);
+        let (slowest, fastest) = calibration.bpm_range().unwrap();
+        assert!(slowest < 120.0 && fastest > 120.0 && fastest < 125.0);
+        assert_eq!(calibration.sensitivity(), 2.5);
+        assert!(calibration.gain().is_some());
+
+        let calibration = Calibration::from_bytes(&calibration.to_bytes()).unwrap();
+        let mut restored = BeatDetector::new(44100.0, true);
+        restored.restore_calibration(&calibration);
+        assert_eq!(restored.noise_profile(), detector.noise_profile());
+        assert_eq!(restored.gain_normalization(), detector.gain_normalization());
+        assert_eq!(restored.envelope_config().max_peak_to_median_min_ratio, 2.5);
+
+        // The tempo is known right away.
+        let mut restored_tempo = TempoEstimator::new(TempoConfig::default());
+        calibration.restore_tempo(&mut restored_tempo);
+        let bpm = restored_tempo.bpm().unwrap();
+        assert!((slowest..=fastest).contains(&bpm), "{bpm}");
+
+        // The noise profile only applies to its sampling frequency.
+        let mut restored = BeatDetector::new(48000.0, true);
+        restored.restore_calibration(&calibration);
+        assert_eq!(restored.noise_profile(), None);
+    }
+}
@@ -0,0 +1,415 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`CustomFilter`].
+
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::SnapshotError;
+use biquad::{Coefficients, ToHertz, Type, Q_BUTTERWORTH_F32};
+use core::fmt::{Display, Formatter};
+
+/// Maximum amount of biquad stages of a [`CustomFilter`].
+pub const MAX_CUSTOM_FILTER_STAGES: usize = 4;
+
+/// Coefficients of a biquad filter, normalized so that `a0` is `1`:
+///
+/// `y[n] = b0 * x[n] + b1 * x[n-1] + b2 * x[n-2] - a1 * y[n-1] - a2 * y[n-2]`
+///
+/// Most filter design tools output coefficients in this form. Some tools
+/// use the opposite sign for `a1` and `a2`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BiquadCoefficients {
+    /// Feedforward coefficient of the current input.
+    pub b0: f32,
+    /// Feedforward coefficient of the previous input.
+    pub b1: f32,
+    /// Feedforward coefficient of the input before the previous one.
+    pub b2: f32,
+    /// Feedback coefficient of the previous output.
+    pub a1: f32,
+    /// Feedback coefficient of the output before the previous one.
+    pub a2: f32,
+}
+
+impl BiquadCoefficients {
+    /// Creates new coefficients.
+    pub const fn new(b0: f32, b1: f32, b2: f32, a1: f32, a2: f32) -> Self {
+        Self { b0, b1, b2, a1, a2 }
+    }
+
+    /// Creates the coefficients of a second order Butterworth lowpass filter,
+    /// like the built-in lowpass filter of the detector but with another
+    /// cutoff frequency. Returns `None` if the cutoff frequency isn't below
+    /// the Nyquist frequency.
+    pub fn lowpass(sampling_frequency_hz: f32, cutoff_frequency_hz: f32) -> Option<Self> {
+        Coefficients::<f32>::from_params(
+            Type::LowPass,
+            sampling_frequency_hz.hz(),
+            cutoff_frequency_hz.hz(),
+            Q_BUTTERWORTH_F32,
+        )
+        .ok()
+        .map(Self::from)
+    }
+
+    /// Returns whether all poles are inside the unit circle, i.e., whether
+    /// the output of the filter decays for every input.
+    pub fn is_stable(&self) -> bool {
+        libm::fabsf(self.a2) < 1.0 && libm::fabsf(self.a1) < 1.0 + self.a2
+    }
+
+    fn is_finite(&self) -> bool {
+        [self.b0, self.b1, self.b2, self.a1, self.a2]
+            .iter()
+            .all(|coefficient| coefficient.is_finite())
+    }
+
+    /// Returns the gain in dB and the phase in radians at the angular
+    /// frequency `omega` (radians per sample).
+    pub(crate) fn response(&self, omega: f32) -> (f32, f32) {
+        let (sin1, cos1) = (libm::sinf(omega), libm::cosf(omega));
+        let (sin2, cos2) = (libm::sinf(2.0 * omega), libm::cosf(2.0 * omega));
+        let Self { b0, b1, b2, a1, a2 } = *self;
+        // Real and imaginary parts of the numerator and the denominator.
+        let (num_re, num_im) = (b0 + b1 * cos1 + b2 * cos2, -(b1 * sin1 + b2 * sin2));
+        let (den_re, den_im) = (1.0 + a1 * cos1 + a2 * cos2, -(a1 * sin1 + a2 * sin2));
+        let gain_db = 10.0
+            * libm::log10f(
+                (num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im),
+            );
+        let phase = libm::atan2f(num_im, num_re) - libm::atan2f(den_im, den_re);
+        (gain_db, phase)
+    }
+
+    /// Returns the group delay in samples at the angular frequency `omega`
+    /// (radians per sample).
+    ///
+    /// This is computed analytically instead of from the derivative of the
+    /// phase, which wraps around at ±π. For a polynomial `P(z) = Σ p_k z^-k`,
+    /// the delay is `Re(Σ k p_k e^-jkω / P(e^jω))`. The delay of the filter
+    /// is the delay of the numerator minus the delay of the denominator.
+    pub(crate) fn group_delay(&self, omega: f32) -> f32 {
+        let polynomial_delay = |p0: f32, p1: f32, p2: f32| {
+            let (sin1, cos1) = (libm::sinf(omega), libm::cosf(omega));
+            let (sin2, cos2) = (libm::sinf(2.0 * omega), libm::cosf(2.0 * omega));
+            let (re, im) = (p0 + p1 * cos1 + p2 * cos2, -(p1 * sin1 + p2 * sin2));
+            let (ramp_re, ramp_im) = (p1 * cos1 + 2.0 * p2 * cos2, -(p1 * sin1 + 2.0 * p2 * sin2));
+            let magnitude_squared = re * re + im * im;
+            (ramp_re * re + ramp_im * im) / magnitude_squared
+        };
+        let Self { b0, b1, b2, a1, a2 } = *self;
+        polynomial_delay(b0, b1, b2) - polynomial_delay(1.0, a1, a2)
+    }
+}
+
+impl From<Coefficients<f32>> for BiquadCoefficients {
+    fn from(coefficients: Coefficients<f32>) -> Self {
+        let Coefficients { a1, a2, b0, b1, b2 } = coefficients;
+        Self { b0, b1, b2, a1, a2 }
+    }
+}
+
+impl From<BiquadCoefficients> for Coefficients<f32> {
+    fn from(coefficients: BiquadCoefficients) -> Self {
+        let BiquadCoefficients { b0, b1, b2, a1, a2 } = coefficients;
+        Self { a1, a2, b0, b1, b2 }
+    }
+}
+
+/// Error of [`CustomFilter::new`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CustomFilterError {
+    /// The filter has no stages.
+    NoStages,
+    /// The filter has more than [`MAX_CUSTOM_FILTER_STAGES`] stages.
+    TooManyStages,
+    /// A coefficient of the stage with the given index is not finite.
+    NotFinite { stage: usize },
+    /// The stage with the given index is unstable.
+    Unstable { stage: usize },
+}
+
+impl Display for CustomFilterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NoStages => write!(f, "the filter has no stages"),
+            Self::TooManyStages => write!(
+                f,
+                "the filter has more than {MAX_CUSTOM_FILTER_STAGES} stages"
+            ),
+            Self::NotFinite { stage } => {
+                write!(f, "stage {stage} has a coefficient that is not finite")
+            }
+            Self::Unstable { stage } => write!(f, "stage {stage} is unstable"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CustomFilterError {}
+
+/// Cascade of biquad filters that replaces the built-in filters of a
+/// [`BeatDetector`].
+///
+/// This is for users who designed their filter elsewhere. See
+/// [`BeatDetector::set_custom_filter`].
+///
+/// The coefficients only apply to the sampling frequency they were designed
+/// for. The stages are validated, so that an unstable filter can't silently
+/// break the detection.
+///
+/// [`BeatDetector`]: crate::BeatDetector
+/// [`BeatDetector::set_custom_filter`]: crate::BeatDetectorConst::set_custom_filter
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CustomFilter {
+    stages: [BiquadCoefficients; MAX_CUSTOM_FILTER_STAGES],
+    len: usize,
+}
+
+impl CustomFilter {
+    /// Creates a cascade of the given stages, which are applied in order.
+    pub fn new(stages: &[BiquadCoefficients]) -> Result<Self, CustomFilterError> {
+        if stages.is_empty() {
+            return Err(CustomFilterError::NoStages);
+        }
+        if stages.len() > MAX_CUSTOM_FILTER_STAGES {
+            return Err(CustomFilterError::TooManyStages);
+        }
+        for (stage, coefficients) in stages.iter().enumerate() {
+            if !coefficients.is_finite() {
+                return Err(CustomFilterError::NotFinite { stage });
+            }
+            if !coefficients.is_stable() {
+                return Err(CustomFilterError::Unstable { stage });
+            }
+        }
+        let mut filter = Self {
+            stages: [BiquadCoefficients::new(1.0, 0.0, 0.0, 0.0, 0.0); MAX_CUSTOM_FILTER_STAGES],
+            len: stages.len(),
+        };
+        filter.stages[..stages.len()].copy_from_slice(stages);
+        Ok(filter)
+    }
+
+    /// Returns the stages of the cascade.
+    pub fn stages(&self) -> &[BiquadCoefficients] {
+        &self.stages[..self.len]
+    }
+
+    /// Returns the gain in dB and the phase in radians of the cascade at the
+    /// angular frequency `omega` (radians per sample).
+    pub(crate) fn response(&self, omega: f32) -> (f32, f32) {
+        self.stages()
+            .iter()
+            .fold((0.0, 0.0), |(gain_db, phase), stage| {
+                let (stage_gain_db, stage_phase) = stage.response(omega);
+                (gain_db + stage_gain_db, phase + stage_phase)
+            })
+    }
+
+    /// Returns the group delay of the cascade in samples at the angular
+    /// frequency `omega` (radians per sample), i.e., the sum of the delays of
+    /// the stages.
+    pub(crate) fn group_delay(&self, omega: f32) -> f32 {
+        self.stages()
+            .iter()
+            .map(|stage| stage.group_delay(omega))
+            .sum()
+    }
+}
+
+/// A biquad filter in direct form 1, which computes exactly like
+/// [`biquad::DirectForm1`]. Other than that, its state is accessible, so that it can
+/// be part of a snapshot of the detector.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct FilterStage {
+    coefficients: Coefficients<f32>,
+    /// The previous two inputs and outputs: `x1`, `x2`, `y1`, `y2`.
+    state: [f32; 4],
+}
+
+impl FilterStage {
+    pub(crate) const fn new(coefficients: Coefficients<f32>) -> Self {
+        Self {
+            coefficients,
+            state: [0.0; 4],
+        }
+    }
+
+    #[inline]
+    pub(crate) fn run(&mut self, input: f32) -> f32 {
+        let Coefficients { a1, a2, b0, b1, b2 } = self.coefficients;
+        let [x1, x2, y1, y2] = self.state;
+        let output = b0 * input + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+        self.state = [input, x1, output, y1];
+        output
+    }
+
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        for value in self.state {
+            writer.f32(value);
+        }
+    }
+
+    pub(crate) fn read_snapshot(
+        &mut self,
+        reader: &mut SnapshotReader,
+    ) -> Result<(), SnapshotError> {
+        for value in &mut self.state {
+            *value = reader.f32()?;
+        }
+        Ok(())
+    }
+}
+
+/// A [`CustomFilter`] together with the state of its stages.
+#[derive(Debug, Clone)]
+pub(crate) struct CustomFilterChain {
+    filter: CustomFilter,
+    stages: [FilterStage; MAX_CUSTOM_FILTER_STAGES],
+}
+
+impl CustomFilterChain {
+    pub(crate) fn new(filter: CustomFilter) -> Self {
+        Self {
+            filter,
+            stages: filter.stages.map(|stage| FilterStage::new(stage.into())),
+        }
+    }
+
+    pub(crate) const fn filter(&self) -> &CustomFilter {
+        &self.filter
+    }
+
+    /// Returns the filtered sample.
+    pub(crate) fn run(&mut self, sample: f32) -> f32 {
+        self.stages[..self.filter.len]
+            .iter_mut()
+            .fold(sample, |sample, stage| stage.run(sample))
+    }
+
+    /// Writes the coefficients and the state of the stages.
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.u8(self.filter.len as u8);
+        for (coefficients, stage) in self.filter.stages().iter().zip(&self.stages) {
+            for value in [
+                coefficients.b0,
+                coefficients.b1,
+                coefficients.b2,
+                coefficients.a1,
+                coefficients.a2,
+            ] {
+                writer.f32(value);
+            }
+            stage.write_snapshot(writer);
+        }
+    }
+
+    /// Restores a chain written by [`Self::write_snapshot`].
+    pub(crate) fn read_snapshot(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let len = reader.u8()? as usize;
+        SnapshotReader::check(len <= MAX_CUSTOM_FILTER_STAGES)?;
+        let mut stages =
+            [BiquadCoefficients::new(1.0, 0.0, 0.0, 0.0, 0.0); MAX_CUSTOM_FILTER_STAGES];
+        let mut states = [[0.0; 4]; MAX_CUSTOM_FILTER_STAGES];
+        for (coefficients, state) in stages.iter_mut().zip(&mut states).take(len) {
+            *coefficients = BiquadCoefficients::new(
+                reader.f32()?,
+                reader.f32()?,
+                reader.f32()?,
+                reader.f32()?,
+                reader.f32()?,
+            );
+            for value in state {
+                *value = reader.f32()?;
+            }
+        }
+        let filter = CustomFilter::new(&stages[..len]).map_err(|_| SnapshotError::InvalidFormat)?;
+        let mut chain = Self::new(filter);
+        for (stage, state) in chain.stages.iter_mut().zip(states) {
+            stage.state = state;
+        }
+        Ok(chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation() {
+        let pass = BiquadCoefficients::new(1.0, 0.0, 0.0, 0.0, 0.0);
+        let unstable = BiquadCoefficients::new(1.0, 0.0, 0.0, -2.0, 1.0);
+        assert!(pass.is_stable());
+        assert!(!unstable.is_stable());
+
+        assert_eq!(CustomFilter::new(&[]), Err(CustomFilterError::NoStages));
+        assert_eq!(
+            CustomFilter::new(&[pass; MAX_CUSTOM_FILTER_STAGES + 1]),
+            Err(CustomFilterError::TooManyStages)
+        );
+        assert_eq!(
+            CustomFilter::new(&[pass, unstable]),
+            Err(CustomFilterError::Unstable { stage: 1 })
+        );
+        assert_eq!(
+            CustomFilter::new(&[BiquadCoefficients::new(f32::NAN, 0.0, 0.0, 0.0, 0.0)]),
+            Err(CustomFilterError::NotFinite { stage: 0 })
+        );
+        let filter = CustomFilter::new(&[pass, pass]).unwrap();
+        assert_eq!(filter.stages(), &[pass, pass]);
+    }
+
+    #[test]
+    fn cascade() {
+        // A one-sample delay, twice.
+        let delay = BiquadCoefficients::new(0.0, 1.0, 0.0, 0.0, 0.0);
+        let filter = CustomFilter::new(&[delay, delay]).unwrap();
+        let mut chain = CustomFilterChain::new(filter);
+        let output = [1.0, 2.0, 3.0, 4.0].map(|sample| chain.run(sample));
+        assert_eq!(output, [0.0, 0.0, 1.0, 2.0]);
+
+        // Unit gain and a phase of two samples.
+        let (gain_db, phase) = filter.response(0.1);
+        assert!(libm::fabsf(gain_db) < 0.001);
+        assert!(libm::fabsf(phase + 0.2) < 0.001);
+        assert!(libm::fabsf(filter.group_delay(0.1) - 2.0) < 0.001);
+    }
+
+    #[test]
+    fn lowpass() {
+        let lowpass = BiquadCoefficients::lowpass(44100.0, 120.0).unwrap();
+        assert!(lowpass.is_stable());
+        let omega = |frequency_hz: f32| 2.0 * core::f32::consts::PI * frequency_hz / 44100.0;
+        // -3 dB at the cutoff frequency.
+        assert!(libm::fabsf(lowpass.response(omega(120.0)).0 + 3.0) < 0.1);
+        assert!(lowpass.response(omega(1000.0)).0 < -30.0);
+        // The group delay at low frequencies is `1 / (Q * ω0)`.
+        let delay = 1.0 / (Q_BUTTERWORTH_F32 * omega(120.0));
+        assert!(libm::fabsf(lowpass.group_delay(omega(10.0)) - delay) < 1.0);
+
+        assert_eq!(BiquadCoefficients::lowpass(44100.0, 30000.0), None);
+    }
+}
@@ -0,0 +1,191 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`DecisionTrace`].
+
+use core::time::Duration;
+
+/// Amount of entries that a [`DecisionTrace`] holds. With typical audio
+/// callbacks of 20-40 ms, this covers the last few seconds.
+pub const DECISION_TRACE_CAPACITY: usize = 128;
+
+/// Outcome of one update of a [`BeatDetector`], i.e., why a beat was or
+/// wasn't reported.
+///
+/// [`BeatDetector`]: crate::BeatDetector
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Decision {
+    /// A beat was reported.
+    Beat,
+    /// The detector didn't consume enough audio yet.
+    WarmingUp,
+    /// The update added no audio that wasn't analyzed already.
+    NothingNew,
+    /// All new peaks are below [`EnvelopeConfig::min_value`].
+    ///
+    /// [`EnvelopeConfig::min_value`]: crate::EnvelopeConfig::min_value
+    NoiseGate,
+    /// The begin of a possible beat is too close to the newest audio. The
+    /// next update decides about it.
+    TooRecent,
+    /// The audio history contains too few peaks to compare beats with.
+    InsufficientHistory,
+    /// No peak exceeds the median of the peaks by
+    /// [`EnvelopeConfig::max_peak_to_median_min_ratio`].
+    ///
+    /// [`EnvelopeConfig::max_peak_to_median_min_ratio`]: crate::EnvelopeConfig::max_peak_to_median_min_ratio
+    RatioBelowThreshold,
+    /// A loud enough peak was found, but the envelope around it was rejected,
+    /// as its decay doesn't end within the audio history yet.
+    EnvelopeRejected,
+    /// A beat was found but merged into the previous beat, as it follows it
+    /// too closely according to [`EnvelopeConfig::merge_policy`].
+    ///
+    /// [`EnvelopeConfig::merge_policy`]: crate::EnvelopeConfig::merge_policy
+    Refractory,
+    /// A beat was found but suppressed, as the detector is muted. See
+    /// [`BeatDetector::mute_for`].
+    ///
+    /// [`BeatDetector::mute_for`]: crate::BeatDetectorConst::mute_for
+    Muted,
+}
+
+/// Entry of a [`DecisionTrace`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct DecisionTraceEntry {
+    /// [`BeatDetector::passed_time`] after the update.
+    ///
+    /// [`BeatDetector::passed_time`]: crate::BeatDetectorConst::passed_time
+    pub time: Duration,
+    /// The outcome of the update.
+    pub decision: Decision,
+    /// Ratio between the loudest sample of the update and the median of the
+    /// peaks in the audio history. `0.0` if there is no median yet.
+    pub peak_to_median_ratio: f32,
+    /// The ratio a beat must reach, i.e.,
+    /// [`EnvelopeConfig::max_peak_to_median_min_ratio`].
+    ///
+    /// [`EnvelopeConfig::max_peak_to_median_min_ratio`]: crate::EnvelopeConfig::max_peak_to_median_min_ratio
+    pub threshold: f32,
+}
+
+impl Default for DecisionTraceEntry {
+    fn default() -> Self {
+        Self {
+            time: Duration::ZERO,
+            decision: Decision::WarmingUp,
+            peak_to_median_ratio: 0.0,
+            threshold: 0.0,
+        }
+    }
+}
+
+/// Bounded record of the latest [`Decision`]s of a [`BeatDetector`], to
+/// debug why it missed a beat.
+///
+/// The trace keeps the latest [`DECISION_TRACE_CAPACITY`] decisions and is
+/// filled by [`BeatDetector::update_and_detect_beat_traced`].
+///
+/// [`BeatDetector`]: crate::BeatDetector
+/// [`BeatDetector::update_and_detect_beat_traced`]: crate::BeatDetectorConst::update_and_detect_beat_traced
+#[derive(Debug, Clone)]
+pub struct DecisionTrace {
+    entries: [DecisionTraceEntry; DECISION_TRACE_CAPACITY],
+    len: usize,
+    next: usize,
+}
+
+impl DecisionTrace {
+    /// Creates an empty trace.
+    pub fn new() -> Self {
+        Self {
+            entries: [DecisionTraceEntry::default(); DECISION_TRACE_CAPACITY],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// Adds the entry of the latest update. The oldest entry is dropped if
+    /// the trace is full.
+    pub(crate) fn push(&mut self, entry: DecisionTraceEntry) {
+        self.entries[self.next] = entry;
+        self.next = (self.next + 1) % DECISION_TRACE_CAPACITY;
+        self.len = (self.len + 1).min(DECISION_TRACE_CAPACITY);
+    }
+
+    /// Returns the amount of entries.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there are no entries.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the entry of the latest update.
+    pub fn latest(&self) -> Option<&DecisionTraceEntry> {
+        self.iter().next_back()
+    }
+
+    /// Returns an iterator over all entries, from the oldest to the latest.
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = &DecisionTraceEntry> + ExactSizeIterator + '_ {
+        (0..self.len).rev().map(|age| {
+            &self.entries[(self.next + DECISION_TRACE_CAPACITY - 1 - age) % DECISION_TRACE_CAPACITY]
+        })
+    }
+}
+
+impl Default for DecisionTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded() {
+        let mut trace = DecisionTrace::new();
+        assert!(trace.is_empty());
+        assert_eq!(trace.latest(), None);
+        for millis in 0..DECISION_TRACE_CAPACITY as u64 + 10 {
+            trace.push(DecisionTraceEntry {
+                time: Duration::from_millis(millis),
+                ..Default::default()
+            });
+        }
+        assert_eq!(trace.len(), DECISION_TRACE_CAPACITY);
+        assert_eq!(trace.iter().next().unwrap().time, Duration::from_millis(10));
+        assert_eq!(
+            trace.latest().unwrap().time,
+            Duration::from_millis(DECISION_TRACE_CAPACITY as u64 + 9)
+        );
+    }
+}
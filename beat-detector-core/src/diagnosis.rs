@@ -0,0 +1,235 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`Diagnosis`].
+
+/// Peak amplitude below which the audio is considered as noise. Matches the
+/// threshold of the detection pipeline.
+pub(crate) const NOISE_FLOOR: i16 = (i16::MAX as f32 * 0.05) as i16;
+
+/// Amount of consecutive full-scale samples that are considered as clipping.
+/// A single full-scale sample is common in loudly mastered music.
+const CLIPPING_MIN_CONSECUTIVE_SAMPLES: usize = 3;
+
+/// Lowest plausible frequency of the signal the detector operates on.
+const MIN_PLAUSIBLE_FREQUENCY_HZ: f32 = 20.0;
+
+/// A reason why the detector might not detect beats.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DiagnosticIssue {
+    /// The detector didn't consume enough audio yet.
+    InsufficientHistory,
+    /// The signal is so quiet that it is considered as noise.
+    BelowNoiseFloor,
+    /// The latest audio input was clipping.
+    Clipping,
+    /// The frequencies in the lowpassed signal are implausible for the
+    /// configured sampling rate. Probably, the configured sampling rate
+    /// doesn't match the one of the audio source.
+    ///
+    /// This is only checked if the detector applies its built-in lowpass
+    /// filter, as the frequencies of unfiltered audio or of a custom filter
+    /// are unknown.
+    SampleRateMismatchSuspected,
+}
+
+/// Result of [`BeatDetector::diagnose`]. Lists possible reasons why no beats
+/// are detected.
+///
+/// [`BeatDetector::diagnose`]: crate::BeatDetector::diagnose
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Diagnosis {
+    /// See [`DiagnosticIssue::InsufficientHistory`].
+    pub insufficient_history: bool,
+    /// See [`DiagnosticIssue::BelowNoiseFloor`].
+    pub below_noise_floor: bool,
+    /// See [`DiagnosticIssue::Clipping`].
+    pub clipping: bool,
+    /// See [`DiagnosticIssue::SampleRateMismatchSuspected`].
+    pub sample_rate_mismatch_suspected: bool,
+}
+
+impl Diagnosis {
+    /// Returns whether no issue was found.
+    pub const fn is_ok(&self) -> bool {
+        !(self.insufficient_history
+            || self.below_noise_floor
+            || self.clipping
+            || self.sample_rate_mismatch_suspected)
+    }
+
+    /// Returns an iterator over all found issues.
+    pub fn issues(&self) -> impl Iterator<Item = DiagnosticIssue> {
+        [
+            (
+                self.insufficient_history,
+                DiagnosticIssue::InsufficientHistory,
+            ),
+            (self.below_noise_floor, DiagnosticIssue::BelowNoiseFloor),
+            (self.clipping, DiagnosticIssue::Clipping),
+            (
+                self.sample_rate_mismatch_suspected,
+                DiagnosticIssue::SampleRateMismatchSuspected,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(found, issue)| found.then_some(issue))
+    }
+}
+
+/// Tracks whether the raw audio input of the latest update was clipping.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ClippingDetector {
+    consecutive_full_scale_samples: usize,
+    is_clipping: bool,
+}
+
+impl ClippingDetector {
+    /// Must be called before the samples of a new update are fed.
+    pub fn begin_update(&mut self) {
+        self.consecutive_full_scale_samples = 0;
+        self.is_clipping = false;
+    }
+
+    #[inline]
+    pub fn feed(&mut self, sample: i16) {
+        if sample == i16::MAX || sample <= -i16::MAX {
+            self.consecutive_full_scale_samples += 1;
+            if self.consecutive_full_scale_samples >= CLIPPING_MIN_CONSECUTIVE_SAMPLES {
+                self.is_clipping = true;
+            }
+        } else {
+            self.consecutive_full_scale_samples = 0;
+        }
+    }
+
+    pub const fn is_clipping(&self) -> bool {
+        self.is_clipping
+    }
+}
+
+/// Returns whether the highest amplitude of the samples is below the noise
+/// floor.
+pub(crate) fn is_below_noise_floor(samples: impl Iterator<Item = i16>) -> bool {
+    samples.map(i16::saturating_abs).max().unwrap_or(0) < NOISE_FLOOR
+}
+
+/// Returns whether the zero-crossing frequency of the samples is implausible
+/// for a signal that went through a lowpass filter with the given cutoff
+/// frequency. Samples within the noise floor are ignored.
+///
+/// `sample_count` is the length of `samples`.
+pub(crate) fn is_sample_rate_mismatch_suspected(
+    samples: impl Iterator<Item = i16>,
+    sample_count: usize,
+    sampling_frequency_hz: f32,
+    cutoff_frequency_hz: f32,
+) -> bool {
+    let duration_secs = sample_count as f32 / sampling_frequency_hz;
+    let mut is_above = None;
+    let mut crossings = 0;
+    for sample in samples.filter(|sample| sample.saturating_abs() >= NOISE_FLOOR) {
+        let above = sample.is_positive();
+        if is_above.is_some_and(|is_above| is_above != above) {
+            crossings += 1;
+        }
+        is_above.replace(above);
+    }
+
+    let frequency_hz = crossings as f32 / 2.0 / duration_secs;
+    !(MIN_PLAUSIBLE_FREQUENCY_HZ..=cutoff_frequency_hz * 4.0).contains(&frequency_hz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn sine(frequency_hz: f32, sampling_frequency_hz: f32, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / sampling_frequency_hz;
+                (libm::sinf(2.0 * core::f32::consts::PI * frequency_hz * t) * 16000.0) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn issues_are_listed() {
+        let diagnosis = Diagnosis::default();
+        assert!(diagnosis.is_ok());
+        assert_eq!(diagnosis.issues().count(), 0);
+
+        let diagnosis = Diagnosis {
+            below_noise_floor: true,
+            clipping: true,
+            ..Default::default()
+        };
+        assert!(!diagnosis.is_ok());
+        assert_eq!(
+            diagnosis.issues().collect::<Vec<_>>(),
+            [DiagnosticIssue::BelowNoiseFloor, DiagnosticIssue::Clipping]
+        );
+    }
+
+    #[test]
+    fn clipping_is_detected() {
+        let mut detector = ClippingDetector::default();
+        [i16::MAX, 0, i16::MAX, i16::MAX, 0, i16::MIN, i16::MIN]
+            .into_iter()
+            .for_each(|sample| detector.feed(sample));
+        assert!(!detector.is_clipping());
+        detector.feed(i16::MIN);
+        assert!(detector.is_clipping());
+
+        detector.begin_update();
+        assert!(!detector.is_clipping());
+    }
+
+    #[test]
+    fn sample_rate_mismatch_is_detected() {
+        let samples = sine(60.0, 44100.0, 44100);
+        assert!(!is_sample_rate_mismatch_suspected(
+            samples.iter().copied(),
+            samples.len(),
+            44100.0,
+            95.0
+        ));
+        // Configured sampling rate is way too low.
+        assert!(is_sample_rate_mismatch_suspected(
+            samples.iter().copied(),
+            samples.len(),
+            8000.0,
+            95.0
+        ));
+        // Configured sampling rate is way too high.
+        assert!(is_sample_rate_mismatch_suspected(
+            samples.iter().copied(),
+            samples.len(),
+            441000.0,
+            95.0
+        ));
+    }
+}
@@ -0,0 +1,500 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`DropTracker`].
+
+use crate::{ChunkLevel, TempoEstimator};
+use core::time::Duration;
+
+/// Duration of a bar while the tempo is unknown, i.e., a bar of four beats at
+/// 120 BPM.
+const FALLBACK_BAR_DURATION: Duration = Duration::from_secs(2);
+
+/// Maximum for [`DropConfig::build_bars`].
+const MAX_BUILD_BARS: u8 = 16;
+
+/// Phase of a track in terms of a build-up and its drop, see [`DropTracker`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DropPhase {
+    /// No build-up is going on, such as in a verse or a breakdown.
+    #[default]
+    Calm,
+    /// The energy or the density of the beats rises from bar to bar towards
+    /// a drop.
+    Building,
+    /// The first bar of a drop, beginning with its first beat.
+    Drop,
+    /// The remainder of a drop, until the energy falls again.
+    Sustain,
+}
+
+/// A change of the [`DropPhase`], as returned by [`DropTracker::update`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct DropTransition {
+    /// The previous phase.
+    pub from: DropPhase,
+    /// The new phase.
+    pub to: DropPhase,
+    /// Time of the update that caused the transition.
+    pub time: Duration,
+}
+
+/// Configuration of a [`DropTracker`]. All values must be in the documented
+/// ranges.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DropConfig {
+    /// Amount of consecutive rising bars that begin a build-up.
+    ///
+    /// Range: `1..=16`.
+    pub build_bars: u8,
+    /// Minimum rise of the RMS level from one bar to the next, in dB, for the
+    /// bar to count as rising. A bar with more beats than the previous one,
+    /// such as during a snare roll, also counts as rising if its level
+    /// didn't fall.
+    ///
+    /// Range: greater than `0.0`.
+    pub min_build_rise_db: f32,
+    /// Maximum duration of a build-up in bars. If no drop follows, the phase
+    /// returns to [`DropPhase::Calm`].
+    ///
+    /// Range: at least [`Self::build_bars`].
+    pub max_build_bars: u8,
+    /// How much louder than the loudest beat of the build-up the chunk with
+    /// the first beat of the drop is, in dB. Build-ups without beats, such as
+    /// a riser, are compared by the level of their loudest bar.
+    ///
+    /// Range: greater than `0.0`.
+    pub drop_rise_db: f32,
+    /// How much the RMS level of a bar must fall below the loudest bar of the
+    /// drop, in dB, to end it. A bar without any beat also ends it.
+    ///
+    /// Range: greater than `0.0`.
+    pub calm_fall_db: f32,
+}
+
+impl Default for DropConfig {
+    fn default() -> Self {
+        Self {
+            build_bars: 2,
+            min_build_rise_db: 1.0,
+            max_build_bars: 16,
+            drop_rise_db: 3.0,
+            calm_fall_db: 6.0,
+        }
+    }
+}
+
+impl DropConfig {
+    /// Panics if a value is out of its documented range.
+    fn check(&self) {
+        assert!(
+            (1..=MAX_BUILD_BARS).contains(&self.build_bars),
+            "build_bars must be in range 1..={MAX_BUILD_BARS}"
+        );
+        assert!(
+            self.max_build_bars >= self.build_bars,
+            "max_build_bars must be at least build_bars"
+        );
+        assert!(
+            self.min_build_rise_db > 0.0 && self.drop_rise_db > 0.0 && self.calm_fall_db > 0.0,
+            "min_build_rise_db, drop_rise_db, and calm_fall_db must be positive"
+        );
+    }
+}
+
+/// RMS level and amount of beats of a bar.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct BarStats {
+    rms: f32,
+    beats: u32,
+}
+
+/// State machine for "drop anticipation" effects: it tracks whether the
+/// music is calm, builds up, drops, or sustains the drop.
+///
+/// It combines a simple build-up detection with the tempo and the bar
+/// tracking of a [`TempoEstimator`], so that lighting scenes can be authored
+/// against a few stable phases instead of raw features:
+///
+/// - [`DropPhase::Calm`] to [`DropPhase::Building`]: the RMS level or the
+///   amount of beats rose in each of the latest [`DropConfig::build_bars`]
+///   bars.
+/// - [`DropPhase::Building`] to [`DropPhase::Drop`]: a beat whose chunk is
+///   clearly louder than all beats and bars of the build-up.
+/// - [`DropPhase::Drop`] to [`DropPhase::Sustain`]: at the end of the bar of
+///   the drop.
+/// - [`DropPhase::Sustain`] to [`DropPhase::Calm`]: a bar whose level fell
+///   clearly below the drop, or without beats. A build-up without a drop
+///   also ends in [`DropPhase::Calm`].
+///
+/// Apart from the drop itself, transitions happen at bar boundaries. While
+/// the tempo is unknown, bars are two seconds long.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{
+///     BeatDetector, ChunkLevel, DropConfig, DropPhase, DropTracker, TempoConfig, TempoEstimator,
+/// };
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut tempo = TempoEstimator::new(TempoConfig::default());
+/// let mut drops = DropTracker::new(DropConfig::default());
+///
+/// // TODO regularly call this with the latest audio data.
+/// let beat = detector.update_and_detect_beat(mono_samples.iter().copied());
+/// if let Some(beat) = &beat {
+///     tempo.update(beat.timestamp());
+/// }
+/// let level = ChunkLevel::measure(&mono_samples);
+/// let now = detector.passed_time();
+/// if let Some(transition) = drops.update(now, &level, beat.is_some(), &tempo) {
+///     if transition.to == DropPhase::Drop {
+///         // TODO fire the confetti cannon.
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropTracker {
+    config: DropConfig,
+    phase: DropPhase,
+    phase_start: Duration,
+    /// Index of the current bar, if any update was seen.
+    bar: Option<u64>,
+    /// Sum of the squared samples of the current bar.
+    bar_sum_of_squares: f32,
+    bar_samples: usize,
+    bar_beats: u32,
+    /// RMS level of the loudest chunk with a beat in the current bar.
+    bar_beat_rms: f32,
+    previous_bar: Option<BarStats>,
+    /// Amount of consecutive rising bars while calm.
+    rising_bars: u8,
+    /// Amount of completed bars in the current phase.
+    bars_in_phase: u8,
+    /// RMS level of the loudest chunk with a beat or of the loudest bar of
+    /// the build-up.
+    build_beat_rms: f32,
+    /// RMS level of the loudest bar of the drop.
+    drop_rms: f32,
+}
+
+impl DropTracker {
+    /// Creates a new tracker in [`DropPhase::Calm`]. Panics if the config is
+    /// invalid.
+    pub fn new(config: DropConfig) -> Self {
+        config.check();
+        Self {
+            config,
+            phase: DropPhase::Calm,
+            phase_start: Duration::ZERO,
+            bar: None,
+            bar_sum_of_squares: 0.0,
+            bar_samples: 0,
+            bar_beats: 0,
+            bar_beat_rms: 0.0,
+            previous_bar: None,
+            rising_bars: 0,
+            bars_in_phase: 0,
+            build_beat_rms: 0.0,
+            drop_rms: 0.0,
+        }
+    }
+
+    /// Returns the config.
+    pub const fn config(&self) -> &DropConfig {
+        &self.config
+    }
+
+    /// Forgets everything and returns to [`DropPhase::Calm`], for example,
+    /// when the song changes.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// Returns the current phase.
+    pub const fn phase(&self) -> DropPhase {
+        self.phase
+    }
+
+    /// Returns the time of the transition to the current phase.
+    pub const fn phase_start(&self) -> Duration {
+        self.phase_start
+    }
+
+    /// Returns the amount of completed bars in the current phase, such as the
+    /// length of the build-up so far.
+    pub const fn bars_in_phase(&self) -> u8 {
+        self.bars_in_phase
+    }
+
+    /// Feeds the level of the latest chunk of audio and whether the detector
+    /// reported a beat in it. Returns the transition to a new phase, if any.
+    ///
+    /// `now` is the time at the end of the chunk, on the same clock as the
+    /// beats passed to the `tempo` estimator, which the caller keeps up to
+    /// date. Call this for every chunk, with chunks much shorter than a bar.
+    pub fn update(
+        &mut self,
+        now: Duration,
+        level: &ChunkLevel,
+        is_beat: bool,
+        tempo: &TempoEstimator,
+    ) -> Option<DropTransition> {
+        let bar = tempo.bars_elapsed(now).map_or_else(
+            || (now.as_secs_f64() / FALLBACK_BAR_DURATION.as_secs_f64()) as u64,
+            |bars| bars as u64,
+        );
+        let mut transition = if self.bar.is_some_and(|current| current != bar) {
+            self.finish_bar(now)
+        } else {
+            None
+        };
+        self.bar = Some(bar);
+
+        self.bar_sum_of_squares += level.rms * level.rms * level.samples as f32;
+        self.bar_samples += level.samples;
+        if is_beat {
+            self.bar_beats += 1;
+            self.bar_beat_rms = self.bar_beat_rms.max(level.rms);
+            if self.phase == DropPhase::Building {
+                let is_drop =
+                    level.rms >= self.build_beat_rms * db_to_factor(self.config.drop_rise_db);
+                if transition.is_none() && is_drop {
+                    transition = self.transition(DropPhase::Drop, now);
+                } else {
+                    self.build_beat_rms = self.build_beat_rms.max(level.rms);
+                }
+            }
+        }
+        transition
+    }
+
+    /// Evaluates the bar that just ended.
+    fn finish_bar(&mut self, now: Duration) -> Option<DropTransition> {
+        let stats = BarStats {
+            rms: if self.bar_samples == 0 {
+                0.0
+            } else {
+                libm::sqrtf(self.bar_sum_of_squares / self.bar_samples as f32)
+            },
+            beats: self.bar_beats,
+        };
+        let previous = self.previous_bar.replace(stats);
+        self.bar_sum_of_squares = 0.0;
+        self.bar_samples = 0;
+        self.bar_beats = 0;
+        let beat_rms = core::mem::take(&mut self.bar_beat_rms);
+        self.bars_in_phase = self.bars_in_phase.saturating_add(1);
+
+        match self.phase {
+            DropPhase::Calm => {
+                let is_rising = previous.is_some_and(|previous| {
+                    stats.rms >= previous.rms * db_to_factor(self.config.min_build_rise_db)
+                        || (stats.beats > previous.beats && stats.rms >= previous.rms)
+                });
+                self.rising_bars = if is_rising { self.rising_bars + 1 } else { 0 };
+                if self.rising_bars < self.config.build_bars {
+                    return None;
+                }
+                self.build_beat_rms = beat_rms.max(stats.rms);
+                self.transition(DropPhase::Building, now)
+            }
+            DropPhase::Building => {
+                self.build_beat_rms = self.build_beat_rms.max(stats.rms);
+                if self.bars_in_phase <= self.config.max_build_bars {
+                    return None;
+                }
+                self.transition(DropPhase::Calm, now)
+            }
+            DropPhase::Drop => {
+                self.drop_rms = stats.rms;
+                self.transition(DropPhase::Sustain, now)
+            }
+            DropPhase::Sustain => {
+                self.drop_rms = self.drop_rms.max(stats.rms);
+                let has_fallen =
+                    stats.rms * db_to_factor(self.config.calm_fall_db) <= self.drop_rms;
+                if !has_fallen && stats.beats > 0 {
+                    return None;
+                }
+                self.transition(DropPhase::Calm, now)
+            }
+        }
+    }
+
+    fn transition(&mut self, to: DropPhase, now: Duration) -> Option<DropTransition> {
+        let from = core::mem::replace(&mut self.phase, to);
+        self.phase_start = now;
+        self.bars_in_phase = 0;
+        self.rising_bars = 0;
+        Some(DropTransition {
+            from,
+            to,
+            time: now,
+        })
+    }
+}
+
+/// Converts a level difference in dB into a factor of the amplitude.
+fn db_to_factor(db: f32) -> f32 {
+    libm::powf(10.0, db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempoConfig;
+    use std::vec::Vec;
+
+    /// Feeds bars of four beats at 120 BPM in chunks of 20 ms. Each bar is
+    /// given as RMS level and the RMS level of the chunks with a beat. Bars
+    /// with a beat level of zero have no beats.
+    fn run(bars: &[(f32, f32)]) -> Vec<(DropPhase, u128)> {
+        let mut tracker = DropTracker::new(DropConfig::default());
+        let mut tempo = TempoEstimator::new(TempoConfig::default());
+        let mut transitions = Vec::new();
+        for (bar, &(rms, beat_rms)) in bars.iter().enumerate() {
+            for chunk in 0..100 {
+                let now = Duration::from_millis((bar * 2000 + chunk * 20) as u64);
+                let is_beat = beat_rms > 0.0 && chunk % 25 == 0;
+                if is_beat {
+                    tempo.update(now);
+                }
+                let level = ChunkLevel {
+                    rms: if is_beat { beat_rms } else { rms },
+                    samples: 882,
+                    ..ChunkLevel::default()
+                };
+                if let Some(transition) = tracker.update(now, &level, is_beat, &tempo) {
+                    assert_eq!(transition.to, tracker.phase());
+                    assert_eq!(transition.time, tracker.phase_start());
+                    transitions.push((transition.to, transition.time.as_millis()));
+                }
+            }
+        }
+        transitions
+    }
+
+    #[test]
+    fn build_up_without_beats() {
+        let calm = (2000.0, 4000.0);
+        let mut bars = vec![calm; 4];
+        // A riser without beats, and then beats that aren't clearly louder
+        // than it.
+        bars.extend([
+            (2600.0, 0.0),
+            (3200.0, 0.0),
+            (3800.0, 0.0),
+            (4400.0, 4400.0),
+        ]);
+        bars.extend([(12000.0, 20000.0); 2]);
+
+        assert_eq!(
+            run(&bars),
+            [
+                (DropPhase::Building, 12000),
+                (DropPhase::Drop, 16000),
+                (DropPhase::Sustain, 18000),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_up_and_drop() {
+        let calm = (2000.0, 4000.0);
+        let mut bars = vec![calm; 4];
+        // A riser over four bars.
+        for bar in 0..4 {
+            let rms = 2000.0 * (1.0 + 0.3 * (bar + 1) as f32);
+            bars.push((rms, rms * 1.2));
+        }
+        // The drop and four bars of it.
+        bars.extend([(12000.0, 20000.0); 4]);
+        // The breakdown.
+        bars.extend([calm; 2]);
+
+        assert_eq!(
+            run(&bars),
+            [
+                (DropPhase::Building, 12000),
+                (DropPhase::Drop, 16000),
+                (DropPhase::Sustain, 18000),
+                (DropPhase::Calm, 26000),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_up_without_drop() {
+        let mut bars = vec![(2000.0, 4000.0); 2];
+        bars.extend([(3000.0, 4000.0), (4000.0, 5000.0)]);
+        bars.extend([(4000.0, 5000.0); 18]);
+
+        assert_eq!(
+            run(&bars),
+            [(DropPhase::Building, 8000), (DropPhase::Calm, 42000)]
+        );
+    }
+
+    #[test]
+    fn rising_beat_density_builds_up() {
+        let mut tracker = DropTracker::new(DropConfig::default());
+        let tempo = TempoEstimator::new(TempoConfig::default());
+        let level = ChunkLevel {
+            rms: 1000.0,
+            samples: 882,
+            ..ChunkLevel::default()
+        };
+        let mut transitions = Vec::new();
+        // Without a tempo, bars are two seconds long. A snare roll doubles
+        // the amount of beats in every bar.
+        for (bar, beats) in [4, 4, 8, 16, 16].into_iter().enumerate() {
+            for chunk in 0..100 {
+                let now = Duration::from_millis((bar * 2000 + chunk * 20) as u64);
+                let is_beat = chunk % (100 / beats) == 0;
+                transitions.extend(tracker.update(now, &level, is_beat, &tempo));
+            }
+        }
+        assert_eq!(
+            transitions,
+            [DropTransition {
+                from: DropPhase::Calm,
+                to: DropPhase::Building,
+                time: Duration::from_secs(8),
+            }]
+        );
+        assert_eq!(tracker.bars_in_phase(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_config() {
+        DropTracker::new(DropConfig {
+            build_bars: 4,
+            max_build_bars: 2,
+            ..DropConfig::default()
+        });
+    }
+}
@@ -0,0 +1,266 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`EchoCanceller`].
+
+/// Default amount of taps of an [`EchoCanceller`]: roughly 6 ms at 44.1 kHz.
+pub const DEFAULT_ECHO_TAPS: usize = 256;
+
+/// Default of [`EchoCanceller::set_step_size`].
+pub const DEFAULT_ECHO_STEP_SIZE: f32 = 0.1;
+
+/// Prevents the division by zero when the reference signal is silent.
+const REGULARIZATION: f32 = 1e-3;
+
+/// Removes known output audio, such as the beeps of the own speakers, from
+/// the microphone signal before it is fed into a [`BeatDetector`].
+///
+/// This is an adaptive filter (normalized LMS) with `L` taps that learns how
+/// the reference signal, i.e., the audio that the system plays, arrives at
+/// the microphone and subtracts that echo. The reference must be aligned with
+/// the microphone up to the length of the filter: the echo may arrive at most
+/// `L` samples after the reference sample. Hence, compensate the latencies of
+/// the audio devices before, if necessary.
+///
+/// The filter needs a moment to converge after the reference signal becomes
+/// audible. Other than muting the detector (see [`BeatDetector::mute_for`]),
+/// beats in the music are still detected while the own output plays.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BeatDetector, EchoCanceller};
+/// let mic_samples = [0, 500, -800, 700 /*, ... */];
+/// let played_samples = [0, 200, -300, 100 /*, ... */];
+/// let mut canceller = EchoCanceller::<256>::new();
+/// let mut detector = BeatDetector::new(44100.0, true);
+///
+/// // TODO regularly call this with the latest audio data.
+/// let is_beat = detector.update_and_detect_beat(canceller.cancel_iter(
+///     mic_samples.iter().copied(),
+///     played_samples.iter().copied(),
+/// ));
+/// ```
+///
+/// [`BeatDetector`]: crate::BeatDetector
+/// [`BeatDetector::mute_for`]: crate::BeatDetectorConst::mute_for
+#[derive(Debug, Clone)]
+pub struct EchoCanceller<const L: usize = DEFAULT_ECHO_TAPS> {
+    weights: [f32; L],
+    /// The latest reference samples. The newest one is at `pos`.
+    reference: [f32; L],
+    pos: usize,
+    /// Sum of the squares of `reference`.
+    energy: f32,
+    step_size: f32,
+}
+
+impl<const L: usize> EchoCanceller<L> {
+    /// Creates a new echo canceller that didn't learn anything yet.
+    pub const fn new() -> Self {
+        if L == 0 {
+            panic!("the filter needs at least one tap");
+        }
+        Self {
+            weights: [0.0; L],
+            reference: [0.0; L],
+            pos: 0,
+            energy: 0.0,
+            step_size: DEFAULT_ECHO_STEP_SIZE,
+        }
+    }
+
+    /// Returns the step size of the adaptation.
+    pub const fn step_size(&self) -> f32 {
+        self.step_size
+    }
+
+    /// Sets how fast the filter adapts. Larger values converge faster but
+    /// are disturbed more by the music that the microphone hears at the same
+    /// time. The default is [`DEFAULT_ECHO_STEP_SIZE`].
+    ///
+    /// Range: `0.0..=1.0`
+    pub fn set_step_size(&mut self, step_size: f32) {
+        assert!(
+            step_size > 0.0 && step_size <= 1.0,
+            "The step size must be in range 0.0..=1.0"
+        );
+        self.step_size = step_size;
+    }
+
+    /// Forgets everything that the filter learned, such as when the speakers
+    /// or the microphone moved.
+    pub fn reset(&mut self) {
+        *self = Self {
+            step_size: self.step_size,
+            ..Self::new()
+        };
+    }
+
+    /// Removes the echo of the reference sample and of the previous reference
+    /// samples from the microphone sample, which both reflect the same point
+    /// in time.
+    #[inline]
+    pub fn process(&mut self, mic: i16, reference: i16) -> i16 {
+        let reference = reference as f32 / i16::MAX as f32;
+        self.pos = (self.pos + 1) % L;
+        let oldest = self.reference[self.pos];
+        self.reference[self.pos] = reference;
+        self.energy = (self.energy + reference * reference - oldest * oldest).max(0.0);
+
+        // The reference samples from the newest to the oldest.
+        let (newer, older) = self.reference.split_at(self.pos + 1);
+        let history = || newer.iter().rev().chain(older.iter().rev());
+
+        let echo = self
+            .weights
+            .iter()
+            .zip(history())
+            .map(|(weight, reference)| weight * reference)
+            .sum::<f32>();
+        let error = mic as f32 / i16::MAX as f32 - echo;
+
+        let step = self.step_size * error / (self.energy + REGULARIZATION);
+        for (weight, reference) in self.weights.iter_mut().zip(history()) {
+            *weight += step * reference;
+        }
+
+        crate::util::saturating_f32_to_i16(error * i16::MAX as f32)
+    }
+
+    /// Returns an iterator that removes the echo of the reference signal from
+    /// the microphone signal sample by sample. The iterator ends as soon as
+    /// one of both ends.
+    pub fn cancel_iter<'a>(
+        &'a mut self,
+        mic: impl Iterator<Item = i16> + 'a,
+        reference: impl Iterator<Item = i16> + 'a,
+    ) -> impl Iterator<Item = i16> + 'a {
+        mic.zip(reference)
+            .map(move |(mic, reference)| self.process(mic, reference))
+    }
+}
+
+impl<const L: usize> Default for EchoCanceller<L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BeatDetector;
+    use std::vec::Vec;
+
+    /// Deterministic white noise in range `-1.0..1.0`.
+    fn noise(len: usize) -> Vec<f32> {
+        let mut state = 0x1234_5678_u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    /// The echo of the signal in a small room: delayed and with a reflection.
+    fn echo(signal: &[i16]) -> Vec<i16> {
+        (0..signal.len())
+            .map(|i| {
+                let direct = i.checked_sub(10).map_or(0.0, |i| signal[i] as f32 * 0.6);
+                let reflection = i.checked_sub(40).map_or(0.0, |i| signal[i] as f32 * -0.3);
+                (direct + reflection) as i16
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[i16]) -> f32 {
+        let sum = samples
+            .iter()
+            .map(|&s| (s as f32) * (s as f32))
+            .sum::<f32>();
+        libm::sqrtf(sum / samples.len() as f32)
+    }
+
+    #[test]
+    fn cancels_echo() {
+        let reference = noise(44100)
+            .into_iter()
+            .map(|sample| (sample * 10000.0) as i16)
+            .collect::<Vec<_>>();
+        let mic = echo(&reference);
+        let mut canceller = EchoCanceller::<64>::new();
+        let residual = canceller
+            .cancel_iter(mic.iter().copied(), reference.iter().copied())
+            .collect::<Vec<_>>();
+
+        // After the filter converged, the echo is attenuated by more than
+        // 30 dB.
+        let attenuation = rms(&residual[22050..]) / rms(&mic[22050..]);
+        assert!(attenuation < 0.03, "{attenuation}");
+
+        canceller.reset();
+        assert_eq!(canceller.process(1000, 0), 1000);
+    }
+
+    #[test]
+    fn removes_beeps_but_keeps_beats() {
+        let (samples, header) = crate::test_utils::samples::holiday_long();
+        // Ignores the first second, in which the filter converges.
+        let detect = |samples: &[i16]| {
+            let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+            samples
+                .chunks(2048)
+                .filter_map(|chunk| detector.update_and_detect_beat(chunk.iter().copied()))
+                .map(|beat| beat.max.total_index)
+                .filter(|&index| index >= header.sample_rate as u64)
+                .collect::<Vec<_>>()
+        };
+        let expected = detect(&samples);
+
+        // Loud low beeps that the speakers play between the beats.
+        let beeps = (0..samples.len())
+            .map(|i| {
+                let t = (i % 11025) as f32 / 44100.0;
+                if t < 0.05 {
+                    libm::sinf(t * 70.0 * 2.0 * core::f32::consts::PI) * 20000.0
+                } else {
+                    0.0
+                }
+            })
+            .map(|sample| sample as i16)
+            .collect::<Vec<_>>();
+        let mic = samples
+            .iter()
+            .zip(echo(&beeps))
+            .map(|(&sample, echo)| sample.saturating_add(echo))
+            .collect::<Vec<_>>();
+        assert_ne!(detect(&mic), expected);
+
+        let mut canceller = EchoCanceller::<64>::new();
+        let cancelled = canceller
+            .cancel_iter(mic.iter().copied(), beeps.iter().copied())
+            .collect::<Vec<_>>();
+        assert_eq!(detect(&cancelled), expected);
+    }
+}
@@ -0,0 +1,317 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`EnergyBeatDetector`].
+
+/// Duration of a block whose energy is compared with the average energy.
+const BLOCK_DURATION_MS: u32 = 10;
+
+/// Approximate cutoff frequency of the one-pole lowpass filter.
+const LOWPASS_CUTOFF_HZ: u32 = 100;
+
+/// Approximate sampling frequency after downsampling. Beats are in the low
+/// frequencies, so this is plenty.
+const DOWNSAMPLED_FREQUENCY_HZ: u32 = 2000;
+
+/// Fixed-point shift of the state of the lowpass filter, so that small
+/// changes are not lost in the integer arithmetic.
+const LOWPASS_PRECISION_SHIFT: u32 = 8;
+
+/// The average energy follows the energy of each block with a weight of
+/// `1 / 2^AVERAGE_SHIFT`. With 10 ms blocks, this averages over roughly the
+/// last 640 ms.
+const AVERAGE_SHIFT: u32 = 6;
+
+/// Default for [`EnergyBeatDetector::set_threshold_x16`]: a block must have
+/// twice the average energy.
+pub const DEFAULT_ENERGY_THRESHOLD_X16: u32 = 32;
+
+/// Minimum time between two beats.
+const MIN_BEAT_DISTANCE_MS: u32 = 150;
+
+/// Default for [`EnergyBeatDetector::set_min_level_q15`]: 10 % of full
+/// scale.
+pub const DEFAULT_ENERGY_MIN_LEVEL_Q15: u16 = 3277;
+
+/// A beat found by the [`EnergyBeatDetector`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct EnergyBeat {
+    /// Index of the last sample of the block that contains the beat, in the
+    /// audio that was passed to the detector.
+    pub total_index: u64,
+    /// Time of [`Self::total_index`] in milliseconds since the detector was
+    /// created.
+    pub timestamp_ms: u64,
+    /// Mean square of the lowpassed samples of the block.
+    pub energy: u32,
+}
+
+/// Minimal beat detector for tiny microcontrollers, such as ATtiny- or
+/// CH32-class targets, where even the [`BeatDetector`] is too heavy.
+///
+/// Other than the [`BeatDetector`], it doesn't look at envelopes and peaks.
+/// The audio is lowpassed with a one-pole filter, downsampled to roughly
+/// 2 kHz, and split into blocks of 10 ms. A block is a beat if its energy
+/// clearly exceeds the average energy of the previous blocks. Everything is
+/// computed with integers and the detector only needs a few bytes of memory.
+/// Each block costs a few hundred cycles.
+///
+/// This is much less precise than the [`BeatDetector`]: beats are only found
+/// with the granularity of a block and everything loud in the low frequencies
+/// may count as a beat.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::EnergyBeatDetector;
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = EnergyBeatDetector::new(16000);
+///
+/// // TODO regularly call this with the latest audio data.
+/// let beat = detector.update_and_detect_beat(mono_samples.iter().copied());
+/// ```
+///
+/// [`BeatDetector`]: crate::BeatDetector
+#[derive(Debug, Clone)]
+pub struct EnergyBeatDetector {
+    sampling_frequency_hz: u32,
+    /// Shift that implements the coefficient of the lowpass filter.
+    lowpass_shift: u32,
+    /// State of the lowpass filter, scaled by [`LOWPASS_PRECISION_SHIFT`].
+    lowpass_state: i32,
+    /// Only every n-th sample is kept.
+    downsample_factor: u32,
+    downsample_phase: u32,
+    /// Amount of downsampled samples in a block.
+    block_len: u32,
+    /// Sum of the squared samples of the current block.
+    block_sum: u64,
+    block_count: u32,
+    /// Average mean square of the previous blocks.
+    average: u32,
+    threshold_x16: u32,
+    /// Minimum mean square of a beat block. Quieter blocks are considered as
+    /// noise.
+    min_energy: u32,
+    /// Blocks that must pass after a beat until the next beat.
+    min_beat_distance_blocks: u32,
+    blocks_since_beat: u32,
+    /// Blocks until the average is meaningful.
+    warm_up_blocks: u32,
+    total_index: u64,
+}
+
+impl EnergyBeatDetector {
+    /// Creates a new detector for audio with the given sampling frequency.
+    pub fn new(sampling_frequency_hz: u32) -> Self {
+        assert!(sampling_frequency_hz >= DOWNSAMPLED_FREQUENCY_HZ);
+        // The coefficient of a one-pole lowpass filter is roughly
+        // `2π * fc / fs`. Round it to a power of two.
+        let lowpass_shift = (sampling_frequency_hz * 1000 / (6283 * LOWPASS_CUTOFF_HZ))
+            .max(1)
+            .ilog2();
+        let downsample_factor = sampling_frequency_hz / DOWNSAMPLED_FREQUENCY_HZ;
+        let block_len =
+            (sampling_frequency_hz / downsample_factor * BLOCK_DURATION_MS / 1000).max(1);
+        Self {
+            sampling_frequency_hz,
+            lowpass_shift,
+            lowpass_state: 0,
+            downsample_factor,
+            downsample_phase: 0,
+            block_len,
+            block_sum: 0,
+            block_count: 0,
+            average: 0,
+            threshold_x16: DEFAULT_ENERGY_THRESHOLD_X16,
+            min_energy: min_energy(DEFAULT_ENERGY_MIN_LEVEL_Q15),
+            min_beat_distance_blocks: MIN_BEAT_DISTANCE_MS / BLOCK_DURATION_MS,
+            blocks_since_beat: u32::MAX,
+            warm_up_blocks: 1 << AVERAGE_SHIFT,
+            total_index: 0,
+        }
+    }
+
+    /// Returns the sampling frequency of the audio in Hz.
+    pub const fn sampling_frequency_hz(&self) -> u32 {
+        self.sampling_frequency_hz
+    }
+
+    /// Returns how much the energy of a block must exceed the average
+    /// energy, in sixteenths.
+    pub const fn threshold_x16(&self) -> u32 {
+        self.threshold_x16
+    }
+
+    /// Sets how much the energy of a block must exceed the average energy to
+    /// be a beat, in sixteenths. For example, `24` means 1.5 times the
+    /// average energy. The default is [`DEFAULT_ENERGY_THRESHOLD_X16`]. Lower
+    /// values detect more beats but also more false positives.
+    pub fn set_threshold_x16(&mut self, threshold_x16: u32) {
+        assert!(
+            threshold_x16 > 16,
+            "the threshold must be above the average"
+        );
+        self.threshold_x16 = threshold_x16;
+    }
+
+    /// Sets the minimum level of a beat as amplitude of a sine in Q15, i.e.,
+    /// relative to `i16::MAX`. Quieter audio is considered as noise. The
+    /// default is [`DEFAULT_ENERGY_MIN_LEVEL_Q15`].
+    pub fn set_min_level_q15(&mut self, level_q15: u16) {
+        self.min_energy = min_energy(level_q15);
+    }
+
+    /// Consumes the latest audio data and returns the first beat in it, if
+    /// any. Like the [`BeatDetector`], this is supposed to be called with
+    /// small chunks of audio.
+    ///
+    /// [`BeatDetector`]: crate::BeatDetector
+    pub fn update_and_detect_beat(
+        &mut self,
+        mono_samples_iter: impl Iterator<Item = i16>,
+    ) -> Option<EnergyBeat> {
+        let mut beat = None;
+        for sample in mono_samples_iter {
+            let sample = (sample as i32) << LOWPASS_PRECISION_SHIFT;
+            self.lowpass_state += (sample - self.lowpass_state) >> self.lowpass_shift;
+            self.total_index += 1;
+
+            self.downsample_phase += 1;
+            if self.downsample_phase < self.downsample_factor {
+                continue;
+            }
+            self.downsample_phase = 0;
+
+            let sample = (self.lowpass_state >> LOWPASS_PRECISION_SHIFT) as i64;
+            self.block_sum += (sample * sample) as u64;
+            self.block_count += 1;
+            if self.block_count == self.block_len {
+                let is_beat = self.finish_block();
+                if is_beat && beat.is_none() {
+                    let total_index = self.total_index - 1;
+                    beat = Some(EnergyBeat {
+                        total_index,
+                        timestamp_ms: total_index * 1000 / self.sampling_frequency_hz as u64,
+                        energy: self.average_energy_of_block(),
+                    });
+                }
+                self.block_sum = 0;
+                self.block_count = 0;
+            }
+        }
+        beat
+    }
+
+    const fn average_energy_of_block(&self) -> u32 {
+        (self.block_sum / self.block_count as u64) as u32
+    }
+
+    /// Compares the energy of the completed block with the average energy and
+    /// updates the average afterwards. Returns whether the block is a beat.
+    fn finish_block(&mut self) -> bool {
+        let energy = self.average_energy_of_block();
+        self.blocks_since_beat = self.blocks_since_beat.saturating_add(1);
+
+        let is_beat = self.warm_up_blocks == 0
+            && self.blocks_since_beat >= self.min_beat_distance_blocks
+            && energy >= self.min_energy
+            && energy as u64 * 16 >= self.average as u64 * self.threshold_x16 as u64;
+        if is_beat {
+            self.blocks_since_beat = 0;
+        }
+
+        self.warm_up_blocks = self.warm_up_blocks.saturating_sub(1);
+        let average = self.average as i64;
+        self.average = (average + ((energy as i64 - average) >> AVERAGE_SHIFT)) as u32;
+        is_beat
+    }
+}
+
+/// Returns the mean square of a sine with the given amplitude.
+const fn min_energy(level_q15: u16) -> u32 {
+    (level_q15 as u32 * level_q15 as u32) / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use std::vec::Vec;
+
+    fn detect(samples: &[i16], sampling_frequency_hz: u32) -> Vec<u64> {
+        let mut detector = EnergyBeatDetector::new(sampling_frequency_hz);
+        samples
+            .chunks(256)
+            .filter_map(|chunk| detector.update_and_detect_beat(chunk.iter().copied()))
+            .map(|beat| beat.total_index)
+            .collect()
+    }
+
+    #[test]
+    fn energy_detector_finds_beats() {
+        // The beats are found slightly earlier than by the `BeatDetector`, as
+        // the energy already rises at the begin of the envelope. There are
+        // two false positives.
+        let (samples, header) = test_utils::samples::holiday_long();
+        assert_eq!(
+            detect(&samples, header.sample_rate),
+            &[29039, 37839, 47079, 65559, 83599, 101639, 119239, 138159]
+        );
+
+        // The first beat is missed during the warm-up.
+        let (samples, header) = test_utils::samples::sample1_long();
+        assert_eq!(
+            detect(&samples, header.sample_rate),
+            &[93279, 100759, 188759, 270159, 277639]
+        );
+    }
+
+    #[test]
+    fn energy_detector_timestamp_and_min_level() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut detector = EnergyBeatDetector::new(header.sample_rate);
+        let beat = samples
+            .chunks(256)
+            .find_map(|chunk| detector.update_and_detect_beat(chunk.iter().copied()))
+            .unwrap();
+        assert_eq!(beat.total_index, 29039);
+        assert_eq!(beat.timestamp_ms, 658);
+
+        let mut detector = EnergyBeatDetector::new(header.sample_rate);
+        detector.set_min_level_q15(i16::MAX as u16);
+        assert!(samples.chunks(256).all(|chunk| detector
+            .update_and_detect_beat(chunk.iter().copied())
+            .is_none()));
+    }
+
+    #[test]
+    fn energy_detector_ignores_constant_level() {
+        let samples = (0..44100 * 2)
+            .map(|i| (i as f32 * 60.0 * 2.0 * core::f32::consts::PI / 44100.0).sin())
+            .map(|sample| (sample * 20000.0) as i16)
+            .collect::<Vec<_>>();
+        assert_eq!(detect(&samples, 44100), &[]);
+    }
+}
@@ -0,0 +1,1134 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+use crate::audio_history::BUFFER_STORAGE_SIZE;
+use crate::decision_trace::Decision;
+use crate::peak_cache::{CachedPeaks, PeakCache, MAX_TRACKED_PEAKS};
+use crate::root_iterator::DEFAULT_SCAN_STRIDE;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::{AmplitudeHistogram, MaxMinIterator};
+use crate::{AudioHistory, SampleInfo, SnapshotError};
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+use core::time::Duration;
+
+/// Threshold to ignore noise.
+const ENVELOPE_MIN_VALUE: i16 = (i16::MAX as f32 * 0.1) as i16;
+
+/// Ratio between the maximum absolute peak and the median of all absolute
+/// peaks, so that we can be sure there is a clear envelope.
+const ENVELOPE_MAX_PEAK_TO_MEDIAN_MIN_RATIO: f32 = 2.0;
+
+/// Minimum sane duration of an envelope. This value comes from looking at
+/// waveforms of songs. I picked a beat that I considered as fast/short.
+pub(crate) const ENVELOPE_MIN_DURATION_MS: u64 = 140;
+
+/// Minimum realistic duration of an envelope. This value is the result of
+/// analyzing some waveforms in Audacity. Specifically, this results from an
+/// envelope of two beats very close to each other.
+const ENVELOPE_MIN_DURATION: Duration = Duration::from_millis(ENVELOPE_MIN_DURATION_MS);
+
+/// Default amount of peaks that decide whether the peaks after the maximum of
+/// an envelope are still descending.
+const DEFAULT_TREND_WINDOW: usize = 5;
+/// The current peak and at least two following peaks.
+const MIN_TREND_WINDOW: usize = 3;
+const MAX_TREND_WINDOW: usize = 16;
+
+/// Tuning parameters of the envelope search.
+///
+/// The defaults were tuned on a few songs. Use one of the presets or adjust
+/// single values if your audio source differs significantly. All values must
+/// be in the documented ranges.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EnvelopeConfig {
+    /// How long the audio after the begin of a possible envelope must be
+    /// before the envelope is analyzed. Shorter values reduce the latency but
+    /// may cut off the end of envelopes.
+    ///
+    /// Range: `10..=140` ms. The audio history doesn't hold enough audio for
+    /// longer durations.
+    pub min_duration: Duration,
+    /// Absolute amplitude below which peaks are considered as noise.
+    ///
+    /// Range: `0..=i16::MAX`.
+    pub min_value: i16,
+    /// Minimum ratio between the maximum peak of an envelope and the median
+    /// of all peaks in the audio history. Higher values only accept clearer
+    /// beats.
+    ///
+    /// Range: `1.0..=10.0`.
+    pub max_peak_to_median_min_ratio: f32,
+    /// Amount of peaks that are looked at to decide whether the peaks after
+    /// the maximum of an envelope are still descending. Higher values
+    /// tolerate more irregular decays but may merge an envelope with the
+    /// begin of the next one.
+    ///
+    /// Range: `3..=16`.
+    pub trend_window: usize,
+    /// Policy to merge envelopes that belong to the same musical event, such
+    /// as a double-triggered kick drum. `None` disables merging.
+    pub merge_policy: Option<MergePolicy>,
+}
+
+/// Policy to merge an envelope with the envelope that directly follows it.
+///
+/// Both envelopes are merged if the gap between them is shorter than
+/// [`Self::max_gap`] and the maximum of the second envelope is at most
+/// [`Self::max_peak_ratio`] times the maximum of the first envelope. The
+/// merged envelope keeps the maximum of the first envelope, so that the beat
+/// is reported at the first event.
+///
+/// The [`EnvelopeIterator`] merges all envelopes that are in the audio
+/// history. The [`BeatDetector`] doesn't wait for a follow-up envelope.
+/// Instead, it reports the first envelope right away and doesn't report the
+/// follow-up envelope as a beat.
+///
+/// [`BeatDetector`]: crate::BeatDetector
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MergePolicy {
+    /// Maximum duration between the end of an envelope and the begin of the
+    /// next envelope.
+    ///
+    /// Range: `0..=140` ms.
+    pub max_gap: Duration,
+    /// Maximum ratio between the maximum peak of the second envelope and the
+    /// maximum peak of the first envelope.
+    ///
+    /// Range: `0.0..=2.0`.
+    pub max_peak_ratio: f32,
+}
+
+impl MergePolicy {
+    /// Returns true if `next` directly follows `envelope` and belongs to the
+    /// same musical event.
+    pub(crate) fn should_merge(
+        &self,
+        envelope: &EnvelopeInfo,
+        next: &EnvelopeInfo,
+        sampling_frequency: f32,
+    ) -> bool {
+        let gap_samples = next
+            .from
+            .total_index
+            .saturating_sub(envelope.to.total_index);
+        let max_gap_samples = self.max_gap.as_secs_f32() * sampling_frequency;
+        let peak_ratio = next.max.value_abs as f32 / envelope.max.value_abs as f32;
+        next.from.total_index >= envelope.to.total_index
+            && gap_samples as f32 <= max_gap_samples
+            && peak_ratio <= self.max_peak_ratio
+    }
+}
+
+impl EnvelopeConfig {
+    /// The default configuration, suited for typical music.
+    pub const DEFAULT: Self = Self {
+        min_duration: ENVELOPE_MIN_DURATION,
+        min_value: ENVELOPE_MIN_VALUE,
+        max_peak_to_median_min_ratio: ENVELOPE_MAX_PEAK_TO_MEDIAN_MIN_RATIO,
+        trend_window: DEFAULT_TREND_WINDOW,
+        merge_policy: None,
+    };
+
+    /// Preset for quiet input, such as a microphone that is far away from the
+    /// speakers. Beats are detected at lower amplitudes and with less
+    /// contrast, at the cost of more false positives.
+    pub const SENSITIVE: Self = Self {
+        min_duration: ENVELOPE_MIN_DURATION,
+        min_value: ENVELOPE_MIN_VALUE / 4,
+        max_peak_to_median_min_ratio: 1.5,
+        trend_window: DEFAULT_TREND_WINDOW,
+        merge_policy: None,
+    };
+
+    /// Preset for loud input with a lot of background noise, such as a
+    /// crowded venue. Only very clear beats are detected.
+    pub const STRICT: Self = Self {
+        min_duration: ENVELOPE_MIN_DURATION,
+        min_value: ENVELOPE_MIN_VALUE * 2,
+        max_peak_to_median_min_ratio: 3.0,
+        trend_window: DEFAULT_TREND_WINDOW,
+        merge_policy: None,
+    };
+
+    /// Returns whether all values are in their documented range. See
+    /// [`Self::check`] for a message about the violated range.
+    pub(crate) fn is_valid(&self) -> bool {
+        (10..=ENVELOPE_MIN_DURATION_MS as u128).contains(&self.min_duration.as_millis())
+            && self.min_value >= 0
+            && (1.0..=10.0).contains(&self.max_peak_to_median_min_ratio)
+            && (MIN_TREND_WINDOW..=MAX_TREND_WINDOW).contains(&self.trend_window)
+            && self.merge_policy.map_or(true, |policy| {
+                policy.max_gap <= ENVELOPE_MIN_DURATION
+                    && (0.0..=2.0).contains(&policy.max_peak_ratio)
+            })
+    }
+
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.duration(self.min_duration);
+        writer.i16(self.min_value);
+        writer.f32(self.max_peak_to_median_min_ratio);
+        writer.u32(self.trend_window as u32);
+        writer.option(self.merge_policy, |writer, policy| {
+            writer.duration(policy.max_gap);
+            writer.f32(policy.max_peak_ratio);
+        });
+    }
+
+    pub(crate) fn read_snapshot(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let config = Self {
+            min_duration: reader.duration()?,
+            min_value: reader.i16()?,
+            max_peak_to_median_min_ratio: reader.f32()?,
+            trend_window: reader.u32()? as usize,
+            merge_policy: reader.option(|reader| {
+                Ok(MergePolicy {
+                    max_gap: reader.duration()?,
+                    max_peak_ratio: reader.f32()?,
+                })
+            })?,
+        };
+        SnapshotReader::check(config.is_valid())?;
+        Ok(config)
+    }
+
+    /// Panics if a value is out of its documented range.
+    pub(crate) fn check(&self) {
+        let min_duration_ms = self.min_duration.as_millis();
+        assert!(
+            (10..=ENVELOPE_MIN_DURATION_MS as u128).contains(&min_duration_ms),
+            "min_duration must be in range 10..={ENVELOPE_MIN_DURATION_MS} ms"
+        );
+        assert!(self.min_value >= 0, "min_value must not be negative");
+        assert!(
+            (1.0..=10.0).contains(&self.max_peak_to_median_min_ratio),
+            "max_peak_to_median_min_ratio must be in range 1.0..=10.0"
+        );
+        assert!(
+            (MIN_TREND_WINDOW..=MAX_TREND_WINDOW).contains(&self.trend_window),
+            "trend_window must be in range {MIN_TREND_WINDOW}..={MAX_TREND_WINDOW}"
+        );
+        if let Some(policy) = self.merge_policy {
+            assert!(
+                policy.max_gap <= ENVELOPE_MIN_DURATION,
+                "max_gap must be in range 0..={ENVELOPE_MIN_DURATION_MS} ms"
+            );
+            assert!(
+                (0.0..=2.0).contains(&policy.max_peak_ratio),
+                "max_peak_ratio must be in range 0.0..=2.0"
+            );
+        }
+    }
+}
+
+impl Default for EnvelopeConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Iterates the envelopes of the provided audio history. An envelope is the set
+/// of vibrations(? - german: Schwingungen) that characterize a beat. Its
+/// waveform looks somehow like this:
+/// ```text
+///         x
+///        x x       x
+///       x   x     x x   x
+/// -x---x-----x---x---x-x-x----- (and again for next beat)
+///   x x       x x     x
+///    x         x
+/// ```
+///
+/// The properties to detect an envelope are not based on scientific research,
+/// but on a best-effort and common sense from my side.
+///
+/// The envelopes are returned in ascending order and never overlap.
+///
+/// This iterator is supposed to be used multiple times on the same audio
+/// history object. However, once the audio history was updated, a new iterator
+/// must be created.
+#[derive(Debug, Clone)]
+pub struct EnvelopeIterator<
+    'a,
+    const N: usize = BUFFER_STORAGE_SIZE,
+    const P: usize = MAX_TRACKED_PEAKS,
+> {
+    index: usize,
+    /// Index where a later search can resume without missing an envelope that
+    /// is not complete yet. See [`Self::resume_index`].
+    resume_index: usize,
+    buffer: &'a AudioHistory<N>,
+    scan_stride: usize,
+    /// Cache of the peaks in the audio history. If `None`, the peaks are
+    /// found by scanning the audio history.
+    peak_cache: Option<&'a PeakCache<P>>,
+    config: EnvelopeConfig,
+    /// Lower limit of the median of the peaks. See [`Self::with_median_floor`].
+    median_floor: i16,
+    /// Why the latest search didn't find an envelope.
+    rejection: Option<Decision>,
+}
+
+impl<'a, const N: usize> EnvelopeIterator<'a, N> {
+    pub fn new(buffer: &'a AudioHistory<N>, begin_index: Option<usize>) -> Self {
+        Self::with_scan_stride(buffer, begin_index, DEFAULT_SCAN_STRIDE)
+    }
+
+    /// Like [`Self::new`] but with a custom amount of samples to advance per
+    /// step when scanning the audio history for peaks. A higher value means
+    /// less precision but also fewer iterations.
+    pub fn with_scan_stride(
+        buffer: &'a AudioHistory<N>,
+        begin_index: Option<usize>,
+        scan_stride: usize,
+    ) -> Self {
+        let index = begin_index.unwrap_or(0);
+        assert!(index < buffer.len());
+        assert!(scan_stride > 0);
+        Self {
+            buffer,
+            index,
+            resume_index: index,
+            scan_stride,
+            peak_cache: None,
+            config: EnvelopeConfig::DEFAULT,
+            median_floor: 0,
+            rejection: None,
+        }
+    }
+
+    /// Uses the peaks of the given cache instead of scanning the audio
+    /// history. The cache must be up-to-date with the audio history.
+    pub(crate) const fn with_peak_cache<const P: usize>(
+        self,
+        peak_cache: &'a PeakCache<P>,
+    ) -> EnvelopeIterator<'a, N, P> {
+        EnvelopeIterator {
+            index: self.index,
+            resume_index: self.resume_index,
+            buffer: self.buffer,
+            scan_stride: self.scan_stride,
+            peak_cache: Some(peak_cache),
+            config: self.config,
+            median_floor: self.median_floor,
+            rejection: self.rejection,
+        }
+    }
+}
+
+impl<'a, const N: usize, const P: usize> EnvelopeIterator<'a, N, P> {
+    /// Uses the given configuration instead of [`EnvelopeConfig::DEFAULT`].
+    pub fn with_config(mut self, config: EnvelopeConfig) -> Self {
+        config.check();
+        self.config = config;
+        self
+    }
+
+    /// Compares the peaks with at least the given median, even if the median
+    /// of the peaks in the audio history is lower. The [`BeatDetector`] uses
+    /// this to remember the level of the audio beyond the audio history.
+    ///
+    /// [`BeatDetector`]: crate::BeatDetector
+    pub(crate) const fn with_median_floor(mut self, median_floor: i16) -> Self {
+        self.median_floor = median_floor;
+        self
+    }
+
+    /// Returns the index where a search on an updated audio history can begin
+    /// without missing an envelope. Everything before is either noise or
+    /// belongs to an envelope that was already returned.
+    pub(crate) const fn resume_index(&self) -> usize {
+        self.resume_index
+    }
+
+    /// Returns why the latest search didn't find an envelope.
+    pub(crate) const fn rejection(&self) -> Option<Decision> {
+        self.rejection
+    }
+
+    /// Calculates the median of all peaks in the audio history.
+    fn calc_peaks_median(&self) -> Option<i16> {
+        self.peaks(None)
+            .map(|info| info.value_abs)
+            .collect::<AmplitudeHistogram>()
+            .median()
+    }
+
+    /// Creates a peak iterator, either over the cached peaks or with the
+    /// stride of this iterator.
+    fn peaks(&self, begin_index: Option<usize>) -> Peaks<'a, N, P> {
+        self.peak_cache.map_or_else(
+            || {
+                Peaks::Scanned(MaxMinIterator::new(
+                    self.buffer,
+                    begin_index,
+                    self.scan_stride,
+                ))
+            },
+            |cache| Peaks::Cached(cache.peaks(self.buffer, begin_index)),
+        )
+    }
+}
+
+impl<const N: usize, const P: usize> Iterator for EnvelopeIterator<'_, N, P> {
+    type Item = EnvelopeInfo;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut envelope = self.find_envelope()?;
+        let Some(policy) = self.config.merge_policy else {
+            return Some(envelope);
+        };
+
+        loop {
+            let (index, resume_index) = (self.index, self.resume_index);
+            match self.find_envelope() {
+                Some(next)
+                    if policy.should_merge(&envelope, &next, self.buffer.sampling_frequency()) =>
+                {
+                    envelope.to = next.to;
+                }
+                _ => {
+                    // The next envelope is found again by the next invocation.
+                    self.index = index;
+                    self.resume_index = resume_index;
+                    return Some(envelope);
+                }
+            }
+        }
+    }
+}
+
+impl<const N: usize, const P: usize> EnvelopeIterator<'_, N, P> {
+    /// Finds the next envelope, without merging it with following envelopes.
+    fn find_envelope(&mut self) -> Option<EnvelopeInfo> {
+        let envelope = self.try_find_envelope();
+        self.rejection = envelope.err();
+        envelope.ok()
+    }
+
+    /// Like [`Self::find_envelope`], but returns why no envelope was found.
+    fn try_find_envelope(&mut self) -> Result<EnvelopeInfo, Decision> {
+        debug_assert!(self.index < self.buffer.len());
+        if self.index == self.buffer.len() - 1 {
+            return Err(Decision::NothingNew);
+        }
+
+        // #####################################################################
+        // PREREQUISITES
+
+        // Skip noise. Restarting the search at the last skipped noise peak
+        // finds the same envelope begin again.
+        let mut peaks = self.peaks(Some(self.index));
+        let envelope_begin = loop {
+            let info = peaks.next().ok_or(Decision::NoiseGate)?;
+            if info.value_abs >= self.config.min_value {
+                break info;
+            }
+            self.resume_index = info.index;
+        };
+
+        // Update index to prevent unnecessary iterations on next
+        // invocation.
+        self.index = envelope_begin.index + 1;
+
+        // First check. Is the (possible) envelope begin far enough behind to
+        // actually point to an
+        if envelope_begin.duration_behind <= self.config.min_duration {
+            return Err(Decision::TooRecent);
+        }
+
+        // #####################################################################
+        // FIND ENVELOPE
+
+        // Find median. Other than the average, it is not distorted by a few
+        // very loud peaks.
+        let peaks_median = self
+            .peak_cache
+            .map_or_else(
+                || self.calc_peaks_median(),
+                |cache| cache.histogram().median(),
+            )
+            .ok_or(Decision::InsufficientHistory)?
+            .max(self.median_floor);
+
+        // Sanity checks.
+        debug_assert!(peaks_median > 0);
+
+        // Find max of envelope.
+        let min_ratio = self.config.max_peak_to_median_min_ratio;
+        let envelope_max = self
+            .peaks(Some(envelope_begin.index + 1))
+            // ignore irrelevant peaks
+            .skip_while(|info| (info.value_abs as f32 / peaks_median as f32) < min_ratio)
+            // look at interesting peaks
+            .take_while(|info| (info.value_abs as f32 / peaks_median as f32) >= min_ratio)
+            // get the maximum
+            .reduce(|a, b| if a.value_abs > b.value_abs { a } else { b })
+            .ok_or(Decision::RatioBelowThreshold)?;
+
+        // Find end of envelope.
+        let envelope_end = find_descending_peak_trend_end(
+            self.peaks(Some(envelope_max.index)),
+            self.config.trend_window,
+        )
+        .ok_or(Decision::EnvelopeRejected)?;
+
+        // #####################################################################
+        // FINALIZE
+
+        let envelope = EnvelopeInfo {
+            from: envelope_begin,
+            to: envelope_end,
+            max: envelope_max,
+            dominant_frequency_hz: dominant_frequency_hz(
+                self.buffer,
+                envelope_begin.index,
+                envelope_end.index,
+            ),
+            stereo_balance: None,
+        };
+
+        // TODO do I need this?
+        /*if envelope.duration() < ENVELOPE_MIN_DURATION {
+            return None;
+        }*/
+
+        // Update index to prevent unnecessary iterations on next
+        // invocation.
+        self.index = envelope_end.index + 1;
+        self.resume_index = envelope_end.index;
+
+        Ok(envelope)
+    }
+}
+
+/// Estimates the dominant frequency of the audio between the given indices
+/// of the history from its zero crossings. The crossings are interpolated
+/// between samples, so that low frequencies are precise even in short
+/// envelopes. Returns `None` for less than a full period.
+fn dominant_frequency_hz<const N: usize>(
+    history: &AudioHistory<N>,
+    begin_index: usize,
+    end_index: usize,
+) -> Option<f32> {
+    let mut first_crossing = None;
+    let mut last_crossing = 0.0;
+    let mut crossings = 0;
+    let mut previous = None;
+    let samples = history.samples().enumerate();
+    for (index, &sample) in samples.skip(begin_index).take(end_index + 1 - begin_index) {
+        if let Some(previous) = previous.filter(|&previous: &i16| (previous < 0) != (sample < 0)) {
+            let fraction = previous as f32 / (previous as f32 - sample as f32);
+            last_crossing = (index - 1) as f32 + fraction;
+            first_crossing.get_or_insert(last_crossing);
+            crossings += 1;
+        }
+        previous = Some(sample);
+    }
+    let duration_samples = last_crossing - first_crossing?;
+    // Two crossings per period.
+    let periods = (crossings - 1) as f32 / 2.0;
+    (periods >= 1.0).then(|| periods * history.sampling_frequency() / duration_samples)
+}
+
+/// Helper to find the end of an envelope.
+/// Finds the end of an envelope. This itself turned out as complex enough to
+/// justify a dedicated, testable function. An envelope ends when the trend of
+/// descending (abs) peaks is over. We must prevent that the envelope end
+/// clashes with the beginning of the possibly next envelope.
+///
+/// Real music doesn't decay monotonically. Therefore, a peak that is slightly
+/// higher than its predecessor doesn't end the trend, as long as the
+/// `trend_window` peaks ahead are still descending overall, i.e., the slope of
+/// their regression line is not positive. Close to the end of the audio
+/// history, the window shrinks down to [`MIN_TREND_WINDOW`].
+///
+/// The provided peak iterator is supposed to begin at the maximum of the
+/// envelope.
+fn find_descending_peak_trend_end(
+    mut peak_iter: impl Iterator<Item = SampleInfo> + Clone,
+    trend_window: usize,
+) -> Option<SampleInfo> {
+    // We allow peaks to be out of line within a trend of descending peaks.
+    // But only within this reasonable limit.
+    const MAX_NEXT_TO_CURR_OUT_OF_LINE_FACTOR: f32 = 1.05;
+    debug_assert!((MIN_TREND_WINDOW..=MAX_TREND_WINDOW).contains(&trend_window));
+
+    let mut end = None;
+    while let Some(current) = peak_iter.next() {
+        let mut window = [0; MAX_TREND_WINDOW];
+        window[0] = current.value_abs;
+        let mut len = 1;
+        for (value, peak) in window[1..trend_window].iter_mut().zip(peak_iter.clone()) {
+            *value = peak.value_abs;
+            len += 1;
+        }
+        if len < MIN_TREND_WINDOW {
+            break;
+        }
+        let window = &window[..len];
+
+        let next_is_descending = window[1] <= window[0];
+        let next_is_tolerated = window[1] as f32 / window[0] as f32
+            <= MAX_NEXT_TO_CURR_OUT_OF_LINE_FACTOR
+            && regression_slope(window) <= 0.0;
+        if !next_is_descending && !next_is_tolerated {
+            break;
+        }
+        end = Some(current);
+    }
+    end
+}
+
+/// Returns the slope of the least squares regression line through the
+/// values, with their positions as x coordinates.
+fn regression_slope(values: &[i16]) -> f32 {
+    let len = values.len() as f32;
+    let x_mean = (len - 1.0) / 2.0;
+    let y_mean = values.iter().map(|&value| value as f32).sum::<f32>() / len;
+    let (covariance, variance) =
+        values
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(covariance, variance), (x, &y)| {
+                let dx = x as f32 - x_mean;
+                (covariance + dx * (y as f32 - y_mean), variance + dx * dx)
+            });
+    covariance / variance
+}
+
+/// Iterator over the peaks of the audio history. See
+/// [`EnvelopeIterator::peaks`].
+#[derive(Debug, Clone)]
+enum Peaks<'a, const N: usize, const P: usize> {
+    Scanned(MaxMinIterator<'a, N>),
+    Cached(CachedPeaks<'a, N, P>),
+}
+
+impl<const N: usize, const P: usize> Iterator for Peaks<'_, N, P> {
+    type Item = SampleInfo;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Scanned(iter) => iter.next(),
+            Self::Cached(iter) => iter.next(),
+        }
+    }
+}
+
+/// Information about an envelope.
+///
+/// Two envelopes are equal if they begin, peak, and end at the same
+/// [`SampleInfo::total_index`]. They are ordered by these positions in that
+/// order. Use [`Self::overlaps`] to check whether they share audio.
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct EnvelopeInfo {
+    pub from: SampleInfo,
+    pub to: SampleInfo,
+    pub max: SampleInfo,
+    /// Dominant frequency of the audio of the envelope in Hz, estimated from
+    /// its zero crossings. This tells a 50 Hz sub kick from a 120 Hz tom.
+    /// `None` if the envelope doesn't span a full period, such as in an
+    /// envelope stream.
+    pub dominant_frequency_hz: Option<f32>,
+    /// Balance of the energy of the beat between the left (`-1.0`) and the
+    /// right (`1.0`) channel. Only for stereo input, see
+    /// [`StereoBeatDetector`].
+    ///
+    /// [`StereoBeatDetector`]: crate::StereoBeatDetector
+    pub stereo_balance: Option<f32>,
+}
+
+impl EnvelopeInfo {
+    /// Returns true if two envelopes overlap. The end of an envelope is
+    /// exclusive. This covers the following
+    /// scenarios:
+    /// ```text
+    /// Overlap 1:
+    /// |___|      or  |______|
+    ///   |___|          |__|
+    ///
+    /// Overlap 2:
+    ///   |___|          |__|
+    /// |___|      or  |______|
+    ///
+    /// Overlap 3:
+    /// |___|
+    /// |___|
+    ///
+    /// No Overlap 1:
+    ///       |___|
+    /// |___|
+    ///
+    /// No overlap 2:
+    /// |___|
+    ///       |___|
+    /// ```
+    pub const fn overlaps(&self, other: &Self) -> bool {
+        let self_from = self.from.total_index;
+        let self_to = self.to.total_index;
+        let other_from = other.from.total_index;
+        let other_to = other.to.total_index;
+
+        if other_from >= self_from {
+            other_from < self_to
+        } else if other_from < self_from {
+            other_to > self_from
+        } else {
+            false
+        }
+    }
+
+    /// Returns true if two envelopes overlap.
+    #[deprecated(note = "use `EnvelopeInfo::overlaps` instead")]
+    pub const fn overlap(&self, other: &Self) -> bool {
+        self.overlaps(other)
+    }
+
+    /// Returns the positions that identify the envelope.
+    const fn key(&self) -> (u64, u64, u64) {
+        (
+            self.from.total_index,
+            self.max.total_index,
+            self.to.total_index,
+        )
+    }
+
+    /// The duration/length of the envelope.
+    pub fn duration(&self) -> Duration {
+        self.to.timestamp - self.from.timestamp
+    }
+
+    /// The relative timestamp of the beat/the envelope since the beginning of
+    /// the audio recording. See [`SampleInfo::source`].
+    ///
+    /// This is the position of the maximum in the original audio. For beats
+    /// of the [`BeatDetector`], it compensates the downsampling and the delay
+    /// of the lowpass filter, which is about 2.4 ms at 44.1 kHz. Hence, it is
+    /// slightly earlier than [`SampleInfo::timestamp`] of [`Self::max`],
+    /// which refers to the processed audio. In 0.2, this returned the
+    /// latter.
+    ///
+    /// [`BeatDetector`]: crate::BeatDetector
+    pub const fn timestamp(&self) -> Duration {
+        self.max.source.time
+    }
+}
+
+impl EnvelopeInfo {
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        for info in [&self.from, &self.to, &self.max] {
+            info.write_snapshot(writer);
+        }
+        writer.option(self.dominant_frequency_hz, SnapshotWriter::f32);
+        writer.option(self.stereo_balance, SnapshotWriter::f32);
+    }
+
+    pub(crate) fn read_snapshot(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            from: SampleInfo::read_snapshot(reader)?,
+            to: SampleInfo::read_snapshot(reader)?,
+            max: SampleInfo::read_snapshot(reader)?,
+            dominant_frequency_hz: reader.option(SnapshotReader::f32)?,
+            stereo_balance: reader.option(SnapshotReader::f32)?,
+        })
+    }
+}
+
+impl PartialOrd for EnvelopeInfo {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for EnvelopeInfo {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialEq for EnvelopeInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for EnvelopeInfo {}
+
+impl Hash for EnvelopeInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use std::vec::Vec;
+
+    #[allow(clippy::cognitive_complexity)]
+    #[test]
+    fn envelope_info_overlaps() {
+        let mut this = EnvelopeInfo::default();
+        let mut that = EnvelopeInfo::default();
+
+        this.from.total_index = 0;
+        this.to.total_index = 10;
+
+        that.from.total_index = 11;
+        that.to.total_index = 20;
+
+        assert!(this.overlaps(&this));
+        assert!(that.overlaps(&that));
+
+        assert!(!this.overlaps(&that));
+        assert!(!that.overlaps(&this));
+
+        that.from.total_index = 10;
+        assert!(!this.overlaps(&that));
+        assert!(!that.overlaps(&this));
+
+        that.from.total_index = 9;
+        assert!(this.overlaps(&that));
+        assert!(that.overlaps(&this));
+
+        this.from.total_index = 10;
+        this.to.total_index = 20;
+        that.from.total_index = 10;
+        that.to.total_index = 20;
+        assert!(this.overlaps(&that));
+        assert!(that.overlaps(&this));
+
+        this.to.total_index = 16;
+        assert!(this.overlaps(&that));
+        assert!(that.overlaps(&this));
+
+        this.from.total_index = 10;
+        this.to.total_index = 20;
+        that.from.total_index = 0;
+        that.to.total_index = 10;
+        assert!(!this.overlaps(&that));
+        assert!(!that.overlaps(&this));
+
+        this.from.total_index = 10;
+        this.to.total_index = 20;
+        that.from.total_index = 5;
+        that.to.total_index = 15;
+        assert!(this.overlaps(&that));
+        assert!(that.overlaps(&this));
+    }
+
+    #[test]
+    fn envelope_info_identity() {
+        let mut this = EnvelopeInfo::default();
+        this.from.total_index = 0;
+        this.max.total_index = 5;
+        this.to.total_index = 10;
+
+        // Overlapping envelopes are not necessarily equal.
+        let mut that = this;
+        that.to.total_index = 12;
+        assert!(this.overlaps(&that));
+        assert_ne!(this, that);
+        assert!(this < that);
+
+        let mut later = this;
+        later.from.total_index = 1;
+        assert!(that < later);
+
+        let set = [later, that, this, this]
+            .into_iter()
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), [this, that, later]);
+
+        let set = [this, that, this]
+            .into_iter()
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(set.len(), 2);
+    }
+
+    /// `==` used to mean `overlaps()`. Distinct envelopes must not be equal,
+    /// even if they overlap.
+    #[test]
+    fn overlapping_envelopes_are_not_equal() {
+        let mut this = EnvelopeInfo::default();
+        this.from.total_index = 100;
+        this.max.total_index = 150;
+        this.to.total_index = 200;
+        let mut that = EnvelopeInfo::default();
+        that.from.total_index = 180;
+        that.max.total_index = 220;
+        that.to.total_index = 300;
+
+        assert!(this.overlaps(&that));
+        assert!(that.overlaps(&this));
+        assert_ne!(this, that);
+        assert_ne!(this.cmp(&that), Ordering::Equal);
+        assert_eq!(this, this);
+    }
+
+    #[test]
+    fn find_descending_peak_trend_end_is_correct() {
+        // sample1: single beat
+        {
+            let (samples, header) = test_utils::samples::sample1_single_beat();
+            let mut history = AudioHistory::new(header.sample_rate as f32);
+            history.update(samples.iter().copied());
+
+            // Taken from waveform in Audacity.
+            let peak_sample_index = 1430;
+            assert_eq!(
+                find_descending_peak_trend_end(
+                    MaxMinIterator::new(&history, Some(peak_sample_index), DEFAULT_SCAN_STRIDE),
+                    DEFAULT_TREND_WINDOW
+                )
+                .map(|info| info.index),
+                Some(7099)
+            )
+        }
+        // sample1: double beat
+        {
+            let (samples, header) = test_utils::samples::sample1_double_beat();
+            let mut history = AudioHistory::new(header.sample_rate as f32);
+            history.update(samples.iter().copied());
+
+            // Taken from waveform in Audacity.
+            let peak_sample_index = 1634;
+            assert_eq!(
+                find_descending_peak_trend_end(
+                    MaxMinIterator::new(&history, Some(peak_sample_index), DEFAULT_SCAN_STRIDE),
+                    DEFAULT_TREND_WINDOW
+                )
+                .map(|info| info.index),
+                Some(6983)
+            );
+
+            // The decay of the second beat has a small bump at 16900, which
+            // doesn't end the envelope. Three peaks were not enough to see
+            // that the decay continues.
+            let peak_sample_index = 8961;
+            assert_eq!(
+                find_descending_peak_trend_end(
+                    MaxMinIterator::new(&history, Some(peak_sample_index), DEFAULT_SCAN_STRIDE),
+                    DEFAULT_TREND_WINDOW
+                )
+                .map(|info| info.index),
+                Some(17270)
+            );
+            assert_eq!(
+                find_descending_peak_trend_end(
+                    MaxMinIterator::new(&history, Some(peak_sample_index), DEFAULT_SCAN_STRIDE),
+                    MIN_TREND_WINDOW
+                )
+                .map(|info| info.index),
+                Some(16140)
+            );
+        }
+        // holiday: single beat
+        // The decay is interrupted by a deep dip at 2289, after which the
+        // following notes rise again. The envelope ends before that.
+        {
+            let (samples, header) = test_utils::samples::holiday_single_beat();
+            let mut history = AudioHistory::new(header.sample_rate as f32);
+            history.update(samples.iter().copied());
+
+            // Taken from waveform in Audacity.
+            let peak_sample_index = 820;
+            assert_eq!(
+                find_descending_peak_trend_end(
+                    MaxMinIterator::new(&history, Some(peak_sample_index), DEFAULT_SCAN_STRIDE),
+                    DEFAULT_TREND_WINDOW
+                )
+                .map(|info| info.index),
+                Some(1969)
+            )
+        }
+    }
+
+    #[test]
+    fn find_descending_peak_trend_end_tolerates_bumps() {
+        let peaks = |values: &'static [i16]| {
+            values.iter().enumerate().map(|(index, &value)| SampleInfo {
+                value,
+                value_abs: value,
+                index,
+                ..Default::default()
+            })
+        };
+
+        // Bump at indices 3 and 4 within a longer decay. The peaks after
+        // index 6 belong to the next envelope.
+        let values = &[10000, 9000, 8000, 8300, 8200, 7000, 6000, 5000, 9000, 12000];
+        assert_eq!(
+            find_descending_peak_trend_end(peaks(values), DEFAULT_TREND_WINDOW)
+                .map(|info| info.index),
+            Some(6)
+        );
+        // With the minimal window, the bump is not tolerated.
+        assert_eq!(
+            find_descending_peak_trend_end(peaks(values), MIN_TREND_WINDOW).map(|info| info.index),
+            Some(1)
+        );
+
+        // Bumps that are too high always end the envelope.
+        let values = &[10000, 9000, 8000, 9000, 7000, 6000, 5000, 4000];
+        assert_eq!(
+            find_descending_peak_trend_end(peaks(values), DEFAULT_TREND_WINDOW)
+                .map(|info| info.index),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn regression_slope_is_correct() {
+        assert_eq!(regression_slope(&[1, 2, 3]), 1.0);
+        assert_eq!(regression_slope(&[30, 20, 10, 0]), -10.0);
+        assert_eq!(regression_slope(&[5, 5, 5]), 0.0);
+    }
+
+    #[test]
+    fn find_envelopes_sample1_single_beat() {
+        let (samples, header) = test_utils::samples::sample1_single_beat();
+        let mut history = AudioHistory::new(header.sample_rate as f32);
+        history.update(samples.iter().copied());
+
+        let envelopes = EnvelopeIterator::new(&history, None)
+            .take(1)
+            .map(|info| (info.from.index, info.to.index))
+            .collect::<Vec<_>>();
+        assert_eq!(&envelopes, &[(409, 7098)])
+    }
+
+    #[test]
+    fn find_envelopes_sample1_double_beat() {
+        let (samples, header) = test_utils::samples::sample1_double_beat();
+        let mut history = AudioHistory::new(header.sample_rate as f32);
+        history.update(samples.iter().copied());
+
+        let envelopes = EnvelopeIterator::new(&history, None)
+            .map(|info| (info.from.index, info.to.index))
+            .collect::<Vec<_>>();
+        #[rustfmt::skip]
+        assert_eq!(
+            &envelopes,
+            &[
+                (449, 6978),
+                (7328, 17267)
+            ]
+        );
+    }
+
+    #[test]
+    fn find_envelopes_sample1_double_beat_merged() {
+        let (samples, header) = test_utils::samples::sample1_double_beat();
+        let mut history = AudioHistory::new(header.sample_rate as f32);
+        history.update(samples.iter().copied());
+
+        let find = |max_peak_ratio| {
+            let config = EnvelopeConfig {
+                merge_policy: Some(MergePolicy {
+                    max_gap: Duration::from_millis(20),
+                    max_peak_ratio,
+                }),
+                ..EnvelopeConfig::DEFAULT
+            };
+            EnvelopeIterator::new(&history, None)
+                .with_config(config)
+                .map(|info| (info.from.index, info.max.index, info.to.index))
+                .collect::<Vec<_>>()
+        };
+        // The second beat is slightly louder than the first one.
+        assert_eq!(find(1.2), &[(449, 1309, 17267)]);
+        assert_eq!(find(1.0), &[(449, 1309, 6978), (7328, 8638, 17267)]);
+    }
+
+    #[test]
+    fn find_envelopes_holiday_single_beat() {
+        let (samples, header) = test_utils::samples::holiday_single_beat();
+        let mut history = AudioHistory::new(header.sample_rate as f32);
+        history.update(samples.iter().copied());
+
+        let envelopes = EnvelopeIterator::new(&history, None)
+            .map(|info| (info.from.index, info.to.index))
+            .collect::<Vec<_>>();
+        assert_eq!(&envelopes, &[(259, 1968)]);
+    }
+
+    #[test]
+    fn dominant_frequency() {
+        let mut history = AudioHistory::new(44100.0);
+        for frequency in [50.0, 120.0] {
+            // 100 ms of a decaying sine, like a kick drum or a tom.
+            history.update((0..4410).map(|i| {
+                let t = i as f32 / 44100.0;
+                let sine = libm::sinf(2.0 * core::f32::consts::PI * frequency * t);
+                (sine * libm::expf(-t * 20.0) * 20000.0) as i16
+            }));
+            let estimate = dominant_frequency_hz(&history, history.len() - 4410, history.len() - 1);
+            assert!(
+                (estimate.unwrap() - frequency).abs() < 1.0,
+                "{estimate:?} vs {frequency}"
+            );
+        }
+
+        // Less than a period.
+        assert_eq!(
+            dominant_frequency_hz(&history, history.len() - 300, history.len() - 1),
+            None
+        );
+        history.update([1000; 100].iter().copied());
+        assert_eq!(
+            dominant_frequency_hz(&history, history.len() - 100, history.len() - 1),
+            None
+        );
+    }
+
+    #[test]
+    fn dominant_frequency_of_kick_drum() {
+        let (samples, header) = test_utils::samples::holiday_single_beat();
+        let mut history = AudioHistory::new(header.sample_rate as f32);
+        history.update(samples.iter().copied());
+
+        let envelope = EnvelopeIterator::new(&history, None).next().unwrap();
+        let frequency = envelope.dominant_frequency_hz.unwrap();
+        assert!((40.0..=100.0).contains(&frequency), "{frequency}");
+    }
+
+    #[test]
+    fn envelopes_are_sorted_and_disjoint() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut history =
+            AudioHistory::<{ 1 << 18 }>::with_capacity(header.sample_rate as f32, samples.len());
+        history.update(samples.iter().copied());
+
+        let envelopes = EnvelopeIterator::new(&history, None).collect::<Vec<_>>();
+        assert!(envelopes.len() > 1);
+        assert!(envelopes
+            .windows(2)
+            .all(|w| w[0] < w[1] && !w[0].overlaps(&w[1])));
+    }
+}
@@ -0,0 +1,107 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`Error`].
+
+use crate::util::OutOfRangeError;
+use crate::{CustomFilterError, SnapshotError};
+use core::fmt::{Display, Formatter};
+
+/// Top-level error type of the crate that covers all failure classes of the
+/// detection pipeline.
+///
+/// All specific error types of the crate convert into this type via [`From`],
+/// so applications can use `?` and match on the failure class uniformly.
+/// `beat-detector-io` has an error type that additionally covers the failures
+/// of audio I/O.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An audio sample was out of the valid range.
+    SampleOutOfRange(OutOfRangeError),
+    /// A snapshot couldn't be created or loaded.
+    Snapshot(SnapshotError),
+    /// A custom filter is invalid.
+    CustomFilter(CustomFilterError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::SampleOutOfRange(err) => write!(f, "sample out of range: {err}"),
+            Self::Snapshot(err) => write!(f, "snapshot error: {err}"),
+            Self::CustomFilter(err) => write!(f, "invalid custom filter: {err}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::SampleOutOfRange(err) => Some(err),
+            Self::Snapshot(err) => Some(err),
+            Self::CustomFilter(err) => Some(err),
+        }
+    }
+}
+
+impl From<OutOfRangeError> for Error {
+    fn from(err: OutOfRangeError) -> Self {
+        Self::SampleOutOfRange(err)
+    }
+}
+
+impl From<SnapshotError> for Error {
+    fn from(err: SnapshotError) -> Self {
+        Self::Snapshot(err)
+    }
+}
+
+impl From<CustomFilterError> for Error {
+    fn from(err: CustomFilterError) -> Self {
+        Self::CustomFilter(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::f32_sample_to_i16;
+    use std::string::ToString;
+
+    #[test]
+    fn errors_convert_into_top_level_error() {
+        fn convert(val: f32) -> Result<i16, Error> {
+            Ok(f32_sample_to_i16(val)?)
+        }
+
+        let err = convert(2.0).unwrap_err();
+        assert!(matches!(err, Error::SampleOutOfRange(_)));
+        assert_eq!(
+            err.to_string(),
+            "sample out of range: 2.0 is not in range -1.0..=1.0"
+        );
+        assert_eq!(convert(0.0).unwrap(), 0);
+    }
+}
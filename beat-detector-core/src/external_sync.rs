@@ -0,0 +1,365 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`AccentTracker`].
+
+use crate::{BeatDetectorConst, BeatInfo};
+use core::time::Duration;
+
+/// Pulses per quarter note of the MIDI clock.
+const MIDI_CLOCK_PPQN: u32 = 24;
+
+/// Weight of the latest pulse interval when averaging the pulse intervals of
+/// the MIDI clock. Pulses are delivered with a lot of jitter.
+const MIDI_CLOCK_ALPHA: f32 = 0.05;
+
+/// Default for [`AccentTracker::new`].
+pub const DEFAULT_ACCENT_WINDOW: Duration = Duration::from_millis(60);
+
+/// Tempo and phase of an external clock, such as a MIDI clock or an Ableton
+/// Link session, on the time axis of the [`BeatDetector`], i.e., its
+/// [passed time].
+///
+/// [`BeatDetector`]: crate::BeatDetector
+/// [passed time]: crate::BeatDetector::passed_time
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExternalClock {
+    beat_period: Duration,
+    beat_time: Duration,
+}
+
+impl ExternalClock {
+    /// Creates a clock with the given duration of a beat. `beat_time` is the
+    /// time of any beat of the clock.
+    pub fn new(beat_period: Duration, beat_time: Duration) -> Self {
+        assert!(!beat_period.is_zero());
+        Self {
+            beat_period,
+            beat_time,
+        }
+    }
+
+    /// Creates a clock with the given tempo in beats per minute (BPM).
+    /// `beat_time` is the time of any beat of the clock.
+    pub fn from_bpm(bpm: f32, beat_time: Duration) -> Self {
+        assert!(bpm > 0.0 && bpm.is_finite());
+        Self::new(Duration::from_secs_f32(60.0 / bpm), beat_time)
+    }
+
+    /// Returns the duration of a beat.
+    pub const fn beat_period(&self) -> Duration {
+        self.beat_period
+    }
+
+    /// Returns the tempo in BPM.
+    pub fn bpm(&self) -> f32 {
+        60.0 / self.beat_period.as_secs_f32()
+    }
+
+    /// Returns the time of the given beat slot. Slot `0` is at the beat time
+    /// of the clock. Slots before the begin of the audio are at time zero.
+    pub fn slot_time(&self, slot: i64) -> Duration {
+        let offset = self.beat_period.as_nanos() as i128 * slot as i128;
+        let time = self.beat_time.as_nanos() as i128 + offset;
+        Duration::from_nanos(time.max(0) as u64)
+    }
+
+    /// Returns the slot that is closest to the given time.
+    pub const fn nearest_slot(&self, time: Duration) -> i64 {
+        let offset = time.as_nanos() as i128 - self.beat_time.as_nanos() as i128;
+        let period = self.beat_period.as_nanos() as i128;
+        (offset + period / 2).div_euclid(period) as i64
+    }
+}
+
+/// Derives an [`ExternalClock`] from the MIDI clock, i.e., from 24 pulses per
+/// quarter note.
+///
+/// All times must be on the time axis of the [`BeatDetector`], for example,
+/// by converting the arrival time of a MIDI message with the sample clock of
+/// the audio input.
+///
+/// [`BeatDetector`]: crate::BeatDetector
+#[derive(Debug, Clone, Default)]
+pub struct MidiClock {
+    /// Pulses since the latest start message.
+    pulses: u32,
+    latest_pulse: Option<Duration>,
+    /// Time of the latest pulse that was on a beat.
+    beat_time: Option<Duration>,
+    /// Average interval between pulses in seconds.
+    pulse_interval: Option<f32>,
+}
+
+impl MidiClock {
+    /// Creates a new MIDI clock that waits for pulses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles a MIDI start message. The next pulse is on a beat.
+    pub fn start(&mut self) {
+        self.pulses = 0;
+    }
+
+    /// Handles a MIDI timing clock message at the given time. Returns the
+    /// clock if the tempo is known. Without a start message, the first pulse
+    /// is considered to be on a beat.
+    pub fn pulse(&mut self, time: Duration) -> Option<ExternalClock> {
+        if let Some(latest_pulse) = self.latest_pulse.replace(time) {
+            let interval = time.saturating_sub(latest_pulse).as_secs_f32();
+            self.pulse_interval = Some(self.pulse_interval.map_or(interval, |average| {
+                average + MIDI_CLOCK_ALPHA * (interval - average)
+            }));
+        }
+        if self.pulses % MIDI_CLOCK_PPQN == 0 {
+            self.beat_time = Some(time);
+        }
+        self.pulses = self.pulses.wrapping_add(1);
+        self.clock()
+    }
+
+    /// Returns the clock if the tempo is known.
+    pub fn clock(&self) -> Option<ExternalClock> {
+        let pulse_interval = self.pulse_interval.filter(|interval| *interval > 0.0)?;
+        Some(ExternalClock::new(
+            Duration::from_secs_f32(pulse_interval * MIDI_CLOCK_PPQN as f32),
+            self.beat_time?,
+        ))
+    }
+}
+
+/// Accent strength of a beat slot of the [`ExternalClock`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct BeatSlotAccent {
+    /// Index of the slot, see [`ExternalClock::slot_time`].
+    pub slot: i64,
+    /// Time of the slot.
+    pub time: Duration,
+    /// Accent strength in range `0.0..=1.0`, i.e., the highest
+    /// [beat probability] around the slot.
+    ///
+    /// [beat probability]: crate::BeatDetector::beat_probability
+    pub accent: f32,
+    /// The detected beat around the slot, if any.
+    pub beat: Option<BeatInfo>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct SlotState {
+    slot: i64,
+    accent: f32,
+    beat: Option<BeatInfo>,
+}
+
+impl SlotState {
+    const fn new(slot: i64) -> Self {
+        Self {
+            slot,
+            accent: 0.0,
+            beat: None,
+        }
+    }
+}
+
+/// Follows the tempo and the phase of an [`ExternalClock`] instead of
+/// estimating them, and reports how strong the accent in the audio is on
+/// each expected beat, i.e., beat slot.
+///
+/// This is useful when the tempo is already known, such as in DJ software.
+/// Every slot is reported once, even if it is silent, shortly after the
+/// detector could have found a beat in it.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{AccentTracker, BeatDetector, ExternalClock, DEFAULT_ACCENT_WINDOW};
+/// use core::time::Duration;
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut tracker = AccentTracker::new(DEFAULT_ACCENT_WINDOW);
+/// tracker.set_clock(Some(ExternalClock::from_bpm(128.0, Duration::ZERO)));
+///
+/// // TODO regularly call this with the latest audio data.
+/// let beat = detector.update_and_detect_beat(mono_samples.iter().copied());
+/// if let Some(accent) = tracker.update(&detector, beat.as_ref()) {
+///     println!("{accent:?}");
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AccentTracker {
+    clock: Option<ExternalClock>,
+    window: Duration,
+    /// The slot that is reported next and the slot after it, whose windows
+    /// may already be open.
+    slots: Option<(SlotState, SlotState)>,
+}
+
+impl AccentTracker {
+    /// Creates a new tracker. Audio and beats within `window` around a slot
+    /// count for the slot.
+    pub const fn new(window: Duration) -> Self {
+        Self {
+            clock: None,
+            window,
+            slots: None,
+        }
+    }
+
+    /// Returns the external clock.
+    pub const fn clock(&self) -> Option<ExternalClock> {
+        self.clock
+    }
+
+    /// Sets the external clock, for example, on each tempo change. `None`
+    /// pauses the tracking.
+    pub fn set_clock(&mut self, clock: Option<ExternalClock>) {
+        if clock != self.clock {
+            self.clock = clock;
+            self.slots = None;
+        }
+    }
+
+    /// Supposed to be called after each update of the detector with the beat
+    /// it reported, if any. Returns the accent of the oldest slot that is
+    /// complete, if any.
+    pub fn update<const N: usize, const D: usize, const P: usize>(
+        &mut self,
+        detector: &BeatDetectorConst<N, D, P>,
+        beat: Option<&BeatInfo>,
+    ) -> Option<BeatSlotAccent> {
+        let clock = self.clock?;
+        let now = detector.passed_time();
+        // A beat is reported after the envelope is complete.
+        let latency = detector.envelope_config().min_duration + self.window;
+
+        let (mut current, mut next) = self.slots.unwrap_or_else(|| {
+            let slot = clock.nearest_slot(now.saturating_sub(latency));
+            (SlotState::new(slot), SlotState::new(slot + 1))
+        });
+
+        let window = self.window;
+        let in_window = |slot: &SlotState, time: Duration| {
+            let slot_time = clock.slot_time(slot.slot);
+            slot_time
+                .saturating_sub(time)
+                .max(time.saturating_sub(slot_time))
+                <= window
+        };
+        for slot in [&mut current, &mut next] {
+            if in_window(slot, now) {
+                slot.accent = slot.accent.max(detector.beat_probability());
+            }
+            if let Some(beat) = beat.filter(|beat| in_window(slot, beat.timestamp())) {
+                slot.beat = Some(*beat);
+            }
+        }
+
+        let time = clock.slot_time(current.slot);
+        let accent = (now >= time + latency).then(|| {
+            let accent = BeatSlotAccent {
+                slot: current.slot,
+                time,
+                accent: current.accent,
+                beat: current.beat,
+            };
+            current = next;
+            next = SlotState::new(next.slot + 1);
+            accent
+        });
+        self.slots = Some((current, next));
+        accent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use crate::BeatDetector;
+    use std::vec::Vec;
+
+    #[test]
+    fn clock_slots() {
+        let clock = ExternalClock::from_bpm(120.0, Duration::from_millis(1200));
+        assert_eq!(clock.beat_period(), Duration::from_millis(500));
+        assert_eq!(clock.slot_time(0), Duration::from_millis(1200));
+        assert_eq!(clock.slot_time(-2), Duration::from_millis(200));
+        assert_eq!(clock.slot_time(-3), Duration::ZERO);
+        assert_eq!(clock.nearest_slot(Duration::from_millis(1400)), 0);
+        assert_eq!(clock.nearest_slot(Duration::from_millis(1500)), 1);
+        assert_eq!(clock.nearest_slot(Duration::from_millis(100)), -2);
+    }
+
+    #[test]
+    fn midi_clock() {
+        let mut midi = MidiClock::new();
+        // 125 BPM, i.e., 20 ms per pulse.
+        let pulse = |i: u64| Duration::from_millis(100 + i * 20);
+        assert_eq!(midi.pulse(pulse(0)), None);
+        midi.start();
+        for i in 1..48 {
+            midi.pulse(pulse(i));
+        }
+        let clock = midi.clock().unwrap();
+        assert!((clock.bpm() - 125.0).abs() < 0.01);
+        // The start message defines the phase.
+        assert_eq!(clock.slot_time(0), pulse(25));
+        assert_eq!(clock.nearest_slot(pulse(1)), -1);
+    }
+
+    #[test]
+    fn accents_follow_the_external_clock() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        let mut tracker = AccentTracker::new(DEFAULT_ACCENT_WINDOW);
+        // The tempo of the sample, with a beat at 47167 samples.
+        tracker.set_clock(Some(ExternalClock::new(
+            Duration::from_secs_f32(18270.0 / 44100.0),
+            Duration::from_secs_f32(47167.0 / 44100.0),
+        )));
+
+        let accents = samples
+            .chunks(1024)
+            .filter_map(|chunk| {
+                let beat = detector.update_and_detect_beat(chunk.iter().copied());
+                tracker.update(&detector, beat.as_ref())
+            })
+            .collect::<Vec<_>>();
+
+        // Every slot is reported once, in order.
+        assert!(accents
+            .iter()
+            .zip(accents.iter().skip(1))
+            .all(|(a, b)| b.slot == a.slot + 1));
+        // The clock continues the beats of the sample.
+        for accent in accents.iter().filter(|accent| accent.slot >= 0) {
+            assert_eq!(accent.accent, 1.0);
+            let beat = accent.beat.unwrap();
+            let offset = beat.timestamp().as_secs_f32() - accent.time.as_secs_f32();
+            assert!(offset.abs() < 0.02);
+        }
+        assert_eq!(accents.last().unwrap().slot, 4);
+    }
+}
@@ -0,0 +1,345 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`BeatFingerprint`].
+
+use crate::{AudioHistory, BeatInfo};
+
+/// Amount of frequency bands of a [`BeatFingerprint`].
+pub const FINGERPRINT_BANDS: usize = 8;
+
+/// Center frequencies of the bands in Hz. Third octaves in the range of kick
+/// drums and bass. Higher frequencies are mostly removed by the lowpass
+/// filter of the detector anyway.
+const BAND_FREQUENCIES_HZ: [f32; FINGERPRINT_BANDS] =
+    [40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0];
+
+/// Weight of the spectrum in [`BeatFingerprint::similarity`]. The remaining
+/// weight is on the shape of the envelope.
+const SPECTRUM_WEIGHT: f32 = 0.8;
+
+/// Default capacity of a [`FingerprintHistory`]: eight bars of four beats.
+pub const DEFAULT_FINGERPRINT_HISTORY: usize = 32;
+
+/// Tiny spectral fingerprint of a beat, i.e., of the audio of its envelope.
+///
+/// It consists of the relative energy in a few low frequency bands and the
+/// relative position of the maximum in the envelope. It doesn't depend on
+/// the volume. Beats of the same instrument, such as the kick drum at the
+/// begin of every bar, have similar fingerprints.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct BeatFingerprint {
+    /// Energy per band, scaled so that the strongest band is `255`.
+    pub bands: [u8; FINGERPRINT_BANDS],
+    /// Position of the maximum in the envelope, scaled to `0..=255`.
+    pub attack: u8,
+}
+
+impl BeatFingerprint {
+    /// Computes the fingerprint of a beat from the audio history it was found
+    /// in, such as the [history] of the detector right after the beat was
+    /// reported. Returns `None` if the audio of the beat is no longer
+    /// completely in the history.
+    ///
+    /// [history]: crate::BeatDetector::history
+    pub fn new<const N: usize>(history: &AudioHistory<N>, beat: &BeatInfo) -> Option<Self> {
+        let begin = history.total_index_to_index(beat.from.total_index)?;
+        let end = history.total_index_to_index(beat.to.total_index)?;
+        if end <= begin || end >= history.len() {
+            return None;
+        }
+        let len = end - begin + 1;
+
+        let sampling_frequency = history.sampling_frequency();
+        let mut goertzel = BAND_FREQUENCIES_HZ.map(|frequency| {
+            // Bands above the Nyquist frequency stay empty.
+            (frequency < sampling_frequency / 2.0)
+                .then(|| Goertzel::new(frequency, sampling_frequency))
+        });
+        for &sample in history.samples().skip(begin).take(len) {
+            let sample = sample as f32 / i16::MAX as f32;
+            for goertzel in goertzel.iter_mut().flatten() {
+                goertzel.feed(sample);
+            }
+        }
+        let energies = goertzel.map(|goertzel| goertzel.map_or(0.0, |goertzel| goertzel.energy()));
+        let max_energy = energies.iter().copied().fold(0.0, f32::max);
+        let bands = energies.map(|energy| {
+            if max_energy > 0.0 {
+                (energy / max_energy * u8::MAX as f32) as u8
+            } else {
+                0
+            }
+        });
+
+        let attack =
+            beat.max.total_index.saturating_sub(beat.from.total_index) as f32 / (len - 1) as f32;
+        Some(Self {
+            bands,
+            attack: (attack.clamp(0.0, 1.0) * u8::MAX as f32) as u8,
+        })
+    }
+
+    /// Returns the similarity to another fingerprint in range `0.0..=1.0`.
+    /// `1.0` means that both are identical.
+    pub fn similarity(&self, other: &Self) -> f32 {
+        let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+        for (&a, &b) in self.bands.iter().zip(other.bands.iter()) {
+            let (a, b) = (a as f32, b as f32);
+            dot += a * b;
+            norm_a += a * a;
+            norm_b += b * b;
+        }
+        let spectrum = if norm_a > 0.0 && norm_b > 0.0 {
+            dot / libm::sqrtf(norm_a * norm_b)
+        } else {
+            0.0
+        };
+        let shape = 1.0 - self.attack.abs_diff(other.attack) as f32 / u8::MAX as f32;
+        (SPECTRUM_WEIGHT * spectrum + (1.0 - SPECTRUM_WEIGHT) * shape).clamp(0.0, 1.0)
+    }
+}
+
+/// Goertzel filter that measures the energy of a single frequency.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Goertzel {
+    coefficient: f32,
+    s1: f32,
+    s2: f32,
+    count: usize,
+}
+
+impl Goertzel {
+    pub(crate) fn new(frequency: f32, sampling_frequency: f32) -> Self {
+        let omega = 2.0 * core::f32::consts::PI * frequency / sampling_frequency;
+        Self {
+            coefficient: 2.0 * libm::cosf(omega),
+            s1: 0.0,
+            s2: 0.0,
+            count: 0,
+        }
+    }
+
+    pub(crate) fn feed(&mut self, sample: f32) {
+        let s0 = sample + self.coefficient * self.s1 - self.s2;
+        self.s2 = self.s1;
+        self.s1 = s0;
+        self.count += 1;
+    }
+
+    /// Returns the energy normalized to the amount of samples.
+    pub(crate) fn energy(&self) -> f32 {
+        let power = self.s1 * self.s1 + self.s2 * self.s2 - self.coefficient * self.s1 * self.s2;
+        power / self.count.max(1) as f32
+    }
+}
+
+/// Keeps the fingerprints of the latest `N` beats to find repeating patterns,
+/// such as the same kick drum at the begin of every bar.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BeatDetector, BeatFingerprint, FingerprintHistory};
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut fingerprints = FingerprintHistory::<32>::new();
+///
+/// // TODO regularly call this with the latest audio data.
+/// if let Some(beat) = detector.update_and_detect_beat(mono_samples.iter().copied()) {
+///     if let Some(fingerprint) = BeatFingerprint::new(detector.history(), &beat) {
+///         fingerprints.push(fingerprint);
+///     }
+/// }
+/// let period = fingerprints.repetition_period(8);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FingerprintHistory<const N: usize = DEFAULT_FINGERPRINT_HISTORY> {
+    fingerprints: [BeatFingerprint; N],
+    len: usize,
+    next: usize,
+}
+
+impl<const N: usize> FingerprintHistory<N> {
+    /// Fails the build if the history can't hold a single fingerprint.
+    const NOT_EMPTY: () = {
+        if N == 0 {
+            panic!("The capacity must not be zero");
+        }
+    };
+
+    /// Creates an empty history.
+    ///
+    /// A capacity `N` of zero doesn't compile:
+    /// ```compile_fail
+    /// let history = beat_detector_core::FingerprintHistory::<0>::new();
+    /// ```
+    pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::NOT_EMPTY;
+        Self {
+            fingerprints: [BeatFingerprint {
+                bands: [0; FINGERPRINT_BANDS],
+                attack: 0,
+            }; N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// Adds the fingerprint of the latest beat. The oldest fingerprint is
+    /// dropped if the history is full.
+    pub fn push(&mut self, fingerprint: BeatFingerprint) {
+        self.fingerprints[self.next] = fingerprint;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    /// Returns the amount of fingerprints.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there are no fingerprints.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the fingerprint of the beat `age` beats ago. `0` is the latest
+    /// beat.
+    pub fn get(&self, age: usize) -> Option<&BeatFingerprint> {
+        (age < self.len).then(|| &self.fingerprints[(self.next + N - 1 - age) % N])
+    }
+
+    /// Returns the similarity of the latest beat to the beat `lag` beats
+    /// before it.
+    pub fn similarity(&self, lag: usize) -> Option<f32> {
+        Some(self.get(0)?.similarity(self.get(lag)?))
+    }
+
+    /// Returns the average similarity of all pairs of beats that are `lag`
+    /// beats apart.
+    pub fn average_similarity(&self, lag: usize) -> Option<f32> {
+        if lag == 0 || lag >= self.len {
+            return None;
+        }
+        let pairs = self.len - lag;
+        let sum = (0..pairs)
+            .filter_map(|age| Some(self.get(age)?.similarity(self.get(age + lag)?)))
+            .sum::<f32>();
+        Some(sum / pairs as f32)
+    }
+
+    /// Returns the amount of beats after which the pattern of beats repeats,
+    /// such as `4` if every bar of four beats begins with the same kick
+    /// drum, and the average similarity for that period. Only periods up to
+    /// `max_period` are considered. Each period needs at least two pairs of
+    /// beats. The shortest period wins if several are almost equally good.
+    pub fn repetition_period(&self, max_period: usize) -> Option<(usize, f32)> {
+        let mut best: Option<(usize, f32)> = None;
+        for lag in 1..=max_period.min(self.len.saturating_sub(2)) {
+            let Some(similarity) = self.average_similarity(lag) else {
+                continue;
+            };
+            // Multiples of the period are as similar as the period itself.
+            if best.map_or(true, |(_, best)| similarity > best + 0.01) {
+                best = Some((lag, similarity));
+            }
+        }
+        best
+    }
+}
+
+impl<const N: usize> Default for FingerprintHistory<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use crate::BeatDetector;
+    use std::vec::Vec;
+
+    fn fingerprints(
+        samples: &[i16],
+        sampling_frequency: f32,
+        lowpass: bool,
+    ) -> Vec<BeatFingerprint> {
+        let mut detector = BeatDetector::new(sampling_frequency, lowpass);
+        samples
+            .chunks(2048)
+            .filter_map(|chunk| {
+                let beat = detector.update_and_detect_beat(chunk.iter().copied())?;
+                BeatFingerprint::new(detector.history(), &beat)
+            })
+            .collect()
+    }
+
+    /// Alternating low and high drums, every 500 ms.
+    fn alternating_drums() -> Vec<i16> {
+        (0..44100 * 6)
+            .map(|i| {
+                let beat = i / 22050;
+                let t = (i % 22050) as f32 / 44100.0;
+                let frequency = if beat % 2 == 0 { 50.0 } else { 160.0 };
+                let sample =
+                    libm::sinf(t * frequency * 2.0 * core::f32::consts::PI) * libm::expf(-t * 20.0);
+                (sample * 20000.0) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_drum_has_similar_fingerprints() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let fingerprints = fingerprints(&samples, header.sample_rate as f32, true);
+        assert_eq!(fingerprints.len(), 7);
+        // The kick drum plays over two different bass notes.
+        for (a, b) in [(1, 5), (2, 4), (2, 6), (4, 6)] {
+            assert!(fingerprints[a].similarity(&fingerprints[b]) > 0.9);
+        }
+        for (a, b) in [(1, 2), (5, 6)] {
+            assert!(fingerprints[a].similarity(&fingerprints[b]) < 0.75);
+        }
+        assert_eq!(fingerprints[0].similarity(&fingerprints[0]), 1.0);
+    }
+
+    #[test]
+    fn repeating_pattern_is_found() {
+        let fingerprints = fingerprints(&alternating_drums(), 44100.0, false);
+        let mut history = FingerprintHistory::<DEFAULT_FINGERPRINT_HISTORY>::new();
+        assert_eq!(history.repetition_period(4), None);
+        for fingerprint in fingerprints {
+            history.push(fingerprint);
+        }
+        assert!(history.similarity(1).unwrap() < 0.8);
+        assert!(history.similarity(2).unwrap() > 0.95);
+        let (period, similarity) = history.repetition_period(4).unwrap();
+        assert_eq!(period, 2);
+        assert!(similarity > 0.95);
+    }
+}
@@ -0,0 +1,345 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`GainNormalizer`].
+
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::util::saturating_f32_to_i16;
+use crate::SnapshotError;
+use core::time::Duration;
+
+/// Time constant of the fast level follower. Long enough to smooth out the
+/// gaps between beats.
+const FAST_TIME_CONSTANT: Duration = Duration::from_millis(200);
+
+/// How long the fast level must deviate from the slow level until the
+/// deviation counts as gain change rather than as a quiet or loud passage.
+const SUSTAIN: Duration = Duration::from_millis(300);
+
+/// Factor by which the fast level must deviate from the slow level. This is
+/// 6 dB, more than the usual dynamics between beats and the gaps between
+/// them.
+const THRESHOLD: f32 = 2.0;
+
+/// Minimum fast level for a gain change. Prevents that silence, such as the
+/// pause between two songs, is amplified.
+const MIN_LEVEL: f32 = 512.0;
+
+/// Limits of the compensating gain.
+const MIN_GAIN: f32 = 1.0 / 8.0;
+const MAX_GAIN: f32 = 8.0;
+
+/// A sudden and sustained change of the gain of the audio input, as reported
+/// by [`GainNormalizer::update`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct GainChange {
+    /// Factor by which the samples since [`Self::begin_total_index`] must be
+    /// scaled so that they match the new compensating gain.
+    pub correction: f32,
+    /// Total index of the first processed sample that is affected.
+    pub begin_total_index: u64,
+}
+
+/// Compensates sudden and sustained gain changes of the audio input, such as
+/// a user who turns the volume knob.
+///
+/// A fast and a slow level follower track the peak level of each update. If
+/// the fast level stays far above or below the slow level for a while, the
+/// compensating gain is adjusted at once, so that the processed audio keeps
+/// its previous level. The audio history and the statistics derived from it
+/// stay consistent with the new audio instead of lagging behind.
+#[derive(Debug, Clone)]
+pub(crate) struct GainNormalizer {
+    gain: f32,
+    fast_level: f32,
+    slow_level: f32,
+    /// Time constants of the level followers in samples.
+    fast_samples: f32,
+    slow_samples: f32,
+    sustain_samples: u64,
+    /// Amount of samples since the level of the audio was adopted.
+    settled_samples: u64,
+    /// The ongoing deviation of the fast level from the slow level.
+    deviation: Option<Deviation>,
+}
+
+/// A period in which the fast level deviates from the slow level.
+#[derive(Debug, Copy, Clone)]
+struct Deviation {
+    /// Total index of the first sample of the first deviating update.
+    begin_total_index: u64,
+    /// Sum of the peaks of all deviating updates.
+    peak_sum: f32,
+    updates: u32,
+}
+
+impl Deviation {
+    fn mean_peak(&self) -> f32 {
+        self.peak_sum / self.updates as f32
+    }
+}
+
+impl GainNormalizer {
+    /// Creates a new normalizer for processed samples of the given sampling
+    /// frequency. `decay` is the time constant of the slow level follower,
+    /// which represents the level that the adaptive statistics of the
+    /// detector are used to.
+    pub(crate) fn new(sampling_frequency: f32, decay: Duration) -> Self {
+        let samples = |duration: Duration| (duration.as_secs_f32() * sampling_frequency).max(1.0);
+        Self {
+            gain: 1.0,
+            fast_level: 0.0,
+            slow_level: 0.0,
+            fast_samples: samples(FAST_TIME_CONSTANT),
+            slow_samples: samples(decay),
+            sustain_samples: samples(SUSTAIN) as u64,
+            settled_samples: 0,
+            deviation: None,
+        }
+    }
+
+    /// Sets the time constant of the slow level follower.
+    pub(crate) fn set_decay(&mut self, sampling_frequency: f32, decay: Duration) {
+        self.slow_samples = (decay.as_secs_f32() * sampling_frequency).max(1.0);
+    }
+
+    /// Returns the current compensating gain.
+    pub(crate) const fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Returns the level that the slow level follower settled on, i.e., the
+    /// usual peak level of the processed audio. Zero before any audio.
+    pub(crate) const fn level(&self) -> f32 {
+        self.slow_level
+    }
+
+    /// Continues with a gain and a level of a previous run, as if the level
+    /// followers had already settled.
+    pub(crate) fn restore(&mut self, gain: f32, level: f32) {
+        self.gain = gain.clamp(MIN_GAIN, MAX_GAIN);
+        self.fast_level = level;
+        self.slow_level = level;
+        self.settled_samples = self.slow_samples as u64;
+        self.deviation = None;
+    }
+
+    /// Returns the sample with the compensating gain applied.
+    #[inline]
+    pub(crate) fn process(&self, sample: i16) -> i16 {
+        saturating_f32_to_i16(sample as f32 * self.gain)
+    }
+
+    /// Updates the level followers with the peak of the latest update of
+    /// `len` processed samples, which begins at `begin_total_index`. The
+    /// peak is taken before the compensating gain is applied, so that it
+    /// isn't limited by saturation. Returns the gain change, if one was
+    /// detected.
+    pub(crate) fn update(
+        &mut self,
+        peak: i16,
+        len: usize,
+        begin_total_index: u64,
+    ) -> Option<GainChange> {
+        if len == 0 {
+            return None;
+        }
+        let peak = peak as f32 * self.gain;
+        if self.slow_level == 0.0 {
+            if peak >= MIN_LEVEL {
+                // Adopt the level of the first audio right away.
+                self.fast_level = peak;
+                self.slow_level = peak;
+            }
+            return None;
+        }
+        let alpha = |time_constant: f32| (len as f32 / time_constant).min(1.0);
+        self.fast_level += alpha(self.fast_samples) * (peak - self.fast_level);
+
+        if self.fast_level < MIN_LEVEL {
+            // Keep the level of the audio before the silence.
+            self.deviation = None;
+            return None;
+        }
+        let ratio = self.fast_level / self.slow_level;
+        // The level of the beginning of a song, such as of a quiet intro,
+        // needs some time to settle.
+        let is_settled = self.settled_samples >= self.slow_samples as u64;
+        self.settled_samples += len as u64;
+        if !is_settled || (1.0 / THRESHOLD..=THRESHOLD).contains(&ratio) {
+            self.deviation = None;
+            self.slow_level += alpha(self.slow_samples) * (self.fast_level - self.slow_level);
+            return None;
+        }
+
+        let deviation = self.deviation.get_or_insert(Deviation {
+            begin_total_index,
+            peak_sum: 0.0,
+            updates: 0,
+        });
+        deviation.peak_sum += peak;
+        deviation.updates += 1;
+        let deviation = *deviation;
+        let end_total_index = begin_total_index + len as u64;
+        // Audio that fades into silence is no gain change.
+        if end_total_index - deviation.begin_total_index < self.sustain_samples
+            || deviation.mean_peak() < MIN_LEVEL
+        {
+            return None;
+        }
+
+        // The fast level still lags behind, but the peaks since the
+        // beginning of the deviation reflect the new level.
+        let ratio = deviation.mean_peak() / self.slow_level;
+        let gain = (self.gain / ratio).clamp(MIN_GAIN, MAX_GAIN);
+        let correction = gain / self.gain;
+        self.gain = gain;
+        self.fast_level = deviation.mean_peak() * correction;
+        self.slow_level = self.fast_level;
+        self.deviation = None;
+        // The deviation begins a little after the actual change. When the
+        // audio is attenuated, a few samples before the change are
+        // attenuated as well, which is harmless. When the audio is amplified,
+        // only samples after the change are, as amplified loud samples would
+        // look like a beat.
+        let begin_total_index = if correction < 1.0 {
+            deviation
+                .begin_total_index
+                .saturating_sub(self.fast_samples as u64)
+        } else {
+            deviation.begin_total_index
+        };
+        Some(GainChange {
+            correction,
+            begin_total_index,
+        })
+    }
+
+    /// Writes the gain and the state of the level followers. The time
+    /// constants aren't included.
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.f32(self.gain);
+        writer.f32(self.fast_level);
+        writer.f32(self.slow_level);
+        writer.u64(self.settled_samples);
+        writer.option(self.deviation, |writer, deviation| {
+            writer.u64(deviation.begin_total_index);
+            writer.f32(deviation.peak_sum);
+            writer.u32(deviation.updates);
+        });
+    }
+
+    pub(crate) fn read_snapshot(
+        &mut self,
+        reader: &mut SnapshotReader,
+    ) -> Result<(), SnapshotError> {
+        self.gain = reader.f32()?;
+        SnapshotReader::check((MIN_GAIN..=MAX_GAIN).contains(&self.gain))?;
+        self.fast_level = reader.f32()?;
+        self.slow_level = reader.f32()?;
+        self.settled_samples = reader.u64()?;
+        self.deviation = reader.option(|reader| {
+            let deviation = Deviation {
+                begin_total_index: reader.u64()?,
+                peak_sum: reader.f32()?,
+                updates: reader.u32()?,
+            };
+            // The mean peak divides by the amount of updates.
+            SnapshotReader::check(deviation.updates > 0)?;
+            Ok(deviation)
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds updates of 1024 samples with the given peak and returns the
+    /// latest gain change.
+    fn feed(
+        normalizer: &mut GainNormalizer,
+        total_index: &mut u64,
+        peak: i16,
+        updates: usize,
+    ) -> Option<GainChange> {
+        let mut change = None;
+        for _ in 0..updates {
+            change = normalizer.update(peak, 1024, *total_index).or(change);
+            *total_index += 1024;
+        }
+        change
+    }
+
+    #[test]
+    fn gain_normalizer_compensates_sustained_gain_changes() {
+        let mut normalizer = GainNormalizer::new(44100.0, Duration::from_secs(3));
+        let mut total_index = 0;
+        assert_eq!(feed(&mut normalizer, &mut total_index, 16000, 200), None);
+        assert_eq!(normalizer.gain(), 1.0);
+
+        // The volume is turned down.
+        let change = feed(&mut normalizer, &mut total_index, 4000, 40).unwrap();
+        assert!(change.correction > 3.0);
+        assert!(change.begin_total_index > 200 * 1024);
+        assert!((3.5..4.5).contains(&normalizer.gain()));
+
+        // And up again.
+        let change = feed(&mut normalizer, &mut total_index, 16000, 40).unwrap();
+        assert!(change.correction < 0.4);
+        assert!((0.8..1.25).contains(&normalizer.gain()));
+    }
+
+    #[test]
+    fn gain_normalizer_decay() {
+        // The song gets quieter, then the volume is turned down.
+        let gain_after_decay = |decay| {
+            let mut normalizer = GainNormalizer::new(44100.0, decay);
+            let mut total_index = 0;
+            feed(&mut normalizer, &mut total_index, 16000, 500);
+            assert_eq!(feed(&mut normalizer, &mut total_index, 10000, 130), None);
+            feed(&mut normalizer, &mut total_index, 3000, 40).unwrap();
+            normalizer.gain()
+        };
+
+        // The longer the decay, the more the louder part is remembered.
+        let short = gain_after_decay(Duration::from_secs(2));
+        let long = gain_after_decay(Duration::from_secs(10));
+        assert!(long > short * 1.2, "{short} {long}");
+        assert!((3.0..3.7).contains(&short), "{short}");
+    }
+
+    #[test]
+    fn gain_normalizer_ignores_short_passages_and_silence() {
+        let mut normalizer = GainNormalizer::new(44100.0, Duration::from_secs(3));
+        let mut total_index = 0;
+        feed(&mut normalizer, &mut total_index, 16000, 200);
+        // A short break.
+        assert_eq!(feed(&mut normalizer, &mut total_index, 2000, 6), None);
+        assert_eq!(feed(&mut normalizer, &mut total_index, 16000, 200), None);
+        // Silence.
+        assert_eq!(feed(&mut normalizer, &mut total_index, 0, 200), None);
+        assert_eq!(normalizer.gain(), 1.0);
+    }
+}
@@ -0,0 +1,160 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`HeartbeatGenerator`].
+
+use crate::BeatDetectorConst;
+use core::time::Duration;
+
+/// Basic health information of a running beat detection.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Heartbeat {
+    /// Whether the audio stream delivered data without errors since the
+    /// previous heartbeat.
+    pub stream_alive: bool,
+    /// Duration of all audio consumed so far.
+    pub passed_time: Duration,
+    /// How long ago the previous beat was. `None` if there was no beat yet.
+    pub last_beat_age: Option<Duration>,
+    /// Fill level of the internal audio buffer in range `0.0..=1.0`.
+    pub buffer_fill: f32,
+}
+
+/// Emits a [`Heartbeat`] in a configurable interval, so that unattended
+/// installations can wire the health of the beat detection into their
+/// monitoring.
+///
+/// The interval is measured with the sample clock, i.e., the amount of audio
+/// the detector consumed. Thus, if the audio stream stalls, no heartbeats are
+/// emitted anymore, which is exactly what a watchdog should notice.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BeatDetector, HeartbeatGenerator};
+/// use core::time::Duration;
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut heartbeat = HeartbeatGenerator::new(Duration::from_secs(10));
+///
+/// // TODO regularly call this with the latest audio data.
+/// let is_beat = detector.update_and_detect_beat(mono_samples.iter().copied());
+/// if let Some(heartbeat) = heartbeat.poll(&detector, true) {
+///     println!("{heartbeat:?}");
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HeartbeatGenerator {
+    interval: Duration,
+    next_heartbeat: Duration,
+}
+
+impl HeartbeatGenerator {
+    /// Creates a new generator that emits a heartbeat each `interval`.
+    pub fn new(interval: Duration) -> Self {
+        assert!(!interval.is_zero());
+        Self {
+            interval,
+            next_heartbeat: interval,
+        }
+    }
+
+    /// Returns a heartbeat if the interval elapsed since the previous one.
+    /// Supposed to be called after each update of the detector.
+    ///
+    /// `stream_alive` should reflect whether the audio stream reported
+    /// errors since the previous call.
+    pub fn poll<const N: usize, const D: usize, const P: usize>(
+        &mut self,
+        detector: &BeatDetectorConst<N, D, P>,
+        stream_alive: bool,
+    ) -> Option<Heartbeat> {
+        let passed_time = detector.passed_time();
+        if passed_time < self.next_heartbeat {
+            return None;
+        }
+
+        // Skip missed intervals instead of emitting a burst of heartbeats.
+        while self.next_heartbeat <= passed_time {
+            self.next_heartbeat += self.interval;
+        }
+
+        Some(Heartbeat {
+            stream_alive,
+            passed_time,
+            last_beat_age: detector.last_beat_age(),
+            buffer_fill: detector.buffer_fill(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use crate::BeatDetector;
+    use std::vec::Vec;
+
+    #[test]
+    fn heartbeats_are_emitted_periodically() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        let mut generator = HeartbeatGenerator::new(Duration::from_secs(1));
+
+        let heartbeats = samples
+            .chunks(2048)
+            .filter_map(|chunk| {
+                let _ = detector.update_and_detect_beat(chunk.iter().copied());
+                generator.poll(&detector, true)
+            })
+            .collect::<Vec<_>>();
+
+        // The sample is ~3.2s long.
+        assert_eq!(heartbeats.len(), 3);
+        for (i, heartbeat) in heartbeats.iter().enumerate() {
+            assert!(heartbeat.stream_alive);
+            assert!(heartbeat.passed_time >= Duration::from_secs(i as u64 + 1));
+            assert_eq!(heartbeat.buffer_fill, 1.0);
+            assert!(heartbeat.last_beat_age.unwrap() < Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn missed_intervals_are_skipped() {
+        let mut detector = BeatDetector::new(1000.0, false);
+        let mut generator = HeartbeatGenerator::new(Duration::from_secs(1));
+
+        assert_eq!(generator.poll(&detector, true), None);
+
+        let _ = detector.update_and_detect_beat([0; 5500].iter().copied());
+        let heartbeat = generator.poll(&detector, false).unwrap();
+        assert!(!heartbeat.stream_alive);
+        assert_eq!(heartbeat.passed_time, Duration::from_millis(5500));
+        assert_eq!(heartbeat.last_beat_age, None);
+        assert_eq!(generator.poll(&detector, true), None);
+
+        let _ = detector.update_and_detect_beat([0; 500].iter().copied());
+        assert!(generator.poll(&detector, true).is_some());
+    }
+}
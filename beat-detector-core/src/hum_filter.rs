@@ -0,0 +1,424 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`MainsFrequency`].
+
+use crate::custom_filter::FilterStage;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::util::saturating_f32_to_i16;
+use crate::{EnvelopeInfo, SnapshotError};
+use biquad::{Coefficients, ToHertz, Type};
+use core::time::Duration;
+
+/// Amount of notch filters: the fundamental of the hum and its harmonics.
+const HUM_HARMONICS: usize = 3;
+
+/// Quality of the notch filters. Higher values result in narrower notches,
+/// which remove less of the beats but need an accurate mains frequency.
+const NOTCH_Q: f32 = 8.0;
+
+/// Maximum ratio between the maximum peak of an envelope and the maximum
+/// peak of the beat before it, so that the envelope counts as ringing of the
+/// notch. Like [`MergePolicy::max_peak_ratio`], but clearly below `1.0`, as
+/// real beats that quickly follow each other are about equally loud.
+///
+/// [`MergePolicy::max_peak_ratio`]: crate::MergePolicy::max_peak_ratio
+const RINGING_MAX_PEAK_RATIO: f32 = 0.9;
+
+/// Frequency of the mains power grid. Ground loops add hum with this
+/// frequency and its harmonics to the audio.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MainsFrequency {
+    /// 50 Hz, used in most of Europe, Asia, Africa, and Australia.
+    Hz50,
+    /// 60 Hz, used in most of the Americas.
+    Hz60,
+}
+
+impl MainsFrequency {
+    /// Returns the frequency in Hertz.
+    pub const fn hz(self) -> f32 {
+        match self {
+            Self::Hz50 => 50.0,
+            Self::Hz60 => 60.0,
+        }
+    }
+}
+
+/// Time constant of the phasors of the audio at the harmonics of the mains
+/// frequency. Short enough to follow the music, long enough to tell the
+/// harmonics apart from neighboring tones.
+const PHASOR_TIME_CONSTANT_S: f32 = 0.05;
+
+/// Time constant over which the phasor of a harmonic must stay stable to
+/// count as hum. The mains frequency may drift by about 0.1 Hz before the
+/// hum no longer counts as stable.
+const COHERENCE_TIME_CONSTANT_S: f32 = 0.5;
+
+/// Interval between the decisions whether the audio hums. The filter also
+/// fades in and out over this interval.
+const DECISION_INTERVAL_S: f32 = 0.01;
+
+/// The filter engages if the coherence of a phasor, i.e., the magnitude of
+/// its average over the magnitude of the phasor, rises above this. Hum has a
+/// stable amplitude and phase, so its coherence is close to `1.0`. Tones of
+/// the music come and go and drift in phase against the mains frequency.
+const ENGAGE_COHERENCE: f32 = 0.95;
+
+/// Time for which the coherence must stay above [`ENGAGE_COHERENCE`]. Right
+/// after a tone next to the mains frequency starts, its phasor hasn't drifted
+/// yet, so it briefly looks like hum.
+const ENGAGE_TIME_S: f32 = 0.5;
+
+/// The filter releases if the coherence of all phasors falls below this.
+const RELEASE_COHERENCE: f32 = 0.85;
+
+/// Minimum amplitude of the hum for the filter to engage, about -42 dBFS.
+/// Quieter hum doesn't mask beats, so the audio stays untouched.
+const MIN_HUM_AMPLITUDE: f32 = 256.0;
+
+/// Returns the product of two complex numbers.
+fn multiply((a_re, a_im): (f32, f32), (b_re, b_im): (f32, f32)) -> (f32, f32) {
+    (a_re * b_re - a_im * b_im, a_re * b_im + a_im * b_re)
+}
+
+/// Watches one harmonic of the mains frequency for hum.
+#[derive(Debug, Clone, Default)]
+struct HumDetector {
+    /// Phasor of the audio at the harmonic, i.e., its amplitude and phase.
+    phasor: (f32, f32),
+    /// Average of the phasor over [`COHERENCE_TIME_CONSTANT_S`].
+    average_phasor: (f32, f32),
+    /// Average of the magnitude of the phasor.
+    average_magnitude: f32,
+    /// Amount of consecutive decisions in which the harmonic looked like hum.
+    stable_decisions: u32,
+    hums: bool,
+}
+
+impl HumDetector {
+    /// Demodulates the sample with the oscillator at the harmonic.
+    fn track(&mut self, sample: f32, (cos, sin): (f32, f32), factor: f32) {
+        self.phasor.0 += factor * (sample * cos - self.phasor.0);
+        self.phasor.1 += factor * (sample * sin - self.phasor.1);
+    }
+
+    /// Decides whether the audio hums at the harmonic. It must look like hum
+    /// for `engage_decisions` in a row to engage the filter.
+    fn decide(&mut self, factor: f32, engage_decisions: u32) {
+        let (re, im) = self.phasor;
+        let magnitude = libm::sqrtf(re * re + im * im);
+        self.average_phasor.0 += factor * (re - self.average_phasor.0);
+        self.average_phasor.1 += factor * (im - self.average_phasor.1);
+        self.average_magnitude += factor * (magnitude - self.average_magnitude);
+
+        let (re, im) = self.average_phasor;
+        let stable_magnitude = libm::sqrtf(re * re + im * im);
+        let coherence = stable_magnitude / self.average_magnitude.max(f32::MIN_POSITIVE);
+        // The phasor has half the amplitude of the tone. The current phasor
+        // quickly tells when the hum stops.
+        let amplitude = 2.0 * stable_magnitude.min(magnitude);
+        if coherence > ENGAGE_COHERENCE && amplitude > MIN_HUM_AMPLITUDE {
+            self.stable_decisions = self.stable_decisions.saturating_add(1);
+        } else {
+            self.stable_decisions = 0;
+        }
+        self.hums = if self.hums {
+            coherence > RELEASE_COHERENCE && amplitude > MIN_HUM_AMPLITUDE / 2.0
+        } else {
+            self.stable_decisions >= engage_decisions
+        };
+    }
+
+    fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.f32(self.phasor.0);
+        writer.f32(self.phasor.1);
+        writer.f32(self.average_phasor.0);
+        writer.f32(self.average_phasor.1);
+        writer.f32(self.average_magnitude);
+        writer.u32(self.stable_decisions);
+        writer.bool(self.hums);
+    }
+
+    fn read_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.phasor = (reader.f32()?, reader.f32()?);
+        self.average_phasor = (reader.f32()?, reader.f32()?);
+        self.average_magnitude = reader.f32()?;
+        self.stable_decisions = reader.u32()?;
+        self.hums = reader.bool()?;
+        Ok(())
+    }
+}
+
+/// Removes hum of the [`MainsFrequency`] and its harmonics with a series of
+/// notch filters.
+///
+/// The notches also cut into the kick drums and ring after them, which
+/// shifts and adds beats. Hence, the filter only engages while one of the
+/// harmonics carries a stable tone, i.e., while the audio actually hums.
+/// Otherwise, the audio passes unchanged.
+#[derive(Debug, Clone)]
+pub(crate) struct HumFilter {
+    mains_frequency: MainsFrequency,
+    filters: [FilterStage; HUM_HARMONICS],
+    /// Amount of filters below the Nyquist frequency. Only these are used.
+    len: usize,
+    detectors: [HumDetector; HUM_HARMONICS],
+    /// Oscillator at the mains frequency, as cosine and sine.
+    oscillator: (f32, f32),
+    /// Rotation of the oscillator per sample.
+    rotation: (f32, f32),
+    phasor_factor: f32,
+    average_factor: f32,
+    decision_interval: u32,
+    engage_decisions: u32,
+    /// Samples until the next decision whether the audio hums.
+    countdown: u32,
+    /// Share of the filtered audio in the output, from `0.0` to `1.0`.
+    mix: f32,
+}
+
+impl HumFilter {
+    pub(crate) fn new(mains_frequency: MainsFrequency, sampling_frequency: f32) -> Self {
+        let max_frequency = sampling_frequency * 0.45;
+        let harmonic_frequency = |harmonic: usize| mains_frequency.hz() * (harmonic + 1) as f32;
+        let filter = |harmonic| {
+            Coefficients::<f32>::from_params(
+                Type::Notch,
+                sampling_frequency.hz(),
+                harmonic_frequency(harmonic).min(max_frequency).hz(),
+                NOTCH_Q,
+            )
+            .map(FilterStage::new)
+            .expect("Should be valid parameters")
+        };
+        let len = (0..HUM_HARMONICS)
+            .take_while(|&harmonic| harmonic_frequency(harmonic) < max_frequency)
+            .count();
+        let angle = 2.0 * core::f32::consts::PI * mains_frequency.hz() / sampling_frequency;
+        let decision_interval = libm::roundf(DECISION_INTERVAL_S * sampling_frequency).max(1.0);
+        Self {
+            mains_frequency,
+            filters: [filter(0), filter(1), filter(2)],
+            len,
+            detectors: Default::default(),
+            oscillator: (1.0, 0.0),
+            rotation: (libm::cosf(angle), libm::sinf(angle)),
+            phasor_factor: (1.0 / (PHASOR_TIME_CONSTANT_S * sampling_frequency)).min(1.0),
+            average_factor: (decision_interval / (COHERENCE_TIME_CONSTANT_S * sampling_frequency))
+                .min(1.0),
+            decision_interval: decision_interval as u32,
+            engage_decisions: libm::roundf(ENGAGE_TIME_S / DECISION_INTERVAL_S) as u32,
+            countdown: decision_interval as u32,
+            mix: 0.0,
+        }
+    }
+
+    pub(crate) const fn mains_frequency(&self) -> MainsFrequency {
+        self.mains_frequency
+    }
+
+    /// Returns true if the filter currently removes hum.
+    pub(crate) fn is_engaged(&self) -> bool {
+        self.mix > 0.0
+    }
+
+    /// Returns how long the notch at the mains frequency rings after a kick
+    /// drum, i.e., two time constants of its decay.
+    pub(crate) fn settle_time(&self) -> Duration {
+        let time_constant = NOTCH_Q / (core::f32::consts::PI * self.mains_frequency.hz());
+        Duration::from_secs_f32(2.0 * time_constant)
+    }
+
+    /// Returns true if `next` is likely the ringing of the notch after the
+    /// `beat`: the filter is engaged, and `next` follows within
+    /// [`Self::settle_time`] and is clearly quieter.
+    pub(crate) fn is_ringing(&self, beat: &EnvelopeInfo, next: &EnvelopeInfo) -> bool {
+        let distance = next.timestamp().saturating_sub(beat.timestamp());
+        let peak_ratio = next.max.value_abs as f32 / beat.max.value_abs as f32;
+        self.is_engaged() && distance < self.settle_time() && peak_ratio <= RINGING_MAX_PEAK_RATIO
+    }
+
+    /// Returns the sample without the hum, or the sample itself if the audio
+    /// doesn't hum.
+    pub(crate) fn process(&mut self, sample: i16) -> i16 {
+        let input = sample as f32;
+        let mut harmonic = (1.0, 0.0);
+        for detector in &mut self.detectors[..self.len] {
+            harmonic = multiply(harmonic, self.oscillator);
+            detector.track(input, harmonic, self.phasor_factor);
+        }
+        let (cos, sin) = multiply(self.oscillator, self.rotation);
+        // Keeps the amplitude of the oscillator at 1 despite rounding errors.
+        let correction = 1.5 - 0.5 * (cos * cos + sin * sin);
+        self.oscillator = (cos * correction, sin * correction);
+
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            self.countdown = self.decision_interval;
+            for detector in &mut self.detectors[..self.len] {
+                detector.decide(self.average_factor, self.engage_decisions);
+            }
+        }
+
+        // The notches always run, so that they are settled once the filter
+        // engages.
+        let filtered = self.filters[..self.len]
+            .iter_mut()
+            .fold(input, |sample, filter| filter.run(sample));
+        let step = 1.0 / self.decision_interval as f32;
+        self.mix = if self.detectors.iter().any(|detector| detector.hums) {
+            (self.mix + step).min(1.0)
+        } else {
+            (self.mix - step).max(0.0)
+        };
+        if self.mix == 0.0 {
+            sample
+        } else {
+            saturating_f32_to_i16(input + self.mix * (filtered - input))
+        }
+    }
+
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        for filter in &self.filters[..self.len] {
+            filter.write_snapshot(writer);
+        }
+        for detector in &self.detectors[..self.len] {
+            detector.write_snapshot(writer);
+        }
+        writer.f32(self.oscillator.0);
+        writer.f32(self.oscillator.1);
+        writer.u32(self.countdown);
+        writer.f32(self.mix);
+    }
+
+    pub(crate) fn read_snapshot(
+        &mut self,
+        reader: &mut SnapshotReader,
+    ) -> Result<(), SnapshotError> {
+        for filter in &mut self.filters[..self.len] {
+            filter.read_snapshot(reader)?;
+        }
+        for detector in &mut self.detectors[..self.len] {
+            detector.read_snapshot(reader)?;
+        }
+        self.oscillator = (reader.f32()?, reader.f32()?);
+        let countdown = reader.u32()?;
+        if countdown == 0 || countdown > self.decision_interval {
+            return Err(SnapshotError::InvalidFormat);
+        }
+        self.countdown = countdown;
+        self.mix = reader.f32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// Returns the RMS of a tone after the filter engaged. The filter needs
+    /// about half a second to engage.
+    fn filtered_rms(filter: &mut HumFilter, frequency: f32) -> f32 {
+        let samples = (0..88200)
+            .map(|i| libm::sinf(i as f32 * frequency * 2.0 * core::f32::consts::PI / 44100.0))
+            .map(|sample| filter.process((sample * 10000.0) as i16))
+            .skip(44100)
+            .collect::<Vec<_>>();
+        let sum = samples
+            .iter()
+            .map(|&s| (s as f32) * (s as f32))
+            .sum::<f32>();
+        libm::sqrtf(sum / samples.len() as f32) / 10000.0
+    }
+
+    #[test]
+    fn hum_filter_removes_hum_and_harmonics() {
+        for mains_frequency in [MainsFrequency::Hz50, MainsFrequency::Hz60] {
+            for harmonic in 1..=3 {
+                let mut filter = HumFilter::new(mains_frequency, 44100.0);
+                let rms = filtered_rms(&mut filter, mains_frequency.hz() * harmonic as f32);
+                assert!(rms < 0.01, "{mains_frequency:?} {harmonic}: {rms}");
+            }
+        }
+
+        // Frequencies between the harmonics mostly pass.
+        let mut filter = HumFilter::new(MainsFrequency::Hz50, 44100.0);
+        assert!(filtered_rms(&mut filter, 80.0) > 0.75 / core::f32::consts::SQRT_2);
+        let mut filter = HumFilter::new(MainsFrequency::Hz50, 44100.0);
+        assert!(filtered_rms(&mut filter, 1000.0) > 0.95 / core::f32::consts::SQRT_2);
+    }
+
+    #[test]
+    fn hum_filter_only_engages_on_stable_hum() {
+        let tone = |frequency: f32, amplitude: f32| {
+            (0..44100)
+                .map(move |i| {
+                    let phase = i as f32 * frequency * 2.0 * core::f32::consts::PI / 44100.0;
+                    (libm::sinf(phase) * amplitude) as i16
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Neither a tone next to the mains frequency nor quiet hum engage the
+        // filter, so the audio passes unchanged.
+        for samples in [
+            tone(49.0, 10000.0),
+            tone(50.0, 100.0),
+            tone(1000.0, 10000.0),
+        ] {
+            let mut filter = HumFilter::new(MainsFrequency::Hz50, 44100.0);
+            assert!(samples
+                .iter()
+                .all(|&sample| filter.process(sample) == sample));
+            assert!(!filter.is_engaged());
+        }
+
+        let mut filter = HumFilter::new(MainsFrequency::Hz50, 44100.0);
+        for sample in tone(50.0, 10000.0) {
+            filter.process(sample);
+        }
+        assert!(filter.is_engaged());
+
+        // The filter releases once the hum stops.
+        for _ in 0..44100 {
+            filter.process(0);
+        }
+        assert!(!filter.is_engaged());
+    }
+
+    #[test]
+    fn settle_time() {
+        let filter = HumFilter::new(MainsFrequency::Hz50, 44100.0);
+        assert_eq!(filter.settle_time().as_millis(), 101);
+        let filter = HumFilter::new(MainsFrequency::Hz60, 44100.0);
+        assert_eq!(filter.settle_time().as_millis(), 84);
+    }
+
+    #[test]
+    fn hum_filter_skips_harmonics_above_nyquist() {
+        let filter = HumFilter::new(MainsFrequency::Hz60, 300.0);
+        assert_eq!(filter.len, 2);
+    }
+}
@@ -0,0 +1,433 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`KeyEstimator`].
+
+use crate::fingerprint::Goertzel;
+use core::fmt::{Display, Formatter};
+use core::time::Duration;
+
+/// Default window of a [`KeyEstimator`].
+pub const DEFAULT_KEY_WINDOW: Duration = Duration::from_secs(30);
+
+/// Default amount of frames of the chromagram that a [`KeyEstimator`] holds.
+/// A frame lasts at least ~186 ms, so this covers the [`DEFAULT_KEY_WINDOW`]
+/// at every sampling frequency. This needs ~9 KiB.
+pub const DEFAULT_KEY_FRAMES: usize = 192;
+
+/// Lowest note of the chromagram as MIDI note number, i.e., C3.
+const LOWEST_NOTE: usize = 48;
+
+/// Amount of notes of the chromagram: three octaves up to B5. Lower notes are
+/// dominated by the bass and the drums, higher notes by overtones.
+const NOTES: usize = 36;
+
+/// The audio is decimated to at least this sampling frequency, which is
+/// plenty for the highest note.
+const DECIMATED_SAMPLING_FREQUENCY_HZ: f32 = 11025.0;
+
+/// Amount of decimated samples per frame of the chromagram, i.e., about
+/// 370 ms. Long enough to tell neighbouring semitones of the lowest note
+/// apart.
+const FRAME_LEN: usize = 4096;
+
+/// Frames with a lower mean power of the notes, relative to full scale, are
+/// silence and skipped.
+const MIN_FRAME_POWER: f32 = 1e-6;
+
+/// A new key must correlate better than the current key by this much before
+/// the estimate changes, so that it doesn't flicker between related keys.
+const KEY_CHANGE_MARGIN: f32 = 0.05;
+
+/// Key profile of major keys by Krumhansl and Kessler, beginning with the
+/// tonic.
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+
+/// Key profile of minor keys by Krumhansl and Kessler, beginning with the
+/// tonic.
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+const PITCH_CLASS_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Scale of a [`Key`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Scale {
+    /// Major scale.
+    Major,
+    /// Natural, harmonic, or melodic minor scale.
+    Minor,
+}
+
+impl Scale {
+    const fn profile(self) -> &'static [f32; 12] {
+        match self {
+            Self::Major => &MAJOR_PROFILE,
+            Self::Minor => &MINOR_PROFILE,
+        }
+    }
+}
+
+/// Musical key, see [`KeyEstimator`].
+///
+/// Formats as the name of the key, e.g., `F# minor`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Key {
+    /// Pitch class of the tonic in range `0..12`, where `0` is C, `1` is C#,
+    /// and so on.
+    pub tonic: u8,
+    /// Major or minor.
+    pub scale: Scale,
+    /// Correlation of the chromagram with the profile of the key in range
+    /// `-1.0..=1.0`. Values above `0.6` are typical for tonal music.
+    pub confidence: f32,
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let scale = match self.scale {
+            Scale::Major => "major",
+            Scale::Minor => "minor",
+        };
+        write!(f, "{} {scale}", PITCH_CLASS_NAMES[self.tonic as usize])
+    }
+}
+
+/// Estimates the key of the music from a chromagram over a long window.
+///
+/// Each frame of the audio is reduced to the energy of the twelve pitch
+/// classes, measured with a Goertzel filter per note, so no FFT is needed.
+/// The key is the one whose profile correlates best with the sum of the
+/// frames in the window. As the window spans many seconds, the estimate
+/// updates slowly and is meant for the overall mood of the visuals, as an
+/// analysis alongside the beats and the tempo.
+///
+/// Feed it the unfiltered audio, as the beat detection only keeps the low
+/// frequencies. The frames of the window are kept in a ring buffer of `N`
+/// frames, see [`DEFAULT_KEY_FRAMES`].
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BeatDetector, KeyEstimator};
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut key_estimator: KeyEstimator = KeyEstimator::new(44100.0);
+///
+/// // TODO regularly call this with the latest audio data.
+/// let is_beat = detector.update_and_detect_beat(mono_samples.iter().copied());
+/// if let Some(key) = key_estimator.update(mono_samples.iter().copied()) {
+///     println!("{key}");
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeyEstimator<const N: usize = DEFAULT_KEY_FRAMES> {
+    /// Amount of samples that are averaged to a decimated sample.
+    step: usize,
+    /// Sum of the samples of the current decimation block.
+    block_sum: f32,
+    block_len: usize,
+    /// Goertzel filters per note in their initial state.
+    initial_goertzel: [Goertzel; NOTES],
+    goertzel: [Goertzel; NOTES],
+    frame_len: usize,
+    /// Chroma vectors of the latest frames, each normalized to a sum of one.
+    /// A ring buffer of `max_frames` frames.
+    frames: [[f32; 12]; N],
+    len: usize,
+    next: usize,
+    max_frames: usize,
+    key: Option<Key>,
+}
+
+impl<const N: usize> KeyEstimator<N> {
+    /// Creates a new estimator with the [`DEFAULT_KEY_WINDOW`].
+    pub fn new(sampling_frequency_hz: f32) -> Self {
+        Self::with_window(sampling_frequency_hz, DEFAULT_KEY_WINDOW)
+    }
+
+    /// Creates a new estimator that considers the given duration of the
+    /// latest audio. Longer windows make the estimate more stable but slower
+    /// to follow a change of the key. Windows longer than `N` frames are
+    /// shortened.
+    pub fn with_window(sampling_frequency_hz: f32, window: Duration) -> Self {
+        assert!(N > 0, "The capacity must not be zero");
+        let step = ((sampling_frequency_hz / DECIMATED_SAMPLING_FREQUENCY_HZ) as usize).max(1);
+        let sampling_frequency = sampling_frequency_hz / step as f32;
+        let initial_goertzel = core::array::from_fn(|note| {
+            let midi_note = (LOWEST_NOTE + note) as f32;
+            let frequency = 440.0 * libm::powf(2.0, (midi_note - 69.0) / 12.0);
+            Goertzel::new(frequency, sampling_frequency)
+        });
+        let frame_duration = FRAME_LEN as f32 / sampling_frequency;
+        Self {
+            step,
+            block_sum: 0.0,
+            block_len: 0,
+            initial_goertzel,
+            goertzel: initial_goertzel,
+            frame_len: 0,
+            frames: [[0.0; 12]; N],
+            len: 0,
+            next: 0,
+            max_frames: (libm::ceilf(window.as_secs_f32() / frame_duration) as usize).clamp(1, N),
+            key: None,
+        }
+    }
+
+    /// Forgets all audio, for example, when the song changes.
+    pub fn reset(&mut self) {
+        self.block_sum = 0.0;
+        self.block_len = 0;
+        self.goertzel = self.initial_goertzel;
+        self.frame_len = 0;
+        self.len = 0;
+        self.next = 0;
+        self.key = None;
+    }
+
+    /// Analyzes the given audio and returns the current estimate of the key.
+    /// It changes at most once per frame of the chromagram.
+    pub fn update(&mut self, mono_samples: impl Iterator<Item = i16>) -> Option<Key> {
+        for sample in mono_samples {
+            // Averaging blocks of samples decimates the audio, with a crude
+            // lowpass filter against aliasing.
+            self.block_sum += sample as f32 / i16::MAX as f32;
+            self.block_len += 1;
+            if self.block_len < self.step {
+                continue;
+            }
+            let sample = self.block_sum / self.step as f32;
+            self.block_sum = 0.0;
+            self.block_len = 0;
+
+            for goertzel in &mut self.goertzel {
+                goertzel.feed(sample);
+            }
+            self.frame_len += 1;
+            if self.frame_len == FRAME_LEN {
+                self.finish_frame();
+            }
+        }
+        self.key
+    }
+
+    /// Returns the current estimate of the key, see [`Self::update`].
+    pub const fn key(&self) -> Option<Key> {
+        self.key
+    }
+
+    /// Returns the chromagram of the window, i.e., the energy of the pitch
+    /// classes beginning with C, scaled so that the strongest one is `1.0`.
+    pub fn chroma(&self) -> [f32; 12] {
+        let mut chroma = [0.0; 12];
+        for frame in &self.frames[..self.len] {
+            for (sum, value) in chroma.iter_mut().zip(frame) {
+                *sum += value;
+            }
+        }
+        let max = chroma.iter().copied().fold(0.0, f32::max);
+        if max > 0.0 {
+            chroma.iter_mut().for_each(|value| *value /= max);
+        }
+        chroma
+    }
+
+    fn finish_frame(&mut self) {
+        let mut chroma = [0.0; 12];
+        for (note, goertzel) in self.goertzel.iter().enumerate() {
+            chroma[(LOWEST_NOTE + note) % 12] += goertzel.energy();
+        }
+        self.goertzel = self.initial_goertzel;
+        self.frame_len = 0;
+
+        let sum = chroma.iter().sum::<f32>();
+        if sum / (FRAME_LEN * NOTES) as f32 <= MIN_FRAME_POWER {
+            return;
+        }
+        chroma.iter_mut().for_each(|value| *value /= sum);
+        self.frames[self.next] = chroma;
+        self.next = (self.next + 1) % self.max_frames;
+        self.len = (self.len + 1).min(self.max_frames);
+        self.update_key();
+    }
+
+    fn update_key(&mut self) {
+        let chroma = self.chroma();
+        let correlation = |tonic: u8, scale: Scale| {
+            let profile = scale.profile();
+            correlation(|pitch_class| {
+                let degree = (pitch_class + 12 - tonic as usize) % 12;
+                (chroma[pitch_class], profile[degree])
+            })
+        };
+        let best = [Scale::Major, Scale::Minor]
+            .into_iter()
+            .flat_map(|scale| (0..12).map(move |tonic| (tonic, scale)))
+            .map(|(tonic, scale)| Key {
+                tonic,
+                scale,
+                confidence: correlation(tonic, scale),
+            })
+            .fold(None, |best: Option<Key>, key| match best {
+                Some(best) if best.confidence >= key.confidence => Some(best),
+                _ => Some(key),
+            });
+
+        self.key = match (self.key, best) {
+            (Some(current), Some(best))
+                if (current.tonic, current.scale) != (best.tonic, best.scale) =>
+            {
+                let confidence = correlation(current.tonic, current.scale);
+                if best.confidence > confidence + KEY_CHANGE_MARGIN {
+                    Some(best)
+                } else {
+                    Some(Key {
+                        confidence,
+                        ..current
+                    })
+                }
+            }
+            (_, best) => best,
+        };
+    }
+}
+
+/// Pearson correlation of the twelve pairs of values.
+fn correlation(pair: impl Fn(usize) -> (f32, f32)) -> f32 {
+    let (mean_a, mean_b) = (0..12)
+        .map(&pair)
+        .fold((0.0, 0.0), |(sum_a, sum_b), (a, b)| {
+            (sum_a + a / 12.0, sum_b + b / 12.0)
+        });
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (a, b) in (0..12).map(pair) {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a) * (a - mean_a);
+        variance_b += (b - mean_b) * (b - mean_b);
+    }
+    if variance_a > 0.0 && variance_b > 0.0 {
+        covariance / libm::sqrtf(variance_a * variance_b)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    const SAMPLING_FREQUENCY: f32 = 11025.0;
+
+    /// Chords as MIDI notes, each played for one second.
+    fn chords(chords: &[[u8; 3]]) -> Vec<i16> {
+        chords
+            .iter()
+            .flat_map(|chord| {
+                (0..SAMPLING_FREQUENCY as usize).map(move |i| {
+                    let t = i as f32 / SAMPLING_FREQUENCY;
+                    let sample = chord
+                        .iter()
+                        .map(|&note| {
+                            let frequency = 440.0 * libm::powf(2.0, (note as f32 - 69.0) / 12.0);
+                            libm::sinf(t * frequency * 2.0 * core::f32::consts::PI)
+                        })
+                        .sum::<f32>();
+                    (sample * 8000.0) as i16
+                })
+            })
+            .collect()
+    }
+
+    /// C, F, G, and C major chords.
+    fn c_major() -> Vec<i16> {
+        chords(&[[60, 64, 67], [65, 69, 72], [67, 71, 74], [60, 64, 67]])
+    }
+
+    /// G, C, D, and G major chords.
+    fn g_major() -> Vec<i16> {
+        chords(&[[67, 71, 74], [60, 64, 67], [62, 66, 69], [67, 71, 74]])
+    }
+
+    #[test]
+    fn estimates_key() {
+        let mut estimator: KeyEstimator = KeyEstimator::new(SAMPLING_FREQUENCY);
+        assert_eq!(estimator.update(core::iter::repeat(0).take(20000)), None);
+        assert_eq!(estimator.chroma(), [0.0; 12]);
+
+        let key = estimator.update(c_major().into_iter()).unwrap();
+        assert_eq!((key.tonic, key.scale), (0, Scale::Major));
+        assert!(key.confidence > 0.6, "{key:?}");
+        assert_eq!(std::format!("{key}"), "C major");
+        let chroma = estimator.chroma();
+        assert!(chroma[0] > 0.9, "{chroma:?}");
+        // Not in the scale.
+        assert!(chroma[1] < 0.1, "{chroma:?}");
+
+        // A minor, D minor, E major, and A minor chords.
+        estimator.reset();
+        assert_eq!(estimator.key(), None);
+        let a_minor = chords(&[[57, 60, 64], [62, 65, 69], [64, 68, 71], [57, 60, 64]]);
+        let key = estimator.update(a_minor.into_iter()).unwrap();
+        assert_eq!(std::format!("{key}"), "A minor");
+    }
+
+    #[test]
+    fn follows_key_change_slowly() {
+        let mut estimator =
+            KeyEstimator::<32>::with_window(SAMPLING_FREQUENCY, Duration::from_secs(8));
+        for _ in 0..2 {
+            estimator.update(c_major().into_iter());
+        }
+        assert_eq!(std::format!("{}", estimator.key().unwrap()), "C major");
+
+        // The first chord of the new key is not enough.
+        let g_major = g_major();
+        estimator.update(g_major[..g_major.len() / 4].iter().copied());
+        assert_eq!(std::format!("{}", estimator.key().unwrap()), "C major");
+
+        for _ in 0..2 {
+            estimator.update(g_major.iter().copied());
+        }
+        assert_eq!(std::format!("{}", estimator.key().unwrap()), "G major");
+    }
+
+    #[test]
+    fn window_is_limited_by_capacity() {
+        // Eight frames are about three seconds.
+        let mut estimator = KeyEstimator::<8>::new(SAMPLING_FREQUENCY);
+        for _ in 0..4 {
+            estimator.update(c_major().into_iter());
+        }
+        // With a longer window, the C major chords before would keep the key.
+        estimator.update(g_major().into_iter());
+        assert_eq!(std::format!("{}", estimator.key().unwrap()), "G major");
+    }
+}
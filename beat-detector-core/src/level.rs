@@ -0,0 +1,106 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`ChunkLevel`].
+
+/// Level of a chunk of audio, such as one callback of an audio device. This
+/// is what a VU meter shows.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct ChunkLevel {
+    /// Maximum absolute value of the samples.
+    pub peak: i16,
+    /// Root mean square of the samples.
+    pub rms: f32,
+    /// Amount of samples in the chunk.
+    pub samples: usize,
+}
+
+impl ChunkLevel {
+    /// Measures the level of the given samples. The level of an empty chunk
+    /// is zero.
+    pub fn measure(samples: &[i16]) -> Self {
+        let peak = samples
+            .iter()
+            .map(|sample| sample.saturating_abs())
+            .max()
+            .unwrap_or(0);
+        let sum_of_squares = samples
+            .iter()
+            .map(|&sample| sample as f32 * sample as f32)
+            .sum::<f32>();
+        let rms = if samples.is_empty() {
+            0.0
+        } else {
+            libm::sqrtf(sum_of_squares / samples.len() as f32)
+        };
+        Self {
+            peak,
+            rms,
+            samples: samples.len(),
+        }
+    }
+
+    /// Returns the peak in dBFS, i.e., `0.0` at full scale. Silence is
+    /// negative infinity.
+    pub fn peak_dbfs(&self) -> f32 {
+        Self::dbfs(self.peak as f32)
+    }
+
+    /// Returns the RMS in dBFS, i.e., `0.0` for a full-scale square wave.
+    /// Silence is negative infinity.
+    pub fn rms_dbfs(&self) -> f32 {
+        Self::dbfs(self.rms)
+    }
+
+    fn dbfs(value: f32) -> f32 {
+        20.0 * libm::log10f(value / i16::MAX as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure() {
+        assert_eq!(ChunkLevel::measure(&[]), ChunkLevel::default());
+        assert_eq!(ChunkLevel::measure(&[0; 16]).peak_dbfs(), f32::NEG_INFINITY);
+
+        let level = ChunkLevel::measure(&[i16::MIN, i16::MAX, -i16::MAX, i16::MAX]);
+        assert_eq!(level.peak, i16::MAX);
+        assert_eq!(level.samples, 4);
+        assert!(libm::fabsf(level.peak_dbfs()) < 0.001);
+        assert!(libm::fabsf(level.rms_dbfs()) < 0.001);
+
+        // A sine wave at half scale.
+        let samples = (0..4410)
+            .map(|i| libm::sinf(i as f32 * 441.0 * 2.0 * core::f32::consts::PI / 44100.0))
+            .map(|sample| (sample * 16384.0) as i16)
+            .collect::<std::vec::Vec<_>>();
+        let level = ChunkLevel::measure(&samples);
+        assert!((16380..=16384).contains(&level.peak));
+        assert!(libm::fabsf(level.peak_dbfs() + 6.02) < 0.01);
+        assert!(libm::fabsf(level.rms_dbfs() + 9.03) < 0.01);
+    }
+}
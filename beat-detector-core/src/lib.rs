@@ -0,0 +1,343 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! The `no_std` core of [beat-detector]: the detection pipeline without any
+//! I/O. It neither needs the standard library nor `alloc`.
+//!
+//! Most users want the [beat-detector] facade crate, which re-exports this
+//! crate and adds audio sources and live recording from
+//! `beat-detector-io`.
+//!
+//! ## TL;DR
+//!
+//! Use [`BeatDetector`]. On tiny microcontrollers, where even that is too
+//! heavy, use [`EnergyBeatDetector`].
+//!
+//! ## Example
+//!
+//! ```rust
+//! # #[cfg(feature = "float")]
+//! # {
+//! use beat_detector_core::BeatDetector;
+//! let mono_samples = [0, 500, -800, 700 /*, ... */];
+//! let mut detector = BeatDetector::new(44100.0, false);
+//!
+//! let is_beat = detector.update_and_detect_beat(
+//!     mono_samples.iter().copied()
+//! );
+//! # }
+//! ```
+//!
+//! ## Cargo Features
+//!
+//! - `float` (default): The floating point based detection pipeline, i.e.,
+//!   [`BeatDetector`] and everything around it. Without it, only
+//!   [`EnergyBeatDetector`] and a few integer-only helpers remain. The
+//!   [`EnergyBeatDetector`] is the integer alternative: it reports beats as
+//!   sample counts and takes its thresholds in Q15 and fixed point. The
+//!   [`BeatDetector`] itself has no integer variant, as its filters,
+//!   envelopes, and statistics are inherently floating point.
+//! - `std`: Implements `std::error::Error` for the error types and adds a few
+//!   conveniences that allocate, such as [`AudioHistory::snapshot`] and the
+//!   [`TimecodeGenerator`]. Implies `float`. This doesn't add any I/O.
+//!
+//! [beat-detector]: https://docs.rs/beat-detector
+
+#![no_std]
+#![deny(
+    clippy::all,
+    clippy::cargo,
+    clippy::nursery,
+    // clippy::restriction,
+    // clippy::pedantic
+)]
+// now allow a few rules which are denied by the above statement
+// --> they are ridiculous and not necessary
+#![allow(
+    clippy::suboptimal_flops,
+    clippy::redundant_pub_crate,
+    clippy::fallible_impl_from,
+    clippy::multiple_crate_versions
+)]
+#![deny(missing_debug_implementations)]
+#![deny(rustdoc::all)]
+// The docs link the floating point pipeline and the conveniences of `std`,
+// which are missing without their features.
+#![cfg_attr(not(feature = "std"), allow(rustdoc::broken_intra_doc_links))]
+
+#[cfg_attr(any(test, feature = "std"), macro_use)]
+#[cfg(any(test, feature = "std"))]
+extern crate std;
+
+// Better drop-in replacement for "assert!" and even better "check!" macro.
+#[cfg_attr(test, macro_use)]
+#[cfg(test)]
+extern crate assert2;
+
+#[cfg_attr(all(test, feature = "float"), macro_use)]
+#[cfg(all(test, feature = "float"))]
+extern crate float_cmp;
+
+#[cfg(feature = "float")]
+mod adaptive_statistics;
+#[cfg(feature = "float")]
+mod amplitude_histogram;
+#[cfg(feature = "float")]
+mod audio_buffer;
+#[cfg(feature = "float")]
+mod audio_history;
+#[cfg(feature = "float")]
+mod bass_pitch;
+#[cfg(feature = "float")]
+mod beat_detector;
+#[cfg(feature = "float")]
+mod beat_intensity;
+#[cfg(feature = "float")]
+mod beat_log;
+#[cfg(feature = "float")]
+mod calibration;
+#[cfg(feature = "float")]
+mod custom_filter;
+#[cfg(feature = "float")]
+mod decision_trace;
+#[cfg(feature = "float")]
+mod diagnosis;
+#[cfg(feature = "float")]
+mod drop_tracker;
+#[cfg(feature = "float")]
+mod echo_canceller;
+mod energy_detector;
+#[cfg(feature = "float")]
+mod envelope_iterator;
+#[cfg(feature = "float")]
+mod error;
+#[cfg(feature = "float")]
+mod external_sync;
+#[cfg(feature = "float")]
+mod fingerprint;
+#[cfg(feature = "float")]
+mod gain_normalizer;
+#[cfg(feature = "float")]
+mod heartbeat;
+#[cfg(feature = "float")]
+mod hum_filter;
+#[cfg(feature = "float")]
+mod key;
+#[cfg(feature = "float")]
+mod level;
+#[cfg(feature = "float")]
+mod max_min_iterator;
+#[cfg(feature = "float")]
+mod mixer;
+#[cfg(feature = "float")]
+mod multi_source_detector;
+#[cfg(feature = "float")]
+mod noise_profile;
+#[cfg(feature = "float")]
+mod peak_cache;
+mod pwm;
+#[cfg(feature = "float")]
+mod root_iterator;
+#[cfg(feature = "float")]
+mod scene;
+#[cfg(feature = "float")]
+mod sensor_adapter;
+#[cfg(feature = "float")]
+mod snapshot;
+mod spsc;
+#[cfg(feature = "float")]
+mod stereo_balance;
+#[cfg(feature = "float")]
+mod stereo_beat_detector;
+#[cfg(feature = "float")]
+mod sustain_suppressor;
+#[cfg(feature = "float")]
+mod tempo;
+/// PRIVATE. For tests and helper binaries.
+#[cfg(test)]
+mod test_utils;
+#[cfg(feature = "std")]
+mod timecode;
+#[cfg(feature = "float")]
+mod uniforms;
+pub mod util;
+
+#[cfg(feature = "float")]
+pub use amplitude_histogram::AmplitudeHistogram;
+#[cfg(feature = "float")]
+pub use audio_history::{AudioHistory, SampleInfo, SnapshotError, SourcePosition};
+#[cfg(feature = "float")]
+pub use bass_pitch::BassPitch;
+#[cfg(feature = "float")]
+pub use beat_detector::{
+    BeatDetector, BeatDetectorConst, BeatInfo, FrequencyWeighting, WarmState,
+    DEFAULT_SEARCH_OVERLAP, DEFAULT_STATISTICS_DECAY,
+};
+#[cfg(feature = "float")]
+pub use beat_intensity::{BeatIntensity, IntensityCurve};
+#[cfg(feature = "float")]
+pub use beat_log::{BeatLog, PruningPolicy, DEFAULT_BEAT_LOG_CAPACITY};
+#[cfg(feature = "float")]
+pub use calibration::{Calibration, CalibrationError, CALIBRATION_LEN};
+#[cfg(feature = "float")]
+pub use custom_filter::{
+    BiquadCoefficients, CustomFilter, CustomFilterError, MAX_CUSTOM_FILTER_STAGES,
+};
+#[cfg(feature = "float")]
+pub use decision_trace::{Decision, DecisionTrace, DecisionTraceEntry, DECISION_TRACE_CAPACITY};
+#[cfg(feature = "float")]
+pub use diagnosis::{Diagnosis, DiagnosticIssue};
+#[cfg(feature = "float")]
+pub use drop_tracker::{DropConfig, DropPhase, DropTracker, DropTransition};
+#[cfg(feature = "float")]
+pub use echo_canceller::{EchoCanceller, DEFAULT_ECHO_STEP_SIZE, DEFAULT_ECHO_TAPS};
+pub use energy_detector::{
+    EnergyBeat, EnergyBeatDetector, DEFAULT_ENERGY_MIN_LEVEL_Q15, DEFAULT_ENERGY_THRESHOLD_X16,
+};
+#[cfg(feature = "float")]
+pub use envelope_iterator::{EnvelopeConfig, EnvelopeInfo, MergePolicy};
+// Implementation detail of the detector. Only public for compatibility, it
+// isn't part of the documented API.
+#[cfg(feature = "float")]
+#[doc(hidden)]
+pub use envelope_iterator::EnvelopeIterator;
+#[cfg(feature = "float")]
+pub use error::Error;
+#[cfg(feature = "float")]
+pub use external_sync::{
+    AccentTracker, BeatSlotAccent, ExternalClock, MidiClock, DEFAULT_ACCENT_WINDOW,
+};
+#[cfg(feature = "float")]
+pub use fingerprint::{
+    BeatFingerprint, FingerprintHistory, DEFAULT_FINGERPRINT_HISTORY, FINGERPRINT_BANDS,
+};
+#[cfg(feature = "float")]
+pub use heartbeat::{Heartbeat, HeartbeatGenerator};
+#[cfg(feature = "float")]
+pub use hum_filter::MainsFrequency;
+#[cfg(feature = "float")]
+pub use key::{Key, KeyEstimator, Scale, DEFAULT_KEY_FRAMES, DEFAULT_KEY_WINDOW};
+#[cfg(feature = "float")]
+pub use level::ChunkLevel;
+#[cfg(feature = "float")]
+pub use mixer::{MixIter, Mixer};
+#[cfg(feature = "float")]
+pub use multi_source_detector::{MultiSourceDetector, SourceBeatInfo, DEFAULT_DEDUP_WINDOW};
+#[cfg(feature = "float")]
+pub use noise_profile::{NoiseProfile, NoiseProfileLearner, NOISE_PROFILE_BANDS};
+pub use pwm::{PwmBeatPulse, PwmCurve};
+#[cfg(feature = "float")]
+pub use root_iterator::DEFAULT_SCAN_STRIDE;
+#[cfg(feature = "float")]
+pub use scene::{AudioFeatures, DefaultSceneMapping, Hsv, Scene, SceneMapping, PALETTE_SIZE};
+#[cfg(feature = "float")]
+pub use sensor_adapter::SensorAdapter;
+pub use spsc::{QueueFullError, SampleConsumer, SampleProducer, SampleQueue};
+#[cfg(feature = "float")]
+pub use stereo_beat_detector::StereoBeatDetector;
+#[cfg(feature = "float")]
+pub use tempo::{
+    BeatInterval, IntervalStatus, MusicalPosition, TempoConfig, TempoEstimator, TempoSmoothing,
+    HIGHEST_BPM, LOWEST_BPM, MAX_MEDIAN_INTERVALS,
+};
+#[cfg(feature = "std")]
+pub use timecode::{
+    FrameRate, LtcEncoder, MtcMessage, Timecode, TimecodeConfig, TimecodeGenerator,
+};
+#[cfg(feature = "float")]
+pub use uniforms::{BeatUniforms, UniformProvider, DEFAULT_ENERGY_RELEASE};
+
+#[cfg(feature = "float")]
+use max_min_iterator::MaxMinIterator;
+#[cfg(feature = "float")]
+use root_iterator::RootIterator;
+
+#[cfg(all(test, feature = "float"))]
+mod tests {
+    use super::*;
+    use crate::audio_history::AudioHistory;
+    use crate::max_min_iterator::MaxMinIterator;
+    use crate::test_utils;
+    use std::vec::Vec;
+
+    fn _print_sample_stats((samples, header): (Vec<i16>, hound::WavSpec)) {
+        let mut history = AudioHistory::new(header.sample_rate as f32);
+        history.update(samples.iter().copied());
+
+        let all_peaks =
+            MaxMinIterator::new(&history, None, DEFAULT_SCAN_STRIDE).collect::<Vec<_>>();
+
+        let abs_peak_value_iter = all_peaks.iter().map(|info| info.value_abs);
+
+        let max: i16 = abs_peak_value_iter.clone().max().unwrap();
+        let min: i16 = abs_peak_value_iter.clone().min().unwrap();
+
+        let avg: i16 =
+            (abs_peak_value_iter.map(|v| v as u64).sum::<u64>() / all_peaks.len() as u64) as i16;
+
+        let mut all_peaks_sorted = all_peaks.clone();
+        all_peaks_sorted.sort_by(|a, b| a.value_abs.partial_cmp(&b.value_abs).unwrap());
+
+        let median: i16 = all_peaks_sorted[all_peaks_sorted.len() / 2].value_abs;
+
+        eprintln!("max abs peak     : {max:.3}");
+        eprintln!("min abs peak     : {min:.3}");
+        eprintln!("average abs peak : {avg:.3}");
+        eprintln!("median abs peak  : {median:.3}");
+        eprintln!("max / avg peak   : {:.3}", max / avg);
+        eprintln!("max / median peak: {:.3}", max / median);
+        eprintln!(
+            "peaks abs        : {:#.3?}",
+            all_peaks
+                .iter()
+                .map(|info| info.value_abs)
+                .collect::<Vec<_>>()
+        );
+        eprintln!(
+            "peak next_to_curr ratio: {:#.3?}",
+            all_peaks
+                .iter()
+                .zip(all_peaks.iter().skip(1))
+                .map(|(current, next)| { next.value_abs / current.value_abs })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// This just prints a few statistics of the used sample. This helps to
+    /// understand characteristics of certain properties in a sample, such as
+    /// the characteristic of an envelope.
+    #[test]
+    fn print_holiday_single_beat_stats() {
+        eprintln!("holiday stats (single beat):");
+        _print_sample_stats(test_utils::samples::holiday_single_beat())
+    }
+
+    /// This just prints a few statistics of the used sample. This helps to
+    /// understand characteristics of certain properties in a sample, such as
+    /// the characteristic of an envelope.
+    #[test]
+    fn print_sample1_single_beat_stats() {
+        eprintln!("sample1 stats (single beat):");
+        _print_sample_stats(test_utils::samples::sample1_single_beat())
+    }
+}
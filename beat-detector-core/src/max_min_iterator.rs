@@ -22,10 +22,10 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
 
+use crate::audio_history::BUFFER_STORAGE_SIZE;
 use crate::RootIterator;
 use crate::{AudioHistory, SampleInfo};
 use core::cmp::Ordering;
-use ringbuffer::RingBuffer;
 
 // const IGNORE_NOISE_THRESHOLD: f32 = 0.05;
 
@@ -35,50 +35,83 @@ use ringbuffer::RingBuffer;
 /// history object. However, once the audio history was updated, a new iterator
 /// must be created.
 #[derive(Debug, Clone)]
-pub struct MaxMinIterator<'a> {
+pub struct MaxMinIterator<'a, const N: usize = BUFFER_STORAGE_SIZE> {
     index: usize,
-    buffer: &'a AudioHistory,
+    buffer: &'a AudioHistory<N>,
+    scan_stride: usize,
 }
 
-impl<'a> MaxMinIterator<'a> {
+impl<'a, const N: usize> MaxMinIterator<'a, N> {
     /// Creates a new iterator. Immediately moves the index to point to the
     /// next root of the wave. This way, we prevent detection of
     /// "invalid/false peaks" before the first root has been found.
-    pub fn new(buffer: &'a AudioHistory, begin_index: Option<usize>) -> Self {
+    ///
+    /// `scan_stride` is the amount of samples to advance per step. A higher
+    /// value means less precision but also fewer iterations.
+    pub fn new(
+        buffer: &'a AudioHistory<N>,
+        begin_index: Option<usize>,
+        scan_stride: usize,
+    ) -> Self {
         let index = begin_index.unwrap_or(0);
-        assert!(index < buffer.data().len());
-        let index = RootIterator::new(buffer, Some(index))
+        assert!(index < buffer.len());
+        let index = RootIterator::new(buffer, Some(index), scan_stride)
             .next()
             .map(|info| info.index)
-            .unwrap_or_else(|| buffer.data().len() - 1);
-        Self { buffer, index }
+            .unwrap_or_else(|| buffer.len() - 1);
+        Self {
+            buffer,
+            index,
+            scan_stride,
+        }
+    }
+
+    /// Creates a new iterator that continues the search at the given index,
+    /// which must be the index of a root. This is the counterpart of
+    /// [`Self::index`] to resume an iteration on an updated audio history.
+    pub(crate) fn continue_at(
+        buffer: &'a AudioHistory<N>,
+        index: usize,
+        scan_stride: usize,
+    ) -> Self {
+        assert!(index < buffer.len());
+        assert!(scan_stride > 0);
+        Self {
+            buffer,
+            index,
+            scan_stride,
+        }
+    }
+
+    /// Returns the index where the search for the next peak begins.
+    pub(crate) const fn index(&self) -> usize {
+        self.index
     }
 }
 
-impl Iterator for MaxMinIterator<'_> {
+impl<const N: usize> Iterator for MaxMinIterator<'_, N> {
     type Item = SampleInfo;
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        debug_assert!(self.index < self.buffer.data().len());
-        if self.index == self.buffer.data().len() - 1 {
+        debug_assert!(self.index < self.buffer.len());
+        if self.index == self.buffer.len() - 1 {
             return None;
         }
 
         let begin_index = self.index;
-        let end_index = RootIterator::new(self.buffer, Some(begin_index))
+        let end_index = RootIterator::new(self.buffer, Some(begin_index), self.scan_stride)
             .next()?
             .index;
         let sample_count = end_index - begin_index;
 
         let max_or_min = self
             .buffer
-            .data()
-            .iter()
+            .samples()
             .enumerate()
             .skip(begin_index)
             .take(sample_count)
-            .step_by(10)
+            .step_by(self.scan_stride)
             .max_by(|(_x_index, &x_value), (_y_index, &y_value)| {
                 if x_value.abs() > y_value.abs() {
                     Ordering::Greater
@@ -98,6 +131,7 @@ impl Iterator for MaxMinIterator<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::root_iterator::DEFAULT_SCAN_STRIDE;
     use crate::test_utils;
     use crate::util::i16_sample_to_f32;
     use std::vec::Vec;
@@ -108,7 +142,7 @@ mod tests {
         let mut history = AudioHistory::new(header.sample_rate as f32);
         history.update(samples.iter().copied());
 
-        let iter = MaxMinIterator::new(&history, None);
+        let iter = MaxMinIterator::new(&history, None, DEFAULT_SCAN_STRIDE);
         #[rustfmt::skip]
         assert_eq!(
             iter.map(|info| (info.total_index, i16_sample_to_f32(info.value)))
@@ -0,0 +1,163 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`Mixer`].
+
+/// Mixes multiple mono audio sources with per-source gains into one signal.
+///
+/// A typical use case is an installation with a room microphone and a
+/// microphone in the DJ booth. The mixed signal can then be fed into a single
+/// [`BeatDetector`].
+///
+/// The sum of all sources is clamped to the `i16` range, so loud sources
+/// clip instead of overflowing.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BeatDetector, Mixer};
+/// let room_samples = [0, 500, -800, 700 /*, ... */];
+/// let booth_samples = [0, 200, -300, 100 /*, ... */];
+/// let mixer = Mixer::new([1.0, 0.5]);
+/// let mut detector = BeatDetector::new(44100.0, true);
+///
+/// // TODO regularly call this with the latest audio data.
+/// let is_beat = detector.update_and_detect_beat(mixer.mix_iter([
+///     room_samples.iter().copied(),
+///     booth_samples.iter().copied(),
+/// ]));
+/// ```
+///
+/// [`BeatDetector`]: crate::BeatDetector
+#[derive(Debug, Clone)]
+pub struct Mixer<const N: usize> {
+    gains: [f32; N],
+}
+
+impl<const N: usize> Mixer<N> {
+    /// Creates a new mixer with the given gain for each source. A gain of
+    /// `1.0` keeps the source unchanged.
+    pub fn new(gains: [f32; N]) -> Self {
+        assert!(N > 0, "Need at least one source");
+        gains.iter().copied().for_each(assert_valid_gain);
+        Self { gains }
+    }
+
+    /// Returns the gains of all sources.
+    pub const fn gains(&self) -> &[f32; N] {
+        &self.gains
+    }
+
+    /// Updates the gain of the given source.
+    pub fn set_gain(&mut self, source: usize, gain: f32) {
+        assert_valid_gain(gain);
+        self.gains[source] = gain;
+    }
+
+    /// Mixes the samples of all sources that reflect the same point in time
+    /// into one sample.
+    #[inline]
+    pub fn mix(&self, samples: [i16; N]) -> i16 {
+        let sum = samples
+            .iter()
+            .zip(self.gains.iter())
+            .map(|(&sample, &gain)| sample as f32 * gain)
+            .sum::<f32>();
+        // Clipping protection. The cast saturates, but we want to be explicit.
+        sum.clamp(i16::MIN as f32, i16::MAX as f32) as i16
+    }
+
+    /// Returns an iterator that mixes the given sources sample by sample. The
+    /// iterator ends as soon as one of the sources ends.
+    pub const fn mix_iter<I: Iterator<Item = i16>>(&self, sources: [I; N]) -> MixIter<'_, I, N> {
+        MixIter {
+            mixer: self,
+            sources,
+        }
+    }
+}
+
+fn assert_valid_gain(gain: f32) {
+    assert!(
+        gain.is_finite() && gain >= 0.0,
+        "gain must be finite and not negative"
+    );
+}
+
+/// Iterator returned by [`Mixer::mix_iter`].
+#[derive(Debug)]
+pub struct MixIter<'a, I: Iterator<Item = i16>, const N: usize> {
+    mixer: &'a Mixer<N>,
+    sources: [I; N],
+}
+
+impl<I: Iterator<Item = i16>, const N: usize> Iterator for MixIter<'_, I, N> {
+    type Item = i16;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut samples = [0; N];
+        for (sample, source) in samples.iter_mut().zip(self.sources.iter_mut()) {
+            *sample = source.next()?;
+        }
+        Some(self.mixer.mix(samples))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn mix_applies_gains() {
+        let mixer = Mixer::new([1.0, 0.5]);
+        check!(mixer.mix([0, 0]) == 0);
+        check!(mixer.mix([100, 100]) == 150);
+        check!(mixer.mix([-100, 100]) == -50);
+        check!(Mixer::new([0.0, 1.0]).mix([1000, -20]) == -20);
+    }
+
+    #[test]
+    fn mix_clips_instead_of_overflowing() {
+        let mixer = Mixer::new([1.0, 1.0]);
+        check!(mixer.mix([i16::MAX, i16::MAX]) == i16::MAX);
+        check!(mixer.mix([i16::MIN, i16::MIN]) == i16::MIN);
+        check!(mixer.mix([i16::MAX, i16::MIN]) == -1);
+    }
+
+    #[test]
+    fn mix_iter_ends_with_shortest_source() {
+        let mut mixer = Mixer::new([1.0, 1.0]);
+        mixer.set_gain(1, 2.0);
+        let mixed = mixer
+            .mix_iter([[1, 2, 3].iter().copied(), [10, 20].iter().copied()])
+            .collect::<Vec<_>>();
+        check!(mixed == [21, 42]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_gain_is_rejected() {
+        let _ = Mixer::new([1.0, -1.0]);
+    }
+}
@@ -0,0 +1,226 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`MultiSourceDetector`].
+
+use crate::{BeatDetector, BeatInfo};
+use core::time::Duration;
+
+/// Default time window in which beats from different sources are considered
+/// as the same musical event.
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_millis(50);
+
+/// A beat that was detected in a specific source of a
+/// [`MultiSourceDetector`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SourceBeatInfo {
+    /// Index of the source the beat was detected in.
+    pub source: usize,
+    /// The beat itself. Timestamps are relative to the beginning of the
+    /// source.
+    pub beat: BeatInfo,
+}
+
+/// Runs one independent [`BeatDetector`] per audio source and merges their
+/// beats into a single stream of labelled beats.
+///
+/// This is an alternative to mixing all sources into one signal (see
+/// [`Mixer`]). Beats of different sources that are less than the dedup window
+/// apart are considered as the same musical event. Only the first of them is
+/// reported.
+///
+/// All sources are expected to be started at the same time, so that their
+/// timestamps are comparable.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::MultiSourceDetector;
+/// let room_samples = [0, 500, -800, 700 /*, ... */];
+/// let booth_samples = [0, 200, -300, 100 /*, ... */];
+/// let mut detector = MultiSourceDetector::new([44100.0, 48000.0], true);
+///
+/// // TODO regularly call this with the latest audio data.
+/// detector.update_and_detect_beats(
+///     [room_samples.iter().copied(), booth_samples.iter().copied()],
+///     |info| println!("beat in source {}: {:?}", info.source, info.beat),
+/// );
+/// ```
+///
+/// [`Mixer`]: crate::Mixer
+#[derive(Debug)]
+pub struct MultiSourceDetector<const N: usize> {
+    detectors: [BeatDetector; N],
+    dedup_window: Duration,
+    /// The most recently reported beat.
+    previous_beat: Option<SourceBeatInfo>,
+}
+
+impl<const N: usize> MultiSourceDetector<N> {
+    /// Creates a new detector with one [`BeatDetector`] per source. See
+    /// [`BeatDetector::new`] for `needs_lowpass_filter`.
+    pub fn new(sampling_frequencies_hz: [f32; N], needs_lowpass_filter: bool) -> Self {
+        assert!(N > 0, "Need at least one source");
+        Self {
+            detectors: sampling_frequencies_hz
+                .map(|frequency| BeatDetector::new(frequency, needs_lowpass_filter)),
+            dedup_window: DEFAULT_DEDUP_WINDOW,
+            previous_beat: None,
+        }
+    }
+
+    /// Sets the time window in which beats from different sources are
+    /// considered as the same musical event. A window of zero disables the
+    /// deduplication.
+    pub fn set_dedup_window(&mut self, dedup_window: Duration) {
+        self.dedup_window = dedup_window;
+    }
+
+    /// Returns the detector of the given source.
+    pub const fn detector(&self, source: usize) -> &BeatDetector {
+        &self.detectors[source]
+    }
+
+    /// Consumes the latest audio data of the given source and returns a beat,
+    /// if the source's detector found one that is not a duplicate of a beat
+    /// that was recently reported for another source.
+    ///
+    /// See [`BeatDetector::update_and_detect_beat`].
+    pub fn update_and_detect_beat(
+        &mut self,
+        source: usize,
+        mono_samples_iter: impl Iterator<Item = i16>,
+    ) -> Option<SourceBeatInfo> {
+        let beat = self.detectors[source].update_and_detect_beat(mono_samples_iter)?;
+        let info = SourceBeatInfo { source, beat };
+
+        if self.is_duplicate(&info) {
+            log::debug!("Dropping beat of source {source} as duplicate");
+            return None;
+        }
+
+        self.previous_beat.replace(info);
+        Some(info)
+    }
+
+    /// Consumes the latest audio data of all sources and invokes the callback
+    /// for each reported beat. See [`Self::update_and_detect_beat`].
+    pub fn update_and_detect_beats<I: Iterator<Item = i16>>(
+        &mut self,
+        mono_samples_iters: [I; N],
+        mut on_beat: impl FnMut(SourceBeatInfo),
+    ) {
+        for (source, mono_samples_iter) in mono_samples_iters.into_iter().enumerate() {
+            if let Some(info) = self.update_and_detect_beat(source, mono_samples_iter) {
+                on_beat(info);
+            }
+        }
+    }
+
+    /// Returns whether the beat belongs to the same musical event as the
+    /// previously reported beat of another source.
+    fn is_duplicate(&self, info: &SourceBeatInfo) -> bool {
+        self.previous_beat.is_some_and(|previous| {
+            let previous_timestamp = previous.beat.timestamp();
+            let timestamp = info.beat.timestamp();
+            let distance = if timestamp > previous_timestamp {
+                timestamp - previous_timestamp
+            } else {
+                previous_timestamp - timestamp
+            };
+            previous.source != info.source && distance < self.dedup_window
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use std::vec::Vec;
+
+    fn simulate_dynamic_audio_sources(
+        chunk_size: usize,
+        samples: [&[i16]; 2],
+        detector: &mut MultiSourceDetector<2>,
+    ) -> Vec<(usize, u64)> {
+        let mut beats = Vec::new();
+        samples[0]
+            .chunks(chunk_size)
+            .zip(samples[1].chunks(chunk_size))
+            .for_each(|(chunk_a, chunk_b)| {
+                detector.update_and_detect_beats(
+                    [chunk_a.iter().copied(), chunk_b.iter().copied()],
+                    |info| beats.push((info.source, info.beat.max.total_index)),
+                )
+            });
+        beats
+    }
+
+    #[test]
+    fn is_send_and_sync() {
+        fn accept<I: Send + Sync>() {}
+
+        accept::<MultiSourceDetector<2>>();
+    }
+
+    #[test]
+    fn duplicates_across_sources_are_dropped() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sampling_rate = header.sample_rate as f32;
+
+        let mut detector = MultiSourceDetector::new([sampling_rate; 2], true);
+        assert_eq!(
+            simulate_dynamic_audio_sources(2048, [&samples, &samples], &mut detector),
+            &[
+                (0, 31337),
+                (0, 47167),
+                (0, 65927),
+                (0, 84217),
+                (0, 102107),
+                (0, 120247),
+                (0, 138557)
+            ]
+        );
+    }
+
+    #[test]
+    fn beats_of_all_sources_are_reported_without_dedup_window() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sampling_rate = header.sample_rate as f32;
+        let silence = [0].repeat(samples.len());
+
+        let mut detector = MultiSourceDetector::new([sampling_rate; 2], true);
+        detector.set_dedup_window(Duration::ZERO);
+        let beats = simulate_dynamic_audio_sources(2048, [&samples, &samples], &mut detector);
+        assert_eq!(beats.len(), 14);
+        assert_eq!(beats[0], (0, 31337));
+        assert_eq!(beats[1], (1, 31337));
+
+        // A silent source doesn't contribute beats.
+        let mut detector = MultiSourceDetector::new([sampling_rate; 2], true);
+        let beats = simulate_dynamic_audio_sources(2048, [&silence, &samples], &mut detector);
+        assert_eq!(beats.len(), 7);
+        assert!(beats.iter().all(|&(source, _)| source == 1));
+    }
+}
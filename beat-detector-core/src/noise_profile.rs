@@ -0,0 +1,337 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`NoiseProfile`].
+
+use crate::custom_filter::FilterStage;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::util::saturating_f32_to_i16;
+use crate::SnapshotError;
+use biquad::{Coefficients, ToHertz, Type, Q_BUTTERWORTH_F32};
+use core::time::Duration;
+
+/// Amount of frequency bands of a [`NoiseProfile`].
+pub const NOISE_PROFILE_BANDS: usize = 4;
+
+/// Edges between the frequency bands. The first band starts at 0 Hz, the last
+/// band ends at the Nyquist frequency. The lowest band covers the frequencies
+/// that the beat detection works on.
+const BAND_EDGES_HZ: [f32; NOISE_PROFILE_BANDS - 1] = [150.0, 600.0, 2400.0];
+
+/// Factor by that the noise level is over-estimated during the suppression.
+/// Noise is not stationary, so a band must be clearly louder than the learned
+/// noise level to pass.
+const OVER_SUBTRACTION: f32 = 2.0;
+
+/// Time constant of the level follower of each band. Short enough to not
+/// smear the begin of beats.
+const LEVEL_TIME_CONSTANT: Duration = Duration::from_millis(10);
+
+/// Splits audio into [`NOISE_PROFILE_BANDS`] frequency bands.
+#[derive(Debug, Clone)]
+struct FilterBank {
+    filters: [FilterStage; NOISE_PROFILE_BANDS],
+}
+
+impl FilterBank {
+    fn new(sampling_frequency: f32) -> Self {
+        // Filters must stay below the Nyquist frequency.
+        let max_frequency = sampling_frequency * 0.45;
+        let filter = |ty, frequency: f32, q| {
+            let coefficients = Coefficients::<f32>::from_params(
+                ty,
+                sampling_frequency.hz(),
+                frequency.min(max_frequency).hz(),
+                q,
+            )
+            .expect("Should be valid parameters");
+            FilterStage::new(coefficients)
+        };
+        let bandpass = |from: f32, to: f32| {
+            let center = libm::sqrtf(from * to);
+            filter(Type::BandPass, center, center / (to - from))
+        };
+        Self {
+            filters: [
+                filter(Type::LowPass, BAND_EDGES_HZ[0], Q_BUTTERWORTH_F32),
+                bandpass(BAND_EDGES_HZ[0], BAND_EDGES_HZ[1]),
+                bandpass(BAND_EDGES_HZ[1], BAND_EDGES_HZ[2]),
+                filter(Type::HighPass, BAND_EDGES_HZ[2], Q_BUTTERWORTH_F32),
+            ],
+        }
+    }
+
+    fn split(&mut self, sample: f32) -> [f32; NOISE_PROFILE_BANDS] {
+        let mut bands = [0.0; NOISE_PROFILE_BANDS];
+        for (band, filter) in bands.iter_mut().zip(self.filters.iter_mut()) {
+            *band = filter.run(sample);
+        }
+        bands
+    }
+}
+
+/// The noise levels of an audio source during silence, such as the hiss of a
+/// cheap USB microphone. Used by the [`BeatDetector`] to suppress that noise.
+///
+/// The profile holds the RMS level of each of the [`NOISE_PROFILE_BANDS`]
+/// frequency bands. Learn it with a [`NoiseProfileLearner`] while no music is
+/// playing.
+///
+/// [`BeatDetector`]: crate::BeatDetector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseProfile {
+    sampling_frequency: f32,
+    levels: [f32; NOISE_PROFILE_BANDS],
+}
+
+impl NoiseProfile {
+    /// Creates a profile from previously learned [levels], e.g., to restore a
+    /// profile from a configuration file.
+    ///
+    /// [levels]: Self::levels
+    pub fn from_levels(sampling_frequency: f32, levels: [f32; NOISE_PROFILE_BANDS]) -> Self {
+        assert!(sampling_frequency > 0.0);
+        assert!(levels
+            .iter()
+            .all(|level| level.is_finite() && *level >= 0.0));
+        Self {
+            sampling_frequency,
+            levels,
+        }
+    }
+
+    /// Returns the sampling frequency of the audio the profile was learned
+    /// from.
+    pub const fn sampling_frequency(&self) -> f32 {
+        self.sampling_frequency
+    }
+
+    /// Returns the RMS noise level of each frequency band, from low to high
+    /// frequencies.
+    pub const fn levels(&self) -> [f32; NOISE_PROFILE_BANDS] {
+        self.levels
+    }
+}
+
+/// Learns a [`NoiseProfile`] from audio that only contains noise.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BeatDetector, NoiseProfileLearner};
+///
+/// let mut learner = NoiseProfileLearner::new(44100.0);
+/// // TODO feed a few seconds of "silence" from the microphone.
+/// learner.update([12, -40, 31, -7].iter().copied());
+///
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// detector.set_noise_profile(learner.finish());
+/// ```
+#[derive(Debug, Clone)]
+pub struct NoiseProfileLearner {
+    sampling_frequency: f32,
+    filter_bank: FilterBank,
+    /// Sum of the squared samples of each band.
+    sums: [f64; NOISE_PROFILE_BANDS],
+    count: u64,
+}
+
+impl NoiseProfileLearner {
+    /// Creates a new learner for audio with the given sampling frequency.
+    pub fn new(sampling_frequency: f32) -> Self {
+        Self {
+            sampling_frequency,
+            filter_bank: FilterBank::new(sampling_frequency),
+            sums: [0.0; NOISE_PROFILE_BANDS],
+            count: 0,
+        }
+    }
+
+    /// Consumes the next mono samples of the noise.
+    pub fn update(&mut self, mono_samples_iter: impl Iterator<Item = i16>) {
+        for sample in mono_samples_iter {
+            let bands = self.filter_bank.split(sample as f32);
+            for (sum, band) in self.sums.iter_mut().zip(bands) {
+                *sum += (band * band) as f64;
+            }
+            self.count += 1;
+        }
+    }
+
+    /// Returns the duration of the consumed noise. A few seconds are
+    /// sufficient.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.count as f64 / self.sampling_frequency as f64)
+    }
+
+    /// Returns the learned profile or `None` if no audio was consumed.
+    pub fn finish(&self) -> Option<NoiseProfile> {
+        if self.count == 0 {
+            return None;
+        }
+        let levels = self
+            .sums
+            .map(|sum| libm::sqrt(sum / self.count as f64) as f32);
+        Some(NoiseProfile::from_levels(self.sampling_frequency, levels))
+    }
+}
+
+/// Suppresses the noise of a [`NoiseProfile`] in audio.
+///
+/// Each band is attenuated depending on how much louder it currently is than
+/// its noise level. Bands that are much louder than the noise pass
+/// unchanged, so that beats keep their shape.
+#[derive(Debug, Clone)]
+pub(crate) struct NoiseSuppressor {
+    profile: NoiseProfile,
+    filter_bank: FilterBank,
+    /// Smoothed squared level of each band.
+    levels_squared: [f32; NOISE_PROFILE_BANDS],
+    /// Smoothing factor of the level follower.
+    alpha: f32,
+}
+
+impl NoiseSuppressor {
+    pub(crate) fn new(profile: NoiseProfile) -> Self {
+        let samples = LEVEL_TIME_CONSTANT.as_secs_f32() * profile.sampling_frequency;
+        Self {
+            profile,
+            filter_bank: FilterBank::new(profile.sampling_frequency),
+            levels_squared: [0.0; NOISE_PROFILE_BANDS],
+            alpha: 1.0 / samples.max(1.0),
+        }
+    }
+
+    pub(crate) const fn profile(&self) -> &NoiseProfile {
+        &self.profile
+    }
+
+    /// Returns the sample with suppressed noise.
+    pub(crate) fn process(&mut self, sample: i16) -> i16 {
+        let bands = self.filter_bank.split(sample as f32);
+        // Only subtract what is removed from each band. If no band is
+        // attenuated, the sample passes unchanged.
+        let mut removed = 0.0;
+        for ((band, level_squared), noise_level) in bands
+            .iter()
+            .zip(self.levels_squared.iter_mut())
+            .zip(self.profile.levels)
+        {
+            *level_squared += self.alpha * (band * band - *level_squared);
+            let level = libm::sqrtf(*level_squared);
+            let gain = if level > 0.0 {
+                (1.0 - OVER_SUBTRACTION * noise_level / level).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            removed += (1.0 - gain) * band;
+        }
+        saturating_f32_to_i16(sample as f32 - removed)
+    }
+
+    /// Writes the state of the filter bank and of the level followers. The
+    /// profile isn't included.
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        for filter in &self.filter_bank.filters {
+            filter.write_snapshot(writer);
+        }
+        for level_squared in self.levels_squared {
+            writer.f32(level_squared);
+        }
+    }
+
+    pub(crate) fn read_snapshot(
+        &mut self,
+        reader: &mut SnapshotReader,
+    ) -> Result<(), SnapshotError> {
+        for filter in &mut self.filter_bank.filters {
+            filter.read_snapshot(reader)?;
+        }
+        for level_squared in &mut self.levels_squared {
+            *level_squared = reader.f32()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::vec::Vec;
+
+    fn hiss(len: usize) -> Vec<i16> {
+        let mut rng = StdRng::seed_from_u64(42);
+        (0..len).map(|_| rng.gen_range(-1000..=1000)).collect()
+    }
+
+    fn rms(samples: &[i16]) -> f32 {
+        let sum = samples
+            .iter()
+            .map(|&s| (s as f32) * (s as f32))
+            .sum::<f32>();
+        libm::sqrtf(sum / samples.len() as f32)
+    }
+
+    #[test]
+    fn learner_finds_band_levels() {
+        let mut learner = NoiseProfileLearner::new(44100.0);
+        assert_eq!(learner.finish(), None);
+
+        learner.update(hiss(44100).into_iter());
+        assert_eq!(learner.duration(), Duration::from_secs(1));
+        let levels = learner.finish().unwrap().levels();
+        // White noise has most of its energy in the wide, high bands.
+        assert!(levels.iter().all(|&level| level > 0.0));
+        assert!(levels[0] < levels[3]);
+    }
+
+    #[test]
+    fn suppressor_removes_noise_but_keeps_signal() {
+        let noise = hiss(44100);
+        let mut learner = NoiseProfileLearner::new(44100.0);
+        learner.update(noise.iter().copied());
+        let profile = learner.finish().unwrap();
+
+        let mut suppressor = NoiseSuppressor::new(profile);
+        let suppressed = noise
+            .iter()
+            .map(|&sample| suppressor.process(sample))
+            .collect::<Vec<_>>();
+        assert!(rms(&suppressed[4410..]) < rms(&noise[4410..]) / 2.0);
+
+        // A loud bass tone with noise on top.
+        let mut suppressor = NoiseSuppressor::new(profile);
+        let signal = (0..44100)
+            .map(|i| libm::sinf(i as f32 * 60.0 * 2.0 * core::f32::consts::PI / 44100.0) * 20000.0)
+            .zip(hiss(44100))
+            .map(|(tone, noise)| tone as i16 + noise)
+            .collect::<Vec<_>>();
+        let suppressed = signal
+            .iter()
+            .map(|&sample| suppressor.process(sample))
+            .collect::<Vec<_>>();
+        let ratio = rms(&suppressed[4410..]) / rms(&signal[4410..]);
+        assert!((0.95..=1.05).contains(&ratio), "{ratio}");
+    }
+}
@@ -0,0 +1,443 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`PeakCache`].
+
+use crate::diagnosis::NOISE_FLOOR;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::{AmplitudeHistogram, AudioHistory, MaxMinIterator, SampleInfo, SnapshotError};
+use core::ptr::addr_of_mut;
+
+/// Maximum amount of peaks that are tracked. This is plenty for the audio
+/// history of typical (lowpassed) music. If there are more peaks in the audio
+/// history, the oldest ones are dropped from the cache.
+pub(crate) const MAX_TRACKED_PEAKS: usize = 512;
+
+/// A peak of the wave in the audio history.
+#[derive(Copy, Clone, Debug, Default)]
+struct Peak {
+    /// Total index of the peak itself.
+    total_index: u64,
+    /// Distance from the root where the scan for this peak began to the peak.
+    begin_offset: u32,
+    value_abs: i16,
+}
+
+impl Peak {
+    const fn begin_total_index(&self) -> u64 {
+        self.total_index - self.begin_offset as u64
+    }
+}
+
+/// Cache of all peaks in the audio history, keyed by their total index,
+/// together with a histogram of their values.
+///
+/// Peaks of old samples don't change when new samples arrive. Therefore, on
+/// each update, only the newly added samples are scanned for peaks and peaks
+/// that left the audio history are dropped. The envelope search then iterates
+/// the cached peaks instead of scanning the audio history again and again.
+#[derive(Debug, Clone)]
+pub(crate) struct PeakCache<const P: usize = MAX_TRACKED_PEAKS> {
+    /// Ringbuffer of the tracked peaks, sorted by their total index.
+    peaks: [Peak; P],
+    /// Index of the oldest peak in `peaks`.
+    head: usize,
+    len: usize,
+    /// Histogram of all absolute peak values.
+    histogram: AmplitudeHistogram,
+    /// Total index of the root where the search for the next peak continues.
+    resume_total_index: Option<u64>,
+    scan_stride: usize,
+    /// Whether each sample above the noise floor is a peak on its own, which
+    /// is the case for an envelope stream that has no roots.
+    samples_are_peaks: bool,
+}
+
+impl<const P: usize> PeakCache<P> {
+    pub fn new(scan_stride: usize) -> Self {
+        Self {
+            peaks: [Peak::default(); P],
+            head: 0,
+            len: 0,
+            histogram: AmplitudeHistogram::new(),
+            resume_total_index: None,
+            scan_stride,
+            samples_are_peaks: false,
+        }
+    }
+
+    /// Like [`Self::new`] but initializes the cache in place, without
+    /// creating the (large) cache on the stack first.
+    ///
+    /// # Safety
+    /// `this` must be valid for writes and properly aligned.
+    pub unsafe fn init_in_place(this: *mut Self, scan_stride: usize) {
+        // SAFETY: Guaranteed by the caller. All-zero is a valid
+        // `Peak::default()`.
+        unsafe {
+            addr_of_mut!((*this).peaks).write_bytes(0, 1);
+            addr_of_mut!((*this).head).write(0);
+            addr_of_mut!((*this).len).write(0);
+            addr_of_mut!((*this).histogram).write(AmplitudeHistogram::new());
+            addr_of_mut!((*this).resume_total_index).write(None);
+            addr_of_mut!((*this).scan_stride).write(scan_stride);
+            addr_of_mut!((*this).samples_are_peaks).write(false);
+        }
+    }
+
+    /// Drops all cached peaks and continues with the given scan stride.
+    pub fn reset(&mut self, scan_stride: usize) {
+        *self = Self {
+            samples_are_peaks: self.samples_are_peaks,
+            ..Self::new(scan_stride)
+        };
+    }
+
+    /// Drops all cached peaks and continues with each sample above the noise
+    /// floor as a peak on its own. This is how an envelope stream, which
+    /// never crosses zero, is analyzed.
+    pub fn use_samples_as_peaks(&mut self) {
+        self.clear();
+        self.resume_total_index = None;
+        self.samples_are_peaks = true;
+    }
+
+    /// Updates the cache with the samples that were added to the audio
+    /// history since the previous update.
+    pub fn update<const N: usize>(&mut self, history: &AudioHistory<N>) {
+        if history.is_empty() {
+            return;
+        }
+
+        if self.samples_are_peaks {
+            self.add_samples(history);
+        } else {
+            self.add_scanned_peaks(history);
+        }
+
+        let oldest_total_index = history.index_to_sample_info(0).total_index;
+        while self.len > 0 && self.peaks[self.head].total_index < oldest_total_index {
+            self.pop();
+        }
+    }
+
+    /// Scans the new samples of the audio history for peaks between roots.
+    fn add_scanned_peaks<const N: usize>(&mut self, history: &AudioHistory<N>) {
+        let resume_index = self
+            .resume_total_index
+            .and_then(|total_index| history.total_index_to_index(total_index))
+            .filter(|&index| index < history.len());
+        let mut peak_iter = match resume_index {
+            Some(index) => MaxMinIterator::continue_at(history, index, self.scan_stride),
+            // Start from scratch, e.g., because the history was overwritten
+            // completely since the previous update.
+            None => {
+                self.clear();
+                MaxMinIterator::new(history, None, self.scan_stride)
+            }
+        };
+
+        loop {
+            let begin_index = peak_iter.index();
+            let Some(info) = peak_iter.next() else {
+                break;
+            };
+            self.push(Peak {
+                total_index: info.total_index,
+                begin_offset: (info.index - begin_index) as u32,
+                value_abs: info.value_abs,
+            });
+        }
+        self.resume_total_index = Some(history.index_to_sample_info(peak_iter.index()).total_index);
+    }
+
+    /// Adds each new sample of the audio history above the noise floor as a
+    /// peak.
+    fn add_samples<const N: usize>(&mut self, history: &AudioHistory<N>) {
+        let oldest_total_index = history.index_to_sample_info(0).total_index;
+        let begin_total_index = match self.resume_total_index {
+            Some(total_index) if total_index >= oldest_total_index => total_index,
+            // Start from scratch, e.g., because the history was overwritten
+            // completely since the previous update.
+            _ => {
+                self.clear();
+                oldest_total_index
+            }
+        };
+
+        let begin_index = (begin_total_index - oldest_total_index) as usize;
+        for index in begin_index..history.len() {
+            let info = history.index_to_sample_info(index);
+            if info.value_abs >= NOISE_FLOOR {
+                self.push(Peak {
+                    total_index: info.total_index,
+                    begin_offset: 0,
+                    value_abs: info.value_abs,
+                });
+            }
+        }
+        self.resume_total_index = Some(oldest_total_index + history.len() as u64);
+    }
+
+    /// Writes the cached peaks and where the scan for peaks continues. The
+    /// scan stride isn't included.
+    pub fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.option(self.resume_total_index, SnapshotWriter::u64);
+        writer.u32(self.len as u32);
+        for pos in 0..self.len {
+            let peak = self.get(pos);
+            writer.u64(peak.total_index);
+            writer.u32(peak.begin_offset);
+            writer.i16(peak.value_abs);
+        }
+    }
+
+    /// Replaces the cached peaks with the ones written by
+    /// [`Self::write_snapshot`].
+    pub fn read_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        let resume_total_index = reader.option(SnapshotReader::u64)?;
+        let len = reader.u32()? as usize;
+        if len > P {
+            return Err(SnapshotError::CapacityTooLarge { capacity: len });
+        }
+        self.clear();
+        let mut previous_total_index = None;
+        for _ in 0..len {
+            let peak = Peak {
+                total_index: reader.u64()?,
+                begin_offset: reader.u32()?,
+                value_abs: reader.i16()?,
+            };
+            // The peaks must be sorted and the begin of their scan must not
+            // underflow.
+            SnapshotReader::check(
+                previous_total_index < Some(peak.total_index)
+                    && peak.begin_offset as u64 <= peak.total_index
+                    && peak.value_abs >= 0,
+            )?;
+            previous_total_index = Some(peak.total_index);
+            self.push(peak);
+        }
+        self.resume_total_index = resume_total_index;
+        Ok(())
+    }
+
+    /// Returns the histogram of all absolute peak values in the audio
+    /// history.
+    pub const fn histogram(&self) -> &AmplitudeHistogram {
+        &self.histogram
+    }
+
+    /// Returns an iterator over the cached peaks, starting with the first peak
+    /// whose scan began at or after the given index of the audio history.
+    ///
+    /// This is the cached equivalent of [`MaxMinIterator::new`].
+    pub fn peaks<'a, const N: usize>(
+        &'a self,
+        history: &'a AudioHistory<N>,
+        begin_index: Option<usize>,
+    ) -> CachedPeaks<'a, N, P> {
+        let pos = begin_index.map_or(0, |index| {
+            let begin_total_index = history.index_to_sample_info(index).total_index;
+            self.partition_point(|peak| peak.begin_total_index() < begin_total_index)
+        });
+        CachedPeaks {
+            cache: self,
+            history,
+            pos,
+        }
+    }
+
+    /// Returns the peak at the given position, where `0` is the oldest peak.
+    fn get(&self, pos: usize) -> &Peak {
+        debug_assert!(pos < self.len);
+        &self.peaks[(self.head + pos) % P]
+    }
+
+    /// Binary search for the first position where `pred` is `false`. The
+    /// peaks must be partitioned accordingly.
+    fn partition_point(&self, pred: impl Fn(&Peak) -> bool) -> usize {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if pred(self.get(mid)) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.histogram.clear();
+    }
+
+    fn push(&mut self, peak: Peak) {
+        if self.len == P {
+            self.pop();
+        }
+        self.peaks[(self.head + self.len) % P] = peak;
+        self.len += 1;
+        self.histogram.add(peak.value_abs);
+    }
+
+    fn pop(&mut self) {
+        debug_assert!(self.len > 0);
+        self.histogram.remove(self.peaks[self.head].value_abs);
+        self.head = (self.head + 1) % P;
+        self.len -= 1;
+    }
+}
+
+/// Iterator over the peaks of a [`PeakCache`]. See [`PeakCache::peaks`].
+#[derive(Debug, Clone)]
+pub(crate) struct CachedPeaks<'a, const N: usize, const P: usize> {
+    cache: &'a PeakCache<P>,
+    history: &'a AudioHistory<N>,
+    /// Position of the next peak in the cache.
+    pos: usize,
+}
+
+impl<const N: usize, const P: usize> Iterator for CachedPeaks<'_, N, P> {
+    type Item = SampleInfo;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.cache.len {
+            return None;
+        }
+        let peak = self.cache.get(self.pos);
+        self.pos += 1;
+        let index = self.history.total_index_to_index(peak.total_index)?;
+        Some(self.history.index_to_sample_info(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::root_iterator::DEFAULT_SCAN_STRIDE;
+    use crate::test_utils;
+    use std::vec::Vec;
+
+    /// Histogram of all peaks, calculated from scratch.
+    fn histogram(history: &AudioHistory) -> AmplitudeHistogram {
+        MaxMinIterator::new(history, None, DEFAULT_SCAN_STRIDE)
+            .map(|info| info.value_abs)
+            .collect()
+    }
+
+    #[test]
+    fn histogram_matches_full_recalculation() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut history = AudioHistory::new(header.sample_rate as f32);
+        let mut stats = PeakCache::<MAX_TRACKED_PEAKS>::new(DEFAULT_SCAN_STRIDE);
+
+        for chunk in samples.chunks(1024) {
+            history.update(chunk.iter().copied());
+            stats.update(&history);
+
+            // With only a few peaks, a single one at the beginning makes a
+            // big difference.
+            if history.len() < history.capacity() {
+                continue;
+            }
+            let expected = histogram(&history);
+            let actual = stats.histogram();
+            // Peaks at the very beginning of the history and the sampling
+            // grid may differ slightly from a scan from scratch.
+            assert!(actual.len().abs_diff(expected.len()) <= 1);
+            for percentile in [0.5, 0.9] {
+                let expected = expected.percentile(percentile).unwrap();
+                let actual = actual.percentile(percentile).unwrap();
+                assert!(expected.abs_diff(actual) <= 512);
+            }
+        }
+    }
+
+    #[test]
+    fn cached_peaks_match_scanned_peaks() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut history = AudioHistory::new(header.sample_rate as f32);
+        let mut cache = PeakCache::<MAX_TRACKED_PEAKS>::new(DEFAULT_SCAN_STRIDE);
+        // The first beat of the sample is at ~0.7s.
+        for chunk in samples[..44100].chunks(1024) {
+            history.update(chunk.iter().copied());
+            cache.update(&history);
+        }
+
+        for begin_index in [None, Some(0), Some(5000), Some(history.len() / 2)] {
+            let scanned = MaxMinIterator::new(&history, begin_index, DEFAULT_SCAN_STRIDE)
+                .map(|info| info.total_index)
+                .collect::<Vec<_>>();
+            let cached = cache
+                .peaks(&history, begin_index)
+                .map(|info| info.total_index)
+                .collect::<Vec<_>>();
+
+            // The sampling grid may differ by a few samples.
+            assert_eq!(scanned.len(), cached.len(), "begin_index={begin_index:?}");
+            scanned.iter().zip(cached).for_each(|(&scanned, cached)| {
+                assert!(scanned.abs_diff(cached) < DEFAULT_SCAN_STRIDE as u64);
+            });
+        }
+    }
+
+    #[test]
+    fn silence_has_no_peaks() {
+        let mut history = AudioHistory::new(44100.0);
+        let mut stats = PeakCache::<MAX_TRACKED_PEAKS>::new(DEFAULT_SCAN_STRIDE);
+        history.update([0; 4096].iter().copied());
+        stats.update(&history);
+        assert!(stats.histogram().is_empty());
+    }
+
+    #[test]
+    fn samples_as_peaks() {
+        let mut history = AudioHistory::<128>::with_capacity(200.0, 84);
+        let mut cache = PeakCache::<MAX_TRACKED_PEAKS>::new(DEFAULT_SCAN_STRIDE);
+        cache.use_samples_as_peaks();
+        let envelope = (0..300_i16).map(|i| (i % 50) * 500).collect::<Vec<_>>();
+        for chunk in envelope.chunks(7) {
+            history.update(chunk.iter().copied());
+            cache.update(&history);
+        }
+
+        // All samples above the noise floor, without gaps or duplicates.
+        let expected = history
+            .samples()
+            .zip(history.index_to_sample_info(0).total_index..)
+            .filter(|(&sample, _)| sample >= NOISE_FLOOR)
+            .map(|(_, total_index)| total_index)
+            .collect::<Vec<_>>();
+        let cached = cache
+            .peaks(&history, None)
+            .map(|info| info.total_index)
+            .collect::<Vec<_>>();
+        assert_eq!(cached, expected);
+        assert_eq!(cache.histogram().len(), expected.len());
+    }
+}
@@ -0,0 +1,153 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`PwmBeatPulse`].
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Shape of the pulse of a [`PwmBeatPulse`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PwmCurve {
+    /// The duty cycle decreases linearly.
+    Linear,
+    /// The duty cycle decreases quadratically. As the human eye perceives
+    /// brightness non-linearly, this looks like a linear fade on an LED.
+    #[default]
+    Quadratic,
+}
+
+/// Converts beats into the duty cycle of a PWM output, e.g., to drive a single
+/// LED or transistor on embedded targets.
+///
+/// On each beat, the duty cycle jumps to the maximum and then fades out
+/// within a fixed amount of timer ticks. It only uses integer math and atomic
+/// loads and stores, so that it also works on targets without an FPU or
+/// without atomic read-modify-write operations, such as the RP2040.
+///
+/// [`Self::trigger`] is supposed to be called from the main loop when a beat
+/// was detected and [`Self::tick`] from a periodic timer interrupt. The
+/// pulse can live in a `static`. Only one context may call [`Self::tick`].
+/// If [`Self::trigger`] runs in an interrupt with a higher priority than
+/// [`Self::tick`], a beat may be lost in rare cases, which is not noticeable
+/// for lighting.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{PwmBeatPulse, PwmCurve};
+///
+/// // 1 kHz timer interrupt, fade out within 200ms.
+/// static PULSE: PwmBeatPulse = PwmBeatPulse::new(u16::MAX, 200, PwmCurve::Quadratic);
+///
+/// // TODO call this when a beat was detected.
+/// PULSE.trigger();
+///
+/// // TODO call this from the timer interrupt.
+/// let duty = PULSE.tick();
+/// assert_eq!(duty, u16::MAX);
+/// ```
+#[derive(Debug)]
+pub struct PwmBeatPulse {
+    max_duty: u16,
+    decay_ticks: u32,
+    curve: PwmCurve,
+    /// Ticks since the latest beat. Saturates at `decay_ticks`.
+    phase: AtomicU32,
+}
+
+impl PwmBeatPulse {
+    /// Creates a new pulse that fades out from `max_duty` to `0` within
+    /// `decay_ticks` calls of [`Self::tick`].
+    pub const fn new(max_duty: u16, decay_ticks: u32, curve: PwmCurve) -> Self {
+        if decay_ticks == 0 {
+            panic!("decay ticks must not be zero");
+        }
+        Self {
+            max_duty,
+            decay_ticks,
+            curve,
+            phase: AtomicU32::new(decay_ticks),
+        }
+    }
+
+    /// Restarts the pulse. Call this on each beat.
+    pub fn trigger(&self) {
+        self.phase.store(0, Ordering::Relaxed);
+    }
+
+    /// Returns the duty cycle for the current tick and advances the phase by
+    /// one tick.
+    pub fn tick(&self) -> u16 {
+        let phase = self.phase.load(Ordering::Relaxed);
+        if phase < self.decay_ticks {
+            self.phase.store(phase + 1, Ordering::Relaxed);
+        }
+        self.duty_at(phase)
+    }
+
+    /// Returns the duty cycle at the given amount of ticks after a beat.
+    pub const fn duty_at(&self, phase: u32) -> u16 {
+        if phase >= self.decay_ticks {
+            return 0;
+        }
+        let remaining = (self.decay_ticks - phase) as u64;
+        let decay_ticks = self.decay_ticks as u64;
+        let max_duty = self.max_duty as u64;
+        // Doesn't overflow, as all factors fit into 32 bits.
+        let linear = max_duty * remaining / decay_ticks;
+        let duty = match self.curve {
+            PwmCurve::Linear => linear,
+            PwmCurve::Quadratic => linear * remaining / decay_ticks,
+        };
+        duty as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn pulse() {
+        let pulse = PwmBeatPulse::new(1000, 4, PwmCurve::Linear);
+        assert_eq!(pulse.tick(), 0);
+
+        pulse.trigger();
+        let duties = (0..6).map(|_| pulse.tick()).collect::<Vec<_>>();
+        assert_eq!(duties, [1000, 750, 500, 250, 0, 0]);
+
+        // Retrigger during the pulse.
+        pulse.trigger();
+        let _ = pulse.tick();
+        pulse.trigger();
+        assert_eq!(pulse.tick(), 1000);
+    }
+
+    #[test]
+    fn quadratic() {
+        let pulse = PwmBeatPulse::new(u16::MAX, u32::MAX, PwmCurve::Quadratic);
+        assert_eq!(pulse.duty_at(0), u16::MAX);
+        assert_eq!(pulse.duty_at(u32::MAX / 2), u16::MAX / 4);
+        assert_eq!(pulse.duty_at(u32::MAX), 0);
+    }
+}
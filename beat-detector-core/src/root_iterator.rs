@@ -21,10 +21,16 @@ LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
+use crate::audio_history::BUFFER_STORAGE_SIZE;
+use crate::diagnosis::NOISE_FLOOR as IGNORE_NOISE_THRESHOLD;
 use crate::{AudioHistory, SampleInfo};
-use ringbuffer::RingBuffer;
 
-const IGNORE_NOISE_THRESHOLD: i16 = (i16::MAX as f32 * 0.05) as i16;
+/// Default amount of samples to advance per step when scanning the audio
+/// history.
+///
+/// Given the very high sampling rate, we can sacrifice a negligible impact on
+/// precision for better performance / fewer iterations.
+pub const DEFAULT_SCAN_STRIDE: usize = 10;
 
 /// The state a sample. Either above x-axis or below.
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -52,38 +58,48 @@ impl From<i16 /* sample */> for State {
 /// history object. However, once the audio history was updated, a new iterator
 /// must be created.
 #[derive(Debug, Clone)]
-pub struct RootIterator<'a> {
+pub struct RootIterator<'a, const N: usize = BUFFER_STORAGE_SIZE> {
     index: usize,
-    buffer: &'a AudioHistory,
+    buffer: &'a AudioHistory<N>,
+    scan_stride: usize,
 }
 
-impl<'a> RootIterator<'a> {
-    pub fn new(buffer: &'a AudioHistory, begin_index: Option<usize>) -> Self {
+impl<'a, const N: usize> RootIterator<'a, N> {
+    /// Creates a new iterator. `scan_stride` is the amount of samples to
+    /// advance per step. A higher value means less precision but also fewer
+    /// iterations.
+    pub fn new(
+        buffer: &'a AudioHistory<N>,
+        begin_index: Option<usize>,
+        scan_stride: usize,
+    ) -> Self {
         let index = begin_index.unwrap_or(0);
-        assert!(index < buffer.data().len());
-        Self { buffer, index }
+        assert!(index < buffer.len());
+        assert!(scan_stride > 0);
+        Self {
+            buffer,
+            index,
+            scan_stride,
+        }
     }
 }
 
-impl Iterator for RootIterator<'_> {
+impl<const N: usize> Iterator for RootIterator<'_, N> {
     type Item = SampleInfo;
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        debug_assert!(self.index < self.buffer.data().len());
-        if self.index == self.buffer.data().len() - 1 {
+        debug_assert!(self.index < self.buffer.len());
+        if self.index == self.buffer.len() - 1 {
             return None;
         }
 
         let mut iter = self
             .buffer
-            .data()
-            .iter()
+            .samples()
             .enumerate()
             .skip(self.index)
-            // Given the very high sampling rate, we can sacrifice a negligible
-            // impact on precision for better performance / fewer iterations.
-            .step_by(10)
+            .step_by(self.scan_stride)
             .skip_while(|(_, &sample)| sample.abs() < IGNORE_NOISE_THRESHOLD);
 
         let initial_state = State::from(iter.next().map(|(_, &sample)| sample)?);
@@ -117,7 +133,7 @@ mod tests {
         let mut history = AudioHistory::new(header.sample_rate as f32);
         history.update(samples.iter().copied());
 
-        let iter = RootIterator::new(&history, None);
+        let iter = RootIterator::new(&history, None, DEFAULT_SCAN_STRIDE);
         #[rustfmt::skip]
         assert_eq!(
             iter.map(|info| (info.total_index, i16_sample_to_f32(info.value))).collect::<Vec<_>>(),
@@ -139,7 +155,11 @@ mod tests {
         let mut history = AudioHistory::new(header.sample_rate as f32);
         history.update(samples.iter().copied());
 
-        let iter = RootIterator::new(&history, Some(929 /* index taken from test above */ + 1));
+        let iter = RootIterator::new(
+            &history,
+            Some(929 /* index taken from test above */ + 1),
+            DEFAULT_SCAN_STRIDE,
+        );
         #[rustfmt::skip]
         assert_eq!(
             iter.map(|info| (info.total_index, i16_sample_to_f32(info.value))).collect::<Vec<_>>(),
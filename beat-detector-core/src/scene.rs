@@ -0,0 +1,250 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`SceneMapping`] and the types around it.
+
+use crate::BeatDetector;
+
+/// Amount of colors of a [`Scene`].
+pub const PALETTE_SIZE: usize = 3;
+
+/// Tempo that maps to the coldest hue in [`DefaultSceneMapping`].
+const SLOW_TEMPO_BPM: f32 = 60.0;
+
+/// Tempo that maps to the warmest hue in [`DefaultSceneMapping`].
+const FAST_TEMPO_BPM: f32 = 180.0;
+
+/// Hue for cold colors (blue).
+const COLD_HUE: f32 = 240.0;
+
+/// Hue if neither tempo nor brightness are known (orange).
+const NEUTRAL_HUE: f32 = 30.0;
+
+/// A color in the HSV color space.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Hsv {
+    /// Hue in degrees in range `0.0..360.0`.
+    pub hue: f32,
+    /// Saturation in range `0.0..=1.0`.
+    pub saturation: f32,
+    /// Value (brightness) in range `0.0..=1.0`.
+    pub value: f32,
+}
+
+impl Hsv {
+    /// Converts the color to RGB.
+    pub fn to_rgb(self) -> [u8; 3] {
+        let hue = libm::fmodf(self.hue, 360.0);
+        let hue = if hue < 0.0 { hue + 360.0 } else { hue };
+        let chroma = self.value * self.saturation;
+        let x = chroma * (1.0 - libm::fabsf(libm::fmodf(hue / 60.0, 2.0) - 1.0));
+        let m = self.value - chroma;
+        let rgb = match (hue / 60.0) as u8 {
+            0 => [chroma, x, 0.0],
+            1 => [x, chroma, 0.0],
+            2 => [0.0, chroma, x],
+            3 => [0.0, x, chroma],
+            4 => [x, 0.0, chroma],
+            _ => [chroma, 0.0, x],
+        };
+        rgb.map(|channel| ((channel + m) * u8::MAX as f32) as u8)
+    }
+}
+
+/// Audio features that drive a [`SceneMapping`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AudioFeatures {
+    /// Tempo in beats per minute, if known.
+    pub tempo_bpm: Option<f32>,
+    /// Loudness of the audio in range `0.0..=1.0`.
+    pub energy: f32,
+    /// Brightness of the sound in range `0.0..=1.0`, e.g., the normalized
+    /// spectral centroid, if known.
+    pub brightness: Option<f32>,
+}
+
+impl AudioFeatures {
+    /// Takes the energy from the given detector. As the detector only looks
+    /// at the low frequencies, the tempo and the brightness must be provided
+    /// by the caller, if available.
+    pub fn from_detector(
+        detector: &BeatDetector,
+        tempo_bpm: Option<f32>,
+        brightness: Option<f32>,
+    ) -> Self {
+        let energy = detector
+            .amplitude_histogram()
+            .p90()
+            .map_or(0.0, |p90| p90 as f32 / i16::MAX as f32);
+        Self {
+            tempo_bpm,
+            energy,
+            brightness,
+        }
+    }
+}
+
+/// A suggested lighting scene.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Scene {
+    /// Index of a scene of the user, such as a preset of a lighting console.
+    pub index: usize,
+    /// Suggested colors, the most important one first.
+    pub palette: [Hsv; PALETTE_SIZE],
+}
+
+/// Maps [`AudioFeatures`] to a suggested [`Scene`].
+///
+/// This is implemented for all `Fn(&AudioFeatures) -> Scene`, so that users
+/// can plug in their own mapping. [`DefaultSceneMapping`] is a reasonable
+/// start.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{AudioFeatures, BeatDetector, DefaultSceneMapping, SceneMapping};
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mapping = DefaultSceneMapping::new(4);
+///
+/// // TODO regularly call this with the latest audio data.
+/// let _ = detector.update_and_detect_beat(mono_samples.iter().copied());
+/// let features = AudioFeatures::from_detector(&detector, Some(128.0), None);
+/// let scene = mapping.map(&features);
+/// let [r, g, b] = scene.palette[0].to_rgb();
+/// ```
+pub trait SceneMapping {
+    /// Returns the suggested scene for the given features.
+    fn map(&self, features: &AudioFeatures) -> Scene;
+}
+
+impl<F: Fn(&AudioFeatures) -> Scene> SceneMapping for F {
+    fn map(&self, features: &AudioFeatures) -> Scene {
+        self(features)
+    }
+}
+
+/// Default [`SceneMapping`]. Fast or bright music results in warm colors,
+/// slow or dull music in cold colors. The more energy, the more saturated the
+/// colors and the higher the scene index.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DefaultSceneMapping {
+    scene_count: usize,
+}
+
+impl DefaultSceneMapping {
+    /// Creates a new mapping that suggests scene indices in range
+    /// `0..scene_count`.
+    pub const fn new(scene_count: usize) -> Self {
+        if scene_count == 0 {
+            panic!("scene count must not be zero");
+        }
+        Self { scene_count }
+    }
+
+    /// Returns the hue of the main color.
+    fn hue(features: &AudioFeatures) -> f32 {
+        // 0.0 means warm, 1.0 means cold.
+        let coldness = match (features.brightness, features.tempo_bpm) {
+            (Some(brightness), _) => 1.0 - brightness,
+            (None, Some(tempo_bpm)) => {
+                (FAST_TEMPO_BPM - tempo_bpm) / (FAST_TEMPO_BPM - SLOW_TEMPO_BPM)
+            }
+            (None, None) => return NEUTRAL_HUE,
+        };
+        coldness.clamp(0.0, 1.0) * COLD_HUE
+    }
+}
+
+impl SceneMapping for DefaultSceneMapping {
+    fn map(&self, features: &AudioFeatures) -> Scene {
+        let energy = features.energy.clamp(0.0, 1.0);
+        let hue = Self::hue(features);
+        let saturation = 0.6 + 0.4 * energy;
+        let color = |hue_offset: f32, value: f32| Hsv {
+            hue: libm::fmodf(hue + hue_offset + 360.0, 360.0),
+            saturation,
+            value,
+        };
+        Scene {
+            index: ((energy * self.scene_count as f32) as usize).min(self.scene_count - 1),
+            palette: [color(0.0, 1.0), color(30.0, 0.8), color(-30.0, 0.6)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hsv_to_rgb() {
+        let hsv = |hue, saturation, value| Hsv {
+            hue,
+            saturation,
+            value,
+        };
+        assert_eq!(hsv(0.0, 1.0, 1.0).to_rgb(), [255, 0, 0]);
+        assert_eq!(hsv(120.0, 1.0, 1.0).to_rgb(), [0, 255, 0]);
+        assert_eq!(hsv(240.0, 1.0, 1.0).to_rgb(), [0, 0, 255]);
+        assert_eq!(hsv(600.0, 1.0, 1.0).to_rgb(), [0, 0, 255]);
+        assert_eq!(hsv(0.0, 0.0, 1.0).to_rgb(), [255, 255, 255]);
+        assert_eq!(hsv(42.0, 1.0, 0.0).to_rgb(), [0, 0, 0]);
+    }
+
+    #[test]
+    fn default_mapping() {
+        let mapping = DefaultSceneMapping::new(4);
+        let features = |tempo_bpm, energy, brightness| AudioFeatures {
+            tempo_bpm,
+            energy,
+            brightness,
+        };
+
+        let fast = mapping.map(&features(Some(180.0), 1.0, None));
+        let slow = mapping.map(&features(Some(60.0), 0.0, None));
+        assert_eq!(fast.palette[0].hue, 0.0);
+        assert_eq!(slow.palette[0].hue, COLD_HUE);
+        assert_eq!(fast.index, 3);
+        assert_eq!(slow.index, 0);
+        assert!(fast.palette[0].saturation > slow.palette[0].saturation);
+
+        // Brightness takes precedence over the tempo.
+        let bright = mapping.map(&features(Some(60.0), 0.5, Some(1.0)));
+        assert_eq!(bright.palette[0].hue, 0.0);
+        assert_eq!(bright.palette[2].hue, 330.0);
+    }
+
+    #[test]
+    fn custom_mapping() {
+        let mapping = |features: &AudioFeatures| Scene {
+            index: features.tempo_bpm.map_or(0, |tempo| tempo as usize / 60),
+            ..Scene::default()
+        };
+        let features = AudioFeatures {
+            tempo_bpm: Some(128.0),
+            ..AudioFeatures::default()
+        };
+        assert_eq!(mapping.map(&features).index, 2);
+    }
+}
@@ -0,0 +1,199 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`SensorAdapter`].
+
+use crate::util::saturating_f32_to_i16;
+use biquad::{Biquad, Coefficients, DirectForm1, ToHertz, Type, Q_BUTTERWORTH_F32};
+
+/// Cutoff frequency of the highpass filter that removes the offset of the
+/// sensor, such as gravity. Well below the frequencies of impulses.
+const HIGHPASS_CUTOFF_HZ: f32 = 10.0;
+
+/// Duration of readings that is fed through the highpass filter before the
+/// first real reading, so that the offset doesn't cause an impulse.
+const HIGHPASS_PRIMING_DURATION_MS: f32 = 500.0;
+
+/// Turns the readings of a sensor other than a microphone, such as an
+/// accelerometer on a dance floor, into samples that a [`BeatDetector`]
+/// analyzes like audio.
+///
+/// The envelope and peak analysis of the detector isn't specific to audio.
+/// It works for each signal in which the beats are impulses that oscillate
+/// around zero. This adapter removes the offset of the sensor, such as
+/// gravity, with a highpass filter and scales the readings, with
+/// `full_scale` mapping to [`i16::MAX`]. Choose it close to the strongest
+/// impulses, so that the weaker vibrations between the beats don't vanish in
+/// the noise floor of the detector, which they are compared to. Create the
+/// detector with the sampling frequency of the sensor. With its lowpass
+/// filter, it only looks at the impulses below roughly 100 Hz.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BeatDetector, SensorAdapter};
+/// // Acceleration along three axes in g, sampled with 1 kHz.
+/// let readings = [[0.01, -0.02, 1.0], [0.02, -0.01, 1.3] /*, ... */];
+/// let mut adapter = SensorAdapter::new(1000.0, 1.0);
+/// let mut detector = BeatDetector::new(1000.0, true);
+///
+/// // TODO regularly call this with the latest sensor data.
+/// let is_beat = detector.update_and_detect_beat(
+///     readings.iter().map(|&reading| adapter.process_xyz(reading))
+/// );
+/// ```
+///
+/// [`BeatDetector`]: crate::BeatDetector
+#[derive(Debug, Clone)]
+pub struct SensorAdapter {
+    highpass_filter: DirectForm1<f32>,
+    /// Amount of readings to prime the highpass filter with.
+    priming_readings: usize,
+    /// Whether the highpass filter was already primed with the first reading.
+    is_primed: bool,
+    /// Factor from the unit of the readings to samples.
+    scale: f32,
+}
+
+impl SensorAdapter {
+    /// Creates a new adapter for readings with the given sampling frequency.
+    /// Readings of `full_scale`, in the unit of the sensor, correspond to the
+    /// loudest possible audio.
+    ///
+    /// # Panics
+    /// Panics if `full_scale` isn't positive or if the sampling frequency is
+    /// too low for the highpass filter, i.e., not above 20 Hz.
+    pub fn new(sampling_frequency_hz: f32, full_scale: f32) -> Self {
+        assert!(full_scale > 0.0, "The full scale must be positive");
+        let coefficients = Coefficients::<f32>::from_params(
+            Type::HighPass,
+            sampling_frequency_hz.hz(),
+            HIGHPASS_CUTOFF_HZ.hz(),
+            Q_BUTTERWORTH_F32,
+        )
+        .expect("The sampling frequency should be above 20 Hz");
+        Self {
+            highpass_filter: DirectForm1::<f32>::new(coefficients),
+            priming_readings: (sampling_frequency_hz * HIGHPASS_PRIMING_DURATION_MS / 1000.0)
+                as usize,
+            is_primed: false,
+            scale: i16::MAX as f32 / full_scale,
+        }
+    }
+
+    /// Returns the sample of a reading of a single axis.
+    #[inline]
+    pub fn process(&mut self, reading: f32) -> i16 {
+        if !self.is_primed {
+            for _ in 0..self.priming_readings {
+                let _ = self.highpass_filter.run(reading);
+            }
+            self.is_primed = true;
+        }
+        saturating_f32_to_i16(self.highpass_filter.run(reading) * self.scale)
+    }
+
+    /// Returns the sample of a reading of three axes. This uses the magnitude
+    /// of the reading, so that the orientation of the sensor doesn't matter.
+    #[inline]
+    pub fn process_xyz(&mut self, [x, y, z]: [f32; 3]) -> i16 {
+        self.process(libm::sqrtf(x * x + y * y + z * z))
+    }
+
+    /// Returns an iterator that turns readings of a single axis into samples.
+    pub fn adapt_iter<'a>(
+        &'a mut self,
+        readings: impl Iterator<Item = f32> + 'a,
+    ) -> impl Iterator<Item = i16> + 'a {
+        readings.map(move |reading| self.process(reading))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BeatDetector, EnvelopeConfig, MergePolicy};
+    use core::time::Duration;
+    use std::vec::Vec;
+
+    /// Acceleration in g of a dance floor at 1 kHz: the rumble of the
+    /// dancers and a kick drum every 500 ms, beginning at one second.
+    fn dance_floor(len: usize) -> Vec<[f32; 3]> {
+        let mut state = 0x1234_5678_u32;
+        let mut noise = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            ((state >> 8) as f32 / (1 << 23) as f32 - 1.0) * 0.02
+        };
+        let sine = |i: usize, frequency: f32| {
+            libm::sinf(i as f32 / 1000.0 * frequency * 2.0 * core::f32::consts::PI)
+        };
+        (0..len)
+            .map(|i| {
+                let rumble = (sine(i, 23.0) + sine(i, 37.0)) * 0.1;
+                let kick = i.checked_sub(1000).map_or(0.0, |i| {
+                    let i = i % 500;
+                    sine(i, 50.0) * libm::expf(-(i as f32) / 30.0) * 0.8
+                });
+                [noise() + kick * 0.3, noise(), 1.0 + noise() + rumble + kick]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn offset_is_removed() {
+        let mut adapter = SensorAdapter::new(1000.0, 2.0);
+        let samples = adapter
+            .adapt_iter(core::iter::repeat(1.0).take(1000))
+            .collect::<Vec<_>>();
+        assert!(samples.iter().all(|sample| sample.abs() < 10));
+    }
+
+    #[test]
+    fn detects_kicks_in_acceleration() {
+        let mut adapter = SensorAdapter::new(1000.0, 1.0);
+        let mut detector = BeatDetector::new(1000.0, true);
+        // An update may end between the first peaks of a kick, which then
+        // splits into two envelopes.
+        detector.set_envelope_config(EnvelopeConfig {
+            merge_policy: Some(MergePolicy {
+                max_gap: Duration::from_millis(10),
+                max_peak_ratio: 1.2,
+            }),
+            ..EnvelopeConfig::DEFAULT
+        });
+        let beats = dance_floor(10000)
+            .chunks(20)
+            .filter_map(|chunk| {
+                detector.update_and_detect_beat(
+                    chunk.iter().map(|&reading| adapter.process_xyz(reading)),
+                )
+            })
+            .map(|beat| beat.max.source.time.as_millis())
+            .collect::<Vec<_>>();
+
+        assert_eq!(beats.len(), 18, "{beats:?}");
+        for (i, beat) in beats.iter().enumerate() {
+            let kick = 1000 + i as u128 * 500;
+            assert!((kick..kick + 20).contains(beat), "{beats:?}");
+        }
+    }
+}
@@ -0,0 +1,234 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`SnapshotWriter`] and [`SnapshotReader`].
+
+use crate::SnapshotError;
+use core::time::Duration;
+
+/// Writes the little-endian fields of a snapshot into a buffer.
+///
+/// Writing continues past the end of the buffer without writing anything,
+/// so that the same code also measures the size of a snapshot.
+#[derive(Debug)]
+pub(crate) struct SnapshotWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> SnapshotWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Returns the amount of bytes written so far, including the ones that
+    /// didn't fit into the buffer.
+    pub const fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the amount of written bytes or an error, if the buffer was too
+    /// small for them.
+    pub const fn finish(self) -> Result<usize, SnapshotError> {
+        if self.pos > self.buf.len() {
+            Err(SnapshotError::BufferTooSmall { required: self.pos })
+        } else {
+            Ok(self.pos)
+        }
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        if let Some(dest) = self.buf.get_mut(self.pos..self.pos + bytes.len()) {
+            dest.copy_from_slice(bytes);
+        }
+        self.pos += bytes.len();
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value.into());
+    }
+
+    pub fn i16(&mut self, value: i16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    /// Writes the duration in nanoseconds.
+    pub fn duration(&mut self, value: Duration) {
+        self.u64(value.as_nanos() as u64);
+    }
+
+    /// Writes whether the value is present, followed by the value.
+    pub fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T)) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+}
+
+/// Reads the fields written by a [`SnapshotWriter`]. Truncated data and
+/// invalid values are reported as [`SnapshotError::InvalidFormat`].
+#[derive(Debug)]
+pub(crate) struct SnapshotReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Returns the data that wasn't read yet.
+    pub const fn rest(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    pub fn bytes<const LEN: usize>(&mut self) -> Result<[u8; LEN], SnapshotError> {
+        if self.buf.len() < LEN {
+            return Err(SnapshotError::InvalidFormat);
+        }
+        let (bytes, rest) = self.buf.split_at(LEN);
+        self.buf = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SnapshotError> {
+        self.bytes::<1>().map(|[value]| value)
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::InvalidFormat),
+        }
+    }
+
+    pub fn i16(&mut self) -> Result<i16, SnapshotError> {
+        self.bytes().map(i16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SnapshotError> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+
+    /// Reads a finite `f32`.
+    pub fn f32(&mut self) -> Result<f32, SnapshotError> {
+        let value = f32::from_le_bytes(self.bytes()?);
+        Self::check(value.is_finite())?;
+        Ok(value)
+    }
+
+    pub fn duration(&mut self) -> Result<Duration, SnapshotError> {
+        self.u64().map(Duration::from_nanos)
+    }
+
+    pub fn option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, SnapshotError>,
+    ) -> Result<Option<T>, SnapshotError> {
+        if self.bool()? {
+            read(self).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Fails with [`SnapshotError::InvalidFormat`] if the condition doesn't
+    /// hold.
+    pub fn check(valid: bool) -> Result<(), SnapshotError> {
+        valid.then_some(()).ok_or(SnapshotError::InvalidFormat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let write = |writer: &mut SnapshotWriter| {
+            writer.u8(7);
+            writer.i16(-300);
+            writer.u64(u64::MAX);
+            writer.f32(0.5);
+            writer.duration(Duration::from_millis(1500));
+            writer.option(Some(true), SnapshotWriter::bool);
+            writer.option(None, SnapshotWriter::u32);
+        };
+        let mut counter = SnapshotWriter::new(&mut []);
+        write(&mut counter);
+        assert_eq!(
+            counter.finish(),
+            Err(SnapshotError::BufferTooSmall { required: 26 })
+        );
+
+        let mut buf = [0; 26];
+        let mut writer = SnapshotWriter::new(&mut buf);
+        write(&mut writer);
+        assert_eq!(writer.finish(), Ok(26));
+
+        let mut reader = SnapshotReader::new(&buf);
+        assert_eq!(reader.u8(), Ok(7));
+        assert_eq!(reader.i16(), Ok(-300));
+        assert_eq!(reader.u64(), Ok(u64::MAX));
+        assert_eq!(reader.f32(), Ok(0.5));
+        assert_eq!(reader.duration(), Ok(Duration::from_millis(1500)));
+        assert_eq!(reader.option(SnapshotReader::bool), Ok(Some(true)));
+        assert_eq!(reader.option(SnapshotReader::u32), Ok(None));
+        assert!(reader.rest().is_empty());
+        assert_eq!(reader.u8(), Err(SnapshotError::InvalidFormat));
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert_eq!(
+            SnapshotReader::new(&[2]).bool(),
+            Err(SnapshotError::InvalidFormat)
+        );
+        assert_eq!(
+            SnapshotReader::new(&f32::NAN.to_le_bytes()).f32(),
+            Err(SnapshotError::InvalidFormat)
+        );
+    }
+}
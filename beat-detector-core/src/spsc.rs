@@ -0,0 +1,268 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`SampleQueue`].
+
+use core::cell::UnsafeCell;
+use core::fmt::{Debug, Formatter};
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Lock-free single-producer single-consumer queue of fixed-size blocks of
+/// mono samples, in the style of `heapless::spsc::Queue`.
+///
+/// This connects an interrupt handler that receives audio, e.g., from an I2S
+/// DMA transfer, with the main loop that runs the detector. The
+/// producer and the consumer never block each other. The queue holds up to
+/// `N - 1` blocks of `BLOCK` samples each.
+///
+/// It only needs atomic loads and stores, so it also works on targets without
+/// atomic read-modify-write operations, such as the RP2040 (Cortex-M0+).
+///
+/// ## Memory Ordering
+/// The producer writes a block into a free slot and then publishes it by
+/// storing the new tail index with [`Ordering::Release`]. The consumer loads
+/// the tail index with [`Ordering::Acquire`], so the content of the block is
+/// visible to it. Freeing a slot works the same way in the opposite direction
+/// with the head index. Hence, no critical sections are required.
+///
+/// ## Example
+/// On the RP2040, the queue typically lives in a `static` (e.g., with the
+/// `static_cell` crate). The producer is moved into the DMA interrupt
+/// handler, which pushes each finished block, and the consumer stays in the
+/// main loop. `examples/rp2040` in the repository is a complete firmware that
+/// feeds the queue from the ADC interrupt. The following example simulates
+/// both sides in one thread. It uses the [`EnergyBeatDetector`], but the
+/// [`BeatDetector`] works the same.
+/// ```rust
+/// use beat_detector_core::{EnergyBeatDetector, SampleQueue};
+///
+/// // 4 blocks of 256 samples each.
+/// let mut queue = SampleQueue::<256, 5>::new();
+/// let (mut producer, mut consumer) = queue.split();
+///
+/// // Interrupt handler: called when the DMA transfer of a block finished.
+/// let dma_block = [0_i16; 256];
+/// if producer.push(&dma_block).is_err() {
+///     // The main loop is too slow. Drop the block.
+/// }
+///
+/// // Main loop.
+/// let mut detector = EnergyBeatDetector::new(44100);
+/// while let Some(block) = consumer.pop() {
+///     let _beat = detector.update_and_detect_beat(block.iter().copied());
+/// }
+/// ```
+///
+/// [`BeatDetector`]: crate::BeatDetector
+/// [`EnergyBeatDetector`]: crate::EnergyBeatDetector
+pub struct SampleQueue<const BLOCK: usize, const N: usize> {
+    blocks: UnsafeCell<[[i16; BLOCK]; N]>,
+    /// Index of the next block to pop. Only written by the consumer.
+    head: AtomicUsize,
+    /// Index of the next block to push. Only written by the producer.
+    tail: AtomicUsize,
+}
+
+// SAFETY: The producer and the consumer never access the same block at the
+// same time. See the type description.
+unsafe impl<const BLOCK: usize, const N: usize> Sync for SampleQueue<BLOCK, N> {}
+
+impl<const BLOCK: usize, const N: usize> SampleQueue<BLOCK, N> {
+    /// Creates an empty queue.
+    pub const fn new() -> Self {
+        if N < 2 {
+            panic!("the queue needs at least two slots");
+        }
+        Self {
+            blocks: UnsafeCell::new([[0; BLOCK]; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Splits the queue into its producer and its consumer half.
+    pub fn split(&mut self) -> (SampleProducer<'_, BLOCK, N>, SampleConsumer<'_, BLOCK, N>) {
+        let queue: &Self = self;
+        (
+            SampleProducer {
+                queue,
+                _not_sync: PhantomData,
+            },
+            SampleConsumer {
+                queue,
+                _not_sync: PhantomData,
+            },
+        )
+    }
+
+    /// Returns a pointer to the block at the given index. Other than a
+    /// reference to all blocks, this doesn't alias with the other half.
+    fn block_ptr(&self, index: usize) -> *mut [i16; BLOCK] {
+        debug_assert!(index < N);
+        // SAFETY: The index is in bounds.
+        unsafe { self.blocks.get().cast::<[i16; BLOCK]>().add(index) }
+    }
+
+    /// Returns the amount of queued blocks.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (tail + N - head) % N
+    }
+
+    /// Returns whether there are no queued blocks.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum amount of queued blocks.
+    pub const fn capacity(&self) -> usize {
+        N - 1
+    }
+}
+
+impl<const BLOCK: usize, const N: usize> Default for SampleQueue<BLOCK, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const BLOCK: usize, const N: usize> Debug for SampleQueue<BLOCK, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SampleQueue")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+/// Error of [`SampleProducer::push`] if the queue is full.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct QueueFullError;
+
+/// Producer half of a [`SampleQueue`]. Supposed to be used in an interrupt
+/// handler.
+#[derive(Debug)]
+pub struct SampleProducer<'a, const BLOCK: usize, const N: usize> {
+    queue: &'a SampleQueue<BLOCK, N>,
+    /// Only a single context may push.
+    _not_sync: PhantomData<core::cell::Cell<()>>,
+}
+
+impl<const BLOCK: usize, const N: usize> SampleProducer<'_, BLOCK, N> {
+    /// Copies a block of samples into the queue. Returns an error if the
+    /// queue is full.
+    pub fn push(&mut self, block: &[i16; BLOCK]) -> Result<(), QueueFullError> {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        let next_tail = (tail + 1) % N;
+        if next_tail == self.queue.head.load(Ordering::Acquire) {
+            return Err(QueueFullError);
+        }
+
+        // SAFETY: The consumer doesn't access the block until it is
+        // published below.
+        unsafe { self.queue.block_ptr(tail).write(*block) };
+        self.queue.tail.store(next_tail, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Consumer half of a [`SampleQueue`]. Supposed to be used in the main loop.
+#[derive(Debug)]
+pub struct SampleConsumer<'a, const BLOCK: usize, const N: usize> {
+    queue: &'a SampleQueue<BLOCK, N>,
+    /// Only a single context may pop.
+    _not_sync: PhantomData<core::cell::Cell<()>>,
+}
+
+impl<const BLOCK: usize, const N: usize> SampleConsumer<'_, BLOCK, N> {
+    /// Removes the oldest block from the queue and returns a copy of it.
+    pub fn pop(&mut self) -> Option<[i16; BLOCK]> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        if head == self.queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        // SAFETY: The producer doesn't access the block until it is freed
+        // below.
+        let block = unsafe { self.queue.block_ptr(head).read() };
+        self.queue.head.store((head + 1) % N, Ordering::Release);
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn push_and_pop() {
+        let mut queue = SampleQueue::<2, 3>::new();
+        assert_eq!(queue.capacity(), 2);
+        let (mut producer, mut consumer) = queue.split();
+
+        assert_eq!(consumer.pop(), None);
+        assert_eq!(producer.push(&[1, 2]), Ok(()));
+        assert_eq!(producer.push(&[3, 4]), Ok(()));
+        assert_eq!(producer.push(&[5, 6]), Err(QueueFullError));
+        assert_eq!(consumer.pop(), Some([1, 2]));
+        assert_eq!(producer.push(&[5, 6]), Ok(()));
+        assert_eq!(consumer.pop(), Some([3, 4]));
+        assert_eq!(consumer.pop(), Some([5, 6]));
+        assert_eq!(consumer.pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn threads() {
+        const BLOCKS: i16 = 10000;
+        let mut queue = SampleQueue::<4, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        let received = std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for i in 0..BLOCKS {
+                    while producer.push(&[i; 4]).is_err() {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+
+            let mut received = Vec::new();
+            while received.len() < BLOCKS as usize {
+                match consumer.pop() {
+                    Some(block) => received.push(block),
+                    None => std::thread::yield_now(),
+                }
+            }
+            received
+        });
+
+        assert!(received
+            .iter()
+            .enumerate()
+            .all(|(i, block)| *block == [i as i16; 4]));
+    }
+}
@@ -0,0 +1,147 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`StereoBalance`].
+
+use biquad::{Biquad, Coefficients, DirectForm1, ToHertz, Type, Q_BUTTERWORTH_F32};
+
+/// Amount of blocks of the energy history. Together with the length of the
+/// blocks, this must cover the audio history of the detector.
+const BLOCKS: usize = 256;
+
+/// Tracks the energy of the left and the right channel of stereo input in
+/// blocks of frames, so that the balance of a beat can be determined once it
+/// is detected. This needs much less memory than a stereo audio history.
+#[derive(Debug, Clone)]
+pub(crate) struct StereoBalance {
+    /// Lowpass filters of both channels, so that the energy of the beat and
+    /// not of the hi-hats is compared.
+    filters: Option<[DirectForm1<f32>; 2]>,
+    /// Energy of the left and the right channel per block. A ring buffer.
+    energy: [[f32; 2]; BLOCKS],
+    block_len: u64,
+    /// Total index of the next frame.
+    next_frame: u64,
+}
+
+impl StereoBalance {
+    /// Creates a new tracker whose energy history covers at least
+    /// `min_frames` frames, beginning with the frame with the total index
+    /// `first_frame`. Without a cutoff frequency, the channels are not
+    /// filtered.
+    pub fn new(
+        sampling_frequency_hz: f32,
+        cutoff_frequency_hz: Option<f32>,
+        min_frames: usize,
+        first_frame: u64,
+    ) -> Self {
+        let filters = cutoff_frequency_hz.map(|cutoff| {
+            let coefficients = Coefficients::<f32>::from_params(
+                Type::LowPass,
+                sampling_frequency_hz.hz(),
+                cutoff.hz(),
+                Q_BUTTERWORTH_F32,
+            )
+            .expect("The cutoff frequency of the detector should be below the Nyquist frequency");
+            [DirectForm1::<f32>::new(coefficients); 2]
+        });
+        Self {
+            filters,
+            energy: [[0.0; 2]; BLOCKS],
+            // One block is partially overwritten.
+            block_len: min_frames.div_ceil(BLOCKS - 1).max(1) as u64,
+            next_frame: first_frame,
+        }
+    }
+
+    /// Adds the next frame.
+    pub fn feed(&mut self, l: i16, r: i16) {
+        let mut frame = [l as f32, r as f32];
+        if let Some(filters) = self.filters.as_mut() {
+            for (sample, filter) in frame.iter_mut().zip(filters) {
+                *sample = filter.run(*sample);
+            }
+        }
+        let block = &mut self.energy[(self.next_frame / self.block_len) as usize % BLOCKS];
+        if self.next_frame % self.block_len == 0 {
+            *block = [0.0; 2];
+        }
+        block[0] += frame[0] * frame[0];
+        block[1] += frame[1] * frame[1];
+        self.next_frame += 1;
+    }
+
+    /// Returns the balance of the energy of the frames from `from` to `to`,
+    /// both inclusive, between `-1.0` (left) and `1.0` (right). Returns
+    /// `None` for silence or if the frames are not completely tracked.
+    pub fn balance(&self, from: u64, to: u64) -> Option<f32> {
+        let first_block = from / self.block_len;
+        let last_block = to / self.block_len;
+        let latest_block = self.next_frame.checked_sub(1)? / self.block_len;
+        if last_block > latest_block || latest_block - first_block >= BLOCKS as u64 {
+            return None;
+        }
+        let [l, r] = (first_block..=last_block)
+            .map(|block| self.energy[block as usize % BLOCKS])
+            .fold([0.0; 2], |[l, r], [block_l, block_r]| {
+                [l + block_l, r + block_r]
+            });
+        (l + r > 0.0).then(|| (r - l) / (l + r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 100 frames left, 100 frames right, and then both channels with a
+    /// quarter of the energy on the right channel.
+    fn feed(tracker: &mut StereoBalance, frames: usize) {
+        for i in 0..frames {
+            let sample = if i % 2 == 0 { 1000 } else { -1000 };
+            match i {
+                0..=99 => tracker.feed(sample, 0),
+                100..=199 => tracker.feed(0, sample),
+                _ => tracker.feed(sample, sample / 2),
+            }
+        }
+    }
+
+    #[test]
+    fn balance() {
+        let mut tracker = StereoBalance::new(1000.0, None, 500, 0);
+        feed(&mut tracker, 300);
+        assert_eq!(tracker.balance(0, 99), Some(-1.0));
+        assert_eq!(tracker.balance(100, 199), Some(1.0));
+        assert_eq!(tracker.balance(0, 199), Some(0.0));
+        assert_eq!(tracker.balance(200, 299), Some(-0.6));
+        // Not yet consumed.
+        assert_eq!(tracker.balance(200, 300), None);
+
+        let mut tracker = StereoBalance::new(1000.0, None, 500, 0);
+        feed(&mut tracker, 1000);
+        // No longer tracked.
+        assert_eq!(tracker.balance(0, 99), None);
+        assert_eq!(tracker.balance(600, 999), Some(-0.6));
+    }
+}
@@ -0,0 +1,154 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`StereoBeatDetector`].
+
+use crate::audio_history::BUFFER_STORAGE_SIZE;
+use crate::peak_cache::MAX_TRACKED_PEAKS;
+use crate::stereo_balance::StereoBalance;
+use crate::{util, BeatDetectorConst, BeatInfo};
+
+/// Wraps a [`BeatDetector`] for stereo input, so that beats report their
+/// [`stereo_balance`].
+///
+/// The channels are mixed down to mono with [`util::stereo_to_mono`] for the
+/// detection. The balance lets lighting rigs follow the mix to the left or
+/// to the right. It needs a history of the energy of both channels, which
+/// only detectors for stereo input carry around.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{BeatDetector, StereoBeatDetector};
+/// let stereo_frames = [(0, 0), (500, 300), (-800, -400), (700, 350) /*, ... */];
+/// let mut detector = StereoBeatDetector::new(BeatDetector::new(44100.0, true));
+///
+/// // TODO regularly call this with the latest audio data.
+/// let is_beat = detector.update_and_detect_beat(stereo_frames.iter().copied());
+/// ```
+///
+/// [`BeatDetector`]: crate::BeatDetector
+/// [`stereo_balance`]: crate::EnvelopeInfo::stereo_balance
+#[derive(Debug)]
+pub struct StereoBeatDetector<
+    const N: usize = BUFFER_STORAGE_SIZE,
+    const D: usize = 1,
+    const P: usize = MAX_TRACKED_PEAKS,
+> {
+    detector: BeatDetectorConst<N, D, P>,
+    stereo_balance: StereoBalance,
+}
+
+impl<const N: usize, const D: usize, const P: usize> StereoBeatDetector<N, D, P> {
+    /// Creates a new stereo detector from a configured mono detector.
+    pub fn new(detector: BeatDetectorConst<N, D, P>) -> Self {
+        let stereo_balance = StereoBalance::new(
+            detector.original_sampling_frequency(),
+            detector.filter_cutoff_frequency_hz(),
+            detector.history().capacity() * D,
+            detector.original_total_index(detector.history().total_consumed_samples()),
+        );
+        Self {
+            detector,
+            stereo_balance,
+        }
+    }
+
+    /// Like [`BeatDetector::update_and_detect_beat`], but for stereo frames
+    /// of the left and the right channel.
+    ///
+    /// [`BeatDetector::update_and_detect_beat`]: BeatDetectorConst::update_and_detect_beat
+    pub fn update_and_detect_beat(
+        &mut self,
+        frames: impl Iterator<Item = (i16, i16)>,
+    ) -> Option<BeatInfo> {
+        let stereo_balance = &mut self.stereo_balance;
+        let beat = self.detector.update_and_detect_beat(frames.map(|(l, r)| {
+            stereo_balance.feed(l, r);
+            util::stereo_to_mono(l, r)
+        }))?;
+        Some(BeatInfo {
+            stereo_balance: self.stereo_balance.balance(
+                self.detector.original_total_index(beat.from.total_index),
+                self.detector.original_total_index(beat.to.total_index),
+            ),
+            ..beat
+        })
+    }
+
+    /// Returns the underlying mono detector.
+    pub const fn detector(&self) -> &BeatDetectorConst<N, D, P> {
+        &self.detector
+    }
+
+    /// Returns the underlying mono detector, e.g., to change its
+    /// configuration. Don't pass audio to it directly, as the balance only
+    /// knows the frames of [`Self::update_and_detect_beat`].
+    pub fn detector_mut(&mut self) -> &mut BeatDetectorConst<N, D, P> {
+        &mut self.detector
+    }
+
+    /// Returns the underlying mono detector.
+    pub const fn into_detector(self) -> BeatDetectorConst<N, D, P> {
+        self.detector
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils, BeatDetector};
+    use std::vec::Vec;
+
+    #[test]
+    fn stereo_balance() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        // Panned to the left: a third of the amplitude on the right channel.
+        let frames = samples
+            .iter()
+            .map(|&sample| (sample, sample / 3))
+            .collect::<Vec<_>>();
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        let expected = frames
+            .chunks(1024)
+            .filter_map(|chunk| {
+                detector
+                    .update_and_detect_beat(chunk.iter().map(|&(l, r)| util::stereo_to_mono(l, r)))
+            })
+            .collect::<Vec<_>>();
+        assert!(expected.iter().all(|beat| beat.stereo_balance.is_none()));
+
+        let mut detector =
+            StereoBeatDetector::new(BeatDetector::new(header.sample_rate as f32, true));
+        let beats = frames
+            .chunks(1024)
+            .filter_map(|chunk| detector.update_and_detect_beat(chunk.iter().copied()))
+            .collect::<Vec<_>>();
+        assert_eq!(beats, expected);
+        for beat in beats {
+            // (1/9 - 1) / (1/9 + 1)
+            let balance = beat.stereo_balance.unwrap();
+            assert!((balance + 0.8).abs() < 0.01, "{balance}");
+        }
+    }
+}
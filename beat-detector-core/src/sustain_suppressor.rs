@@ -0,0 +1,128 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`SustainSuppressor`].
+
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::util::saturating_f32_to_i16;
+use crate::SnapshotError;
+use core::time::Duration;
+
+/// Release time of the fast level follower. Longer than half a period of the
+/// lowest bass frequencies, so that the level doesn't ripple.
+const FAST_RELEASE: Duration = Duration::from_millis(20);
+
+/// Time constant of the slow level follower. Sounds that stay loud for longer
+/// are considered as sustained.
+const SLOW_TIME_CONSTANT: Duration = Duration::from_millis(100);
+
+/// How strongly a rise of the level above the sustained level passes. A kick
+/// drum on top of sustained sub-bass of similar loudness only raises the
+/// level of the lowpassed audio by 10 to 20 %.
+const SENSITIVITY: f32 = 5.0;
+
+/// Attenuates sustained sounds, such as 808 slides, and keeps transients,
+/// such as kick drums.
+///
+/// A fast and a slow level follower track the absolute amplitude. At the
+/// attack of a transient, the fast level is much higher than the slow level
+/// and the audio passes. Once a sound is sustained, the slow level catches up
+/// and the audio is attenuated.
+#[derive(Debug, Clone)]
+pub(crate) struct SustainSuppressor {
+    fast_level: f32,
+    slow_level: f32,
+    /// Smoothing factor of the release of the fast level follower.
+    fast_release: f32,
+    /// Smoothing factor of the slow level follower.
+    slow_alpha: f32,
+}
+
+impl SustainSuppressor {
+    pub(crate) fn new(sampling_frequency: f32) -> Self {
+        let alpha =
+            |duration: Duration| 1.0 / (duration.as_secs_f32() * sampling_frequency).max(1.0);
+        Self {
+            fast_level: 0.0,
+            slow_level: 0.0,
+            fast_release: alpha(FAST_RELEASE),
+            slow_alpha: alpha(SLOW_TIME_CONSTANT),
+        }
+    }
+
+    /// Returns the sample with attenuated sustained sounds.
+    pub(crate) fn process(&mut self, sample: i16) -> i16 {
+        let level = (sample as f32).abs();
+        if level > self.fast_level {
+            self.fast_level = level;
+        } else {
+            self.fast_level += self.fast_release * (level - self.fast_level);
+        }
+        self.slow_level += self.slow_alpha * (self.fast_level - self.slow_level);
+
+        let gain = if self.fast_level > 0.0 {
+            ((1.0 - self.slow_level / self.fast_level) * SENSITIVITY).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        saturating_f32_to_i16(sample as f32 * gain)
+    }
+
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.f32(self.fast_level);
+        writer.f32(self.slow_level);
+    }
+
+    pub(crate) fn read_snapshot(
+        &mut self,
+        reader: &mut SnapshotReader,
+    ) -> Result<(), SnapshotError> {
+        self.fast_level = reader.f32()?;
+        self.slow_level = reader.f32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn peak(samples: &[i16]) -> i16 {
+        samples.iter().map(|sample| sample.abs()).max().unwrap()
+    }
+
+    #[test]
+    fn sustain_suppressor_keeps_attack_of_transients() {
+        let mut suppressor = SustainSuppressor::new(44100.0);
+        // A tone that is sustained for one second.
+        let output = (0..44100)
+            .map(|i| libm::sinf(i as f32 * 50.0 * 2.0 * core::f32::consts::PI / 44100.0))
+            .map(|sample| suppressor.process((sample * 16000.0) as i16))
+            .collect::<Vec<_>>();
+
+        // The attack passes, the sustained part is attenuated.
+        assert!(peak(&output[..441]) > 12000);
+        assert!(peak(&output[22050..]) < 16000 / 2);
+    }
+}
@@ -0,0 +1,759 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`TempoEstimator`].
+
+use core::fmt::{Display, Formatter};
+use core::time::Duration;
+
+/// Default for [`TempoConfig::min_bpm`].
+const DEFAULT_MIN_BPM: f32 = 60.0;
+/// Default for [`TempoConfig::max_bpm`].
+const DEFAULT_MAX_BPM: f32 = 200.0;
+/// Lower bound of the range of [`TempoConfig::min_bpm`] and
+/// [`TempoConfig::max_bpm`], i.e., the lowest tempo that is ever estimated.
+pub const LOWEST_BPM: f32 = 20.0;
+/// Upper bound of the range of [`TempoConfig::min_bpm`] and
+/// [`TempoConfig::max_bpm`], i.e., the highest tempo that is ever estimated.
+pub const HIGHEST_BPM: f32 = 400.0;
+
+/// Default for [`TempoSmoothing::Median::intervals`].
+const DEFAULT_MEDIAN_INTERVALS: usize = 8;
+const MIN_MEDIAN_INTERVALS: usize = 3;
+/// Maximum for [`TempoSmoothing::Median::intervals`]. This is also the amount
+/// of intervals that the [`TempoEstimator`] keeps.
+pub const MAX_MEDIAN_INTERVALS: usize = 16;
+
+/// Default for [`TempoConfig::beats_per_bar`].
+const DEFAULT_BEATS_PER_BAR: u8 = 4;
+/// Maximum for [`TempoConfig::beats_per_bar`].
+const MAX_BEATS_PER_BAR: u8 = 16;
+
+/// A tap that follows the previous tap later than this begins a new tap
+/// sequence.
+const MAX_TAP_GAP: Duration = Duration::from_secs(2);
+/// Weight of the latest tap interval when averaging the taps of a sequence.
+const TAP_ALPHA: f32 = 0.5;
+/// Amount of detected beats after the latest tap until the tempo is solely
+/// based on the detected beats again.
+const TAP_HOLD_BEATS: u32 = 16;
+
+/// Strategy to smooth the tempo over the intervals between beats.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TempoSmoothing {
+    /// The tempo follows each interval immediately. Best for practice tools
+    /// that must react to every change.
+    None,
+    /// The tempo is the median of the latest intervals. Single missed or
+    /// additional beats don't affect the tempo, which is best for lighting.
+    Median {
+        /// Amount of intervals.
+        ///
+        /// Range: `3..=16`.
+        intervals: usize,
+    },
+    /// The tempo follows the intervals with an exponential moving average.
+    /// Reacts faster to tempo changes than the median, but is more affected
+    /// by outliers.
+    Exponential {
+        /// Weight of the latest interval. Higher values react faster.
+        ///
+        /// Range: `0.0 < alpha <= 1.0`.
+        alpha: f32,
+    },
+}
+
+impl Default for TempoSmoothing {
+    fn default() -> Self {
+        Self::Median {
+            intervals: DEFAULT_MEDIAN_INTERVALS,
+        }
+    }
+}
+
+/// How the [`TempoEstimator`] treated an interval between two beats.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IntervalStatus {
+    /// The interval is in the tempo range and was used as is.
+    Accepted,
+    /// The interval was doubled or halved into the tempo range, as it was
+    /// most likely caused by a missed or an additional beat. It was used
+    /// afterwards.
+    OctaveCorrected,
+    /// No octave of the interval is in the tempo range, such as for pauses in
+    /// the music. The interval was ignored.
+    Outlier,
+}
+
+/// An interval between two beats that the [`TempoEstimator`] has seen, see
+/// [`TempoEstimator::intervals`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct BeatInterval {
+    /// Time of the beat that ends the interval.
+    pub beat_time: Duration,
+    /// The measured interval.
+    pub raw: Duration,
+    /// The interval that was used for the tempo, i.e., after the octave
+    /// correction. `None` for outliers.
+    pub used: Option<Duration>,
+    /// How the interval was treated.
+    pub status: IntervalStatus,
+}
+
+/// A position in musical time, see [`TempoEstimator::musical_position`].
+///
+/// Formats as `bar:beat:phase`, e.g., `3:2:0.50`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct MusicalPosition {
+    /// The bar, beginning at `1` with the first beat.
+    pub bar: u64,
+    /// The beat in the bar, beginning at `1`.
+    pub beat: u8,
+    /// Progress towards the next beat in range `0.0..1.0`.
+    pub phase: f32,
+}
+
+impl Display for MusicalPosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}:{}:{:.2}", self.bar, self.beat, self.phase)
+    }
+}
+
+/// Configuration of a [`TempoEstimator`]. All values must be in the
+/// documented ranges.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TempoConfig {
+    /// Strategy to smooth the tempo.
+    pub smoothing: TempoSmoothing,
+    /// Slowest expected tempo. Slower intervals are folded into the range.
+    /// Constraining the range to the tempo of a known genre, such as 120 to
+    /// 150 BPM for a techno set, improves the stability.
+    ///
+    /// Range: `20.0..=400.0`, less than [`Self::max_bpm`].
+    pub min_bpm: f32,
+    /// Fastest expected tempo. Faster intervals are folded into the range.
+    ///
+    /// Range: `20.0..=400.0`, greater than [`Self::min_bpm`].
+    pub max_bpm: f32,
+    /// Amount of beats per bar for [`TempoEstimator::musical_position`],
+    /// e.g., `4` for a 4/4 time signature.
+    ///
+    /// Range: `1..=16`.
+    pub beats_per_bar: u8,
+}
+
+impl Default for TempoConfig {
+    fn default() -> Self {
+        Self {
+            smoothing: TempoSmoothing::default(),
+            min_bpm: DEFAULT_MIN_BPM,
+            max_bpm: DEFAULT_MAX_BPM,
+            beats_per_bar: DEFAULT_BEATS_PER_BAR,
+        }
+    }
+}
+
+impl TempoConfig {
+    /// Panics if a value is out of its documented range.
+    fn check(&self) {
+        assert!(
+            (LOWEST_BPM..=HIGHEST_BPM).contains(&self.min_bpm)
+                && (LOWEST_BPM..=HIGHEST_BPM).contains(&self.max_bpm),
+            "min_bpm and max_bpm must be in range {LOWEST_BPM}..={HIGHEST_BPM}"
+        );
+        assert!(
+            self.min_bpm < self.max_bpm,
+            "min_bpm must be less than max_bpm"
+        );
+        assert!(
+            (1..=MAX_BEATS_PER_BAR).contains(&self.beats_per_bar),
+            "beats_per_bar must be in range 1..={MAX_BEATS_PER_BAR}"
+        );
+        match self.smoothing {
+            TempoSmoothing::None => {}
+            TempoSmoothing::Median { intervals } => assert!(
+                (MIN_MEDIAN_INTERVALS..=MAX_MEDIAN_INTERVALS).contains(&intervals),
+                "intervals must be in range {MIN_MEDIAN_INTERVALS}..={MAX_MEDIAN_INTERVALS}"
+            ),
+            TempoSmoothing::Exponential { alpha } => assert!(
+                alpha > 0.0 && alpha <= 1.0,
+                "alpha must be in range 0.0 < alpha <= 1.0"
+            ),
+        }
+    }
+
+    /// Folds an interval in seconds by one octave into the tempo range. If
+    /// several octaves are in range, the one closest to `reference`, i.e.,
+    /// the current interval estimate, wins. Returns `None` if no octave is
+    /// in range.
+    fn fold_interval(&self, interval: f32, reference: Option<f32>) -> Option<f32> {
+        let min_interval = 60.0 / self.max_bpm;
+        let max_interval = 60.0 / self.min_bpm;
+        let distance = |candidate: f32| {
+            reference.map_or(0.0, |reference| {
+                (candidate / reference).max(reference / candidate)
+            })
+        };
+        [interval, interval * 2.0, interval / 2.0]
+            .into_iter()
+            .filter(|interval| (min_interval..=max_interval).contains(interval))
+            .min_by(|a, b| distance(*a).total_cmp(&distance(*b)))
+    }
+}
+
+/// Estimates the tempo in beats per minute (BPM) from the times of beats,
+/// such as the [timestamps] of the beats that the [`BeatDetector`] reports.
+///
+/// Intervals that imply a tempo outside of the [configured range] (60 to 200
+/// BPM by default) are folded into the range by doubling or halving them, as
+/// they are most likely caused by a missed or an additional beat. Intervals
+/// that can't be folded, such as pauses in the music, are ignored.
+///
+/// If the audio is too ambiguous, the tempo can be tapped manually with
+/// [`Self::tap`]. Right after a tap sequence, the tapped tempo overrides the
+/// detected tempo. With each following detected beat, the tempo is blended
+/// more towards the detected tempo, which is also folded towards the tapped
+/// tempo. Taps alone are enough to seed the tempo.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{TempoConfig, TempoEstimator, TempoSmoothing};
+/// use core::time::Duration;
+///
+/// let mut tempo = TempoEstimator::new(TempoConfig {
+///     smoothing: TempoSmoothing::Exponential { alpha: 0.3 },
+///     ..TempoConfig::default()
+/// });
+///
+/// // TODO call this on every beat.
+/// tempo.update(Duration::from_millis(0));
+/// let bpm = tempo.update(Duration::from_millis(500));
+/// assert_eq!(bpm, Some(120.0));
+/// ```
+///
+/// [configured range]: TempoConfig::min_bpm
+/// [timestamps]: crate::EnvelopeInfo::timestamp
+/// [`BeatDetector`]: crate::BeatDetector
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoEstimator {
+    config: TempoConfig,
+    latest_beat: Option<Duration>,
+    /// Ring buffer of the latest accepted intervals in seconds.
+    intervals: [f32; MAX_MEDIAN_INTERVALS],
+    intervals_len: usize,
+    intervals_next: usize,
+    /// Exponential moving average of the intervals in seconds.
+    average_interval: Option<f32>,
+    latest_tap: Option<Duration>,
+    /// Average interval of the current tap sequence in seconds.
+    tap_interval: Option<f32>,
+    beats_since_tap: u32,
+    /// Ring buffer of the latest intervals including the outliers, for
+    /// [`Self::intervals`].
+    interval_log: [Option<BeatInterval>; MAX_MEDIAN_INTERVALS],
+    interval_log_next: usize,
+    /// Time of the latest beat that is on the beat grid. Additional beats
+    /// between two grid beats don't move it.
+    grid_anchor: Option<Duration>,
+    /// Amount of beats of the grid from the first beat to `grid_anchor`.
+    grid_beats: u64,
+}
+
+impl TempoEstimator {
+    /// Creates a new estimator. Panics if the config is invalid.
+    pub fn new(config: TempoConfig) -> Self {
+        config.check();
+        Self {
+            config,
+            latest_beat: None,
+            intervals: [0.0; MAX_MEDIAN_INTERVALS],
+            intervals_len: 0,
+            intervals_next: 0,
+            average_interval: None,
+            latest_tap: None,
+            tap_interval: None,
+            beats_since_tap: 0,
+            interval_log: [None; MAX_MEDIAN_INTERVALS],
+            interval_log_next: 0,
+            grid_anchor: None,
+            grid_beats: 0,
+        }
+    }
+
+    /// Returns the config.
+    pub const fn config(&self) -> &TempoConfig {
+        &self.config
+    }
+
+    /// Replaces the config. The intervals seen so far are kept, so that the
+    /// tempo is available right away. Panics if the config is invalid.
+    pub fn set_config(&mut self, config: TempoConfig) {
+        config.check();
+        self.config = config;
+    }
+
+    /// Forgets all beats and taps, for example, when the song changes.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// Registers a beat at the given time and returns the updated tempo in
+    /// BPM. The times must be monotonic.
+    pub fn update(&mut self, beat_time: Duration) -> Option<f32> {
+        self.beats_since_tap = self.beats_since_tap.saturating_add(1);
+        self.update_intervals(beat_time);
+        self.advance_grid(beat_time);
+        self.bpm()
+    }
+
+    /// Adds the interval that ends with the given beat.
+    fn update_intervals(&mut self, beat_time: Duration) {
+        let Some(latest_beat) = self.latest_beat.replace(beat_time) else {
+            return;
+        };
+        let raw = beat_time.saturating_sub(latest_beat);
+        let reference = self.bpm().map(|bpm| 60.0 / bpm);
+        let folded = self.config.fold_interval(raw.as_secs_f32(), reference);
+        let status = match folded {
+            None => IntervalStatus::Outlier,
+            Some(folded) if folded == raw.as_secs_f32() => IntervalStatus::Accepted,
+            Some(_) => IntervalStatus::OctaveCorrected,
+        };
+        self.interval_log[self.interval_log_next] = Some(BeatInterval {
+            beat_time,
+            raw,
+            used: folded.map(Duration::from_secs_f32),
+            status,
+        });
+        self.interval_log_next = (self.interval_log_next + 1) % MAX_MEDIAN_INTERVALS;
+
+        if let Some(interval) = folded {
+            self.intervals[self.intervals_next] = interval;
+            self.intervals_next = (self.intervals_next + 1) % MAX_MEDIAN_INTERVALS;
+            self.intervals_len = (self.intervals_len + 1).min(MAX_MEDIAN_INTERVALS);
+            self.average_interval = Some(self.average_interval.map_or(interval, |average| {
+                let alpha = match self.config.smoothing {
+                    TempoSmoothing::Exponential { alpha } => alpha,
+                    _ => 1.0,
+                };
+                average + alpha * (interval - average)
+            }));
+        }
+    }
+
+    /// Moves the beat grid to the given beat, unless the beat is closer to
+    /// the previous grid beat than to the next one, such as an additional
+    /// beat. Missed beats and pauses are counted with the current tempo.
+    fn advance_grid(&mut self, beat_time: Duration) {
+        let Some(anchor) = self.grid_anchor else {
+            self.grid_anchor = Some(beat_time);
+            return;
+        };
+        let beats = self.bpm().map_or(1, |bpm| {
+            let beats = beat_time.saturating_sub(anchor).as_secs_f64() * bpm as f64 / 60.0;
+            libm::round(beats) as u64
+        });
+        if beats > 0 {
+            self.grid_beats += beats;
+            self.grid_anchor = Some(beat_time);
+        }
+    }
+
+    /// Returns the amount of beats since the first beat at the given time,
+    /// which must be on the same clock as the beats. The fractional part is
+    /// the progress towards the next beat. Missed beats and pauses are
+    /// counted with the current tempo.
+    ///
+    /// Returns `None` if no beat and no tempo is known yet.
+    pub fn beats_elapsed(&self, now: Duration) -> Option<f64> {
+        let anchor = self.grid_anchor?;
+        let bpm = self.bpm()?;
+        let since_anchor = now.saturating_sub(anchor).as_secs_f64() * bpm as f64 / 60.0;
+        Some(self.grid_beats as f64 + since_anchor)
+    }
+
+    /// Returns the amount of bars since the first beat at the given time.
+    /// See [`Self::beats_elapsed`] and [`TempoConfig::beats_per_bar`].
+    pub fn bars_elapsed(&self, now: Duration) -> Option<f64> {
+        Some(self.beats_elapsed(now)? / self.config.beats_per_bar as f64)
+    }
+
+    /// Returns the position in musical time at the given time, so that
+    /// sequencer-like applications can schedule events in bars and beats.
+    /// The first beat is `1:1:0.00`. See [`Self::beats_elapsed`].
+    pub fn musical_position(&self, now: Duration) -> Option<MusicalPosition> {
+        let beats = self.beats_elapsed(now)?;
+        let whole_beats = beats as u64;
+        let beats_per_bar = self.config.beats_per_bar as u64;
+        Some(MusicalPosition {
+            bar: whole_beats / beats_per_bar + 1,
+            beat: (whole_beats % beats_per_bar) as u8 + 1,
+            phase: (beats - whole_beats as f64) as f32,
+        })
+    }
+
+    /// Registers a manual tap, such as the press of a tap-tempo button, at
+    /// the given time and returns the updated tempo in BPM. The times must be
+    /// monotonic, but don't need to be on the same clock as the beats.
+    ///
+    /// A tap more than 2 s after the previous tap begins a new tap sequence.
+    /// The tapped tempo overrides the detected tempo until further beats are
+    /// detected.
+    pub fn tap(&mut self, tap_time: Duration) -> Option<f32> {
+        let latest_tap = self.latest_tap.replace(tap_time);
+        let gap = latest_tap.map(|latest_tap| tap_time.saturating_sub(latest_tap));
+        match gap {
+            Some(gap) if gap <= MAX_TAP_GAP => {
+                if let Some(interval) = self.config.fold_interval(gap.as_secs_f32(), None) {
+                    self.tap_interval = Some(self.tap_interval.map_or(interval, |average| {
+                        average + TAP_ALPHA * (interval - average)
+                    }));
+                    self.beats_since_tap = 0;
+                }
+            }
+            _ => {
+                // The first tap of a sequence carries no tempo.
+                self.tap_interval = None;
+            }
+        }
+        self.bpm()
+    }
+
+    /// Returns the current tempo in BPM, if at least one interval is known.
+    pub fn bpm(&self) -> Option<f32> {
+        let interval = match (self.detected_interval(), self.tap_interval) {
+            (Some(detected), Some(tapped)) => {
+                let tap_weight =
+                    1.0 - self.beats_since_tap.min(TAP_HOLD_BEATS) as f32 / TAP_HOLD_BEATS as f32;
+                tap_weight * tapped + (1.0 - tap_weight) * detected
+            }
+            (detected, tapped) => detected.or(tapped)?,
+        };
+        Some(60.0 / interval)
+    }
+
+    /// Returns the latest (up to 16) intervals between the beats passed to
+    /// [`Self::update`], from the oldest to the newest, including the
+    /// intervals that were ignored. This helps to debug tempo
+    /// mis-estimates.
+    pub fn intervals(&self) -> impl Iterator<Item = BeatInterval> + '_ {
+        (0..MAX_MEDIAN_INTERVALS)
+            .filter_map(|i| self.interval_log[(self.interval_log_next + i) % MAX_MEDIAN_INTERVALS])
+    }
+
+    /// Returns the range of the tempo in BPM of the latest (up to 16)
+    /// intervals that weren't ignored.
+    pub(crate) fn bpm_range(&self) -> Option<(f32, f32)> {
+        let intervals = (0..self.intervals_len).map(|i| self.interval(i));
+        let shortest = intervals.clone().reduce(f32::min)?;
+        let longest = intervals.reduce(f32::max)?;
+        Some((60.0 / longest, 60.0 / shortest))
+    }
+
+    /// Seeds the tempo like a tap sequence with the given tempo in BPM, so
+    /// that it is known before the first beat. It is blended towards the
+    /// detected tempo like a tapped tempo.
+    pub(crate) fn seed(&mut self, bpm: f32) {
+        self.tap_interval = self.config.fold_interval(60.0 / bpm, None);
+        self.beats_since_tap = 0;
+    }
+
+    /// Returns the smoothed interval of the detected beats in seconds.
+    fn detected_interval(&self) -> Option<f32> {
+        if self.intervals_len == 0 {
+            return None;
+        }
+        let interval = match self.config.smoothing {
+            TempoSmoothing::None => self.interval(0),
+            TempoSmoothing::Median { intervals } => {
+                let mut sorted = [0.0; MAX_MEDIAN_INTERVALS];
+                let sorted = &mut sorted[..intervals.min(self.intervals_len)];
+                for (i, interval) in sorted.iter_mut().enumerate() {
+                    *interval = self.interval(i);
+                }
+                sorted.sort_unstable_by(f32::total_cmp);
+                sorted[sorted.len() / 2]
+            }
+            TempoSmoothing::Exponential { .. } => self.average_interval?,
+        };
+        Some(interval)
+    }
+
+    /// Returns the `i`-th latest interval in seconds.
+    const fn interval(&self, i: usize) -> f32 {
+        self.intervals[(self.intervals_next + MAX_MEDIAN_INTERVALS - 1 - i) % MAX_MEDIAN_INTERVALS]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// Beats at 120 BPM with a few ms of jitter, a missed beat, an additional
+    /// beat, and a tempo change to 150 BPM after the 16th beat.
+    fn beats() -> Vec<Duration> {
+        let mut time = 0;
+        let mut beats = Vec::new();
+        for i in 0..32 {
+            let interval = if i < 16 { 500 } else { 400 };
+            let jitter = [0, 7, -5, 3, -8][i % 5];
+            time += interval;
+            match i {
+                // missed beat
+                5 => continue,
+                // additional beat
+                9 => beats.push(Duration::from_millis(time as u64 - 130)),
+                _ => {}
+            }
+            beats.push(Duration::from_millis((time + jitter) as u64));
+        }
+        beats
+    }
+
+    fn estimate(smoothing: TempoSmoothing) -> Vec<f32> {
+        let mut tempo = TempoEstimator::new(TempoConfig {
+            smoothing,
+            ..TempoConfig::default()
+        });
+        beats()
+            .into_iter()
+            .filter_map(|beat| tempo.update(beat))
+            .collect()
+    }
+
+    #[test]
+    fn taps_seed_and_override_the_tempo() {
+        let ms = Duration::from_millis;
+        let is_close = |bpm: Option<f32>, expected: f32| (bpm.unwrap() - expected).abs() < 0.5;
+        let mut tempo = TempoEstimator::new(TempoConfig::default());
+
+        // Taps alone seed the tempo.
+        assert_eq!(tempo.tap(ms(10_000)), None);
+        tempo.tap(ms(10_600));
+        assert!(is_close(tempo.tap(ms(11_200)), 100.0));
+
+        // The detected beats are at 120 BPM, but every other beat is missed.
+        // Without the taps, this would be 60 BPM.
+        let bpm = (0..16)
+            .map(|i| tempo.update(ms(i * 1000)))
+            .collect::<Vec<_>>();
+        assert!(is_close(bpm[0], 100.0));
+        assert!(is_close(bpm[7], 109.1));
+        assert!(is_close(bpm[15], 120.0));
+
+        // A new tap sequence overrides the detected tempo.
+        assert!(is_close(tempo.tap(ms(20_000)), 120.0));
+        assert!(is_close(tempo.tap(ms(20_400)), 150.0));
+
+        tempo.reset();
+        assert_eq!(tempo.bpm(), None);
+    }
+
+    #[test]
+    fn intervals_are_flagged() {
+        let mut tempo = TempoEstimator::new(TempoConfig::default());
+        assert_eq!(tempo.intervals().count(), 0);
+        for beat in beats() {
+            tempo.update(beat);
+        }
+        // A pause.
+        tempo.update(Duration::from_secs(60));
+
+        let intervals = tempo.intervals().collect::<Vec<_>>();
+        assert_eq!(intervals.len(), MAX_MEDIAN_INTERVALS);
+        let last = intervals.last().unwrap();
+        assert_eq!(last.status, IntervalStatus::Outlier);
+        assert_eq!(last.used, None);
+        assert_eq!(last.beat_time, Duration::from_secs(60));
+        assert!(intervals[..MAX_MEDIAN_INTERVALS - 1]
+            .iter()
+            .all(|interval| interval.status == IntervalStatus::Accepted));
+
+        // The missed beat and the additional beat.
+        let mut tempo = TempoEstimator::new(TempoConfig::default());
+        let statuses = beats()[..12]
+            .iter()
+            .filter_map(|&beat| {
+                tempo.update(beat);
+                tempo.intervals().last()
+            })
+            .map(|interval| interval.status)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses[4], IntervalStatus::OctaveCorrected);
+        // The additional beat splits an interval into a fast one, which is
+        // still in range, and one that is even too fast when doubled.
+        assert_eq!(statuses[7], IntervalStatus::Accepted);
+        assert_eq!(statuses[8], IntervalStatus::Outlier);
+        assert_eq!(
+            statuses
+                .iter()
+                .filter(|status| **status != IntervalStatus::Accepted)
+                .count(),
+            2
+        );
+        let missed = tempo.intervals().nth(4).unwrap();
+        assert_eq!(missed.raw, Duration::from_millis(1015));
+        assert_eq!(missed.used, Some(Duration::from_secs_f32(0.5075)));
+    }
+
+    #[test]
+    fn musical_time() {
+        let ms = Duration::from_millis;
+        let mut tempo = TempoEstimator::new(TempoConfig {
+            beats_per_bar: 3,
+            ..TempoConfig::default()
+        });
+        assert_eq!(tempo.beats_elapsed(ms(0)), None);
+
+        // 120 BPM with a missed beat, an additional beat, and a pause.
+        for time in [1000, 1500, 2000, 3000, 3500, 3620, 4000, 4500] {
+            tempo.update(ms(time));
+        }
+        assert_eq!(tempo.beats_elapsed(ms(4500)), Some(7.0));
+        assert_eq!(tempo.beats_elapsed(ms(4750)), Some(7.5));
+        assert_eq!(tempo.bars_elapsed(ms(4750)), Some(2.5));
+        let position = tempo.musical_position(ms(4750)).unwrap();
+        assert_eq!(
+            position,
+            MusicalPosition {
+                bar: 3,
+                beat: 2,
+                phase: 0.5
+            }
+        );
+        assert_eq!(std::format!("{position}"), "3:2:0.50");
+
+        tempo.update(ms(9500));
+        assert_eq!(tempo.beats_elapsed(ms(9500)), Some(17.0));
+        let position = tempo.musical_position(ms(10_000)).unwrap();
+        assert_eq!(std::format!("{position}"), "7:1:0.00");
+    }
+
+    #[test]
+    fn missed_beats_are_folded() {
+        let fold_interval =
+            |interval, reference| TempoConfig::default().fold_interval(interval, reference);
+        assert_eq!(fold_interval(0.5, None), Some(0.5));
+        assert_eq!(fold_interval(1.2, None), Some(0.6));
+        assert_eq!(fold_interval(0.2, None), Some(0.4));
+        assert_eq!(fold_interval(5.0, None), None);
+        assert_eq!(fold_interval(0.0, None), None);
+        // Both octaves are in range.
+        assert_eq!(fold_interval(0.8, None), Some(0.8));
+        assert_eq!(fold_interval(0.8, Some(0.5)), Some(0.4));
+    }
+
+    #[test]
+    fn bpm_range_is_configurable() {
+        let techno = TempoConfig {
+            min_bpm: 120.0,
+            max_bpm: 150.0,
+            ..TempoConfig::default()
+        };
+        // 100 BPM is folded to 200 BPM by default, but rejected here.
+        assert_eq!(techno.fold_interval(0.6, None), None);
+        assert_eq!(techno.fold_interval(0.9, None), Some(0.45));
+        // 70 BPM would be accepted as is by default.
+        assert_eq!(
+            TempoConfig::default().fold_interval(0.857, None),
+            Some(0.857)
+        );
+        assert_eq!(techno.fold_interval(0.857, None), Some(0.4285));
+
+        // Beats at 140 BPM, but the second beat is missed. By default, the
+        // first interval locks the estimate to 70 BPM, as both octaves are in
+        // range.
+        let mut default = TempoEstimator::new(TempoConfig {
+            smoothing: TempoSmoothing::None,
+            ..TempoConfig::default()
+        });
+        let mut constrained = TempoEstimator::new(TempoConfig {
+            smoothing: TempoSmoothing::None,
+            ..techno
+        });
+        let interval = 60.0 / 140.0;
+        for i in (0..16).filter(|i| *i != 1) {
+            let time = Duration::from_secs_f32(i as f32 * interval);
+            default.update(time);
+            constrained.update(time);
+        }
+        assert!((constrained.bpm().unwrap() - 140.0).abs() < 0.5);
+        assert!((default.bpm().unwrap() - 70.0).abs() < 0.5);
+
+        let invalid = |min_bpm, max_bpm| {
+            std::panic::catch_unwind(|| {
+                TempoEstimator::new(TempoConfig {
+                    min_bpm,
+                    max_bpm,
+                    ..TempoConfig::default()
+                })
+            })
+            .is_err()
+        };
+        assert!(invalid(150.0, 120.0));
+        assert!(invalid(10.0, 120.0));
+        assert!(invalid(120.0, 500.0));
+        assert!(!invalid(120.0, 150.0));
+    }
+
+    #[test]
+    fn smoothing_strategies() {
+        let is_close = |bpm: f32, expected: f32| (bpm - expected).abs() < 3.0;
+
+        // Follows every interval, including the additional beat.
+        let none = estimate(TempoSmoothing::None);
+        assert!(none
+            .iter()
+            .any(|&bpm| !is_close(bpm, 120.0) && !is_close(bpm, 150.0)));
+        assert!(is_close(*none.last().unwrap(), 150.0));
+
+        // Stable around the outliers.
+        let median = estimate(TempoSmoothing::default());
+        assert!(median[..17].iter().all(|&bpm| is_close(bpm, 120.0)));
+        assert!(median[19..].iter().all(|&bpm| is_close(bpm, 150.0)));
+
+        // Reacts faster than the median, but the outliers are visible.
+        let exponential = estimate(TempoSmoothing::Exponential { alpha: 0.5 });
+        assert!(!is_close(exponential[7], 120.0));
+        assert!(exponential[16] > median[16] + 10.0);
+        assert!(exponential[19..].iter().all(|&bpm| is_close(bpm, 150.0)));
+
+        // The smoothing is only configurable in the documented ranges.
+        let config = |smoothing| TempoConfig {
+            smoothing,
+            ..TempoConfig::default()
+        };
+        assert!(std::panic::catch_unwind(|| {
+            TempoEstimator::new(config(TempoSmoothing::Median { intervals: 17 }))
+        })
+        .is_err());
+        assert!(std::panic::catch_unwind(|| {
+            TempoEstimator::new(config(TempoSmoothing::Exponential { alpha: 0.0 }))
+        })
+        .is_err());
+    }
+}
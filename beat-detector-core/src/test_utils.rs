@@ -0,0 +1,330 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+use crate::util::stereo_to_mono;
+use itertools::Itertools;
+use std::path::{Path, PathBuf};
+use std::vec::Vec;
+
+/// Returns the path of a file in the `res` directory of the workspace.
+pub fn res(file: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../res")
+        .join(file)
+}
+
+/// Reads a WAV file to mono audio. Returns the samples as mono audio.
+/// Additionally, it returns the sampling rate of the file.
+fn read_wav_to_mono<T: AsRef<Path>>(file: T) -> (Vec<i16>, hound::WavSpec) {
+    let mut reader = hound::WavReader::open(file).unwrap();
+    let header = reader.spec();
+
+    // owning vector with original data in i16 format
+    let data = reader
+        .samples::<i16>()
+        .map(|s| s.unwrap())
+        .collect::<Vec<_>>();
+
+    if header.channels == 1 {
+        (data, header)
+    } else if header.channels == 2 {
+        let data = data
+            .into_iter()
+            .chunks(2)
+            .into_iter()
+            .map(|mut lr| {
+                let l = lr.next().unwrap();
+                let r = lr
+                    .next()
+                    .expect("should have an even number of LRLR samples");
+                stereo_to_mono(l, r)
+            })
+            .collect::<Vec<_>>();
+        (data, header)
+    } else {
+        panic!("unsupported format!");
+    }
+}
+
+/// Accessor to various samples. One sample here refers to what a sample is in
+/// the music industry: A small excerpt of audio. "Samples" however refer to the
+/// individual data points.
+// Only the long samples are used by the integer-only code.
+#[cfg_attr(not(feature = "float"), allow(dead_code))]
+pub mod samples {
+    use super::*;
+    #[cfg(feature = "float")]
+    use crate::audio_history::DEFAULT_AUDIO_HISTORY_WINDOW_MS;
+
+    /// Returns the mono samples of the holiday sample (long version)
+    /// together with the sampling rate.
+    pub fn holiday_long() -> (Vec<i16>, hound::WavSpec) {
+        read_wav_to_mono(res("holiday_lowpassed--long.wav"))
+    }
+
+    /// Returns the mono samples of the holiday sample (excerpt version)
+    /// together with the sampling rate.
+    pub fn holiday_excerpt() -> (Vec<i16>, hound::WavSpec) {
+        read_wav_to_mono(res("holiday_lowpassed--excerpt.wav"))
+    }
+
+    /// Returns the mono samples of the holiday sample (single-beat version)
+    /// together with the sampling rate.
+    pub fn holiday_single_beat() -> (Vec<i16>, hound::WavSpec) {
+        read_wav_to_mono(res("holiday_lowpassed--single-beat.wav"))
+    }
+
+    /// Returns the mono samples of the "sample1" sample (long version)
+    /// together with the sampling rate.
+    pub fn sample1_long() -> (Vec<i16>, hound::WavSpec) {
+        read_wav_to_mono(res("sample1_lowpassed--long.wav"))
+    }
+
+    /// Returns the mono samples of the "sample1" sample (single-beat version)
+    /// together with the sampling rate.
+    pub fn sample1_single_beat() -> (Vec<i16>, hound::WavSpec) {
+        read_wav_to_mono(res("sample1_lowpassed--single-beat.wav"))
+    }
+
+    /// Returns the mono samples of the "sample1" sample (double-beat version)
+    /// together with the sampling rate.
+    pub fn sample1_double_beat() -> (Vec<i16>, hound::WavSpec) {
+        read_wav_to_mono(res("sample1_lowpassed--double-beat.wav"))
+    }
+
+    #[test]
+    #[cfg(feature = "float")]
+    fn test_samples_are_as_long_as_expected() {
+        fn to_duration_in_seconds((samples, header): (Vec<i16>, hound::WavSpec)) -> f32 {
+            // Although my code is generic regarding the sampling rate, in my
+            // demo samples, I only use this sampling rate. So let's do a
+            // sanity check.
+            assert_eq!(header.sample_rate, 44100);
+
+            samples.len() as f32 / header.sample_rate as f32
+        }
+
+        let duration = to_duration_in_seconds(holiday_excerpt());
+        assert_eq!(duration, 0.035804987 /* seconds */);
+        assert!(
+            duration * 1000.0 <= DEFAULT_AUDIO_HISTORY_WINDOW_MS as f32,
+            "All test code relies on that this sample fully fits into the audio window!"
+        );
+
+        let duration = to_duration_in_seconds(holiday_long());
+        assert_eq!(duration, 3.1764627 /* seconds */);
+
+        let duration = to_duration_in_seconds(holiday_single_beat());
+        assert_eq!(duration, 0.40773243 /* seconds */);
+        assert!(
+            duration * 1000.0 <= DEFAULT_AUDIO_HISTORY_WINDOW_MS as f32,
+            "All test code relies on that this sample fully fits into the audio window!"
+        );
+
+        let duration = to_duration_in_seconds(sample1_long());
+        assert_eq!(duration, 7.998526 /* seconds */);
+
+        let duration = to_duration_in_seconds(sample1_single_beat());
+        assert_eq!(duration, 0.18380952 /* seconds */);
+        assert!(
+            duration * 1000.0 <= DEFAULT_AUDIO_HISTORY_WINDOW_MS as f32,
+            "All test code relies on that this sample fully fits into the audio window!"
+        );
+
+        let duration = to_duration_in_seconds(sample1_double_beat());
+        assert_eq!(duration, 0.41687074 /* seconds */);
+        assert!(
+            duration * 1000.0 <= DEFAULT_AUDIO_HISTORY_WINDOW_MS as f32,
+            "All test code relies on that this sample fully fits into the audio window!"
+        );
+    }
+}
+
+/// Renders variants of the samples, such as faster or louder versions, to
+/// check that the detection doesn't depend on hidden assumptions about the
+/// sampling rate or the level of the bundled samples.
+#[cfg(feature = "float")]
+pub mod render {
+    use std::vec::Vec;
+
+    /// Speed factors of the variants of [`corpus`].
+    pub const SPEEDS: [f32; 3] = [0.9, 1.0, 1.1];
+    /// Gains of the variants of [`corpus`].
+    pub const GAINS: [f32; 3] = [0.5, 1.0, 1.5];
+
+    /// A rendered variant of a sample.
+    #[derive(Clone, Debug)]
+    pub struct Variant {
+        pub speed: f32,
+        pub gain: f32,
+        pub samples: Vec<i16>,
+    }
+
+    /// Resamples the audio with linear interpolation so that it has
+    /// `1 / ratio` times as many samples. Played back at the same sampling
+    /// rate, a ratio of `1.1` is 10 % faster. Played back at `ratio` times
+    /// the sampling rate, it sounds the same. The bundled samples are
+    /// lowpassed, so there is no need for an anti-aliasing filter.
+    pub fn resample(samples: &[i16], ratio: f32) -> Vec<i16> {
+        let len = (samples.len() as f64 / ratio as f64) as usize;
+        (0..len)
+            .map(|i| {
+                let position = i as f64 * ratio as f64;
+                let index = position as usize;
+                let fraction = (position - index as f64) as f32;
+                let a = samples[index] as f32;
+                let b = samples.get(index + 1).copied().unwrap_or(samples[index]) as f32;
+                (a + (b - a) * fraction) as i16
+            })
+            .collect()
+    }
+
+    /// Converts the audio from the sampling rate `from_hz` to `to_hz`. The
+    /// bundled samples are all recorded at 44.1 kHz but many audio
+    /// interfaces default to 48 or 96 kHz.
+    pub fn with_sampling_rate(samples: &[i16], from_hz: u32, to_hz: u32) -> Vec<i16> {
+        resample(samples, from_hz as f32 / to_hz as f32)
+    }
+
+    /// Amplifies the audio, saturating at the range of the samples.
+    pub fn gain(samples: &[i16], gain: f32) -> Vec<i16> {
+        samples
+            .iter()
+            .map(|&sample| crate::util::saturating_f32_to_i16(sample as f32 * gain))
+            .collect()
+    }
+
+    /// Renders all combinations of [`SPEEDS`] and [`GAINS`].
+    pub fn corpus(samples: &[i16]) -> Vec<Variant> {
+        SPEEDS
+            .iter()
+            .flat_map(|&speed| {
+                let stretched = resample(samples, speed);
+                GAINS.iter().map(move |&gain| Variant {
+                    speed,
+                    gain,
+                    samples: self::gain(&stretched, gain),
+                })
+            })
+            .collect()
+    }
+
+    /// Writes the corpus of a sample to WAV files, so that the variants can
+    /// be inspected in an audio editor.
+    #[allow(dead_code)]
+    pub fn write_corpus(samples: &[i16], sampling_rate: u32, dir: &std::path::Path) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: sampling_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        for variant in corpus(samples) {
+            let name = std::format!("speed{:.2}--gain{:.2}.wav", variant.speed, variant.gain);
+            let mut writer = hound::WavWriter::create(dir.join(name), spec).unwrap();
+            for sample in variant.samples {
+                writer.write_sample(sample).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+    }
+
+    #[test]
+    fn resample_scales_the_length() {
+        let samples = (0..1000).collect::<Vec<i16>>();
+        assert_eq!(resample(&samples, 1.0), samples);
+        let faster = resample(&samples, 1.25);
+        assert_eq!(faster.len(), 800);
+        assert_eq!(faster[4], 5);
+        let slower = resample(&samples, 0.5);
+        assert_eq!(slower.len(), 2000);
+        assert_eq!(slower[3], 1);
+        assert_eq!(gain(&[1000, -30000], 1.5), &[1500, -i16::MAX]);
+    }
+}
+
+/// Harness that replays audio as if it was captured live.
+#[cfg(feature = "float")]
+pub mod realtime {
+    use crate::{BeatDetector, BeatInfo};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::time::Duration;
+    use std::vec::Vec;
+
+    /// A beat reported by the detector during a simulated real-time session.
+    #[derive(Copy, Clone, Debug)]
+    pub struct ReportedBeat {
+        pub beat: BeatInfo,
+        /// Simulated wall-clock time at which the beat was reported.
+        pub reported_at: Duration,
+    }
+
+    impl ReportedBeat {
+        /// Time between the actual beat (its maximum) and when it was
+        /// reported.
+        pub fn latency(&self) -> Duration {
+            self.reported_at - self.beat.timestamp()
+        }
+    }
+
+    /// Replays the samples to the detector in chunks, similar to how audio
+    /// input libraries deliver audio. The size of each chunk varies by up to
+    /// `max_jitter_percent` and its (simulated) time of delivery is delayed by
+    /// up to `max_delivery_jitter`. The processing time of the detector itself
+    /// is not part of the simulation.
+    ///
+    /// The random jitter is seeded, so results are reproducible.
+    pub fn replay(
+        samples: &[i16],
+        sampling_rate: f32,
+        chunk_size: usize,
+        max_jitter_percent: usize,
+        max_delivery_jitter: Duration,
+        detector: &mut BeatDetector,
+    ) -> Vec<ReportedBeat> {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let max_chunk_jitter = chunk_size * max_jitter_percent / 100;
+
+        let mut beats = Vec::new();
+        let mut consumed = 0;
+        while consumed < samples.len() {
+            let chunk_size =
+                rng.gen_range(chunk_size - max_chunk_jitter..=chunk_size + max_chunk_jitter);
+            let end = (consumed + chunk_size).min(samples.len());
+            let chunk = &samples[consumed..end];
+            consumed = end;
+
+            // The chunk can't be delivered before its last sample was
+            // captured.
+            let delivery_jitter = rng.gen_range(Duration::ZERO..=max_delivery_jitter);
+            let reported_at =
+                Duration::from_secs_f64(consumed as f64 / sampling_rate as f64) + delivery_jitter;
+
+            if let Some(beat) = detector.update_and_detect_beat(chunk.iter().copied()) {
+                beats.push(ReportedBeat { beat, reported_at });
+            }
+        }
+        beats
+    }
+}
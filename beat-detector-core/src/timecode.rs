@@ -0,0 +1,658 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`TimecodeGenerator`].
+
+use crate::tempo::{HIGHEST_BPM, LOWEST_BPM};
+use crate::TempoEstimator;
+use core::fmt::{Display, Formatter};
+use core::time::Duration;
+use std::vec::Vec;
+
+/// Amount of quarter-frame messages of the MIDI Time Code that make up one
+/// timecode, i.e., two frames.
+const MTC_QUARTER_FRAMES: u64 = 8;
+
+/// Amount of bits of a frame of the linear timecode (LTC).
+const LTC_FRAME_BITS: usize = 80;
+
+/// Sync word at the end of each LTC frame, in the order of transmission.
+const LTC_SYNC_WORD: [bool; 16] = [
+    false, false, true, true, true, true, true, true, true, true, true, true, true, true, false,
+    true,
+];
+
+/// Amplitude of the LTC signal in range `0.0..=1.0`.
+const LTC_AMPLITUDE: f32 = 0.5;
+
+/// Frame rate of a [`Timecode`] as a fraction, so that rates such as
+/// 29.97 fps (`30000 / 1001`) are exact.
+///
+/// The MIDI Time Code and the [`TimecodeGenerator`] support only
+/// [`Self::FPS_24`], [`Self::FPS_25`], [`Self::FPS_29_97_DROP_FRAME`], and
+/// [`Self::FPS_30`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FrameRate {
+    numerator: u32,
+    denominator: u32,
+    drop_frame: bool,
+}
+
+impl FrameRate {
+    /// 23.976 fps, film transferred to NTSC video.
+    pub const FPS_23_976: Self = Self::new(24000, 1001);
+    /// 24 fps, film.
+    pub const FPS_24: Self = Self::new(24, 1);
+    /// 25 fps, PAL video.
+    pub const FPS_25: Self = Self::new(25, 1);
+    /// 29.97 fps, NTSC video, with non-drop-frame counting.
+    pub const FPS_29_97: Self = Self::new(30000, 1001);
+    /// 29.97 fps, NTSC video, with drop-frame counting, see
+    /// [`Self::drop_frame`].
+    pub const FPS_29_97_DROP_FRAME: Self = Self {
+        drop_frame: true,
+        ..Self::FPS_29_97
+    };
+    /// 30 fps.
+    pub const FPS_30: Self = Self::new(30, 1);
+    /// 50 fps.
+    pub const FPS_50: Self = Self::new(50, 1);
+    /// 59.94 fps.
+    pub const FPS_59_94: Self = Self::new(60000, 1001);
+    /// 60 fps.
+    pub const FPS_60: Self = Self::new(60, 1);
+
+    /// Creates a frame rate of `numerator / denominator` frames per second
+    /// with non-drop-frame counting. Panics if the rate doesn't round to
+    /// `1..=255` frames per second.
+    pub const fn new(numerator: u32, denominator: u32) -> Self {
+        if denominator == 0 {
+            panic!("the frame rate must be positive");
+        }
+        let nominal_fps = (numerator as u64 + denominator as u64 / 2) / denominator as u64;
+        if nominal_fps == 0 || nominal_fps > u8::MAX as u64 {
+            panic!("the frame rate must round to 1..=255 fps");
+        }
+        Self {
+            numerator,
+            denominator,
+            drop_frame: false,
+        }
+    }
+
+    /// Returns the numerator of the frames per second.
+    pub const fn numerator(self) -> u32 {
+        self.numerator
+    }
+
+    /// Returns the denominator of the frames per second.
+    pub const fn denominator(self) -> u32 {
+        self.denominator
+    }
+
+    /// Returns the frames per second.
+    pub fn fps(self) -> f64 {
+        f64::from(self.numerator) / f64::from(self.denominator)
+    }
+
+    /// Returns the frames per second of the counting, i.e., the amount of
+    /// frame numbers per second, e.g., `30` for 29.97 fps.
+    pub const fn nominal_fps(self) -> u8 {
+        ((self.numerator as u64 + self.denominator as u64 / 2) / self.denominator as u64) as u8
+    }
+
+    /// Returns whether frame numbers are skipped, so that the timecode
+    /// keeps up with the clock: frames 0 and 1 of each minute, except for
+    /// every tenth minute. Only [`Self::FPS_29_97_DROP_FRAME`] does so.
+    pub const fn drop_frame(self) -> bool {
+        self.drop_frame
+    }
+
+    /// Returns the rate code of the MIDI Time Code, if the MTC supports the
+    /// rate.
+    const fn mtc_code(self) -> Option<u8> {
+        match (self.numerator, self.denominator, self.drop_frame) {
+            (24, 1, false) => Some(0),
+            (25, 1, false) => Some(1),
+            (30000, 1001, true) => Some(2),
+            (30, 1, false) => Some(3),
+            _ => None,
+        }
+    }
+}
+
+impl Default for FrameRate {
+    fn default() -> Self {
+        Self::FPS_25
+    }
+}
+
+/// SMPTE timecode, i.e., hours, minutes, seconds, and frames.
+///
+/// Formats as `HH:MM:SS:FF`, or `HH:MM:SS;FF` with drop-frame counting.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Timecode {
+    /// Hours in range `0..24`.
+    pub hours: u8,
+    /// Minutes in range `0..60`.
+    pub minutes: u8,
+    /// Seconds in range `0..60`.
+    pub seconds: u8,
+    /// Frames in range `0..` [`FrameRate::nominal_fps`].
+    pub frames: u8,
+    /// The frame rate.
+    pub rate: FrameRate,
+}
+
+impl Timecode {
+    /// Creates the timecode of the given frame, counting from
+    /// `00:00:00:00`. Wraps around after 24 hours.
+    pub const fn from_frame_count(frame_count: u64, rate: FrameRate) -> Self {
+        let mut frame_count = frame_count;
+        if rate.drop_frame() {
+            // Frame numbers 0 and 1 are skipped at the begin of each minute,
+            // except for every tenth minute.
+            const FRAMES_PER_10_MINUTES: u64 = 17982;
+            const FRAMES_PER_MINUTE: u64 = 1798;
+            let tens = frame_count / FRAMES_PER_10_MINUTES;
+            let remainder = frame_count % FRAMES_PER_10_MINUTES;
+            frame_count += 18 * tens;
+            if remainder > 1 {
+                frame_count += 2 * ((remainder - 2) / FRAMES_PER_MINUTE);
+            }
+        }
+        let fps = rate.nominal_fps() as u64;
+        let seconds = frame_count / fps;
+        Self {
+            hours: (seconds / 3600 % 24) as u8,
+            minutes: (seconds / 60 % 60) as u8,
+            seconds: (seconds % 60) as u8,
+            frames: (frame_count % fps) as u8,
+            rate,
+        }
+    }
+
+    /// Creates the timecode of the frame at the given time since
+    /// `00:00:00:00`.
+    pub fn from_duration(duration: Duration, rate: FrameRate) -> Self {
+        Self::from_frame_count((duration.as_secs_f64() * rate.fps()) as u64, rate)
+    }
+
+    /// Encodes the timecode as the 80 bits of an LTC frame, in the order of
+    /// transmission. The user bits are zero.
+    fn ltc_bits(&self) -> [bool; LTC_FRAME_BITS] {
+        let mut bits = [false; LTC_FRAME_BITS];
+        let mut put = |begin: usize, len: usize, value: u8| {
+            for bit in 0..len {
+                bits[begin + bit] = value >> bit & 1 == 1;
+            }
+        };
+        put(0, 4, self.frames % 10);
+        put(8, 2, self.frames / 10);
+        put(10, 1, u8::from(self.rate.drop_frame()));
+        put(16, 4, self.seconds % 10);
+        put(24, 3, self.seconds / 10);
+        put(32, 4, self.minutes % 10);
+        put(40, 3, self.minutes / 10);
+        put(48, 4, self.hours % 10);
+        put(56, 2, self.hours / 10);
+        bits[64..].copy_from_slice(&LTC_SYNC_WORD);
+
+        // The polarity correction bit makes the amount of zeros even, so
+        // that each frame begins with the same polarity.
+        let polarity_bit = if self.rate == FrameRate::FPS_25 {
+            59
+        } else {
+            27
+        };
+        bits[polarity_bit] = bits.iter().filter(|bit| !**bit).count() % 2 == 1;
+        bits
+    }
+}
+
+impl Display for Timecode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let separator = if self.rate.drop_frame() { ';' } else { ':' };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{separator}{:02}",
+            self.hours, self.minutes, self.seconds, self.frames
+        )
+    }
+}
+
+/// Configuration of a [`TimecodeGenerator`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimecodeConfig {
+    /// The frame rate of the timecode. Must be supported by the MIDI Time
+    /// Code, see [`FrameRate`].
+    pub rate: FrameRate,
+    /// Tempo of the show in BPM, i.e., the tempo at which the timecode runs
+    /// in real time. Must be in range `20.0..=400.0`.
+    pub reference_bpm: f32,
+    /// The timecode of the first beat.
+    pub offset: Duration,
+}
+
+impl Default for TimecodeConfig {
+    fn default() -> Self {
+        Self {
+            rate: FrameRate::default(),
+            reference_bpm: 120.0,
+            offset: Duration::ZERO,
+        }
+    }
+}
+
+impl TimecodeConfig {
+    /// Panics if a value is out of its documented range.
+    fn check(&self) {
+        assert!(
+            (LOWEST_BPM..=HIGHEST_BPM).contains(&self.reference_bpm),
+            "reference_bpm must be in range {LOWEST_BPM}..={HIGHEST_BPM}"
+        );
+        assert!(
+            self.rate.mtc_code().is_some(),
+            "rate must be supported by the MIDI Time Code"
+        );
+    }
+}
+
+/// A message of the MIDI Time Code (MTC).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MtcMessage {
+    /// A quarter-frame message. Eight of them carry one timecode.
+    QuarterFrame([u8; 2]),
+    /// A full-frame SysEx message, sent when the timecode jumps.
+    FullFrame([u8; 10]),
+}
+
+impl MtcMessage {
+    /// Creates the given piece in range `0..8` of the quarter-frame messages
+    /// of the timecode. Panics if the MTC doesn't support the frame rate of
+    /// the timecode.
+    pub const fn quarter_frame(timecode: &Timecode, piece: u8) -> Self {
+        let nibble = match piece {
+            0 => timecode.frames & 0xf,
+            1 => timecode.frames >> 4,
+            2 => timecode.seconds & 0xf,
+            3 => timecode.seconds >> 4,
+            4 => timecode.minutes & 0xf,
+            5 => timecode.minutes >> 4,
+            6 => timecode.hours & 0xf,
+            _ => timecode.hours >> 4 | Self::rate_code(timecode) << 1,
+        };
+        Self::QuarterFrame([0xf1, (piece & 0x7) << 4 | nibble])
+    }
+
+    /// Creates the full-frame message of the timecode. Panics if the MTC
+    /// doesn't support the frame rate of the timecode.
+    pub const fn full_frame(timecode: &Timecode) -> Self {
+        Self::FullFrame([
+            0xf0,
+            0x7f,
+            // All devices.
+            0x7f,
+            0x01,
+            0x01,
+            Self::rate_code(timecode) << 5 | timecode.hours,
+            timecode.minutes,
+            timecode.seconds,
+            timecode.frames,
+            0xf7,
+        ])
+    }
+
+    /// Returns the rate code of the timecode.
+    const fn rate_code(timecode: &Timecode) -> u8 {
+        match timecode.rate.mtc_code() {
+            Some(code) => code,
+            None => panic!("the MIDI Time Code doesn't support the frame rate"),
+        }
+    }
+
+    /// Returns the raw MIDI bytes.
+    pub const fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::QuarterFrame(bytes) => bytes,
+            Self::FullFrame(bytes) => bytes,
+        }
+    }
+}
+
+/// Generates timecode that is locked to the beat grid of a
+/// [`TempoEstimator`], so that lighting and video consoles can chase it.
+///
+/// Shows on such consoles are programmed against a fixed timeline. The
+/// generator maps musical time onto that timeline: the timecode advances by
+/// one beat of the [reference tempo] per detected beat, beginning at the
+/// [offset] on the first beat. If the music is faster than the reference
+/// tempo, the timecode runs faster than real time, and vice versa, so that
+/// the cues of the show stay on the beats. There is no timecode before the
+/// first beat.
+///
+/// The timecode is available as MIDI Time Code, see [`Self::mtc`], and as
+/// audio signal of the linear timecode (LTC), see [`LtcEncoder`].
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{TempoConfig, TempoEstimator, TimecodeConfig, TimecodeGenerator};
+/// use core::time::Duration;
+///
+/// let mut tempo = TempoEstimator::new(TempoConfig::default());
+/// let mut generator = TimecodeGenerator::new(TimecodeConfig::default());
+/// for beat in 0..8 {
+///     tempo.update(Duration::from_millis(beat * 500));
+/// }
+/// let now = Duration::from_millis(3600);
+/// println!("{}", generator.timecode(&tempo, now).unwrap());
+/// for message in generator.mtc(&tempo, now) {
+///     // TODO send `message.as_bytes()` to the MIDI output.
+/// }
+/// ```
+///
+/// [reference tempo]: TimecodeConfig::reference_bpm
+/// [offset]: TimecodeConfig::offset
+#[derive(Debug, Clone)]
+pub struct TimecodeGenerator {
+    config: TimecodeConfig,
+    /// The next quarter-frame message of the MTC to send.
+    next_quarter_frame: Option<u64>,
+}
+
+impl TimecodeGenerator {
+    /// Creates a new generator. Panics if the configuration is invalid.
+    pub fn new(config: TimecodeConfig) -> Self {
+        config.check();
+        Self {
+            config,
+            next_quarter_frame: None,
+        }
+    }
+
+    /// Returns the configuration.
+    pub const fn config(&self) -> &TimecodeConfig {
+        &self.config
+    }
+
+    /// Resets the MTC output, so that the next call to [`Self::mtc`] begins
+    /// with a full-frame message.
+    pub fn reset(&mut self) {
+        self.next_quarter_frame = None;
+    }
+
+    /// Returns the position on the timeline of the show at the given time,
+    /// which must be on the same clock as the beats of the estimator.
+    pub fn position(&self, tempo: &TempoEstimator, now: Duration) -> Option<Duration> {
+        let beats = tempo.beats_elapsed(now)?;
+        let position = beats * 60.0 / f64::from(self.config.reference_bpm);
+        Some(self.config.offset + Duration::from_secs_f64(position))
+    }
+
+    /// Returns the timecode at the given time. See [`Self::position`].
+    pub fn timecode(&self, tempo: &TempoEstimator, now: Duration) -> Option<Timecode> {
+        let position = self.position(tempo, now)?;
+        Some(Timecode::from_duration(position, self.config.rate))
+    }
+
+    /// Returns the MTC messages that are due at the given time, i.e., the
+    /// quarter-frame messages since the previous call. Call it at least once
+    /// per frame, e.g., with each chunk of audio.
+    ///
+    /// On the first call and if the timecode jumps by more than two frames,
+    /// e.g., because the tempo changed abruptly, a full-frame message is
+    /// sent and the quarter-frame messages resume with the next timecode.
+    /// A timecode that runs backwards a little is held until it catches up.
+    pub fn mtc(&mut self, tempo: &TempoEstimator, now: Duration) -> Vec<MtcMessage> {
+        let rate = self.config.rate;
+        let Some(position) = self.position(tempo, now) else {
+            self.next_quarter_frame = None;
+            return Vec::new();
+        };
+        let due = (position.as_secs_f64() * rate.fps() * 4.0) as u64;
+
+        let mut messages = Vec::new();
+        let next = match self.next_quarter_frame {
+            Some(next) if due + MTC_QUARTER_FRAMES >= next && due < next + MTC_QUARTER_FRAMES => {
+                next
+            }
+            _ => {
+                let timecode = Timecode::from_frame_count(due / 4, rate);
+                messages.push(MtcMessage::full_frame(&timecode));
+                due.next_multiple_of(MTC_QUARTER_FRAMES)
+            }
+        };
+        let mut next = next;
+        while next <= due {
+            let piece = next % MTC_QUARTER_FRAMES;
+            let timecode = Timecode::from_frame_count((next - piece) / 4, rate);
+            messages.push(MtcMessage::quarter_frame(&timecode, piece as u8));
+            next += 1;
+        }
+        self.next_quarter_frame = Some(next);
+        messages
+    }
+}
+
+/// Renders timecode as linear timecode (LTC), i.e., the audio signal of
+/// SMPTE timecode, with biphase mark code.
+///
+/// Each frame of the timecode is rendered separately with
+/// [`Self::render_frame`], e.g., with the timecode of the
+/// [`TimecodeGenerator`] at the time the frame is played.
+#[derive(Debug, Clone)]
+pub struct LtcEncoder {
+    /// Samples per bit of the LTC frame.
+    bit_len: f64,
+    /// Begin of the next bit, relative to the next sample.
+    bit_begin: f64,
+    /// Current level of the signal.
+    high: bool,
+}
+
+impl LtcEncoder {
+    /// Creates a new encoder for an audio output with the given sampling
+    /// rate and the frame rate of the timecode.
+    pub fn new(sampling_rate: f32, rate: FrameRate) -> Self {
+        let bit_len = f64::from(sampling_rate) / (rate.fps() * LTC_FRAME_BITS as f64);
+        assert!(bit_len >= 2.0, "sampling rate too low for LTC");
+        Self {
+            bit_len,
+            bit_begin: 0.0,
+            high: false,
+        }
+    }
+
+    /// Appends the audio samples of one frame of the timecode to the
+    /// buffer, i.e., about `sampling_rate / fps` samples in range
+    /// `-1.0..=1.0`.
+    pub fn render_frame(&mut self, timecode: &Timecode, samples: &mut Vec<f32>) {
+        let mut sample = 0_u64;
+        for bit in timecode.ltc_bits() {
+            let begin = self.bit_begin;
+            let middle = libm::ceil(begin + self.bit_len / 2.0) as u64;
+            let end = libm::ceil(begin + self.bit_len) as u64;
+            // Each bit begins with a transition, a one has another one in
+            // the middle.
+            self.high = !self.high;
+            for index in sample..end {
+                if bit && index == middle {
+                    self.high = !self.high;
+                }
+                samples.push(if self.high {
+                    LTC_AMPLITUDE
+                } else {
+                    -LTC_AMPLITUDE
+                });
+            }
+            sample = end;
+            self.bit_begin = begin + self.bit_len;
+        }
+        // Carry the fraction of a sample over to the next frame.
+        self.bit_begin -= sample as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempoConfig;
+    use std::string::ToString;
+
+    fn tempo(bpm: u64) -> TempoEstimator {
+        let mut tempo = TempoEstimator::new(TempoConfig::default());
+        for beat in 0..8 {
+            tempo.update(Duration::from_millis(beat * 60_000 / bpm));
+        }
+        tempo
+    }
+
+    #[test]
+    fn timecode() {
+        let timecode = Timecode::from_frame_count(25 * 3661 + 7, FrameRate::FPS_25);
+        assert_eq!(timecode.to_string(), "01:01:01:07");
+        let timecode = Timecode::from_frame_count(25 * 3600 * 24, FrameRate::FPS_25);
+        assert_eq!(timecode.to_string(), "00:00:00:00");
+        let timecode = Timecode::from_frame_count(60 * 61 + 59, FrameRate::FPS_59_94);
+        assert_eq!(timecode.to_string(), "00:01:01:59");
+
+        // Frames 0 and 1 of each minute are skipped, except every tenth.
+        let drop_frame =
+            |frames| Timecode::from_frame_count(frames, FrameRate::FPS_29_97_DROP_FRAME);
+        assert_eq!(drop_frame(1799).to_string(), "00:00:59;29");
+        assert_eq!(drop_frame(1800).to_string(), "00:01:00;02");
+        assert_eq!(drop_frame(17981).to_string(), "00:09:59;29");
+        assert_eq!(drop_frame(17982).to_string(), "00:10:00;00");
+        // One hour of NTSC video.
+        assert_eq!(drop_frame(107892).to_string(), "01:00:00;00");
+    }
+
+    #[test]
+    #[should_panic]
+    fn rate_without_mtc() {
+        TimecodeGenerator::new(TimecodeConfig {
+            rate: FrameRate::FPS_29_97,
+            ..TimecodeConfig::default()
+        });
+    }
+
+    #[test]
+    fn locked_to_tempo() {
+        let config = TimecodeConfig {
+            reference_bpm: 60.0,
+            offset: Duration::from_secs(3600),
+            ..TimecodeConfig::default()
+        };
+        let generator = TimecodeGenerator::new(config);
+        assert_eq!(
+            generator.timecode(&TempoEstimator::new(TempoConfig::default()), Duration::ZERO),
+            None
+        );
+
+        // Twice the reference tempo: two seconds of timecode per second. The
+        // eighth beat is at 3.5 s.
+        let tempo = tempo(120);
+        let position = generator.position(&tempo, Duration::from_millis(4000));
+        assert_eq!(position, Some(Duration::from_secs(3608)));
+        let timecode = generator.timecode(&tempo, Duration::from_millis(4020));
+        assert_eq!(timecode.unwrap().to_string(), "01:00:08:01");
+    }
+
+    #[test]
+    fn mtc() {
+        let tempo = tempo(120);
+        let mut generator = TimecodeGenerator::new(TimecodeConfig::default());
+        let at = |ms| Duration::from_millis(ms);
+
+        // Begins with a full frame at 4 s, i.e., 100 frames or 400 quarter
+        // frames, and the first piece.
+        let messages = generator.mtc(&tempo, at(4000));
+        assert_eq!(
+            messages,
+            [
+                MtcMessage::FullFrame([0xf0, 0x7f, 0x7f, 0x01, 0x01, 0x20, 0, 4, 0, 0xf7]),
+                MtcMessage::QuarterFrame([0xf1, 0x00]),
+            ]
+        );
+
+        // One frame later, i.e., four quarter frames.
+        let messages = generator.mtc(&tempo, at(4040));
+        let pieces = messages
+            .iter()
+            .map(|message| message.as_bytes()[1] >> 4)
+            .collect::<Vec<_>>();
+        assert_eq!(pieces, [1, 2, 3, 4]);
+        // Seconds low nibble.
+        assert_eq!(messages[1], MtcMessage::QuarterFrame([0xf1, 0x24]));
+
+        // A jump.
+        let messages = generator.mtc(&tempo, at(5000));
+        assert!(matches!(messages[0], MtcMessage::FullFrame(_)));
+
+        // Slightly backwards: held.
+        assert!(generator.mtc(&tempo, at(4990)).is_empty());
+    }
+
+    /// Decodes the biphase mark code of one frame.
+    fn decode_ltc(samples: &[f32], bit_len: f64) -> Vec<bool> {
+        let level = |position: f64| samples[position as usize] > 0.0;
+        let mut previous = !level(0.0);
+        (0..LTC_FRAME_BITS)
+            .map(|bit| {
+                let begin = bit as f64 * bit_len;
+                let first = level(begin + bit_len * 0.25);
+                let second = level(begin + bit_len * 0.75);
+                assert_ne!(first, previous, "missing transition at bit {bit}");
+                previous = second;
+                first != second
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ltc() {
+        let rate = FrameRate::FPS_25;
+        let mut encoder = LtcEncoder::new(48000.0, rate);
+        let mut samples = Vec::new();
+        let timecode = Timecode::from_frame_count(25 * 3723 + 17, rate);
+        encoder.render_frame(&timecode, &mut samples);
+        encoder.render_frame(&timecode, &mut samples);
+        assert_eq!(samples.len(), 2 * 48000 / 25);
+
+        let bits = decode_ltc(&samples, 24.0);
+        assert_eq!(bits[64..], LTC_SYNC_WORD);
+        assert_eq!(bits, timecode.ltc_bits());
+        let value = |begin: usize, len: usize| {
+            (0..len).fold(0, |value, bit| value | u8::from(bits[begin + bit]) << bit)
+        };
+        // 01:02:03:17
+        assert_eq!((value(56, 2), value(48, 4)), (0, 1));
+        assert_eq!((value(40, 3), value(32, 4)), (0, 2));
+        assert_eq!((value(24, 3), value(16, 4)), (0, 3));
+        assert_eq!((value(8, 2), value(0, 4)), (1, 7));
+
+        // Each frame has the same polarity at its begin.
+        assert_eq!(samples[0], samples[48000 / 25]);
+    }
+}
@@ -0,0 +1,202 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`UniformProvider`].
+
+use crate::{BeatDetectorConst, TempoEstimator};
+use core::time::Duration;
+
+/// Default for [`UniformProvider::new`].
+pub const DEFAULT_ENERGY_RELEASE: Duration = Duration::from_millis(250);
+
+/// Values that drive a beat-synchronized animation, such as the uniforms of
+/// a shader, see [`UniformProvider`].
+///
+/// The layout matches a `vec4<f32>` in WGSL and GLSL, so that the struct can
+/// be uploaded to a uniform buffer as is, e.g., via [`Self::to_ne_bytes`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BeatUniforms {
+    /// Progress from the latest beat to the next one in range `0.0..1.0`.
+    pub beat_phase: f32,
+    /// Progress from the first beat of the latest bar to the next bar in
+    /// range `0.0..1.0`, see [`TempoConfig::beats_per_bar`].
+    ///
+    /// [`TempoConfig::beats_per_bar`]: crate::TempoConfig::beats_per_bar
+    pub bar_phase: f32,
+    /// Energy in range `0.0..=1.0`: the [beat probability] with an instant
+    /// attack and a smooth release.
+    ///
+    /// [beat probability]: crate::BeatDetector::beat_probability
+    pub energy: f32,
+    /// Tempo in BPM or `0.0` if it isn't known yet.
+    pub bpm: f32,
+}
+
+impl BeatUniforms {
+    /// Returns the values in the order of the fields.
+    pub const fn to_array(self) -> [f32; 4] {
+        [self.beat_phase, self.bar_phase, self.energy, self.bpm]
+    }
+
+    /// Returns the values in native byte order, e.g., for
+    /// `wgpu::Queue::write_buffer`.
+    pub fn to_ne_bytes(self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        for (chunk, value) in bytes.chunks_exact_mut(4).zip(self.to_array()) {
+            chunk.copy_from_slice(&value.to_ne_bytes());
+        }
+        bytes
+    }
+}
+
+/// Provides [`BeatUniforms`] once per rendered frame, so that creative coding
+/// sketches, such as with wgpu or nannou, don't have to derive them from the
+/// discrete beats themselves.
+///
+/// The phases come from the beat grid of a [`TempoEstimator`], so they
+/// advance smoothly between the beats and keep going through short breaks.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::{
+///     BeatDetector, TempoConfig, TempoEstimator, UniformProvider, DEFAULT_ENERGY_RELEASE,
+/// };
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut tempo = TempoEstimator::new(TempoConfig::default());
+/// let mut uniforms = UniformProvider::new(DEFAULT_ENERGY_RELEASE);
+///
+/// // TODO regularly call this with the latest audio data.
+/// if let Some(beat) = detector.update_and_detect_beat(mono_samples.iter().copied()) {
+///     tempo.update(beat.timestamp());
+/// }
+///
+/// // TODO call this once per rendered frame.
+/// let frame = uniforms.update(&detector, &tempo, detector.passed_time());
+/// // TODO upload `frame.to_ne_bytes()` to the uniform buffer.
+/// ```
+#[derive(Debug, Clone)]
+pub struct UniformProvider {
+    release: Duration,
+    energy: f32,
+    latest_update: Option<Duration>,
+}
+
+impl UniformProvider {
+    /// Creates a new provider. The energy falls to about a third within the
+    /// `release` time after a beat.
+    pub fn new(release: Duration) -> Self {
+        assert!(!release.is_zero());
+        Self {
+            release,
+            energy: 0.0,
+            latest_update: None,
+        }
+    }
+
+    /// Returns the uniforms at the given time, which must be on the same
+    /// clock as the beats of the estimator.
+    ///
+    /// Audio arrives in chunks, so [`BeatDetector::passed_time`] advances in
+    /// steps. For smooth phases at high frame rates, add the time since the
+    /// latest update of the detector to it.
+    ///
+    /// [`BeatDetector::passed_time`]: crate::BeatDetector::passed_time
+    pub fn update<const N: usize, const D: usize, const P: usize>(
+        &mut self,
+        detector: &BeatDetectorConst<N, D, P>,
+        tempo: &TempoEstimator,
+        now: Duration,
+    ) -> BeatUniforms {
+        let elapsed = self
+            .latest_update
+            .replace(now)
+            .map_or(Duration::ZERO, |latest| now.saturating_sub(latest));
+        let decay = libm::expf(-elapsed.as_secs_f32() / self.release.as_secs_f32());
+        self.energy = (self.energy * decay).max(detector.beat_probability());
+
+        let fract = |value: f64| (value - libm::floor(value)) as f32;
+        BeatUniforms {
+            beat_phase: tempo.beats_elapsed(now).map_or(0.0, fract),
+            bar_phase: tempo.bars_elapsed(now).map_or(0.0, fract),
+            energy: self.energy,
+            bpm: tempo.bpm().unwrap_or(0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BeatDetector, TempoConfig};
+
+    #[test]
+    fn phases_follow_the_beat_grid() {
+        let detector = BeatDetector::new(44100.0, true);
+        let mut tempo = TempoEstimator::new(TempoConfig::default());
+        let mut provider = UniformProvider::new(DEFAULT_ENERGY_RELEASE);
+        assert_eq!(
+            provider.update(&detector, &tempo, Duration::ZERO),
+            BeatUniforms::default()
+        );
+
+        for beat in 0..8 {
+            tempo.update(Duration::from_millis(beat * 500));
+        }
+        // Half a beat after the eighth beat, i.e., in the second half of the
+        // second bar.
+        let uniforms = provider.update(&detector, &tempo, Duration::from_millis(3750));
+        assert_eq!(uniforms.bpm, 120.0);
+        assert!((uniforms.beat_phase - 0.5).abs() < 1e-4);
+        assert!((uniforms.bar_phase - 0.875).abs() < 1e-4);
+        assert_eq!(uniforms.energy, 0.0);
+        assert_eq!(uniforms.to_array()[1], uniforms.bar_phase);
+    }
+
+    #[test]
+    fn energy_release() {
+        let (samples, _) = crate::test_utils::samples::holiday_single_beat();
+        let mut detector = BeatDetector::new(44100.0, true);
+        let tempo = TempoEstimator::new(TempoConfig::default());
+        let mut provider = UniformProvider::new(DEFAULT_ENERGY_RELEASE);
+
+        let mut peak = 0.0_f32;
+        for chunk in samples.chunks(441) {
+            let _ = detector.update_and_detect_beat(chunk.iter().copied());
+            peak = peak.max(
+                provider
+                    .update(&detector, &tempo, detector.passed_time())
+                    .energy,
+            );
+        }
+        assert!(peak > 0.5, "{peak}");
+
+        // Without new audio, the energy fades out.
+        let now = detector.passed_time();
+        let energy = provider.update(&detector, &tempo, now).energy;
+        let later = provider.update(&detector, &tempo, now + DEFAULT_ENERGY_RELEASE);
+        assert!(later.energy < energy);
+    }
+}
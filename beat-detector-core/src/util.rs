@@ -0,0 +1,143 @@
+//! Some common utilities required internally but also useful for external
+//! users, when working with this library.
+
+#[cfg(feature = "float")]
+use core::fmt::{Display, Formatter};
+
+/// Transforms an audio sample in range `i16::MIN..=i16::MAX` to a `f32` in
+/// range `-1.0..1.0`.
+#[cfg(feature = "float")]
+#[inline]
+pub fn i16_sample_to_f32(val: i16) -> f32 {
+    // If to prevent division result >1.0.
+    if val == i16::MIN {
+        -1.0
+    } else {
+        val as f32 / i16::MAX as f32
+    }
+}
+
+/// The sample is out of range `-1.0..1.0`.
+#[cfg(feature = "float")]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OutOfRangeError(f32);
+
+#[cfg(feature = "float")]
+impl Display for OutOfRangeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?} is not in range -1.0..=1.0", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OutOfRangeError {}
+
+/// Transforms an audio sample of type `f32` in range `-1.0..1.0` to  a `i16` in
+/// range `-i16::MAX..=i16::MAX`.
+#[cfg(feature = "float")]
+#[inline]
+pub fn f32_sample_to_i16(val: f32) -> Result<i16, OutOfRangeError> {
+    if val.is_finite() && libm::fabsf(val) <= 1.0 {
+        Ok((val * i16::MAX as f32) as i16)
+    } else {
+        Err(OutOfRangeError(val))
+    }
+}
+
+/// Converts the output of a filter to a sample, saturating at the bounds.
+///
+/// The input is a `f32` in the value range of `i16` samples and the result is
+/// in range `-i16::MAX..=i16::MAX`. `NaN` becomes `0`. `i16::MIN` is avoided
+/// so that the absolute value of the sample is still an `i16`.
+///
+/// This is safe for every input and as fast as
+/// [`f32_to_i16_unchecked`]: the cast already saturates, which leaves only
+/// one branch-free `max`.
+#[cfg(feature = "float")]
+#[inline]
+pub fn saturating_f32_to_i16(val: f32) -> i16 {
+    (val as i16).max(-i16::MAX)
+}
+
+/// Converts the output of a filter, i.e., a `f32` in the value range of `i16`
+/// samples, to a sample without any checks. Prefer
+/// [`saturating_f32_to_i16`], which is equally fast on common platforms.
+///
+/// With the `all-safe` feature, this is the same as [`saturating_f32_to_i16`],
+/// except for `i16::MIN`.
+///
+/// # Safety
+/// `val` must be finite and, after truncation, in range
+/// `i16::MIN..=i16::MAX`.
+// Implementation detail of the detector. Only public for the benchmarks.
+#[cfg(feature = "float")]
+#[doc(hidden)]
+#[inline]
+pub unsafe fn f32_to_i16_unchecked(val: f32) -> i16 {
+    debug_assert!(val.is_finite());
+    #[cfg(feature = "all-safe")]
+    let val = val as i16;
+    // SAFETY: Guaranteed by the caller.
+    #[cfg(not(feature = "all-safe"))]
+    let val = unsafe { val.to_int_unchecked() };
+    val
+}
+
+/// Transforms two stereo samples (that reflect the same point in time on
+/// different channels) into one mono sample.
+#[inline]
+pub const fn stereo_to_mono(l: i16, r: i16) -> i16 {
+    let l = l as i32;
+    let r = r as i32;
+    let avg = (l + r) / 2;
+    avg as i16
+}
+
+#[cfg(all(test, feature = "float"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_i16_sample_to_f32() {
+        check!(i16_sample_to_f32(0) == 0.0);
+        check!(approx_eq!(
+            f32,
+            i16_sample_to_f32(i16::MAX / 2),
+            0.5,
+            epsilon = 0.01
+        ));
+        check!(i16_sample_to_f32(i16::MAX) == 1.0);
+        check!(i16_sample_to_f32(-i16::MAX) == -1.0);
+        check!(i16_sample_to_f32(i16::MIN) == -1.0);
+    }
+
+    #[test]
+    fn test_saturating_f32_to_i16() {
+        check!(saturating_f32_to_i16(0.0) == 0);
+        check!(saturating_f32_to_i16(1234.9) == 1234);
+        check!(saturating_f32_to_i16(-1234.9) == -1234);
+        check!(saturating_f32_to_i16(40000.0) == i16::MAX);
+        check!(saturating_f32_to_i16(-40000.0) == -i16::MAX);
+        check!(saturating_f32_to_i16(i16::MIN as f32) == -i16::MAX);
+        check!(saturating_f32_to_i16(f32::INFINITY) == i16::MAX);
+        check!(saturating_f32_to_i16(f32::NEG_INFINITY) == -i16::MAX);
+        check!(saturating_f32_to_i16(f32::NAN) == 0);
+        for val in [-32767.0, -0.5, 0.5, 1000.0, 32767.0] {
+            check!(saturating_f32_to_i16(val) == unsafe { f32_to_i16_unchecked(val) });
+        }
+    }
+
+    #[test]
+    fn test_f32_sample_to_i16() {
+        check!(f32_sample_to_i16(0.0) == Ok(0));
+        check!(f32_sample_to_i16(-0.5) == Ok(-i16::MAX / 2));
+        check!(f32_sample_to_i16(0.5) == Ok(i16::MAX / 2));
+        check!(f32_sample_to_i16(-1.0) == Ok(-i16::MAX));
+        check!(f32_sample_to_i16(1.0) == Ok(i16::MAX));
+        check!(f32_sample_to_i16(1.1) == Err(OutOfRangeError(1.1)));
+        check!(matches!(
+            f32_sample_to_i16(f32::NAN),
+            Err(OutOfRangeError(_))
+        ));
+    }
+}
@@ -0,0 +1,208 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Module for [`AdaptiveBeatDetector`].
+
+use crate::latency::{LatencyBudget, LatencyMonitor, LatencyStats};
+use beat_detector_core::{BeatDetector, BeatInfo, DEFAULT_SCAN_STRIDE};
+
+/// Maximum scan stride the quality is degraded to. Beyond that, the
+/// precision of the detection suffers too much.
+pub const MAX_SCAN_STRIDE: usize = DEFAULT_SCAN_STRIDE * 8;
+
+/// If the processing time is below this fraction of the budget, there is
+/// enough headroom to restore the quality.
+const HEADROOM_RATIO: f64 = 0.5;
+
+/// Amount of consecutive chunks with enough headroom before the quality is
+/// increased again. This prevents oscillation.
+const CHUNKS_WITH_HEADROOM_BEFORE_RESTORE: u32 = 50;
+
+/// Wraps a [`BeatDetector`] and automatically scales its analysis quality to
+/// the available CPU time.
+///
+/// When the processing time of a chunk exceeds the [`LatencyBudget`], the
+/// detector scans the audio history with a bigger stride (see
+/// [`BeatDetector::set_scan_stride`]) to reduce the CPU load. When there is
+/// enough headroom again for a while, the quality is restored step by step.
+///
+/// This helps on weak hardware, such as a Raspberry Pi Zero that also drives
+/// LEDs, where occasional overruns happen.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::BeatDetector;
+/// use beat_detector_io::adaptive_quality::AdaptiveBeatDetector;
+/// use beat_detector_io::latency::LatencyBudget;
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let detector = BeatDetector::new(44100.0, true);
+/// let mut detector = AdaptiveBeatDetector::new(detector, LatencyBudget::realtime(), 44100.0);
+///
+/// // TODO regularly call this with the latest audio data.
+/// let is_beat = detector.update_and_detect_beat(&mono_samples);
+/// ```
+#[derive(Debug)]
+pub struct AdaptiveBeatDetector {
+    detector: BeatDetector,
+    monitor: LatencyMonitor,
+    chunks_with_headroom: u32,
+}
+
+impl AdaptiveBeatDetector {
+    /// Creates a new adaptive detector for an audio source with the given
+    /// sampling frequency.
+    pub fn new(detector: BeatDetector, budget: LatencyBudget, sampling_frequency_hz: f32) -> Self {
+        Self {
+            detector,
+            monitor: LatencyMonitor::new(budget, sampling_frequency_hz),
+            chunks_with_headroom: 0,
+        }
+    }
+
+    /// Like [`BeatDetector::update_and_detect_beat`], but measures the
+    /// processing time and adapts the analysis quality.
+    pub fn update_and_detect_beat(&mut self, mono_samples: &[i16]) -> Option<BeatInfo> {
+        let detector = &mut self.detector;
+        let beat = self.monitor.measure(mono_samples.len(), || {
+            detector.update_and_detect_beat(mono_samples.iter().copied())
+        });
+        let stats = *self.monitor.stats();
+        self.adapt_quality(&stats);
+        beat
+    }
+
+    /// Returns the underlying detector.
+    pub const fn detector(&self) -> &BeatDetector {
+        &self.detector
+    }
+
+    /// Returns the latency statistics.
+    pub const fn stats(&self) -> &LatencyStats {
+        self.monitor.stats()
+    }
+
+    /// Returns whether the detector currently runs with degraded quality.
+    pub const fn is_degraded(&self) -> bool {
+        self.detector.scan_stride() > DEFAULT_SCAN_STRIDE
+    }
+
+    fn adapt_quality(&mut self, stats: &LatencyStats) {
+        let scan_stride = self.detector.scan_stride();
+
+        if stats.latest_processing_time > stats.latest_allowed_processing_time {
+            self.chunks_with_headroom = 0;
+            let new_scan_stride = (scan_stride * 2).min(MAX_SCAN_STRIDE);
+            if new_scan_stride != scan_stride {
+                log::warn!("Exceeded latency budget: degrading scan stride to {new_scan_stride}");
+                self.detector.set_scan_stride(new_scan_stride);
+            }
+            return;
+        }
+
+        let has_headroom = stats.latest_processing_time.as_secs_f64()
+            < stats.latest_allowed_processing_time.as_secs_f64() * HEADROOM_RATIO;
+        if !has_headroom || scan_stride == DEFAULT_SCAN_STRIDE {
+            self.chunks_with_headroom = 0;
+            return;
+        }
+
+        self.chunks_with_headroom += 1;
+        if self.chunks_with_headroom >= CHUNKS_WITH_HEADROOM_BEFORE_RESTORE {
+            self.chunks_with_headroom = 0;
+            let new_scan_stride = (scan_stride / 2).max(DEFAULT_SCAN_STRIDE);
+            log::info!("Enough headroom: restoring scan stride to {new_scan_stride}");
+            self.detector.set_scan_stride(new_scan_stride);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn stats(processing_time_ms: u64, allowed_processing_time_ms: u64) -> LatencyStats {
+        LatencyStats {
+            latest_processing_time: Duration::from_millis(processing_time_ms),
+            latest_allowed_processing_time: Duration::from_millis(allowed_processing_time_ms),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn quality_is_degraded_and_restored() {
+        let mut detector = AdaptiveBeatDetector::new(
+            BeatDetector::new(44100.0, true),
+            LatencyBudget::realtime(),
+            44100.0,
+        );
+        assert!(!detector.is_degraded());
+
+        detector.adapt_quality(&stats(30, 20));
+        assert_eq!(detector.detector().scan_stride(), DEFAULT_SCAN_STRIDE * 2);
+        assert!(detector.is_degraded());
+
+        for _ in 0..10 {
+            detector.adapt_quality(&stats(30, 20));
+        }
+        assert_eq!(detector.detector().scan_stride(), MAX_SCAN_STRIDE);
+
+        // Within budget but not enough headroom: keep quality.
+        for _ in 0..CHUNKS_WITH_HEADROOM_BEFORE_RESTORE {
+            detector.adapt_quality(&stats(15, 20));
+        }
+        assert_eq!(detector.detector().scan_stride(), MAX_SCAN_STRIDE);
+
+        // Enough headroom: restore step by step.
+        for _ in 0..CHUNKS_WITH_HEADROOM_BEFORE_RESTORE {
+            detector.adapt_quality(&stats(5, 20));
+        }
+        assert_eq!(detector.detector().scan_stride(), MAX_SCAN_STRIDE / 2);
+
+        for _ in 0..CHUNKS_WITH_HEADROOM_BEFORE_RESTORE * 10 {
+            detector.adapt_quality(&stats(5, 20));
+        }
+        assert_eq!(detector.detector().scan_stride(), DEFAULT_SCAN_STRIDE);
+        assert!(!detector.is_degraded());
+    }
+
+    #[test]
+    fn detects_beats_with_degraded_quality() {
+        let (samples, header) = crate::test_utils::samples::holiday_long();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        detector.set_scan_stride(MAX_SCAN_STRIDE);
+        let mut detector = AdaptiveBeatDetector::new(
+            detector,
+            LatencyBudget::realtime(),
+            header.sample_rate as f32,
+        );
+
+        let beats = samples
+            .chunks(2048)
+            .flat_map(|chunk| detector.update_and_detect_beat(chunk))
+            .count();
+        assert!(beats >= 5, "beats: {beats}");
+    }
+}
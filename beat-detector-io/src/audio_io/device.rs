@@ -0,0 +1,169 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! [`SampleSource`] for audio input devices.
+
+use super::{SampleSource, SourceError};
+use crate::recording::{capture_time, open_input_device, DetectorConfig, StartDetectorThreadError};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+use std::vec::Vec;
+
+/// Maximum amount of buffered callbacks of the audio device. If the consumer
+/// doesn't keep up, newer samples are dropped.
+const MAX_PENDING_CALLBACKS: usize = 64;
+
+/// Reads mono samples from an audio input device.
+///
+/// The device delivers samples in its own thread. [`SampleSource::next_chunk`]
+/// blocks until the next samples are available. Use
+/// [`recording::start_detector_thread`] instead if you want the detection to
+/// run directly in the audio thread.
+///
+/// [`recording::start_detector_thread`]: crate::recording::start_detector_thread
+pub struct DeviceSource {
+    // Recording stops when the stream is dropped.
+    _stream: cpal::Stream,
+    sample_rate: f32,
+    receiver: Receiver<Result<Chunk, cpal::StreamError>>,
+    /// Samples of the latest callback that didn't fit into the caller's
+    /// buffer yet.
+    pending: Chunk,
+    pending_pos: usize,
+    /// Total index of the first sample of `pending`.
+    pending_total_index: u64,
+}
+
+/// Samples of one callback of the audio device.
+#[derive(Debug, Default)]
+struct Chunk {
+    samples: Vec<i16>,
+    capture_time: Option<cpal::StreamInstant>,
+}
+
+impl DeviceSource {
+    /// Starts recording from the preferred input device or the default input
+    /// device of the platform.
+    pub fn new(
+        preferred_input_dev: Option<cpal::Device>,
+    ) -> Result<Self, StartDetectorThreadError> {
+        let (input_dev, input_config) = open_input_device(preferred_input_dev)?;
+        let detector_config = DetectorConfig::try_from(&input_config)
+            .map_err(StartDetectorThreadError::UnsupportedStreamConfig)?;
+        let (sender, receiver) = mpsc::sync_channel(MAX_PENDING_CALLBACKS);
+        let err_sender = sender.clone();
+
+        let stream = input_dev
+            .build_input_stream(
+                &input_config,
+                move |data: &[i16], info: &cpal::InputCallbackInfo| {
+                    let chunk = Chunk {
+                        samples: data.to_vec(),
+                        capture_time: Some(info.timestamp().capture),
+                    };
+                    if sender.try_send(Ok(chunk)).is_err() {
+                        log::warn!("Dropping {} samples, the consumer is too slow", data.len());
+                    }
+                },
+                move |e| {
+                    log::error!("Input error: {e:#?}");
+                    let _ = err_sender.try_send(Err(e));
+                },
+                // See `start_detector_thread`.
+                Some(Duration::from_secs(1)),
+            )
+            .map_err(StartDetectorThreadError::FailedBuildingInputStream)?;
+        stream
+            .play()
+            .map_err(StartDetectorThreadError::InputError)?;
+
+        Ok(Self {
+            _stream: stream,
+            sample_rate: detector_config.sampling_frequency_hz,
+            receiver,
+            pending: Chunk::default(),
+            pending_pos: 0,
+            pending_total_index: 0,
+        })
+    }
+
+    /// Returns the point in time when the audio device captured the sample
+    /// with the given total index, such as [`SourcePosition::samples`] of a
+    /// beat, according to the clock of the audio device.
+    ///
+    /// The timestamp is derived from the capture timestamp of the latest chunk
+    /// that the device delivered and the sampling rate. It is `None` before the
+    /// first chunk was read. If the consumer is too slow and samples are
+    /// dropped, the timestamps drift.
+    ///
+    /// [`SourcePosition::samples`]: beat_detector_core::SourcePosition::samples
+    pub fn capture_time(&self, total_index: u64) -> Option<cpal::StreamInstant> {
+        capture_time(
+            total_index,
+            self.pending_total_index,
+            self.pending.capture_time?,
+            self.sample_rate,
+        )
+    }
+}
+
+impl core::fmt::Debug for DeviceSource {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DeviceSource")
+            .field("sample_rate", &self.sample_rate)
+            .field("pending", &(self.pending.samples.len() - self.pending_pos))
+            .finish_non_exhaustive()
+    }
+}
+
+impl SampleSource for DeviceSource {
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn next_chunk(&mut self, buf: &mut [i16]) -> Result<usize, SourceError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pending_pos == self.pending.samples.len() {
+            match self.receiver.recv() {
+                Ok(Ok(chunk)) => {
+                    self.pending_total_index += self.pending.samples.len() as u64;
+                    self.pending = chunk;
+                    self.pending_pos = 0;
+                }
+                Ok(Err(err)) => return Err(SourceError::Device(err)),
+                // Can't happen as long as the stream lives, but the source
+                // is exhausted in any case.
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let pending = &self.pending.samples[self.pending_pos..];
+        let count = pending.len().min(buf.len());
+        buf[..count].copy_from_slice(&pending[..count]);
+        self.pending_pos += count;
+        Ok(count)
+    }
+}
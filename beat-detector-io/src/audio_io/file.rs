@@ -0,0 +1,197 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! [`SampleSource`] for WAV files.
+
+use super::{SampleSource, SourceError};
+use beat_detector_core::util::{f32_sample_to_i16, stereo_to_mono};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Reads mono samples from a WAV file with 16 bit integer or 32 bit float
+/// samples. Stereo files are mixed down to mono.
+pub struct WavSource<R: Read> {
+    reader: hound::WavReader<R>,
+}
+
+impl WavSource<BufReader<File>> {
+    /// Opens the WAV file at the given path.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SourceError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> WavSource<R> {
+    /// Creates a new source from a reader that yields a WAV file, such as a
+    /// [`std::io::Cursor`] for in-memory data.
+    pub fn new(reader: R) -> Result<Self, SourceError> {
+        let reader = hound::WavReader::new(reader)?;
+        let spec = reader.spec();
+        let supported_format = matches!(
+            (spec.sample_format, spec.bits_per_sample),
+            (hound::SampleFormat::Int, 16) | (hound::SampleFormat::Float, 32)
+        );
+        if !supported_format || !(1..=2).contains(&spec.channels) {
+            return Err(SourceError::UnsupportedFormat);
+        }
+        Ok(Self { reader })
+    }
+
+    /// Returns the format of the WAV file.
+    pub fn spec(&self) -> hound::WavSpec {
+        self.reader.spec()
+    }
+
+    /// Returns the total amount of mono samples of the file, according to its
+    /// header. This doesn't change while samples are read.
+    pub fn total_samples(&self) -> u64 {
+        u64::from(self.reader.duration())
+    }
+
+    /// Reads the next sample of a single channel.
+    fn next_sample(&mut self) -> Option<Result<i16, SourceError>> {
+        let sample = match self.reader.spec().sample_format {
+            hound::SampleFormat::Int => self.reader.samples::<i16>().next()?,
+            hound::SampleFormat::Float => self
+                .reader
+                .samples::<f32>()
+                .next()?
+                // Float samples may slightly exceed the valid range. NaN
+                // becomes silence.
+                .map(|sample| f32_sample_to_i16(sample.clamp(-1.0, 1.0)).unwrap_or(0)),
+        };
+        Some(sample.map_err(SourceError::from))
+    }
+}
+
+impl<R: Read> core::fmt::Debug for WavSource<R> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("WavSource")
+            .field("spec", &self.reader.spec())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Read> SampleSource for WavSource<R> {
+    fn sample_rate(&self) -> f32 {
+        self.reader.spec().sample_rate as f32
+    }
+
+    fn next_chunk(&mut self, buf: &mut [i16]) -> Result<usize, SourceError> {
+        let stereo = self.reader.spec().channels == 2;
+        for (count, slot) in buf.iter_mut().enumerate() {
+            let Some(sample) = self.next_sample().transpose()? else {
+                return Ok(count);
+            };
+            *slot = if stereo {
+                // A truncated file might end in the middle of a frame.
+                let Some(r) = self.next_sample().transpose()? else {
+                    return Ok(count);
+                };
+                stereo_to_mono(sample, r)
+            } else {
+                sample
+            };
+        }
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use std::io::Cursor;
+    use std::vec::Vec;
+
+    fn write_wav(
+        spec: hound::WavSpec,
+        write: impl Fn(&mut hound::WavWriter<&mut Cursor<Vec<u8>>>),
+    ) -> Cursor<Vec<u8>> {
+        let mut file = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut file, spec).unwrap();
+        write(&mut writer);
+        writer.finalize().unwrap();
+        file.set_position(0);
+        file
+    }
+
+    fn read_all(source: &mut impl SampleSource, chunk_size: usize) -> Vec<i16> {
+        let mut samples = Vec::new();
+        let mut buf = vec![0; chunk_size];
+        loop {
+            let count = source.next_chunk(&mut buf).unwrap();
+            if count == 0 {
+                break samples;
+            }
+            samples.extend_from_slice(&buf[..count]);
+        }
+    }
+
+    #[test]
+    fn reads_mono_file() {
+        let (expected, header) = test_utils::samples::holiday_long();
+        let mut source =
+            WavSource::open(crate::test_utils::res("holiday_lowpassed--long.wav")).unwrap();
+        assert_eq!(source.sample_rate(), header.sample_rate as f32);
+        assert_eq!(source.total_samples(), expected.len() as u64);
+        assert_eq!(read_all(&mut source, 1000), expected);
+    }
+
+    #[test]
+    fn mixes_stereo_float_file_to_mono() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let file = write_wav(spec, |writer| {
+            for (l, r) in [(1.0, 0.0), (-1.0, -1.0), (0.5, 0.5), (2.0, 2.0)] {
+                writer.write_sample(l).unwrap();
+                writer.write_sample(r).unwrap();
+            }
+        });
+
+        let mut source = WavSource::new(file).unwrap();
+        assert_eq!(source.sample_rate(), 48000.0);
+        assert_eq!(source.total_samples(), 4);
+        assert_eq!(read_all(&mut source, 3), &[16383, -32767, 16383, 32767]);
+    }
+
+    #[test]
+    fn rejects_unsupported_format() {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 8,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let file = write_wav(spec, |writer| writer.write_sample(1_i8).unwrap());
+        assert!(matches!(
+            WavSource::new(file),
+            Err(SourceError::UnsupportedFormat)
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! [`SampleSource`] for audio data in memory.
+
+use super::{SampleSource, SourceError};
+
+/// Reads mono samples from a slice.
+#[derive(Debug, Clone)]
+pub struct MemorySource<'a> {
+    samples: &'a [i16],
+    sample_rate: f32,
+}
+
+impl<'a> MemorySource<'a> {
+    /// Creates a new source for the given mono samples.
+    pub const fn new(samples: &'a [i16], sample_rate: f32) -> Self {
+        Self {
+            samples,
+            sample_rate,
+        }
+    }
+
+    /// Returns the samples that were not read yet.
+    pub const fn remaining(&self) -> &'a [i16] {
+        self.samples
+    }
+}
+
+impl SampleSource for MemorySource<'_> {
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn next_chunk(&mut self, buf: &mut [i16]) -> Result<usize, SourceError> {
+        let count = buf.len().min(self.samples.len());
+        let (chunk, remaining) = self.samples.split_at(count);
+        buf[..count].copy_from_slice(chunk);
+        self.samples = remaining;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_all_samples_in_chunks() {
+        let samples = [1, 2, 3, 4, 5];
+        let mut source = MemorySource::new(&samples, 44100.0);
+        let mut buf = [0; 2];
+        assert_eq!(source.next_chunk(&mut buf).unwrap(), 2);
+        assert_eq!(buf, [1, 2]);
+        assert_eq!(source.remaining(), &[3, 4, 5]);
+        assert_eq!(source.next_chunk(&mut buf).unwrap(), 2);
+        assert_eq!(buf, [3, 4]);
+        assert_eq!(source.next_chunk(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 5);
+        assert_eq!(source.next_chunk(&mut buf).unwrap(), 0);
+    }
+}
@@ -0,0 +1,134 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Audio input backends that all implement [`SampleSource`].
+//!
+//! Each backend lives behind its own Cargo feature, so applications only pull
+//! in what they need:
+//!
+//! - [`memory`]: audio data in memory
+//! - [`file`](mod@file) (`audio-file`): WAV files via `hound`
+//! - [`device`] (`recording`): audio input devices via `cpal`
+//! - [`net`] (`audio-net`): raw PCM streams, such as a TCP connection
+//!
+//! Code that consumes audio, such as [`detect_beats_from_source`], is written
+//! against [`SampleSource`], so new backends don't need to touch it.
+//!
+//! [`detect_beats_from_source`]: crate::offline::detect_beats_from_source
+
+#[cfg(feature = "recording")]
+pub mod device;
+#[cfg(feature = "audio-file")]
+pub mod file;
+pub mod memory;
+#[cfg(feature = "audio-net")]
+pub mod net;
+
+use core::fmt::{Display, Formatter};
+
+/// A source of mono `i16` audio samples.
+///
+/// Higher-level code that drives a [`BeatDetector`] is written once against
+/// this trait and works with all inputs, i.e., audio data in memory, WAV
+/// files, audio input devices, and network streams.
+///
+/// [`BeatDetector`]: beat_detector_core::BeatDetector
+pub trait SampleSource {
+    /// Returns the sampling rate of the samples in Hz.
+    fn sample_rate(&self) -> f32;
+
+    /// Writes the next samples into `buf` and returns how many samples were
+    /// written. `Ok(0)` means that the source is exhausted. Sources may block
+    /// until new samples are available.
+    fn next_chunk(&mut self, buf: &mut [i16]) -> Result<usize, SourceError>;
+}
+
+impl<S: SampleSource + ?Sized> SampleSource for &mut S {
+    fn sample_rate(&self) -> f32 {
+        (**self).sample_rate()
+    }
+
+    fn next_chunk(&mut self, buf: &mut [i16]) -> Result<usize, SourceError> {
+        (**self).next_chunk(buf)
+    }
+}
+
+/// Errors of a [`SampleSource`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SourceError {
+    /// Reading from the underlying I/O resource failed.
+    Io(std::io::Error),
+    /// The audio data has a format that isn't supported, such as an unknown
+    /// bit depth or more than two channels.
+    UnsupportedFormat,
+    /// The WAV file couldn't be decoded.
+    #[cfg(feature = "audio-file")]
+    Wav(hound::Error),
+    /// The audio input device reported an error.
+    #[cfg(feature = "recording")]
+    Device(cpal::StreamError),
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::UnsupportedFormat => f.write_str("unsupported audio format"),
+            #[cfg(feature = "audio-file")]
+            Self::Wav(err) => write!(f, "invalid WAV file: {err}"),
+            #[cfg(feature = "recording")]
+            Self::Device(err) => write!(f, "audio device error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::UnsupportedFormat => None,
+            #[cfg(feature = "audio-file")]
+            Self::Wav(err) => Some(err),
+            #[cfg(feature = "recording")]
+            Self::Device(err) => Some(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for SourceError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[cfg(feature = "audio-file")]
+impl From<hound::Error> for SourceError {
+    fn from(err: hound::Error) -> Self {
+        match err {
+            hound::Error::IoError(err) => Self::Io(err),
+            hound::Error::Unsupported => Self::UnsupportedFormat,
+            err => Self::Wav(err),
+        }
+    }
+}
@@ -0,0 +1,179 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! [`SampleSource`] for raw PCM streams, such as audio sent over the network.
+
+use super::{SampleSource, SourceError};
+use std::io::{ErrorKind, Read};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::vec::Vec;
+
+/// Reads raw mono PCM audio with signed 16 bit little-endian samples, i.e.,
+/// the `s16le` format of `ffmpeg` and PulseAudio, from any [`Read`].
+///
+/// The stream doesn't carry the sampling rate, so it must be known in advance.
+///
+/// # Example
+/// Stream the default audio input of a remote machine with
+/// `arecord -f S16_LE -c 1 -r 44100 | nc -l 4000` and connect to it with
+/// `NetSource::connect("remote:4000", 44100.0)`.
+#[derive(Debug)]
+pub struct NetSource<R: Read> {
+    reader: R,
+    sample_rate: f32,
+    bytes: Vec<u8>,
+    /// The first byte of a sample that was split across two reads.
+    pending_byte: Option<u8>,
+}
+
+impl NetSource<TcpStream> {
+    /// Connects to a TCP server that streams raw PCM audio.
+    pub fn connect(addr: impl ToSocketAddrs, sample_rate: f32) -> Result<Self, SourceError> {
+        let stream = TcpStream::connect(addr)?;
+        // Keep the latency low.
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream, sample_rate))
+    }
+}
+
+impl<R: Read> NetSource<R> {
+    /// Creates a new source from a reader that yields raw PCM audio.
+    pub const fn new(reader: R, sample_rate: f32) -> Self {
+        Self {
+            reader,
+            sample_rate,
+            bytes: Vec::new(),
+            pending_byte: None,
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> SampleSource for NetSource<R> {
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn next_chunk(&mut self, buf: &mut [i16]) -> Result<usize, SourceError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.bytes.resize(buf.len() * 2, 0);
+        let offset = usize::from(self.pending_byte.is_some());
+        if let Some(byte) = self.pending_byte {
+            self.bytes[0] = byte;
+        }
+
+        // Read until there is at least one complete sample. A single read
+        // may only return one byte.
+        let mut len = offset;
+        while len < 2 {
+            match self.reader.read(&mut self.bytes[len..]) {
+                // A trailing incomplete sample is dropped.
+                Ok(0) => return Ok(0),
+                Ok(count) => len += count,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let samples = self.bytes[..len].chunks_exact(2);
+        self.pending_byte = samples.remainder().first().copied();
+        let count = samples.len();
+        for (slot, bytes) in buf.iter_mut().zip(samples) {
+            *slot = i16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Returns one byte per read.
+    struct ByteReader(Cursor<Vec<u8>>);
+
+    impl Read for ByteReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn to_bytes(samples: &[i16]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    fn read_all(source: &mut impl SampleSource) -> Vec<i16> {
+        let mut samples = Vec::new();
+        let mut buf = [0; 3];
+        loop {
+            let count = source.next_chunk(&mut buf).unwrap();
+            if count == 0 {
+                break samples;
+            }
+            samples.extend_from_slice(&buf[..count]);
+        }
+    }
+
+    #[test]
+    fn reads_samples_split_across_reads() {
+        let samples = [0, 1, -1, i16::MAX, i16::MIN, 1234, -4321];
+        let mut bytes = to_bytes(&samples);
+        // Incomplete trailing sample.
+        bytes.push(0xff);
+
+        let mut source = NetSource::new(Cursor::new(bytes.clone()), 44100.0);
+        assert_eq!(read_all(&mut source), samples);
+
+        let mut source = NetSource::new(ByteReader(Cursor::new(bytes)), 44100.0);
+        assert_eq!(read_all(&mut source), samples);
+    }
+
+    #[test]
+    fn reads_from_tcp_stream() {
+        let samples = (-500..500).collect::<Vec<i16>>();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let bytes = to_bytes(&samples);
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for chunk in bytes.chunks(333) {
+                stream.write_all(chunk).unwrap();
+            }
+        });
+
+        let mut source = NetSource::connect(addr, 48000.0).unwrap();
+        assert_eq!(source.sample_rate(), 48000.0);
+        assert_eq!(read_all(&mut source), samples);
+        server.join().unwrap();
+    }
+}
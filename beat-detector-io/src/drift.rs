@@ -0,0 +1,171 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Module for estimating the drift between the sample clock of an audio
+//! source and the system clock.
+
+use std::time::{Duration, Instant};
+
+/// Minimum amount of wall-clock time that must have passed before the
+/// estimation is considered meaningful. Audio is delivered in chunks, which
+/// introduces jitter that only averages out over time.
+const MIN_MEASUREMENT_DURATION: Duration = Duration::from_secs(1);
+
+const PPM: f64 = 1_000_000.0;
+
+/// Estimates the drift between the sample clock of an audio source and the
+/// system clock.
+///
+/// The sample clock of an audio device is never exactly as fast as the
+/// system clock. This matters if you drive lights with a wall-clock scheduler
+/// but use timestamps of beats, which are based on the sample clock.
+///
+/// The estimator compares the amount of received samples against the elapsed
+/// time of the system clock ([`Instant`]). It should be updated each time new
+/// audio data is received.
+#[derive(Debug, Clone)]
+pub struct DriftEstimator {
+    sampling_frequency_hz: f64,
+    /// Time of the first update. Serves as reference point.
+    begin: Option<Instant>,
+    /// Time of the latest update.
+    latest: Option<Instant>,
+    /// Samples received after `begin`.
+    received_samples: u64,
+}
+
+impl DriftEstimator {
+    /// Creates a new estimator for an audio source with the given nominal
+    /// sampling frequency.
+    pub fn new(sampling_frequency_hz: f32) -> Self {
+        assert!(sampling_frequency_hz.is_normal() && sampling_frequency_hz.is_sign_positive());
+        Self {
+            sampling_frequency_hz: sampling_frequency_hz as f64,
+            begin: None,
+            latest: None,
+            received_samples: 0,
+        }
+    }
+
+    /// Updates the estimator with the amount of samples that were just
+    /// received.
+    pub fn update(&mut self, sample_count: usize) {
+        self.update_at(Instant::now(), sample_count)
+    }
+
+    /// Like [`Self::update`] but with an explicit time of arrival.
+    pub fn update_at(&mut self, now: Instant, sample_count: usize) {
+        match self.begin {
+            // The samples of the first chunk were recorded before the
+            // reference point, hence they are not counted.
+            None => self.begin = Some(now),
+            Some(_) => self.received_samples += sample_count as u64,
+        }
+        self.latest = Some(now);
+    }
+
+    /// Returns the elapsed time according to the sample clock.
+    pub fn elapsed_sample_time(&self) -> Duration {
+        Duration::from_secs_f64(self.received_samples as f64 / self.sampling_frequency_hz)
+    }
+
+    /// Returns the elapsed time according to the system clock.
+    pub fn elapsed_wall_time(&self) -> Duration {
+        match (self.begin, self.latest) {
+            (Some(begin), Some(latest)) => latest - begin,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Returns the factor to multiply durations of the sample clock with to
+    /// get durations of the system clock. Returns `None` if not enough data
+    /// was received so far.
+    pub fn correction_factor(&self) -> Option<f64> {
+        let wall_time = self.elapsed_wall_time();
+        if wall_time < MIN_MEASUREMENT_DURATION || self.received_samples == 0 {
+            return None;
+        }
+        Some(wall_time.as_secs_f64() / self.elapsed_sample_time().as_secs_f64())
+    }
+
+    /// Returns the current drift in parts per million (ppm). A positive value
+    /// means that the sample clock runs faster than the system clock. Returns
+    /// `None` if not enough data was received so far.
+    pub fn drift_ppm(&self) -> Option<f64> {
+        self.correction_factor()
+            .map(|correction_factor| (1.0 / correction_factor - 1.0) * PPM)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_estimation_without_enough_data() {
+        let begin = Instant::now();
+        let mut estimator = DriftEstimator::new(44100.0);
+        assert_eq!(estimator.drift_ppm(), None);
+
+        estimator.update_at(begin, 441);
+        assert_eq!(estimator.drift_ppm(), None);
+
+        estimator.update_at(begin + Duration::from_millis(10), 441);
+        assert_eq!(estimator.elapsed_sample_time(), Duration::from_millis(10));
+        assert_eq!(estimator.elapsed_wall_time(), Duration::from_millis(10));
+        assert_eq!(estimator.drift_ppm(), None);
+    }
+
+    #[test]
+    fn estimates_drift() {
+        let begin = Instant::now();
+
+        // Sample clock is exact.
+        let mut estimator = DriftEstimator::new(44100.0);
+        estimator.update_at(begin, 441);
+        for i in 1..=1000 {
+            estimator.update_at(begin + Duration::from_millis(10 * i), 441);
+        }
+        assert_eq!(estimator.correction_factor(), Some(1.0));
+        assert_eq!(estimator.drift_ppm(), Some(0.0));
+
+        // Sample clock is 100 ppm too fast: 441044.1 instead of 441000 samples
+        // in 10 seconds.
+        let mut estimator = DriftEstimator::new(44100.0);
+        estimator.update_at(begin, 441);
+        estimator.update_at(begin + Duration::from_secs(5), 220_522);
+        estimator.update_at(begin + Duration::from_secs(10), 220_522);
+        let drift = estimator.drift_ppm().unwrap();
+        assert!((drift - 100.0).abs() < 1.0, "drift: {drift}");
+        assert!(estimator.correction_factor().unwrap() < 1.0);
+
+        // Sample clock is 100 ppm too slow.
+        let mut estimator = DriftEstimator::new(44100.0);
+        estimator.update_at(begin, 441);
+        estimator.update_at(begin + Duration::from_secs(10), 440_956);
+        let drift = estimator.drift_ppm().unwrap();
+        assert!((drift + 100.0).abs() < 1.0, "drift: {drift}");
+        assert!(estimator.correction_factor().unwrap() > 1.0);
+    }
+}
@@ -0,0 +1,167 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for driving a beat detector with audio from a [`SampleSource`].
+
+use crate::audio_io::{SampleSource, SourceError};
+use crate::stop::{StopSource, StopToken};
+use beat_detector_core::{BeatDetectorConst, BeatInfo};
+use std::vec;
+
+/// Duration of audio that is fed into the detector per step. This mimics the
+/// typical buffer size of audio input devices.
+pub(crate) const CHUNK_DURATION_MS: f32 = 20.0;
+
+/// Feeds all samples of `source` into `detector` and passes each detected
+/// beat to `sink`. Returns when the source is exhausted or fails.
+///
+/// The detector must have been created with the sampling rate of the source.
+pub fn run_detector<const N: usize, const D: usize, const P: usize>(
+    source: impl SampleSource,
+    detector: &mut BeatDetectorConst<N, D, P>,
+    sink: impl FnMut(BeatInfo),
+) -> Result<(), SourceError> {
+    run_detector_until(source, detector, &StopSource::new().token(), sink)
+}
+
+/// Like [`run_detector`], but additionally returns once `stop` is stopped,
+/// e.g., by a Ctrl+C handler or by `sink` itself.
+///
+/// `stop` is checked once per chunk of ~20 ms of audio. Sources that block,
+/// such as audio input devices, only return when they deliver new samples.
+pub fn run_detector_until<const N: usize, const D: usize, const P: usize>(
+    source: impl SampleSource,
+    detector: &mut BeatDetectorConst<N, D, P>,
+    stop: &StopToken,
+    mut sink: impl FnMut(BeatInfo),
+) -> Result<(), SourceError> {
+    run_detector_with_updates(source, detector, stop, |_detector, _chunk, beat| {
+        if let Some(beat) = beat {
+            sink(beat);
+        }
+    })
+    .map(|_is_exhausted| ())
+}
+
+/// Like [`run_detector_until`], but passes the detector to `on_update` after
+/// each chunk, together with the samples of the chunk and its beat, if any.
+///
+/// Returns whether the source was exhausted, i.e., `false` if `stop` was
+/// stopped before.
+pub(crate) fn run_detector_with_updates<const N: usize, const D: usize, const P: usize>(
+    mut source: impl SampleSource,
+    detector: &mut BeatDetectorConst<N, D, P>,
+    stop: &StopToken,
+    mut on_update: impl FnMut(&mut BeatDetectorConst<N, D, P>, &[i16], Option<BeatInfo>),
+) -> Result<bool, SourceError> {
+    let chunk_size = ((source.sample_rate() * CHUNK_DURATION_MS / 1000.0) as usize).max(1);
+    let mut buf = vec![0; chunk_size];
+    while !stop.is_stopped() {
+        let count = source.next_chunk(&mut buf)?;
+        if count == 0 {
+            return Ok(true);
+        }
+        let beat = detector.update_and_detect_beat(buf[..count].iter().copied());
+        on_update(detector, &buf[..count], beat);
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_io::memory::MemorySource;
+    use crate::test_utils;
+    use beat_detector_core::BeatDetector;
+    use std::io::ErrorKind;
+    use std::vec::Vec;
+
+    /// Fails after the inner source delivered a certain amount of chunks.
+    struct FailingSource<'a> {
+        inner: MemorySource<'a>,
+        chunks_left: usize,
+    }
+
+    impl SampleSource for FailingSource<'_> {
+        fn sample_rate(&self) -> f32 {
+            self.inner.sample_rate()
+        }
+
+        fn next_chunk(&mut self, buf: &mut [i16]) -> Result<usize, SourceError> {
+            if self.chunks_left == 0 {
+                return Err(SourceError::Io(ErrorKind::ConnectionReset.into()));
+            }
+            self.chunks_left -= 1;
+            self.inner.next_chunk(buf)
+        }
+    }
+
+    #[test]
+    fn run_detector_detects_all_beats() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sampling_rate = header.sample_rate as f32;
+        let mut detector = BeatDetector::new(sampling_rate, true);
+        let mut beats = Vec::new();
+        run_detector(
+            MemorySource::new(&samples, sampling_rate),
+            &mut detector,
+            |beat| beats.push(beat.max.total_index),
+        )
+        .unwrap();
+        assert_eq!(beats, &[31331, 47161, 65921, 84221, 102111, 120251, 138561]);
+    }
+
+    #[test]
+    fn run_detector_until_stops() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sampling_rate = header.sample_rate as f32;
+        let mut detector = BeatDetector::new(sampling_rate, true);
+        let stop = StopSource::new();
+        let mut beats = 0;
+        run_detector_until(
+            MemorySource::new(&samples, sampling_rate),
+            &mut detector,
+            &stop.token(),
+            |_| {
+                beats += 1;
+                stop.stop();
+            },
+        )
+        .unwrap();
+        assert_eq!(beats, 1);
+    }
+
+    #[test]
+    fn run_detector_propagates_errors() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sampling_rate = header.sample_rate as f32;
+        let mut detector = BeatDetector::new(sampling_rate, true);
+        let source = FailingSource {
+            inner: MemorySource::new(&samples, sampling_rate),
+            chunks_left: 3,
+        };
+        let res = run_detector(source, &mut detector, |_| {});
+        assert!(matches!(res, Err(SourceError::Io(_))));
+        assert!(detector.passed_time() > core::time::Duration::ZERO);
+    }
+}
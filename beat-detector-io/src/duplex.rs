@@ -0,0 +1,262 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for opening an audio input and an audio output with consistent
+//! clocks, e.g., to play the clicks of a [`Metronome`] while recording.
+//!
+//! [`Metronome`]: crate::metronome::Metronome
+
+use crate::metronome::{start_metronome_with_config, Metronome, StartMetronomeError};
+use crate::recording::{start_detector_thread_impl, LiveBeatInfo, StartDetectorThreadError};
+use core::fmt::{Debug, Display, Formatter};
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{BufferSize, SampleRate, StreamConfig};
+use std::error::Error;
+use std::string::ToString;
+use std::vec::Vec;
+
+/// Errors of [`DuplexDevices::open`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DuplexError {
+    /// There was no input device provided and no default device can be found.
+    NoDefaultInputDevice,
+    /// There is no output device.
+    NoOutputDevice,
+    /// There was a problem detecting the input stream config.
+    InputConfigError(cpal::DefaultStreamConfigError),
+    /// There was a problem detecting the output stream config.
+    OutputConfigError(cpal::DefaultStreamConfigError),
+    /// The input and the output don't support a common sampling rate.
+    NoCommonSampleRate,
+}
+
+impl Display for DuplexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!("{:?}", self))
+    }
+}
+
+impl Error for DuplexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InputConfigError(err) => Some(err),
+            Self::OutputConfigError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A matched pair of an audio input device and an audio output device with
+/// the same sampling rate.
+///
+/// If possible, the output belongs to the same hardware as the input, e.g.,
+/// the same USB audio interface, so that both streams run on the same clock.
+/// Otherwise, the default output device of the platform is used, whose clock
+/// may drift slightly against the clock of the input.
+pub struct DuplexDevices {
+    input: cpal::Device,
+    output: cpal::Device,
+    input_config: StreamConfig,
+    output_config: StreamConfig,
+    same_device: bool,
+}
+
+impl DuplexDevices {
+    /// Selects the preferred input device or the default input device of the
+    /// platform, a matching output device, and a common sampling rate.
+    pub fn open(preferred_input_dev: Option<cpal::Device>) -> Result<Self, DuplexError> {
+        let host = cpal::default_host();
+        let input = preferred_input_dev
+            .or_else(|| host.default_input_device())
+            .ok_or(DuplexError::NoDefaultInputDevice)?;
+        let input_name = input.name().ok();
+
+        // Prefer the output of the same hardware. The names of the input and
+        // the output are the same on most backends then.
+        let same_output = input_name.as_ref().and_then(|input_name| {
+            host.output_devices()
+                .ok()?
+                .find(|output| output.name().ok().as_ref() == Some(input_name))
+        });
+        let same_device = same_output.is_some();
+        let output = same_output
+            .or_else(|| host.default_output_device())
+            .ok_or(DuplexError::NoOutputDevice)?;
+
+        let input_default = input
+            .default_input_config()
+            .map_err(DuplexError::InputConfigError)?;
+        let output_default = output
+            .default_output_config()
+            .map_err(DuplexError::OutputConfigError)?;
+        let input_rates = input
+            .supported_input_configs()
+            .map(|configs| {
+                configs
+                    .map(|config| (config.min_sample_rate().0, config.max_sample_rate().0))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let output_rates = output
+            .supported_output_configs()
+            .map(|configs| {
+                configs
+                    .map(|config| (config.min_sample_rate().0, config.max_sample_rate().0))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let sample_rate = common_sample_rate(
+            input_default.sample_rate().0,
+            &input_rates,
+            output_default.sample_rate().0,
+            &output_rates,
+        )
+        .ok_or(DuplexError::NoCommonSampleRate)?;
+
+        let duplex = Self {
+            input,
+            output,
+            input_config: StreamConfig {
+                channels: 1,
+                sample_rate: SampleRate(sample_rate),
+                buffer_size: BufferSize::Default,
+            },
+            output_config: StreamConfig {
+                channels: output_default.channels(),
+                sample_rate: SampleRate(sample_rate),
+                buffer_size: BufferSize::Default,
+            },
+            same_device,
+        };
+        log::debug!("Duplex devices: {duplex:#?}");
+        Ok(duplex)
+    }
+
+    /// Returns the input device.
+    pub const fn input(&self) -> &cpal::Device {
+        &self.input
+    }
+
+    /// Returns the output device.
+    pub const fn output(&self) -> &cpal::Device {
+        &self.output
+    }
+
+    /// Returns the mono configuration of the input stream.
+    pub const fn input_config(&self) -> &StreamConfig {
+        &self.input_config
+    }
+
+    /// Returns the configuration of the output stream.
+    pub const fn output_config(&self) -> &StreamConfig {
+        &self.output_config
+    }
+
+    /// Returns the common sampling rate of the input and the output.
+    pub const fn sample_rate(&self) -> u32 {
+        self.input_config.sample_rate.0
+    }
+
+    /// Returns whether the input and the output belong to the same hardware,
+    /// so that their clocks don't drift against each other.
+    pub const fn same_device(&self) -> bool {
+        self.same_device
+    }
+
+    /// Like [`recording::start_detector_thread_with_timestamps`], but records
+    /// from the input of the pair.
+    ///
+    /// [`recording::start_detector_thread_with_timestamps`]: crate::recording::start_detector_thread_with_timestamps
+    pub fn start_detector_thread(
+        &self,
+        on_beat_cb: impl Fn(LiveBeatInfo) + Send + 'static,
+    ) -> Result<cpal::Stream, StartDetectorThreadError> {
+        start_detector_thread_impl(
+            on_beat_cb,
+            None,
+            None,
+            false,
+            self.input.clone(),
+            self.input_config.clone(),
+        )
+    }
+
+    /// Like [`metronome::start_metronome`], but plays the clicks on the
+    /// output of the pair.
+    ///
+    /// [`metronome::start_metronome`]: crate::metronome::start_metronome
+    pub fn start_metronome(&self) -> Result<(Metronome, cpal::Stream), StartMetronomeError> {
+        start_metronome_with_config(&self.output, &self.output_config)
+    }
+}
+
+impl Debug for DuplexDevices {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let name =
+            |device: &cpal::Device| device.name().unwrap_or_else(|_| "<unknown>".to_string());
+        f.debug_struct("DuplexDevices")
+            .field("input", &name(&self.input))
+            .field("output", &name(&self.output))
+            .field("input_config", &self.input_config)
+            .field("output_config", &self.output_config)
+            .field("same_device", &self.same_device)
+            .finish()
+    }
+}
+
+/// Selects a sampling rate that both the input and the output support, given
+/// their default rates and their supported ranges. The default rate of the
+/// input wins, as resampling the input would affect the detection.
+fn common_sample_rate(
+    input_default: u32,
+    input_ranges: &[(u32, u32)],
+    output_default: u32,
+    output_ranges: &[(u32, u32)],
+) -> Option<u32> {
+    let supports = |ranges: &[(u32, u32)], rate: u32| {
+        ranges.iter().any(|&(min, max)| (min..=max).contains(&rate))
+    };
+    [input_default, output_default]
+        .into_iter()
+        .find(|&rate| supports(input_ranges, rate) && supports(output_ranges, rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_rate_selection() {
+        let wide = [(8000, 192_000)];
+        assert_eq!(common_sample_rate(48000, &wide, 44100, &wide), Some(48000));
+        assert_eq!(
+            common_sample_rate(48000, &wide, 44100, &[(44100, 44100)]),
+            Some(44100)
+        );
+        assert_eq!(
+            common_sample_rate(48000, &[(48000, 48000)], 44100, &[(44100, 44100)]),
+            None
+        );
+    }
+}
@@ -0,0 +1,151 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`Error`].
+
+use beat_detector_core::util::OutOfRangeError;
+use beat_detector_core::{CustomFilterError, SnapshotError};
+use core::fmt::{Display, Formatter};
+
+/// Top-level error type of the crate that covers all failure classes,
+/// including the ones of the detection pipeline.
+///
+/// All specific error types of this crate and of `beat-detector-core`
+/// convert into this type via [`From`], so applications can use `?` and match
+/// on the failure class uniformly.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An audio sample was out of the valid range.
+    SampleOutOfRange(OutOfRangeError),
+    /// A snapshot couldn't be created or loaded.
+    Snapshot(SnapshotError),
+    /// A custom filter is invalid.
+    CustomFilter(CustomFilterError),
+    /// The priority of a thread couldn't be raised.
+    ThreadPriority(crate::thread_priority::ThreadPriorityError),
+    /// A sample source failed.
+    Source(crate::audio_io::SourceError),
+    /// An offline analysis failed or was cancelled.
+    Analysis(crate::offline::AnalysisError),
+    /// The detector thread for live audio input couldn't be started.
+    #[cfg(feature = "recording")]
+    StartDetectorThread(crate::recording::StartDetectorThreadError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::SampleOutOfRange(err) => write!(f, "sample out of range: {err}"),
+            Self::Snapshot(err) => write!(f, "snapshot error: {err}"),
+            Self::CustomFilter(err) => write!(f, "invalid custom filter: {err}"),
+            Self::ThreadPriority(err) => write!(f, "can't raise thread priority: {err}"),
+            Self::Source(err) => write!(f, "sample source failed: {err}"),
+            Self::Analysis(err) => write!(f, "offline analysis failed: {err}"),
+            #[cfg(feature = "recording")]
+            Self::StartDetectorThread(err) => write!(f, "can't start detector thread: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::SampleOutOfRange(err) => Some(err),
+            Self::Snapshot(err) => Some(err),
+            Self::CustomFilter(err) => Some(err),
+            Self::ThreadPriority(err) => Some(err),
+            Self::Source(err) => Some(err),
+            Self::Analysis(err) => Some(err),
+            #[cfg(feature = "recording")]
+            Self::StartDetectorThread(err) => Some(err),
+        }
+    }
+}
+
+impl From<OutOfRangeError> for Error {
+    fn from(err: OutOfRangeError) -> Self {
+        Self::SampleOutOfRange(err)
+    }
+}
+
+impl From<SnapshotError> for Error {
+    fn from(err: SnapshotError) -> Self {
+        Self::Snapshot(err)
+    }
+}
+
+impl From<CustomFilterError> for Error {
+    fn from(err: CustomFilterError) -> Self {
+        Self::CustomFilter(err)
+    }
+}
+
+impl From<crate::thread_priority::ThreadPriorityError> for Error {
+    fn from(err: crate::thread_priority::ThreadPriorityError) -> Self {
+        Self::ThreadPriority(err)
+    }
+}
+
+impl From<crate::audio_io::SourceError> for Error {
+    fn from(err: crate::audio_io::SourceError) -> Self {
+        Self::Source(err)
+    }
+}
+
+impl From<crate::offline::AnalysisError> for Error {
+    fn from(err: crate::offline::AnalysisError) -> Self {
+        Self::Analysis(err)
+    }
+}
+
+#[cfg(feature = "recording")]
+impl From<crate::recording::StartDetectorThreadError> for Error {
+    fn from(err: crate::recording::StartDetectorThreadError) -> Self {
+        Self::StartDetectorThread(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_io::SourceError;
+    use beat_detector_core::util::f32_sample_to_i16;
+
+    #[test]
+    fn errors_convert_into_top_level_error() {
+        fn convert(val: f32) -> Result<i16, Error> {
+            Ok(f32_sample_to_i16(val)?)
+        }
+
+        let err = convert(2.0).unwrap_err();
+        assert!(matches!(err, Error::SampleOutOfRange(_)));
+        assert_eq!(convert(0.0).unwrap(), 0);
+
+        let err = Error::from(SourceError::UnsupportedFormat);
+        assert_eq!(
+            err.to_string(),
+            "sample source failed: unsupported audio format"
+        );
+    }
+}
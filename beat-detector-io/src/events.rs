@@ -0,0 +1,455 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`EventBus`].
+
+use crate::audio_io::{SampleSource, SourceError};
+use crate::driver::run_detector_with_updates;
+use crate::stop::StopToken;
+use beat_detector_core::{
+    BeatDetectorConst, BeatInfo, ChunkLevel, DropConfig, DropTracker, DropTransition, Heartbeat,
+    HeartbeatGenerator, TempoConfig, TempoEstimator,
+};
+use core::fmt::{Debug, Formatter};
+use core::time::Duration;
+use std::boxed::Box;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::vec::Vec;
+
+/// Chunks with a lower RMS are considered as no signal.
+const SIGNAL_LOST_DBFS: f32 = -60.0;
+
+/// The signal must be missing for this long before [`Event::SignalLost`].
+const SIGNAL_LOST_AFTER: Duration = Duration::from_secs(2);
+
+/// The tempo must change by at least this many BPM for
+/// [`Event::TempoChanged`], so that subscribers aren't flooded by jitter.
+const TEMPO_CHANGE_BPM: f32 = 1.0;
+
+/// A runtime condition of the beat detection, see [`EventBus`].
+// Events are rare, boxing the beat isn't worth giving up `Copy`.
+#[allow(clippy::large_enum_variant)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A beat was detected.
+    Beat(BeatInfo),
+    /// The estimated tempo changed noticeably or became known.
+    TempoChanged {
+        /// The new tempo in BPM.
+        bpm: f32,
+        /// The previously reported tempo in BPM, if any.
+        previous: Option<f32>,
+    },
+    /// The music entered another section, such as a build-up or a drop, see
+    /// [`DropTracker`].
+    SectionChange(DropTransition),
+    /// The audio input is silent for a while, e.g., because a cable was
+    /// unplugged.
+    SignalLost {
+        /// Time of the audio at which the silence began.
+        since: Duration,
+    },
+    /// The audio input has a signal again after [`Event::SignalLost`].
+    SignalRestored,
+    /// The audio input began clipping.
+    Clipping,
+    /// Regular health information, see [`EventBusConfig::heartbeat_interval`].
+    Heartbeat(Heartbeat),
+}
+
+impl Event {
+    /// Returns the kind of the event.
+    pub const fn kind(&self) -> EventKind {
+        match self {
+            Self::Beat(_) => EventKind::Beat,
+            Self::TempoChanged { .. } => EventKind::TempoChanged,
+            Self::SectionChange(_) => EventKind::SectionChange,
+            Self::SignalLost { .. } => EventKind::SignalLost,
+            Self::SignalRestored => EventKind::SignalRestored,
+            Self::Clipping => EventKind::Clipping,
+            Self::Heartbeat(_) => EventKind::Heartbeat,
+        }
+    }
+}
+
+/// Kind of an [`Event`], to subscribe to a subset of the events.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EventKind {
+    /// See [`Event::Beat`].
+    Beat,
+    /// See [`Event::TempoChanged`].
+    TempoChanged,
+    /// See [`Event::SectionChange`].
+    SectionChange,
+    /// See [`Event::SignalLost`].
+    SignalLost,
+    /// See [`Event::SignalRestored`].
+    SignalRestored,
+    /// See [`Event::Clipping`].
+    Clipping,
+    /// See [`Event::Heartbeat`].
+    Heartbeat,
+}
+
+impl EventKind {
+    /// All kinds of events.
+    pub const ALL: [Self; 7] = [
+        Self::Beat,
+        Self::TempoChanged,
+        Self::SectionChange,
+        Self::SignalLost,
+        Self::SignalRestored,
+        Self::Clipping,
+        Self::Heartbeat,
+    ];
+}
+
+/// Configuration of an [`EventBus`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventBusConfig {
+    /// Configuration of the tempo estimation for [`Event::TempoChanged`].
+    pub tempo: TempoConfig,
+    /// Configuration of the section tracking for [`Event::SectionChange`].
+    pub sections: DropConfig,
+    /// Interval of [`Event::Heartbeat`]. `None` disables the heartbeats.
+    pub heartbeat_interval: Option<Duration>,
+}
+
+/// Identifies a subscription of an [`EventBus`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+enum Sink {
+    Callback(Box<dyn FnMut(&Event) + Send>),
+    Channel(Sender<Event>),
+}
+
+struct Subscriber {
+    id: SubscriptionId,
+    kinds: Vec<EventKind>,
+    sink: Sink,
+}
+
+/// Emits all runtime conditions of the beat detection as typed [`Event`]s
+/// to its subscribers, so that applications handle them in one place instead
+/// of wiring up a callback per condition.
+///
+/// Subscribers are callbacks, see [`Self::subscribe`], or channels, see
+/// [`Self::subscribe_channel`], and receive the events of the kinds they
+/// subscribed to, in the order they occurred. The bus is fed after each
+/// update of the detector, see [`Self::update`], or drives the detector
+/// itself, see [`Self::run`].
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::BeatDetector;
+/// use beat_detector_io::events::{Event, EventBus, EventBusConfig, EventKind};
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut bus = EventBus::new(EventBusConfig::default());
+/// bus.subscribe(&[EventKind::Beat], |event| println!("{event:?}"));
+/// let events = bus.subscribe_channel(&EventKind::ALL);
+///
+/// // TODO regularly call this with the latest audio data.
+/// let beat = detector.update_and_detect_beat(mono_samples.iter().copied());
+/// bus.update(&detector, &mono_samples, beat.as_ref());
+///
+/// for event in events.try_iter() {
+///     match event {
+///         Event::SignalLost { .. } => println!("check the cables"),
+///         _ => {}
+///     }
+/// }
+/// ```
+pub struct EventBus {
+    subscribers: Vec<Subscriber>,
+    next_id: u64,
+    tempo: TempoEstimator,
+    /// The tempo of the latest [`Event::TempoChanged`].
+    reported_bpm: Option<f32>,
+    sections: DropTracker,
+    heartbeat: Option<HeartbeatGenerator>,
+    stream_alive: bool,
+    /// Begin of the current silence, if any.
+    silent_since: Option<Duration>,
+    signal_lost: bool,
+    clipping: bool,
+}
+
+impl Debug for EventBus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &self.subscribers.len())
+            .field("tempo", &self.tempo)
+            .field("sections", &self.sections)
+            .field("signal_lost", &self.signal_lost)
+            .field("clipping", &self.clipping)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EventBus {
+    /// Creates a new bus without subscribers. Panics if the configuration is
+    /// invalid.
+    pub fn new(config: EventBusConfig) -> Self {
+        Self {
+            subscribers: Vec::new(),
+            next_id: 0,
+            tempo: TempoEstimator::new(config.tempo),
+            reported_bpm: None,
+            sections: DropTracker::new(config.sections),
+            heartbeat: config.heartbeat_interval.map(HeartbeatGenerator::new),
+            stream_alive: true,
+            silent_since: None,
+            signal_lost: false,
+            clipping: false,
+        }
+    }
+
+    /// Subscribes the callback to the events of the given kinds. The
+    /// callback runs in the thread that feeds the bus, which may be the
+    /// audio thread, so it should return quickly.
+    pub fn subscribe(
+        &mut self,
+        kinds: &[EventKind],
+        callback: impl FnMut(&Event) + Send + 'static,
+    ) -> SubscriptionId {
+        self.add_subscriber(kinds, Sink::Callback(Box::new(callback)))
+    }
+
+    /// Subscribes to the events of the given kinds via a channel, e.g., to
+    /// handle them in another thread. The subscription ends when the
+    /// receiver is dropped.
+    pub fn subscribe_channel(&mut self, kinds: &[EventKind]) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.add_subscriber(kinds, Sink::Channel(sender));
+        receiver
+    }
+
+    fn add_subscriber(&mut self, kinds: &[EventKind], sink: Sink) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscribers.push(Subscriber {
+            id,
+            kinds: kinds.to_vec(),
+            sink,
+        });
+        id
+    }
+
+    /// Ends the subscription. Returns `false` if it didn't exist.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers.retain(|subscriber| subscriber.id != id);
+        self.subscribers.len() != len
+    }
+
+    /// Returns the tempo estimation that drives [`Event::TempoChanged`].
+    pub const fn tempo(&self) -> &TempoEstimator {
+        &self.tempo
+    }
+
+    /// Marks the audio stream as failed, so that the next
+    /// [`Event::Heartbeat`] reports [`Heartbeat::stream_alive`] as `false`.
+    /// Call this from the error callback of the audio stream.
+    pub fn report_stream_error(&mut self) {
+        self.stream_alive = false;
+    }
+
+    /// Supposed to be called after each update of the detector with the raw
+    /// samples of the update and the beat it reported, if any. Emits the
+    /// events of the update.
+    pub fn update<const N: usize, const D: usize, const P: usize>(
+        &mut self,
+        detector: &BeatDetectorConst<N, D, P>,
+        mono_samples: &[i16],
+        beat: Option<&BeatInfo>,
+    ) {
+        let now = detector.passed_time();
+        let level = ChunkLevel::measure(mono_samples);
+
+        let clipping = detector.is_clipping();
+        if clipping && !self.clipping {
+            self.emit(&Event::Clipping);
+        }
+        self.clipping = clipping;
+
+        if level.rms_dbfs() < SIGNAL_LOST_DBFS {
+            let since = *self.silent_since.get_or_insert(now);
+            if !self.signal_lost && now.saturating_sub(since) >= SIGNAL_LOST_AFTER {
+                self.signal_lost = true;
+                self.emit(&Event::SignalLost { since });
+            }
+        } else {
+            self.silent_since = None;
+            if self.signal_lost {
+                self.signal_lost = false;
+                self.emit(&Event::SignalRestored);
+            }
+        }
+
+        if let Some(beat) = beat {
+            self.emit(&Event::Beat(*beat));
+            let bpm = self.tempo.update(beat.timestamp());
+            if let Some(bpm) = bpm {
+                let previous = self.reported_bpm;
+                if previous.map_or(true, |previous| (bpm - previous).abs() >= TEMPO_CHANGE_BPM) {
+                    self.reported_bpm = Some(bpm);
+                    self.emit(&Event::TempoChanged { bpm, previous });
+                }
+            }
+        }
+
+        if let Some(transition) = self
+            .sections
+            .update(now, &level, beat.is_some(), &self.tempo)
+        {
+            self.emit(&Event::SectionChange(transition));
+        }
+
+        if let Some(generator) = self.heartbeat.as_mut() {
+            if let Some(heartbeat) = generator.poll(detector, self.stream_alive) {
+                self.stream_alive = true;
+                self.emit(&Event::Heartbeat(heartbeat));
+            }
+        }
+    }
+
+    /// Feeds all samples of `source` into `detector` and emits the events,
+    /// like [`run_detector_until`]. Typically runs in a dedicated thread.
+    ///
+    /// [`run_detector_until`]: crate::driver::run_detector_until
+    pub fn run<const N: usize, const D: usize, const P: usize>(
+        &mut self,
+        source: impl SampleSource,
+        detector: &mut BeatDetectorConst<N, D, P>,
+        stop: &StopToken,
+    ) -> Result<(), SourceError> {
+        run_detector_with_updates(source, detector, stop, |detector, chunk, beat| {
+            self.update(detector, chunk, beat.as_ref());
+        })
+        .map(|_is_exhausted| ())
+    }
+
+    fn emit(&mut self, event: &Event) {
+        let kind = event.kind();
+        self.subscribers.retain_mut(|subscriber| {
+            if !subscriber.kinds.contains(&kind) {
+                return true;
+            }
+            match &mut subscriber.sink {
+                Sink::Callback(callback) => {
+                    callback(event);
+                    true
+                }
+                // The receiver was dropped.
+                Sink::Channel(sender) => sender.send(*event).is_ok(),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_io::memory::MemorySource;
+    use crate::stop::StopSource;
+    use crate::test_utils;
+    use beat_detector_core::BeatDetector;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn emits_beats_and_tempo() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        let mut bus = EventBus::new(EventBusConfig {
+            heartbeat_interval: Some(Duration::from_secs(1)),
+            ..EventBusConfig::default()
+        });
+        let beats = Arc::new(Mutex::new(0));
+        {
+            let beats = beats.clone();
+            bus.subscribe(&[EventKind::Beat], move |event| {
+                assert!(matches!(event, Event::Beat(_)));
+                *beats.lock().unwrap() += 1;
+            });
+        }
+        let events = bus.subscribe_channel(&EventKind::ALL);
+        let tempo_events = bus.subscribe_channel(&[EventKind::TempoChanged]);
+        drop(tempo_events);
+
+        let source = MemorySource::new(&samples, header.sample_rate as f32);
+        bus.run(source, &mut detector, &StopSource::new().token())
+            .unwrap();
+
+        let events = events.try_iter().collect::<Vec<_>>();
+        let count = |kind| events.iter().filter(|event| event.kind() == kind).count();
+        assert!(*beats.lock().unwrap() > 5);
+        assert_eq!(count(EventKind::Beat), *beats.lock().unwrap());
+        assert!(count(EventKind::TempoChanged) >= 1);
+        assert!(count(EventKind::Heartbeat) >= 1);
+        assert_eq!(count(EventKind::SignalLost), 0);
+        // The dropped receiver was unsubscribed.
+        assert_eq!(bus.subscribers.len(), 2);
+    }
+
+    #[test]
+    fn signal_lost_and_restored() {
+        let mut detector = BeatDetector::new(44100.0, true);
+        let mut bus = EventBus::new(EventBusConfig::default());
+        let events = bus.subscribe_channel(&[EventKind::SignalLost, EventKind::SignalRestored]);
+        let mut feed = |value: i16, seconds: usize| {
+            let chunk = [value; 441];
+            for _ in 0..seconds * 100 {
+                let beat = detector.update_and_detect_beat(chunk.iter().copied());
+                bus.update(&detector, &chunk, beat.as_ref());
+            }
+        };
+        feed(5000, 1);
+        feed(0, 3);
+        feed(5000, 1);
+
+        let events = events.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                Event::SignalLost {
+                    since: Duration::from_millis(1010)
+                },
+                Event::SignalRestored
+            ]
+        );
+    }
+
+    #[test]
+    fn unsubscribe() {
+        let mut bus = EventBus::new(EventBusConfig::default());
+        let id = bus.subscribe(&EventKind::ALL, |_| panic!("unsubscribed"));
+        assert!(bus.unsubscribe(id));
+        assert!(!bus.unsubscribe(id));
+        bus.emit(&Event::Clipping);
+    }
+}
@@ -0,0 +1,307 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for recording new test fixtures from live input.
+//!
+//! A fixture is a mono WAV file and an annotation file with the beats that a
+//! human perceived while listening, e.g., by pressing a key. The annotation
+//! lives next to the WAV file and has the same name but the extension `txt`.
+//! It contains the time of one beat per line in seconds. Lines starting with
+//! `#` are comments. This is the format most beat tracking datasets use.
+
+use crate::audio_io::{SampleSource, SourceError};
+use crate::stop::StopToken;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use std::vec;
+use std::vec::Vec;
+
+/// Duration of audio that is recorded per step in [`record_fixture`].
+const CHUNK_DURATION_MS: u32 = 20;
+
+/// Mono audio together with annotated beats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    /// Sampling rate of [`Self::samples`] in Hz.
+    pub sampling_rate: u32,
+    /// The mono samples.
+    pub samples: Vec<i16>,
+    /// Indices of the samples at which beats were perceived, in ascending
+    /// order.
+    pub beats: Vec<u64>,
+}
+
+impl Fixture {
+    /// Returns the time of each beat relative to the beginning of the audio.
+    pub fn beat_times(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.beats
+            .iter()
+            .map(|&index| Duration::from_secs_f64(index as f64 / self.sampling_rate as f64))
+    }
+
+    /// Returns the path of the annotation file that belongs to the WAV file
+    /// at `wav_path`.
+    pub fn annotation_path(wav_path: impl AsRef<Path>) -> PathBuf {
+        wav_path.as_ref().with_extension("txt")
+    }
+
+    /// Writes the audio to `wav_path` and the beats to the corresponding
+    /// [annotation file](Self::annotation_path).
+    pub fn save(&self, wav_path: impl AsRef<Path>) -> Result<(), SourceError> {
+        let wav_path = wav_path.as_ref();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: self.sampling_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(wav_path, spec)?;
+        for &sample in &self.samples {
+            writer.write_sample(sample)?;
+        }
+        writer.finalize()?;
+
+        let mut annotation = BufWriter::new(File::create(Self::annotation_path(wav_path))?);
+        writeln!(annotation, "# beat times in seconds")?;
+        for time in self.beat_times() {
+            writeln!(annotation, "{:.6}", time.as_secs_f64())?;
+        }
+        annotation.flush()?;
+        Ok(())
+    }
+
+    /// Reads a fixture that was written by [`Self::save`] or that follows the
+    /// same format. The WAV file must contain mono 16 bit samples.
+    pub fn load(wav_path: impl AsRef<Path>) -> Result<Self, SourceError> {
+        let wav_path = wav_path.as_ref();
+        let reader = hound::WavReader::open(wav_path)?;
+        let spec = reader.spec();
+        if spec.channels != 1
+            || spec.sample_format != hound::SampleFormat::Int
+            || spec.bits_per_sample != 16
+        {
+            return Err(SourceError::UnsupportedFormat);
+        }
+        let samples = reader.into_samples().collect::<Result<Vec<i16>, _>>()?;
+
+        let annotation = BufReader::new(File::open(Self::annotation_path(wav_path))?);
+        let mut beats = Vec::new();
+        for line in annotation.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let seconds = line.parse::<f64>().map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    std::format!("invalid beat time in annotation: {line}"),
+                )
+            })?;
+            beats.push((seconds * spec.sample_rate as f64).round() as u64);
+        }
+        beats.sort_unstable();
+
+        Ok(Self {
+            sampling_rate: spec.sample_rate,
+            samples,
+            beats,
+        })
+    }
+}
+
+/// Records a [`Fixture`] of a limited duration while beats are marked from
+/// another thread.
+///
+/// Typically, one thread feeds the audio of an input device into the recorder
+/// (see [`record_fixture`]) while another thread calls
+/// [`FixtureRecorder::mark_beat`] whenever the user presses a key.
+#[derive(Debug)]
+pub struct FixtureRecorder {
+    fixture: Mutex<Fixture>,
+    max_samples: usize,
+    tap_latency: Duration,
+}
+
+impl FixtureRecorder {
+    /// Creates a new recorder for audio with the given sampling rate, which
+    /// records at most `max_duration` of audio.
+    pub fn new(sampling_rate: u32, max_duration: Duration) -> Self {
+        let max_samples = (max_duration.as_secs_f64() * sampling_rate as f64) as usize;
+        Self {
+            fixture: Mutex::new(Fixture {
+                sampling_rate,
+                samples: Vec::with_capacity(max_samples),
+                beats: Vec::new(),
+            }),
+            max_samples,
+            tap_latency: Duration::ZERO,
+        }
+    }
+
+    /// Sets the time between the perceived beat and the call to
+    /// [`Self::mark_beat`], e.g., the latency of the keyboard and the
+    /// reaction time of the user. Marks are shifted back by this duration.
+    /// The default is zero.
+    pub fn set_tap_latency(&mut self, tap_latency: Duration) {
+        self.tap_latency = tap_latency;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Fixture> {
+        // The data stays consistent even if another thread panicked.
+        self.fixture
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Appends samples to the recording. Samples that exceed the maximum
+    /// duration are dropped. Returns `false` once the recording is full.
+    pub fn push(&self, samples: &[i16]) -> bool {
+        let mut fixture = self.lock();
+        let count = samples.len().min(self.max_samples - fixture.samples.len());
+        fixture.samples.extend_from_slice(&samples[..count]);
+        fixture.samples.len() < self.max_samples
+    }
+
+    /// Marks a beat at the current end of the recording, shifted back by the
+    /// [tap latency](Self::set_tap_latency). Marks before the beginning of
+    /// the recording are ignored.
+    pub fn mark_beat(&self) {
+        let mut fixture = self.lock();
+        let latency = (self.tap_latency.as_secs_f64() * fixture.sampling_rate as f64) as u64;
+        let Some(index) = (fixture.samples.len() as u64).checked_sub(latency) else {
+            return;
+        };
+        // Beats are in ascending order as long as the tap latency is
+        // constant.
+        fixture.beats.push(index);
+    }
+
+    /// Returns the number of recorded samples.
+    pub fn len(&self) -> usize {
+        self.lock().samples.len()
+    }
+
+    /// Returns `true` if no samples were recorded yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the maximum duration is recorded.
+    pub fn is_full(&self) -> bool {
+        self.len() == self.max_samples
+    }
+
+    /// Returns a copy of the fixture recorded so far. Useful if the recorder
+    /// is still shared with other threads.
+    pub fn snapshot(&self) -> Fixture {
+        self.lock().clone()
+    }
+
+    /// Returns the recorded fixture.
+    pub fn finish(self) -> Fixture {
+        self.fixture
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Feeds samples of `source` into `recorder` until the recording is full, the
+/// source is exhausted, or `stop` is stopped.
+///
+/// The recorder must have been created with the sampling rate of the source.
+pub fn record_fixture(
+    mut source: impl SampleSource,
+    recorder: &FixtureRecorder,
+    stop: &StopToken,
+) -> Result<(), SourceError> {
+    let chunk_size = ((source.sample_rate() as u32 * CHUNK_DURATION_MS / 1000) as usize).max(1);
+    let mut buf = vec![0; chunk_size];
+    while !stop.is_stopped() {
+        let count = source.next_chunk(&mut buf)?;
+        if count == 0 || !recorder.push(&buf[..count]) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_io::memory::MemorySource;
+    use crate::stop::StopSource;
+    use crate::test_utils;
+
+    #[test]
+    fn recorder_marks_beats_and_respects_the_duration() {
+        let mut recorder = FixtureRecorder::new(1000, Duration::from_millis(100));
+        recorder.set_tap_latency(Duration::from_millis(10));
+        assert!(recorder.is_empty());
+
+        // Too early: before the beginning of the recording.
+        recorder.mark_beat();
+        assert!(recorder.push(&[1; 50]));
+        recorder.mark_beat();
+        assert!(!recorder.push(&[2; 70]));
+        recorder.mark_beat();
+        assert!(recorder.is_full());
+
+        let fixture = recorder.finish();
+        assert_eq!(fixture.samples.len(), 100);
+        assert_eq!(fixture.samples[99], 2);
+        assert_eq!(fixture.beats, &[40, 90]);
+        assert_eq!(
+            fixture.beat_times().collect::<Vec<_>>(),
+            &[Duration::from_millis(40), Duration::from_millis(90)]
+        );
+    }
+
+    #[test]
+    fn fixture_roundtrip() {
+        let (samples, header) = test_utils::samples::holiday_single_beat();
+        let recorder = FixtureRecorder::new(header.sample_rate, Duration::from_secs(10));
+        record_fixture(
+            MemorySource::new(&samples, header.sample_rate as f32),
+            &recorder,
+            &StopSource::new().token(),
+        )
+        .unwrap();
+        recorder.mark_beat();
+        let fixture = recorder.finish();
+        assert_eq!(fixture.samples, samples);
+
+        let path = std::env::temp_dir().join(std::format!(
+            "beat-detector-fixture-{}.wav",
+            std::process::id()
+        ));
+        fixture.save(&path).unwrap();
+        let loaded = Fixture::load(&path);
+        let _ = std::fs::remove_file(Fixture::annotation_path(&path));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.unwrap(), fixture);
+    }
+}
@@ -0,0 +1,263 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Module for measuring the processing time of audio chunks against a latency
+//! budget.
+
+use core::fmt::{Debug, Formatter};
+use std::boxed::Box;
+use std::time::{Duration, Instant};
+
+/// Budget for the processing time of a chunk of audio. The budget is relative
+/// to the duration of the audio in the chunk, as audio input libraries don't
+/// always deliver chunks of the same size.
+///
+/// For example, `LatencyBudget::new(Duration::from_millis(10),
+/// Duration::from_millis(20))` allows 10 ms of processing time for 20 ms of
+/// audio, and 5 ms for 10 ms of audio.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LatencyBudget {
+    processing_time: Duration,
+    chunk_duration: Duration,
+}
+
+impl LatencyBudget {
+    /// Creates a new budget that allows `processing_time` for each
+    /// `chunk_duration` of audio.
+    pub fn new(processing_time: Duration, chunk_duration: Duration) -> Self {
+        assert!(!chunk_duration.is_zero());
+        Self {
+            processing_time,
+            chunk_duration,
+        }
+    }
+
+    /// Budget that allows as much processing time as the audio in the chunk
+    /// lasts. Exceeding this means the processing can't keep up with the audio
+    /// source.
+    pub fn realtime() -> Self {
+        Self::new(Duration::from_millis(1), Duration::from_millis(1))
+    }
+
+    /// Returns the allowed processing time for a chunk with the given audio
+    /// duration.
+    pub fn allowed_processing_time(&self, chunk_duration: Duration) -> Duration {
+        self.processing_time
+            .mul_f64(chunk_duration.as_secs_f64() / self.chunk_duration.as_secs_f64())
+    }
+}
+
+/// Statistics collected by a [`LatencyMonitor`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LatencyStats {
+    /// Number of measured chunks.
+    pub chunks: u64,
+    /// Number of chunks whose processing time exceeded the budget.
+    pub violations: u64,
+    /// Processing time of the latest chunk.
+    pub latest_processing_time: Duration,
+    /// Allowed processing time of the latest chunk according to the budget.
+    pub latest_allowed_processing_time: Duration,
+    /// Maximum processing time of all chunks.
+    pub max_processing_time: Duration,
+}
+
+/// Callback that is invoked when the processing time exceeds the budget.
+type ViolationCallback = Box<dyn FnMut(&LatencyStats) + Send>;
+
+/// Measures the processing time of audio chunks against a [`LatencyBudget`]
+/// and counts the violations. Optionally, a callback is invoked on each
+/// violation, for example to reduce the analysis quality.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::BeatDetector;
+/// use beat_detector_io::latency::{LatencyBudget, LatencyMonitor};
+/// use std::time::Duration;
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let budget = LatencyBudget::new(Duration::from_millis(30), Duration::from_millis(20));
+/// let mut monitor = LatencyMonitor::new(budget, 44100.0)
+///     .with_violation_callback(|stats| eprintln!("too slow: {stats:?}"));
+///
+/// // TODO regularly call this with the latest audio data.
+/// let is_beat = monitor.measure(mono_samples.len(), || {
+///     detector.update_and_detect_beat(mono_samples.iter().copied())
+/// });
+/// ```
+pub struct LatencyMonitor {
+    budget: LatencyBudget,
+    sampling_frequency_hz: f32,
+    stats: LatencyStats,
+    on_violation: Option<ViolationCallback>,
+}
+
+impl LatencyMonitor {
+    /// Creates a new monitor for an audio source with the given sampling
+    /// frequency.
+    pub fn new(budget: LatencyBudget, sampling_frequency_hz: f32) -> Self {
+        assert!(sampling_frequency_hz.is_normal() && sampling_frequency_hz.is_sign_positive());
+        Self {
+            budget,
+            sampling_frequency_hz,
+            stats: LatencyStats::default(),
+            on_violation: None,
+        }
+    }
+
+    /// Sets a callback that is invoked each time the budget is exceeded.
+    pub fn with_violation_callback(
+        mut self,
+        on_violation: impl FnMut(&LatencyStats) + Send + 'static,
+    ) -> Self {
+        self.on_violation = Some(Box::new(on_violation));
+        self
+    }
+
+    /// Measures the processing time of the provided function that processes
+    /// a chunk with `sample_count` samples.
+    pub fn measure<R>(&mut self, sample_count: usize, process: impl FnOnce() -> R) -> R {
+        let now = Instant::now();
+        let res = process();
+        self.record(sample_count, now.elapsed());
+        res
+    }
+
+    /// Records the processing time of a chunk with `sample_count` samples.
+    /// Returns whether the processing time was within the budget.
+    pub fn record(&mut self, sample_count: usize, processing_time: Duration) -> bool {
+        let chunk_duration =
+            Duration::from_secs_f64(sample_count as f64 / self.sampling_frequency_hz as f64);
+        let allowed_processing_time = self.budget.allowed_processing_time(chunk_duration);
+        let within_budget = processing_time <= allowed_processing_time;
+
+        self.stats.chunks += 1;
+        self.stats.latest_processing_time = processing_time;
+        self.stats.latest_allowed_processing_time = allowed_processing_time;
+        self.stats.max_processing_time = self.stats.max_processing_time.max(processing_time);
+
+        if !within_budget {
+            self.stats.violations += 1;
+            log::debug!(
+                "Processing of {chunk_duration:?} of audio took {processing_time:?}, exceeding the latency budget"
+            );
+            if let Some(on_violation) = self.on_violation.as_mut() {
+                on_violation(&self.stats);
+            }
+        }
+
+        within_budget
+    }
+
+    /// Returns the collected statistics.
+    pub const fn stats(&self) -> &LatencyStats {
+        &self.stats
+    }
+
+    /// Returns the budget.
+    pub const fn budget(&self) -> &LatencyBudget {
+        &self.budget
+    }
+}
+
+impl Debug for LatencyMonitor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LatencyMonitor")
+            .field("budget", &self.budget)
+            .field("sampling_frequency_hz", &self.sampling_frequency_hz)
+            .field("stats", &self.stats)
+            .field(
+                "on_violation",
+                &self.on_violation.as_ref().map(|_| "<callback>"),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn budget_scales_with_chunk_duration() {
+        let budget = LatencyBudget::new(Duration::from_millis(30), Duration::from_millis(20));
+        assert_eq!(
+            budget.allowed_processing_time(Duration::from_millis(20)),
+            Duration::from_millis(30)
+        );
+        assert_eq!(
+            budget.allowed_processing_time(Duration::from_millis(10)),
+            Duration::from_millis(15)
+        );
+        assert_eq!(
+            LatencyBudget::realtime().allowed_processing_time(Duration::from_millis(20)),
+            Duration::from_millis(20)
+        );
+    }
+
+    #[test]
+    fn violations_are_counted_and_reported() {
+        let violations = Arc::new(AtomicU64::new(0));
+        let mut monitor = {
+            let violations = violations.clone();
+            // 1000 samples = 10ms of audio = 5ms of processing time allowed
+            LatencyMonitor::new(
+                LatencyBudget::new(Duration::from_millis(1), Duration::from_millis(2)),
+                100_000.0,
+            )
+            .with_violation_callback(move |stats| {
+                violations.store(stats.violations, Ordering::SeqCst)
+            })
+        };
+
+        assert!(monitor.record(1000, Duration::from_millis(5)));
+        assert!(!monitor.record(1000, Duration::from_millis(6)));
+        assert!(monitor.record(2000, Duration::from_millis(6)));
+        assert!(!monitor.record(2000, Duration::from_millis(11)));
+
+        assert_eq!(
+            monitor.stats(),
+            &LatencyStats {
+                chunks: 4,
+                violations: 2,
+                latest_processing_time: Duration::from_millis(11),
+                latest_allowed_processing_time: Duration::from_millis(10),
+                max_processing_time: Duration::from_millis(11),
+            }
+        );
+        assert_eq!(violations.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn measure_returns_result() {
+        let mut monitor = LatencyMonitor::new(LatencyBudget::realtime(), 44100.0);
+        assert_eq!(monitor.measure(44100, || 42), 42);
+        assert_eq!(monitor.stats().chunks, 1);
+        assert_eq!(monitor.stats().violations, 0);
+    }
+}
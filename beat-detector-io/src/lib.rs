@@ -0,0 +1,95 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Audio sources, live recording, and other helpers around
+//! [`beat-detector-core`] that need the standard library.
+//!
+//! Most users want the [beat-detector] facade crate, which re-exports this
+//! crate and the core.
+//!
+//! ## Cargo Features
+//!
+//! - `recording`: Live recording from an audio input device via `cpal`, see
+//!   [`recording::start_detector_thread`] and [`audio_io::device`]. Requires
+//!   the native audio libraries of the platform, such as ALSA on Linux.
+//! - `audio-file`: Reading WAV files, see
+//!   [`audio_io::file`](mod@audio_io::file).
+//! - `audio-net`: Reading raw PCM streams, e.g., from the network, see
+//!   [`audio_io::net`].
+//! - `room-sync`: Sharing the tempo and the beat phase between instances on a
+//!   LAN, see [`room_sync`].
+//! - `visualizer`: Handing the audio features to the frame loop of a
+//!   visualizer, independent of the framework, see [`visualizer`].
+//!
+//! All audio inputs implement [`audio_io::SampleSource`].
+//!
+//! [`beat-detector-core`]: beat_detector_core
+//! [beat-detector]: https://docs.rs/beat-detector
+
+#![deny(
+    clippy::all,
+    clippy::cargo,
+    clippy::nursery,
+    // clippy::restriction,
+    // clippy::pedantic
+)]
+// now allow a few rules which are denied by the above statement
+// --> they are ridiculous and not necessary
+#![allow(
+    clippy::suboptimal_flops,
+    clippy::redundant_pub_crate,
+    clippy::fallible_impl_from,
+    clippy::multiple_crate_versions
+)]
+#![deny(missing_debug_implementations)]
+#![deny(rustdoc::all)]
+
+pub mod adaptive_quality;
+pub mod audio_io;
+pub mod drift;
+pub mod driver;
+#[cfg(feature = "recording")]
+pub mod duplex;
+mod error;
+pub mod events;
+#[cfg(feature = "audio-file")]
+pub mod fixture;
+pub mod latency;
+pub mod metronome;
+pub mod offline;
+#[cfg(feature = "recording")]
+pub mod recording;
+pub mod report;
+#[cfg(feature = "room-sync")]
+pub mod room_sync;
+pub mod session;
+pub mod stop;
+pub mod sweep;
+#[cfg(test)]
+mod test_utils;
+pub mod thread_priority;
+pub mod video;
+#[cfg(feature = "visualizer")]
+pub mod visualizer;
+
+pub use error::Error;
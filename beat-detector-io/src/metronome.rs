@@ -0,0 +1,343 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`Metronome`], which plays a click on each detected beat, so
+//! that the timing of the detection can be judged by ear.
+
+use beat_detector_core::BeatInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use std::sync::Arc;
+use std::vec::Vec;
+
+/// Frequency of the click.
+const CLICK_FREQUENCY_HZ: f32 = 1000.0;
+/// Duration of the click.
+const CLICK_DURATION: Duration = Duration::from_millis(15);
+/// Amplitude of the click in range `0.0..=1.0`.
+const CLICK_AMPLITUDE: f32 = 0.5;
+/// Value of [`Shared::next_click`] if no click is pending.
+const NO_CLICK: u64 = u64::MAX;
+
+/// State that is shared between the [`Metronome`] and the [`ClickRenderer`].
+#[derive(Debug)]
+struct Shared {
+    sampling_rate: f32,
+    /// Amount of frames that the renderer has rendered so far.
+    rendered_frames: AtomicU64,
+    /// Frame at which the next click begins.
+    next_click: AtomicU64,
+}
+
+/// Triggers clicks, e.g., from the callback that receives the beats. The
+/// clicks are rendered by the corresponding [`ClickRenderer`], typically in
+/// the callback of an audio output stream.
+///
+/// Without latency compensation, each click plays as soon as possible, i.e.,
+/// the click is late by the latency of the detection, which varies from beat
+/// to beat. With [`Self::set_latency`], each click plays at a constant
+/// latency after the beat instead, which makes the timing of the beats
+/// audible.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_io::metronome::Metronome;
+///
+/// let (metronome, mut renderer) = Metronome::new(44100.0);
+/// metronome.click();
+///
+/// // Audio output callback with interleaved stereo frames.
+/// let mut frames = [0.0; 512];
+/// renderer.render(&mut frames, 2);
+/// assert_ne!(frames[0], 0.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Metronome {
+    shared: Arc<Shared>,
+    latency: Duration,
+}
+
+impl Metronome {
+    /// Creates a new metronome and the renderer for an audio output with the
+    /// given sampling rate.
+    pub fn new(sampling_rate: f32) -> (Self, ClickRenderer) {
+        let shared = Arc::new(Shared {
+            sampling_rate,
+            rendered_frames: AtomicU64::new(0),
+            next_click: AtomicU64::new(NO_CLICK),
+        });
+        let click_len = (CLICK_DURATION.as_secs_f32() * sampling_rate) as usize;
+        let click = (0..click_len)
+            .map(|i| {
+                let t = i as f32 / sampling_rate;
+                let decay = 1.0 - i as f32 / click_len as f32;
+                (2.0 * core::f32::consts::PI * CLICK_FREQUENCY_HZ * t).cos()
+                    * decay
+                    * CLICK_AMPLITUDE
+            })
+            .collect::<Vec<_>>();
+        let renderer = ClickRenderer {
+            shared: shared.clone(),
+            click_pos: click.len(),
+            click,
+        };
+        let metronome = Self {
+            shared,
+            latency: Duration::ZERO,
+        };
+        (metronome, renderer)
+    }
+
+    /// Returns the latency of the clicks after the beats.
+    pub const fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Sets the latency of the clicks after the beats for
+    /// [`Self::click_for_beat`]. It should be a bit higher than the highest
+    /// latency of the detection, which mostly depends on the
+    /// [minimum duration] of the envelopes. The default is zero, i.e., each
+    /// click plays as soon as possible.
+    ///
+    /// [minimum duration]: beat_detector_core::EnvelopeConfig::min_duration
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = latency;
+    }
+
+    /// Plays a click as soon as possible.
+    pub fn click(&self) {
+        self.click_after(Duration::ZERO);
+    }
+
+    /// Plays a click after the given delay. A pending click that didn't
+    /// begin yet is replaced.
+    pub fn click_after(&self, delay: Duration) {
+        let delay = (delay.as_secs_f32() * self.shared.sampling_rate) as u64;
+        let now = self.shared.rendered_frames.load(Ordering::Relaxed);
+        self.shared
+            .next_click
+            .store(now.saturating_add(delay), Ordering::Relaxed);
+    }
+
+    /// Plays a click for a beat that was just reported by the detector,
+    /// delayed so that the click plays at the [latency](Self::set_latency)
+    /// after the beat. The delay of the lowpass filter of the detector, which
+    /// is a few ms, adds to the latency.
+    pub fn click_for_beat(&self, beat: &BeatInfo) {
+        self.click_after(self.latency.saturating_sub(beat.max.duration_behind));
+    }
+}
+
+/// Renders the clicks of a [`Metronome`] into an audio output buffer.
+#[derive(Debug)]
+pub struct ClickRenderer {
+    shared: Arc<Shared>,
+    click: Vec<f32>,
+    /// Position in `click`. `click.len()` if no click is playing.
+    click_pos: usize,
+}
+
+impl ClickRenderer {
+    /// Renders the next frames of interleaved audio with the given amount of
+    /// channels. All channels get the same signal.
+    pub fn render(&mut self, output: &mut [f32], channels: usize) {
+        let channels = channels.max(1);
+        let mut frame_index = self.shared.rendered_frames.load(Ordering::Relaxed);
+        for frame in output.chunks_mut(channels) {
+            if frame_index >= self.shared.next_click.load(Ordering::Relaxed) {
+                self.shared.next_click.store(NO_CLICK, Ordering::Relaxed);
+                self.click_pos = 0;
+            }
+            let value = self.click.get(self.click_pos).copied().unwrap_or(0.0);
+            self.click_pos = (self.click_pos + 1).min(self.click.len());
+            frame.fill(value);
+            frame_index += 1;
+        }
+        self.shared
+            .rendered_frames
+            .store(frame_index, Ordering::Relaxed);
+    }
+}
+
+/// Errors of [`start_metronome`].
+#[cfg(feature = "recording")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StartMetronomeError {
+    /// There was no audio device provided and no default device can be found.
+    NoDefaultAudioDevice,
+    /// There was a problem detecting the output stream config.
+    OutputConfigError(cpal::DefaultStreamConfigError),
+    /// Failed to build an output stream.
+    FailedBuildingOutputStream(cpal::BuildStreamError),
+    /// The output stream can't be started.
+    OutputError(cpal::PlayStreamError),
+}
+
+#[cfg(feature = "recording")]
+impl core::fmt::Display for StartMetronomeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!("{:?}", self))
+    }
+}
+
+#[cfg(feature = "recording")]
+impl std::error::Error for StartMetronomeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::OutputConfigError(err) => Some(err),
+            Self::FailedBuildingOutputStream(err) => Some(err),
+            Self::OutputError(err) => Some(err),
+            Self::NoDefaultAudioDevice => None,
+        }
+    }
+}
+
+/// Starts an output stream on the preferred output device or the default
+/// output device of the platform that plays the clicks of the returned
+/// [`Metronome`]. The stream plays as long as it lives.
+///
+/// Use [`DuplexDevices::start_metronome`] to play the clicks on the device
+/// that also records the audio.
+///
+/// [`DuplexDevices::start_metronome`]: crate::duplex::DuplexDevices::start_metronome
+#[cfg(feature = "recording")]
+pub fn start_metronome(
+    preferred_output_dev: Option<cpal::Device>,
+) -> Result<(Metronome, cpal::Stream), StartMetronomeError> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    let output_dev = preferred_output_dev.map(Ok).unwrap_or_else(|| {
+        cpal::default_host()
+            .default_output_device()
+            .ok_or(StartMetronomeError::NoDefaultAudioDevice)
+    })?;
+    let output_config = output_dev
+        .default_output_config()
+        .map_err(StartMetronomeError::OutputConfigError)?
+        .config();
+    start_metronome_with_config(&output_dev, &output_config)
+}
+
+/// Like [`start_metronome`], but with an explicit output configuration.
+#[cfg(feature = "recording")]
+pub(crate) fn start_metronome_with_config(
+    output_dev: &cpal::Device,
+    output_config: &cpal::StreamConfig,
+) -> Result<(Metronome, cpal::Stream), StartMetronomeError> {
+    use cpal::traits::{DeviceTrait, StreamTrait};
+
+    log::debug!("Metronome output configuration: {:#?}", output_config);
+    let channels = output_config.channels as usize;
+    let (metronome, mut renderer) = Metronome::new(output_config.sample_rate.0 as f32);
+    let stream = output_dev
+        .build_output_stream(
+            output_config,
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                renderer.render(data, channels);
+            },
+            |e| log::error!("Output error: {e:#?}"),
+            // See `start_detector_thread`.
+            Some(Duration::from_secs(1)),
+        )
+        .map_err(StartMetronomeError::FailedBuildingOutputStream)?;
+    stream.play().map_err(StartMetronomeError::OutputError)?;
+    Ok((metronome, stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use beat_detector_core::BeatDetector;
+
+    /// Returns the indices of the frames at which clicks begin.
+    fn click_onsets(frames: &[f32]) -> Vec<usize> {
+        (0..frames.len())
+            .filter(|&i| frames[i] != 0.0 && (i == 0 || frames[i - 1] == 0.0))
+            .collect()
+    }
+
+    #[test]
+    fn clicks_are_scheduled() {
+        let (mut metronome, mut renderer) = Metronome::new(1000.0);
+        let mut output = [1.0; 100];
+        renderer.render(&mut output, 2);
+        assert!(output.iter().all(|&value| value == 0.0));
+
+        // The click begins with the next rendered frame and is 15 ms long.
+        metronome.click();
+        renderer.render(&mut output, 2);
+        assert_ne!(output[0], 0.0);
+        assert_eq!(output[0], output[1]);
+        assert!(output[..30].iter().all(|&value| value != 0.0));
+        assert!(output[30..].iter().all(|&value| value == 0.0));
+
+        // 10 ms after the beat, which was reported 4 ms after it.
+        metronome.set_latency(Duration::from_millis(10));
+        let mut beat = BeatInfo::default();
+        beat.max.duration_behind = Duration::from_millis(4);
+        metronome.click_for_beat(&beat);
+        let mut output = [0.0; 40];
+        renderer.render(&mut output, 1);
+        assert_eq!(click_onsets(&output), &[6]);
+    }
+
+    #[test]
+    fn clicks_follow_the_detected_beats() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sampling_rate = header.sample_rate as f32;
+        let mut detector = BeatDetector::new(sampling_rate, true);
+        let (mut metronome, mut renderer) = Metronome::new(sampling_rate);
+        metronome.set_latency(Duration::from_millis(200));
+
+        let mut output = Vec::new();
+        let mut beats = Vec::new();
+        for chunk in samples.chunks(441) {
+            // The output plays while the input records, so the renderer is
+            // at the same time as the detector.
+            let mut frames = [0.0; 441];
+            renderer.render(&mut frames, 1);
+            output.extend_from_slice(&frames);
+            if let Some(beat) = detector.update_and_detect_beat(chunk.iter().copied()) {
+                metronome.click_for_beat(&beat);
+                beats.push(beat.max.source.samples);
+            }
+        }
+
+        // Each click is 200 ms (8820 samples) plus the delay of the lowpass
+        // filter after its beat, except for the last one, which is after the
+        // end of the audio.
+        let onsets = click_onsets(&output);
+        assert_eq!(onsets.len(), beats.len() - 1);
+        let latencies = onsets
+            .iter()
+            .zip(beats)
+            .map(|(onset, beat)| onset - beat as usize)
+            .collect::<Vec<_>>();
+        assert!(latencies
+            .iter()
+            .all(|latency| latency.abs_diff(latencies[0]) <= 1));
+        assert!(latencies[0].abs_diff(8820) < 150);
+    }
+}
@@ -0,0 +1,445 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for offline analysis of audio data that is completely available in
+//! memory, such as a decoded WAV file.
+
+use crate::audio_io::memory::MemorySource;
+use crate::audio_io::{SampleSource, SourceError};
+use crate::driver::{run_detector, run_detector_with_updates};
+use crate::stop::{StopSource, StopToken};
+use beat_detector_core::{BeatDetector, BeatInfo};
+use core::fmt::{Display, Formatter};
+use core::time::Duration;
+use std::vec::Vec;
+
+/// Duration of audio between two progress reports of
+/// [`detect_beats_with_progress`] and
+/// [`detect_beats_from_source_with_progress`].
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Intermediate state of an analysis that is passed to the progress callback
+/// of [`detect_beats_with_progress`] and
+/// [`detect_beats_from_source_with_progress`].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct Progress<'a> {
+    /// Number of samples that were analyzed so far.
+    pub processed_samples: u64,
+    /// Total number of samples, if known.
+    pub total_samples: Option<u64>,
+    /// All beats that were detected so far.
+    pub beats: &'a [BeatInfo],
+    /// Beats that were detected since the previous report. They are the tail
+    /// of [`Self::beats`], so streaming them doesn't report a beat twice.
+    pub new_beats: &'a [BeatInfo],
+}
+
+impl Progress<'_> {
+    /// Returns the progress in percent, if the total number of samples is
+    /// known. The final report always has 100 %.
+    pub fn percent(&self) -> Option<f32> {
+        self.total_samples.map(|total| match total {
+            0 => 100.0,
+            total => (self.processed_samples as f32 / total as f32 * 100.0).min(100.0),
+        })
+    }
+}
+
+/// Errors of [`detect_beats_from_source_with_progress`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AnalysisError {
+    /// The analysis was cancelled via its [`StopToken`] before the source was
+    /// exhausted. The beats until then were passed to the progress callback.
+    Cancelled,
+    /// The sample source failed.
+    Source(SourceError),
+}
+
+impl Display for AnalysisError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Cancelled => f.write_str("the analysis was cancelled"),
+            Self::Source(err) => write!(f, "sample source failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Cancelled => None,
+            Self::Source(err) => Some(err),
+        }
+    }
+}
+
+impl From<SourceError> for AnalysisError {
+    fn from(err: SourceError) -> Self {
+        Self::Source(err)
+    }
+}
+
+/// Detects all beats in the given mono samples.
+///
+/// This doesn't need the `recording` feature and is therefore suited for
+/// server-side batch analysis without any audio backend. See
+/// [`BeatDetector::new`] for `needs_lowpass_filter`.
+pub fn detect_beats(
+    mono_samples: &[i16],
+    sampling_frequency_hz: f32,
+    needs_lowpass_filter: bool,
+) -> Vec<BeatInfo> {
+    let source = MemorySource::new(mono_samples, sampling_frequency_hz);
+    detect_beats_from_source(source, needs_lowpass_filter)
+        .expect("reading from memory should never fail")
+}
+
+/// Like [`detect_beats`], but reads the samples from any [`SampleSource`]
+/// until it is exhausted.
+pub fn detect_beats_from_source(
+    source: impl SampleSource,
+    needs_lowpass_filter: bool,
+) -> Result<Vec<BeatInfo>, SourceError> {
+    detect_beats_from_source_with_progress(
+        source,
+        None,
+        needs_lowpass_filter,
+        &StopSource::new().token(),
+        |_| {},
+    )
+    .map_err(|err| match err {
+        AnalysisError::Source(err) => err,
+        AnalysisError::Cancelled => unreachable!("the analysis can't be cancelled"),
+    })
+}
+
+/// Like [`detect_beats`], but reports the progress to `on_progress`.
+///
+/// The callback gets the beats detected so far after every
+/// [`PROGRESS_INTERVAL`] of audio and once at the end. This way, CLIs and
+/// GUIs can show a progress bar and stream the results while long
+/// recordings are analyzed.
+///
+/// The analysis is aborted once `stop` is stopped, e.g., when the user picks
+/// a different file. `stop` is checked once per chunk of ~20 ms of audio.
+/// Then, `None` is returned and there is no final report. The beats until
+/// then were already passed to `on_progress`.
+pub fn detect_beats_with_progress(
+    mono_samples: &[i16],
+    sampling_frequency_hz: f32,
+    needs_lowpass_filter: bool,
+    stop: &StopToken,
+    on_progress: impl FnMut(Progress<'_>),
+) -> Option<Vec<BeatInfo>> {
+    let source = MemorySource::new(mono_samples, sampling_frequency_hz);
+    match detect_beats_from_source_with_progress(
+        source,
+        Some(mono_samples.len() as u64),
+        needs_lowpass_filter,
+        stop,
+        on_progress,
+    ) {
+        Ok(beats) => Some(beats),
+        Err(AnalysisError::Cancelled) => None,
+        Err(AnalysisError::Source(_)) => unreachable!("reading from memory should never fail"),
+    }
+}
+
+/// Like [`detect_beats_from_source`], but reports the progress like
+/// [`detect_beats_with_progress`].
+///
+/// `total_samples` is the length of the source, if known, such as
+/// [`WavSource::total_samples`]. Without it, only the final report has a
+/// percentage. The final report is skipped if the source fails or `stop` is
+/// stopped. Then, [`AnalysisError::Source`] or [`AnalysisError::Cancelled`]
+/// is returned.
+///
+/// [`WavSource::total_samples`]: crate::audio_io::file::WavSource::total_samples
+pub fn detect_beats_from_source_with_progress(
+    source: impl SampleSource,
+    total_samples: Option<u64>,
+    needs_lowpass_filter: bool,
+    stop: &StopToken,
+    mut on_progress: impl FnMut(Progress<'_>),
+) -> Result<Vec<BeatInfo>, AnalysisError> {
+    let sample_rate = source.sample_rate();
+    let mut detector = BeatDetector::new(sample_rate, needs_lowpass_filter);
+    let report_interval = ((sample_rate * PROGRESS_INTERVAL.as_secs_f32()) as u64).max(1);
+    let mut beats = Vec::new();
+    let mut processed_samples = 0;
+    let mut reported_samples = 0;
+    let mut reported_beats = 0;
+    let is_exhausted =
+        run_detector_with_updates(source, &mut detector, stop, |_detector, chunk, beat| {
+            beats.extend(beat);
+            processed_samples += chunk.len() as u64;
+            if processed_samples - reported_samples >= report_interval {
+                on_progress(Progress {
+                    processed_samples,
+                    total_samples,
+                    beats: &beats,
+                    new_beats: &beats[reported_beats..],
+                });
+                reported_samples = processed_samples;
+                reported_beats = beats.len();
+            }
+        })?;
+    if !is_exhausted {
+        return Err(AnalysisError::Cancelled);
+    }
+    on_progress(Progress {
+        processed_samples,
+        // At the end, the total is known. The header of a truncated file may
+        // claim more samples.
+        total_samples: Some(processed_samples),
+        beats: &beats,
+        new_beats: &beats[reported_beats..],
+    });
+    Ok(beats)
+}
+
+/// Audio after the filter stages of the detector, kept for repeated offline
+/// analyses with different settings.
+///
+/// Interactive tuning of parameters analyzes the same audio over and over.
+/// This keeps the decoded and lowpassed signal, so that each further run
+/// with different thresholds only repeats the cheap analysis stage.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_io::offline::PreprocessedAudio;
+/// use beat_detector_core::EnvelopeConfig;
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let audio = PreprocessedAudio::new(&mono_samples, 44100.0, true);
+///
+/// let beats = audio.detect_beats(|_| {});
+/// let strict_beats = audio.detect_beats(|detector| {
+///     detector.set_envelope_config(EnvelopeConfig::STRICT);
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct PreprocessedAudio {
+    samples: Vec<i16>,
+    sampling_frequency_hz: f32,
+    needs_lowpass_filter: bool,
+}
+
+impl PreprocessedAudio {
+    /// Runs the given mono samples through the filter stages of a
+    /// [`BeatDetector`]. See [`BeatDetector::new`] for
+    /// `needs_lowpass_filter`.
+    pub fn new(
+        mono_samples: &[i16],
+        sampling_frequency_hz: f32,
+        needs_lowpass_filter: bool,
+    ) -> Self {
+        let source = MemorySource::new(mono_samples, sampling_frequency_hz);
+        Self::from_source(source, needs_lowpass_filter)
+            .expect("reading from memory should never fail")
+    }
+
+    /// Like [`Self::new`], but reads the samples from any [`SampleSource`]
+    /// until it is exhausted. Only this decodes the audio.
+    pub fn from_source(
+        source: impl SampleSource,
+        needs_lowpass_filter: bool,
+    ) -> Result<Self, SourceError> {
+        let sampling_frequency_hz = source.sample_rate();
+        let mut detector = BeatDetector::new(sampling_frequency_hz, needs_lowpass_filter);
+        let mut samples = Vec::new();
+        run_detector_with_updates(
+            source,
+            &mut detector,
+            &StopSource::new().token(),
+            |detector, _chunk, _beat| {
+                // Without downsampling and gain normalization, these are
+                // exactly the filtered samples.
+                samples.extend(
+                    detector
+                        .processed_samples_since_last_call()
+                        .map(|info| info.value),
+                );
+            },
+        )?;
+        Ok(Self {
+            samples,
+            sampling_frequency_hz,
+            needs_lowpass_filter,
+        })
+    }
+
+    /// Returns the preprocessed samples.
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Returns the sampling frequency of the samples in Hz.
+    pub const fn sampling_frequency(&self) -> f32 {
+        self.sampling_frequency_hz
+    }
+
+    /// Detects all beats in the preprocessed audio. The result is the same as
+    /// the one of [`detect_beats`] for the original audio.
+    ///
+    /// `configure` adjusts the settings of the analysis before it starts,
+    /// such as the [`EnvelopeConfig`]. The filter stages are skipped, so the
+    /// settings of the filters, such as the [`FrequencyWeighting`], must not
+    /// be changed.
+    ///
+    /// [`EnvelopeConfig`]: beat_detector_core::EnvelopeConfig
+    /// [`FrequencyWeighting`]: beat_detector_core::FrequencyWeighting
+    pub fn detect_beats(&self, configure: impl FnOnce(&mut BeatDetector)) -> Vec<BeatInfo> {
+        let mut detector = BeatDetector::new(self.sampling_frequency_hz, self.needs_lowpass_filter);
+        detector.set_preprocessed_input(true);
+        configure(&mut detector);
+        let source = MemorySource::new(&self.samples, self.sampling_frequency_hz);
+        let mut beats = Vec::new();
+        run_detector(source, &mut detector, |beat| beats.push(beat))
+            .expect("reading from memory should never fail");
+        beats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use beat_detector_core::{EnvelopeConfig, EnvelopeInfo};
+
+    #[test]
+    fn detect_beats_in_holiday_long() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let beats = detect_beats(&samples, header.sample_rate as f32, true);
+        assert_eq!(
+            beats
+                .iter()
+                .map(|info| info.max.total_index)
+                .collect::<Vec<_>>(),
+            &[31331, 47161, 65921, 84221, 102111, 120251, 138561]
+        );
+    }
+
+    #[test]
+    fn reports_progress_and_partial_beats() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sample_rate = header.sample_rate as f32;
+        let mut reports = Vec::new();
+        let mut streamed_beats = Vec::new();
+        let stop = StopSource::new();
+        let beats =
+            detect_beats_with_progress(&samples, sample_rate, true, &stop.token(), |progress| {
+                reports.push((progress.processed_samples, progress.percent().unwrap()));
+                assert!(progress.beats.ends_with(progress.new_beats));
+                streamed_beats.extend_from_slice(progress.new_beats);
+            })
+            .unwrap();
+
+        assert_eq!(beats, detect_beats(&samples, sample_rate, true));
+        assert_eq!(streamed_beats, beats);
+        // One report per second of audio and one at the end.
+        assert_eq!(
+            reports.len(),
+            samples.len() / header.sample_rate as usize + 1
+        );
+        assert!(reports.windows(2).all(|w| w[0].1 <= w[1].1));
+        assert_eq!(reports.last(), Some(&(samples.len() as u64, 100.0)));
+    }
+
+    #[test]
+    fn analysis_can_be_cancelled() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sample_rate = header.sample_rate as f32;
+        let stop = StopSource::new();
+        let mut reports = 0;
+        let mut streamed_beats = Vec::new();
+        let beats =
+            detect_beats_with_progress(&samples, sample_rate, true, &stop.token(), |progress| {
+                reports += 1;
+                streamed_beats.extend(progress.new_beats.iter().map(|info| info.max.total_index));
+                stop.stop();
+            });
+        assert_eq!(beats, None);
+        assert_eq!(reports, 1);
+        // Only the first second was analyzed.
+        assert_eq!(streamed_beats, &[31331]);
+
+        let source = MemorySource::new(&samples, sample_rate);
+        let res = detect_beats_from_source_with_progress(source, None, true, &stop.token(), |_| {
+            panic!("a cancelled analysis doesn't report progress")
+        });
+        assert!(matches!(res, Err(AnalysisError::Cancelled)));
+    }
+
+    #[test]
+    fn repeat_analysis_with_preprocessed_audio() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sample_rate = header.sample_rate as f32;
+        let audio = PreprocessedAudio::new(&samples, sample_rate, true);
+        assert_eq!(audio.samples().len(), samples.len());
+        assert_same_beats(
+            &audio.detect_beats(|_| {}),
+            &detect_beats(&samples, sample_rate, true),
+        );
+
+        let configure = |detector: &mut BeatDetector| {
+            detector.set_envelope_config(EnvelopeConfig::STRICT);
+            detector.set_search_overlap(Duration::ZERO);
+        };
+        let mut detector = BeatDetector::new(sample_rate, true);
+        configure(&mut detector);
+        let mut expected = Vec::new();
+        run_detector(
+            MemorySource::new(&samples, sample_rate),
+            &mut detector,
+            |beat| expected.push(beat),
+        )
+        .unwrap();
+        assert_same_beats(&audio.detect_beats(configure), &expected);
+    }
+
+    /// `PartialEq` of [`EnvelopeInfo`] only compares the total indices, so
+    /// this also compares the timestamps.
+    fn assert_same_beats(actual: &[EnvelopeInfo], expected: &[EnvelopeInfo]) {
+        assert_eq!(actual, expected);
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert_eq!(actual.timestamp(), expected.timestamp());
+            assert_eq!(actual.max.source, expected.max.source);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "audio-file")]
+    fn detect_beats_from_wav_source() {
+        use crate::audio_io::file::WavSource;
+
+        let (samples, header) = test_utils::samples::holiday_long();
+        let source =
+            WavSource::open(crate::test_utils::res("holiday_lowpassed--long.wav")).unwrap();
+        assert_eq!(
+            detect_beats_from_source(source, true).unwrap(),
+            detect_beats(&samples, header.sample_rate as f32, true)
+        );
+    }
+}
@@ -0,0 +1,483 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Module for audio recording from an audio input device.
+
+use crate::latency::{LatencyBudget, LatencyMonitor};
+use crate::stop::StopToken;
+use crate::thread_priority::set_current_thread_realtime_priority;
+use beat_detector_core::{
+    BeatDetector, BeatInfo, ChunkLevel, FrequencyWeighting, Heartbeat, HeartbeatGenerator,
+};
+use core::fmt::{Display, Formatter};
+use core::ops::RangeInclusive;
+use core::sync::atomic::{AtomicBool, Ordering};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, StreamConfig};
+use std::boxed::Box;
+use std::error::Error;
+use std::string::ToString;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug)]
+// #[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum StartDetectorThreadError {
+    /// There was no audio device provided and no default device can be found.
+    NoDefaultAudioDevice,
+    /// There was a problem detecting the input stream config.
+    InputConfigError(cpal::DefaultStreamConfigError),
+    /// Failed to build an input stream.
+    FailedBuildingInputStream(cpal::BuildStreamError),
+    /// There was a problem
+    InputError(cpal::PlayStreamError),
+    /// The detector can't process audio with the config of the input stream.
+    UnsupportedStreamConfig(StreamConfigError),
+}
+
+impl Display for StartDetectorThreadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!("{:?}", self))
+    }
+}
+
+impl std::error::Error for StartDetectorThreadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InputConfigError(err) => Some(err),
+            Self::FailedBuildingInputStream(err) => Some(err),
+            Self::InputError(err) => Some(err),
+            Self::UnsupportedStreamConfig(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Sampling rates of input streams that [`DetectorConfig`] accepts. Below,
+/// the lowpass filter and the downsampling of the detector don't work
+/// reliably. Above, the audio history covers too little time.
+const SUPPORTED_SAMPLE_RATES_HZ: RangeInclusive<u32> = 8000..=192000;
+
+/// Error of [`DetectorConfig::try_from`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StreamConfigError {
+    /// The stream has no channels.
+    NoChannels,
+    /// The sampling rate (in Hz) of the stream is not supported.
+    UnsupportedSampleRate(u32),
+}
+
+impl Display for StreamConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NoChannels => write!(f, "the stream has no channels"),
+            Self::UnsupportedSampleRate(rate) => write!(
+                f,
+                "sampling rate {rate} Hz is not in the range {}..={} Hz",
+                SUPPORTED_SAMPLE_RATES_HZ.start(),
+                SUPPORTED_SAMPLE_RATES_HZ.end()
+            ),
+        }
+    }
+}
+
+impl Error for StreamConfigError {}
+
+/// Parameters of a [`BeatDetector`] for the audio of a cpal input stream.
+///
+/// Create it from the [`StreamConfig`] that the stream is built with and use
+/// [`Self::build`] to create a matching detector. The detector always applies
+/// its lowpass filter, as audio input devices deliver the full spectrum.
+///
+/// The detector processes mono samples. If [`Self::channels`] is greater than
+/// one, the interleaved samples must be combined first, for example with
+/// [`stereo_to_mono`].
+///
+/// [`stereo_to_mono`]: beat_detector_core::util::stereo_to_mono
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct DetectorConfig {
+    /// Sampling rate of the stream in Hz.
+    pub sampling_frequency_hz: f32,
+    /// Number of interleaved channels of the stream.
+    pub channels: u16,
+    /// Weighting (and thereby the cutoff of the lowpass filter) of the
+    /// detector.
+    pub frequency_weighting: FrequencyWeighting,
+}
+
+impl DetectorConfig {
+    /// Creates a new [`BeatDetector`] with this config.
+    pub fn build(&self) -> BeatDetector {
+        let mut detector = BeatDetector::new(self.sampling_frequency_hz, true);
+        if self.frequency_weighting != FrequencyWeighting::default() {
+            detector.set_frequency_weighting(self.frequency_weighting);
+        }
+        detector
+    }
+}
+
+impl TryFrom<&StreamConfig> for DetectorConfig {
+    type Error = StreamConfigError;
+
+    fn try_from(config: &StreamConfig) -> Result<Self, Self::Error> {
+        if config.channels == 0 {
+            return Err(StreamConfigError::NoChannels);
+        }
+        let sample_rate = config.sample_rate.0;
+        if !SUPPORTED_SAMPLE_RATES_HZ.contains(&sample_rate) {
+            return Err(StreamConfigError::UnsupportedSampleRate(sample_rate));
+        }
+        Ok(Self {
+            sampling_frequency_hz: sample_rate as f32,
+            channels: config.channels,
+            frequency_weighting: FrequencyWeighting::default(),
+        })
+    }
+}
+
+/// A beat detected in live audio together with its timestamp according to
+/// the clock of the audio device.
+///
+/// The timestamp relative to the start of the stream is
+/// [`BeatInfo::timestamp`].
+#[derive(Debug, Copy, Clone)]
+#[non_exhaustive]
+pub struct LiveBeatInfo {
+    /// The detected beat.
+    pub beat: BeatInfo,
+    /// The point in time when the audio device captured the peak of the
+    /// beat, according to the clock of the audio device. This can be used to
+    /// correlate beats with other sensors that use the same clock.
+    ///
+    /// The timestamp is derived from the capture timestamp of the audio chunk
+    /// that was delivered last and the sampling rate. It is `None` if that
+    /// underflows the clock.
+    pub capture_time: Option<cpal::StreamInstant>,
+}
+
+/// Starts a stream (a thread) that combines the audio input with the provided
+/// callback. The stream lives as long as the provided callback
+///
+/// To handle beats, tempo changes, lost signals, and the other runtime
+/// conditions in one place, run an [`EventBus`] with a [`DeviceSource`]
+/// instead.
+///
+/// [`EventBus`]: crate::events::EventBus
+/// [`DeviceSource`]: crate::audio_io::device::DeviceSource
+pub fn start_detector_thread(
+    on_beat_cb: impl Fn(BeatInfo) + Send + 'static,
+    preferred_input_dev: Option<cpal::Device>,
+) -> Result<cpal::Stream, StartDetectorThreadError> {
+    let (input_dev, input_config) = open_input_device(preferred_input_dev)?;
+    start_detector_thread_impl(
+        move |info| on_beat_cb(info.beat),
+        None,
+        None,
+        false,
+        input_dev,
+        input_config,
+    )
+}
+
+/// Like [`start_detector_thread`], but raises the priority of the audio
+/// thread to realtime priority with [`set_current_thread_realtime_priority`]
+/// once the first audio arrives.
+///
+/// This prevents missed beats on busy single-board computers. Raising the
+/// priority is best-effort: if the operating system refuses, the thread keeps
+/// its priority and a debug message is logged. The other functions of this
+/// module leave the scheduling of the audio thread unchanged.
+pub fn start_detector_thread_with_realtime_priority(
+    on_beat_cb: impl Fn(BeatInfo) + Send + 'static,
+    preferred_input_dev: Option<cpal::Device>,
+) -> Result<cpal::Stream, StartDetectorThreadError> {
+    let (input_dev, input_config) = open_input_device(preferred_input_dev)?;
+    start_detector_thread_impl(
+        move |info| on_beat_cb(info.beat),
+        None,
+        None,
+        true,
+        input_dev,
+        input_config,
+    )
+}
+
+/// Like [`start_detector_thread`], but additionally passes the timestamp of
+/// each beat according to the clock of the audio device to the callback.
+pub fn start_detector_thread_with_timestamps(
+    on_beat_cb: impl Fn(LiveBeatInfo) + Send + 'static,
+    preferred_input_dev: Option<cpal::Device>,
+) -> Result<cpal::Stream, StartDetectorThreadError> {
+    let (input_dev, input_config) = open_input_device(preferred_input_dev)?;
+    start_detector_thread_impl(on_beat_cb, None, None, false, input_dev, input_config)
+}
+
+/// Like [`start_detector_thread`], but additionally invokes `on_level_cb`
+/// with the [`ChunkLevel`] of each chunk of the audio input.
+///
+/// This drives a VU meter next to the beats without opening a second stream.
+///
+/// The level is measured on the raw audio input, before any filter of the
+/// detector. The callbacks run in the audio thread, so they should return
+/// quickly.
+pub fn start_detector_thread_with_levels(
+    on_beat_cb: impl Fn(BeatInfo) + Send + 'static,
+    on_level_cb: impl Fn(ChunkLevel) + Send + 'static,
+    preferred_input_dev: Option<cpal::Device>,
+) -> Result<cpal::Stream, StartDetectorThreadError> {
+    let (input_dev, input_config) = open_input_device(preferred_input_dev)?;
+    start_detector_thread_impl(
+        move |info| on_beat_cb(info.beat),
+        Some(Box::new(on_level_cb)),
+        None,
+        false,
+        input_dev,
+        input_config,
+    )
+}
+
+/// Like [`start_detector_thread`], but blocks until `stop` is stopped. Then,
+/// the recording is stopped.
+pub fn record_until(
+    on_beat_cb: impl Fn(BeatInfo) + Send + 'static,
+    preferred_input_dev: Option<cpal::Device>,
+    stop: &StopToken,
+) -> Result<(), StartDetectorThreadError> {
+    let stream = start_detector_thread(on_beat_cb, preferred_input_dev)?;
+    stop.wait();
+    // Dropping the stream stops the recording.
+    drop(stream);
+    Ok(())
+}
+
+/// Like [`start_detector_thread`], but additionally invokes `on_heartbeat_cb`
+/// every `heartbeat_interval` with a [`Heartbeat`].
+///
+/// This is useful for unattended installations to notice when the audio
+/// input dies.
+///
+/// As heartbeats are driven by the incoming audio, a stalled input stream
+/// results in missing heartbeats. An input stream that reports errors results
+/// in heartbeats with [`Heartbeat::stream_alive`] set to `false`.
+pub fn start_detector_thread_with_heartbeat(
+    on_beat_cb: impl Fn(BeatInfo) + Send + 'static,
+    on_heartbeat_cb: impl Fn(Heartbeat) + Send + 'static,
+    heartbeat_interval: Duration,
+    preferred_input_dev: Option<cpal::Device>,
+) -> Result<cpal::Stream, StartDetectorThreadError> {
+    let (input_dev, input_config) = open_input_device(preferred_input_dev)?;
+    start_detector_thread_impl(
+        move |info| on_beat_cb(info.beat),
+        None,
+        Some((Box::new(on_heartbeat_cb), heartbeat_interval)),
+        false,
+        input_dev,
+        input_config,
+    )
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn start_detector_thread_impl(
+    on_beat_cb: impl Fn(LiveBeatInfo) + Send + 'static,
+    on_level_cb: Option<Box<dyn Fn(ChunkLevel) + Send>>,
+    heartbeat: Option<(Box<dyn Fn(Heartbeat) + Send>, Duration)>,
+    realtime_priority: bool,
+    input_dev: cpal::Device,
+    input_config: StreamConfig,
+) -> Result<cpal::Stream, StartDetectorThreadError> {
+    let detector_config = DetectorConfig::try_from(&input_config)
+        .map_err(StartDetectorThreadError::UnsupportedStreamConfig)?;
+    let sampling_rate = detector_config.sampling_frequency_hz;
+    let mut detector = detector_config.build();
+    let mut latency_monitor = LatencyMonitor::new(LatencyBudget::realtime(), sampling_rate);
+    // Only try once. Failing is okay, as this is best-effort.
+    let mut raise_thread_priority = realtime_priority;
+    // Total index of the first sample of the current chunk.
+    let mut chunk_begin_total_index = 0_u64;
+    let mut heartbeat = heartbeat.map(|(cb, interval)| (cb, HeartbeatGenerator::new(interval)));
+    // Set by the error callback, reset with each heartbeat.
+    let stream_failed = Arc::new(AtomicBool::new(false));
+    let stream_failed_err_cb = stream_failed.clone();
+
+    // Under the hood, this spawns a thread.
+    let stream = input_dev
+        .build_input_stream(
+            &input_config,
+            move |data: &[i16], info: &cpal::InputCallbackInfo| {
+                if raise_thread_priority {
+                    raise_thread_priority = false;
+                    if let Err(e) = set_current_thread_realtime_priority() {
+                        log::debug!("Can't raise priority of audio thread: {e}");
+                    }
+                }
+
+                log::trace!(
+                    "audio input callback: {} samples ({} ms, sampling rate = {sampling_rate})",
+                    data.len(),
+                    Duration::from_secs_f32(data.len() as f32 / sampling_rate).as_millis()
+                );
+
+                if let Some(on_level_cb) = on_level_cb.as_ref() {
+                    on_level_cb(ChunkLevel::measure(data));
+                }
+
+                let beat = latency_monitor.measure(data.len(), || {
+                    detector.update_and_detect_beat(data.iter().copied())
+                });
+                let duration = latency_monitor.stats().latest_processing_time;
+                log::trace!("Beat detection took {:?}", duration);
+
+                if let Some(beat) = beat {
+                    log::debug!("Beat detection took {:?}", duration);
+                    let capture_time = capture_time(
+                        beat.max.source.samples,
+                        chunk_begin_total_index,
+                        info.timestamp().capture,
+                        sampling_rate,
+                    );
+                    on_beat_cb(LiveBeatInfo { beat, capture_time });
+                }
+                chunk_begin_total_index += data.len() as u64;
+
+                if let Some((on_heartbeat_cb, generator)) = heartbeat.as_mut() {
+                    let stream_alive = !stream_failed.load(Ordering::Relaxed);
+                    if let Some(heartbeat) = generator.poll(&detector, stream_alive) {
+                        stream_failed.store(false, Ordering::Relaxed);
+                        on_heartbeat_cb(heartbeat);
+                    }
+                }
+            },
+            move |e| {
+                log::error!("Input error: {e:#?}");
+                stream_failed_err_cb.store(true, Ordering::Relaxed);
+            },
+            // Timeout: worst case max blocking time
+            // Don't see too short, as otherwise, the error callback will be
+            // invoked frequently.
+            // https://github.com/RustAudio/cpal/pull/696
+            Some(Duration::from_secs(1)),
+        )
+        .map_err(StartDetectorThreadError::FailedBuildingInputStream)?;
+
+    stream
+        .play()
+        .map_err(StartDetectorThreadError::InputError)?;
+
+    Ok(stream)
+}
+
+/// Returns the capture time of the sample with the given total index, based
+/// on the capture time of the first sample of a chunk.
+pub(crate) fn capture_time(
+    total_index: u64,
+    chunk_begin_total_index: u64,
+    chunk_capture_time: cpal::StreamInstant,
+    sampling_rate: f32,
+) -> Option<cpal::StreamInstant> {
+    let samples_to_duration =
+        |samples: u64| Duration::from_secs_f64(samples as f64 / sampling_rate as f64);
+    if total_index >= chunk_begin_total_index {
+        chunk_capture_time.add(samples_to_duration(total_index - chunk_begin_total_index))
+    } else {
+        chunk_capture_time.sub(samples_to_duration(chunk_begin_total_index - total_index))
+    }
+}
+
+/// Selects the input device (the preferred one or the default one of the
+/// platform) and a mono input configuration for it.
+pub(crate) fn open_input_device(
+    preferred_input_dev: Option<cpal::Device>,
+) -> Result<(cpal::Device, StreamConfig), StartDetectorThreadError> {
+    let input_dev = preferred_input_dev.map(Ok).unwrap_or_else(|| {
+        let host = cpal::default_host();
+        log::debug!("Using '{:?}' as input framework", host.id());
+        host.default_input_device()
+            .ok_or(StartDetectorThreadError::NoDefaultAudioDevice)
+    })?;
+
+    log::debug!(
+        "Using '{}' as input device",
+        input_dev.name().unwrap_or_else(|_| "<unknown>".to_string())
+    );
+
+    let supported_input_config = input_dev
+        .default_input_config()
+        .map_err(StartDetectorThreadError::InputConfigError)?;
+
+    log::trace!(
+        "Supported input configurations: {:#?}",
+        supported_input_config
+    );
+
+    let input_config = StreamConfig {
+        channels: 1,
+        sample_rate: supported_input_config.sample_rate(),
+        //buffer_size: get_desired_frame_count_if_possible(),
+        buffer_size: BufferSize::Default,
+    };
+
+    log::debug!("Input configuration: {:#?}", input_config);
+
+    Ok((input_dev, input_config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::SampleRate;
+
+    fn stream_config(channels: u16, sample_rate: u32) -> StreamConfig {
+        StreamConfig {
+            channels,
+            sample_rate: SampleRate(sample_rate),
+            buffer_size: BufferSize::Default,
+        }
+    }
+
+    #[test]
+    fn detector_config_from_stream_config() {
+        let config = DetectorConfig::try_from(&stream_config(2, 48000)).unwrap();
+        assert_eq!(config.sampling_frequency_hz, 48000.0);
+        assert_eq!(config.channels, 2);
+        assert_eq!(config.frequency_weighting, FrequencyWeighting::Lowpass);
+        let detector = config.build();
+        assert!((detector.original_sampling_frequency() - 48000.0).abs() < 1.0);
+
+        assert_eq!(
+            DetectorConfig::try_from(&stream_config(0, 48000)),
+            Err(StreamConfigError::NoChannels)
+        );
+        assert_eq!(
+            DetectorConfig::try_from(&stream_config(1, 4000)),
+            Err(StreamConfigError::UnsupportedSampleRate(4000))
+        );
+        assert_eq!(
+            DetectorConfig::try_from(&stream_config(1, 384000)),
+            Err(StreamConfigError::UnsupportedSampleRate(384000))
+        );
+    }
+}
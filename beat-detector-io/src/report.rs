@@ -0,0 +1,417 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for quality reports of the beat detection, which are handy for
+//! tuning sessions and to attach to issues.
+
+use crate::driver::CHUNK_DURATION_MS;
+use beat_detector_core::{BeatDetectorConst, BeatInfo, EnvelopeConfig, FrequencyWeighting};
+use beat_detector_core::{TempoConfig, TempoEstimator};
+use core::fmt::{Display, Formatter};
+use core::time::Duration;
+use std::vec::Vec;
+
+/// Amount of columns of the waveform thumbnail.
+const WAVEFORM_COLUMNS: usize = 80;
+
+/// Characters of the thumbnails from low to high.
+const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Upper bounds of the buckets of the confidence distribution. The last
+/// bucket is open.
+pub(crate) const CONFIDENCE_BUCKETS: [f32; 4] = [1.25, 1.5, 2.0, 3.0];
+
+/// A beat of a [`QualityReport`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ReportBeat {
+    /// The detected beat.
+    pub beat: BeatInfo,
+    /// Ratio between the maximum of the beat and the threshold that the
+    /// envelope search uses for the maximum of a beat. Beats close to `1.0`
+    /// barely passed the detection.
+    pub confidence: f32,
+    /// The tempo in BPM after this beat, see [`TempoEstimator`].
+    pub bpm: Option<f32>,
+}
+
+/// Summary of the beat detection over a complete piece of audio, which
+/// renders as Markdown via its [`Display`] implementation.
+///
+/// The report contains the configuration of the detector, a thumbnail of the
+/// waveform with the detected beats, all beats, the tempo curve, and the
+/// distribution of the confidence of the beats.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_io::report::QualityReport;
+///
+/// let samples = [0_i16; 44100];
+/// let report = QualityReport::analyze(&samples, 44100.0, true);
+/// assert!(report.beats().is_empty());
+/// println!("{report}");
+/// ```
+#[derive(Debug, Clone)]
+pub struct QualityReport {
+    sampling_rate: f32,
+    duration: Duration,
+    needs_lowpass_filter: bool,
+    frequency_weighting: FrequencyWeighting,
+    envelope_config: EnvelopeConfig,
+    /// Maximum absolute amplitude per column of the thumbnail.
+    waveform: Vec<i16>,
+    beats: Vec<ReportBeat>,
+}
+
+impl QualityReport {
+    /// Detects all beats in the given mono samples with the default
+    /// configuration and creates a report. See [`BeatDetectorConst::new`] for
+    /// `needs_lowpass_filter`.
+    pub fn analyze(mono_samples: &[i16], sampling_rate: f32, needs_lowpass_filter: bool) -> Self {
+        let mut detector =
+            beat_detector_core::BeatDetector::new(sampling_rate, needs_lowpass_filter);
+        Self::analyze_with(mono_samples, &mut detector)
+    }
+
+    /// Like [`Self::analyze`], but with a detector that was configured by the
+    /// caller, which is what tuning sessions need. The detector must have
+    /// been created with the sampling rate of the samples.
+    pub fn analyze_with<const N: usize, const D: usize, const P: usize>(
+        mono_samples: &[i16],
+        detector: &mut BeatDetectorConst<N, D, P>,
+    ) -> Self {
+        let sampling_rate = detector.original_sampling_frequency();
+        let chunk_size = ((sampling_rate * CHUNK_DURATION_MS / 1000.0) as usize).max(1);
+        let mut tempo = TempoEstimator::new(TempoConfig::default());
+        let mut beats = Vec::new();
+        for chunk in mono_samples.chunks(chunk_size) {
+            let Some(beat) = detector.update_and_detect_beat(chunk.iter().copied()) else {
+                continue;
+            };
+            beats.push(ReportBeat {
+                beat,
+                confidence: confidence(detector, &beat),
+                bpm: tempo.update(beat.timestamp()),
+            });
+        }
+
+        let column_size = mono_samples.len().div_ceil(WAVEFORM_COLUMNS).max(1);
+        let waveform = mono_samples
+            .chunks(column_size)
+            .map(|column| {
+                column
+                    .iter()
+                    .map(|sample| sample.saturating_abs())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        Self {
+            sampling_rate,
+            duration: Duration::from_secs_f64(mono_samples.len() as f64 / sampling_rate as f64),
+            needs_lowpass_filter: detector.needs_lowpass_filter(),
+            frequency_weighting: detector.frequency_weighting(),
+            envelope_config: *detector.envelope_config(),
+            waveform,
+            beats,
+        }
+    }
+
+    /// Like [`Self::analyze`], but reads the audio from a WAV file. Stereo
+    /// files are mixed down to mono.
+    #[cfg(feature = "audio-file")]
+    pub fn analyze_wav_file(
+        path: impl AsRef<std::path::Path>,
+        needs_lowpass_filter: bool,
+    ) -> Result<Self, crate::audio_io::SourceError> {
+        use crate::audio_io::SampleSource;
+
+        let mut source = crate::audio_io::file::WavSource::open(path)?;
+        let mut samples = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let count = source.next_chunk(&mut buf)?;
+            if count == 0 {
+                break;
+            }
+            samples.extend_from_slice(&buf[..count]);
+        }
+        Ok(Self::analyze(
+            &samples,
+            source.sample_rate(),
+            needs_lowpass_filter,
+        ))
+    }
+
+    /// Returns all detected beats.
+    pub fn beats(&self) -> &[ReportBeat] {
+        &self.beats
+    }
+
+    /// Returns the duration of the analyzed audio.
+    pub const fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the amount of beats per bucket of the confidence. The buckets
+    /// are delimited by [`Self::confidence_bucket_bounds`] and the last bucket
+    /// is open.
+    pub fn confidence_distribution(&self) -> [usize; CONFIDENCE_BUCKETS.len() + 1] {
+        let mut distribution = [0; CONFIDENCE_BUCKETS.len() + 1];
+        for beat in &self.beats {
+            distribution[confidence_bucket(beat.confidence)] += 1;
+        }
+        distribution
+    }
+
+    /// Returns the upper bounds of the buckets of
+    /// [`Self::confidence_distribution`].
+    pub const fn confidence_bucket_bounds() -> &'static [f32] {
+        &CONFIDENCE_BUCKETS
+    }
+
+    /// Renders the waveform thumbnail and a line below it with a `^` in each
+    /// column that contains a beat.
+    fn write_waveform(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let max = self.waveform.iter().copied().max().unwrap_or(0);
+        let line = sparkline(self.waveform.iter().map(|&value| value as f32), max as f32);
+        writeln!(f, "{line}")?;
+        let mut markers = [' '; WAVEFORM_COLUMNS];
+        let column_duration = self.duration.as_secs_f64() / self.waveform.len().max(1) as f64;
+        for beat in &self.beats {
+            let column = (beat.beat.timestamp().as_secs_f64() / column_duration) as usize;
+            if let Some(marker) = markers.get_mut(column) {
+                *marker = '^';
+            }
+        }
+        let markers = markers[..self.waveform.len()]
+            .iter()
+            .collect::<std::string::String>();
+        writeln!(f, "{}", markers.trim_end())
+    }
+}
+
+/// Returns the ratio between the maximum of the beat and the threshold that
+/// the envelope search of the detector currently uses, see
+/// [`ReportBeat::confidence`].
+pub(crate) fn confidence<const N: usize, const D: usize, const P: usize>(
+    detector: &BeatDetectorConst<N, D, P>,
+    beat: &BeatInfo,
+) -> f32 {
+    let threshold = detector
+        .amplitude_histogram()
+        .median()
+        .map_or(0.0, |median| {
+            median as f32 * detector.envelope_config().max_peak_to_median_min_ratio
+        });
+    if threshold > 0.0 {
+        beat.max.value_abs as f32 / threshold
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// Returns the bucket of [`CONFIDENCE_BUCKETS`] of the given confidence.
+pub(crate) fn confidence_bucket(confidence: f32) -> usize {
+    CONFIDENCE_BUCKETS
+        .iter()
+        .position(|&bound| confidence < bound)
+        .unwrap_or(CONFIDENCE_BUCKETS.len())
+}
+
+/// Maps the values to the [`LEVELS`], where `max` is the highest level.
+fn sparkline(values: impl Iterator<Item = f32>, max: f32) -> std::string::String {
+    values
+        .map(|value| {
+            let level = if max > 0.0 {
+                (value / max * (LEVELS.len() - 1) as f32) as usize
+            } else {
+                0
+            };
+            LEVELS[level.min(LEVELS.len() - 1)]
+        })
+        .collect()
+}
+
+impl Display for QualityReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "# Beat Detection Report")?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{} beats in {:.2} s of audio.",
+            self.beats.len(),
+            self.duration.as_secs_f32()
+        )?;
+
+        let config = &self.envelope_config;
+        writeln!(f)?;
+        writeln!(f, "## Configuration")?;
+        writeln!(f)?;
+        writeln!(f, "| Parameter | Value |")?;
+        writeln!(f, "|---|---|")?;
+        writeln!(f, "| Sampling rate | {} Hz |", self.sampling_rate)?;
+        writeln!(f, "| Lowpass filter | {} |", self.needs_lowpass_filter)?;
+        writeln!(
+            f,
+            "| Frequency weighting | {:?} |",
+            self.frequency_weighting
+        )?;
+        writeln!(f, "| Envelope min duration | {:?} |", config.min_duration)?;
+        writeln!(f, "| Envelope min value | {} |", config.min_value)?;
+        writeln!(
+            f,
+            "| Max peak to median min ratio | {} |",
+            config.max_peak_to_median_min_ratio
+        )?;
+        writeln!(f, "| Trend window | {} |", config.trend_window)?;
+        writeln!(f, "| Merge policy | {:?} |", config.merge_policy)?;
+
+        writeln!(f)?;
+        writeln!(f, "## Waveform")?;
+        writeln!(f)?;
+        writeln!(f, "```text")?;
+        self.write_waveform(f)?;
+        writeln!(f, "```")?;
+
+        writeln!(f)?;
+        writeln!(f, "## Beats")?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "| # | Time | Amplitude | Frequency | Confidence | Tempo |"
+        )?;
+        writeln!(f, "|---|---|---|---|---|---|")?;
+        for (i, beat) in self.beats.iter().enumerate() {
+            write!(
+                f,
+                "| {} | {:.3} s | {} |",
+                i + 1,
+                beat.beat.timestamp().as_secs_f32(),
+                beat.beat.max.value_abs,
+            )?;
+            match beat.beat.dominant_frequency_hz {
+                Some(frequency) => write!(f, " {frequency:.0} Hz |")?,
+                None => write!(f, " - |")?,
+            }
+            write!(f, " {:.2} |", beat.confidence)?;
+            match beat.bpm {
+                Some(bpm) => writeln!(f, " {bpm:.1} BPM |")?,
+                None => writeln!(f, " - |")?,
+            }
+        }
+
+        let bpms = self.beats.iter().filter_map(|beat| beat.bpm);
+        let (min, max) = bpms.clone().fold((f32::MAX, 0.0_f32), |(min, max), bpm| {
+            (min.min(bpm), max.max(bpm))
+        });
+        writeln!(f)?;
+        writeln!(f, "## Tempo")?;
+        writeln!(f)?;
+        if max > 0.0 {
+            writeln!(f, "Between {min:.1} and {max:.1} BPM.")?;
+            writeln!(f)?;
+            writeln!(f, "```text")?;
+            // Relative to the minimum, so that small changes are visible.
+            let line = sparkline(bpms.map(|bpm| bpm - min), max - min);
+            writeln!(f, "{line}")?;
+            writeln!(f, "```")?;
+        } else {
+            writeln!(f, "Not enough beats.")?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "## Confidence")?;
+        writeln!(f)?;
+        write_confidence_table(f, &self.confidence_distribution())
+    }
+}
+
+/// Renders a distribution of the confidence of beats, see
+/// [`QualityReport::confidence_distribution`], as Markdown table.
+pub(crate) fn write_confidence_table(
+    f: &mut Formatter<'_>,
+    distribution: &[usize; CONFIDENCE_BUCKETS.len() + 1],
+) -> core::fmt::Result {
+    writeln!(f, "| Confidence | Beats |")?;
+    writeln!(f, "|---|---|")?;
+    let mut lower = 1.0;
+    for (i, count) in distribution.iter().enumerate() {
+        match CONFIDENCE_BUCKETS.get(i) {
+            Some(upper) => writeln!(f, "| {lower:.2} - {upper:.2} | {count} |")?,
+            None => writeln!(f, "| >= {lower:.2} | {count} |")?,
+        }
+        lower = CONFIDENCE_BUCKETS.get(i).copied().unwrap_or(lower);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use std::string::ToString;
+
+    #[test]
+    fn report_of_holiday_long() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let report = QualityReport::analyze(&samples, header.sample_rate as f32, true);
+        assert_eq!(report.beats().len(), 7);
+        assert!(report.beats().iter().all(|beat| beat.confidence >= 1.0));
+        assert_eq!(report.confidence_distribution().iter().sum::<usize>(), 7);
+        let bpm = report.beats().last().unwrap().bpm.unwrap();
+        assert!((bpm - 143.0).abs() < 5.0, "{bpm}");
+
+        let markdown = report.to_string();
+        let trend_window = EnvelopeConfig::DEFAULT.trend_window;
+        assert!(markdown.contains(&std::format!("| Trend window | {trend_window} |")));
+        assert!(markdown.contains("| 7 | 3.140 s |"));
+        let waveform = markdown
+            .lines()
+            .skip_while(|line| *line != "```text")
+            .nth(1);
+        assert_eq!(waveform.unwrap().chars().count(), WAVEFORM_COLUMNS);
+    }
+
+    #[test]
+    #[cfg(feature = "audio-file")]
+    fn report_of_wav_file() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let report = QualityReport::analyze_wav_file(
+            crate::test_utils::res("holiday_lowpassed--long.wav"),
+            true,
+        );
+        assert_eq!(
+            report.unwrap().beats(),
+            QualityReport::analyze(&samples, header.sample_rate as f32, true).beats()
+        );
+    }
+
+    #[test]
+    fn report_of_silence() {
+        let report = QualityReport::analyze(&[], 44100.0, false);
+        assert!(report.beats().is_empty());
+        assert!(report.to_string().contains("Not enough beats."));
+    }
+}
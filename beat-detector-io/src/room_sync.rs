@@ -0,0 +1,835 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`RoomSync`], which keeps the tempo and the beat phase of
+//! multiple detector instances on a LAN in sync.
+//!
+//! ## Protocol
+//!
+//! Each instance periodically sends two UDP datagrams to each of its peers:
+//! a ping, which the peer answers with a pong, and its local tempo and beat
+//! phase. All datagrams begin with the magic `BDRS`, a version byte, a kind
+//! byte, and a random 32 bit instance ID, followed by little-endian `f64`
+//! values in seconds of the sender's clock:
+//!
+//! | Kind      | Values                                          |
+//! |-----------|-------------------------------------------------|
+//! | `0` ping  | send time                                       |
+//! | `1` pong  | send time of the ping, receive time, send time  |
+//! | `2` state | tempo in BPM, time of a beat                    |
+//!
+//! Like NTP, the round trip of ping and pong yields the offset between the
+//! clocks of two instances. Of the latest measurements, the one with the
+//! shortest round trip wins, as it is the least affected by queueing in the
+//! network.
+//!
+//! An instance that receives a ping from an unknown address only answers with
+//! a ping of its own, which isn't larger than the received one, so that pings
+//! with a forged sender can't amplify traffic. Each unknown address gets at
+//! most one ping in three seconds. Only once that ping is answered with a
+//! pong that echoes its send time, the sender becomes a peer. Pongs that
+//! don't answer an outstanding ping and datagrams with values out of range,
+//! such as a tempo below [`LOWEST_BPM`] or above [`HIGHEST_BPM`], are
+//! ignored.
+
+use beat_detector_core::{TempoEstimator, HIGHEST_BPM, LOWEST_BPM};
+use std::collections::hash_map::RandomState;
+use std::f64::consts::TAU;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+/// Identifies a datagram of the protocol.
+const MAGIC: [u8; 4] = *b"BDRS";
+/// Version of the protocol. Bump on incompatible changes.
+const VERSION: u8 = 1;
+/// Magic, version, kind, and instance ID.
+const HEADER_LEN: usize = 4 + 1 + 1 + 4;
+
+const KIND_PING: u8 = 0;
+const KIND_PONG: u8 = 1;
+const KIND_STATE: u8 = 2;
+
+/// Default interval of the pings and state announcements.
+pub const DEFAULT_ANNOUNCE_INTERVAL: Duration = Duration::from_millis(500);
+/// Peers whose latest state is older are ignored, in seconds. Peers that
+/// weren't added with [`RoomSync::add_peer`] are dropped if they were silent
+/// for this long.
+const PEER_TIMEOUT: f64 = 3.0;
+/// Maximum amount of peers, so that a flood of datagrams from many addresses
+/// can't exhaust the memory. Also limits the amount of unknown addresses with
+/// an outstanding ping.
+const MAX_PEERS: usize = 32;
+/// Maximum amount of outstanding pings per address. Older pings are
+/// forgotten, their pongs are ignored.
+const MAX_OUTSTANDING_PINGS: usize = 8;
+/// Maximum distance of the time of a beat of a peer to the arrival of its
+/// state, in seconds. [`RoomSync::set_local_tempo`] announces a recent beat,
+/// so that only absurd times that break the arithmetic of the phases are
+/// rejected.
+const MAX_ANCHOR_DISTANCE: f64 = 60.0;
+/// Amount of offset measurements per peer of which the one with the shortest
+/// round trip is used.
+const OFFSET_SAMPLES: usize = 8;
+/// Time constant of the convergence towards the consensus, in seconds.
+/// Smooths jumps of the synced beat if peers join, leave, or change their
+/// tempo.
+const CONVERGENCE_TIME: f64 = 2.0;
+
+/// The tempo and the beat phase all instances agree on.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct SyncedTempo {
+    /// Tempo in BPM.
+    pub bpm: f32,
+    /// The next beat on the local clock.
+    pub next_beat: Instant,
+}
+
+impl SyncedTempo {
+    /// Returns the duration of one beat.
+    pub fn period(&self) -> Duration {
+        Duration::from_secs_f32(60.0 / self.bpm)
+    }
+}
+
+/// Tempo and time of a beat on some clock, in seconds.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Beat {
+    bpm: f32,
+    anchor: f64,
+}
+
+impl Beat {
+    fn period(&self) -> f64 {
+        60.0 / f64::from(self.bpm)
+    }
+}
+
+#[derive(Debug)]
+struct Peer {
+    addr: SocketAddr,
+    id: u32,
+    /// Whether the peer was added with [`RoomSync::add_peer`]. Other peers
+    /// are dropped once they are silent.
+    is_configured: bool,
+    /// Local time of the latest datagram of the peer.
+    latest_seen: f64,
+    /// Latest round trips and offsets of the peer's clock to the local clock.
+    offsets: Vec<(f64, f64)>,
+    /// Latest state of the peer on its clock and the local time it arrived.
+    state: Option<(Beat, f64)>,
+}
+
+impl Peer {
+    fn add_offset(&mut self, rtt: f64, offset: f64) {
+        if self.offsets.len() == OFFSET_SAMPLES {
+            self.offsets.remove(0);
+        }
+        self.offsets.push((rtt, offset));
+    }
+
+    fn offset(&self) -> Option<f64> {
+        self.offsets
+            .iter()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|&(_, offset)| offset)
+    }
+
+    /// Returns the latest state of the peer on the local clock, if it is
+    /// recent and plausible.
+    fn beat(&self, now: f64) -> Option<Beat> {
+        let (beat, received) = self.state?;
+        let anchor = beat.anchor - self.offset()?;
+        let is_plausible = (received - anchor).abs() <= MAX_ANCHOR_DISTANCE;
+        (now - received < PEER_TIMEOUT && is_plausible).then_some(Beat {
+            bpm: beat.bpm,
+            anchor,
+        })
+    }
+}
+
+/// The protocol without I/O, driven with the local time in seconds.
+#[derive(Debug)]
+struct Node {
+    id: u32,
+    peers: Vec<Peer>,
+    /// Addresses and send times of the pings that weren't answered yet. Only
+    /// pongs that echo one of them are accepted.
+    pings: Vec<(SocketAddr, f64)>,
+    local: Option<Beat>,
+    synced: Option<Beat>,
+    latest_update: Option<f64>,
+}
+
+impl Node {
+    const fn new(id: u32) -> Self {
+        Self {
+            id,
+            peers: Vec::new(),
+            pings: Vec::new(),
+            local: None,
+            synced: None,
+            latest_update: None,
+        }
+    }
+
+    fn encode(&self, kind: u8, values: &[f64]) -> Vec<u8> {
+        let mut message = Vec::with_capacity(HEADER_LEN + values.len() * 8);
+        message.extend_from_slice(&MAGIC);
+        message.push(VERSION);
+        message.push(kind);
+        message.extend_from_slice(&self.id.to_le_bytes());
+        for value in values {
+            message.extend_from_slice(&value.to_le_bytes());
+        }
+        message
+    }
+
+    fn decode(message: &[u8]) -> Option<(u8, u32, Vec<f64>)> {
+        if message.len() < HEADER_LEN || message[..4] != MAGIC || message[4] != VERSION {
+            return None;
+        }
+        let id = u32::from_le_bytes(message[6..10].try_into().unwrap());
+        let values = message[HEADER_LEN..].chunks_exact(8);
+        if !values.remainder().is_empty() {
+            return None;
+        }
+        let values = values
+            .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        values
+            .iter()
+            .all(|value| value.is_finite())
+            .then_some((message[5], id, values))
+    }
+
+    /// Returns the datagrams to send to all peers.
+    fn announce(&mut self, now: f64) -> Vec<Vec<u8>> {
+        for index in 0..self.peers.len() {
+            self.remember_ping(self.peers[index].addr, now);
+        }
+        let mut messages = vec![self.encode(KIND_PING, &[now])];
+        if let Some(local) = self.local {
+            messages.push(self.encode(KIND_STATE, &[f64::from(local.bpm), local.anchor]));
+        }
+        messages
+    }
+
+    /// Handles a received datagram and returns the replies.
+    fn handle(&mut self, message: &[u8], from: SocketAddr, now: f64) -> Vec<Vec<u8>> {
+        let Some((kind, id, values)) = Self::decode(message) else {
+            return Vec::new();
+        };
+        // Our own datagram, e.g., if the own address is among the peers.
+        if id == self.id {
+            return Vec::new();
+        }
+        match (kind, values.as_slice()) {
+            (KIND_PING, &[sent]) => {
+                if self.peer_mut(from, id, now).is_some() {
+                    vec![self.encode(KIND_PONG, &[sent, now, now])]
+                } else if self.may_ping_unknown(from, now) {
+                    // Becomes a peer once it answers.
+                    self.remember_ping(from, now);
+                    vec![self.encode(KIND_PING, &[now])]
+                } else {
+                    Vec::new()
+                }
+            }
+            (KIND_PONG, &[sent, received, replied]) => {
+                let Some(position) = self.pings.iter().position(|&ping| ping == (from, sent))
+                else {
+                    return Vec::new();
+                };
+                self.pings.remove(position);
+                let rtt = (now - sent) - (replied - received);
+                let offset = ((received - sent) + (replied - now)) / 2.0;
+                if !(0.0..PEER_TIMEOUT).contains(&rtt) || sent > now || !offset.is_finite() {
+                    return Vec::new();
+                }
+                if self.peer_mut(from, id, now).is_none() && self.peers.len() < MAX_PEERS {
+                    self.add_peer(from, false, now);
+                }
+                if let Some(peer) = self.peer_mut(from, id, now) {
+                    peer.add_offset(rtt, offset);
+                }
+                Vec::new()
+            }
+            (KIND_STATE, &[bpm, anchor])
+                if (f64::from(LOWEST_BPM)..=f64::from(HIGHEST_BPM)).contains(&bpm) =>
+            {
+                if let Some(peer) = self.peer_mut(from, id, now) {
+                    peer.state = Some((
+                        Beat {
+                            bpm: bpm as f32,
+                            anchor,
+                        },
+                        now,
+                    ));
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Notes a ping to the address, so that its pong is accepted.
+    fn remember_ping(&mut self, addr: SocketAddr, now: f64) {
+        let outstanding = self.pings.iter().filter(|ping| ping.0 == addr).count();
+        if outstanding == MAX_OUTSTANDING_PINGS {
+            let oldest = self.pings.iter().position(|ping| ping.0 == addr).unwrap();
+            self.pings.remove(oldest);
+        }
+        self.pings.push((addr, now));
+    }
+
+    /// Returns whether an unknown address that sent a ping may get a ping
+    /// back: the peers aren't full, it has no outstanding ping yet, and the
+    /// amount of unknown addresses with an outstanding ping is limited.
+    fn may_ping_unknown(&mut self, addr: SocketAddr, now: f64) -> bool {
+        self.forget_pings(now);
+        let peers = &self.peers;
+        let unknown = self
+            .pings
+            .iter()
+            .filter(|ping| peers.iter().all(|peer| peer.addr != ping.0))
+            .count();
+        self.peers.len() < MAX_PEERS
+            && unknown < MAX_PEERS
+            && self.pings.iter().all(|ping| ping.0 != addr)
+    }
+
+    /// Forgets the pings that can't be answered anymore.
+    fn forget_pings(&mut self, now: f64) {
+        self.pings.retain(|&(_, sent)| now - sent < PEER_TIMEOUT);
+    }
+
+    /// Returns the peer with the given address, if it is known, and notes
+    /// that it was seen now.
+    fn peer_mut(&mut self, addr: SocketAddr, id: u32, now: f64) -> Option<&mut Peer> {
+        let peer = self.peers.iter_mut().find(|peer| peer.addr == addr)?;
+        if peer.id != id {
+            // The peer restarted: its clock changed.
+            peer.id = id;
+            peer.offsets.clear();
+            peer.state = None;
+        }
+        peer.latest_seen = now;
+        Some(peer)
+    }
+
+    fn add_peer(&mut self, addr: SocketAddr, is_configured: bool, now: f64) {
+        match self.peers.iter_mut().find(|peer| peer.addr == addr) {
+            Some(peer) => peer.is_configured |= is_configured,
+            None => self.peers.push(Peer {
+                addr,
+                id: self.id,
+                is_configured,
+                latest_seen: now,
+                offsets: Vec::with_capacity(OFFSET_SAMPLES),
+                state: None,
+            }),
+        }
+    }
+
+    /// Moves the synced beat towards the consensus of the local beat and the
+    /// beats of all peers.
+    fn update(&mut self, now: f64) {
+        self.peers
+            .retain(|peer| peer.is_configured || now - peer.latest_seen < PEER_TIMEOUT);
+        self.forget_pings(now);
+        let beats = self
+            .local
+            .into_iter()
+            .chain(self.peers.iter().filter_map(|peer| peer.beat(now)))
+            .collect::<Vec<_>>();
+        let Some(consensus) = Self::consensus(&beats) else {
+            self.synced = None;
+            return;
+        };
+        let dt = self.latest_update.map_or(0.0, |latest| now - latest);
+        self.latest_update = Some(now);
+        self.synced = Some(self.synced.map_or(consensus, |mut synced| {
+            // Follow the consensus to another octave right away. The anchor
+            // is a beat in both octaves.
+            synced.bpm = fold(synced.bpm.into(), consensus.bpm.into()) as f32;
+            let alpha = 1.0 - (-dt / CONVERGENCE_TIME).exp();
+            let period = synced.period();
+            let phase_diff = wrap((consensus.anchor - synced.anchor) / period);
+            Beat {
+                bpm: synced.bpm + (consensus.bpm - synced.bpm) * alpha as f32,
+                anchor: synced.anchor + phase_diff * alpha * period,
+            }
+        }));
+    }
+
+    /// Returns the average tempo and the circular average of the beat
+    /// phases. Tempos are folded by octaves towards the median, as instances
+    /// may detect the double or half tempo.
+    fn consensus(beats: &[Beat]) -> Option<Beat> {
+        let mut bpms = beats
+            .iter()
+            .map(|beat| f64::from(beat.bpm))
+            .collect::<Vec<_>>();
+        bpms.sort_by(f64::total_cmp);
+        let median = *bpms.get(bpms.len() / 2)?;
+        let bpm = bpms.iter().map(|&bpm| fold(bpm, median)).sum::<f64>() / bpms.len() as f64;
+        let period = 60.0 / bpm;
+        let (sin, cos) = beats
+            .iter()
+            .map(|beat| (beat.anchor / period * TAU).sin_cos())
+            .fold((0.0, 0.0), |(sin, cos), (s, c)| (sin + s, cos + c));
+        Some(Beat {
+            bpm: bpm as f32,
+            anchor: sin.atan2(cos) / TAU * period,
+        })
+    }
+}
+
+/// Folds the tempo by octaves into the range of half an octave around the
+/// reference.
+fn fold(bpm: f64, reference: f64) -> f64 {
+    bpm / (bpm / reference).log2().round().exp2()
+}
+
+/// Wraps a phase in beats to `-0.5..0.5`.
+fn wrap(phase: f64) -> f64 {
+    phase - phase.round()
+}
+
+/// Shares the tempo and the beat phase between detector instances on a LAN,
+/// so that lights in different rooms, listening to different speakers, stay
+/// in sync.
+///
+/// Each instance feeds its local tempo with [`Self::set_local_tempo`] and
+/// calls [`Self::poll`] regularly, e.g., with each chunk of audio. All
+/// instances converge to a common tempo and beat phase, see [`Self::synced`],
+/// even though the audio reaches each detector with a different delay. The
+/// peers are configured with [`Self::add_peer`]; instances that contact us
+/// are added automatically, up to 32 peers, once they answered a ping. See
+/// the [module documentation](self) for the protocol.
+///
+/// ## Example
+/// ```rust,no_run
+/// use beat_detector_io::room_sync::RoomSync;
+/// use beat_detector_core::{TempoConfig, TempoEstimator};
+/// use std::time::Duration;
+///
+/// let mut sync = RoomSync::bind("0.0.0.0:4500").unwrap();
+/// sync.add_peer("kitchen.local:4500").unwrap();
+/// let tempo = TempoEstimator::new(TempoConfig::default());
+/// loop {
+///     // TODO detect beats and update the tempo.
+///     sync.set_local_tempo(&tempo, Duration::ZERO);
+///     sync.poll().unwrap();
+///     if let Some(synced) = sync.synced() {
+///         // TODO schedule the lights at `synced.next_beat`.
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RoomSync {
+    socket: UdpSocket,
+    node: Node,
+    /// Reference point of the local clock.
+    epoch: Instant,
+    announce_interval: Duration,
+    latest_announce: Option<Instant>,
+}
+
+impl RoomSync {
+    /// Binds the UDP socket of this instance.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        let id = RandomState::new().build_hasher().finish() as u32;
+        Ok(Self {
+            socket,
+            node: Node::new(id),
+            epoch: Instant::now(),
+            announce_interval: DEFAULT_ANNOUNCE_INTERVAL,
+            latest_announce: None,
+        })
+    }
+
+    /// Returns the local address of the socket.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Adds a peer to which the tempo is announced. Unlike peers that
+    /// contacted this instance, it is kept while it is offline.
+    pub fn add_peer(&mut self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let now = self.now();
+        for addr in addr.to_socket_addrs()? {
+            self.node.add_peer(addr, true, now);
+        }
+        Ok(())
+    }
+
+    /// Sets the interval of the pings and state announcements. The default
+    /// is [`DEFAULT_ANNOUNCE_INTERVAL`].
+    pub fn set_announce_interval(&mut self, interval: Duration) {
+        self.announce_interval = interval;
+    }
+
+    /// Returns the amount of peers whose tempo is currently known.
+    pub fn active_peers(&self) -> usize {
+        let now = self.now();
+        self.node
+            .peers
+            .iter()
+            .filter(|peer| peer.beat(now).is_some())
+            .count()
+    }
+
+    /// Sets the local tempo and beat phase from the estimator.
+    ///
+    /// `audio_now` is the time on the clock of the beats that corresponds to
+    /// now, e.g., [`BeatDetector::passed_time`] after the latest update. Call
+    /// this regularly, as peers ignore beats that are older than a minute.
+    ///
+    /// [`BeatDetector::passed_time`]: beat_detector_core::BeatDetector::passed_time
+    pub fn set_local_tempo(&mut self, tempo: &TempoEstimator, audio_now: Duration) {
+        let now = self.now();
+        self.node.local = tempo
+            .bpm()
+            .zip(tempo.beats_elapsed(audio_now))
+            .map(|(bpm, beats)| {
+                let period = 60.0 / f64::from(bpm);
+                Beat {
+                    bpm,
+                    anchor: now - beats.fract() * period,
+                }
+            });
+    }
+
+    /// Receives all pending datagrams, announces the local state if the
+    /// announce interval passed, and updates the synced tempo.
+    pub fn poll(&mut self) -> io::Result<()> {
+        let mut buf = [0; 64];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, from)) => {
+                    for reply in self.node.handle(&buf[..len], from, self.now()) {
+                        self.send(&reply, from);
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                // ICMP errors of earlier datagrams to peers that are offline.
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset
+                    ) => {}
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let due = self
+            .latest_announce
+            .map_or(true, |latest| latest.elapsed() >= self.announce_interval);
+        if due {
+            self.latest_announce = Some(Instant::now());
+            let addrs = self
+                .node
+                .peers
+                .iter()
+                .map(|peer| peer.addr)
+                .collect::<Vec<_>>();
+            for message in self.node.announce(self.now()) {
+                for &addr in &addrs {
+                    self.send(&message, addr);
+                }
+            }
+        }
+
+        self.node.update(self.now());
+        Ok(())
+    }
+
+    /// Returns the tempo and the beat phase all instances agree on, or
+    /// `None` if no instance knows its tempo.
+    pub fn synced(&self) -> Option<SyncedTempo> {
+        let synced = self.node.synced?;
+        let now = self.now();
+        let period = synced.period();
+        let next_beat = synced.anchor + ((now - synced.anchor) / period).ceil() * period;
+        Some(SyncedTempo {
+            bpm: synced.bpm,
+            next_beat: self.epoch + Duration::from_secs_f64(next_beat.max(0.0)),
+        })
+    }
+
+    /// Sends a datagram. Failures are only logged, as an unreachable peer
+    /// must not stop the synchronization with the others.
+    fn send(&self, message: &[u8], addr: SocketAddr) {
+        if let Err(err) = self.socket.send_to(message, addr) {
+            log::debug!("Can't send to peer {addr}: {err}");
+        }
+    }
+
+    /// Returns the local time in seconds.
+    fn now(&self) -> f64 {
+        self.epoch.elapsed().as_secs_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use beat_detector_core::TempoConfig;
+
+    /// Simulates instances whose clocks are offset against a common clock,
+    /// connected via a network with the given one-way delay. The instances
+    /// announce their state every 500 ms.
+    fn simulate(nodes: &mut [(Node, f64)], duration: f64, delay: f64) {
+        let addr = |index: usize| SocketAddr::from(([10, 0, 0, index as u8], 4500));
+        let count = nodes.len();
+        let mut in_flight = Vec::new();
+        for step in 0..(duration / 0.5) as usize {
+            let time = step as f64 * 0.5;
+            // Deliver, including the replies.
+            while let Some(position) = in_flight
+                .iter()
+                .position(|&(arrival, _, _, _)| arrival <= time)
+            {
+                let (arrival, from, to, message): (f64, usize, usize, Vec<u8>) =
+                    in_flight.remove(position);
+                let (node, offset) = &mut nodes[to];
+                for reply in node.handle(&message, addr(from), arrival + *offset) {
+                    in_flight.push((arrival + delay, to, from, reply));
+                }
+            }
+            for (from, (node, offset)) in nodes.iter_mut().enumerate() {
+                for message in node.announce(time + *offset) {
+                    for to in (0..count).filter(|&to| to != from) {
+                        in_flight.push((time + delay, from, to, message.clone()));
+                    }
+                }
+                node.update(time + *offset);
+            }
+        }
+    }
+
+    #[test]
+    fn converges() {
+        // Four rooms with clocks that are far apart and a slightly different
+        // tempo and phase each. One detects the double tempo.
+        let local = [(120.0, 0.00), (121.0, 0.05), (240.0, 0.1), (119.0, -0.05)];
+        let offsets = [0.0, 100.0, -3.5, 7.25];
+        let mut nodes = (0..4)
+            .map(|index| {
+                let mut node = Node::new(index as u32 + 1);
+                for peer in (0..4).filter(|&peer| peer != index) {
+                    node.add_peer(SocketAddr::from(([10, 0, 0, peer as u8], 4500)), true, 0.0);
+                }
+                let (bpm, anchor) = local[index];
+                node.local = Some(Beat {
+                    bpm,
+                    anchor: anchor + offsets[index],
+                });
+                (node, offsets[index])
+            })
+            .collect::<Vec<_>>();
+        simulate(&mut nodes, 30.0, 0.004);
+
+        for (node, offset) in &nodes {
+            let synced = node.synced.unwrap();
+            assert!((synced.bpm - 120.0).abs() < 0.1, "{synced:?}");
+            // The phase on the common clock is the average of the phases.
+            let phase = wrap((synced.anchor - offset) / synced.period());
+            assert!((phase - 0.05).abs() < 0.005, "{phase}");
+        }
+    }
+
+    #[test]
+    fn codec() {
+        let node = Node::new(42);
+        let message = node.encode(KIND_STATE, &[120.0, 1.5]);
+        assert_eq!(
+            Node::decode(&message),
+            Some((KIND_STATE, 42, vec![120.0, 1.5]))
+        );
+        assert_eq!(Node::decode(&message[..message.len() - 1]), None);
+        assert_eq!(Node::decode(b"garbage"), None);
+        let nan = node.encode(KIND_STATE, &[f64::NAN, 1.5]);
+        assert_eq!(Node::decode(&nan), None);
+    }
+
+    /// Runs the handshake of `other`, which has `node` configured as peer:
+    /// `other` pings `node`, which answers with a ping of its own, which
+    /// `other` answers with a pong.
+    fn handshake(node: &mut Node, other: &mut Node, other_addr: SocketAddr, now: f64) {
+        let node_addr = SocketAddr::from(([10, 0, 2, 0], 4500));
+        other.add_peer(node_addr, true, now);
+        for ping in other.announce(now) {
+            for ping in node.handle(&ping, other_addr, now) {
+                for pong in other.handle(&ping, node_addr, now) {
+                    assert!(node.handle(&pong, other_addr, now).is_empty());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn handshake_adds_peer() {
+        let addr = SocketAddr::from(([10, 0, 1, 1], 4500));
+        let mut node = Node::new(1);
+        let mut other = Node::new(2);
+        handshake(&mut node, &mut other, addr, 1.0);
+        assert_eq!(node.peers.len(), 1);
+        assert_eq!(node.peers[0].addr, addr);
+        assert_eq!(node.peers[0].id, 2);
+        assert_eq!(node.peers[0].offset(), Some(0.0));
+        assert!(node.pings.is_empty());
+    }
+
+    #[test]
+    fn ignores_forged_pongs() {
+        let addr = |index: u8| SocketAddr::from(([10, 0, 1, index], 4500));
+        let mut node = Node::new(1);
+        node.add_peer(addr(0), true, 0.0);
+        let forger = Node::new(2);
+        let pong = |sent: f64| forger.encode(KIND_PONG, &[sent, 0.95, 0.95]);
+
+        // Unsolicited pongs with plausible times don't add peers.
+        for index in 1..=MAX_PEERS as u8 {
+            assert!(node.handle(&pong(0.9), addr(index), 1.0).is_empty());
+        }
+        assert_eq!(node.peers.len(), 1);
+
+        // Pongs of the known peer must echo an outstanding ping, and only
+        // once.
+        node.announce(0.9);
+        node.handle(&pong(0.8), addr(0), 1.0);
+        node.handle(&pong(0.9), addr(1), 1.0);
+        assert_eq!(node.peers[0].offset(), None);
+        node.handle(&pong(0.9), addr(0), 1.0);
+        node.handle(&pong(0.9), addr(0), 1.1);
+        assert_eq!(node.peers[0].offsets.len(), 1);
+
+        // The pong to a ping that answered the ping of an unknown address
+        // can't be forged from another address either.
+        node.handle(&forger.encode(KIND_PING, &[0.5]), addr(2), 2.0);
+        node.handle(&pong(2.0), addr(3), 2.1);
+        assert_eq!(node.peers.len(), 1);
+    }
+
+    #[test]
+    fn ignores_malformed_datagrams() {
+        let addr = |index: u8| SocketAddr::from(([10, 0, 1, index], 4500));
+        let mut node = Node::new(1);
+        node.add_peer(addr(0), true, 0.0);
+        let peer = Node::new(2);
+        let state = |bpm: f64, anchor: f64| peer.encode(KIND_STATE, &[bpm, anchor]);
+        let pong = |sent: f64, received: f64, replied: f64| {
+            peer.encode(KIND_PONG, &[sent, received, replied])
+        };
+
+        // Unknown senders don't become peers by pings or states alone. They
+        // only get a ping back, which isn't larger than theirs, and only once.
+        let ping = peer.encode(KIND_PING, &[5.0]);
+        let replies = node.handle(&ping, addr(1), 1.0);
+        assert_eq!(replies.len(), 1);
+        assert_eq!(Node::decode(&replies[0]), Some((KIND_PING, 1, vec![1.0])));
+        assert!(replies[0].len() <= ping.len());
+        assert!(node.handle(&ping, addr(1), 1.5).is_empty());
+        assert!(node.handle(&state(120.0, 0.5), addr(1), 1.0).is_empty());
+        assert_eq!(node.peers.len(), 1);
+
+        // Pongs of outstanding pings with impossible round trips or values.
+        for (sent, message) in [
+            (2.0, pong(2.0, 0.0, 0.0)),
+            (0.5, pong(0.5, 10.0, 5.0)),
+            (0.5, pong(0.5, -1e308, 1e308)),
+            (0.5, peer.encode(KIND_PONG, &[0.5])),
+            (0.5, peer.encode(42, &[0.5, 0.5, 0.5])),
+        ] {
+            node.remember_ping(addr(1), sent);
+            assert!(node.handle(&message, addr(1), 1.0).is_empty());
+        }
+        assert_eq!(node.peers.len(), 1);
+
+        // A valid pong of the known peer, and states out of range.
+        node.announce(0.9);
+        node.handle(&pong(0.9, 0.95, 0.95), addr(0), 1.0);
+        assert!(node.peers[0].offset().is_some());
+        for bpm in [0.0, -120.0, 1e9, 10.0] {
+            node.handle(&state(bpm, 0.5), addr(0), 1.0);
+            assert_eq!(node.peers[0].state, None);
+        }
+        node.handle(&state(120.0, 1e300), addr(0), 1.0);
+        node.update(1.0);
+        assert_eq!(node.synced, None);
+        node.handle(&state(120.0, 0.5), addr(0), 1.0);
+        node.update(1.0);
+        assert_eq!(node.synced.map(|synced| synced.bpm), Some(120.0));
+
+        // The amount of peers is limited.
+        for index in 100..100 + 2 * MAX_PEERS as u8 {
+            let mut other = Node::new(u32::from(index));
+            handshake(&mut node, &mut other, addr(index), 1.0);
+        }
+        assert_eq!(node.peers.len(), MAX_PEERS);
+
+        // Silent peers are dropped, apart from the configured one.
+        node.update(1.0 + PEER_TIMEOUT);
+        assert_eq!(node.peers.len(), 1);
+        assert_eq!(node.peers[0].addr, addr(0));
+    }
+
+    #[test]
+    fn udp() {
+        let mut a = RoomSync::bind("127.0.0.1:0").unwrap();
+        let mut b = RoomSync::bind("127.0.0.1:0").unwrap();
+        a.add_peer(b.local_addr().unwrap()).unwrap();
+        a.set_announce_interval(Duration::from_millis(10));
+        b.set_announce_interval(Duration::from_millis(10));
+
+        let mut tempo = TempoEstimator::new(TempoConfig::default());
+        for beat in 0..8 {
+            tempo.update(Duration::from_millis(beat * 500));
+        }
+        a.set_local_tempo(&tempo, Duration::from_millis(3600));
+
+        let begin = Instant::now();
+        while b.active_peers() == 0 && begin.elapsed() < Duration::from_secs(5) {
+            a.poll().unwrap();
+            b.poll().unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        // `b` learned about `a` from its datagrams and follows its tempo.
+        assert_eq!(b.active_peers(), 1);
+        let synced = b.synced().unwrap();
+        assert!((synced.bpm - 120.0).abs() < 0.1, "{synced:?}");
+        // The same beat or a neighboring one, 500 ms apart.
+        let (a_next, b_next) = (a.synced().unwrap().next_beat, synced.next_beat);
+        let diff = (a_next.max(b_next) - a_next.min(b_next)).as_secs_f64() % 0.5;
+        assert!(!(0.02..=0.48).contains(&diff), "{diff}");
+    }
+}
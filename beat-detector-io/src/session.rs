@@ -0,0 +1,374 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for statistics over a whole session, such as a night's set, which
+//! are handy for logging.
+
+use crate::report::{confidence, confidence_bucket, write_confidence_table, CONFIDENCE_BUCKETS};
+use beat_detector_core::{BeatDetectorConst, BeatInfo, ChunkLevel, TempoConfig, TempoEstimator};
+use core::fmt::{Display, Formatter};
+use core::ops::RangeInclusive;
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::vec::Vec;
+
+/// Width of the buckets of [`SessionSummary::bpm_histogram`] in BPM.
+pub const BPM_BUCKET_WIDTH: u16 = 5;
+
+/// Duration of the blocks whose level makes up the loudness range.
+const LOUDNESS_BLOCK_DURATION: Duration = Duration::from_secs(1);
+
+/// Blocks below this level in dBFS are silence, such as a pause between two
+/// sets, and don't count for the loudness range.
+const SILENCE_DBFS: f32 = -70.0;
+
+/// Resolution of the loudness histogram in dB.
+const LOUDNESS_RESOLUTION_DB: f32 = 0.5;
+
+/// Amount of bins of the loudness histogram, from [`SILENCE_DBFS`] to 0 dBFS.
+const LOUDNESS_BINS: usize = (-SILENCE_DBFS / LOUDNESS_RESOLUTION_DB) as usize;
+
+/// Percentiles of the loudness of the blocks that delimit the loudness range.
+/// Like for the loudness range of EBU R 128, the extremes are excluded, so
+/// that a single silent break or a single loud effect doesn't dominate.
+const LOUDNESS_PERCENTILES: (f32, f32) = (0.10, 0.95);
+
+/// Collects statistics over a session, such as a night's set or a single
+/// track, and produces a [`SessionSummary`] on demand or once the session
+/// is [finished](Self::finish).
+///
+/// Feed it every chunk of audio with [`Self::update`], or, if the detector
+/// isn't at hand, such as in the callbacks of the [`recording`] module, the
+/// levels and the beats separately with [`Self::record_level`] and
+/// [`Self::record_beat`]. The memory doesn't grow with the duration.
+///
+/// ## Example
+/// ```rust
+/// use beat_detector_core::BeatDetector;
+/// use beat_detector_io::session::SessionStats;
+///
+/// let mono_samples = [0, 500, -800, 700 /*, ... */];
+/// let mut detector = BeatDetector::new(44100.0, true);
+/// let mut stats = SessionStats::new(44100.0);
+///
+/// // TODO regularly call this with the latest audio data.
+/// let beat = detector.update_and_detect_beat(mono_samples.iter().copied());
+/// stats.update(&detector, &mono_samples, beat.as_ref());
+///
+/// // At the end of the night.
+/// println!("{}", stats.finish());
+/// ```
+///
+/// [`recording`]: crate#cargo-features
+#[derive(Debug, Clone)]
+pub struct SessionStats {
+    sampling_rate: f32,
+    samples: u64,
+    tempo: TempoEstimator,
+    beats: usize,
+    /// Sum of the tempo after each beat, for the average.
+    bpm_sum: f64,
+    bpm_count: usize,
+    /// Amount of beats per bucket of [`BPM_BUCKET_WIDTH`], by the lower bound
+    /// of the bucket.
+    bpm_histogram: BTreeMap<u16, usize>,
+    confidence_distribution: [usize; CONFIDENCE_BUCKETS.len() + 1],
+    block_sum_of_squares: f64,
+    block_samples: usize,
+    /// Amount of loudness blocks per bin of [`LOUDNESS_RESOLUTION_DB`],
+    /// beginning at [`SILENCE_DBFS`].
+    loudness_histogram: [u32; LOUDNESS_BINS],
+}
+
+impl SessionStats {
+    /// Creates empty statistics for audio with the given sampling rate.
+    pub fn new(sampling_rate: f32) -> Self {
+        Self {
+            sampling_rate,
+            samples: 0,
+            tempo: TempoEstimator::new(TempoConfig::default()),
+            beats: 0,
+            bpm_sum: 0.0,
+            bpm_count: 0,
+            bpm_histogram: BTreeMap::new(),
+            confidence_distribution: [0; CONFIDENCE_BUCKETS.len() + 1],
+            block_sum_of_squares: 0.0,
+            block_samples: 0,
+            loudness_histogram: [0; LOUDNESS_BINS],
+        }
+    }
+
+    /// Records a chunk of audio that was just passed to the detector and the
+    /// beat that the detector reported for it, if any.
+    pub fn update<const N: usize, const D: usize, const P: usize>(
+        &mut self,
+        detector: &BeatDetectorConst<N, D, P>,
+        mono_samples: &[i16],
+        beat: Option<&BeatInfo>,
+    ) {
+        self.record_level(&ChunkLevel::measure(mono_samples));
+        if let Some(beat) = beat {
+            self.record_beat(beat, Some(confidence(detector, beat)));
+        }
+    }
+
+    /// Records the level of a chunk of audio, which also advances the
+    /// duration of the session.
+    pub fn record_level(&mut self, level: &ChunkLevel) {
+        self.samples += level.samples as u64;
+        self.block_sum_of_squares += level.rms as f64 * level.rms as f64 * level.samples as f64;
+        self.block_samples += level.samples;
+        let block_len = LOUDNESS_BLOCK_DURATION.as_secs_f32() * self.sampling_rate;
+        if self.block_samples as f32 >= block_len {
+            self.finish_block();
+        }
+    }
+
+    /// Records a beat. The confidence is the one of
+    /// [`ReportBeat::confidence`], if known. Beats without it only miss in
+    /// the confidence distribution.
+    ///
+    /// [`ReportBeat::confidence`]: crate::report::ReportBeat::confidence
+    pub fn record_beat(&mut self, beat: &BeatInfo, confidence: Option<f32>) {
+        self.beats += 1;
+        if let Some(bpm) = self.tempo.update(beat.timestamp()) {
+            self.bpm_sum += bpm as f64;
+            self.bpm_count += 1;
+            let bucket = (bpm as u16 / BPM_BUCKET_WIDTH) * BPM_BUCKET_WIDTH;
+            *self.bpm_histogram.entry(bucket).or_default() += 1;
+        }
+        if let Some(confidence) = confidence {
+            self.confidence_distribution[confidence_bucket(confidence)] += 1;
+        }
+    }
+
+    /// Returns the summary of the session so far. The latest second of audio
+    /// only counts for the loudness once it is complete.
+    pub fn summary(&self) -> SessionSummary {
+        let blocks = self.loudness_histogram.iter().sum::<u32>();
+        let percentile = |percentile: f32| {
+            let rank = (percentile * (blocks - 1) as f32) as u32;
+            let mut count = 0;
+            let bin = self
+                .loudness_histogram
+                .iter()
+                .position(|&bin| {
+                    count += bin;
+                    count > rank
+                })
+                .expect("rank should be below the amount of blocks");
+            SILENCE_DBFS + bin as f32 * LOUDNESS_RESOLUTION_DB
+        };
+        let (low, high) = LOUDNESS_PERCENTILES;
+        SessionSummary {
+            duration: Duration::from_secs_f64(self.samples as f64 / self.sampling_rate as f64),
+            beats: self.beats,
+            average_bpm: (self.bpm_count > 0)
+                .then(|| (self.bpm_sum / self.bpm_count as f64) as f32),
+            bpm_histogram: self
+                .bpm_histogram
+                .iter()
+                .map(|(&bucket, &count)| (bucket, count))
+                .collect(),
+            loudness_range_dbfs: (blocks > 0).then(|| percentile(low)..=percentile(high)),
+            confidence_distribution: self.confidence_distribution,
+        }
+    }
+
+    /// Ends the session and returns its summary, including the incomplete
+    /// latest second of audio.
+    pub fn finish(mut self) -> SessionSummary {
+        if self.block_samples > 0 {
+            self.finish_block();
+        }
+        self.summary()
+    }
+
+    /// Adds the level of the current block to the loudness histogram.
+    fn finish_block(&mut self) {
+        let rms = (self.block_sum_of_squares / self.block_samples as f64).sqrt() as f32;
+        let dbfs = 20.0 * (rms / i16::MAX as f32).log10();
+        if dbfs >= SILENCE_DBFS {
+            let bin = ((dbfs - SILENCE_DBFS) / LOUDNESS_RESOLUTION_DB) as usize;
+            self.loudness_histogram[bin.min(LOUDNESS_BINS - 1)] += 1;
+        }
+        self.block_sum_of_squares = 0.0;
+        self.block_samples = 0;
+    }
+}
+
+/// Summary of a session, see [`SessionStats`], which renders as Markdown via
+/// its [`Display`] implementation.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct SessionSummary {
+    /// Duration of the recorded audio.
+    pub duration: Duration,
+    /// Total amount of beats.
+    pub beats: usize,
+    /// Average of the tempo after each beat, if any tempo was known.
+    pub average_bpm: Option<f32>,
+    /// Amount of beats per tempo bucket of [`BPM_BUCKET_WIDTH`], as the lower
+    /// bound of the bucket and the amount of beats, sorted by the tempo.
+    /// Empty buckets are omitted.
+    pub bpm_histogram: Vec<(u16, usize)>,
+    /// Range of the level of the audio in dBFS, from the quiet to the loud
+    /// parts, measured per second. Silence is excluded. `None` if there was
+    /// only silence.
+    pub loudness_range_dbfs: Option<RangeInclusive<f32>>,
+    /// Amount of beats per bucket of the confidence, like
+    /// [`QualityReport::confidence_distribution`].
+    ///
+    /// [`QualityReport::confidence_distribution`]: crate::report::QualityReport::confidence_distribution
+    pub confidence_distribution: [usize; CONFIDENCE_BUCKETS.len() + 1],
+}
+
+impl SessionSummary {
+    /// Returns the width of the [loudness range] in dB.
+    ///
+    /// [loudness range]: Self::loudness_range_dbfs
+    pub fn loudness_range_db(&self) -> Option<f32> {
+        let range = self.loudness_range_dbfs.as_ref()?;
+        Some(range.end() - range.start())
+    }
+}
+
+impl Display for SessionSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "# Session Summary")?;
+        writeln!(f)?;
+        writeln!(f, "| Statistic | Value |")?;
+        writeln!(f, "|---|---|")?;
+        let seconds = self.duration.as_secs();
+        writeln!(
+            f,
+            "| Duration | {}:{:02}:{:02} |",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )?;
+        writeln!(f, "| Beats | {} |", self.beats)?;
+        match self.average_bpm {
+            Some(bpm) => writeln!(f, "| Average tempo | {bpm:.1} BPM |")?,
+            None => writeln!(f, "| Average tempo | - |")?,
+        }
+        match &self.loudness_range_dbfs {
+            Some(range) => writeln!(
+                f,
+                "| Loudness range | {:.1} to {:.1} dBFS |",
+                range.start(),
+                range.end()
+            )?,
+            None => writeln!(f, "| Loudness range | - |")?,
+        }
+
+        writeln!(f)?;
+        writeln!(f, "## Tempo")?;
+        writeln!(f)?;
+        writeln!(f, "| Tempo | Beats |")?;
+        writeln!(f, "|---|---|")?;
+        for (bucket, count) in &self.bpm_histogram {
+            writeln!(
+                f,
+                "| {bucket} - {} BPM | {count} |",
+                bucket + BPM_BUCKET_WIDTH
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "## Confidence")?;
+        writeln!(f)?;
+        write_confidence_table(f, &self.confidence_distribution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use beat_detector_core::BeatDetector;
+    use std::string::ToString;
+
+    #[test]
+    fn summary_of_holiday_long() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let sampling_rate = header.sample_rate as f32;
+        let mut detector = BeatDetector::new(sampling_rate, true);
+        let mut stats = SessionStats::new(sampling_rate);
+        for chunk in samples.chunks(882) {
+            let beat = detector.update_and_detect_beat(chunk.iter().copied());
+            stats.update(&detector, chunk, beat.as_ref());
+        }
+
+        let summary = stats.summary();
+        assert_eq!(summary.beats, 7);
+        let bpm = summary.average_bpm.unwrap();
+        assert!((130.0..160.0).contains(&bpm), "{bpm}");
+        assert_eq!(
+            summary
+                .bpm_histogram
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<usize>(),
+            6
+        );
+        assert_eq!(summary.confidence_distribution.iter().sum::<usize>(), 7);
+        let range = summary.loudness_range_dbfs.unwrap();
+        assert!(range.start() <= range.end());
+        assert!(*range.end() < 0.0);
+
+        let finished = stats.finish();
+        assert_eq!(
+            finished.duration,
+            Duration::from_secs_f64(samples.len() as f64 / sampling_rate as f64)
+        );
+        let markdown = finished.to_string();
+        assert!(markdown.contains("| Beats | 7 |"), "{markdown}");
+        assert!(markdown.contains("## Confidence"));
+    }
+
+    #[test]
+    fn loudness_range() {
+        let mut stats = SessionStats::new(1000.0);
+        assert_eq!(stats.summary().loudness_range_dbfs, None);
+        let level = |sample: i16| ChunkLevel::measure(&[sample; 1000]);
+        // 20 quiet seconds at -40 dBFS, 80 loud seconds at -10 dBFS, and a
+        // pause that doesn't count.
+        for _ in 0..20 {
+            stats.record_level(&level(328));
+        }
+        for _ in 0..80 {
+            stats.record_level(&level(10354));
+        }
+        for _ in 0..100 {
+            stats.record_level(&level(0));
+        }
+
+        let summary = stats.finish();
+        assert_eq!(summary.duration, Duration::from_secs(200));
+        assert_eq!(summary.loudness_range_dbfs, Some(-40.0..=-10.5));
+        assert_eq!(summary.loudness_range_db(), Some(29.5));
+        assert_eq!(summary.average_bpm, None);
+        assert!(summary.bpm_histogram.is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for graceful shutdown of long-running operations, such as the
+//! recording thread or the [`driver`] loop.
+//!
+//! [`driver`]: crate::driver
+
+use core::fmt::{Debug, Formatter};
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::boxed::Box;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+use std::vec::Vec;
+
+type StopCallback = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct Inner {
+    /// Fast path for polling.
+    stopped: AtomicBool,
+    /// Callbacks that are not invoked yet. Guards the slow path.
+    callbacks: Mutex<Vec<StopCallback>>,
+    condvar: Condvar,
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, Vec<StopCallback>> {
+        // Callbacks run outside the lock, so it can't be poisoned in a way
+        // that leaves inconsistent state behind.
+        self.callbacks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Requests a stop of all associated [`StopToken`]s.
+///
+/// Typically, the source is owned by whatever decides about the shutdown,
+/// such as a Ctrl+C handler, and the tokens are passed to the operations that
+/// should stop.
+pub struct StopSource {
+    inner: Arc<Inner>,
+}
+
+impl StopSource {
+    /// Creates a new source that is not stopped.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::default(),
+        }
+    }
+
+    /// Returns a new token that is associated with this source.
+    pub fn token(&self) -> StopToken {
+        StopToken {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Requests the stop. Wakes up all waiting threads and invokes all
+    /// registered callbacks. Subsequent calls have no effect.
+    pub fn stop(&self) {
+        let callbacks = {
+            let mut callbacks = self.inner.lock();
+            if self.inner.stopped.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            core::mem::take(&mut *callbacks)
+        };
+        self.inner.condvar.notify_all();
+        for callback in callbacks {
+            callback();
+        }
+    }
+
+    /// Returns whether the stop was requested.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::SeqCst)
+    }
+}
+
+impl Default for StopSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for StopSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("StopSource")
+            .field("stopped", &self.is_stopped())
+            .finish()
+    }
+}
+
+/// Observes whether the associated [`StopSource`] requested a stop.
+#[derive(Clone)]
+pub struct StopToken {
+    inner: Arc<Inner>,
+}
+
+impl StopToken {
+    /// Returns whether the stop was requested. This is cheap and suited for
+    /// polling in hot loops.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Blocks the current thread until the stop is requested.
+    pub fn wait(&self) {
+        drop(
+            self.inner
+                .condvar
+                .wait_while(self.inner.lock(), |_| !self.is_stopped())
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+    }
+
+    /// Blocks the current thread until the stop is requested or the timeout
+    /// elapsed. Returns whether the stop was requested.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        drop(
+            self.inner
+                .condvar
+                .wait_timeout_while(self.inner.lock(), timeout, |_| !self.is_stopped())
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+        self.is_stopped()
+    }
+
+    /// Registers a callback that is invoked once when the stop is requested,
+    /// in the thread that requests it. If the stop was already requested, the
+    /// callback is invoked immediately.
+    pub fn on_stop(&self, callback: impl FnOnce() + Send + 'static) {
+        let mut callbacks = self.inner.lock();
+        if self.is_stopped() {
+            drop(callbacks);
+            callback();
+        } else {
+            callbacks.push(Box::new(callback));
+        }
+    }
+}
+
+impl Debug for StopToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("StopToken")
+            .field("stopped", &self.is_stopped())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    #[test]
+    fn stop_wakes_up_waiting_threads() {
+        let source = StopSource::new();
+        let token = source.token();
+        assert!(!token.is_stopped());
+        assert!(!token.wait_timeout(Duration::from_millis(1)));
+
+        let waiters = (0..2)
+            .map(|_| {
+                let token = token.clone();
+                thread::spawn(move || token.wait())
+            })
+            .collect::<Vec<_>>();
+        source.stop();
+        for waiter in waiters {
+            waiter.join().unwrap();
+        }
+        assert!(source.is_stopped());
+        assert!(token.is_stopped());
+        assert!(token.wait_timeout(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn callbacks_are_invoked_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let source = StopSource::new();
+        let token = source.token();
+        for _ in 0..2 {
+            let calls = calls.clone();
+            token.on_stop(move || {
+                calls.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        source.stop();
+        source.stop();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Registered after the stop.
+        let calls_cpy = calls.clone();
+        token.on_stop(move || {
+            calls_cpy.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,382 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for tuning the detector on audio with annotated beats.
+//!
+//! [`sweep`] runs the detector with each combination of a
+//! [`ParameterGrid`] and compares the detected beats with the annotated
+//! ones, e.g., of a [fixture]. The best combination is the one with the
+//! highest F-measure, the common metric of beat tracking evaluations.
+//!
+//! [fixture]: crate::fixture
+
+use crate::audio_io::memory::MemorySource;
+use crate::driver::run_detector_with_updates;
+use crate::stop::StopSource;
+use beat_detector_core::{BeatDetector, BiquadCoefficients, CustomFilter, EnvelopeConfig};
+use core::time::Duration;
+use std::vec;
+use std::vec::Vec;
+
+/// Default for the `tolerance` of [`Evaluation::new`] and [`sweep`]. This is
+/// the tolerance window of the MIREX beat tracking evaluation.
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_millis(70);
+
+/// Comparison of detected beats with annotated beats.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Evaluation {
+    /// Detected beats that match an annotated beat.
+    pub true_positives: usize,
+    /// Detected beats that don't match any annotated beat.
+    pub false_positives: usize,
+    /// Annotated beats that weren't detected.
+    pub false_negatives: usize,
+}
+
+impl Evaluation {
+    /// Compares the times of the detected beats with the times of the
+    /// annotated beats, both in ascending order. Each annotated beat matches
+    /// at most one detected beat that is at most `tolerance` away.
+    pub fn new(detected: &[Duration], annotated: &[Duration], tolerance: Duration) -> Self {
+        let mut evaluation = Self::default();
+        let (mut detected, mut annotated) =
+            (detected.iter().peekable(), annotated.iter().peekable());
+        while let (Some(&&detected_beat), Some(&&annotated_beat)) =
+            (detected.peek(), annotated.peek())
+        {
+            if detected_beat.max(annotated_beat) - detected_beat.min(annotated_beat) <= tolerance {
+                evaluation.true_positives += 1;
+                detected.next();
+                annotated.next();
+            } else if detected_beat < annotated_beat {
+                evaluation.false_positives += 1;
+                detected.next();
+            } else {
+                evaluation.false_negatives += 1;
+                annotated.next();
+            }
+        }
+        evaluation.false_positives += detected.count();
+        evaluation.false_negatives += annotated.count();
+        evaluation
+    }
+
+    /// Returns the share of the detected beats that match an annotated beat.
+    pub fn precision(&self) -> f32 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives,
+        )
+    }
+
+    /// Returns the share of the annotated beats that were detected.
+    pub fn recall(&self) -> f32 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_negatives,
+        )
+    }
+
+    /// Returns the harmonic mean of [`Self::precision`] and [`Self::recall`],
+    /// from `0.0` to `1.0`.
+    pub fn f_measure(&self) -> f32 {
+        ratio(
+            2 * self.true_positives,
+            2 * self.true_positives + self.false_positives + self.false_negatives,
+        )
+    }
+}
+
+impl core::ops::AddAssign for Evaluation {
+    fn add_assign(&mut self, other: Self) {
+        self.true_positives += other.true_positives;
+        self.false_positives += other.false_positives;
+        self.false_negatives += other.false_negatives;
+    }
+}
+
+/// Returns `numerator / denominator`, which is `0.0` for an empty
+/// denominator.
+fn ratio(numerator: usize, denominator: usize) -> f32 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f32 / denominator as f32
+    }
+}
+
+/// Mono audio together with the times of its annotated beats.
+#[derive(Debug, Copy, Clone)]
+pub struct AnnotatedAudio<'a> {
+    /// The mono samples.
+    pub samples: &'a [i16],
+    /// Sampling rate of the samples in Hz.
+    pub sampling_rate: f32,
+    /// Times of the annotated beats in ascending order, such as
+    /// [`Fixture::beat_times`].
+    ///
+    /// [`Fixture::beat_times`]: crate::fixture::Fixture::beat_times
+    pub beats: &'a [Duration],
+}
+
+/// One combination of the parameters of a [`ParameterGrid`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SweepParameters {
+    /// Cutoff frequency of the lowpass filter, see
+    /// [`BiquadCoefficients::lowpass`].
+    pub lowpass_cutoff_hz: f32,
+    /// The sensitivity, see [`EnvelopeConfig::max_peak_to_median_min_ratio`].
+    /// Lower values detect more beats.
+    pub max_peak_to_median_min_ratio: f32,
+    /// Minimum time between two beats. Beats that follow a beat earlier are
+    /// suppressed, see [`Self::after_beat`].
+    pub min_beat_distance: Duration,
+}
+
+impl SweepParameters {
+    /// Applies the parameters to a detector that was created with a lowpass
+    /// filter and the sampling rate of the audio.
+    ///
+    /// # Panics
+    /// Panics if the cutoff frequency isn't below the Nyquist frequency.
+    pub fn configure(&self, detector: &mut BeatDetector) {
+        let lowpass = BiquadCoefficients::lowpass(
+            detector.original_sampling_frequency(),
+            self.lowpass_cutoff_hz,
+        )
+        .expect("The cutoff frequency should be below the Nyquist frequency");
+        detector.set_custom_filter(Some(
+            CustomFilter::new(&[lowpass]).expect("A Butterworth lowpass filter should be stable"),
+        ));
+        detector.set_envelope_config(EnvelopeConfig {
+            max_peak_to_median_min_ratio: self.max_peak_to_median_min_ratio,
+            ..*detector.envelope_config()
+        });
+    }
+
+    /// Enforces [`Self::min_beat_distance`] after the detector reported a beat
+    /// at `beat_time`, by [muting](BeatDetector::mute_for) it until then. Call
+    /// this after each beat, like [`sweep`] does.
+    pub fn after_beat(&self, detector: &mut BeatDetector, beat_time: Duration) {
+        let elapsed = detector.passed_time().saturating_sub(beat_time);
+        detector.mute_for(self.min_beat_distance.saturating_sub(elapsed));
+    }
+}
+
+/// The values of each parameter that [`sweep`] tries. All combinations are
+/// tried, so the duration of the sweep grows with the product of the amount
+/// of values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterGrid {
+    /// Values of [`SweepParameters::lowpass_cutoff_hz`].
+    pub lowpass_cutoffs_hz: Vec<f32>,
+    /// Values of [`SweepParameters::max_peak_to_median_min_ratio`].
+    pub max_peak_to_median_min_ratios: Vec<f32>,
+    /// Values of [`SweepParameters::min_beat_distance`].
+    pub min_beat_distances: Vec<Duration>,
+}
+
+impl ParameterGrid {
+    /// Returns all combinations of the values.
+    pub fn parameters(&self) -> impl Iterator<Item = SweepParameters> + '_ {
+        self.lowpass_cutoffs_hz
+            .iter()
+            .flat_map(move |&lowpass_cutoff_hz| {
+                self.max_peak_to_median_min_ratios.iter().flat_map(
+                    move |&max_peak_to_median_min_ratio| {
+                        self.min_beat_distances
+                            .iter()
+                            .map(move |&min_beat_distance| SweepParameters {
+                                lowpass_cutoff_hz,
+                                max_peak_to_median_min_ratio,
+                                min_beat_distance,
+                            })
+                    },
+                )
+            })
+    }
+}
+
+impl Default for ParameterGrid {
+    /// Values around the defaults of the detector, which suit most music.
+    fn default() -> Self {
+        Self {
+            lowpass_cutoffs_hz: vec![70.0, 95.0, 120.0, 150.0],
+            max_peak_to_median_min_ratios: vec![1.5, 2.0, 2.5, 3.0, 4.0],
+            min_beat_distances: [0, 150, 250, 350]
+                .into_iter()
+                .map(Duration::from_millis)
+                .collect(),
+        }
+    }
+}
+
+/// Result of one combination of parameters of a [`sweep`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SweepResult {
+    /// The parameters of the detector.
+    pub parameters: SweepParameters,
+    /// Comparison of the detected beats with the annotated beats of all
+    /// audio.
+    pub evaluation: Evaluation,
+}
+
+/// Runs the detector with each combination of parameters of `grid` on each
+/// annotated audio and evaluates the detected beats with the given
+/// `tolerance`, such as [`DEFAULT_TOLERANCE`].
+///
+/// Returns the results ordered by descending F-measure, i.e., the best
+/// configuration first. Ties keep the order of [`ParameterGrid::parameters`].
+pub fn sweep(
+    grid: &ParameterGrid,
+    audio: &[AnnotatedAudio<'_>],
+    tolerance: Duration,
+) -> Vec<SweepResult> {
+    let mut results = grid
+        .parameters()
+        .map(|parameters| {
+            let mut evaluation = Evaluation::default();
+            for audio in audio {
+                let detected = detect_beat_times(audio, &parameters);
+                evaluation += Evaluation::new(&detected, audio.beats, tolerance);
+            }
+            SweepResult {
+                parameters,
+                evaluation,
+            }
+        })
+        .collect::<Vec<_>>();
+    results.sort_by(|a, b| {
+        b.evaluation
+            .f_measure()
+            .total_cmp(&a.evaluation.f_measure())
+    });
+    results
+}
+
+/// Detects the times of all beats in `audio` with the given parameters.
+fn detect_beat_times(audio: &AnnotatedAudio<'_>, parameters: &SweepParameters) -> Vec<Duration> {
+    let mut detector = BeatDetector::new(audio.sampling_rate, true);
+    parameters.configure(&mut detector);
+    let source = MemorySource::new(audio.samples, audio.sampling_rate);
+    let mut beats = Vec::new();
+    run_detector_with_updates(
+        source,
+        &mut detector,
+        &StopSource::new().token(),
+        |detector, _chunk, beat| {
+            if let Some(beat) = beat {
+                parameters.after_beat(detector, beat.timestamp());
+                beats.push(beat.timestamp());
+            }
+        },
+    )
+    .expect("reading from memory should never fail");
+    beats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offline::detect_beats;
+
+    fn millis(millis: &[u64]) -> Vec<Duration> {
+        millis.iter().copied().map(Duration::from_millis).collect()
+    }
+
+    #[test]
+    fn evaluation() {
+        let annotated = millis(&[1000, 2000, 3000, 4000]);
+        let detected = millis(&[950, 1030, 2500, 3060, 4100]);
+        let evaluation = Evaluation::new(&detected, &annotated, DEFAULT_TOLERANCE);
+        assert_eq!(
+            evaluation,
+            Evaluation {
+                true_positives: 2,
+                false_positives: 3,
+                false_negatives: 2,
+            }
+        );
+        assert_eq!(evaluation.precision(), 0.4);
+        assert_eq!(evaluation.recall(), 0.5);
+        assert!((evaluation.f_measure() - 4.0 / 9.0).abs() < 1e-6);
+
+        let empty = Evaluation::new(&[], &[], DEFAULT_TOLERANCE);
+        assert_eq!(empty.f_measure(), 0.0);
+    }
+
+    /// Renders a kick drum every 500 ms, each followed by a ghost note of
+    /// half the amplitude 250 ms later. Only the kicks are annotated as beats.
+    fn kicks_with_ghost_notes(sampling_rate: f32, duration: Duration) -> (Vec<i16>, Vec<Duration>) {
+        let len = (sampling_rate * duration.as_secs_f32()) as usize;
+        let period = (sampling_rate * 0.5) as usize;
+        let hit_len = (sampling_rate * 0.2) as usize;
+        let mut samples = vec![0.0_f32; len];
+        let mut beats = Vec::new();
+        let mut start = period / 2;
+        while start + period < len {
+            beats.push(Duration::from_secs_f32(start as f32 / sampling_rate));
+            for (offset, amplitude) in [(0, 20000.0), (period / 2, 10000.0)] {
+                for (i, sample) in samples[start + offset..][..hit_len].iter_mut().enumerate() {
+                    let t = i as f32 / sampling_rate;
+                    *sample += amplitude
+                        * (2.0 * core::f32::consts::PI * 55.0 * t).sin()
+                        * (-t / 0.06).exp();
+                }
+            }
+            start += period;
+        }
+        let samples = samples.iter().map(|&sample| sample as i16).collect();
+        (samples, beats)
+    }
+
+    #[test]
+    fn sweep_finds_the_annotated_beats() {
+        let sampling_rate = 44100.0;
+        let (samples, beats) = kicks_with_ghost_notes(sampling_rate, Duration::from_secs(10));
+        // The default configuration also reports the ghost notes.
+        let default_beats = detect_beats(&samples, sampling_rate, true)
+            .iter()
+            .map(|beat| beat.timestamp())
+            .collect::<Vec<_>>();
+        let default_evaluation = Evaluation::new(&default_beats, &beats, DEFAULT_TOLERANCE);
+        assert_eq!(default_evaluation.false_negatives, 0);
+        assert!(default_evaluation.false_positives > 10);
+
+        let audio = AnnotatedAudio {
+            samples: &samples,
+            sampling_rate,
+            beats: &beats,
+        };
+        let grid = ParameterGrid::default();
+        let results = sweep(&grid, &[audio], DEFAULT_TOLERANCE);
+        assert_eq!(results.len(), grid.parameters().count());
+        assert!(results
+            .windows(2)
+            .all(|w| w[0].evaluation.f_measure() >= w[1].evaluation.f_measure()));
+        // Only a minimum distance between beats suppresses the ghost notes.
+        assert_eq!(results[0].evaluation.f_measure(), 1.0);
+        assert!(results[0].parameters.min_beat_distance > Duration::from_millis(250));
+        assert!(results.last().unwrap().evaluation.f_measure() < default_evaluation.f_measure());
+    }
+}
@@ -0,0 +1,71 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+use beat_detector_core::util::stereo_to_mono;
+use std::path::{Path, PathBuf};
+
+/// Returns the path of a file in the `res` directory of the workspace.
+pub fn res(file: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../res")
+        .join(file)
+}
+
+/// Reads a WAV file to mono audio. Returns the samples as mono audio.
+/// Additionally, it returns the sampling rate of the file.
+fn read_wav_to_mono(file: impl AsRef<Path>) -> (Vec<i16>, hound::WavSpec) {
+    let mut reader = hound::WavReader::open(file).unwrap();
+    let header = reader.spec();
+    let data = reader
+        .samples::<i16>()
+        .map(|s| s.unwrap())
+        .collect::<Vec<_>>();
+    match header.channels {
+        1 => (data, header),
+        2 => {
+            let data = data
+                .chunks_exact(2)
+                .map(|lr| stereo_to_mono(lr[0], lr[1]))
+                .collect();
+            (data, header)
+        }
+        _ => panic!("unsupported format!"),
+    }
+}
+
+/// Accessor to the samples of the `res` directory that the tests use.
+pub mod samples {
+    use super::*;
+
+    /// Returns the mono samples of the holiday sample (long version)
+    /// together with the WAV header.
+    pub fn holiday_long() -> (Vec<i16>, hound::WavSpec) {
+        read_wav_to_mono(res("holiday_lowpassed--long.wav"))
+    }
+
+    /// Returns the mono samples of the holiday sample (single beat) together
+    /// with the WAV header.
+    pub fn holiday_single_beat() -> (Vec<i16>, hound::WavSpec) {
+        read_wav_to_mono(res("holiday_lowpassed--single-beat.wav"))
+    }
+}
@@ -0,0 +1,144 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Module for raising the scheduling priority of the audio analysis thread.
+//!
+//! Audio-thread starvation is the most common cause of missed beats on
+//! single-board computers, such as the Raspberry Pi, which run other
+//! workloads (e.g., LED drivers) next to the beat detection.
+//!
+//! Nothing in this crate raises the priority on its own. With the `recording`
+//! feature, `recording::start_detector_thread_with_realtime_priority` raises
+//! the priority of the audio thread it records on.
+
+use core::fmt::{Display, Formatter};
+
+/// Errors of [`set_current_thread_realtime_priority`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ThreadPriorityError {
+    /// The platform is not supported.
+    Unsupported,
+    /// The operating system refused the request. Contains the OS error code.
+    /// On Linux, this typically is `EPERM`, if the process is neither
+    /// privileged nor has a sufficient `RLIMIT_RTPRIO`.
+    Os(i32),
+}
+
+impl Display for ThreadPriorityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!("{:?}", self))
+    }
+}
+
+impl std::error::Error for ThreadPriorityError {}
+
+/// Raises the priority of the current thread to realtime (Linux and macOS:
+/// `SCHED_FIFO`) or time-critical (Windows) priority.
+///
+/// This should be called from the thread that runs the beat detection.
+pub fn set_current_thread_realtime_priority() -> Result<(), ThreadPriorityError> {
+    platform::set_current_thread_realtime_priority()
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::ThreadPriorityError;
+
+    pub fn set_current_thread_realtime_priority() -> Result<(), ThreadPriorityError> {
+        // SAFETY: These functions have no memory safety preconditions and
+        // `param` is a valid, initialized struct.
+        unsafe {
+            let min = libc::sched_get_priority_min(libc::SCHED_FIFO);
+            let max = libc::sched_get_priority_max(libc::SCHED_FIFO);
+            if min < 0 || max < 0 {
+                let errno = std::io::Error::last_os_error().raw_os_error();
+                return Err(ThreadPriorityError::Os(errno.unwrap_or(-1)));
+            }
+
+            // High, but leave room for the audio server and the kernel.
+            let param = libc::sched_param {
+                sched_priority: min + (max - min) / 2,
+            };
+            match libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) {
+                0 => Ok(()),
+                err => Err(ThreadPriorityError::Os(err)),
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::ThreadPriorityError;
+
+    const THREAD_PRIORITY_TIME_CRITICAL: i32 = 15;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> isize;
+        fn SetThreadPriority(thread: isize, priority: i32) -> i32;
+        fn GetLastError() -> u32;
+    }
+
+    pub fn set_current_thread_realtime_priority() -> Result<(), ThreadPriorityError> {
+        // SAFETY: GetCurrentThread returns a pseudo handle that is always
+        // valid for the calling thread.
+        unsafe {
+            if SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL) != 0 {
+                Ok(())
+            } else {
+                Err(ThreadPriorityError::Os(GetLastError() as i32))
+            }
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use super::ThreadPriorityError;
+
+    pub fn set_current_thread_realtime_priority() -> Result<(), ThreadPriorityError> {
+        Err(ThreadPriorityError::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether this succeeds depends on the privileges of the test runner, so
+    /// we just check that the call doesn't crash and returns a plausible
+    /// result.
+    #[test]
+    fn set_current_thread_realtime_priority_works_or_fails_gracefully() {
+        std::thread::spawn(|| match set_current_thread_realtime_priority() {
+            Ok(()) => {}
+            Err(ThreadPriorityError::Os(code)) => assert_ne!(code, 0),
+            Err(ThreadPriorityError::Unsupported) => panic!("should be supported"),
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for aligning beats with the frames of a video, such as for video
+//! overlays or for editors that sync cuts to the beats.
+//!
+//! [`FrameAligner`] converts timestamps into frame numbers and timecodes,
+//! with the [`FrameRate`] and [`Timecode`] of the core crate.
+//! [`write_markers`] exports beats as markers that video editors import.
+//!
+//! ## Example
+//! ```rust
+//! use beat_detector_core::FrameRate;
+//! use beat_detector_io::offline::detect_beats;
+//! use beat_detector_io::video::{write_markers, FrameAligner, MarkerFormat};
+//!
+//! let samples = [0_i16; 44100];
+//! let beats = detect_beats(&samples, 44100.0, true);
+//! let aligner = FrameAligner::new(FrameRate::FPS_25);
+//! let mut csv = Vec::new();
+//! write_markers(&mut csv, &beats, &aligner, MarkerFormat::Csv).unwrap();
+//! ```
+
+use beat_detector_core::{BeatInfo, FrameRate, Timecode};
+use core::time::Duration;
+use std::io::Write;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// How a timestamp between two frames is mapped to a frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FrameRounding {
+    /// The frame that begins closest to the timestamp.
+    #[default]
+    Nearest,
+    /// The frame that is visible at the timestamp.
+    Down,
+    /// The first frame that begins at or after the timestamp, so that an
+    /// overlay never shows up before the beat is audible.
+    Up,
+}
+
+/// Converts timestamps of the audio, such as [`BeatInfo::timestamp`], into
+/// frames of a video.
+///
+/// The audio may begin later than the video, such as when a song is placed
+/// on the timeline of an editor. [`Self::with_offset`] sets where the audio
+/// begins in the video.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FrameAligner {
+    frame_rate: FrameRate,
+    rounding: FrameRounding,
+    offset: Duration,
+}
+
+impl FrameAligner {
+    /// Creates a new aligner for the given frame rate that rounds to the
+    /// nearest frame and assumes that the audio begins with the video.
+    pub const fn new(frame_rate: FrameRate) -> Self {
+        Self {
+            frame_rate,
+            rounding: FrameRounding::Nearest,
+            offset: Duration::ZERO,
+        }
+    }
+
+    /// Uses the given rounding policy.
+    pub const fn with_rounding(mut self, rounding: FrameRounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Sets where the audio begins in the video.
+    pub const fn with_offset(mut self, offset: Duration) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Returns the frame rate.
+    pub const fn frame_rate(&self) -> FrameRate {
+        self.frame_rate
+    }
+
+    /// Returns the rounding policy.
+    pub const fn rounding(&self) -> FrameRounding {
+        self.rounding
+    }
+
+    /// Returns where the audio begins in the video.
+    pub const fn offset(&self) -> Duration {
+        self.offset
+    }
+
+    /// Returns the frame of the video at the given timestamp of the audio.
+    pub fn frame(&self, time: Duration) -> u64 {
+        let nanos = (time + self.offset).as_nanos() * self.frame_rate.numerator() as u128;
+        let divisor = self.frame_rate.denominator() as u128 * NANOS_PER_SECOND;
+        let frame = match self.rounding {
+            FrameRounding::Nearest => (nanos + divisor / 2) / divisor,
+            FrameRounding::Down => nanos / divisor,
+            FrameRounding::Up => nanos.div_ceil(divisor),
+        };
+        frame as u64
+    }
+
+    /// Returns the frame of the video in which the given beat is audible.
+    pub fn beat_frame(&self, beat: &BeatInfo) -> u64 {
+        self.frame(beat.timestamp())
+    }
+
+    /// Returns when the given frame begins in the video.
+    pub const fn frame_time(&self, frame: u64) -> Duration {
+        let nanos = frame as u128 * self.frame_rate.denominator() as u128 * NANOS_PER_SECOND
+            / self.frame_rate.numerator() as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    /// Returns the timecode of the given frame, with drop-frame counting if
+    /// the frame rate has it. Wraps around after 24 hours.
+    pub const fn timecode(&self, frame: u64) -> Timecode {
+        Timecode::from_frame_count(frame, self.frame_rate)
+    }
+}
+
+/// File format of [`write_markers`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MarkerFormat {
+    /// One line per beat with the number of the beat, the timestamp in
+    /// seconds, the frame, and the timecode, after a header line.
+    Csv,
+    /// Edit decision list in the CMX 3600 format with one single-frame
+    /// marker per beat, as DaVinci Resolve imports it as timeline markers.
+    Edl,
+}
+
+/// Writes the beats as markers in the given format, with their frames
+/// calculated by the given aligner.
+pub fn write_markers<'a>(
+    mut writer: impl Write,
+    beats: impl IntoIterator<Item = &'a BeatInfo>,
+    aligner: &FrameAligner,
+    format: MarkerFormat,
+) -> std::io::Result<()> {
+    match format {
+        MarkerFormat::Csv => writeln!(writer, "beat,time_s,frame,timecode")?,
+        MarkerFormat::Edl => {
+            writeln!(writer, "TITLE: Beats")?;
+            if aligner.frame_rate().drop_frame() {
+                writeln!(writer, "FCM: DROP FRAME")?;
+            } else {
+                writeln!(writer, "FCM: NON-DROP FRAME")?;
+            }
+            writeln!(writer)?;
+        }
+    }
+    for (number, beat) in (1..).zip(beats) {
+        let frame = aligner.beat_frame(beat);
+        let timecode = aligner.timecode(frame);
+        match format {
+            MarkerFormat::Csv => writeln!(
+                writer,
+                "{number},{:.6},{frame},{timecode}",
+                beat.timestamp().as_secs_f64()
+            )?,
+            MarkerFormat::Edl => {
+                let end = aligner.timecode(frame + 1);
+                writeln!(
+                    writer,
+                    "{number:03}  001      V     C        {timecode} {end} {timecode} {end}"
+                )?;
+                writeln!(writer, " |C:ResolveColorBlue |M:Beat {number} |D:1")?;
+                writeln!(writer)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use beat_detector_core::{EnvelopeInfo, SampleInfo, SourcePosition};
+
+    fn beat_at(time: Duration) -> BeatInfo {
+        let mut info = SampleInfo::default();
+        info.source = SourcePosition::from_time(time, 44100.0);
+        let mut beat = EnvelopeInfo::default();
+        beat.max = info;
+        beat
+    }
+
+    #[test]
+    fn frames() {
+        let aligner = FrameAligner::new(FrameRate::FPS_25);
+        assert_eq!(aligner.frame(Duration::from_millis(0)), 0);
+        assert_eq!(aligner.frame(Duration::from_millis(19)), 0);
+        assert_eq!(aligner.frame(Duration::from_millis(20)), 1);
+        assert_eq!(aligner.frame(Duration::from_secs(1)), 25);
+
+        let down = aligner.with_rounding(FrameRounding::Down);
+        assert_eq!(down.frame(Duration::from_millis(39)), 0);
+        let up = aligner.with_rounding(FrameRounding::Up);
+        assert_eq!(up.frame(Duration::from_millis(1)), 1);
+        assert_eq!(up.frame(Duration::from_millis(40)), 1);
+
+        let offset = aligner.with_offset(Duration::from_secs(10));
+        assert_eq!(offset.frame(Duration::from_secs(1)), 275);
+        assert_eq!(offset.frame_time(275), Duration::from_secs(11));
+    }
+
+    #[test]
+    fn ntsc_frames_are_exact() {
+        let aligner = FrameAligner::new(FrameRate::FPS_29_97);
+        // An hour of NTSC video has 107892 frames, not 108000.
+        assert_eq!(aligner.frame(Duration::from_secs(3600)), 107_892);
+        assert_eq!(aligner.frame(aligner.frame_time(107_892)), 107_892);
+        assert_eq!(aligner.timecode(107_892).to_string(), "00:59:56:12");
+        let drop_frame = FrameAligner::new(FrameRate::FPS_29_97_DROP_FRAME);
+        assert_eq!(drop_frame.timecode(107_892).to_string(), "01:00:00;00");
+    }
+
+    #[test]
+    fn csv_and_edl() {
+        let beats = [
+            beat_at(Duration::from_millis(480)),
+            beat_at(Duration::from_millis(3_999)),
+        ];
+        let aligner = FrameAligner::new(FrameRate::FPS_25).with_offset(Duration::from_secs(3600));
+
+        let mut csv = Vec::new();
+        write_markers(&mut csv, &beats, &aligner, MarkerFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "beat,time_s,frame,timecode\n\
+             1,0.480000,90012,01:00:00:12\n\
+             2,3.999000,90100,01:00:04:00\n"
+        );
+
+        let mut edl = Vec::new();
+        write_markers(&mut edl, &beats, &aligner, MarkerFormat::Edl).unwrap();
+        let edl = String::from_utf8(edl).unwrap();
+        assert!(edl.starts_with("TITLE: Beats\nFCM: NON-DROP FRAME\n\n"));
+        assert!(edl.contains(
+            "001  001      V     C        01:00:00:12 01:00:00:13 01:00:00:12 01:00:00:13\n \
+             |C:ResolveColorBlue |M:Beat 1 |D:1\n"
+        ));
+        assert!(edl.contains("002  001      V     C        01:00:04:00 01:00:04:01"));
+    }
+}
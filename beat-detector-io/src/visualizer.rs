@@ -0,0 +1,272 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Module for [`FeatureFeed`] and [`VisualFeatures`], which hand the audio
+//! features from the detector thread to the frame loop of a visualizer.
+//!
+//! Most users of this crate build visualizers with creative coding
+//! frameworks. Their app loops run at the frame rate of the display, whereas
+//! the detection runs at the pace of the audio. The [`FeatureFeed`] lives in
+//! the detector thread and publishes the features after each chunk of audio.
+//! The [`VisualFeatures`] live in the app and return the latest
+//! [`AudioFrame`] on each frame, extrapolated to the current time, so that
+//! the animation stays smooth.
+//!
+//! The module doesn't depend on any framework, as the app loops of the
+//! frameworks differ and pinning one, such as nannou, would tie the releases
+//! of this crate to it. There is no nannou integration. With nannou, the
+//! [`VisualFeatures`] are part of the model and the update function calls
+//! [`VisualFeatures::frame`]. The `visualizer` example of the repository is a
+//! maintained, complete visualizer on top of `minifb`.
+//!
+//! ## Example
+//! ```rust
+//! use beat_detector_core::{BeatDetector, TempoConfig};
+//! use beat_detector_io::audio_io::memory::MemorySource;
+//! use beat_detector_io::stop::StopSource;
+//! use beat_detector_io::visualizer::FeatureFeed;
+//! use std::time::Duration;
+//!
+//! let samples = [0_i16; 44100];
+//! let (mut feed, features) = FeatureFeed::new(TempoConfig::default());
+//! let stop = StopSource::new();
+//! let token = stop.token();
+//! std::thread::scope(|scope| {
+//!     // The detector thread, typically with a `DeviceSource`.
+//!     let detector_thread = scope.spawn(|| {
+//!         let mut detector = BeatDetector::new(44100.0, true);
+//!         feed.run(MemorySource::new(&samples, 44100.0), &mut detector, &token)
+//!     });
+//!     // The frame loop of the app, here only for a few frames at 60 FPS.
+//!     let mut brightness = 0.0;
+//!     for _ in 0..10 {
+//!         let frame = features.frame();
+//!         brightness = frame.uniforms.energy;
+//!         std::thread::sleep(Duration::from_millis(16));
+//!     }
+//!     assert!((0.0..=1.0).contains(&brightness));
+//!     stop.stop();
+//!     detector_thread.join().unwrap().unwrap();
+//! });
+//! ```
+
+use crate::audio_io::{SampleSource, SourceError};
+use crate::driver::run_detector_with_updates;
+use crate::stop::StopToken;
+use beat_detector_core::{
+    BeatDetectorConst, BeatInfo, BeatUniforms, TempoConfig, TempoEstimator, UniformProvider,
+    DEFAULT_ENERGY_RELEASE,
+};
+use core::time::Duration;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+/// The features are extrapolated at most this far, so that the animation
+/// freezes if the audio stream stalls.
+const MAX_EXTRAPOLATION: Duration = Duration::from_millis(500);
+
+/// The audio features for one frame of a visualizer.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct AudioFrame {
+    /// Beat phase, bar phase, energy, and tempo, see [`BeatUniforms`].
+    pub uniforms: BeatUniforms,
+    /// Amount of beats detected so far.
+    pub beat_count: u64,
+    /// The latest detected beat.
+    pub latest_beat: Option<BeatInfo>,
+    /// Amount of audio the detector consumed.
+    pub audio_time: Duration,
+}
+
+impl AudioFrame {
+    /// Returns whether a beat was detected since the previous frame, e.g., to
+    /// trigger a flash.
+    pub const fn has_new_beat(&self, previous: &Self) -> bool {
+        self.beat_count != previous.beat_count
+    }
+}
+
+/// State that is shared between the [`FeatureFeed`] and the
+/// [`VisualFeatures`].
+#[derive(Debug, Copy, Clone, Default)]
+struct Shared {
+    frame: AudioFrame,
+    /// Time of the latest update of the frame.
+    updated: Option<Instant>,
+    /// Beats since the first beat at the time of the update, if known.
+    beats: Option<f64>,
+    beats_per_bar: u8,
+}
+
+/// Publishes the audio features after each update of the detector, see the
+/// [module documentation](self).
+#[derive(Debug)]
+pub struct FeatureFeed {
+    shared: Arc<Mutex<Shared>>,
+    tempo: TempoEstimator,
+    uniforms: UniformProvider,
+}
+
+impl FeatureFeed {
+    /// Creates a new feed with the given configuration of the tempo
+    /// estimation, and the corresponding handle for the visualizer.
+    pub fn new(config: TempoConfig) -> (Self, VisualFeatures) {
+        let shared = Arc::new(Mutex::new(Shared {
+            beats_per_bar: config.beats_per_bar,
+            ..Shared::default()
+        }));
+        let feed = Self {
+            shared: shared.clone(),
+            tempo: TempoEstimator::new(config),
+            uniforms: UniformProvider::new(DEFAULT_ENERGY_RELEASE),
+        };
+        (feed, VisualFeatures { shared })
+    }
+
+    /// Supposed to be called after each update of the detector with the beat
+    /// it reported, if any.
+    pub fn update<const N: usize, const D: usize, const P: usize>(
+        &mut self,
+        detector: &BeatDetectorConst<N, D, P>,
+        beat: Option<&BeatInfo>,
+    ) {
+        if let Some(beat) = beat {
+            self.tempo.update(beat.timestamp());
+        }
+        let now = detector.passed_time();
+        let uniforms = self.uniforms.update(detector, &self.tempo, now);
+
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.frame.uniforms = uniforms;
+        shared.frame.audio_time = now;
+        if let Some(beat) = beat {
+            shared.frame.beat_count += 1;
+            shared.frame.latest_beat = Some(*beat);
+        }
+        shared.beats = self.tempo.beats_elapsed(now);
+        shared.updated = Some(Instant::now());
+    }
+
+    /// Feeds all samples of `source` into `detector` and publishes the
+    /// features after each chunk, like [`run_detector_until`]. Typically runs
+    /// in a dedicated thread.
+    ///
+    /// [`run_detector_until`]: crate::driver::run_detector_until
+    pub fn run<const N: usize, const D: usize, const P: usize>(
+        &mut self,
+        source: impl SampleSource,
+        detector: &mut BeatDetectorConst<N, D, P>,
+        stop: &StopToken,
+    ) -> Result<(), SourceError> {
+        run_detector_with_updates(source, detector, stop, |detector, _chunk, beat| {
+            self.update(detector, beat.as_ref());
+        })
+        .map(|_is_exhausted| ())
+    }
+}
+
+/// Returns the latest audio features in the frame loop of a visualizer, see
+/// the [module documentation](self). Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct VisualFeatures {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl VisualFeatures {
+    /// Returns the features for the current frame. The phases and the energy
+    /// are extrapolated from the latest update of the feed to now.
+    pub fn frame(&self) -> AudioFrame {
+        let shared = *self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        let elapsed = shared
+            .updated
+            .map_or(Duration::ZERO, |updated| updated.elapsed());
+        Self::extrapolate(&shared, elapsed)
+    }
+
+    fn extrapolate(shared: &Shared, elapsed: Duration) -> AudioFrame {
+        let elapsed = elapsed.min(MAX_EXTRAPOLATION).as_secs_f64();
+        let mut frame = shared.frame;
+        let uniforms = &mut frame.uniforms;
+        if let Some(beats) = shared.beats {
+            let beats = beats + elapsed * f64::from(uniforms.bpm) / 60.0;
+            uniforms.beat_phase = beats.fract() as f32;
+            uniforms.bar_phase = (beats / f64::from(shared.beats_per_bar)).fract() as f32;
+        }
+        uniforms.energy *= (-elapsed / DEFAULT_ENERGY_RELEASE.as_secs_f64()).exp() as f32;
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_io::memory::MemorySource;
+    use crate::stop::StopSource;
+    use crate::test_utils;
+    use beat_detector_core::BeatDetector;
+
+    #[test]
+    fn feed() {
+        let (samples, header) = test_utils::samples::holiday_long();
+        let (mut feed, features) = FeatureFeed::new(TempoConfig::default());
+        assert_eq!(features.frame(), AudioFrame::default());
+
+        let mut detector = BeatDetector::new(header.sample_rate as f32, true);
+        let source = MemorySource::new(&samples, header.sample_rate as f32);
+        feed.run(source, &mut detector, &StopSource::new().token())
+            .unwrap();
+
+        let frame = features.frame();
+        assert!(frame.beat_count > 5, "{frame:?}");
+        assert!(frame.has_new_beat(&AudioFrame::default()));
+        assert!(frame.uniforms.bpm > 0.0);
+        assert_eq!(frame.audio_time, detector.passed_time());
+    }
+
+    #[test]
+    fn extrapolation() {
+        let shared = Shared {
+            frame: AudioFrame {
+                uniforms: BeatUniforms {
+                    beat_phase: 0.0,
+                    bar_phase: 0.0,
+                    energy: 1.0,
+                    bpm: 120.0,
+                },
+                ..AudioFrame::default()
+            },
+            updated: None,
+            beats: Some(4.0),
+            beats_per_bar: 4,
+        };
+        let frame = VisualFeatures::extrapolate(&shared, Duration::from_millis(250));
+        assert!((frame.uniforms.beat_phase - 0.5).abs() < 1e-4);
+        assert!((frame.uniforms.bar_phase - 0.125).abs() < 1e-4);
+        assert!(frame.uniforms.energy < 0.5);
+
+        // Stalled audio.
+        let frame = VisualFeatures::extrapolate(&shared, Duration::from_secs(10));
+        assert!((frame.uniforms.beat_phase - 0.0).abs() < 1e-4);
+    }
+}
@@ -3,7 +3,10 @@
 //!
 //! To run bench these, run `$ cargo bench "convert samples"`
 
-use beat_detector::util::{f32_sample_to_i16, i16_sample_to_f32, stereo_to_mono};
+use beat_detector::util::{
+    f32_sample_to_i16, f32_to_i16_unchecked, i16_sample_to_f32, saturating_f32_to_i16,
+    stereo_to_mono,
+};
 use criterion::{criterion_group, criterion_main, Criterion};
 use itertools::Itertools;
 use std::hint::black_box;
@@ -64,6 +67,43 @@ fn criterion_benchmark(c: &mut Criterion) {
         },
     );
 
+    // Typical output of the lowpass filter, which is in the value range of i16.
+    let filter_output = samples_f32
+        .iter()
+        .map(|s| (s * 2.0 - 1.0) * i16::MAX as f32)
+        .collect::<Vec<_>>();
+
+    c.bench_function(
+        &format!("{sample_count} convert samples (filter output to i16 (saturating))"),
+        |b| {
+            b.iter(|| {
+                let _res = black_box(
+                    filter_output
+                        .iter()
+                        .copied()
+                        .map(|s| saturating_f32_to_i16(black_box(s)))
+                        .collect::<Vec<_>>(),
+                );
+            })
+        },
+    );
+
+    c.bench_function(
+        &format!("{sample_count} convert samples (filter output to i16 (unchecked))"),
+        |b| {
+            b.iter(|| {
+                let _res = black_box(
+                    filter_output
+                        .iter()
+                        .copied()
+                        // SAFETY: All values are in range.
+                        .map(|s| unsafe { f32_to_i16_unchecked(black_box(s)) })
+                        .collect::<Vec<_>>(),
+                );
+            })
+        },
+    );
+
     c.bench_function(
         &format!("{sample_count} convert samples (i16 stereo to mono)"),
         |b| {
@@ -2,13 +2,26 @@
 
 echo "checks that this builds on std+no_std + that all tests run"
 
-cargo build --all-targets # build works
-cargo test --all-targets # tests work
+cargo build --workspace --all-targets # build works
+cargo test --workspace --all-targets # tests work
+cargo test -p beat-detector-core --no-default-features # tests work without float
 # install some no_std target
 rustup target add thumbv7em-none-eabihf
 # test no_std-build
 RUSTFLAGS="-C target-cpu=" cargo build --no-default-features --target thumbv7em-none-eabihf
+RUSTFLAGS="-C target-cpu=" cargo build --no-default-features --features float --target thumbv7em-none-eabihf
+RUSTFLAGS="-C target-cpu=" cargo build -p beat-detector-core --target thumbv7em-none-eabihf
+# worked RP2040 example; its own config overrides target-cpu=native
+rustup target add thumbv6m-none-eabi
+(cd examples/rp2040 && cargo build --target thumbv6m-none-eabi)
+# test public API with every feature combination
+cargo run --example features-check --no-default-features
+cargo run --example features-check --no-default-features --features float
+cargo run --example features-check --no-default-features --features std
+cargo run --example features-check --no-default-features --features recording
+cargo run --example features-check --no-default-features --features audio-file
+cargo run --example features-check --no-default-features --features audio-net
 
-cargo doc
-cargo fmt -- --check
-cargo clippy --all-targets
+cargo doc --workspace
+cargo fmt --all -- --check
+cargo clippy --workspace --all-targets
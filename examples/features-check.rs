@@ -0,0 +1,208 @@
+//! Smoke test that exercises the public API that is available with the
+//! enabled cargo features. It is supposed to be built and run with every
+//! feature combination, so that broken feature gating is noticed before a
+//! release:
+//!
+//! - `cargo run --example features-check --no-default-features`
+//! - `cargo run --example features-check --no-default-features --features float`
+//! - `cargo run --example features-check --no-default-features --features std`
+//! - `cargo run --example features-check --no-default-features --features recording`
+//! - `cargo run --example features-check --no-default-features --features audio-file`
+//! - `cargo run --example features-check --no-default-features --features audio-net`
+//! - `cargo run --example features-check --no-default-features --features room-sync`
+//! - `cargo run --example features-check --no-default-features --features visualizer`
+
+use beat_detector::util::stereo_to_mono;
+use beat_detector::EnergyBeatDetector;
+
+const SAMPLING_RATE: f32 = 44100.0;
+
+/// Returns the mono samples of a real-world recording with a few beats.
+fn samples() -> Vec<i16> {
+    let mut reader = hound::WavReader::open("res/holiday_lowpassed--long.wav").unwrap();
+    assert_eq!(reader.spec().sample_rate, SAMPLING_RATE as u32);
+    let channels = reader.spec().channels as usize;
+    let data = reader
+        .samples::<i16>()
+        .map(|s| s.unwrap())
+        .collect::<Vec<_>>();
+    match channels {
+        1 => data,
+        2 => data
+            .chunks(2)
+            .map(|lr| stereo_to_mono(lr[0], lr[1]))
+            .collect(),
+        _ => panic!("unsupported channel count"),
+    }
+}
+
+fn check_integer(samples: &[i16]) {
+    let mut detector = EnergyBeatDetector::new(SAMPLING_RATE as u32);
+    let beats = samples
+        .chunks(1024)
+        .filter_map(|chunk| detector.update_and_detect_beat(chunk.iter().copied()))
+        .count();
+    println!("integer: {beats} beats");
+    assert!(beats > 0);
+}
+
+#[cfg(feature = "float")]
+fn check_core(samples: &[i16]) {
+    use beat_detector::util::f32_sample_to_i16;
+    use beat_detector::{
+        AudioHistory, BeatDetector, HeartbeatGenerator, Mixer, MultiSourceDetector,
+        DEFAULT_SCAN_STRIDE,
+    };
+    use core::time::Duration;
+
+    let mut history = AudioHistory::new(SAMPLING_RATE);
+    history.update(samples.iter().copied());
+    assert!(history.passed_time() > Duration::ZERO);
+
+    let mut detector = BeatDetector::new(SAMPLING_RATE, true);
+    detector.set_scan_stride(DEFAULT_SCAN_STRIDE);
+    let mut heartbeat = HeartbeatGenerator::new(Duration::from_secs(1));
+    let mut beats = 0;
+    let mut heartbeats = 0;
+    for chunk in samples.chunks(1024) {
+        if detector
+            .update_and_detect_beat(chunk.iter().copied())
+            .is_some()
+        {
+            beats += 1;
+        }
+        if heartbeat.poll(&detector, true).is_some() {
+            heartbeats += 1;
+        }
+    }
+    println!("core: {beats} beats, {heartbeats} heartbeats");
+    assert!(beats > 0);
+    assert!(heartbeats > 0);
+
+    let mixer = Mixer::new([0.5, 0.5]);
+    let mixed = mixer.mix([samples[0], f32_sample_to_i16(0.5).unwrap()]);
+    let _ = mixed;
+
+    let mut multi = MultiSourceDetector::new([SAMPLING_RATE; 2], true);
+    for chunk in samples.chunks(1024) {
+        multi.update_and_detect_beats([chunk.iter().copied(), chunk.iter().copied()], |_| {});
+    }
+}
+
+#[cfg(feature = "std")]
+fn check_std(samples: &[i16]) {
+    use beat_detector::adaptive_quality::AdaptiveBeatDetector;
+    use beat_detector::drift::DriftEstimator;
+    use beat_detector::latency::{LatencyBudget, LatencyMonitor};
+    use beat_detector::BeatDetector;
+
+    let mut drift = DriftEstimator::new(SAMPLING_RATE);
+    drift.update(samples.len());
+    let _ = drift.drift_ppm();
+
+    let mut monitor = LatencyMonitor::new(LatencyBudget::realtime(), SAMPLING_RATE);
+    monitor.measure(samples.len(), || ());
+    assert_eq!(monitor.stats().chunks, 1);
+
+    let mut detector = AdaptiveBeatDetector::new(
+        BeatDetector::new(SAMPLING_RATE, true),
+        LatencyBudget::realtime(),
+        SAMPLING_RATE,
+    );
+    let beats = samples
+        .chunks(1024)
+        .filter_map(|chunk| detector.update_and_detect_beat(chunk))
+        .count();
+    println!("std: {beats} beats");
+    assert!(beats > 0);
+
+    let _ = beat_detector::thread_priority::set_current_thread_realtime_priority;
+
+    let source = beat_detector::audio_io::memory::MemorySource::new(samples, SAMPLING_RATE);
+    let beats = beat_detector::offline::detect_beats_from_source(source, true).unwrap();
+    assert_eq!(
+        beats,
+        beat_detector::offline::detect_beats(samples, SAMPLING_RATE, true)
+    );
+}
+
+#[cfg(feature = "audio-file")]
+fn check_audio_file() {
+    use beat_detector::audio_io::file::WavSource;
+    use beat_detector::offline::detect_beats_from_source;
+
+    let source = WavSource::open("res/holiday_lowpassed--long.wav").unwrap();
+    let beats = detect_beats_from_source(source, true).unwrap();
+    println!("audio-file: {} beats", beats.len());
+    assert!(!beats.is_empty());
+}
+
+#[cfg(feature = "audio-net")]
+fn check_audio_net(samples: &[i16]) {
+    use beat_detector::audio_io::net::NetSource;
+    use beat_detector::offline::detect_beats_from_source;
+
+    let bytes = samples
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+    let source = NetSource::new(bytes.as_slice(), SAMPLING_RATE);
+    let beats = detect_beats_from_source(source, true).unwrap();
+    println!("audio-net: {} beats", beats.len());
+    assert!(!beats.is_empty());
+}
+
+#[cfg(feature = "room-sync")]
+fn check_room_sync() {
+    use beat_detector::room_sync::RoomSync;
+
+    let mut sync = RoomSync::bind("127.0.0.1:0").unwrap();
+    sync.poll().unwrap();
+    println!("room-sync: bound to {}", sync.local_addr().unwrap());
+    assert!(sync.synced().is_none());
+}
+
+#[cfg(feature = "visualizer")]
+fn check_visualizer(samples: &[i16]) {
+    use beat_detector::audio_io::memory::MemorySource;
+    use beat_detector::stop::StopSource;
+    use beat_detector::visualizer::FeatureFeed;
+    use beat_detector::{BeatDetector, TempoConfig};
+
+    let (mut feed, features) = FeatureFeed::new(TempoConfig::default());
+    let mut detector = BeatDetector::new(SAMPLING_RATE, true);
+    let source = MemorySource::new(samples, SAMPLING_RATE);
+    feed.run(source, &mut detector, &StopSource::new().token())
+        .unwrap();
+    let frame = features.frame();
+    println!("visualizer: {} beats", frame.beat_count);
+    assert!(frame.beat_count > 0);
+}
+
+#[cfg(feature = "recording")]
+fn check_recording() {
+    // Only reference the API. There might not be an audio device.
+    let _start = |device| beat_detector::recording::start_detector_thread(|_beat| {}, device);
+    let _source = beat_detector::audio_io::device::DeviceSource::new;
+    let _record = |device, stop| beat_detector::recording::record_until(|_beat| {}, device, stop);
+    println!("recording: available");
+}
+
+fn main() {
+    let samples = samples();
+    check_integer(&samples);
+    #[cfg(feature = "float")]
+    check_core(&samples);
+    #[cfg(feature = "std")]
+    check_std(&samples);
+    #[cfg(feature = "recording")]
+    check_recording();
+    #[cfg(feature = "audio-file")]
+    check_audio_file();
+    #[cfg(feature = "audio-net")]
+    check_audio_net(&samples);
+    #[cfg(feature = "room-sync")]
+    check_room_sync();
+    #[cfg(feature = "visualizer")]
+    check_visualizer(&samples);
+}
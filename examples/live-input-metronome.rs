@@ -0,0 +1,57 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Plays a click on each detected beat, so that the timing of the detection
+//! can be judged by ear. Headphones prevent that the clicks are detected as
+//! beats.
+use beat_detector::duplex::DuplexDevices;
+use beat_detector::stop::StopSource;
+use std::time::Duration;
+
+#[path = "_modules/example_utils.rs"]
+mod example_utils;
+
+/// Higher than the latency of the detection, so that all clicks have the
+/// same latency after their beat.
+const CLICK_LATENCY: Duration = Duration::from_millis(250);
+
+fn main() {
+    example_utils::init_logger();
+    let input_device = example_utils::select_audio_device();
+
+    let stop_source = StopSource::new();
+    let stop_recording = stop_source.token();
+    ctrlc::set_handler(move || stop_source.stop()).unwrap();
+
+    let duplex = DuplexDevices::open(Some(input_device)).unwrap();
+    let (mut metronome, _output_stream) = duplex.start_metronome().unwrap();
+    metronome.set_latency(CLICK_LATENCY);
+
+    log::info!("Start recording");
+    let input_stream = duplex
+        .start_detector_thread(move |info| metronome.click_for_beat(&info.beat))
+        .unwrap();
+    stop_recording.wait();
+    drop(input_stream);
+    log::info!("Stopped recording");
+}
@@ -1,7 +1,8 @@
-use beat_detector::recording;
-use cpal::traits::StreamTrait;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use beat_detector::audio_io::device::DeviceSource;
+use beat_detector::audio_io::SampleSource;
+use beat_detector::driver::run_detector_until;
+use beat_detector::stop::StopSource;
+use beat_detector::BeatDetector;
 
 #[path = "_modules/example_utils.rs"]
 mod example_utils;
@@ -10,25 +11,17 @@ fn main() {
     example_utils::init_logger();
     let input_device = example_utils::select_audio_device();
 
-    let stop_recording = Arc::new(AtomicBool::new(false));
-    {
-        let stop_recording = stop_recording.clone();
-        ctrlc::set_handler(move || {
-            stop_recording.store(true, Ordering::SeqCst);
-        })
-        .unwrap();
-    }
+    let stop_source = StopSource::new();
+    let stop_recording = stop_source.token();
+    ctrlc::set_handler(move || stop_source.stop()).unwrap();
 
-    let handle = recording::start_detector_thread(
-        |info| {
-            println!("beat: {info:?}");
-        },
-        Some(input_device),
-    )
-    .unwrap();
+    let source = DeviceSource::new(Some(input_device)).unwrap();
+    let mut detector = BeatDetector::new(source.sample_rate(), true);
 
     log::info!("Start recording");
-    while !stop_recording.load(Ordering::SeqCst) {}
-    handle.pause().unwrap();
+    run_detector_until(source, &mut detector, &stop_recording, |info| {
+        println!("beat: {info:?}");
+    })
+    .unwrap();
     log::info!("Stopped recording");
 }
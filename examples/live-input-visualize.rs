@@ -1,8 +1,9 @@
-use beat_detector::recording;
+use beat_detector::stop::StopSource;
+use beat_detector::{recording, BeatIntensity, IntensityCurve};
 use cpal::traits::StreamTrait;
 use minifb::{Key, Window, WindowOptions};
-use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[path = "_modules/example_utils.rs"]
 mod example_utils;
@@ -14,19 +15,16 @@ fn main() {
     example_utils::init_logger();
     let input_device = example_utils::select_audio_device();
 
-    let ctrlc_pressed = Arc::new(AtomicBool::new(false));
-    {
-        let stop_recording = ctrlc_pressed.clone();
-        ctrlc::set_handler(move || {
-            stop_recording.store(true, Ordering::SeqCst);
-        })
-        .unwrap();
-    }
+    let stop_source = StopSource::new();
+    let stop_recording = stop_source.token();
+    ctrlc::set_handler(move || stop_source.stop()).unwrap();
 
     // Each Pixel is encoded as "<:8><red:8><green:8><blue:8>".
-    let rgb_buffer: Vec<u32> = vec![0 /* black */; WIDTH * HEIGHT];
-    let mut rgb_copy_buffer = rgb_buffer.clone();
-    let rgb_buffer = Arc::new(Mutex::new(rgb_buffer));
+    let mut rgb_buffer: Vec<u32> = vec![0 /* black */; WIDTH * HEIGHT];
+    let start = Instant::now();
+    let intensity =
+        BeatIntensity::new(Duration::from_millis(400)).with_curve(IntensityCurve::Exponential);
+    let intensity = Arc::new(Mutex::new(intensity));
 
     let mut window = Window::new(
         "Live Beat Visualizer - ESC to exit",
@@ -41,14 +39,11 @@ fn main() {
     window.set_target_fps(60);
 
     let handle = {
-        let rgb_buffer = rgb_buffer.clone();
+        let intensity = intensity.clone();
         recording::start_detector_thread(
             move |_info| {
                 println!("found beat!");
-                let mut rgb_buffer_locked = rgb_buffer.lock().unwrap();
-                for xrgb_pxl in rgb_buffer_locked.iter_mut() {
-                    *xrgb_pxl = 0x00ffffffff;
-                }
+                intensity.lock().unwrap().trigger(start.elapsed());
             },
             Some(input_device),
         )
@@ -57,28 +52,14 @@ fn main() {
 
     log::info!("Start recording");
 
-    while window.is_open()
-        && !window.is_key_down(Key::Escape)
-        && !ctrlc_pressed.load(Ordering::SeqCst)
-    {
-        let mut rgb_buffer_locked = rgb_buffer.lock().unwrap();
-        for (i, xrgb_pxl) in rgb_buffer_locked.iter_mut().enumerate() {
-            *xrgb_pxl = u32::from_ne_bytes([
-                (xrgb_pxl.to_ne_bytes()[0] as f32 * 0.95) as u8,
-                (xrgb_pxl.to_ne_bytes()[1] as f32 * 0.95) as u8,
-                (xrgb_pxl.to_ne_bytes()[2] as f32 * 0.95) as u8,
-                0,
-            ]);
-            // Update copy buffer.
-            rgb_copy_buffer[i] = *xrgb_pxl;
-        }
-
-        // drop lock as early as possible to unblock beat detection thread.
-        drop(rgb_buffer_locked);
+    while window.is_open() && !window.is_key_down(Key::Escape) && !stop_recording.is_stopped() {
+        let brightness = intensity.lock().unwrap().intensity(start.elapsed());
+        let value = (brightness * u8::MAX as f32) as u8;
+        rgb_buffer.fill(u32::from_ne_bytes([value, value, value, 0]));
 
         // We unwrap here as we want this code to exit if it fails.
         window
-            .update_with_buffer(&rgb_copy_buffer, WIDTH, HEIGHT)
+            .update_with_buffer(&rgb_buffer, WIDTH, HEIGHT)
             .unwrap();
     }
     handle.pause().unwrap();
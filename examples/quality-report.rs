@@ -21,6 +21,16 @@ LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
-//! All modules that require `std` functionality.
+//! Prints a Markdown report of the beat detection in a WAV file.
+//!
+//! Usage: `cargo run --example quality-report --features audio-file -- <file.wav>`
+use beat_detector::report::QualityReport;
 
-pub mod recording;
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: quality-report <file.wav>");
+        std::process::exit(1);
+    };
+    let report = QualityReport::analyze_wav_file(&path, true).unwrap();
+    println!("{report}");
+}
@@ -0,0 +1,81 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Records a new test fixture from an audio input device. Press Enter
+//! whenever you perceive a beat. The recording stops after the given
+//! duration or with Ctrl+C.
+//!
+//! Usage: `cargo run --example record-fixture --features audio-file -- <out.wav> [seconds]`
+use beat_detector::audio_io::device::DeviceSource;
+use beat_detector::audio_io::SampleSource;
+use beat_detector::fixture::{record_fixture, FixtureRecorder};
+use beat_detector::stop::StopSource;
+use std::io::BufRead;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[path = "_modules/example_utils.rs"]
+mod example_utils;
+
+/// Typical latency of a keyboard and the reaction of a human.
+const TAP_LATENCY: Duration = Duration::from_millis(50);
+
+fn main() {
+    example_utils::init_logger();
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("Usage: record-fixture <out.wav> [seconds]");
+        std::process::exit(1);
+    };
+    let duration = args.next().map_or(30, |secs| secs.parse().unwrap());
+    let input_device = example_utils::select_audio_device();
+
+    let stop_source = StopSource::new();
+    let stop_recording = stop_source.token();
+    ctrlc::set_handler(move || stop_source.stop()).unwrap();
+
+    let source = DeviceSource::new(Some(input_device)).unwrap();
+    let mut recorder =
+        FixtureRecorder::new(source.sample_rate() as u32, Duration::from_secs(duration));
+    recorder.set_tap_latency(TAP_LATENCY);
+    let recorder = Arc::new(recorder);
+
+    // Blocks on stdin, so it can't be joined. It dies with the process.
+    let tap_recorder = recorder.clone();
+    std::thread::spawn(move || {
+        for _ in std::io::stdin().lock().lines() {
+            tap_recorder.mark_beat();
+        }
+    });
+
+    log::info!("Recording {duration}s of audio, press Enter on each beat");
+    record_fixture(source, &recorder, &stop_recording).unwrap();
+
+    let fixture = recorder.snapshot();
+    fixture.save(&path).unwrap();
+    log::info!(
+        "Wrote {} samples and {} beats to {path}",
+        fixture.samples.len(),
+        fixture.beats.len()
+    );
+}
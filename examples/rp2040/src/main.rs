@@ -0,0 +1,176 @@
+/*
+MIT License
+
+Copyright (c) 2024 Philipp Schuster
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Beat detection on a Raspberry Pi Pico (RP2040) with an analog microphone
+//! module, such as a MAX4466, at GPIO 26 (ADC 0). The onboard LED at GPIO 25
+//! flashes on each beat.
+//!
+//! The ADC samples the microphone at 16 kHz in free-running mode. Its
+//! interrupt handler collects the samples into blocks and pushes each full
+//! block into a [`SampleQueue`]. The main loop pops the blocks and runs the
+//! [`EnergyBeatDetector`], as the Cortex-M0+ has no FPU.
+//!
+//! Build it with `cargo build --release` in this directory and flash it,
+//! e.g., with `elf2uf2-rs` or a debug probe.
+
+#![no_std]
+#![no_main]
+
+use beat_detector_core::{EnergyBeatDetector, SampleProducer, SampleQueue};
+use core::cell::RefCell;
+use critical_section::Mutex;
+use embedded_hal::digital::OutputPin;
+use panic_halt as _;
+use rp2040_hal::adc::{Adc, AdcFifo, AdcPin};
+use rp2040_hal::clocks::init_clocks_and_plls;
+use rp2040_hal::gpio::Pins;
+use rp2040_hal::pac::{self, interrupt};
+use rp2040_hal::{Sio, Watchdog};
+
+/// Second stage bootloader for the flash chip of the Raspberry Pi Pico.
+#[link_section = ".boot2"]
+#[used]
+pub static BOOT2: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
+
+/// Frequency of the crystal of the Raspberry Pi Pico.
+const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+/// Clock of the ADC after [`init_clocks_and_plls`].
+const ADC_CLOCK_FREQ: u32 = 48_000_000;
+const SAMPLING_FREQUENCY: u32 = 16_000;
+/// Samples per block, i.e., 16 ms.
+const BLOCK: usize = 256;
+/// Slots of the queue, which holds up to `SLOTS - 1` blocks.
+const SLOTS: usize = 4;
+/// Amount of blocks the LED stays on after a beat.
+const FLASH_BLOCKS: u32 = 4;
+
+/// State of the ADC interrupt handler.
+struct AdcState {
+    fifo: AdcFifo<'static, u16>,
+    producer: SampleProducer<'static, BLOCK, SLOTS>,
+    block: [i16; BLOCK],
+    len: usize,
+}
+
+/// Only accessed by the interrupt handler after the initialization. The
+/// critical section protects the handler state, not the queue.
+static ADC_STATE: Mutex<RefCell<Option<AdcState>>> = Mutex::new(RefCell::new(None));
+
+#[rp2040_hal::entry]
+fn main() -> ! {
+    let mut pac = pac::Peripherals::take().unwrap();
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+    let _clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+    let sio = Sio::new(pac.SIO);
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+    let mut led = pins.gpio25.into_push_pull_output();
+
+    // The queue and the ADC must outlive the interrupt handler.
+    let queue = cortex_m::singleton!(: SampleQueue<BLOCK, SLOTS> = SampleQueue::new()).unwrap();
+    let (producer, mut consumer) = queue.split();
+    let adc = cortex_m::singleton!(: Adc = Adc::new(pac.ADC, &mut pac.RESETS)).unwrap();
+    let mut microphone = AdcPin::new(pins.gpio26.into_floating_input()).unwrap();
+    // A conversion starts every `1 + int` cycles of the ADC clock.
+    let fifo = adc
+        .build_fifo()
+        .clock_divider((ADC_CLOCK_FREQ / SAMPLING_FREQUENCY - 1) as u16, 0)
+        .set_channel(&mut microphone)
+        .enable_interrupt(2)
+        .start();
+
+    critical_section::with(|cs| {
+        ADC_STATE.borrow_ref_mut(cs).replace(AdcState {
+            fifo,
+            producer,
+            block: [0; BLOCK],
+            len: 0,
+        });
+    });
+    // SAFETY: The state of the handler is initialized and no critical section
+    // is active.
+    unsafe { pac::NVIC::unmask(pac::Interrupt::ADC_IRQ_FIFO) };
+
+    let mut detector = EnergyBeatDetector::new(SAMPLING_FREQUENCY);
+    let mut flash_blocks = 0;
+    loop {
+        let Some(block) = consumer.pop() else {
+            // The next block is at least one ADC interrupt away.
+            cortex_m::asm::wfi();
+            continue;
+        };
+
+        if detector
+            .update_and_detect_beat(block.iter().copied())
+            .is_some()
+        {
+            flash_blocks = FLASH_BLOCKS;
+        }
+        if flash_blocks > 0 {
+            flash_blocks -= 1;
+            led.set_high().unwrap();
+        } else {
+            led.set_low().unwrap();
+        }
+    }
+}
+
+#[interrupt]
+fn ADC_IRQ_FIFO() {
+    critical_section::with(|cs| {
+        let mut state = ADC_STATE.borrow_ref_mut(cs);
+        let Some(state) = state.as_mut() else {
+            return;
+        };
+
+        while state.fifo.len() > 0 {
+            // The microphone output is biased at half of the supply voltage.
+            // Scale the 12 bit sample to the range of `i16`. Conversion
+            // errors become silence.
+            let sample = state.fifo.read().map_or(0, |raw| (raw as i16 - 2048) << 4);
+            state.block[state.len] = sample;
+            state.len += 1;
+
+            if state.len == BLOCK {
+                state.len = 0;
+                if state.producer.push(&state.block).is_err() {
+                    // The main loop is too slow. Drop the block.
+                }
+            }
+        }
+    });
+}
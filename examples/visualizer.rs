@@ -0,0 +1,83 @@
+//! Maintained visualizer on top of [`beat_detector::visualizer`]: the
+//! brightness follows the energy, the bar at the bottom the progress of the
+//! bar, and each detected beat flashes in red.
+//!
+//! Creative coding frameworks, such as nannou, integrate the same way, see
+//! the documentation of the `visualizer` module.
+
+use beat_detector::audio_io::device::DeviceSource;
+use beat_detector::audio_io::SampleSource;
+use beat_detector::stop::StopSource;
+use beat_detector::visualizer::{AudioFrame, FeatureFeed};
+use beat_detector::{BeatDetector, TempoConfig};
+use minifb::{Key, Window, WindowOptions};
+use std::sync::Arc;
+
+#[path = "_modules/example_utils.rs"]
+mod example_utils;
+
+const WIDTH: usize = 600;
+const HEIGHT: usize = 400;
+const BAR_HEIGHT: usize = 20;
+
+fn main() {
+    example_utils::init_logger();
+    let input_device = example_utils::select_audio_device();
+
+    let stop_source = Arc::new(StopSource::new());
+    let (mut feed, features) = FeatureFeed::new(TempoConfig::default());
+    let detector_thread = {
+        let stop = stop_source.token();
+        std::thread::spawn(move || {
+            let source = DeviceSource::new(Some(input_device)).unwrap();
+            let mut detector = BeatDetector::new(source.sample_rate(), true);
+            feed.run(source, &mut detector, &stop).unwrap();
+        })
+    };
+    {
+        let stop_source = stop_source.clone();
+        ctrlc::set_handler(move || stop_source.stop()).unwrap();
+    }
+
+    let mut window = Window::new(
+        "Beat Visualizer - ESC to exit",
+        WIDTH,
+        HEIGHT,
+        WindowOptions::default(),
+    )
+    .unwrap_or_else(|e| {
+        panic!("{}", e);
+    });
+    window.set_target_fps(60);
+
+    // Each Pixel is encoded as "<:8><red:8><green:8><blue:8>".
+    let mut rgb_buffer: Vec<u32> = vec![0 /* black */; WIDTH * HEIGHT];
+    let mut previous = AudioFrame::default();
+    while window.is_open() && !window.is_key_down(Key::Escape) && !stop_source.is_stopped() {
+        let frame = features.frame();
+        let uniforms = frame.uniforms;
+
+        let value = (uniforms.energy * u8::MAX as f32) as u8;
+        let background = if frame.has_new_beat(&previous) {
+            u32::from_be_bytes([0, u8::MAX, 0, 0])
+        } else {
+            u32::from_be_bytes([0, value, value, value])
+        };
+        let (canvas, bar) = rgb_buffer.split_at_mut(WIDTH * (HEIGHT - BAR_HEIGHT));
+        canvas.fill(background);
+        let progress = (uniforms.bar_phase * WIDTH as f32) as usize;
+        for row in bar.chunks_exact_mut(WIDTH) {
+            row[..progress].fill(u32::from_be_bytes([0, 0, 0x80, u8::MAX]));
+            row[progress..].fill(0);
+        }
+        previous = frame;
+
+        // We unwrap here as we want this code to exit if it fails.
+        window
+            .update_with_buffer(&rgb_buffer, WIDTH, HEIGHT)
+            .unwrap();
+    }
+
+    stop_source.stop();
+    detector_thread.join().unwrap();
+}
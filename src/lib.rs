@@ -32,7 +32,8 @@ SOFTWARE.
 //!
 //! ## TL;DR
 //!
-//! Use [`BeatDetector`].
+//! Use [`BeatDetector`]. On tiny microcontrollers, where even that is too
+//! heavy, use [`EnergyBeatDetector`].
 //!
 //! ## Audio Source
 //!
@@ -41,6 +42,7 @@ SOFTWARE.
 //!
 //! - [`util::f32_sample_to_i16`]
 //! - [`util::stereo_to_mono`]
+//! - [`Mixer`] to combine multiple audio sources into one
 //!
 //! ## Example
 //!
@@ -54,6 +56,65 @@ SOFTWARE.
 //! );
 //! ```
 //!
+//! ## Cargo Features
+//!
+//! - `float` (default): The floating point based detection pipeline, i.e.,
+//!   [`BeatDetector`] and everything around it. Without it, only
+//!   [`EnergyBeatDetector`] and a few integer-only helpers remain. Timestamps
+//!   are then sample counts and milliseconds, and thresholds are fixed-point
+//!   numbers, so that no soft-float routines end up in the binary of MCUs
+//!   without an FPU. The [`BeatDetector`] itself has no integer variant.
+//! - `std`: Helpers that need the standard library, such as
+//!   [`offline::detect_beats`] for batch analysis of audio data in memory.
+//!   Implies `float`. This doesn't pull in any audio backend.
+//! - `recording` (default): Live recording from an audio input device via
+//!   `cpal`, see [`recording::start_detector_thread`] and
+//!   [`audio_io::device`]. Implies `std` and requires the native audio
+//!   libraries of the platform, such as ALSA on Linux.
+//! - `audio-file`: Reading WAV files, see
+//!   [`audio_io::file`](mod@audio_io::file).
+//! - `audio-net`: Reading raw PCM streams, e.g., from the network, see
+//!   [`audio_io::net`].
+//! - `room-sync`: Sharing the tempo and the beat phase between instances on a
+//!   LAN, see [`room_sync`].
+//! - `visualizer`: Handing the audio features to the frame loop of a
+//!   visualizer, independent of the framework, see [`visualizer`].
+//!
+//! All audio inputs implement [`audio_io::SampleSource`].
+//!
+//! With `float` or without any feature, the crate is `no_std`-compatible.
+//!
+//! ## Crates
+//!
+//! This crate is a facade that re-exports the crates of the workspace:
+//!
+//! - [`beat-detector-core`](beat_detector_core): the detection pipeline.
+//!   It is always `no_std` and doesn't need `alloc`.
+//! - `beat-detector-io`: audio sources, live recording, and the other
+//!   helpers that need the standard library. Only with `std`.
+//!
+//! The command line interface lives in `beat-detector-cli`.
+//!
+//! ## Public API
+//!
+//! The public API is small on purpose, so that the internals can evolve
+//! without breaking changes:
+//!
+//! - Detectors: [`BeatDetector`], [`EnergyBeatDetector`], and
+//!   [`MultiSourceDetector`].
+//! - Configuration: [`EnvelopeConfig`], [`FrequencyWeighting`], and
+//!   [`TempoConfig`].
+//! - Results: [`BeatInfo`], [`Diagnosis`], [`Heartbeat`], and friends. They
+//!   are `#[non_exhaustive]`, i.e., they may gain fields in minor releases and
+//!   only the crates create them.
+//! - Sources: [`audio_io::SampleSource`] and its implementations.
+//! - Sinks: [`metronome`], [`PwmBeatPulse`], and [`SceneMapping`].
+//!
+//! Items that are hidden from the documentation are implementation details
+//! and may change in any release. The test `public_api` compares the names of
+//! the public items with `public-api.txt`, so that items aren't added or
+//! removed by accident. It doesn't cover methods, fields, or signatures.
+//!
 //! ## Detection and Usage
 //!
 //! The beat detector is supposed to be continuously invoked with the latest
@@ -94,117 +155,159 @@ SOFTWARE.
     // clippy::restriction,
     // clippy::pedantic
 )]
-// now allow a few rules which are denied by the above statement
-// --> they are ridiculous and not necessary
-#![allow(
-    clippy::suboptimal_flops,
-    clippy::redundant_pub_crate,
-    clippy::fallible_impl_from,
-    clippy::multiple_crate_versions
-)]
+#![allow(clippy::multiple_crate_versions)]
 #![deny(missing_debug_implementations)]
 #![deny(rustdoc::all)]
 
-extern crate alloc;
-#[cfg_attr(any(test, feature = "std"), macro_use)]
-#[cfg(any(test, feature = "std"))]
-extern crate std;
-
-// Better drop-in replacement for "assert!" and even better "check!" macro.
-#[cfg_attr(test, macro_use)]
 #[cfg(test)]
-extern crate assert2;
-
-#[cfg_attr(test, macro_use)]
-#[cfg(test)]
-extern crate float_cmp;
+#[macro_use]
+extern crate std;
 
-mod audio_history;
-mod beat_detector;
-mod envelope_iterator;
-mod max_min_iterator;
-mod root_iterator;
+pub use beat_detector_core::*;
 #[cfg(feature = "std")]
-mod stdlib;
-/// PRIVATE. For tests and helper binaries.
-#[cfg(test)]
-mod test_utils;
-pub mod util;
-
-pub use audio_history::{AudioHistory, SampleInfo};
-pub use beat_detector::{BeatDetector, BeatInfo};
-pub use envelope_iterator::{EnvelopeInfo, EnvelopeIterator};
+pub use beat_detector_io::*;
+// Both crates have an error type. The one of `beat-detector-io` covers all
+// failure classes.
 #[cfg(feature = "std")]
-pub use stdlib::*;
-
-use max_min_iterator::MaxMinIterator;
-use root_iterator::RootIterator;
+pub use beat_detector_io::Error;
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use crate::audio_history::AudioHistory;
-    use crate::max_min_iterator::MaxMinIterator;
-    use crate::test_utils;
+    use std::path::Path;
+    use std::string::{String, ToString};
     use std::vec::Vec;
 
-    fn _print_sample_stats((samples, header): (Vec<i16>, hound::WavSpec)) {
-        let mut history = AudioHistory::new(header.sample_rate as f32);
-        history.update(samples.iter().copied());
-
-        let all_peaks = MaxMinIterator::new(&history, None).collect::<Vec<_>>();
+    /// File with the expected public items. Regenerate it with
+    /// `UPDATE_PUBLIC_API=1 cargo test -p beat-detector --lib public_api`.
+    const SNAPSHOT: &str = "public-api.txt";
 
-        let abs_peak_value_iter = all_peaks.iter().map(|info| info.value_abs);
+    /// Collects the public items of the module in `file` and of its public
+    /// submodules, prefixed with `path`.
+    ///
+    /// This is a line scanner, not a parser: it only looks at lines that
+    /// begin with `pub ` at the top level of each file and at the names in
+    /// `pub use` lists, as they are written. Re-exports aren't resolved and
+    /// `#[cfg]` attributes are ignored. Items marked as `#[doc(hidden)]`
+    /// directly above are skipped.
+    fn collect_public_items(file: &Path, path: &str, items: &mut Vec<String>) {
+        let source = std::fs::read_to_string(file).unwrap();
+        let dir = if file.ends_with("lib.rs") || file.ends_with("mod.rs") {
+            file.parent().unwrap().to_path_buf()
+        } else {
+            file.with_extension("")
+        };
 
-        let max: i16 = abs_peak_value_iter.clone().max().unwrap();
-        let min: i16 = abs_peak_value_iter.clone().min().unwrap();
+        let mut hidden = false;
+        let mut lines = source.lines();
+        while let Some(line) = lines.next() {
+            if line.starts_with("#[doc(hidden)]") {
+                hidden = true;
+                continue;
+            }
+            if line.starts_with("#[") || line.starts_with("//") {
+                continue;
+            }
+            let is_hidden = core::mem::take(&mut hidden);
+            let Some(item) = line.strip_prefix("pub ") else {
+                continue;
+            };
+            if is_hidden {
+                // Skip the rest of a multi-line `use`.
+                if line.contains('{') && !line.contains('}') {
+                    lines.by_ref().find(|line| line.contains('}'));
+                }
+                continue;
+            }
 
-        let avg: i16 =
-            (abs_peak_value_iter.map(|v| v as u64).sum::<u64>() / all_peaks.len() as u64) as i16;
+            if let Some(tree) = item.strip_prefix("use ") {
+                let mut tree = tree.to_string();
+                while !tree.contains(';') {
+                    tree.push_str(lines.next().unwrap());
+                }
+                let names = match tree.split_once('{') {
+                    Some((_, names)) => names.split('}').next().unwrap().to_string(),
+                    None => tree.rsplit("::").next().unwrap().to_string(),
+                };
+                items.extend(
+                    names
+                        .split(',')
+                        .map(|name| name.trim().trim_end_matches(';'))
+                        .filter(|name| !name.is_empty() && *name != "*")
+                        .map(|name| format!("{path}::{name}")),
+                );
+                continue;
+            }
 
-        let mut all_peaks_sorted = all_peaks.clone();
-        all_peaks_sorted.sort_by(|a, b| a.value_abs.partial_cmp(&b.value_abs).unwrap());
-
-        let median: i16 = all_peaks_sorted[all_peaks_sorted.len() / 2].value_abs;
-
-        eprintln!("max abs peak     : {max:.3}");
-        eprintln!("min abs peak     : {min:.3}");
-        eprintln!("average abs peak : {avg:.3}");
-        eprintln!("median abs peak  : {median:.3}");
-        eprintln!("max / avg peak   : {:.3}", max / avg);
-        eprintln!("max / median peak: {:.3}", max / median);
-        eprintln!(
-            "peaks abs        : {:#.3?}",
-            all_peaks
-                .iter()
-                .map(|info| info.value_abs)
-                .collect::<Vec<_>>()
-        );
-        eprintln!(
-            "peak next_to_curr ratio: {:#.3?}",
-            all_peaks
-                .iter()
-                .zip(all_peaks.iter().skip(1))
-                .map(|(current, next)| { next.value_abs / current.value_abs })
-                .collect::<Vec<_>>()
-        );
+            // `const fn` and `unsafe fn` are functions, other `const` items
+            // are constants.
+            let item = item
+                .strip_prefix("const ")
+                .filter(|rest| rest.starts_with("fn ") || rest.starts_with("unsafe "))
+                .unwrap_or(item)
+                .trim_start_matches("unsafe ");
+            let Some((kind, rest)) = item.split_once(' ') else {
+                continue;
+            };
+            let name = rest
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .next()
+                .unwrap();
+            match kind {
+                "mod" => {
+                    items.push(format!("{path}::{name}"));
+                    let file = dir.join(format!("{name}.rs"));
+                    let file = if file.exists() {
+                        file
+                    } else {
+                        dir.join(name).join("mod.rs")
+                    };
+                    collect_public_items(&file, &format!("{path}::{name}"), items);
+                }
+                "fn" | "struct" | "enum" | "trait" | "const" | "static" | "type" => {
+                    items.push(format!("{path}::{name}"));
+                }
+                _ => {}
+            }
+        }
     }
 
-    /// This just prints a few statistics of the used sample. This helps to
-    /// understand characteristics of certain properties in a sample, such as
-    /// the characteristic of an envelope.
+    /// The public API of the workspace is kept small on purpose, so that the
+    /// internals can be refactored freely. This test fails if public items
+    /// are added or removed by accident.
+    ///
+    /// It only compares the names of the items, see
+    /// [`collect_public_items`]. Changes to methods, fields, enum variants,
+    /// trait implementations, or signatures aren't detected. They still need
+    /// a careful review.
     #[test]
-    fn print_holiday_single_beat_stats() {
-        eprintln!("holiday stats (single beat):");
-        _print_sample_stats(test_utils::samples::holiday_single_beat())
-    }
+    fn public_api() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut items = Vec::new();
+        for krate in ["beat-detector-core", "beat-detector-io"] {
+            let lib = root.join(krate).join("src/lib.rs");
+            collect_public_items(&lib, &krate.replace('-', "_"), &mut items);
+        }
+        items.sort();
+        items.dedup();
+        let actual = items.join("\n") + "\n";
 
-    /// This just prints a few statistics of the used sample. This helps to
-    /// understand characteristics of certain properties in a sample, such as
-    /// the characteristic of an envelope.
-    #[test]
-    fn print_sample1_single_beat_stats() {
-        eprintln!("sample1 stats (single beat):");
-        _print_sample_stats(test_utils::samples::sample1_single_beat())
+        let snapshot = root.join(SNAPSHOT);
+        if std::env::var_os("UPDATE_PUBLIC_API").is_some() {
+            std::fs::write(&snapshot, &actual).unwrap();
+        }
+        let expected = std::fs::read_to_string(&snapshot).unwrap();
+        let added = actual
+            .lines()
+            .filter(|item| !expected.lines().any(|expected| expected == *item))
+            .collect::<Vec<_>>();
+        let removed = expected
+            .lines()
+            .filter(|item| !actual.lines().any(|actual| actual == *item))
+            .collect::<Vec<_>>();
+        assert!(
+            added.is_empty() && removed.is_empty(),
+            "The public API changed. Added: {added:#?}, removed: {removed:#?}. \
+            Update {SNAPSHOT} if this is intended."
+        );
     }
 }